@@ -21,7 +21,7 @@ pub type Test = super::tester::GenericTester<String, State>;
 
 use crate::{
 	bytes::Bytes,
-	hash::{Address, H256},
+	hash::{Address, Bloom, H256},
 	maybe::MaybeEmpty,
 	spec::{ForkSpec, State as AccountState},
 	transaction::Transaction,
@@ -44,6 +44,10 @@ pub struct State {
 	pub post_states: BTreeMap<ForkSpec, Vec<PostStateResult>>,
 	/// Transaction.
 	pub transaction: MultiTransaction,
+	/// Transactions applied in order before `transaction`, selected with the
+	/// same indexes. Only the state they leave behind is checked.
+	#[serde(rename = "preTransactions", default)]
+	pub pre_transactions: Vec<MultiTransaction>,
 }
 
 /// State test transaction deserialization.
@@ -128,8 +132,15 @@ pub struct PostStateIndexes {
 pub struct PostStateResult {
 	/// Post state hash
 	pub hash: H256,
+	/// Hash of the RLP-encoded logs emitted by the transaction.
+	pub logs: Option<H256>,
+	/// Logs bloom, as it would appear in a block header.
+	pub bloom: Option<Bloom>,
 	/// Indexes
 	pub indexes: PostStateIndexes,
+	/// Reason the transaction is invalid, if it is.
+	#[serde(rename = "expectException", default)]
+	pub expect_exception: Option<String>,
 }
 
 #[cfg(test)]
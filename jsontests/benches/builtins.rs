@@ -0,0 +1,57 @@
+//! Throughput of builtin lookups and calls per thread at growing thread
+//! counts, as the parallel runner makes them once per execution. Every
+//! thread looks builtins up on its own copy, so the throughput per thread
+//! should stay flat up to the number of cores.
+//!
+//! Run with `cargo bench --bench builtins`.
+
+use evm_jsontests::builtins;
+use primitive_types::H160;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const CALLS: u32 = 200_000;
+
+/// Calls of the identity builtin per second of each of `threads` threads,
+/// looking it up before every call.
+fn calls_per_thread(threads: usize) -> f64 {
+	let identity = H160::from_low_u64_be(4);
+	let input = [0u8; 32];
+	let workers = (0..threads)
+		.map(|_| {
+			thread::spawn(move || {
+				// Builds the builtins of the thread outside of the timing.
+				builtins::for_fork("Berlin").unwrap();
+				let start = Instant::now();
+				for _ in 0..CALLS {
+					let builtins = builtins::for_fork("Berlin").unwrap();
+					builtins[&identity].execute(&input).unwrap();
+				}
+				start.elapsed()
+			})
+		})
+		.collect::<Vec<_>>();
+	let elapsed = workers
+		.into_iter()
+		.map(|worker| worker.join().unwrap())
+		.max()
+		.unwrap_or_default()
+		.max(Duration::from_nanos(1));
+	f64::from(CALLS) / elapsed.as_secs_f64()
+}
+
+fn main() {
+	let cores = rayon::current_num_threads();
+	let mut threads = 1;
+	let single = calls_per_thread(1);
+	while threads <= 2 * cores {
+		let rate = calls_per_thread(threads);
+		println!(
+			"{:>3} threads: {:>10.0} calls/s per thread, {:.2} of a single thread",
+			threads,
+			rate,
+			rate / single
+		);
+		threads *= 2;
+	}
+}
@@ -0,0 +1,30 @@
+//! Pass the version of the evm crate the runner links to its skip reasons.
+
+use std::env;
+use std::fs;
+use std::path::Path;
+
+fn main() {
+	let manifest = Path::new(&env::var("CARGO_MANIFEST_DIR").unwrap())
+		.join("..")
+		.join("evm")
+		.join("Cargo.toml");
+	println!("cargo:rerun-if-changed={}", manifest.display());
+	// The first `version` of the manifest is the one of its package.
+	let version = fs::read_to_string(&manifest)
+		.ok()
+		.and_then(|manifest| {
+			manifest.lines().find_map(|line| {
+				let value = line.trim().strip_prefix("version")?.trim_start();
+				Some(
+					value
+						.strip_prefix('=')?
+						.trim()
+						.trim_matches('"')
+						.to_string(),
+				)
+			})
+		})
+		.unwrap_or_else(|| "unknown".to_string());
+	println!("cargo:rustc-env=EVM_VERSION={}", version);
+}
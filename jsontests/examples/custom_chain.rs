@@ -0,0 +1,146 @@
+//! Validate the precompile set of a custom chain against the Berlin state
+//! tests, embedding the runner as a library: a chain spec in place of
+//! mainnet, a builtins schedule for the chain's precompiles, a skip list for
+//! the tests of the precompiles it lacks and a JSON report of every index.
+//!
+//! The chain follows Berlin from genesis without the BLAKE2F precompile of
+//! EIP-152, as some L2s launched:
+//!
+//! ```sh
+//! cargo run --release --example custom_chain -- res/ethtests/GeneralStateTests report.jsonl
+//! ```
+//!
+//! The run fails if a test fails or a post state is skipped for a fork the
+//! runner does not support. Post states of other forks do not apply to the
+//! chain and skipped tests are on the list, so neither fails it.
+
+use evm_jsontests::builtins::{self, Schedule};
+use evm_jsontests::chainspec::{self, ChainSpec};
+use evm_jsontests::console;
+use evm_jsontests::fixtures::{self, json_files};
+use evm_jsontests::report;
+use evm_jsontests::state::{self, Options, Test};
+use evm_jsontests::summary::{SkipReason, Summary};
+use regex::Regex;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+
+/// The chain: mainnet's chain id, Berlin from genesis. Its gas table could
+/// reprice opcodes; it only spells out mainnet's EIP-170 code size limit,
+/// which L2s often raise, so that the Berlin tests still pass.
+const CHAIN: &str = r#"{
+	"chainId": 1,
+	"forks": [{ "fork": "Berlin" }],
+	"gas": { "createContractLimit": 24576 }
+}"#;
+
+/// Address of the BLAKE2F precompile the chain does not have.
+const BLAKE2F: &str = "0000000000000000000000000000000000000009";
+
+/// Patterns of the ids of the indexes the chain does not run: those of the
+/// BLAKE2F tests, which fail without it.
+const SKIP_LIST: &[&str] = &[r"/blake2B\.json:"];
+
+/// The builtins schedule of mainnet without BLAKE2F.
+pub fn precompiles() -> Schedule {
+	let mut schedule: BTreeMap<String, Value> =
+		serde_json::from_str(include_str!("../res/builtins.json")).unwrap();
+	schedule.remove(BLAKE2F);
+	Schedule::parse(&serde_json::to_string(&schedule).unwrap()).unwrap()
+}
+
+/// Run every test from now on on the chain with its precompiles.
+pub fn install() {
+	builtins::set_schedule(&precompiles());
+	chainspec::set_chain_spec(Some(ChainSpec::parse(CHAIN).unwrap()));
+}
+
+/// Options running the chain's tests quietly, reporting every index into
+/// `report`.
+pub fn options(report: &Path) -> Options {
+	Options {
+		quiet: true,
+		skip_patterns: SKIP_LIST
+			.iter()
+			.map(|pattern| Regex::new(pattern).unwrap())
+			.collect(),
+		json_report: Some(report.to_path_buf()),
+		..Options::default()
+	}
+}
+
+/// Run the state tests of `files` with `options`.
+pub fn validate(files: &[PathBuf], options: &Options) -> Summary {
+	let mut summary = Summary::default();
+	for file in files {
+		let path = file.display().to_string();
+		let tests = fixtures::read(file)
+			.map_err(|err| err.to_string())
+			.and_then(|contents| {
+				serde_json::from_slice::<BTreeMap<String, Test>>(&contents)
+					.map_err(|err| err.to_string())
+			});
+		let tests = match tests {
+			Ok(tests) => tests,
+			Err(err) => {
+				eprintln!("{}: {}", path, err);
+				summary.parse_errors += 1;
+				continue;
+			}
+		};
+		for (name, test) in tests {
+			// A test fails by panicking.
+			let outcome = panic::catch_unwind(AssertUnwindSafe(|| {
+				state::test_in_file(&path, &name, test, options)
+			}));
+			match outcome {
+				Ok(outcome) => summary.pass(outcome),
+				Err(payload) => {
+					let message = console::failure_message(&*payload);
+					summary.fail(&format!("{}:{}", path, name), &message);
+				}
+			}
+		}
+	}
+	summary
+}
+
+fn main() {
+	let mut args = std::env::args().skip(1);
+	let dir = PathBuf::from(
+		args.next()
+			.unwrap_or_else(|| "res/ethtests/GeneralStateTests".to_string()),
+	);
+	let report = PathBuf::from(args.next().unwrap_or_else(|| "report.jsonl".to_string()));
+
+	install();
+	fs::write(&report, "").expect("Create JSON report failed");
+	let summary = validate(&json_files(&dir), &options(&report));
+
+	let indexes = report::load(&report).expect("Read JSON report failed");
+	let failed = indexes.iter().filter(|index| !index.passed).count();
+	println!(
+		"{} indexes reported into {}, {} failed",
+		indexes.len(),
+		report.display(),
+		failed
+	);
+	for reason in [SkipReason::Listed, SkipReason::NotApplicable] {
+		let skipped = summary
+			.skips
+			.iter()
+			.filter(|((_, skip), _)| *skip == reason)
+			.map(|(_, count)| count)
+			.sum::<usize>();
+		println!("{} post states {}", skipped, reason);
+	}
+	if !summary.failures.is_empty() {
+		print!("{}", summary.failures_report());
+	}
+	println!("{}", summary);
+	process::exit(summary.exit_code(false));
+}
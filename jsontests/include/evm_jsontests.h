@@ -0,0 +1,32 @@
+/* C API of the evm-jsontests runner, exported by its cdylib. */
+
+#ifndef EVM_JSONTESTS_H
+#define EVM_JSONTESTS_H
+
+#include <stddef.h>
+#include <stdint.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/*
+ * Run every test of the fixture in the `len` bytes at `data`. Returns a JSON
+ * array with an object per test:
+ *
+ *   {"name", "passed", "executed", "skipped", "error", "output"}
+ *
+ * or an object {"error"} if the fixture cannot be parsed. The string must be
+ * freed with evm_jsontests_free.
+ */
+char *evm_jsontests_run_state(const uint8_t *data, size_t len);
+char *evm_jsontests_run_vm(const uint8_t *data, size_t len);
+
+/* Free a string returned by the functions above. */
+void evm_jsontests_free(char *results);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
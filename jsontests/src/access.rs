@@ -0,0 +1,357 @@
+//! Warm and cold accesses of EIP-2929, tracked while a transaction runs to
+//! triage failures around out-of-gas: the addresses and slots warm when the
+//! first frame ran out of gas, and the gas at every call boundary. The
+//! executor does not expose its access sets, so they are rebuilt from the
+//! instructions that warm an address or slot, and rolled back with the
+//! frames that fail.
+
+use crate::trace::{gas_left, gas_snapshot, Tracer};
+use evm::tracing::Event;
+use evm::{ExitError, ExitReason};
+use evm_runtime::tracing::Event as RuntimeEvent;
+use primitive_types::{H160, H256};
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+/// An address or storage slot made warm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+	Address(H160),
+	Slot(H160, H256),
+}
+
+#[derive(Debug, Default)]
+struct Frame {
+	/// Accesses the frame and its finished sub-calls made warm, undone if it
+	/// fails.
+	added: Vec<Access>,
+	/// Access of the instruction running, warm once it got past its gas
+	/// charge.
+	pending: Option<Access>,
+	gas_left: Option<u64>,
+}
+
+/// Crossing of a frame boundary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Boundary {
+	Enter {
+		depth: usize,
+		to: H160,
+		gas: Option<u64>,
+	},
+	Exit {
+		depth: usize,
+		reason: String,
+		gas_left: Option<u64>,
+	},
+}
+
+/// Warm addresses and slots when a frame ran out of gas.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WarmSets {
+	pub depth: usize,
+	pub addresses: BTreeSet<H160>,
+	pub slots: BTreeSet<(H160, H256)>,
+}
+
+/// Tracer of warm accesses and call boundaries.
+#[derive(Debug, Default)]
+pub struct AccessTracer {
+	frames: Vec<Frame>,
+	addresses: BTreeSet<H160>,
+	slots: BTreeSet<(H160, H256)>,
+	pub boundaries: Vec<Boundary>,
+	/// Warm sets at the first out-of-gas exit.
+	pub out_of_gas: Option<WarmSets>,
+}
+
+fn address_word(word: &H256) -> H160 {
+	H160::from_slice(&word.as_bytes()[12..])
+}
+
+/// Access the instruction `opcode` makes with `stack`, whose top is last,
+/// in the frame of `address`.
+fn access_of(opcode: u8, stack: &[H256], address: H160) -> Option<Access> {
+	let operand = |depth: usize| stack.len().checked_sub(depth + 1).map(|i| &stack[i]);
+	Some(match opcode {
+		// SLOAD, SSTORE
+		0x54 | 0x55 => Access::Slot(address, *operand(0)?),
+		// BALANCE, EXTCODESIZE, EXTCODECOPY, EXTCODEHASH, SELFDESTRUCT
+		0x31 | 0x3b | 0x3c | 0x3f | 0xff => Access::Address(address_word(operand(0)?)),
+		// CALL, CALLCODE, DELEGATECALL, STATICCALL
+		0xf1 | 0xf2 | 0xf4 | 0xfa => Access::Address(address_word(operand(1)?)),
+		_ => return None,
+	})
+}
+
+impl AccessTracer {
+	/// Tracer of a transaction whose `warm` addresses and `slots` are warm
+	/// from the start: precompiles and its access list. Its sender and
+	/// recipient are made warm by entering its frame.
+	pub fn new(warm: impl IntoIterator<Item = H160>, slots: &[(H160, Vec<H256>)]) -> Self {
+		let mut tracer = AccessTracer::default();
+		tracer.addresses.extend(warm);
+		for (address, keys) in slots {
+			tracer.addresses.insert(*address);
+			tracer.slots.extend(keys.iter().map(|key| (*address, *key)));
+		}
+		tracer
+	}
+
+	/// Make `access` warm, remembering it in the current frame if it was
+	/// cold.
+	fn warm(&mut self, access: Access) {
+		let added = match access {
+			Access::Address(address) => self.addresses.insert(address),
+			Access::Slot(address, key) => self.slots.insert((address, key)),
+		};
+		if added {
+			if let Some(frame) = self.frames.last_mut() {
+				frame.added.push(access);
+			}
+		}
+	}
+
+	fn commit_pending(&mut self) {
+		if let Some(access) = self
+			.frames
+			.last_mut()
+			.and_then(|frame| frame.pending.take())
+		{
+			self.warm(access);
+		}
+	}
+
+	fn enter(&mut self, accessed: &[H160], to: H160, gas: Option<u64>) {
+		self.commit_pending();
+		self.boundaries.push(Boundary::Enter {
+			depth: self.frames.len(),
+			to,
+			gas,
+		});
+		self.frames.push(Frame::default());
+		for address in accessed {
+			self.warm(Access::Address(*address));
+		}
+	}
+
+	fn exit(&mut self, reason: &ExitReason) {
+		if reason.is_succeed() {
+			self.commit_pending();
+		}
+		let depth = self.frames.len().saturating_sub(1);
+		let frame = match self.frames.pop() {
+			Some(frame) => frame,
+			None => return,
+		};
+		self.boundaries.push(Boundary::Exit {
+			depth,
+			reason: format!("{:?}", reason),
+			gas_left: frame.gas_left,
+		});
+		if reason.is_succeed() {
+			if let Some(parent) = self.frames.last_mut() {
+				parent.added.extend(frame.added);
+			}
+			return;
+		}
+		if *reason == ExitReason::Error(ExitError::OutOfGas) && self.out_of_gas.is_none() {
+			self.out_of_gas = Some(WarmSets {
+				depth,
+				addresses: self.addresses.clone(),
+				slots: self.slots.clone(),
+			});
+		}
+		for access in frame.added {
+			match access {
+				Access::Address(address) => self.addresses.remove(&address),
+				Access::Slot(address, key) => self.slots.remove(&(address, key)),
+			};
+		}
+	}
+
+	/// Whether a root mismatch of a transaction that exited with `reason`
+	/// may be about EIP-2929 gas: fixtures do not record whether it ran out
+	/// of gas, so either a frame did here, or the transaction succeeded where
+	/// the fixture may expect it to.
+	pub fn suspect(&self, reason: &ExitReason) -> bool {
+		self.out_of_gas.is_some() || reason.is_succeed()
+	}
+
+	/// Rendering of the call boundaries with their gas, indented by depth,
+	/// and of the warm sets at the first out-of-gas exit.
+	pub fn report(&self) -> String {
+		let mut out = String::from("Gas at call boundaries:\n");
+		let gas = |gas: &Option<u64>| gas.map_or("?".to_string(), |gas| gas.to_string());
+		for boundary in &self.boundaries {
+			match boundary {
+				Boundary::Enter {
+					depth,
+					to,
+					gas: forwarded,
+				} => writeln!(
+					out,
+					"{:indent$}enter {:?} with {} gas",
+					"",
+					to,
+					gas(forwarded),
+					indent = 2 + 2 * depth
+				),
+				Boundary::Exit {
+					depth,
+					reason,
+					gas_left,
+				} => writeln!(
+					out,
+					"{:indent$}exit {} with {} gas left",
+					"",
+					reason,
+					gas(gas_left),
+					indent = 2 + 2 * depth
+				),
+			}
+			.unwrap();
+		}
+		match &self.out_of_gas {
+			Some(warm) => {
+				writeln!(out, "Warm at the first out of gas, depth {}:", warm.depth).unwrap();
+				for address in &warm.addresses {
+					writeln!(out, "  {:?}", address).unwrap();
+				}
+				for (address, key) in &warm.slots {
+					writeln!(out, "  {:?}[{:?}]", address, key).unwrap();
+				}
+			}
+			None => out.push_str("No frame ran out of gas\n"),
+		}
+		out
+	}
+}
+
+impl Tracer for AccessTracer {
+	fn evm_event(&mut self, event: Event) {
+		match event {
+			Event::Call {
+				code_address,
+				target_gas,
+				context,
+				..
+			} => self.enter(
+				&[context.caller, context.address, code_address],
+				code_address,
+				target_gas,
+			),
+			Event::Create {
+				caller,
+				address,
+				target_gas,
+				..
+			} => self.enter(&[caller, address], address, target_gas),
+			Event::Exit { reason, .. } => self.exit(reason),
+			_ => (),
+		}
+	}
+
+	fn runtime_event(&mut self, event: RuntimeEvent) {
+		if let RuntimeEvent::Step {
+			context,
+			opcode,
+			stack,
+			..
+		} = event
+		{
+			self.commit_pending();
+			let access = access_of(opcode.0, stack.data(), context.address);
+			if let Some(frame) = self.frames.last_mut() {
+				frame.pending = access;
+			}
+		}
+	}
+
+	fn gas_event(&mut self, event: evm_gasometer::tracing::Event) {
+		if let (Some(frame), Some(snapshot)) = (self.frames.last_mut(), gas_snapshot(&event)) {
+			frame.gas_left = Some(gas_left(&snapshot));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::builtins;
+	use crate::fixture::fork_spec;
+	use crate::state::{transact, Transaction};
+	use evm::backend::{MemoryAccount, MemoryVicinity};
+	use primitive_types::U256;
+	use std::borrow::Cow;
+	use std::collections::BTreeMap;
+
+	fn account(code: &str) -> MemoryAccount {
+		MemoryAccount {
+			nonce: U256::one(),
+			balance: U256::zero(),
+			storage: BTreeMap::new(),
+			code: hex::decode(code).unwrap(),
+		}
+	}
+
+	#[test]
+	fn warm_sets_at_out_of_gas() {
+		let caller = H160::from_low_u64_be(0x3000);
+		let contract = H160::from_low_u64_be(0x1000);
+		let callee = H160::from_low_u64_be(0x2000);
+		let mut state = BTreeMap::new();
+		// PUSH1 1 SLOAD POP, then CALL of the callee with 100 gas and STOP.
+		state.insert(
+			contract,
+			account("60015450600060006000600060006120006064f100"),
+		);
+		// PUSH1 5 SLOAD, out of gas on the cold slot.
+		state.insert(callee, account("600554"));
+		let vicinity = MemoryVicinity {
+			gas_price: U256::zero(),
+			origin: caller,
+			block_hashes: Vec::new(),
+			block_number: U256::zero(),
+			block_coinbase: H160::zero(),
+			block_timestamp: U256::zero(),
+			block_difficulty: U256::zero(),
+			block_gas_limit: U256::from(1_000_000),
+			chain_id: U256::one(),
+		};
+		let transaction = Transaction {
+			caller,
+			to: Some(contract),
+			value: U256::zero(),
+			data: Vec::new(),
+			gas_limit: 1_000_000,
+			access_list: Vec::new(),
+			pricing: None,
+		};
+		let spec = fork_spec("Berlin").unwrap();
+		let precompiles = builtins::for_fork("Berlin").unwrap();
+		let tracer = AccessTracer::new(precompiles.keys().copied(), &transaction.access_list);
+		let execution =
+			transact(&spec, &vicinity, Cow::Owned(state), &transaction, tracer).unwrap();
+		assert!(execution.exit_reason.is_succeed());
+
+		let tracer = execution.tracer;
+		assert!(tracer.suspect(&execution.exit_reason));
+		let warm = tracer.out_of_gas.clone().unwrap();
+		assert_eq!(warm.depth, 1);
+		assert!(warm.addresses.contains(&callee));
+		assert!(warm.addresses.contains(&caller));
+		assert!(warm.slots.contains(&(contract, H256::from_low_u64_be(1))));
+		assert!(!warm.slots.contains(&(callee, H256::from_low_u64_be(5))));
+		assert_eq!(tracer.boundaries.len(), 4);
+		assert!(matches!(
+			tracer.boundaries[1],
+			Boundary::Enter {
+				depth: 1,
+				gas: Some(100),
+				..
+			}
+		));
+		assert!(tracer.report().contains("exit Error(OutOfGas)"));
+	}
+}
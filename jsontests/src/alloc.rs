@@ -0,0 +1,117 @@
+//! Heap allocations of the current thread, counted by a global allocator
+//! installed with the `alloc-count` feature.
+
+/// Allocations made, and bytes requested by them.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Allocations {
+	pub count: u64,
+	pub bytes: u64,
+}
+
+impl Allocations {
+	/// Allocations made between `start` and `self`.
+	pub fn since(self, start: Allocations) -> Allocations {
+		Allocations {
+			count: self.count.saturating_sub(start.count),
+			bytes: self.bytes.saturating_sub(start.bytes),
+		}
+	}
+}
+
+/// Allocations of the current thread so far, if they are counted.
+#[cfg(feature = "alloc-count")]
+pub fn current() -> Option<Allocations> {
+	counting::current()
+}
+
+/// Allocations of the current thread so far, if they are counted.
+#[cfg(not(feature = "alloc-count"))]
+pub fn current() -> Option<Allocations> {
+	None
+}
+
+#[cfg(feature = "alloc-count")]
+mod counting {
+	use super::Allocations;
+	use std::alloc::{GlobalAlloc, Layout, System};
+	use std::cell::Cell;
+
+	thread_local! {
+		// Const initialized, so that counting does not allocate itself.
+		static COUNT: Cell<u64> = const { Cell::new(0) };
+		static BYTES: Cell<u64> = const { Cell::new(0) };
+	}
+
+	fn count(size: usize) {
+		// Threads being torn down have lost their counters already.
+		let _ = COUNT.try_with(|count| count.set(count.get() + 1));
+		let _ = BYTES.try_with(|bytes| bytes.set(bytes.get() + size as u64));
+	}
+
+	pub fn current() -> Option<Allocations> {
+		Some(Allocations {
+			count: COUNT.try_with(Cell::get).ok()?,
+			bytes: BYTES.try_with(Cell::get).ok()?,
+		})
+	}
+
+	struct Counting;
+
+	unsafe impl GlobalAlloc for Counting {
+		unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+			count(layout.size());
+			System.alloc(layout)
+		}
+
+		unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+			count(layout.size());
+			System.alloc_zeroed(layout)
+		}
+
+		unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+			count(new_size);
+			System.realloc(ptr, layout, new_size)
+		}
+
+		unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+			System.dealloc(ptr, layout)
+		}
+	}
+
+	#[global_allocator]
+	static ALLOCATOR: Counting = Counting;
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn since() {
+		let start = Allocations {
+			count: 2,
+			bytes: 64,
+		};
+		let end = Allocations {
+			count: 5,
+			bytes: 160,
+		};
+		assert_eq!(
+			end.since(start),
+			Allocations {
+				count: 3,
+				bytes: 96
+			}
+		);
+		assert_eq!(start.since(end), Allocations::default());
+
+		#[cfg(feature = "alloc-count")]
+		{
+			let start = current().unwrap();
+			let bytes = vec![0u8; 1000];
+			let allocated = current().unwrap().since(start);
+			assert!(allocated.count >= 1 && allocated.bytes >= 1000);
+			drop(bytes);
+		}
+	}
+}
@@ -0,0 +1,102 @@
+use regex::Regex;
+use std::fs::File;
+use std::io::{self, Read};
+use std::path::Path;
+
+/// Whether `path` names a fixture archive rather than a fixture file.
+pub fn is_archive(path: &Path) -> bool {
+	let name = path.to_string_lossy();
+	name.ends_with(".tar.gz")
+		|| name.ends_with(".tgz")
+		|| name.ends_with(".tar")
+		|| name.ends_with(".zip")
+}
+
+fn wanted(path: &str, filter: Option<&Regex>) -> bool {
+	let hidden = path.split('/').any(|component| component.starts_with('.'));
+	!hidden && path.ends_with(".json") && filter.map(|filter| filter.is_match(path)).unwrap_or(true)
+}
+
+fn tar_entries<R: Read, F: FnMut(&str, Vec<u8>)>(
+	reader: R,
+	filter: Option<&Regex>,
+	mut f: F,
+) -> io::Result<()> {
+	let mut archive = tar::Archive::new(reader);
+	for entry in archive.entries()? {
+		let mut entry = entry?;
+		if !entry.header().entry_type().is_file() {
+			continue;
+		}
+		let path = entry.path()?.to_string_lossy().into_owned();
+		if !wanted(&path, filter) {
+			continue;
+		}
+		let mut contents = Vec::new();
+		entry.read_to_end(&mut contents)?;
+		f(&path, contents);
+	}
+	Ok(())
+}
+
+fn zip_entries<F: FnMut(&str, Vec<u8>)>(
+	file: File,
+	filter: Option<&Regex>,
+	mut f: F,
+) -> io::Result<()> {
+	let mut archive = zip::ZipArchive::new(file)?;
+	for i in 0..archive.len() {
+		let mut entry = archive.by_index(i)?;
+		if !entry.is_file() {
+			continue;
+		}
+		let path = entry.name().to_string();
+		if !wanted(&path, filter) {
+			continue;
+		}
+		let mut contents = Vec::new();
+		entry.read_to_end(&mut contents)?;
+		f(&path, contents);
+	}
+	Ok(())
+}
+
+/// Stream every JSON fixture of a `.tar`, `.tar.gz`/`.tgz` or `.zip`
+/// archive to `f` in archive order, without unpacking to disk. Hidden
+/// entries are skipped, and with a `filter` only entries whose internal path
+/// matches it are read.
+pub fn for_each_fixture<F: FnMut(&str, Vec<u8>)>(
+	path: &Path,
+	filter: Option<&Regex>,
+	f: F,
+) -> io::Result<()> {
+	let file = File::open(path)?;
+	let name = path.to_string_lossy();
+	if name.ends_with(".zip") {
+		zip_entries(file, filter, f)
+	} else if name.ends_with(".tar") {
+		tar_entries(file, filter, f)
+	} else {
+		tar_entries(flate2::read::GzDecoder::new(file), filter, f)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn entry_selection() {
+		let filter = Regex::new("^GeneralStateTests/stExample/").unwrap();
+		assert!(wanted(
+			"GeneralStateTests/stExample/add11.json",
+			Some(&filter)
+		));
+		assert!(!wanted(
+			"GeneralStateTests/stOther/add11.json",
+			Some(&filter)
+		));
+		assert!(!wanted("GeneralStateTests/stExample/README.md", None));
+		assert!(!wanted("GeneralStateTests/.hidden/add11.json", None));
+	}
+}
@@ -0,0 +1,172 @@
+//! Bundles of what is needed to reproduce a failing test: its fixture
+//! alone, and the output of the failure with its state diff and trace,
+//! optionally with the disassembly of the code it runs. Runs write them
+//! under `--artifacts-dir` for CI to attach, `reproduce` runs them again.
+
+use crate::dump::file_stem;
+use crate::fixture::FillerInfo;
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+const MANIFEST: &str = "manifest.json";
+const FIXTURE: &str = "fixture.json";
+const OUTPUT: &str = "output.txt";
+const DISASSEMBLY: &str = "disassembly.txt";
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+struct Manifest {
+	kind: String,
+	file: String,
+	name: String,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	fork: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	index: Option<usize>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	filler: Option<FillerInfo>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+	/// Subcommand the test runs under: `state`, `vm` or `blockchain`.
+	pub kind: String,
+	/// Fixture file the test is from.
+	pub file: String,
+	pub name: String,
+	/// Fork and index of a state test that failed.
+	pub fork: Option<String>,
+	pub index: Option<usize>,
+	/// Where the test was filled from, if its fixture says.
+	pub filler: Option<FillerInfo>,
+	/// Fixture holding only the test.
+	pub fixture: Value,
+	/// Output of the failing run.
+	pub output: String,
+	/// Disassembly of the code the test runs, if it was asked for.
+	pub disassembly: Option<String>,
+}
+
+impl Artifact {
+	/// Name of the bundle, the same on every run of the test.
+	pub fn file_name(&self) -> String {
+		format!(
+			"{}.tar.gz",
+			file_stem(&format!("{}-{}", self.kind, self.name))
+		)
+	}
+
+	/// Write the bundle into `dir`, returning its path.
+	pub fn write_to(&self, dir: &Path) -> io::Result<PathBuf> {
+		fs::create_dir_all(dir)?;
+		let path = dir.join(self.file_name());
+		let manifest = Manifest {
+			kind: self.kind.clone(),
+			file: self.file.clone(),
+			name: self.name.clone(),
+			fork: self.fork.clone(),
+			index: self.index,
+			filler: self.filler.clone(),
+		};
+		let mut entries = vec![
+			(MANIFEST, serde_json::to_vec_pretty(&manifest)?),
+			(FIXTURE, serde_json::to_vec_pretty(&self.fixture)?),
+			(OUTPUT, self.output.as_bytes().to_vec()),
+		];
+		if let Some(disassembly) = &self.disassembly {
+			entries.push((DISASSEMBLY, disassembly.as_bytes().to_vec()));
+		}
+
+		let encoder = GzEncoder::new(File::create(&path)?, Compression::default());
+		let mut builder = tar::Builder::new(encoder);
+		for (name, contents) in entries {
+			// No timestamps, so that bundles of the same failure are equal.
+			let mut header = tar::Header::new_gnu();
+			header.set_size(contents.len() as u64);
+			header.set_mode(0o644);
+			header.set_mtime(0);
+			header.set_cksum();
+			builder.append_data(&mut header, name, contents.as_slice())?;
+		}
+		builder.into_inner()?.finish()?;
+		Ok(path)
+	}
+
+	pub fn read(path: &Path) -> io::Result<Self> {
+		let mut archive = tar::Archive::new(GzDecoder::new(File::open(path)?));
+		let mut entries = BTreeMap::new();
+		for entry in archive.entries()? {
+			let mut entry = entry?;
+			let name = entry.path()?.to_string_lossy().into_owned();
+			let mut contents = Vec::new();
+			entry.read_to_end(&mut contents)?;
+			entries.insert(name, contents);
+		}
+		let entry = |name: &str| {
+			entries.get(name).ok_or_else(|| {
+				io::Error::new(io::ErrorKind::InvalidData, format!("missing {}", name))
+			})
+		};
+
+		let manifest: Manifest = serde_json::from_slice(entry(MANIFEST)?)?;
+		Ok(Artifact {
+			kind: manifest.kind,
+			file: manifest.file,
+			name: manifest.name,
+			fork: manifest.fork,
+			index: manifest.index,
+			filler: manifest.filler,
+			fixture: serde_json::from_slice(entry(FIXTURE)?)?,
+			output: String::from_utf8_lossy(entry(OUTPUT)?).into_owned(),
+			disassembly: entries
+				.get(DISASSEMBLY)
+				.map(|disassembly| String::from_utf8_lossy(disassembly).into_owned()),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn round_trip() {
+		let dir = std::env::temp_dir().join("evm-jsontests-artifacts");
+		let artifact = Artifact {
+			kind: "state".to_string(),
+			file: "stExample/add11.json".to_string(),
+			name: "add11".to_string(),
+			fork: Some("Berlin".to_string()),
+			index: Some(0),
+			filler: FillerInfo::of_json(&json!({
+				"_info": {
+					"source": "src/GeneralStateTestsFiller/stExample/add11Filler.json",
+					"filledwith": "testeth 1.5.0"
+				}
+			})),
+			fixture: json!({ "add11": { "env": {} } }),
+			output: "Hash not equal\n".to_string(),
+			disassembly: Some("0000: STOP\n".to_string()),
+		};
+		assert_eq!(
+			artifact.filler.as_ref().unwrap().to_string(),
+			"Filled from: filler src/GeneralStateTestsFiller/stExample/add11Filler.json, \
+			 filled with testeth 1.5.0"
+		);
+		let path = artifact.write_to(&dir).unwrap();
+		assert_eq!(path, dir.join("state-add11.tar.gz"));
+		let first = fs::read(&path).unwrap();
+		assert_eq!(Artifact::read(&path).unwrap(), artifact);
+
+		artifact.write_to(&dir).unwrap();
+		assert_eq!(fs::read(&path).unwrap(), first);
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
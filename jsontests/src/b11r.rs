@@ -0,0 +1,269 @@
+use crate::roots::{empty_root, ordered_root, transactions_root};
+use crate::utils::deserialize_hex;
+use primitive_types::{H160, H256, U256};
+use rlp::{Rlp, RlpStream};
+use serde::Deserialize;
+use serde_json::json;
+use sha3::{Digest, Keccak256};
+use std::fs;
+use std::path::Path;
+
+fn keccak(bytes: &[u8]) -> H256 {
+	H256::from_slice(Keccak256::digest(bytes).as_slice())
+}
+
+fn default_bloom() -> Vec<u8> {
+	vec![0; 256]
+}
+
+fn default_nonce() -> Vec<u8> {
+	vec![0; 8]
+}
+
+/// Header fields of the block to build. Roots left out are computed from the
+/// block body.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct HeaderTemplate {
+	pub parent_hash: H256,
+	#[serde(rename = "sha3Uncles", default)]
+	pub ommers_hash: Option<H256>,
+	#[serde(default)]
+	pub miner: H160,
+	pub state_root: H256,
+	#[serde(default)]
+	pub transactions_root: Option<H256>,
+	#[serde(default)]
+	pub receipts_root: Option<H256>,
+	#[serde(default = "default_bloom", deserialize_with = "deserialize_hex")]
+	pub logs_bloom: Vec<u8>,
+	#[serde(default)]
+	pub difficulty: U256,
+	pub number: U256,
+	pub gas_limit: U256,
+	#[serde(default)]
+	pub gas_used: U256,
+	pub timestamp: U256,
+	#[serde(default, deserialize_with = "deserialize_hex")]
+	pub extra_data: Vec<u8>,
+	#[serde(default)]
+	pub mix_hash: H256,
+	#[serde(default = "default_nonce", deserialize_with = "deserialize_hex")]
+	pub nonce: Vec<u8>,
+	#[serde(default)]
+	pub base_fee_per_gas: Option<U256>,
+	#[serde(default)]
+	pub withdrawals_root: Option<H256>,
+}
+
+/// EIP-4895 beacon chain withdrawal.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Withdrawal {
+	pub index: U256,
+	pub validator_index: U256,
+	pub address: H160,
+	pub amount: U256,
+}
+
+impl rlp::Encodable for Withdrawal {
+	fn rlp_append(&self, stream: &mut RlpStream) {
+		stream.begin_list(4);
+		stream.append(&self.index);
+		stream.append(&self.validator_index);
+		stream.append(&self.address);
+		stream.append(&self.amount);
+	}
+}
+
+/// A built block together with its header hash.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuiltBlock {
+	pub rlp: Vec<u8>,
+	pub hash: H256,
+}
+
+/// Assemble a block. `txs` is the RLP list of transactions, legacy ones as
+/// lists and typed ones as byte strings, and `ommers` are RLP-encoded ommer
+/// headers. The block is not sealed: the template's mix hash and nonce are
+/// used as is.
+pub fn build_block(
+	header: &HeaderTemplate,
+	txs: &[u8],
+	ommers: &[Vec<u8>],
+	withdrawals: Option<&[Withdrawal]>,
+) -> Result<BuiltBlock, String> {
+	if header.logs_bloom.len() != 256 {
+		return Err("logsBloom must be 256 bytes".into());
+	}
+	if header.nonce.len() != 8 {
+		return Err("nonce must be 8 bytes".into());
+	}
+
+	let txs = Rlp::new(txs);
+	let mut tx_encodings = Vec::new();
+	for tx in txs.iter() {
+		let encoding = if tx.is_list() {
+			tx.as_raw().to_vec()
+		} else {
+			tx.data()
+				.map_err(|err| format!("Invalid transaction: {:?}", err))?
+				.to_vec()
+		};
+		tx_encodings.push(encoding);
+	}
+	for ommer in ommers {
+		if !Rlp::new(ommer).is_list() {
+			return Err("Ommer headers must be RLP lists".into());
+		}
+	}
+
+	let mut ommer_list = RlpStream::new_list(ommers.len());
+	for ommer in ommers {
+		ommer_list.append_raw(ommer, 1);
+	}
+	let ommers_rlp = ommer_list.out();
+	let ommers_hash = header.ommers_hash.unwrap_or_else(|| keccak(&ommers_rlp));
+	let transactions_root = header
+		.transactions_root
+		.unwrap_or_else(|| transactions_root(&tx_encodings));
+	let withdrawals_root = header.withdrawals_root.or_else(|| {
+		withdrawals.map(|withdrawals| ordered_root(withdrawals.iter().map(rlp::encode)))
+	});
+
+	let fields =
+		15 + header.base_fee_per_gas.is_some() as usize + withdrawals_root.is_some() as usize;
+	let mut stream = RlpStream::new_list(fields);
+	stream.append(&header.parent_hash);
+	stream.append(&ommers_hash);
+	stream.append(&header.miner);
+	stream.append(&header.state_root);
+	stream.append(&transactions_root);
+	stream.append(&header.receipts_root.unwrap_or_else(empty_root));
+	stream.append(&header.logs_bloom);
+	stream.append(&header.difficulty);
+	stream.append(&header.number);
+	stream.append(&header.gas_limit);
+	stream.append(&header.gas_used);
+	stream.append(&header.timestamp);
+	stream.append(&header.extra_data);
+	stream.append(&header.mix_hash);
+	stream.append(&header.nonce);
+	if let Some(base_fee) = &header.base_fee_per_gas {
+		stream.append(base_fee);
+	}
+	if let Some(withdrawals_root) = &withdrawals_root {
+		stream.append(withdrawals_root);
+	}
+	let encoded_header = stream.out().to_vec();
+
+	let mut block = RlpStream::new_list(3 + withdrawals.is_some() as usize);
+	block.append_raw(&encoded_header, 1);
+	block.begin_list(tx_encodings.len());
+	for tx in txs.iter() {
+		block.append_raw(tx.as_raw(), 1);
+	}
+	block.append_raw(&ommers_rlp, 1);
+	if let Some(withdrawals) = withdrawals {
+		block.append_list(withdrawals);
+	}
+
+	Ok(BuiltBlock {
+		rlp: block.out().to_vec(),
+		hash: keccak(&encoded_header),
+	})
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &Path) -> Result<T, String> {
+	let file = fs::read(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+	serde_json::from_slice(&file).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, String> {
+	hex::decode(value.trim_start_matches("0x")).map_err(|err| err.to_string())
+}
+
+/// Paths of the `b11r` inputs, mirroring geth's `--input.*` flags.
+#[derive(Debug, Clone, Default)]
+pub struct Inputs<'a> {
+	pub header: Option<&'a Path>,
+	/// JSON string holding the hex-encoded RLP list of transactions.
+	pub txs: Option<&'a Path>,
+	/// JSON array of hex-encoded ommer header RLPs.
+	pub ommers: Option<&'a Path>,
+	/// JSON array of withdrawals.
+	pub withdrawals: Option<&'a Path>,
+}
+
+/// Build the block described by `inputs`, returning the `{"rlp", "hash"}`
+/// output object.
+pub fn run(inputs: &Inputs) -> Result<serde_json::Value, String> {
+	let header: HeaderTemplate = read_json(inputs.header.ok_or("Header input is required")?)?;
+	let txs = match inputs.txs {
+		Some(path) => decode_hex(&read_json::<String>(path)?)?,
+		None => rlp::EMPTY_LIST_RLP.to_vec(),
+	};
+	let ommers = match inputs.ommers {
+		Some(path) => read_json::<Vec<String>>(path)?
+			.iter()
+			.map(|ommer| decode_hex(ommer))
+			.collect::<Result<Vec<_>, _>>()?,
+		None => Vec::new(),
+	};
+	let withdrawals = match inputs.withdrawals {
+		Some(path) => Some(read_json::<Vec<Withdrawal>>(path)?),
+		None => None,
+	};
+
+	let block = build_block(&header, &txs, &ommers, withdrawals.as_deref())?;
+	Ok(json!({
+		"rlp": format!("0x{}", hex::encode(&block.rlp)),
+		"hash": block.hash,
+	}))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn template() -> HeaderTemplate {
+		serde_json::from_value(json!({
+			"parentHash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+			"stateRoot": "0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421",
+			"number": "0x1",
+			"gasLimit": "0x1000000",
+			"timestamp": "0xa",
+		}))
+		.unwrap()
+	}
+
+	#[test]
+	fn empty_block() {
+		let block = build_block(&template(), &rlp::EMPTY_LIST_RLP, &[], None).unwrap();
+		let rlp = Rlp::new(&block.rlp);
+		assert_eq!(rlp.item_count(), Ok(3));
+
+		let header = rlp.at(0).unwrap();
+		assert_eq!(header.item_count(), Ok(15));
+		// Root of the empty transaction trie.
+		assert_eq!(header.val_at::<H256>(4), Ok(empty_root()));
+		// Hash of the empty ommer list.
+		assert_eq!(header.val_at::<H256>(1), Ok(keccak(&rlp::EMPTY_LIST_RLP)));
+		assert_eq!(block.hash, keccak(header.as_raw()));
+	}
+
+	#[test]
+	fn withdrawals_extend_header() {
+		let withdrawals = vec![Withdrawal {
+			index: U256::zero(),
+			validator_index: U256::one(),
+			address: H160::repeat_byte(0x11),
+			amount: U256::from(1000),
+		}];
+		let block =
+			build_block(&template(), &rlp::EMPTY_LIST_RLP, &[], Some(&withdrawals)).unwrap();
+		let rlp = Rlp::new(&block.rlp);
+		assert_eq!(rlp.item_count(), Ok(4));
+		assert_eq!(rlp.at(0).unwrap().item_count(), Ok(16));
+	}
+}
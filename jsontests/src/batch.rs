@@ -0,0 +1,186 @@
+//! Lists of state test indexes to run, one `path:test:fork:index` per line,
+//! for bisect scripts and other tools driving precise subsets, and the
+//! failures of an earlier run read from its JSON report.
+
+use crate::report::IndexReport;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// An index of a test, in the fixture file `file` if it is known.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct IndexId {
+	pub file: Option<String>,
+	pub name: String,
+	pub fork: String,
+	pub index: usize,
+}
+
+impl IndexId {
+	fn matches(&self, file: &str, name: &str, fork: &str, index: usize) -> bool {
+		self.file.as_deref().map_or(true, |path| path == file)
+			&& self.name == name
+			&& self.fork == fork
+			&& self.index == index
+	}
+
+	/// Whether `other` is the same index, one of them maybe without a file.
+	fn same_index(&self, other: &IndexId) -> bool {
+		match (&self.file, &other.file) {
+			(Some(file), Some(other_file)) if file != other_file => false,
+			_ => self.name == other.name && self.fork == other.fork && self.index == other.index,
+		}
+	}
+}
+
+impl fmt::Display for IndexId {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		if let Some(file) = &self.file {
+			write!(f, "{}:", file)?;
+		}
+		write!(f, "{}:{}:{}", self.name, self.fork, self.index)
+	}
+}
+
+impl FromStr for IndexId {
+	type Err = String;
+
+	/// Parse `path:test:fork:index`, the path being everything before the
+	/// last three fields so that it may have colons.
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let mut fields = s.trim().rsplitn(4, ':');
+		let (index, fork, name, file) =
+			match (fields.next(), fields.next(), fields.next(), fields.next()) {
+				(Some(index), Some(fork), Some(name), Some(file)) => (index, fork, name, file),
+				_ => return Err(format!("{} is not path:test:fork:index", s)),
+			};
+		Ok(IndexId {
+			file: Some(file.to_string()),
+			name: name.to_string(),
+			fork: fork.to_string(),
+			index: index
+				.parse()
+				.map_err(|_| format!("invalid index {} in {}", index, s))?,
+		})
+	}
+}
+
+/// Indexes to run, all others being skipped.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Selection {
+	ids: BTreeSet<IndexId>,
+}
+
+impl Selection {
+	pub fn new(ids: impl IntoIterator<Item = IndexId>) -> Self {
+		Selection {
+			ids: ids.into_iter().collect(),
+		}
+	}
+
+	/// Indexes listed in the file at `path`, skipping blank lines and `#`
+	/// comments.
+	pub fn read(path: &Path) -> Result<Self, String> {
+		let text =
+			fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+		let ids = text
+			.lines()
+			.enumerate()
+			.filter(|(_, line)| !line.trim().is_empty() && !line.trim_start().starts_with('#'))
+			.map(|(i, line)| {
+				line.parse()
+					.map_err(|err| format!("{}:{}: {}", path.display(), i + 1, err))
+			})
+			.collect::<Result<Vec<_>, _>>()?;
+		Ok(Selection::new(ids))
+	}
+
+	/// Indexes that failed in `reports`, in any fixture file: reports do not
+	/// record files.
+	pub fn failed(reports: &[IndexReport]) -> Self {
+		Selection::new(
+			reports
+				.iter()
+				.filter(|report| !report.passed)
+				.map(|report| IndexId {
+					file: None,
+					name: report.name.clone(),
+					fork: report.fork.clone(),
+					index: report.index,
+				}),
+		)
+	}
+
+	/// Indexes of this selection that `other` also selects.
+	pub fn intersect(&self, other: &Selection) -> Self {
+		Selection::new(
+			self.ids
+				.iter()
+				.filter(|id| other.ids.iter().any(|other| id.same_index(other)))
+				.cloned(),
+		)
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.ids.is_empty()
+	}
+
+	/// Whether index `index` of test `name` of `file` on `fork` is selected.
+	pub fn selects(&self, file: &str, name: &str, fork: &str, index: usize) -> bool {
+		self.ids
+			.iter()
+			.any(|id| id.matches(file, name, fork, index))
+	}
+
+	/// Fixture files of the selected indexes, each once, `None` if some
+	/// index could be in any file.
+	pub fn files(&self) -> Option<Vec<PathBuf>> {
+		let mut files = Vec::new();
+		for id in &self.ids {
+			let file = PathBuf::from(id.file.as_ref()?);
+			if !files.contains(&file) {
+				files.push(file);
+			}
+		}
+		Some(files)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn selection_of_lists_and_failures() {
+		let id: IndexId = "C:/fixtures/a.json:add:Berlin:2".parse().unwrap();
+		assert_eq!(id.file.as_deref(), Some("C:/fixtures/a.json"));
+		assert_eq!(
+			(id.name.as_str(), id.fork.as_str(), id.index),
+			("add", "Berlin", 2)
+		);
+		assert_eq!(id.to_string(), "C:/fixtures/a.json:add:Berlin:2");
+		assert!("add:Berlin:2".parse::<IndexId>().is_err());
+		assert!("a.json:add:Berlin:x".parse::<IndexId>().is_err());
+
+		let listed = Selection::new(vec![id.clone(), "b.json:sub:Berlin:0".parse().unwrap()]);
+		assert!(listed.selects("C:/fixtures/a.json", "add", "Berlin", 2));
+		assert!(!listed.selects("b.json", "add", "Berlin", 2));
+		assert_eq!(
+			listed.files(),
+			Some(vec![
+				PathBuf::from("C:/fixtures/a.json"),
+				PathBuf::from("b.json")
+			])
+		);
+
+		let failed = Selection::new(vec![IndexId {
+			file: None,
+			..id.clone()
+		}]);
+		assert!(failed.selects("other.json", "add", "Berlin", 2));
+		assert_eq!(failed.files(), None);
+		assert_eq!(listed.intersect(&failed), Selection::new(vec![id]));
+	}
+}
@@ -0,0 +1,79 @@
+//! Wall time statistics of repeated executions of an index.
+
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timings {
+	pub runs: usize,
+	pub min: Duration,
+	pub median: Duration,
+	pub max: Duration,
+	/// Gas used by a single execution.
+	pub used_gas: u64,
+	/// Interpreter steps of a single execution.
+	pub steps: u64,
+}
+
+impl Timings {
+	/// Statistics of `times`, which must not be empty.
+	pub fn new(mut times: Vec<Duration>, used_gas: u64, steps: u64) -> Self {
+		times.sort();
+		Timings {
+			runs: times.len(),
+			min: times[0],
+			median: times[times.len() / 2],
+			max: times[times.len() - 1],
+			used_gas,
+			steps,
+		}
+	}
+
+	/// Gas executed per second at the median time.
+	pub fn gas_per_second(&self) -> f64 {
+		self.used_gas as f64 / self.median.as_secs_f64().max(1e-9)
+	}
+
+	/// Steps executed per second at the median time.
+	pub fn steps_per_second(&self) -> f64 {
+		self.steps as f64 / self.median.as_secs_f64().max(1e-9)
+	}
+}
+
+impl fmt::Display for Timings {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} runs: min {:?}, median {:?}, max {:?}, {:.1} Mgas/s, {:.2} Msteps/s",
+			self.runs,
+			self.min,
+			self.median,
+			self.max,
+			self.gas_per_second() / 1e6,
+			self.steps_per_second() / 1e6
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn statistics() {
+		let times = [750, 100, 250, 1000, 500]
+			.iter()
+			.map(|ms| Duration::from_millis(*ms))
+			.collect();
+		let timings = Timings::new(times, 50_000_000, 125_000);
+		assert_eq!(timings.min, Duration::from_millis(100));
+		assert_eq!(timings.median, Duration::from_millis(500));
+		assert_eq!(timings.max, Duration::from_secs(1));
+		assert_eq!(timings.gas_per_second(), 100_000_000.0);
+		assert_eq!(timings.steps_per_second(), 250_000.0);
+		assert_eq!(
+			timings.to_string(),
+			"5 runs: min 100ms, median 500ms, max 1s, 100.0 Mgas/s, 0.25 Msteps/s"
+		);
+	}
+}
@@ -0,0 +1,181 @@
+//! Step of `git bisect run` on the evm crate: rebuild the harness against the
+//! checked out evm sources and run a single test of a fixture with it,
+//! exiting with the status bisect expects of a good, bad or untestable
+//! commit.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+
+/// Exit status `git bisect run` takes for a commit it cannot test.
+pub const SKIP_STATUS: i32 = 125;
+
+/// Manifest of the harness, rebuilt at every step.
+pub const MANIFEST: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/Cargo.toml");
+
+/// Outcome of a bisect step.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Verdict {
+	/// The test passed.
+	Good,
+	/// The test failed, or the runner crashed on it.
+	Bad,
+	/// The commit cannot be tested, e.g. the harness does not build on it.
+	Skip(String),
+}
+
+impl Verdict {
+	/// Exit status telling `git bisect run` the verdict.
+	pub fn exit_code(&self) -> i32 {
+		match self {
+			Verdict::Good => 0,
+			Verdict::Bad => 1,
+			Verdict::Skip(_) => SKIP_STATUS,
+		}
+	}
+}
+
+impl fmt::Display for Verdict {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Verdict::Good => f.write_str("good"),
+			Verdict::Bad => f.write_str("bad"),
+			Verdict::Skip(reason) => write!(f, "skip: {}", reason),
+		}
+	}
+}
+
+/// A test to bisect on.
+#[derive(Debug, Clone)]
+pub struct Bisect {
+	pub fixture: PathBuf,
+	pub test: String,
+	/// Fork to run the test on, all of its forks if `None`.
+	pub fork: Option<String>,
+	/// Directory the harness is built into, apart from the running one.
+	pub target_dir: PathBuf,
+}
+
+/// Lines of `--tests-from` selecting every index of `test` in the state
+/// fixture `json` of `file`, only those of `fork` if given.
+pub fn index_list(
+	json: &[u8],
+	file: &str,
+	test: &str,
+	fork: Option<&str>,
+) -> Result<Vec<String>, String> {
+	let tests: BTreeMap<String, serde_json::Value> =
+		serde_json::from_slice(json).map_err(|err| err.to_string())?;
+	let post = tests
+		.get(test)
+		.ok_or_else(|| format!("no test {}", test))?
+		.get("post")
+		.and_then(|post| post.as_object())
+		.ok_or_else(|| format!("test {} is not a state test", test))?;
+	let lines = post
+		.iter()
+		.filter(|(name, _)| fork.map_or(true, |fork| fork == name.as_str()))
+		.flat_map(|(name, states)| {
+			let count = states.as_array().map_or(0, Vec::len);
+			(0..count).map(move |index| format!("{}:{}:{}:{}", file, test, name, index))
+		})
+		.collect::<Vec<_>>();
+	if lines.is_empty() {
+		return Err(format!("test {} has no index to run", test));
+	}
+	Ok(lines)
+}
+
+impl Bisect {
+	/// Build the harness with the evm sources checked out, returning its
+	/// binary.
+	fn build(&self) -> Result<PathBuf, String> {
+		let status = Command::new(std::env::var("CARGO").unwrap_or_else(|_| "cargo".into()))
+			.args(["build", "--release", "--bin", env!("CARGO_PKG_NAME")])
+			.arg("--manifest-path")
+			.arg(MANIFEST)
+			.arg("--target-dir")
+			.arg(&self.target_dir)
+			.status()
+			.map_err(|err| format!("run cargo failed: {}", err))?;
+		if !status.success() {
+			return Err(format!("build failed, cargo {}", status));
+		}
+		Ok(self.target_dir.join("release").join(env!("CARGO_PKG_NAME")))
+	}
+
+	/// Run the test with `binary`, reading the indexes from `list`.
+	fn run(&self, binary: &Path, list: &Path) -> Verdict {
+		let status = match Command::new(binary)
+			.arg("state")
+			.arg("--tests-from")
+			.arg(list)
+			.status()
+		{
+			Ok(status) => status,
+			Err(err) => return Verdict::Skip(format!("run {} failed: {}", binary.display(), err)),
+		};
+		match status.code() {
+			Some(0) => Verdict::Good,
+			Some(1) | None => Verdict::Bad,
+			Some(code) => Verdict::Skip(format!("runner exited with status {}", code)),
+		}
+	}
+
+	/// Rebuild the harness and run the test with it.
+	pub fn step(&self) -> Verdict {
+		let json = match fs::read(&self.fixture) {
+			Ok(json) => json,
+			Err(err) => return Verdict::Skip(format!("{}: {}", self.fixture.display(), err)),
+		};
+		let file = self.fixture.display().to_string();
+		let lines = match index_list(&json, &file, &self.test, self.fork.as_deref()) {
+			Ok(lines) => lines,
+			Err(err) => return Verdict::Skip(format!("{}: {}", file, err)),
+		};
+		let binary = match self.build() {
+			Ok(binary) => binary,
+			Err(err) => return Verdict::Skip(err),
+		};
+		let list = std::env::temp_dir().join(format!("evm-jsontests-bisect-{}.txt", process::id()));
+		if let Err(err) = fs::write(&list, lines.join("\n")) {
+			return Verdict::Skip(format!("{}: {}", list.display(), err));
+		}
+		let verdict = self.run(&binary, &list);
+		let _ = fs::remove_file(&list);
+		verdict
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn indexes_of_a_test() {
+		let json = br#"{
+			"add": { "post": { "Berlin": [{}, {}], "Istanbul": [{}] } },
+			"vmTest": { "exec": {} }
+		}"#;
+		assert_eq!(
+			index_list(json, "a.json", "add", None),
+			Ok(vec![
+				"a.json:add:Berlin:0".to_string(),
+				"a.json:add:Berlin:1".to_string(),
+				"a.json:add:Istanbul:0".to_string(),
+			])
+		);
+		assert_eq!(
+			index_list(json, "a.json", "add", Some("Istanbul")).unwrap(),
+			vec!["a.json:add:Istanbul:0".to_string()]
+		);
+		assert!(index_list(json, "a.json", "add", Some("London")).is_err());
+		assert!(index_list(json, "a.json", "vmTest", None).is_err());
+		assert!(index_list(json, "a.json", "sub", None).is_err());
+
+		assert_eq!(Verdict::Bad.exit_code(), 1);
+		assert_eq!(Verdict::Skip("no build".into()).exit_code(), SKIP_STATUS);
+	}
+}
@@ -0,0 +1,1001 @@
+use crate::chainspec::{ChainSpec, DaoFork};
+use crate::exceptions;
+use crate::fixture::fork_spec;
+use crate::genesis::{Genesis, GenesisError, GenesisHeader};
+use crate::header::{self as rules, Fields};
+use crate::overlay::{Checkpoint, Journal};
+use crate::requests::{
+	consolidation_request_contract, deposit_contract, deposit_requests, system_address,
+	withdrawal_request_contract, RequestError, Requests, REQUESTS_FORKS, SYSTEM_CALL_GAS,
+};
+use crate::roots::{receipts_root, transactions_root, PostState, Receipt};
+use crate::state::{system_call, transact, unsupported, Transaction};
+use crate::summary::{Outcome, SkipReason};
+use crate::transaction::SignedTransaction;
+use crate::utils::*;
+use crate::{cprint, cprintln};
+use ethjson::spec::ForkSpec;
+use evm::backend::{Log, MemoryAccount, MemoryVicinity};
+use primitive_types::{H160, H256, U256};
+use rlp::Rlp;
+use serde::Deserialize;
+use sha3::{Digest, Keccak256};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::time::Duration;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct FixtureBlock {
+	#[serde(deserialize_with = "deserialize_hex")]
+	pub rlp: Vec<u8>,
+	/// Reason the block must be rejected, for invalid blocks.
+	#[serde(default)]
+	pub expect_exception: Option<String>,
+}
+
+/// Blockchain test in the execution-spec-tests fixture format.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Test {
+	pub network: String,
+	pub genesis_block_header: GenesisHeader,
+	/// Encoding of the genesis block the header must match.
+	#[serde(
+		rename = "genesisRLP",
+		default,
+		deserialize_with = "deserialize_optional_hex"
+	)]
+	pub genesis_rlp: Option<Vec<u8>>,
+	pub blocks: Vec<FixtureBlock>,
+	pre: BTreeMap<H160, JsonAccount>,
+	#[serde(default)]
+	post_state: Option<BTreeMap<H160, JsonAccount>>,
+	#[serde(default)]
+	pub post_state_hash: Option<H256>,
+	#[serde(rename = "lastblockhash")]
+	pub last_block_hash: H256,
+}
+
+fn keccak(bytes: &[u8]) -> H256 {
+	H256::from_slice(Keccak256::digest(bytes).as_slice())
+}
+
+/// Base block reward of proof-of-work forks, lowered by EIP-649 in
+/// Byzantium and EIP-1234 in Constantinople. Ommers and the miner including
+/// them are paid from it.
+fn block_reward(spec: &ForkSpec) -> U256 {
+	match spec {
+		ForkSpec::Frontier | ForkSpec::Homestead | ForkSpec::EIP150 | ForkSpec::EIP158 => {
+			U256::from(5) * U256::exp10(18)
+		}
+		ForkSpec::Byzantium => U256::from(3) * U256::exp10(18),
+		_ => U256::from(2) * U256::exp10(18),
+	}
+}
+
+/// Whether receipts under `spec` record the status of their transaction
+/// instead of the state root after it (EIP-658).
+fn byzantium(spec: &ForkSpec) -> bool {
+	!matches!(
+		spec,
+		ForkSpec::Frontier | ForkSpec::Homestead | ForkSpec::EIP150 | ForkSpec::EIP158
+	)
+}
+
+struct Header {
+	hash: H256,
+	parent_hash: H256,
+	ommers_hash: H256,
+	coinbase: H160,
+	state_root: H256,
+	transactions_root: H256,
+	receipts_root: H256,
+	difficulty: U256,
+	number: U256,
+	gas_limit: U256,
+	gas_used: U256,
+	timestamp: U256,
+	extra_data: Vec<u8>,
+	base_fee: Option<U256>,
+	blob_gas_used: Option<u64>,
+	excess_blob_gas: Option<u64>,
+	requests_hash: Option<H256>,
+}
+
+impl Header {
+	fn decode(rlp: &Rlp) -> Result<Self, rlp::DecoderError> {
+		Ok(Header {
+			hash: keccak(rlp.as_raw()),
+			parent_hash: rlp.val_at(0)?,
+			ommers_hash: rlp.val_at(1)?,
+			coinbase: rlp.val_at(2)?,
+			state_root: rlp.val_at(3)?,
+			transactions_root: rlp.val_at(4)?,
+			receipts_root: rlp.val_at(5)?,
+			difficulty: rlp.val_at(7)?,
+			number: rlp.val_at(8)?,
+			gas_limit: rlp.val_at(9)?,
+			gas_used: rlp.val_at(10)?,
+			timestamp: rlp.val_at(11)?,
+			extra_data: rlp.val_at(12)?,
+			// Fields of later forks, after the mix hash and nonce; 16 is
+			// the withdrawals root of Shanghai.
+			base_fee: optional_at(rlp, 15)?,
+			blob_gas_used: optional_at(rlp, 17)?,
+			excess_blob_gas: optional_at(rlp, 18)?,
+			// After the parent beacon block root of Cancun.
+			requests_hash: optional_at(rlp, 20)?,
+		})
+	}
+
+	fn fields(&self) -> Fields {
+		Fields {
+			number: self.number,
+			timestamp: self.timestamp,
+			gas_limit: self.gas_limit,
+			gas_used: self.gas_used,
+			extra_data: self.extra_data.clone(),
+			base_fee: self.base_fee,
+			blob_gas_used: self.blob_gas_used,
+			excess_blob_gas: self.excess_blob_gas,
+		}
+	}
+}
+
+/// Item `index` of the list `rlp`, if the list is long enough to have it.
+fn optional_at<T: rlp::Decodable>(rlp: &Rlp, index: usize) -> Result<Option<T>, rlp::DecoderError> {
+	if rlp.item_count()? > index {
+		rlp.val_at(index).map(Some)
+	} else {
+		Ok(None)
+	}
+}
+
+/// Header fields of a canonical block its children are validated against.
+#[derive(Clone, Debug)]
+struct Ancestor {
+	hash: H256,
+	fields: Fields,
+}
+
+impl From<&Header> for Ancestor {
+	fn from(header: &Header) -> Self {
+		Ancestor {
+			hash: header.hash,
+			fields: header.fields(),
+		}
+	}
+}
+
+/// Canonical blocks an ommer's parent may be among, counting the parent of
+/// the block including it.
+const OMMER_GENERATIONS: usize = 7;
+/// Ommers a block may include.
+const MAX_OMMERS: usize = 2;
+
+/// Why a block was rejected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Rejection {
+	Rlp(rlp::DecoderError),
+	UnknownParent,
+	Header(rules::Rejection),
+	NoForkActive,
+	UnsupportedFork,
+	InvalidSignature(String),
+	NonceTooLow,
+	NonceTooHigh,
+	InsufficientFunds,
+	/// Transaction `index` of the block has a gas limit above the gas the
+	/// transactions before it left of the block's.
+	GasLimitReached {
+		index: usize,
+		gas_limit: U256,
+		gas_left: U256,
+	},
+	GasUsedMismatch {
+		header: U256,
+		computed: U256,
+	},
+	TransactionsRootMismatch {
+		header: H256,
+		computed: H256,
+	},
+	ReceiptsRootMismatch {
+		header: H256,
+		computed: H256,
+	},
+	OmmersHashMismatch,
+	TooManyOmmers,
+	DuplicateOmmer,
+	OmmerInChain,
+	OmmerIsAncestor,
+	OmmerParentNotAncestor,
+	InvalidOmmer(Box<Rejection>),
+	StateRootMismatch {
+		header: H256,
+		computed: H256,
+	},
+	Requests(RequestError),
+	RequestsHashMismatch {
+		header: Option<H256>,
+		computed: H256,
+	},
+}
+
+impl Rejection {
+	/// Reason of the rejection in exception tables.
+	fn reason(&self) -> &'static str {
+		match self {
+			Rejection::Rlp(_) => "Block::Rlp",
+			Rejection::UnknownParent => "Block::UnknownParent",
+			Rejection::Header(rejection) => rejection.reason(),
+			Rejection::NoForkActive => "Block::NoForkActive",
+			Rejection::UnsupportedFork => "Block::UnsupportedFork",
+			Rejection::InvalidSignature(_) => "Block::InvalidSignature",
+			Rejection::NonceTooLow => "Block::NonceTooLow",
+			Rejection::NonceTooHigh => "Block::NonceTooHigh",
+			Rejection::InsufficientFunds => "Block::InsufficientFunds",
+			Rejection::GasLimitReached { .. } => "Block::GasLimitReached",
+			Rejection::GasUsedMismatch { .. } => "Block::GasUsedMismatch",
+			Rejection::TransactionsRootMismatch { .. } => "Block::TransactionsRootMismatch",
+			Rejection::ReceiptsRootMismatch { .. } => "Block::ReceiptsRootMismatch",
+			Rejection::OmmersHashMismatch => "Block::OmmersHashMismatch",
+			Rejection::TooManyOmmers => "Block::TooManyOmmers",
+			Rejection::DuplicateOmmer => "Block::DuplicateOmmer",
+			Rejection::OmmerInChain => "Block::OmmerInChain",
+			Rejection::OmmerIsAncestor => "Block::OmmerIsAncestor",
+			Rejection::OmmerParentNotAncestor => "Block::OmmerParentNotAncestor",
+			Rejection::InvalidOmmer(_) => "Block::InvalidOmmer",
+			Rejection::StateRootMismatch { .. } => "Block::StateRootMismatch",
+			Rejection::Requests(err) => err.reason(),
+			Rejection::RequestsHashMismatch { .. } => "Block::RequestsHashMismatch",
+		}
+	}
+}
+
+impl fmt::Display for Rejection {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Rejection::Rlp(err) => write!(f, "rlp: {:?}", err),
+			Rejection::UnknownParent => write!(f, "unknown parent"),
+			Rejection::Header(rejection) => write!(f, "{}", rejection),
+			Rejection::NoForkActive => write!(f, "no fork active"),
+			Rejection::UnsupportedFork => write!(f, "unsupported fork"),
+			Rejection::InvalidSignature(err) => write!(f, "{}", err),
+			Rejection::NonceTooLow => write!(f, "nonce too low"),
+			Rejection::NonceTooHigh => write!(f, "nonce too high"),
+			Rejection::InsufficientFunds => write!(f, "insufficient funds"),
+			Rejection::GasLimitReached {
+				index,
+				gas_limit,
+				gas_left,
+			} => write!(
+				f,
+				"transaction {} gas limit {} above block gas left {}",
+				index, gas_limit, gas_left
+			),
+			Rejection::GasUsedMismatch { header, computed } => write!(
+				f,
+				"gas used mismatch: header {}, computed {}",
+				header, computed
+			),
+			Rejection::TransactionsRootMismatch { header, computed } => write!(
+				f,
+				"transactions root mismatch: header {:?}, computed {:?}",
+				header, computed
+			),
+			Rejection::ReceiptsRootMismatch { header, computed } => write!(
+				f,
+				"receipts root mismatch: header {:?}, computed {:?}",
+				header, computed
+			),
+			Rejection::OmmersHashMismatch => write!(f, "ommers hash mismatch"),
+			Rejection::TooManyOmmers => write!(f, "too many ommers"),
+			Rejection::DuplicateOmmer => write!(f, "duplicate ommer"),
+			Rejection::OmmerInChain => write!(f, "ommer already included"),
+			Rejection::OmmerIsAncestor => write!(f, "ommer is an ancestor"),
+			Rejection::OmmerParentNotAncestor => {
+				write!(f, "ommer's parent is not a recent ancestor")
+			}
+			Rejection::InvalidOmmer(rejection) => write!(f, "invalid ommer: {}", rejection),
+			Rejection::StateRootMismatch { header, computed } => write!(
+				f,
+				"state root mismatch: header {:?}, computed {:?}",
+				header, computed
+			),
+			Rejection::Requests(err) => write!(f, "{}", err),
+			Rejection::RequestsHashMismatch { header, computed } => write!(
+				f,
+				"requests hash mismatch: header {:?}, computed {:?}",
+				header, computed
+			),
+		}
+	}
+}
+
+/// Check `header` against its parent under the fork `chain` follows at it,
+/// returning the fork.
+fn check_header<'a>(
+	chain: &'a ChainSpec,
+	header: &Header,
+	parent: &Ancestor,
+) -> Result<&'a str, Rejection> {
+	if header.parent_hash != parent.hash {
+		return Err(Rejection::UnknownParent);
+	}
+	let fork = chain
+		.fork_at(header.number, header.timestamp)
+		.ok_or(Rejection::NoForkActive)?;
+	rules::check(fork, &header.fields(), &parent.fields).map_err(Rejection::Header)?;
+	if let Some(dao) = &chain.dao_fork {
+		rules::check_dao(dao.block, &header.fields()).map_err(Rejection::Header)?;
+	}
+	Ok(fork)
+}
+
+/// Check `ommer` may be included in a block whose canonical ancestors are
+/// `ancestors`, oldest first, and which are not already in `included`.
+fn check_ommer(
+	chain: &ChainSpec,
+	ommer: &Header,
+	ancestors: &[Ancestor],
+	included: &BTreeSet<H256>,
+) -> Result<(), Rejection> {
+	if included.contains(&ommer.hash) {
+		return Err(Rejection::OmmerInChain);
+	}
+	if ancestors.iter().any(|ancestor| ancestor.hash == ommer.hash) {
+		return Err(Rejection::OmmerIsAncestor);
+	}
+	let recent = &ancestors[ancestors.len().saturating_sub(OMMER_GENERATIONS)..];
+	// A sibling of the block's parent at the earliest, not of the block.
+	let parent = recent[..recent.len() - 1]
+		.iter()
+		.find(|ancestor| ancestor.hash == ommer.parent_hash)
+		.ok_or(Rejection::OmmerParentNotAncestor)?;
+	check_header(chain, ommer, parent)
+		.map(|_| ())
+		.map_err(|rejection| Rejection::InvalidOmmer(Box::new(rejection)))
+}
+
+/// Add `amount` to the balance of `address`.
+fn credit(journal: &mut Journal, address: H160, amount: U256) {
+	let mut account = journal
+		.state()
+		.get(&address)
+		.cloned()
+		.unwrap_or_else(empty_account);
+	account.balance += amount;
+	journal.commit(vec![(address, Some(account))].into_iter().collect());
+}
+
+/// Move the balances of the accounts the DAO fork drains to its beneficiary.
+fn drain_dao(journal: &mut Journal, dao: &DaoFork) {
+	let mut drained = U256::zero();
+	let mut changes = BTreeMap::new();
+	for address in &dao.accounts {
+		if let Some(account) = journal.state().get(address) {
+			drained += account.balance;
+			let account = MemoryAccount {
+				balance: U256::zero(),
+				..account.clone()
+			};
+			changes.insert(*address, Some(account));
+		}
+	}
+	journal.commit(changes);
+	credit(journal, dao.beneficiary, drained);
+}
+
+/// Requests of the block of `header` whose transactions emitted `logs`: its
+/// deposits, and the requests the system calls to the request contracts
+/// return on the state of `journal` after the transactions.
+fn collect_requests(
+	spec: &ForkSpec,
+	header: &Header,
+	ancestors: &[Ancestor],
+	journal: &mut Journal,
+	logs: &[Log],
+) -> Result<Requests, Rejection> {
+	let deposits = deposit_requests(logs, deposit_contract()).map_err(Rejection::Requests)?;
+	let vicinity = MemoryVicinity {
+		gas_price: U256::zero(),
+		origin: system_address(),
+		block_hashes: ancestors
+			.iter()
+			.rev()
+			.map(|ancestor| ancestor.hash)
+			.collect(),
+		block_number: header.number,
+		block_coinbase: header.coinbase,
+		block_timestamp: header.timestamp,
+		block_difficulty: header.difficulty,
+		block_gas_limit: header.gas_limit,
+		chain_id: U256::one(),
+	};
+	let mut call = |contract: H160| -> Result<Vec<u8>, Rejection> {
+		let message = Transaction {
+			caller: system_address(),
+			to: Some(contract),
+			value: U256::zero(),
+			data: Vec::new(),
+			gas_limit: SYSTEM_CALL_GAS,
+			access_list: Vec::new(),
+			pricing: None,
+		};
+		let (backend, exit_reason, output) =
+			system_call(spec, &vicinity, Cow::Borrowed(journal.state()), &message)
+				.ok_or(Rejection::UnsupportedFork)?;
+		if !exit_reason.is_succeed() {
+			return Err(Rejection::Requests(RequestError::SystemCallFailed {
+				contract,
+				reason: format!("{:?}", exit_reason),
+			}));
+		}
+		let changes = backend.into_changes();
+		journal.commit(changes);
+		Ok(output)
+	};
+	let withdrawals = call(withdrawal_request_contract())?;
+	let consolidations = call(consolidation_request_contract())?;
+	Requests::new(deposits, withdrawals, consolidations).map_err(Rejection::Requests)
+}
+
+/// Execute a block on the state of `journal` under the fork `chain` follows
+/// at it, returning its header and the hashes of its ommers, or the reason
+/// the block is invalid. `ancestors` are all previous blocks, oldest first,
+/// and `included` the ommers they included. The journal is left with the
+/// changes of an invalid block up to where it was rejected.
+fn apply_block(
+	chain: &ChainSpec,
+	rlp: &[u8],
+	ancestors: &[Ancestor],
+	journal: &mut Journal,
+	included: &BTreeSet<H256>,
+) -> Result<(Header, Vec<H256>), Rejection> {
+	let block = Rlp::new(rlp);
+	let header = Header::decode(&block.at(0).map_err(Rejection::Rlp)?).map_err(Rejection::Rlp)?;
+	let fork = check_header(chain, &header, ancestors.last().unwrap())?;
+	let spec = fork_spec(fork).ok_or(Rejection::NoForkActive)?;
+	// The irregular state change of the DAO fork comes before the
+	// transactions of its block.
+	if let Some(dao) = &chain.dao_fork {
+		if header.number == U256::from(dao.block) {
+			drain_dao(journal, dao);
+		}
+	}
+
+	// Legacy transactions are lists, typed ones byte strings of their
+	// envelope.
+	let encodings = block
+		.at(1)
+		.map_err(Rejection::Rlp)?
+		.iter()
+		.map(|item| {
+			if item.is_list() {
+				Ok(item.as_raw())
+			} else {
+				item.data()
+			}
+		})
+		.collect::<Result<Vec<_>, _>>()
+		.map_err(Rejection::Rlp)?;
+	let computed = transactions_root(&encodings);
+	if computed != header.transactions_root {
+		return Err(Rejection::TransactionsRootMismatch {
+			header: header.transactions_root,
+			computed,
+		});
+	}
+
+	let mut gas_used = U256::zero();
+	let mut receipts = Vec::new();
+	for (index, raw) in encodings.into_iter().enumerate() {
+		let tx = SignedTransaction::decode(raw).map_err(Rejection::Rlp)?;
+		let caller = tx
+			.sender()
+			.map_err(|err| Rejection::InvalidSignature(err.to_string()))?;
+		let account = journal
+			.state()
+			.get(&caller)
+			.cloned()
+			.unwrap_or_else(empty_account);
+		if tx.nonce < account.nonce {
+			return Err(Rejection::NonceTooLow);
+		}
+		if tx.nonce > account.nonce {
+			return Err(Rejection::NonceTooHigh);
+		}
+		if account.balance
+			< tx.gas_limit
+				.saturating_mul(tx.gas_price)
+				.saturating_add(tx.value)
+		{
+			return Err(Rejection::InsufficientFunds);
+		}
+		// Blocks stay within their gas limit by rejecting transactions that
+		// may use more gas than is left, whatever they end up using.
+		let gas_left = header.gas_limit - gas_used;
+		if tx.gas_limit > gas_left {
+			return Err(Rejection::GasLimitReached {
+				index,
+				gas_limit: tx.gas_limit,
+				gas_left,
+			});
+		}
+
+		let vicinity = MemoryVicinity {
+			gas_price: tx.gas_price,
+			origin: caller,
+			block_hashes: ancestors
+				.iter()
+				.rev()
+				.map(|ancestor| ancestor.hash)
+				.collect(),
+			block_number: header.number,
+			block_coinbase: header.coinbase,
+			block_timestamp: header.timestamp,
+			block_difficulty: header.difficulty,
+			block_gas_limit: header.gas_limit,
+			chain_id: U256::from(tx.chain_id.unwrap_or(1)),
+		};
+		let execution = transact(
+			&spec,
+			&vicinity,
+			Cow::Borrowed(journal.state()),
+			&tx.message(caller),
+			(),
+		)
+		.ok_or(Rejection::UnsupportedFork)?;
+		gas_used += U256::from(execution.used_gas);
+		let succeeded = execution.exit_reason.is_succeed();
+		let logs = execution.logs;
+		let changes = execution.backend.into_changes();
+		journal.commit(changes);
+		receipts.push(Receipt {
+			tx_type: tx.tx_type,
+			post_state: if byzantium(&spec) {
+				PostState::Status(succeeded)
+			} else {
+				PostState::Root(state_root(journal.state()))
+			},
+			cumulative_gas_used: gas_used,
+			logs,
+		});
+	}
+	if gas_used != header.gas_used {
+		return Err(Rejection::GasUsedMismatch {
+			header: header.gas_used,
+			computed: gas_used,
+		});
+	}
+	let computed = receipts_root(&receipts);
+	if computed != header.receipts_root {
+		return Err(Rejection::ReceiptsRootMismatch {
+			header: header.receipts_root,
+			computed,
+		});
+	}
+
+	if REQUESTS_FORKS.contains(&fork) {
+		let logs = receipts
+			.iter()
+			.flat_map(|receipt| receipt.logs.iter().cloned())
+			.collect::<Vec<_>>();
+		let computed = collect_requests(&spec, &header, ancestors, journal, &logs)?.hash();
+		if header.requests_hash != Some(computed) {
+			return Err(Rejection::RequestsHashMismatch {
+				header: header.requests_hash,
+				computed,
+			});
+		}
+	}
+
+	let ommers = block.at(2).map_err(Rejection::Rlp)?;
+	if keccak(ommers.as_raw()) != header.ommers_hash {
+		return Err(Rejection::OmmersHashMismatch);
+	}
+	if ommers.item_count().map_err(Rejection::Rlp)? > MAX_OMMERS {
+		return Err(Rejection::TooManyOmmers);
+	}
+	let reward = block_reward(&spec);
+	let mut miner_reward = reward;
+	let mut ommer_hashes = Vec::new();
+	for ommer in ommers.iter() {
+		let ommer = Header::decode(&ommer).map_err(Rejection::Rlp)?;
+		if ommer_hashes.contains(&ommer.hash) {
+			return Err(Rejection::DuplicateOmmer);
+		}
+		check_ommer(chain, &ommer, ancestors, included)?;
+		ommer_hashes.push(ommer.hash);
+		// 7/8 of the block reward for a sibling of the parent, 1/8 less for
+		// every generation further back.
+		let ommer_reward = (ommer.number + 8 - header.number) * reward / 8;
+		credit(journal, ommer.coinbase, ommer_reward);
+		miner_reward += reward / 32;
+	}
+	credit(journal, header.coinbase, miner_reward);
+
+	let root = state_root(journal.state());
+	if root != header.state_root {
+		return Err(Rejection::StateRootMismatch {
+			header: header.state_root,
+			computed: root,
+		});
+	}
+	Ok((header, ommer_hashes))
+}
+
+/// A valid block.
+struct Node {
+	header: Ancestor,
+	parent: Option<H256>,
+	/// Encoding of the block, to execute it again when switching to its
+	/// chain.
+	rlp: Vec<u8>,
+	/// Difficulty of the block and all its ancestors.
+	total_difficulty: U256,
+	ommers: Vec<H256>,
+}
+
+/// Every valid block of the test, on any of its competing chains. The head
+/// of the canonical chain is the block with the most total difficulty, the
+/// first one to reach it on ties unless a child of the head does.
+///
+/// The state of one chain at a time is kept, in a journal with a checkpoint
+/// before each of its blocks. Switching to another chain reverts to the last
+/// block they share and executes the blocks of the other one from there.
+struct BlockTree {
+	nodes: BTreeMap<H256, Node>,
+	head: H256,
+	journal: Journal,
+	/// Blocks after genesis the state of the journal is after, oldest first,
+	/// each with the checkpoint before it.
+	applied: Vec<(H256, Checkpoint)>,
+}
+
+impl BlockTree {
+	fn new(genesis: &GenesisHeader, state: BTreeMap<H160, MemoryAccount>) -> Self {
+		let node = Node {
+			header: Ancestor {
+				hash: genesis.hash,
+				fields: Fields {
+					number: genesis.number,
+					timestamp: genesis.timestamp,
+					gas_limit: genesis.gas_limit,
+					gas_used: genesis.gas_used,
+					extra_data: genesis.extra_data.clone(),
+					base_fee: genesis.base_fee_per_gas,
+					blob_gas_used: genesis.blob_gas_used.map(|used| used.low_u64()),
+					excess_blob_gas: genesis.excess_blob_gas.map(|excess| excess.low_u64()),
+				},
+			},
+			parent: None,
+			rlp: Vec::new(),
+			total_difficulty: genesis.difficulty,
+			ommers: Vec::new(),
+		};
+		let mut nodes = BTreeMap::new();
+		nodes.insert(genesis.hash, node);
+		BlockTree {
+			nodes,
+			head: genesis.hash,
+			journal: Journal::new(state),
+			applied: Vec::new(),
+		}
+	}
+
+	fn head(&self) -> &Node {
+		&self.nodes[&self.head]
+	}
+
+	/// Blocks from genesis to `hash`, and the ommers they included.
+	fn lineage(&self, hash: H256) -> (Vec<Ancestor>, BTreeSet<H256>) {
+		let mut ancestors = Vec::new();
+		let mut included = BTreeSet::new();
+		let mut next = Some(hash);
+		while let Some(node) = next.and_then(|hash| self.nodes.get(&hash)) {
+			ancestors.push(node.header.clone());
+			included.extend(node.ommers.iter().cloned());
+			next = node.parent;
+		}
+		ancestors.reverse();
+		(ancestors, included)
+	}
+
+	/// Execute the block `rlp` on the state of the journal, which is the one
+	/// after `parent`, reverting its changes if it is invalid.
+	fn execute(
+		&mut self,
+		chain: &ChainSpec,
+		rlp: &[u8],
+		parent: H256,
+	) -> Result<(Header, Vec<H256>), Rejection> {
+		let (ancestors, included) = self.lineage(parent);
+		let checkpoint = self.journal.checkpoint();
+		match apply_block(chain, rlp, &ancestors, &mut self.journal, &included) {
+			Ok((header, ommers)) => {
+				self.applied.push((header.hash, checkpoint));
+				Ok((header, ommers))
+			}
+			Err(rejection) => {
+				self.journal.revert_to(checkpoint);
+				Err(rejection)
+			}
+		}
+	}
+
+	/// Bring the journal to the state after the block `hash`.
+	fn switch_to(&mut self, chain: &ChainSpec, hash: H256) {
+		let (ancestors, _) = self.lineage(hash);
+		let path = ancestors
+			.iter()
+			.skip(1)
+			.map(|ancestor| ancestor.hash)
+			.collect::<Vec<_>>();
+		let shared = self
+			.applied
+			.iter()
+			.zip(&path)
+			.take_while(|((applied, _), hash)| applied == *hash)
+			.count();
+		if let Some((_, checkpoint)) = self.applied.get(shared) {
+			self.journal.revert_to(*checkpoint);
+		}
+		self.applied.truncate(shared);
+		for hash in &path[shared..] {
+			let node = &self.nodes[hash];
+			let (rlp, parent) = (node.rlp.clone(), node.parent.unwrap());
+			if let Err(rejection) = self.execute(chain, &rlp, parent) {
+				panic!(
+					"Block {:?} rejected executing it again: {}",
+					hash, rejection
+				);
+			}
+		}
+	}
+
+	/// Execute the block `rlp` on top of its parent, which may be on any
+	/// chain, making it the head if its chain has the most difficulty. The
+	/// tree is left unchanged if the block is invalid.
+	fn apply(&mut self, chain: &ChainSpec, rlp: &[u8]) -> Result<(), Rejection> {
+		let block = Rlp::new(rlp);
+		let parent = Header::decode(&block.at(0).map_err(Rejection::Rlp)?)
+			.map_err(Rejection::Rlp)?
+			.parent_hash;
+		let parent_difficulty = self
+			.nodes
+			.get(&parent)
+			.ok_or(Rejection::UnknownParent)?
+			.total_difficulty;
+		self.switch_to(chain, parent);
+		let (header, ommers) = self.execute(chain, rlp, parent)?;
+
+		let total_difficulty = parent_difficulty + header.difficulty;
+		let head = self.head().total_difficulty;
+		if total_difficulty > head || (total_difficulty == head && parent == self.head) {
+			self.head = header.hash;
+		}
+		self.nodes.insert(
+			header.hash,
+			Node {
+				header: Ancestor::from(&header),
+				parent: Some(parent),
+				rlp: rlp.to_vec(),
+				total_difficulty,
+				ommers,
+			},
+		);
+		Ok(())
+	}
+
+	/// State after the head block.
+	fn head_state(&mut self, chain: &ChainSpec) -> &BTreeMap<H160, MemoryAccount> {
+		self.switch_to(chain, self.head);
+		self.journal.state()
+	}
+}
+
+/// Options controlling how blockchain tests are run.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+	/// Networks to run, all supported ones if empty. Transition networks
+	/// also run if all their forks are listed.
+	pub forks: Vec<String>,
+	/// Time a single test may take.
+	pub timeout: Option<Duration>,
+	/// Do not print a status line per test.
+	pub quiet: bool,
+}
+
+pub fn test(name: &str, test: Test) -> Outcome {
+	test_with_options(name, test, &Options::default())
+}
+
+pub fn test_with_options(name: &str, test: Test, options: &Options) -> Outcome {
+	let selected = |fork: &String| options.forks.is_empty() || options.forks.contains(fork);
+	let forks_selected = ChainSpec::of_network(&test.network).map_or(false, |chain| {
+		chain
+			.forks
+			.iter()
+			.all(|activation| selected(&activation.fork))
+	});
+	if !selected(&test.network) && !forks_selected {
+		let mut outcome = Outcome::default();
+		outcome.skip(&test.network, SkipReason::Filtered, 1);
+		return outcome;
+	}
+
+	let name = name.to_string();
+	let options = options.clone();
+	run_with_stack(options.timeout, move || test_run(&name, test, &options))
+}
+
+fn test_run(name: &str, test: Test, options: &Options) -> Outcome {
+	// Transition networks switch forks, and with them the gasometer config
+	// and builtins, from a block on.
+	let chain = ChainSpec::of_network(&test.network);
+	let reason = match &chain {
+		Some(chain) => chain
+			.forks
+			.iter()
+			.find_map(|activation| unsupported(&activation.fork)),
+		None => unsupported(&test.network),
+	};
+	let chain = match (chain, reason) {
+		(Some(chain), None) => chain,
+		(_, reason) => {
+			let reason = reason.unwrap_or_else(|| format!("network {} unknown", test.network));
+			if !options.quiet {
+				cprintln!("Skip: {}", reason);
+			}
+			let mut outcome = Outcome::default();
+			outcome.skip(&test.network, SkipReason::UnsupportedFork, 1);
+			return outcome;
+		}
+	};
+
+	if !options.quiet {
+		cprint!("Running {}:{} ... ", name, test.network);
+		flush();
+	}
+
+	let genesis = Genesis::new(test.genesis_block_header, test.pre);
+	match genesis.verify(test.genesis_rlp.as_deref()) {
+		Ok(()) => {}
+		Err(GenesisError::StateRoot(mismatch)) => mismatch.fail(&genesis.state),
+		Err(err) => panic!("{}", err),
+	}
+
+	let mut tree = BlockTree::new(&genesis.header, genesis.state);
+	for (i, block) in test.blocks.iter().enumerate() {
+		match (tree.apply(&chain, &block.rlp), &block.expect_exception) {
+			(Ok(()), None) => (),
+			// Rejected blocks leave the tree, and so the head and its state,
+			// as they were.
+			(Err(rejection), Some(exception)) => {
+				if !exceptions::table().is_expected(exception, rejection.reason()) {
+					let actual = exceptions::table()
+						.exception(rejection.reason())
+						.unwrap_or(rejection.reason());
+					panic!(
+						"Block {} rejected with {} ({}), expected exception {}",
+						i, actual, rejection, exception
+					);
+				}
+			}
+			(Ok(_), Some(exception)) => {
+				panic!("Block {} is valid, expected exception {}", i, exception)
+			}
+			(Err(rejection), None) => panic!("Block {} rejected: {}", i, rejection),
+		}
+	}
+
+	assert_eq!(tree.head, test.last_block_hash, "Last block hash not equal");
+	let state = tree.head_state(&chain);
+	if let Some(post_state) = test.post_state {
+		let post_state = post_state
+			.into_iter()
+			.map(|(address, account)| (address, account.into()))
+			.collect::<BTreeMap<_, _>>();
+		assert_valid_hash(&state_root(&post_state), state)
+			.unwrap_or_else(|mismatch| mismatch.fail(state));
+	}
+	if let Some(hash) = &test.post_state_hash {
+		assert_valid_hash(hash, state).unwrap_or_else(|mismatch| mismatch.fail(state));
+	}
+
+	if !options.quiet {
+		cprintln!("passed");
+	}
+	let mut outcome = Outcome::default();
+	outcome.count_fork(&test.network, 1);
+	outcome
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::b11r::{build_block, HeaderTemplate};
+	use crate::header::DAO_EXTRA_DATA;
+
+	fn account(nonce: u64, balance: U256) -> JsonAccount {
+		JsonAccount {
+			balance,
+			nonce: U256::from(nonce),
+			code: Vec::new(),
+			storage: BTreeMap::new(),
+		}
+	}
+
+	#[test]
+	fn dao_fork_drains_to_beneficiary() {
+		let dao = DaoFork::mainnet_at(1);
+		assert_eq!(dao.accounts.len(), 116);
+		let (first, last) = (dao.accounts[0], dao.accounts[115]);
+		let miner = H160::from_low_u64_be(0xc0ffee);
+		let ether = U256::exp10(18);
+
+		let mut pre = BTreeMap::new();
+		pre.insert(first, account(1, ether));
+		pre.insert(last, account(1, ether * 2));
+		let genesis = Genesis::of_alloc(pre.clone(), |header| GenesisHeader {
+			gas_limit: U256::from(1_000_000),
+			difficulty: U256::from(0x20000),
+			..header
+		});
+
+		// The drained accounts keep their nonce, the miner gets the Berlin
+		// block reward.
+		let mut post = BTreeMap::new();
+		post.insert(first, account(1, U256::zero()));
+		post.insert(last, account(1, U256::zero()));
+		post.insert(dao.beneficiary, account(0, ether * 3));
+		post.insert(miner, account(0, ether * 2));
+		let post_state = post
+			.clone()
+			.into_iter()
+			.map(|(address, account)| (address, account.into()))
+			.collect::<BTreeMap<_, MemoryAccount>>();
+
+		let header = HeaderTemplate {
+			parent_hash: genesis.header.hash,
+			ommers_hash: None,
+			miner,
+			state_root: state_root(&post_state),
+			transactions_root: None,
+			receipts_root: None,
+			logs_bloom: vec![0; 256],
+			difficulty: U256::from(0x20000),
+			number: U256::one(),
+			gas_limit: U256::from(1_000_000),
+			gas_used: U256::zero(),
+			timestamp: U256::from(10),
+			extra_data: DAO_EXTRA_DATA.to_vec(),
+			mix_hash: H256::zero(),
+			nonce: vec![0; 8],
+			base_fee_per_gas: None,
+			withdrawals_root: None,
+		};
+		let block = build_block(&header, &rlp::EMPTY_LIST_RLP, &[], None).unwrap();
+
+		let fixture = Test {
+			network: "BerlinToDaoAt1".to_string(),
+			genesis_block_header: genesis.header,
+			genesis_rlp: None,
+			blocks: vec![FixtureBlock {
+				rlp: block.rlp,
+				expect_exception: None,
+			}],
+			pre,
+			post_state: Some(post),
+			post_state_hash: None,
+			last_block_hash: block.hash,
+		};
+		let outcome = test("daoFork", fixture);
+		assert_eq!(outcome.executed, 1);
+		assert!(outcome.skips.is_empty());
+	}
+}
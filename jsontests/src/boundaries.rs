@@ -0,0 +1,209 @@
+//! Generated filler calling precompiles with boundary inputs: empty input,
+//! lengths that price modexp out of any gas, malformed bn128 points and the
+//! extremes of blake2f rounds. Expectations come from the builtins of every
+//! fork, so filling it checks the EVM against them.
+
+use crate::builtins;
+use crate::filler::{self, Filler};
+use primitive_types::H160;
+use serde_json::{json, Value};
+use sha3::{Digest, Keccak256};
+
+/// Name of the generated test.
+pub const NAME: &str = "precompileBoundaries";
+/// Contract calling the precompile addressed by the first word of calldata
+/// with the rest of it as input.
+const PROBE: &str = "0x0000000000000000000000000000000000001000";
+/// Gas given to every precompile call, well below what the transaction
+/// leaves so that it is not capped by EIP-150.
+const CALL_GAS: u64 = 1_000_000;
+
+/// Modulus of the bn128 base field, the first invalid coordinate.
+const BN128_MODULUS: &str = "30644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd47";
+
+/// A call of a precompile.
+#[derive(Debug, Clone)]
+pub struct Case {
+	pub label: &'static str,
+	pub address: H160,
+	pub input: Vec<u8>,
+}
+
+fn case(label: &'static str, index: u64, input: Vec<u8>) -> Case {
+	Case {
+		label,
+		address: H160::from_low_u64_be(index),
+		input,
+	}
+}
+
+fn word(value: u64) -> Vec<u8> {
+	let mut word = vec![0; 32];
+	word[24..].copy_from_slice(&value.to_be_bytes());
+	word
+}
+
+fn modexp_input(base_length: &[u8], exponent_length: &[u8], modulus_length: &[u8]) -> Vec<u8> {
+	[base_length, exponent_length, modulus_length].concat()
+}
+
+/// The point (1, 3), which is not on the curve.
+fn off_curve_point() -> Vec<u8> {
+	[word(1), word(3)].concat()
+}
+
+fn blake2f_input(rounds: u32, final_block: u8) -> Vec<u8> {
+	let mut input = rounds.to_be_bytes().to_vec();
+	input.resize(212, 0);
+	input.push(final_block);
+	input
+}
+
+/// All cases, in the order of the data of the generated transaction.
+pub fn cases() -> Vec<Case> {
+	let max_length = vec![0xff; 32];
+	vec![
+		case("ecrecoverEmpty", 1, Vec::new()),
+		case("sha256Empty", 2, Vec::new()),
+		case("ripemd160Empty", 3, Vec::new()),
+		case("identityEmpty", 4, Vec::new()),
+		case("modexpEmpty", 5, Vec::new()),
+		case(
+			"modexpMaxLengths",
+			5,
+			modexp_input(&max_length, &max_length, &max_length),
+		),
+		case(
+			"modexpMaxExponentLength",
+			5,
+			modexp_input(&word(1), &word(u32::MAX as u64), &word(1)),
+		),
+		case(
+			"modexpZeroModulus",
+			5,
+			[modexp_input(&word(1), &word(1), &word(1)), vec![2, 3, 0]].concat(),
+		),
+		case("bn128AddEmpty", 6, Vec::new()),
+		case("bn128AddOffCurve", 6, off_curve_point()),
+		case(
+			"bn128AddCoordinateOverflow",
+			6,
+			[hex::decode(BN128_MODULUS).unwrap(), word(2)].concat(),
+		),
+		case("bn128MulEmpty", 7, Vec::new()),
+		case("bn128MulOffCurve", 7, [off_curve_point(), word(2)].concat()),
+		case("bn128PairingEmpty", 8, Vec::new()),
+		case("bn128PairingTruncated", 8, vec![0; 191]),
+		case("blake2fEmpty", 9, Vec::new()),
+		case("blake2fZeroRounds", 9, blake2f_input(0, 1)),
+		case("blake2fMaxRounds", 9, blake2f_input(u32::MAX, 1)),
+		case("blake2fInvalidFinalFlag", 9, blake2f_input(12, 2)),
+	]
+}
+
+/// Whether the call of `case` succeeds on `fork` and what it returns, as
+/// the builtins of the fork compute it. An address without a builtin is an
+/// empty account, which calls succeed on.
+pub fn expected_call(fork: &str, case: &Case) -> (bool, Vec<u8>) {
+	let builtins = builtins::for_fork(fork).unwrap_or_default();
+	let builtin = match builtins.get(&case.address) {
+		Some(builtin) => builtin,
+		None => return (true, Vec::new()),
+	};
+	if builtin.cost(&case.input) > CALL_GAS.into() {
+		return (false, Vec::new());
+	}
+	match builtin.execute(&case.input) {
+		Ok(output) => (true, output),
+		Err(_) => (false, Vec::new()),
+	}
+}
+
+/// Code calling the precompile and storing in slots 0, 1 and 2 whether the
+/// call succeeded, the length of its output and the hash of it.
+fn probe_code() -> Vec<u8> {
+	let mut code = vec![
+		// PUSH1 32 CALLDATASIZE SUB DUP1 PUSH1 32 PUSH1 0 CALLDATACOPY
+		0x60, 0x20, 0x36, 0x03, 0x80, 0x60, 0x20, 0x60, 0x00, 0x37,
+		// PUSH1 0 PUSH1 0 DUP3 PUSH1 0 PUSH1 0 PUSH1 0 CALLDATALOAD PUSH3
+		0x60, 0x00, 0x60, 0x00, 0x82, 0x60, 0x00, 0x60, 0x00, 0x60, 0x00, 0x35, 0x62,
+	];
+	code.extend_from_slice(&CALL_GAS.to_be_bytes()[5..]);
+	code.extend_from_slice(&[
+		// CALL PUSH1 0 SSTORE POP RETURNDATASIZE PUSH1 1 SSTORE
+		0xf1, 0x60, 0x00, 0x55, 0x50, 0x3d, 0x60, 0x01, 0x55,
+		// RETURNDATASIZE PUSH1 0 PUSH1 0 RETURNDATACOPY
+		0x3d, 0x60, 0x00, 0x60, 0x00, 0x3e,
+		// RETURNDATASIZE PUSH1 0 SHA3 PUSH1 2 SSTORE STOP
+		0x3d, 0x60, 0x00, 0x20, 0x60, 0x02, 0x55, 0x00,
+	]);
+	code
+}
+
+/// Filler running every case on the forks the runner supports from
+/// Istanbul on, the first with all builtins.
+pub fn precompile_boundaries_filler() -> Filler {
+	let cases = cases();
+	let data = cases
+		.iter()
+		.map(|case| {
+			let calldata = [&[0; 12][..], case.address.as_bytes(), &case.input[..]].concat();
+			json!(format!(":label {} 0x{}", case.label, hex::encode(calldata)))
+		})
+		.collect::<Vec<_>>();
+
+	let mut expect = Vec::new();
+	for fork in filler::filled_forks("Istanbul") {
+		for (index, case) in cases.iter().enumerate() {
+			let (success, output) = expected_call(fork, case);
+			expect.push(json!({
+				"indexes": { "data": index },
+				"network": [fork],
+				"result": {
+					PROBE: {
+						"storage": {
+							"0x00": format!("{:#x}", success as u8),
+							"0x01": format!("{:#x}", output.len()),
+							"0x02": format!("0x{}", hex::encode(Keccak256::digest(&output))),
+						},
+					},
+				},
+			}));
+		}
+	}
+
+	serde_json::from_value(json!({
+		"env": {
+			"currentCoinbase": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba",
+			"currentDifficulty": "0x020000",
+			"currentGasLimit": "0x05f5e100",
+			"currentNumber": "0x01",
+			"currentTimestamp": "0x03e8",
+		},
+		"pre": {
+			PROBE: {
+				"balance": "0x00",
+				"code": format!("0x{}", hex::encode(probe_code())),
+				"nonce": "0x00",
+				"storage": {},
+			},
+			"0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b": {
+				"balance": "0x0de0b6b3a7640000",
+				"code": "0x",
+				"nonce": "0x00",
+				"storage": {},
+			},
+		},
+		"transaction": {
+			"data": data,
+			"gasLimit": ["0x989680"],
+			"gasPrice": "0x0a",
+			"nonce": "0x00",
+			"secretKey": "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8",
+			"to": PROBE,
+			"value": ["0x00"],
+		},
+		"expect": Value::Array(expect),
+	}))
+	.expect("Generated filler is invalid")
+}
@@ -0,0 +1,292 @@
+use crate::precompiles::{Builtin, Pricing};
+use lazy_static::lazy_static;
+use primitive_types::H160;
+use serde::Deserialize;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+
+/// Name of the schedule in a builtins directory.
+pub const FILE_NAME: &str = "builtins.json";
+/// Schedule used unless another one is set, built into the binary so that it
+/// does not depend on the working directory.
+const DEFAULT_SCHEDULE: &str = include_str!("../res/builtins.json");
+
+/// Forks in activation order, named as in fixtures.
+pub const FORKS: [&str; 10] = [
+	"Frontier",
+	"Homestead",
+	"EIP150",
+	"EIP158",
+	"Byzantium",
+	"Constantinople",
+	"ConstantinopleFix",
+	"Istanbul",
+	"Berlin",
+	"London",
+];
+
+/// A builtin contract and the forks at which its pricing changes. The first
+/// transition activates it.
+#[derive(Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+	name: String,
+	pricing: BTreeMap<String, Pricing>,
+}
+
+/// Pricing schedule of all builtins, as read from `builtins.json`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Schedule {
+	entries: BTreeMap<H160, (String, Vec<(usize, Pricing)>)>,
+}
+
+pub type Builtins = BTreeMap<H160, Builtin>;
+
+impl Schedule {
+	pub fn parse(json: &str) -> Result<Self, String> {
+		let entries: BTreeMap<ethjson::hash::Address, Entry> =
+			serde_json::from_str(json).map_err(|err| err.to_string())?;
+
+		let mut schedule = BTreeMap::new();
+		for (address, entry) in entries {
+			let name = entry.name;
+			let mut transitions = Vec::new();
+			for (fork, pricing) in entry.pricing {
+				let fork = FORKS
+					.iter()
+					.position(|known| *known == fork)
+					.ok_or_else(|| format!("{}: unknown fork {}", name, fork))?;
+				Builtin::new(&name, pricing.clone())?;
+				transitions.push((fork, pricing));
+			}
+			transitions.sort_by_key(|(fork, _)| *fork);
+			schedule.insert(address.into(), (name, transitions));
+		}
+		Ok(Schedule { entries: schedule })
+	}
+
+	/// Builtins active at `fork` with the pricing in effect there, or `None`
+	/// if the fork is unknown.
+	pub fn builtins(&self, fork: &str) -> Option<Builtins> {
+		let fork = FORKS.iter().position(|name| *name == fork)?;
+		let builtins = self
+			.entries
+			.iter()
+			.filter_map(|(address, (name, transitions))| {
+				let (_, pricing) = transitions.iter().rev().find(|(at, _)| *at <= fork)?;
+				// Every pricing was validated when parsing.
+				Some((*address, Builtin::new(name, pricing.clone()).unwrap()))
+			})
+			.collect();
+		Some(builtins)
+	}
+
+	fn per_fork(&self) -> BTreeMap<&'static str, Arc<Builtins>> {
+		FORKS
+			.iter()
+			.map(|fork| (*fork, Arc::new(self.builtins(fork).unwrap())))
+			.collect()
+	}
+}
+
+/// Schedule in use, and whether P256VERIFY was added to it.
+#[derive(Debug)]
+struct Loaded {
+	schedule: Schedule,
+	#[cfg_attr(not(feature = "p256verify"), allow(dead_code))]
+	p256verify: bool,
+}
+
+impl Loaded {
+	#[cfg(not(feature = "p256verify"))]
+	fn per_fork(&self) -> BTreeMap<&'static str, Arc<Builtins>> {
+		self.schedule.per_fork()
+	}
+
+	#[cfg(feature = "p256verify")]
+	fn per_fork(&self) -> BTreeMap<&'static str, Arc<Builtins>> {
+		let mut per_fork = self.schedule.per_fork();
+		if self.p256verify {
+			for builtins in per_fork.values_mut() {
+				let pricing = Pricing::Linear {
+					base: P256VERIFY_GAS,
+					word: 0,
+				};
+				Arc::get_mut(builtins).unwrap().insert(
+					H160::from_low_u64_be(P256VERIFY),
+					Builtin::new("p256verify", pricing).unwrap(),
+				);
+			}
+		}
+		per_fork
+	}
+}
+
+lazy_static! {
+	/// Schedule in use, the built-in one unless another was set.
+	static ref LOADED: RwLock<Loaded> = RwLock::new(Loaded {
+		schedule: default_schedule(),
+		p256verify: false,
+	});
+}
+
+/// Changes whenever the schedule in use does, for threads to rebuild their
+/// builtins.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+	/// Builtins of every fork built by this thread from the schedule of a
+	/// generation. Each worker looks builtins up in its own, taking no lock
+	/// and counting references no other thread touches, so that runs with
+	/// many jobs do not contend on them.
+	static PER_THREAD: RefCell<Option<(usize, BTreeMap<&'static str, Arc<Builtins>>)>> =
+		const { RefCell::new(None) };
+}
+
+/// Apply `change` to the schedule in use, which every thread picks up at its
+/// next lookup.
+fn change_loaded(change: impl FnOnce(&mut Loaded)) {
+	let mut loaded = LOADED.write().unwrap();
+	change(&mut loaded);
+	GENERATION.fetch_add(1, Ordering::Release);
+}
+
+/// Read the schedule in `dir`.
+pub fn read_dir(dir: &Path) -> Result<Schedule, String> {
+	let path = dir.join(FILE_NAME);
+	let json = fs::read_to_string(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+	Schedule::parse(&json).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// Address of the secp256r1 verification precompile of RIP-7212.
+pub const P256VERIFY: u64 = 0x100;
+/// Gas of a P256VERIFY call.
+pub const P256VERIFY_GAS: u64 = 3450;
+
+/// Add the P256VERIFY precompile of RIP-7212 to the builtins of every fork,
+/// as L2s ship it. Replacing the schedule afterwards drops it again.
+#[cfg(feature = "p256verify")]
+pub fn enable_p256verify() {
+	change_loaded(|loaded| loaded.p256verify = true);
+}
+
+/// Price builtins according to `schedule` from now on. Executions that
+/// already started keep the pricing they started with.
+pub fn set_schedule(schedule: &Schedule) {
+	change_loaded(|loaded| {
+		*loaded = Loaded {
+			schedule: schedule.clone(),
+			p256verify: false,
+		}
+	});
+}
+
+/// Builtins of `fork` under the schedule in use, or `None` if the fork is
+/// unknown. Unless another schedule was set, the built-in one is used.
+pub fn for_fork(fork: &str) -> Option<Arc<Builtins>> {
+	PER_THREAD.with(|per_thread| {
+		let mut per_thread = per_thread.borrow_mut();
+		let generation = GENERATION.load(Ordering::Acquire);
+		match &*per_thread {
+			Some((built, _)) if *built == generation => (),
+			_ => *per_thread = Some((generation, LOADED.read().unwrap().per_fork())),
+		}
+		per_thread.as_ref().unwrap().1.get(fork).cloned()
+	})
+}
+
+fn default_schedule() -> Schedule {
+	Schedule::parse(DEFAULT_SCHEDULE)
+		.unwrap_or_else(|err| panic!("Invalid built-in schedule: {}", err))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const JSON: &str = r#"{
+		"0000000000000000000000000000000000000004": {
+			"name": "identity",
+			"pricing": { "Frontier": { "linear": { "base": 15, "word": 3 } } }
+		},
+		"0000000000000000000000000000000000000005": {
+			"name": "modexp",
+			"pricing": {
+				"Berlin": { "modexp": { "divisor": 3, "is_eip_2565": true } },
+				"Byzantium": { "modexp": { "divisor": 20, "is_eip_2565": false } }
+			}
+		}
+	}"#;
+
+	#[test]
+	fn builtins_per_fork() {
+		let schedule = Schedule::parse(JSON).unwrap();
+		let identity = H160::from_low_u64_be(4);
+		let modexp = H160::from_low_u64_be(5);
+
+		let homestead = schedule.builtins("Homestead").unwrap();
+		assert_eq!(homestead.keys().collect::<Vec<_>>(), vec![&identity]);
+
+		// Base length, exponent length and modulus length of 32 bytes each,
+		// followed by an exponent of 3.
+		let mut input = vec![0; 96];
+		input[31] = 1;
+		input[63] = 1;
+		input[95] = 1;
+		input.extend_from_slice(&[2, 3, 5]);
+		let istanbul = schedule.builtins("Istanbul").unwrap()[&modexp].cost(&input);
+		let berlin = schedule.builtins("Berlin").unwrap()[&modexp].cost(&input);
+		assert!(berlin > istanbul);
+
+		assert!(schedule.builtins("Tangerine").is_none());
+	}
+
+	#[test]
+	fn default_schedule_covers_every_fork() {
+		let schedule = Schedule::parse(DEFAULT_SCHEDULE).unwrap();
+		assert_eq!(schedule.builtins("Frontier").unwrap().len(), 4);
+		assert_eq!(schedule.builtins("Byzantium").unwrap().len(), 8);
+		assert_eq!(schedule.builtins("London").unwrap().len(), 9);
+	}
+
+	#[test]
+	fn builtins_are_built_per_thread() {
+		let berlin = for_fork("Berlin").unwrap();
+		assert!(Arc::ptr_eq(&berlin, &for_fork("Berlin").unwrap()));
+		let other = std::thread::spawn(|| for_fork("Berlin").unwrap())
+			.join()
+			.unwrap();
+		assert!(!Arc::ptr_eq(&berlin, &other));
+		assert_eq!(
+			berlin.keys().collect::<Vec<_>>(),
+			other.keys().collect::<Vec<_>>()
+		);
+		assert!(for_fork("Tangerine").is_none());
+	}
+
+	#[test]
+	fn unknown_fork_is_rejected() {
+		let json = r#"{
+			"0000000000000000000000000000000000000001": {
+				"name": "ecrecover",
+				"pricing": { "Merge": { "linear": { "base": 3000, "word": 0 } } }
+			}
+		}"#;
+		assert!(Schedule::parse(json).is_err());
+	}
+
+	#[test]
+	fn unknown_builtin_is_rejected() {
+		let json = r#"{
+			"0000000000000000000000000000000000000001": {
+				"name": "ec_recover",
+				"pricing": { "Frontier": { "linear": { "base": 3000, "word": 0 } } }
+			}
+		}"#;
+		assert!(Schedule::parse(json).is_err());
+	}
+}
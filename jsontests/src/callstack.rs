@@ -0,0 +1,136 @@
+//! Frames of the message calls a transaction is in, for tracers keeping
+//! state per frame. The executor does not emit an exit event on every path
+//! out of a frame: a precompile call, a call too deep or a failed value
+//! transfer can return without one. Frames are therefore also matched with
+//! the interpreter stepping them, so that a caller stepping again ends the
+//! frames it called. Gas events are matched with the frame whose gasometer
+//! they come from by the gas limit the frame was given.
+
+use crate::trace::gas_snapshot_after;
+use evm::tracing::Event;
+use evm::Stack;
+use evm_gasometer::tracing::Event as GasEvent;
+use evm_gasometer::Snapshot;
+use primitive_types::U256;
+
+/// Gas added to what a caller forwards to a sub-call transferring value.
+pub const CALL_STIPEND: u64 = 2300;
+
+struct Entry<T> {
+	frame: T,
+	/// Address of the interpreter stack, once the frame has stepped.
+	stack: Option<usize>,
+	/// Gas limit of the frame's gasometer, once the caller has charged it.
+	gas: Option<u64>,
+	stipend: u64,
+}
+
+/// Stack of the frames entered and not yet exited, innermost last.
+pub struct CallStack<T> {
+	entries: Vec<Entry<T>>,
+}
+
+impl<T> Default for CallStack<T> {
+	fn default() -> Self {
+		CallStack {
+			entries: Vec::new(),
+		}
+	}
+}
+
+impl<T> CallStack<T> {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Number of frames, the transaction's own one included.
+	pub fn len(&self) -> usize {
+		self.entries.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.entries.is_empty()
+	}
+
+	pub fn last(&self) -> Option<&T> {
+		self.entries.last().map(|entry| &entry.frame)
+	}
+
+	pub fn last_mut(&mut self) -> Option<&mut T> {
+		self.entries.last_mut().map(|entry| &mut entry.frame)
+	}
+
+	/// Gas made available to the innermost frame, once its caller has
+	/// charged for it.
+	pub fn gas(&self) -> Option<u64> {
+		self.entries.last().and_then(|entry| entry.gas)
+	}
+
+	/// Enter `frame`, started by the `Call` or `Create` `event`. Only
+	/// sub-calls get the stipend, the transaction's own call does not.
+	pub fn enter(&mut self, event: &Event, frame: T) {
+		let transfers = match event {
+			Event::Call {
+				transfer: Some(transfer),
+				..
+			} => transfer.value != U256::zero(),
+			_ => false,
+		};
+		let stipend = if transfers && !self.is_empty() {
+			CALL_STIPEND
+		} else {
+			0
+		};
+		self.entries.push(Entry {
+			frame,
+			stack: None,
+			gas: None,
+			stipend,
+		});
+	}
+
+	/// Leave the innermost frame.
+	pub fn exit(&mut self) -> Option<T> {
+		self.entries.pop().map(|entry| entry.frame)
+	}
+
+	/// Number of innermost frames that ended without an exit event, found
+	/// from the interpreter `stack` of a step: they are the frames above the
+	/// one it belongs to. Leave them with `exit` before handling the step.
+	pub fn step(&mut self, stack: &Stack) -> usize {
+		let id = stack as *const Stack as usize;
+		match self
+			.entries
+			.iter()
+			.rposition(|entry| entry.stack == Some(id))
+		{
+			Some(index) => self.entries.len() - 1 - index,
+			None => {
+				if let Some(entry) = self.entries.last_mut() {
+					entry.stack = Some(id);
+				}
+				0
+			}
+		}
+	}
+
+	/// The frame whose gasometer recorded `event`, with the gasometer's
+	/// state once the event is recorded. The first charge after entering a
+	/// frame is the caller paying for the gas it forwards, which becomes the
+	/// frame's gas limit.
+	pub fn gas_event(&mut self, event: &GasEvent) -> Option<(&mut T, Snapshot)> {
+		let entry = self.entries.last_mut()?;
+		if entry.gas.is_none() {
+			if let GasEvent::RecordCost { cost, .. } = *event {
+				entry.gas = Some(cost.saturating_add(entry.stipend));
+			}
+			return None;
+		}
+		let snapshot = gas_snapshot_after(event)?;
+		self.entries
+			.iter_mut()
+			.rev()
+			.find(|entry| entry.gas == Some(snapshot.gas_limit))
+			.map(|entry| (&mut entry.frame, snapshot))
+	}
+}
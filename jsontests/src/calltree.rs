@@ -0,0 +1,390 @@
+use crate::callstack::CallStack;
+use crate::trace::{exit_error, gas_left, Tracer};
+use crate::utils::serialize_hex;
+use evm::tracing::Event;
+use evm::{CreateScheme, ExitReason};
+use evm_runtime::tracing::Event as RuntimeEvent;
+use primitive_types::{H160, U256};
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::fmt::{self, Write};
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum CallKind {
+	Call,
+	CallCode,
+	DelegateCall,
+	StaticCall,
+	Create,
+	Create2,
+}
+
+/// Largest code a contract may deploy, by EIP-170.
+pub const MAX_CODE_SIZE: usize = 0x6000;
+
+/// Why the code returned by init code may not be deposited.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DepositFailure {
+	/// Code of this size, above the EIP-170 limit.
+	CodeSize(usize),
+	/// Code starting with 0xEF, rejected since London by EIP-3541.
+	EfPrefix,
+}
+
+impl DepositFailure {
+	pub fn of(code: &[u8]) -> Option<Self> {
+		if code.len() > MAX_CODE_SIZE {
+			Some(DepositFailure::CodeSize(code.len()))
+		} else if code.first() == Some(&0xef) {
+			Some(DepositFailure::EfPrefix)
+		} else {
+			None
+		}
+	}
+}
+
+impl fmt::Display for DepositFailure {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			DepositFailure::CodeSize(size) => write!(
+				f,
+				"code of {} bytes above the EIP-170 limit of {}",
+				size, MAX_CODE_SIZE
+			),
+			DepositFailure::EfPrefix => {
+				write!(
+					f,
+					"code starts with 0xEF, rejected since London by EIP-3541"
+				)
+			}
+		}
+	}
+}
+
+/// A single message-call frame and the frames it spawned.
+#[derive(Serialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct CallFrame {
+	#[serde(rename = "type")]
+	pub kind: CallKind,
+	pub from: H160,
+	pub to: H160,
+	pub value: U256,
+	/// Gas made available to the frame: the transaction's gas limit for the
+	/// top-level frame, the gas forwarded by the caller for a sub-call.
+	pub gas: Option<u64>,
+	/// Gas consumed by the frame, as seen by its gasometer at exit.
+	pub gas_used: Option<u64>,
+	#[serde(serialize_with = "serialize_hex")]
+	pub input: Vec<u8>,
+	#[serde(serialize_with = "serialize_hex")]
+	pub output: Vec<u8>,
+	/// Debug rendering of the frame's `ExitReason`, `None` if the executor
+	/// left the frame without an exit event.
+	pub exit: Option<String>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+	#[serde(skip_serializing_if = "Vec::is_empty")]
+	pub calls: Vec<CallFrame>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub is_static: Option<bool>,
+	#[serde(skip_serializing_if = "Option::is_none")]
+	pub selfdestruct: Option<H160>,
+}
+
+impl CallFrame {
+	fn render(&self, depth: usize, out: &mut String) {
+		writeln!(
+			out,
+			"{}{:?} {:?} -> {:?} value: {}, gas: {}, used: {}, exit: {}",
+			"  ".repeat(depth),
+			self.kind,
+			self.from,
+			self.to,
+			self.value,
+			self.gas
+				.map(|g| g.to_string())
+				.unwrap_or_else(|| "-".into()),
+			self.gas_used
+				.map(|g| g.to_string())
+				.unwrap_or_else(|| "-".into()),
+			self.exit.as_deref().unwrap_or("-"),
+		)
+		.unwrap();
+		for call in &self.calls {
+			call.render(depth + 1, out);
+		}
+	}
+
+	/// The frame tree in the output format of geth's `callTracer`.
+	pub fn geth(&self) -> Value {
+		let mut frame = json!({
+			"type": self.kind,
+			"from": self.from,
+			"to": self.to,
+			"value": self.value,
+			"gas": format!("{:#x}", self.gas.unwrap_or(0)),
+			"gasUsed": format!("{:#x}", self.gas_used.unwrap_or(0)),
+			"input": format!("0x{}", hex::encode(&self.input)),
+			"output": format!("0x{}", hex::encode(&self.output)),
+		});
+		if let Some(error) = &self.error {
+			frame["error"] = json!(error);
+		}
+		if !self.calls.is_empty() {
+			frame["calls"] = Value::Array(self.calls.iter().map(CallFrame::geth).collect());
+		}
+		frame
+	}
+
+	/// Creations of the tree whose init code returned code breaking a deposit
+	/// rule. Reverted frames return revert data rather than code.
+	pub fn deposit_failures(&self) -> Vec<(&CallFrame, DepositFailure)> {
+		let mut failures = Vec::new();
+		let creates = matches!(self.kind, CallKind::Create | CallKind::Create2);
+		let reverted = self.error.as_deref() == Some("execution reverted");
+		if creates && !reverted {
+			if let Some(failure) = DepositFailure::of(&self.output) {
+				failures.push((self, failure));
+			}
+		}
+		for call in &self.calls {
+			failures.extend(call.deposit_failures());
+		}
+		failures
+	}
+
+	/// Line by line rendering of `deposit_failures`, empty if there are none.
+	pub fn deposit_report(&self) -> String {
+		self.deposit_failures()
+			.iter()
+			.map(|(frame, failure)| {
+				format!(
+					"  {:?} {:?} -> {:?}: {}\n",
+					frame.kind, frame.from, frame.to, failure
+				)
+			})
+			.collect()
+	}
+
+	/// Indented rendering of the frame tree, one frame per line.
+	pub fn report(&self) -> String {
+		let mut out = String::new();
+		self.render(0, &mut out);
+		out
+	}
+}
+
+/// Builds the message-call tree of a transaction from executor events.
+#[derive(Default)]
+pub struct CallTracer {
+	stack: CallStack<(CallFrame, Option<u64>)>,
+	root: Option<CallFrame>,
+}
+
+impl CallTracer {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// The transaction's top-level frame, once it has exited.
+	pub fn into_root(self) -> Option<CallFrame> {
+		self.root
+	}
+
+	fn push(&mut self, event: &Event, frame: CallFrame) {
+		self.stack.enter(event, (frame, None));
+	}
+
+	/// Leave the innermost frame, ended by `exit` unless the executor left it
+	/// without an exit event.
+	fn pop(&mut self, exit: Option<(&ExitReason, &[u8])>) {
+		let gas = self.stack.gas();
+		let root = self.stack.len() == 1;
+		let (mut frame, left) = match self.stack.exit() {
+			Some(frame) => frame,
+			None => return,
+		};
+		if !root {
+			frame.gas = gas;
+		}
+		if let Some((reason, return_value)) = exit {
+			frame.exit = Some(format!("{:?}", reason));
+			frame.error = exit_error(reason);
+			frame.output = return_value.to_vec();
+		}
+		frame.gas_used = match (frame.gas, left) {
+			(Some(gas), Some(left)) => Some(gas.saturating_sub(left)),
+			_ => None,
+		};
+		match self.stack.last_mut() {
+			Some((parent, _)) => parent.calls.push(frame),
+			None => self.root = Some(frame),
+		}
+	}
+}
+
+impl Tracer for CallTracer {
+	fn evm_event(&mut self, event: Event) {
+		match event {
+			Event::Call {
+				code_address,
+				transfer,
+				input,
+				target_gas,
+				is_static,
+				context,
+			} => {
+				let parent_static = self
+					.stack
+					.last()
+					.map(|(frame, _)| frame.is_static == Some(true))
+					.unwrap_or(false);
+				let gas = if self.stack.is_empty() {
+					target_gas
+				} else {
+					None
+				};
+				let kind = if context.address != code_address {
+					if transfer.is_some() {
+						CallKind::CallCode
+					} else {
+						CallKind::DelegateCall
+					}
+				} else if is_static && !parent_static {
+					CallKind::StaticCall
+				} else {
+					CallKind::Call
+				};
+				self.push(
+					&event,
+					CallFrame {
+						kind,
+						from: context.caller,
+						to: code_address,
+						value: context.apparent_value,
+						gas,
+						gas_used: None,
+						input: input.to_vec(),
+						output: Vec::new(),
+						exit: None,
+						error: None,
+						calls: Vec::new(),
+						is_static: Some(is_static),
+						selfdestruct: None,
+					},
+				);
+			}
+			Event::Create {
+				caller,
+				address,
+				scheme,
+				value,
+				init_code,
+				target_gas,
+			} => {
+				let gas = if self.stack.is_empty() {
+					target_gas
+				} else {
+					None
+				};
+				let kind = match scheme {
+					CreateScheme::Create2 { .. } => CallKind::Create2,
+					_ => CallKind::Create,
+				};
+				self.push(
+					&event,
+					CallFrame {
+						kind,
+						from: caller,
+						to: address,
+						value,
+						gas,
+						gas_used: None,
+						input: init_code.to_vec(),
+						output: Vec::new(),
+						exit: None,
+						error: None,
+						calls: Vec::new(),
+						is_static: None,
+						selfdestruct: None,
+					},
+				);
+			}
+			Event::Suicide { target, .. } => {
+				if let Some((frame, _)) = self.stack.last_mut() {
+					frame.selfdestruct = Some(target);
+				}
+			}
+			Event::Exit {
+				reason,
+				return_value,
+			} => self.pop(Some((reason, return_value))),
+			_ => (),
+		}
+	}
+
+	fn runtime_event(&mut self, event: RuntimeEvent) {
+		if let RuntimeEvent::Step { stack, .. } = event {
+			for _ in 0..self.stack.step(stack) {
+				self.pop(None);
+			}
+		}
+	}
+
+	fn gas_event(&mut self, event: evm_gasometer::tracing::Event) {
+		if let Some(((_, left), snapshot)) = self.stack.gas_event(&event) {
+			*left = Some(gas_left(&snapshot));
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::trace::test_helpers::{account, call};
+	use std::collections::BTreeMap;
+
+	#[test]
+	fn tree_with_precompile_call() {
+		let contract = H160::from_low_u64_be(0x1000);
+		let callee = H160::from_low_u64_be(0x2000);
+		let mut state = BTreeMap::new();
+		// CALL of the identity precompile with 0xffff gas, CALL of the callee
+		// with 0xffff gas, then STOP.
+		state.insert(
+			contract,
+			account(concat!(
+				"60006000600060006000600461fffff150",
+				"6000600060006000600061200061fffff150",
+				"00"
+			)),
+		);
+		// PUSH1 2 PUSH1 0 SSTORE STOP.
+		state.insert(callee, account("600260005500"));
+		let execution = call(state, contract, CallTracer::new());
+		assert!(execution.exit_reason.is_succeed());
+
+		let root = execution.tracer.into_root().unwrap();
+		assert_eq!(root.to, contract);
+		assert_eq!(root.gas, Some(1_000_000));
+		assert_eq!(root.calls.len(), 2);
+
+		let identity = &root.calls[0];
+		assert_eq!(identity.to, H160::from_low_u64_be(4));
+		assert_eq!(identity.gas, Some(0xffff));
+		assert_eq!(identity.gas_used, Some(15));
+		assert!(identity.exit.is_some());
+		assert!(identity.calls.is_empty());
+
+		let call = &root.calls[1];
+		assert_eq!(call.kind, CallKind::Call);
+		assert_eq!(call.to, callee);
+		assert_eq!(call.gas, Some(0xffff));
+		// PUSH1, PUSH1, and SSTORE of a cold slot set from zero.
+		assert_eq!(call.gas_used, Some(3 + 3 + 22_100));
+		assert_eq!(call.geth()["gas"], "0xffff");
+		assert_eq!(call.error, None);
+	}
+}
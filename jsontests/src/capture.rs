@@ -0,0 +1,116 @@
+//! Output of the test running on the current thread, kept back so that only
+//! the output of failing tests is shown, as libtest does without
+//! `--nocapture`. What tests print goes through `cprint!`, `cprintln!` and
+//! `ceprint!`, which write to the console when nothing is captured.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::io::{self, Write};
+use std::panic;
+
+thread_local! {
+	static BUFFER: RefCell<Option<String>> = const { RefCell::new(None) };
+}
+
+/// Like `print!`, into the capture buffer of the thread if any.
+#[macro_export]
+macro_rules! cprint {
+	($($arg:tt)*) => {
+		$crate::capture::write(format_args!($($arg)*), false)
+	};
+}
+
+/// Like `println!`, into the capture buffer of the thread if any.
+#[macro_export]
+macro_rules! cprintln {
+	() => {
+		$crate::cprint!("\n")
+	};
+	($($arg:tt)*) => {
+		$crate::capture::write(format_args!("{}\n", format_args!($($arg)*)), false)
+	};
+}
+
+/// Like `eprint!`, into the capture buffer of the thread if any. Captured
+/// output of stdout and stderr is kept in a single buffer, in order.
+#[macro_export]
+macro_rules! ceprint {
+	($($arg:tt)*) => {
+		$crate::capture::write(format_args!($($arg)*), true)
+	};
+}
+
+/// Whether output of the current thread is captured.
+pub fn is_active() -> bool {
+	BUFFER.with(|buffer| buffer.borrow().is_some())
+}
+
+/// Write `args` to the capture buffer, or else to stderr or stdout.
+pub fn write(args: fmt::Arguments, stderr: bool) {
+	let written = BUFFER.with(|buffer| match &mut *buffer.borrow_mut() {
+		Some(buffer) => {
+			fmt::Write::write_fmt(buffer, args).unwrap();
+			true
+		}
+		None => false,
+	});
+	if !written {
+		if stderr {
+			let _ = io::stderr().write_fmt(args);
+		} else {
+			let _ = io::stdout().write_fmt(args);
+		}
+	}
+}
+
+/// Run `f`, returning its result and everything it printed.
+pub fn capture<T, F: FnOnce() -> T>(f: F) -> (T, String) {
+	let outer = BUFFER.with(|buffer| buffer.replace(Some(String::new())));
+	let result = f();
+	let output = BUFFER
+		.with(|buffer| buffer.replace(outer))
+		.unwrap_or_default();
+	(result, output)
+}
+
+/// Write panic messages of capturing threads to their buffer, so that they
+/// are shown with the rest of the output of the test.
+pub fn capture_panics() {
+	let default_hook = panic::take_hook();
+	panic::set_hook(Box::new(move |info| {
+		if is_active() {
+			let thread = std::thread::current();
+			write(
+				format_args!(
+					"thread '{}' panicked at '{}', {}\n",
+					thread.name().unwrap_or("<unnamed>"),
+					crate::utils::panic_message(info.payload()),
+					info.location()
+						.map(|location| location.to_string())
+						.unwrap_or_default()
+				),
+				true,
+			);
+		} else {
+			default_hook(info);
+		}
+	}));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn captures_until_done() {
+		assert!(!is_active());
+		let ((), output) = capture(|| {
+			cprint!("Running {} ... ", "a");
+			let ((), inner) = capture(|| ceprint!("trace"));
+			assert_eq!(inner, "trace");
+			cprintln!("passed");
+		});
+		assert_eq!(output, "Running a ... passed\n");
+		assert!(!is_active());
+	}
+}
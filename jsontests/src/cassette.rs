@@ -0,0 +1,96 @@
+//! Recordings of the JSON-RPC calls made to a node, so that a replay can be
+//! rerun offline and give the same result.
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub enum Response {
+	Result(Value),
+	Error(String),
+}
+
+/// A call and what the node answered.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Interaction {
+	pub method: String,
+	pub params: Value,
+	#[serde(flatten)]
+	pub response: Response,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct Cassette {
+	pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let json =
+			fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+		serde_json::from_str(&json).map_err(|err| format!("{}: {}", path.display(), err))
+	}
+
+	pub fn save(&self, path: &Path) -> Result<(), String> {
+		let json = serde_json::to_string_pretty(self).unwrap();
+		fs::write(path, json).map_err(|err| format!("{}: {}", path.display(), err))
+	}
+
+	pub fn record(&mut self, method: &str, params: &Value, response: &Result<Value, String>) {
+		let response = match response {
+			Ok(result) => Response::Result(result.clone()),
+			Err(err) => Response::Error(err.clone()),
+		};
+		self.interactions.push(Interaction {
+			method: method.to_string(),
+			params: params.clone(),
+			response,
+		});
+	}
+
+	/// Answer recorded for a call, the first one if it was made several
+	/// times.
+	pub fn answer(&self, method: &str, params: &Value) -> Option<Result<Value, String>> {
+		let interaction = self
+			.interactions
+			.iter()
+			.find(|interaction| interaction.method == method && interaction.params == *params)?;
+		Some(match &interaction.response {
+			Response::Result(result) => Ok(result.clone()),
+			Response::Error(err) => Err(err.clone()),
+		})
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn answers_recorded_calls() {
+		let mut cassette = Cassette::default();
+		let params = json!(["0x01", "latest"]);
+		cassette.record("eth_getBalance", &params, &Ok(json!("0x10")));
+		cassette.record("eth_getCode", &params, &Err("pruned".to_string()));
+
+		let json = serde_json::to_string(&cassette).unwrap();
+		assert!(json.contains(r#""result":"0x10""#));
+		let cassette: Cassette = serde_json::from_str(&json).unwrap();
+		assert_eq!(
+			cassette.answer("eth_getBalance", &params),
+			Some(Ok(json!("0x10")))
+		);
+		assert_eq!(
+			cassette.answer("eth_getCode", &params),
+			Some(Err("pruned".to_string()))
+		);
+		assert_eq!(
+			cassette.answer("eth_getBalance", &json!(["0x02", "latest"])),
+			None
+		);
+	}
+}
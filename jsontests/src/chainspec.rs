@@ -0,0 +1,303 @@
+//! Chain specs of custom chains: the rules they follow from which block or
+//! timestamp, their chain id and changes to the gas table. Under a chain
+//! spec a fixture only runs under the fork the chain follows at its block.
+
+use crate::fixture::fork_spec;
+use evm::Config;
+use lazy_static::lazy_static;
+use primitive_types::{H160, U256};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+
+/// A fork and when the chain activates it. A fork with neither a block nor
+/// a timestamp is active from genesis.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Activation {
+	pub fork: String,
+	#[serde(default)]
+	pub block: Option<u64>,
+	#[serde(default)]
+	pub timestamp: Option<u64>,
+}
+
+/// Gas costs replacing the ones of the fork rules, unchanged where `None`.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+pub struct GasTable {
+	pub balance: Option<u64>,
+	pub sload: Option<u64>,
+	pub sstore_set: Option<u64>,
+	pub sstore_reset: Option<u64>,
+	pub call: Option<u64>,
+	pub ext_code: Option<u64>,
+	pub transaction_call: Option<u64>,
+	pub transaction_create: Option<u64>,
+	/// Largest code a contract may deploy.
+	pub create_contract_limit: Option<usize>,
+}
+
+impl GasTable {
+	fn apply(&self, config: &mut Config) {
+		let replace = |cost: &mut u64, tweak: Option<u64>| {
+			if let Some(tweak) = tweak {
+				*cost = tweak;
+			}
+		};
+		replace(&mut config.gas_balance, self.balance);
+		replace(&mut config.gas_sload, self.sload);
+		replace(&mut config.gas_sstore_set, self.sstore_set);
+		replace(&mut config.gas_sstore_reset, self.sstore_reset);
+		replace(&mut config.gas_call, self.call);
+		replace(&mut config.gas_ext_code, self.ext_code);
+		replace(&mut config.gas_transaction_call, self.transaction_call);
+		replace(&mut config.gas_transaction_create, self.transaction_create);
+		if let Some(limit) = self.create_contract_limit {
+			config.create_contract_limit = Some(limit);
+		}
+	}
+}
+
+fn default_chain_id() -> u64 {
+	1
+}
+
+/// Drain list of the DAO fork mainnet clients ship.
+const DAO_TABLE: &str = include_str!("../res/dao.toml");
+
+#[derive(Deserialize)]
+struct DaoTable {
+	beneficiary: H160,
+	accounts: Vec<H160>,
+}
+
+lazy_static! {
+	static ref DAO: DaoTable = toml::from_str(DAO_TABLE)
+		.unwrap_or_else(|err| panic!("Invalid built-in DAO drain list: {}", err));
+}
+
+fn dao_beneficiary() -> H160 {
+	DAO.beneficiary
+}
+
+fn dao_accounts() -> Vec<H160> {
+	DAO.accounts.clone()
+}
+
+/// The DAO fork: an irregular state change at a block moving the balances of
+/// the drained accounts to the beneficiary. The blocks from it on for
+/// [`DAO_EXTRA_DATA_BLOCKS`](crate::header::DAO_EXTRA_DATA_BLOCKS) carry
+/// the fork's extra data. The beneficiary and accounts default to the
+/// mainnet ones.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct DaoFork {
+	pub block: u64,
+	#[serde(default = "dao_beneficiary")]
+	pub beneficiary: H160,
+	#[serde(default = "dao_accounts")]
+	pub accounts: Vec<H160>,
+}
+
+impl DaoFork {
+	/// The DAO fork of mainnet at `block`.
+	pub fn mainnet_at(block: u64) -> Self {
+		DaoFork {
+			block,
+			beneficiary: dao_beneficiary(),
+			accounts: dao_accounts(),
+		}
+	}
+}
+
+fn genesis(fork: &str) -> Activation {
+	Activation {
+		fork: fork.to_string(),
+		block: None,
+		timestamp: None,
+	}
+}
+
+/// A chain spec file.
+///
+/// ```json
+/// {
+///     "chainId": 1337,
+///     "forks": [
+///         { "fork": "Istanbul" },
+///         { "fork": "Berlin", "block": 100 }
+///     ],
+///     "gas": { "sload": 1000 },
+///     "daoFork": { "block": 5 }
+/// }
+/// ```
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ChainSpec {
+	#[serde(default = "default_chain_id")]
+	pub chain_id: u64,
+	/// Forks of the chain in activation order.
+	pub forks: Vec<Activation>,
+	#[serde(default)]
+	pub gas: GasTable,
+	#[serde(default)]
+	pub dao_fork: Option<DaoFork>,
+}
+
+impl ChainSpec {
+	pub fn parse(json: &str) -> Result<Self, String> {
+		let spec: ChainSpec = serde_json::from_str(json).map_err(|err| err.to_string())?;
+		if let Some(activation) = spec
+			.forks
+			.iter()
+			.find(|activation| fork_spec(&activation.fork).is_none())
+		{
+			return Err(format!("unknown fork {}", activation.fork));
+		}
+		Ok(spec)
+	}
+
+	/// Chain of a fixture network: a single fork, or two for transition
+	/// networks such as `BerlinToLondonAt5` or `ShanghaiToCancunAtTime15k`
+	/// switching at a block or timestamp. `HomesteadToDaoAt5` stays on
+	/// Homestead with the DAO fork at the block. `None` if it names an
+	/// unknown fork.
+	pub fn of_network(network: &str) -> Option<Self> {
+		if let Some((from, at)) = network
+			.split_once("ToDaoAt")
+			.and_then(|(from, at)| Some((from, at.parse::<u64>().ok()?)))
+		{
+			let mut spec = Self::of_network(from)?;
+			spec.dao_fork = Some(DaoFork::mainnet_at(at));
+			return Some(spec);
+		}
+
+		let transition = network.split_once("To").and_then(|(from, rest)| {
+			let (to, at) = rest.rsplit_once("At")?;
+			let (by_time, at) = match at.strip_prefix("Time") {
+				Some(at) => (true, at),
+				None => (false, at),
+			};
+			let at = match at.strip_suffix('k') {
+				Some(at) => at.parse::<u64>().ok()? * 1000,
+				None => at.parse::<u64>().ok()?,
+			};
+			let to = Activation {
+				fork: to.to_string(),
+				block: Some(at).filter(|_| !by_time),
+				timestamp: Some(at).filter(|_| by_time),
+			};
+			Some(vec![genesis(from), to])
+		});
+		let spec = ChainSpec {
+			chain_id: default_chain_id(),
+			forks: transition.unwrap_or_else(|| vec![genesis(network)]),
+			gas: GasTable::default(),
+			dao_fork: None,
+		};
+		if spec
+			.forks
+			.iter()
+			.all(|activation| fork_spec(&activation.fork).is_some())
+		{
+			Some(spec)
+		} else {
+			None
+		}
+	}
+
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let json =
+			fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+		Self::parse(&json).map_err(|err| format!("{}: {}", path.display(), err))
+	}
+
+	/// Fork the chain follows in the block `number` with `timestamp`, if it
+	/// has activated any by then.
+	pub fn fork_at(&self, number: U256, timestamp: U256) -> Option<&str> {
+		self.forks
+			.iter()
+			.filter(|activation| {
+				activation
+					.block
+					.map_or(true, |block| number >= block.into())
+					&& activation
+						.timestamp
+						.map_or(true, |time| timestamp >= time.into())
+			})
+			.last()
+			.map(|activation| activation.fork.as_str())
+	}
+}
+
+lazy_static! {
+	static ref ACTIVE: RwLock<Option<Arc<ChainSpec>>> = RwLock::new(None);
+}
+
+/// Run tests on the chain of `spec` from now on, or on mainnet without one.
+pub fn set_chain_spec(spec: Option<ChainSpec>) {
+	*ACTIVE.write().unwrap() = spec.map(Arc::new);
+}
+
+/// The chain spec tests run on, if one was set.
+pub fn active() -> Option<Arc<ChainSpec>> {
+	ACTIVE.read().unwrap().clone()
+}
+
+/// Apply the gas table of the active chain spec to `config`.
+pub fn tweak(config: &mut Config) {
+	if let Some(spec) = active() {
+		spec.gas.apply(config);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const SPEC: &str = r#"{
+		"chainId": 1337,
+		"forks": [
+			{ "fork": "Istanbul" },
+			{ "fork": "Berlin", "block": 100 }
+		],
+		"gas": { "sload": 1000, "createContractLimit": 49152 }
+	}"#;
+
+	#[test]
+	fn forks_by_block() {
+		let spec = ChainSpec::parse(SPEC).unwrap();
+		assert_eq!(spec.chain_id, 1337);
+		assert_eq!(spec.fork_at(1.into(), 0.into()), Some("Istanbul"));
+		assert_eq!(spec.fork_at(100.into(), 0.into()), Some("Berlin"));
+
+		let mut config = Config::berlin();
+		let sstore_set = config.gas_sstore_set;
+		spec.gas.apply(&mut config);
+		assert_eq!(config.gas_sload, 1000);
+		assert_eq!(config.create_contract_limit, Some(49152));
+		assert_eq!(config.gas_sstore_set, sstore_set);
+
+		assert!(ChainSpec::parse(r#"{ "forks": [{ "fork": "Mainnet2" }] }"#).is_err());
+
+		let transition = ChainSpec::of_network("BerlinToLondonAt5").unwrap();
+		assert_eq!(transition.fork_at(4.into(), 100.into()), Some("Berlin"));
+		assert_eq!(transition.fork_at(5.into(), 100.into()), Some("London"));
+		let by_time = ChainSpec::of_network("IstanbulToBerlinAtTime15k").unwrap();
+		assert_eq!(by_time.fork_at(9.into(), 14_999.into()), Some("Istanbul"));
+		assert_eq!(by_time.fork_at(9.into(), 15_000.into()), Some("Berlin"));
+		assert_eq!(
+			ChainSpec::of_network("Berlin")
+				.unwrap()
+				.fork_at(0.into(), 0.into()),
+			Some("Berlin")
+		);
+		let dao = ChainSpec::of_network("HomesteadToDaoAt5").unwrap();
+		assert_eq!(dao.fork_at(5.into(), 0.into()), Some("Homestead"));
+		assert_eq!(dao.dao_fork.unwrap().block, 5);
+		assert!(ChainSpec::of_network("HomesteadToDaoAtFive").is_none());
+		assert!(ChainSpec::parse(r#"{ "forks": [], "gas": { "sstore": 1 } }"#).is_err());
+	}
+}
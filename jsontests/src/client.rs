@@ -0,0 +1,164 @@
+//! JSON-RPC client of an Ethereum node, or of a recording of one.
+
+use crate::cassette::Cassette;
+use crate::forked::{Remote, RemoteAccount};
+use primitive_types::{H160, H256, U256};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::cell::{Cell, RefCell};
+use std::collections::BTreeMap;
+
+enum Transport {
+	Http(String),
+	/// Answers recorded earlier, no call reaches a node.
+	Offline(Cassette),
+}
+
+pub struct Client {
+	transport: Transport,
+	id: Cell<u64>,
+	/// Calls made so far, when recording.
+	recording: Option<RefCell<Cassette>>,
+}
+
+/// Block tag of `number`.
+pub fn block_tag(number: U256) -> Value {
+	json!(format!("{:#x}", number))
+}
+
+/// Field `name` of a result object.
+pub fn field<T: DeserializeOwned>(object: &Value, name: &str) -> Result<T, String> {
+	serde_json::from_value(object[name].clone()).map_err(|err| format!("{}: {}", name, err))
+}
+
+impl Client {
+	pub fn new(url: &str) -> Self {
+		Client {
+			transport: Transport::Http(url.to_string()),
+			id: Cell::new(0),
+			recording: None,
+		}
+	}
+
+	/// Client answering from `cassette` only.
+	pub fn offline(cassette: Cassette) -> Self {
+		Client {
+			transport: Transport::Offline(cassette),
+			id: Cell::new(0),
+			recording: None,
+		}
+	}
+
+	/// Record every call from now on.
+	pub fn record(mut self) -> Self {
+		self.recording = Some(RefCell::new(Cassette::default()));
+		self
+	}
+
+	/// Calls made since recording started.
+	pub fn recorded(&self) -> Option<Cassette> {
+		self.recording
+			.as_ref()
+			.map(|recording| recording.borrow().clone())
+	}
+
+	/// Call `method`, returning its result.
+	pub fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+		let response = match &self.transport {
+			Transport::Http(url) => self.request(url, method, &params),
+			Transport::Offline(cassette) => cassette
+				.answer(method, &params)
+				.unwrap_or_else(|| Err(format!("{} {} is not in the cassette", method, params))),
+		};
+		if let Some(recording) = &self.recording {
+			recording.borrow_mut().record(method, &params, &response);
+		}
+		response
+	}
+
+	fn request(&self, url: &str, method: &str, params: &Value) -> Result<Value, String> {
+		let id = self.id.get() + 1;
+		self.id.set(id);
+		let request = json!({
+			"jsonrpc": "2.0",
+			"id": id,
+			"method": method,
+			"params": params,
+		});
+		let body = ureq::post(url)
+			.set("Content-Type", "application/json")
+			.send_string(&request.to_string())
+			.map_err(|err| format!("{}: {}", method, err))?
+			.into_string()
+			.map_err(|err| format!("{}: {}", method, err))?;
+		let mut response: Value =
+			serde_json::from_str(&body).map_err(|err| format!("{}: {}", method, err))?;
+		if let Some(error) = response.get("error") {
+			return Err(format!("{}: {}", method, error));
+		}
+		match response.get_mut("result") {
+			Some(result) if !result.is_null() => Ok(result.take()),
+			_ => Err(format!("{}: no result", method)),
+		}
+	}
+
+	pub fn code(&self, address: H160, block: U256) -> Result<Vec<u8>, String> {
+		let code: String =
+			serde_json::from_value(self.call("eth_getCode", json!([address, block_tag(block)]))?)
+				.map_err(|err| err.to_string())?;
+		hex::decode(code.trim_start_matches("0x")).map_err(|err| err.to_string())
+	}
+
+	pub fn storage(&self, address: H160, index: H256, block: U256) -> Result<H256, String> {
+		let value = self.call(
+			"eth_getStorageAt",
+			json!([address, index, block_tag(block)]),
+		)?;
+		let value: U256 = serde_json::from_value(value).map_err(|err| err.to_string())?;
+		let mut bytes = [0; 32];
+		value.to_big_endian(&mut bytes);
+		Ok(bytes.into())
+	}
+
+	pub fn balance(&self, address: H160, block: U256) -> Result<U256, String> {
+		let balance = self.call("eth_getBalance", json!([address, block_tag(block)]))?;
+		serde_json::from_value(balance).map_err(|err| err.to_string())
+	}
+
+	pub fn block_hash(&self, number: U256) -> Result<H256, String> {
+		let block = self.call("eth_getBlockByNumber", json!([block_tag(number), false]))?;
+		field(&block, "hash")
+	}
+}
+
+/// State of the node at `block`, as the remote of a forked backend.
+pub struct NodeRemote<'a> {
+	pub client: &'a Client,
+	pub block: U256,
+}
+
+impl<'a> Remote for NodeRemote<'a> {
+	fn account(&self, address: H160) -> Result<Option<RemoteAccount>, String> {
+		let proof = self.client.call(
+			"eth_getProof",
+			json!([address, Vec::<H256>::new(), block_tag(self.block)]),
+		)?;
+		let account = RemoteAccount {
+			balance: field(&proof, "balance")?,
+			nonce: field(&proof, "nonce")?,
+			code: self.client.code(address, self.block)?,
+			storage: BTreeMap::new(),
+		};
+		let is_empty =
+			account.balance.is_zero() && account.nonce.is_zero() && account.code.is_empty();
+		Ok(if is_empty { None } else { Some(account) })
+	}
+
+	fn storage(&self, address: H160, index: H256) -> Result<H256, String> {
+		self.client.storage(address, index, self.block)
+	}
+
+	fn block_hash(&self, number: U256) -> Result<H256, String> {
+		self.client.block_hash(number)
+	}
+}
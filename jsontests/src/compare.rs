@@ -0,0 +1,316 @@
+use crate::report::IndexReport;
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+
+/// Timing changes smaller than this are noise, whatever their ratio.
+const MIN_REGRESSION_US: u64 = 1000;
+
+/// Test index a result belongs to.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Key {
+	pub name: String,
+	pub fork: String,
+	pub index: usize,
+}
+
+impl fmt::Display for Key {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}:{}:{}", self.name, self.fork, self.index)
+	}
+}
+
+/// An index that got slower between two runs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Slowdown {
+	pub key: Key,
+	pub base_us: u64,
+	pub head_us: u64,
+}
+
+/// An index whose transaction used a different amount of gas, most likely
+/// because gas accounting changed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GasChange {
+	pub key: Key,
+	pub base: u64,
+	pub head: u64,
+}
+
+/// An index whose transaction allocates more than it did.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AllocationGrowth {
+	pub key: Key,
+	pub base: u64,
+	pub head: u64,
+}
+
+/// Differences between a base run and a head run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Comparison {
+	pub newly_failing: Vec<Key>,
+	pub newly_passing: Vec<Key>,
+	pub slower: Vec<Slowdown>,
+	pub gas_changed: Vec<GasChange>,
+	pub more_allocations: Vec<AllocationGrowth>,
+	/// Indexes of the base run the head run did not execute.
+	pub missing: Vec<Key>,
+}
+
+impl Comparison {
+	/// Whether the head run is worse than the base run.
+	pub fn is_regression(&self) -> bool {
+		!self.newly_failing.is_empty()
+			|| !self.slower.is_empty()
+			|| !self.gas_changed.is_empty()
+			|| !self.more_allocations.is_empty()
+	}
+}
+
+/// Thresholds above which a change is reported.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tolerance {
+	/// Percentage an index's time may grow by.
+	pub time_percent: f64,
+	/// Gas an index's usage may differ by in either direction.
+	pub gas: u64,
+}
+
+fn by_key(results: &[IndexReport]) -> BTreeMap<Key, &IndexReport> {
+	results
+		.iter()
+		.map(|result| {
+			let key = Key {
+				name: result.name.clone(),
+				fork: result.fork.clone(),
+				index: result.index,
+			};
+			(key, result)
+		})
+		.collect()
+}
+
+/// Compare two runs. When a report holds several results of an index, the
+/// last one is used. Gas and allocations are only compared when both reports
+/// recorded them.
+pub fn compare(base: &[IndexReport], head: &[IndexReport], tolerance: Tolerance) -> Comparison {
+	let base = by_key(base);
+	let head = by_key(head);
+	let mut comparison = Comparison::default();
+
+	for (key, base) in &base {
+		let head = match head.get(key) {
+			Some(head) => head,
+			None => {
+				comparison.missing.push(key.clone());
+				continue;
+			}
+		};
+		match (base.passed, head.passed) {
+			(true, false) => comparison.newly_failing.push(key.clone()),
+			(false, true) => comparison.newly_passing.push(key.clone()),
+			_ => (),
+		}
+		let limit = base.time_us as f64 * (1.0 + tolerance.time_percent / 100.0);
+		if head.time_us as f64 > limit && head.time_us >= base.time_us + MIN_REGRESSION_US {
+			comparison.slower.push(Slowdown {
+				key: key.clone(),
+				base_us: base.time_us,
+				head_us: head.time_us,
+			});
+		}
+		if let (Some(base), Some(head)) = (base.used_gas, head.used_gas) {
+			let difference = head.max(base) - head.min(base);
+			if difference > tolerance.gas {
+				comparison.gas_changed.push(GasChange {
+					key: key.clone(),
+					base,
+					head,
+				});
+			}
+		}
+		if let (Some(base), Some(head)) = (base.allocations, head.allocations) {
+			if head > base {
+				comparison.more_allocations.push(AllocationGrowth {
+					key: key.clone(),
+					base,
+					head,
+				});
+			}
+		}
+	}
+	for (key, head) in &head {
+		if !head.passed && !base.contains_key(key) {
+			comparison.newly_failing.push(key.clone());
+		}
+	}
+
+	comparison
+}
+
+/// Render a comparison for the console.
+pub fn render(comparison: &Comparison) -> String {
+	let mut out = String::new();
+	let mut section = |title: &str, lines: Vec<String>| {
+		writeln!(out, "{} ({}):", title, lines.len()).unwrap();
+		for line in lines {
+			writeln!(out, "  {}", line).unwrap();
+		}
+	};
+
+	section(
+		"Newly failing",
+		comparison
+			.newly_failing
+			.iter()
+			.map(Key::to_string)
+			.collect(),
+	);
+	section(
+		"Newly passing",
+		comparison
+			.newly_passing
+			.iter()
+			.map(Key::to_string)
+			.collect(),
+	);
+	section(
+		"Slower",
+		comparison
+			.slower
+			.iter()
+			.map(|slowdown| {
+				format!(
+					"{}: {}us -> {}us",
+					slowdown.key, slowdown.base_us, slowdown.head_us
+				)
+			})
+			.collect(),
+	);
+	section(
+		"Gas changed",
+		comparison
+			.gas_changed
+			.iter()
+			.map(|change| format!("{}: {} -> {}", change.key, change.base, change.head))
+			.collect(),
+	);
+	if !comparison.more_allocations.is_empty() {
+		section(
+			"More allocations",
+			comparison
+				.more_allocations
+				.iter()
+				.map(|growth| format!("{}: {} -> {}", growth.key, growth.base, growth.head))
+				.collect(),
+		);
+	}
+	if !comparison.missing.is_empty() {
+		section(
+			"Missing from head",
+			comparison.missing.iter().map(Key::to_string).collect(),
+		);
+	}
+
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn result(name: &str, passed: bool, time_us: u64) -> IndexReport {
+		IndexReport {
+			name: name.to_string(),
+			fork: "Berlin".to_string(),
+			passed,
+			time_us,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn reports_changes() {
+		let base = vec![
+			result("fixed", false, 100),
+			result("broken", true, 100),
+			result("slow", true, 10_000),
+			result("noisy", true, 100),
+			result("removed", true, 100),
+		];
+		let head = vec![
+			result("fixed", true, 100),
+			result("broken", false, 100),
+			result("slow", true, 30_000),
+			result("noisy", true, 900),
+			result("new", false, 100),
+		];
+		let tolerance = Tolerance {
+			time_percent: 50.0,
+			gas: 0,
+		};
+		let comparison = compare(&base, &head, tolerance);
+
+		let names = |keys: &[Key]| keys.iter().map(|key| key.name.clone()).collect::<Vec<_>>();
+		assert_eq!(names(&comparison.newly_failing), vec!["broken", "new"]);
+		assert_eq!(names(&comparison.newly_passing), vec!["fixed"]);
+		assert_eq!(names(&comparison.missing), vec!["removed"]);
+		assert_eq!(comparison.slower.len(), 1);
+		assert_eq!(comparison.slower[0].key.name, "slow");
+		assert!(comparison.gas_changed.is_empty());
+		assert!(comparison.is_regression());
+	}
+
+	#[test]
+	fn gas_tolerance() {
+		let with_gas = |used_gas| IndexReport {
+			used_gas: Some(used_gas),
+			..result("sstore", true, 100)
+		};
+		let tolerance = |gas| Tolerance {
+			time_percent: 50.0,
+			gas,
+		};
+
+		let comparison = compare(&[with_gas(21000)], &[with_gas(21100)], tolerance(0));
+		assert_eq!(
+			comparison.gas_changed,
+			vec![GasChange {
+				key: Key {
+					name: "sstore".to_string(),
+					fork: "Berlin".to_string(),
+					index: 0,
+				},
+				base: 21000,
+				head: 21100,
+			}]
+		);
+		assert!(comparison.is_regression());
+
+		let comparison = compare(&[with_gas(21100)], &[with_gas(21000)], tolerance(100));
+		assert!(!comparison.is_regression());
+
+		// Reports of runs that did not record gas are not compared.
+		let comparison = compare(&[result("sstore", true, 100)], &[with_gas(0)], tolerance(0));
+		assert!(!comparison.is_regression());
+	}
+
+	#[test]
+	fn allocation_growth() {
+		let allocating = |allocations| IndexReport {
+			allocations: Some(allocations),
+			..result("sstore", true, 100)
+		};
+		let tolerance = Tolerance {
+			time_percent: 50.0,
+			gas: 0,
+		};
+
+		let comparison = compare(&[allocating(40)], &[allocating(41)], tolerance);
+		assert_eq!(comparison.more_allocations.len(), 1);
+		assert!(comparison.is_regression());
+		assert!(render(&comparison).contains("sstore:Berlin:0: 40 -> 41"));
+
+		let comparison = compare(&[allocating(40)], &[allocating(12)], tolerance);
+		assert!(!comparison.is_regression());
+	}
+}
@@ -0,0 +1,146 @@
+use serde::Deserialize;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Name of the config file looked up in the working directory.
+pub const FILE_NAME: &str = "evm-tests.toml";
+
+/// Fixture paths of a test suite.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct Suite {
+	/// Fixture files, directories or archives run when none are given on
+	/// the command line.
+	pub paths: Vec<PathBuf>,
+}
+
+/// Defaults read from `evm-tests.toml`. Every setting can be overridden by
+/// the corresponding command-line flag.
+///
+/// ```toml
+/// skip = ["stQuadraticComplexity"]
+/// forks = ["Berlin"]
+/// jobs = 4
+/// heavy-jobs = 1
+/// timeout = 60
+/// base-fee = 0
+/// json-report = "target/report.jsonl"
+///
+/// [state]
+/// paths = ["res/ethtests/GeneralStateTests"]
+/// ```
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Config {
+	/// Names of tests that are never run.
+	pub skip: Vec<String>,
+	/// Forks to run, all supported ones if empty.
+	pub forks: Vec<String>,
+	/// Number of fixture files run in parallel.
+	pub jobs: Option<usize>,
+	/// Number of fixture files of heavy suites run in parallel, 1 if not
+	/// set.
+	pub heavy_jobs: Option<usize>,
+	/// Seconds a single state or blockchain test may take.
+	pub timeout: Option<u64>,
+	/// Base fee in wei of the block of every state test, replacing the one
+	/// of the fixture, to run the suite under the parameters of a chain.
+	pub base_fee: Option<u64>,
+	/// Do not fail the run because of forks the runner does not support.
+	pub allow_failed_skips: bool,
+	pub json_report: Option<PathBuf>,
+	pub html_report: Option<PathBuf>,
+	pub dump_state: Option<PathBuf>,
+	pub trace_dir: Option<PathBuf>,
+	/// Directory with the `builtins.json` pricing schedule of precompiles.
+	pub builtins_dir: Option<PathBuf>,
+	/// Chain spec of the custom chain tests run on.
+	pub chain_spec: Option<PathBuf>,
+	pub vm: Suite,
+	pub state: Suite,
+	pub blockchain: Suite,
+}
+
+impl Config {
+	/// Parse a config file. Relative paths are resolved against `base`, the
+	/// directory of the file.
+	pub fn parse(contents: &str, base: &Path) -> Result<Self, String> {
+		let mut config: Config = toml::from_str(contents).map_err(|err| err.to_string())?;
+
+		let resolve = |path: &mut PathBuf| *path = base.join(&*path);
+		for suite in [&mut config.vm, &mut config.state, &mut config.blockchain] {
+			suite.paths.iter_mut().for_each(resolve);
+		}
+		config.json_report.iter_mut().for_each(resolve);
+		config.html_report.iter_mut().for_each(resolve);
+		config.dump_state.iter_mut().for_each(resolve);
+		config.trace_dir.iter_mut().for_each(resolve);
+		config.builtins_dir.iter_mut().for_each(resolve);
+		config.chain_spec.iter_mut().for_each(resolve);
+
+		Ok(config)
+	}
+
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let contents =
+			fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+		let base = path.parent().unwrap_or_else(|| Path::new(""));
+		Self::parse(&contents, base).map_err(|err| format!("{}: {}", path.display(), err))
+	}
+
+	/// Load `path`, or `evm-tests.toml` in the working directory if it
+	/// exists. Without either, every setting takes its default.
+	pub fn discover(path: Option<&Path>) -> Result<Self, String> {
+		match path {
+			Some(path) => Self::load(path),
+			None if Path::new(FILE_NAME).is_file() => Self::load(Path::new(FILE_NAME)),
+			None => Ok(Config::default()),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_resolves_paths() {
+		let config = Config::parse(
+			r#"
+			skip = ["sstore_combinations_initial00"]
+			forks = ["Istanbul", "Berlin"]
+			jobs = 4
+			heavy-jobs = 2
+			timeout = 60
+			base-fee = 0
+			json-report = "report.jsonl"
+
+			[state]
+			paths = ["res/ethtests/GeneralStateTests", "/abs/fixtures.tar.gz"]
+			"#,
+			Path::new("ci"),
+		)
+		.unwrap();
+
+		assert_eq!(config.skip, vec!["sstore_combinations_initial00"]);
+		assert_eq!(config.forks, vec!["Istanbul", "Berlin"]);
+		assert_eq!(config.jobs, Some(4));
+		assert_eq!(config.heavy_jobs, Some(2));
+		assert_eq!(config.timeout, Some(60));
+		assert_eq!(config.base_fee, Some(0));
+		assert_eq!(config.json_report, Some(PathBuf::from("ci/report.jsonl")));
+		assert_eq!(
+			config.state.paths,
+			vec![
+				PathBuf::from("ci/res/ethtests/GeneralStateTests"),
+				PathBuf::from("/abs/fixtures.tar.gz"),
+			]
+		);
+		assert!(config.vm.paths.is_empty());
+	}
+
+	#[test]
+	fn unknown_keys_are_rejected() {
+		assert!(Config::parse("job = 4", Path::new("")).is_err());
+	}
+}
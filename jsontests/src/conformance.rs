@@ -0,0 +1,424 @@
+//! The execution core of the state test runner: the post-state a
+//! transaction leaves on a pre-state, and its root. It does no I/O, printing
+//! or threading.
+
+use crate::fees::Pricing;
+use crate::utils::state_root_of;
+use evm::backend::{
+	Apply, ApplyBackend, Backend, Log, MemoryAccount, MemoryBackend, MemoryVicinity,
+};
+use evm::executor::{
+	MemoryStackState, PrecompileFn, StackExecutor, StackState, StackSubstateMetadata,
+};
+use evm::{Capture, Config, Context, ExitError, ExitReason, Handler};
+use primitive_types::{H160, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Message of a transaction, with the sender already recovered.
+#[derive(Clone, Debug)]
+pub struct Transaction {
+	pub caller: H160,
+	/// Recipient of a call, or `None` for contract creation.
+	pub to: Option<H160>,
+	pub value: U256,
+	pub data: Vec<u8>,
+	pub gas_limit: u64,
+	pub access_list: Vec<(H160, Vec<H256>)>,
+	/// How the fee is charged, `None` for the gas price of the vicinity
+	/// without a base fee.
+	pub pricing: Option<Pricing>,
+}
+
+/// Substate of an executed transaction, read from the executor before its
+/// changes are applied to the backend.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Substate {
+	/// Accounts the transaction changes or touches.
+	pub touched: Vec<H160>,
+	/// Accounts given code, contracts the transaction deploys.
+	pub created: Vec<H160>,
+	/// Accounts self-destructed or deleted as empty.
+	pub deleted: Vec<H160>,
+	/// Addresses warm at the end under EIP-2929, none before Berlin.
+	pub accessed_addresses: Vec<H160>,
+	pub accessed_storage: Vec<(H160, H256)>,
+	pub logs: usize,
+}
+
+impl Substate {
+	fn new<I>(values: &[Apply<I>], logs: usize) -> Self {
+		let mut substate = Substate {
+			logs,
+			..Default::default()
+		};
+		for value in values {
+			match value {
+				Apply::Modify { address, code, .. } => {
+					substate.touched.push(*address);
+					if code.is_some() {
+						substate.created.push(*address);
+					}
+				}
+				Apply::Delete { address } => {
+					substate.touched.push(*address);
+					substate.deleted.push(*address);
+				}
+			}
+		}
+		substate
+	}
+}
+
+impl fmt::Display for Substate {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let accounts = [
+			("Touched", &self.touched),
+			("Created", &self.created),
+			("Deleted", &self.deleted),
+		];
+		for (name, addresses) in accounts.iter() {
+			write!(f, "{}:", name)?;
+			for address in addresses.iter() {
+				write!(f, " {:?}", address)?;
+			}
+			writeln!(f)?;
+		}
+		writeln!(
+			f,
+			"Accessed: {} addresses, {} storage slots",
+			self.accessed_addresses.len(),
+			self.accessed_storage.len()
+		)?;
+		writeln!(f, "Logs: {}", self.logs)
+	}
+}
+
+/// What a transaction did besides changing the state.
+#[derive(Clone, Debug)]
+pub struct Executed {
+	pub exit_reason: ExitReason,
+	/// Return data of a call, empty for contract creation.
+	pub output: Vec<u8>,
+	/// Gas used after the refund.
+	pub used_gas: u64,
+	/// Refund subtracted from the gas used, capped by the fork.
+	pub refunded_gas: u64,
+	pub logs: Vec<Log>,
+	pub substate: Substate,
+}
+
+/// Stack executor over the backend `B`, its state borrowing it for `'b`.
+pub type Executor<'b, 'c, B> =
+	StackExecutor<'c, 'c, MemoryStackState<'b, 'c, B>, BTreeMap<H160, PrecompileFn>>;
+
+/// Wiring of a stack executor over a backend: the substate metadata of its
+/// gas limit and the state on top of the backend, under a gasometer config
+/// and with precompiles.
+#[derive(Clone, Copy)]
+pub struct ExecutorBuilder<'c> {
+	config: &'c Config,
+	precompiles: &'c BTreeMap<H160, PrecompileFn>,
+	gas_limit: u64,
+}
+
+impl<'c> ExecutorBuilder<'c> {
+	/// Builder of executors under `config` with `precompiles`, with no gas
+	/// limit.
+	pub fn new(config: &'c Config, precompiles: &'c BTreeMap<H160, PrecompileFn>) -> Self {
+		ExecutorBuilder {
+			config,
+			precompiles,
+			gas_limit: u64::MAX,
+		}
+	}
+
+	/// Limit the gas of the executors, as the one of the transaction or call
+	/// they run.
+	pub fn gas_limit(mut self, gas_limit: u64) -> Self {
+		self.gas_limit = gas_limit;
+		self
+	}
+
+	/// Executor over `backend`, whose changes it collects until they are
+	/// applied.
+	pub fn build<'b, B: Backend>(&self, backend: &'b B) -> Executor<'b, 'c, B> {
+		let metadata = StackSubstateMetadata::new(self.gas_limit, self.config);
+		let state = MemoryStackState::new(metadata, backend);
+		StackExecutor::new_with_precompiles(state, self.config, self.precompiles)
+	}
+}
+
+/// Execute `transaction` on `backend` under `config`, burning the base and
+/// blob fees, paying the tip to the coinbase and applying the changes it
+/// makes. Empty accounts it touches are deleted if `delete_empty`. A
+/// transaction whose sender cannot pay its fee and value is invalid: it ends
+/// with `OutOfFund` without using gas or changing the backend.
+pub fn transact<B: Backend + ApplyBackend>(
+	config: &Config,
+	delete_empty: bool,
+	backend: &mut B,
+	transaction: &Transaction,
+	precompiles: &BTreeMap<H160, PrecompileFn>,
+) -> Executed {
+	let caller = transaction.caller;
+	let gas_limit = transaction.gas_limit;
+	let pricing = transaction
+		.pricing
+		.unwrap_or_else(|| Pricing::legacy(backend.gas_price()));
+	let coinbase = backend.block_coinbase();
+
+	let total_fee = pricing.fees(gas_limit).total();
+	if backend.basic(caller).balance < total_fee.saturating_add(transaction.value) {
+		return Executed {
+			exit_reason: ExitReason::Error(ExitError::OutOfFund),
+			output: Vec::new(),
+			used_gas: 0,
+			refunded_gas: 0,
+			logs: Vec::new(),
+			substate: Substate::default(),
+		};
+	}
+
+	let mut executor = ExecutorBuilder::new(config, precompiles)
+		.gas_limit(gas_limit)
+		.build(&*backend);
+	executor
+		.state_mut()
+		.withdraw(caller, total_fee)
+		.expect("The balance was checked to cover the fee");
+
+	let access_list = transaction.access_list.clone();
+	let value = transaction.value;
+	let data = transaction.data.clone();
+	let (exit_reason, output) = match transaction.to {
+		Some(to) => executor.transact_call(caller, to, value, data, gas_limit, access_list),
+		None => {
+			let reason = executor.transact_create(caller, value, data, gas_limit, access_list);
+			(reason, Vec::new())
+		}
+	};
+
+	let used_gas = executor.used_gas();
+	let refunded_gas = executor.state().metadata().gasometer().total_used_gas() - used_gas;
+	let fees = pricing.fees(used_gas);
+	executor.state_mut().deposit(coinbase, fees.tip);
+	executor
+		.state_mut()
+		.deposit(caller, total_fee - fees.total());
+	let (accessed_addresses, accessed_storage) = match executor.state().metadata().accessed() {
+		Some(accessed) => (
+			accessed.accessed_addresses.iter().copied().collect(),
+			accessed.accessed_storage.iter().copied().collect(),
+		),
+		None => (Vec::new(), Vec::new()),
+	};
+	let (values, logs) = executor.into_state().deconstruct();
+	let values = values.into_iter().collect::<Vec<_>>();
+	let logs = logs.into_iter().collect::<Vec<Log>>();
+	let substate = Substate {
+		accessed_addresses,
+		accessed_storage,
+		..Substate::new(&values, logs.len())
+	};
+	backend.apply(values, logs.clone(), delete_empty);
+
+	Executed {
+		exit_reason,
+		output,
+		used_gas,
+		refunded_gas,
+		logs,
+		substate,
+	}
+}
+
+/// Execute the call `call` on `backend` under `config` as the system does
+/// around the transactions of a block: without fee, nonce or intrinsic gas,
+/// and with no value. Applies the changes it makes, deleting empty accounts,
+/// and returns how the call ended and its output.
+pub fn system_call<B: Backend + ApplyBackend>(
+	config: &Config,
+	backend: &mut B,
+	call: &Transaction,
+	precompiles: &BTreeMap<H160, PrecompileFn>,
+) -> (ExitReason, Vec<u8>) {
+	let to = call.to.expect("system calls have a recipient");
+	let mut executor = ExecutorBuilder::new(config, precompiles)
+		.gas_limit(call.gas_limit)
+		.build(&*backend);
+	let context = Context {
+		address: to,
+		caller: call.caller,
+		apparent_value: U256::zero(),
+	};
+	let data = call.data.clone();
+	let (exit_reason, output) =
+		match executor.call(to, None, data, Some(call.gas_limit), false, context) {
+			Capture::Exit(exit) => exit,
+			Capture::Trap(interrupt) => match interrupt {},
+		};
+	let (values, logs) = executor.into_state().deconstruct();
+	backend.apply(values, logs, true);
+	(exit_reason, output)
+}
+
+/// Root of the post-state `transaction` leaves on `pre_state`, to compare
+/// with the one a fixture expects, along with what it did.
+pub fn post_state_root(
+	config: &Config,
+	delete_empty: bool,
+	vicinity: &MemoryVicinity,
+	pre_state: BTreeMap<H160, MemoryAccount>,
+	transaction: &Transaction,
+	precompiles: &BTreeMap<H160, PrecompileFn>,
+) -> (H256, Executed) {
+	let mut backend = MemoryBackend::new(vicinity, pre_state);
+	let executed = transact(config, delete_empty, &mut backend, transaction, precompiles);
+	(state_root_of(backend.state()), executed)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fees::FeeSchema;
+	use evm::backend::Basic;
+
+	#[test]
+	fn substate_of_changes() {
+		let modify = |address: u64, code: Option<Vec<u8>>| Apply::Modify {
+			address: H160::from_low_u64_be(address),
+			basic: Basic::default(),
+			code,
+			storage: Vec::<(H256, H256)>::new(),
+			reset_storage: false,
+		};
+		let values = vec![
+			modify(1, None),
+			modify(2, Some(vec![0x00])),
+			Apply::Delete {
+				address: H160::from_low_u64_be(3),
+			},
+		];
+		let substate = Substate::new(&values, 2);
+		assert_eq!(substate.touched.len(), 3);
+		assert_eq!(substate.created, vec![H160::from_low_u64_be(2)]);
+		assert_eq!(substate.deleted, vec![H160::from_low_u64_be(3)]);
+		assert!(substate
+			.to_string()
+			.ends_with("Accessed: 0 addresses, 0 storage slots\nLogs: 2\n"));
+	}
+
+	fn vicinity(gas_price: U256) -> MemoryVicinity {
+		MemoryVicinity {
+			gas_price,
+			origin: H160::zero(),
+			block_hashes: Vec::new(),
+			block_number: U256::zero(),
+			block_coinbase: H160::zero(),
+			block_timestamp: U256::zero(),
+			block_difficulty: U256::zero(),
+			block_gas_limit: U256::zero(),
+			chain_id: U256::one(),
+		}
+	}
+
+	#[test]
+	fn builds_executors_with_gas_limit() {
+		let vicinity = vicinity(U256::zero());
+		let backend = MemoryBackend::new(&vicinity, BTreeMap::new());
+		let config = Config::istanbul();
+		let precompiles = BTreeMap::new();
+		let builder = ExecutorBuilder::new(&config, &precompiles);
+		assert_eq!(builder.build(&backend).gas(), u64::MAX);
+		assert_eq!(builder.gas_limit(1000).build(&backend).gas(), 1000);
+	}
+
+	#[test]
+	fn unfunded_transactions_change_nothing() {
+		let vicinity = vicinity(U256::one());
+		let caller = H160::repeat_byte(1);
+		let mut state = BTreeMap::new();
+		state.insert(
+			caller,
+			MemoryAccount {
+				balance: U256::from(20_999),
+				nonce: U256::zero(),
+				code: Vec::new(),
+				storage: BTreeMap::new(),
+			},
+		);
+		let mut backend = MemoryBackend::new(&vicinity, state.clone());
+		let transaction = Transaction {
+			caller,
+			to: Some(H160::repeat_byte(2)),
+			value: U256::zero(),
+			data: Vec::new(),
+			gas_limit: 21000,
+			access_list: Vec::new(),
+			pricing: None,
+		};
+		let executed = transact(
+			&Config::istanbul(),
+			true,
+			&mut backend,
+			&transaction,
+			&BTreeMap::new(),
+		);
+		assert_eq!(
+			executed.exit_reason,
+			ExitReason::Error(ExitError::OutOfFund)
+		);
+		assert_eq!(executed.used_gas, 0);
+		assert_eq!(backend.state(), &state);
+	}
+
+	#[test]
+	fn base_fee_is_burnt() {
+		let vicinity = vicinity(U256::from(13));
+		let caller = H160::repeat_byte(1);
+		let coinbase = vicinity.block_coinbase;
+		let mut state = BTreeMap::new();
+		state.insert(
+			caller,
+			MemoryAccount {
+				balance: U256::from(1_000_000),
+				nonce: U256::zero(),
+				code: Vec::new(),
+				storage: BTreeMap::new(),
+			},
+		);
+		let mut backend = MemoryBackend::new(&vicinity, state);
+		let transaction = Transaction {
+			caller,
+			to: Some(H160::repeat_byte(2)),
+			value: U256::zero(),
+			data: Vec::new(),
+			gas_limit: 30000,
+			access_list: Vec::new(),
+			pricing: Some(Pricing {
+				schema: FeeSchema::DynamicFee {
+					max_fee: U256::from(20),
+					priority_fee: U256::from(3),
+				},
+				base_fee: Some(U256::from(10)),
+				blob_base_fee: U256::zero(),
+			}),
+		};
+		let executed = transact(
+			&Config::istanbul(),
+			true,
+			&mut backend,
+			&transaction,
+			&BTreeMap::new(),
+		);
+		assert!(executed.exit_reason.is_succeed());
+		assert_eq!(executed.used_gas, 21000);
+		// The sender pays 13 per gas, of which only the tip of 3 is paid out.
+		let balance = |address| backend.state().get(&address).map(|account| account.balance);
+		assert_eq!(balance(caller), Some(U256::from(1_000_000 - 21000 * 13)));
+		assert_eq!(balance(coinbase), Some(U256::from(21000 * 3)));
+	}
+}
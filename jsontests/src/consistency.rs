@@ -0,0 +1,159 @@
+//! Consistency of the post states of an index across adjacent forks. Where
+//! the forks treat a transaction alike the fixture expects the same root on
+//! both, and where they do not it expects different ones; a fixture and the
+//! runner disagreeing on which is the case points at either a fixture bug
+//! or a change the evm gates on the wrong fork.
+
+use crate::builtins::FORKS;
+use crate::fixture::{fork_spec, Indexes};
+use crate::state::{execute, unsupported, Setup, Test};
+use primitive_types::H256;
+use std::fmt;
+
+/// Roots of an index on two adjacent forks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Roots {
+	pub expected: (H256, H256),
+	pub computed: (H256, H256),
+}
+
+/// An index whose roots change between two adjacent forks for the fixture
+/// but not for the runner, or the other way round.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+	pub name: String,
+	pub from: String,
+	pub to: String,
+	pub indexes: Indexes,
+	pub roots: Roots,
+}
+
+impl Divergence {
+	/// Whether the runner computes different roots on the forks where the
+	/// fixture expects the same one.
+	pub fn runner_diverges(&self) -> bool {
+		self.roots.expected.0 == self.roots.expected.1
+	}
+}
+
+impl fmt::Display for Divergence {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let (data, gas, value) = (self.indexes.data, self.indexes.gas, self.indexes.value);
+		write!(
+			f,
+			"{} data {} gas {} value {} from {} to {}: ",
+			self.name, data, gas, value, self.from, self.to
+		)?;
+		let Roots { expected, computed } = self.roots;
+		if self.runner_diverges() {
+			write!(
+				f,
+				"expected {:?} on both, computed {:?} and {:?}, the evm may gate a change \
+				 on the wrong fork",
+				expected.0, computed.0, computed.1
+			)
+		} else {
+			write!(
+				f,
+				"expected {:?} and {:?}, computed {:?} on both, the fixture may be wrong \
+				 or a change is not gated",
+				expected.0, expected.1, computed.0
+			)
+		}
+	}
+}
+
+/// Pairs of consecutive forks of `FORKS` that `test` has post states of and
+/// the runner supports.
+fn adjacent_forks(test: &Test) -> Vec<(&'static str, &'static str)> {
+	let forks = FORKS
+		.iter()
+		.copied()
+		.filter(|fork| test.0.post_states.contains_key(*fork) && unsupported(fork).is_none())
+		.collect::<Vec<_>>();
+	forks
+		.windows(2)
+		.filter(|pair| {
+			let position = |fork| FORKS.iter().position(|name| *name == fork);
+			position(pair[1]) == position(pair[0]).map(|position| position + 1)
+		})
+		.map(|pair| (pair[0], pair[1]))
+		.collect()
+}
+
+/// Root `test` computes for `indexes` on `fork` from `setup`.
+fn computed_root(test: &Test, setup: &Setup, fork: &str, indexes: &Indexes) -> Option<H256> {
+	let vicinity = setup.vicinity.as_ref().ok()?;
+	let spec = fork_spec(fork)?;
+	let execution = execute(test, &spec, indexes, vicinity, &setup.pre_state, ())?;
+	Some(execution.backend.state_root())
+}
+
+/// Indexes of `test` on adjacent forks whose roots diverge for only one of
+/// the fixture and the runner.
+pub fn check(name: &str, test: &Test) -> Vec<Divergence> {
+	let setup = test.setup();
+	let mut divergences = Vec::new();
+	for (from, to) in adjacent_forks(test) {
+		let states = |fork| &test.0.post_states[fork];
+		for later in states(to) {
+			let earlier = match states(from)
+				.iter()
+				.find(|state| state.indexes == later.indexes)
+			{
+				Some(earlier) => earlier,
+				None => continue,
+			};
+			let computed = match (
+				computed_root(test, &setup, from, &later.indexes),
+				computed_root(test, &setup, to, &later.indexes),
+			) {
+				(Some(from), Some(to)) => (from, to),
+				_ => continue,
+			};
+			let expected = (earlier.hash, later.hash);
+			if (expected.0 == expected.1) != (computed.0 == computed.1) {
+				divergences.push(Divergence {
+					name: name.to_string(),
+					from: from.to_string(),
+					to: to.to_string(),
+					indexes: later.indexes,
+					roots: Roots { expected, computed },
+				});
+			}
+		}
+	}
+	divergences
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn divergence_of_roots() {
+		let divergence = |expected, computed| Divergence {
+			name: "add".to_string(),
+			from: "Istanbul".to_string(),
+			to: "Berlin".to_string(),
+			indexes: Indexes {
+				data: 0,
+				gas: 1,
+				value: 0,
+			},
+			roots: Roots { expected, computed },
+		};
+		let (a, b) = (H256::repeat_byte(0xaa), H256::repeat_byte(0xbb));
+
+		let runner = divergence((a, a), (a, b));
+		assert!(runner.runner_diverges());
+		assert!(runner
+			.to_string()
+			.starts_with("add data 0 gas 1 value 0 from Istanbul to Berlin: expected"));
+		assert!(runner.to_string().ends_with("on the wrong fork"));
+
+		let fixture = divergence((a, b), (b, b));
+		assert!(!fixture.runner_diverges());
+		assert!(fixture.to_string().ends_with("a change is not gated"));
+	}
+}
@@ -0,0 +1,146 @@
+//! Console output of test runs: the results of the tests of a fixture file
+//! printed together under it, so files running in parallel do not
+//! interleave, in aligned columns colored when written to a terminal.
+
+use crate::utils::panic_message;
+use std::any::Any;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+static COLOR: AtomicBool = AtomicBool::new(false);
+
+/// Color the output from now on if `color` is set.
+pub fn set_color(color: bool) {
+	COLOR.store(color, Ordering::Relaxed);
+}
+
+/// Whether the output is colored.
+pub fn color() -> bool {
+	COLOR.load(Ordering::Relaxed)
+}
+
+/// Whether standard output is a terminal and `NO_COLOR` is not set.
+pub fn color_by_default() -> bool {
+	atty::is(atty::Stream::Stdout) && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// Result of a test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+	Passed,
+	Failed,
+	Skipped,
+	/// Passed after failing in an earlier run.
+	Fixed,
+}
+
+impl Status {
+	pub fn label(self) -> &'static str {
+		match self {
+			Status::Passed => "ok",
+			Status::Failed => "FAILED",
+			Status::Skipped => "skipped",
+			Status::Fixed => "FIXED",
+		}
+	}
+
+	fn color(self) -> &'static str {
+		match self {
+			Status::Passed | Status::Fixed => GREEN,
+			Status::Failed => RED,
+			Status::Skipped => YELLOW,
+		}
+	}
+
+	/// Label padded to the widest one, colored if the output is.
+	pub fn painted(self) -> String {
+		let label = format!("{:<7}", self.label());
+		if color() {
+			format!("{}{}{}", self.color(), label, RESET)
+		} else {
+			label
+		}
+	}
+}
+
+/// Message a test failed with, from the payload of its panic, which the
+/// digest of failures groups tests by.
+pub fn failure_message(payload: &(dyn Any + Send)) -> String {
+	panic_message(payload)
+}
+
+/// Line of the result of test `name`, followed by `detail`, such as the
+/// output of a failure, indented under it.
+pub fn result_line(status: Status, name: &str, detail: &str) -> String {
+	let mut line = format!("  {} {}", status.painted(), name);
+	for detail in detail.lines() {
+		write!(line, "\n          {}", detail).unwrap();
+	}
+	line
+}
+
+/// Results of the tests of a fixture file.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FileReport {
+	file: String,
+	lines: Vec<String>,
+}
+
+impl FileReport {
+	pub fn new(file: &str) -> Self {
+		FileReport {
+			file: file.to_string(),
+			lines: Vec::new(),
+		}
+	}
+
+	pub fn push(&mut self, status: Status, name: &str, detail: &str) {
+		self.lines.push(result_line(status, name, detail));
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.lines.is_empty()
+	}
+
+	/// The file followed by the results of its tests.
+	pub fn render(&self) -> String {
+		let mut report = format!("{}\n", self.file);
+		for line in &self.lines {
+			report += line;
+			report.push('\n');
+		}
+		report
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn aligned_results() {
+		let mut report = FileReport::new("stExample/add.json");
+		assert!(report.is_empty());
+		report.push(Status::Passed, "add", "");
+		report.push(
+			Status::Failed,
+			"sub",
+			"Running sub ... \nstate root mismatch",
+		);
+		report.push(Status::Skipped, "mul", "");
+		assert_eq!(
+			report.render(),
+			"stExample/add.json\n\
+			 \x20 ok      add\n\
+			 \x20 FAILED  sub\n\
+			 \x20         Running sub ...\x20\n\
+			 \x20         state root mismatch\n\
+			 \x20 skipped mul\n"
+		);
+	}
+}
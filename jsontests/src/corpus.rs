@@ -0,0 +1,141 @@
+//! Selection of a quick corpus: a small subset of state test indexes
+//! executing every opcode and precompile the full suite does, picked
+//! greedily by the coverage [`listen`] observes, to run in CI in place of
+//! the whole suite.
+
+use crate::batch::IndexId;
+use crate::listener::{listen, Listener, PrecompileCall, Step};
+use crate::opcodes;
+use crate::state::Test;
+use primitive_types::H160;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+/// Something executing an index covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Feature {
+	Opcode(u8),
+	Precompile(H160),
+}
+
+impl fmt::Display for Feature {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Feature::Opcode(opcode) => write!(f, "opcode {}", opcodes::display_name(*opcode)),
+			Feature::Precompile(address) => write!(f, "precompile {:?}", address),
+		}
+	}
+}
+
+/// Features covered by each index of a test.
+#[derive(Debug, Default)]
+struct Recorder {
+	current: Option<(String, usize)>,
+	by_index: BTreeMap<(String, usize), BTreeSet<Feature>>,
+}
+
+impl Recorder {
+	fn record(&mut self, feature: Feature) {
+		if let Some(current) = &self.current {
+			self.by_index
+				.get_mut(current)
+				.expect("index started")
+				.insert(feature);
+		}
+	}
+}
+
+impl Listener for Recorder {
+	fn index(&mut self, fork: &str, index: usize) {
+		let current = (fork.to_string(), index);
+		self.by_index.entry(current.clone()).or_default();
+		self.current = Some(current);
+	}
+
+	fn step(&mut self, step: &Step) {
+		self.record(Feature::Opcode(step.opcode));
+	}
+
+	fn precompile(&mut self, call: &PrecompileCall) {
+		self.record(Feature::Precompile(call.address));
+	}
+}
+
+/// Features each index of `test` of `file` covers, on the forks the runner
+/// supports. Indexes that fail still cover what they executed.
+pub fn coverage(file: &str, name: &str, test: &Test) -> Vec<(IndexId, BTreeSet<Feature>)> {
+	listen(test, Recorder::default())
+		.by_index
+		.into_iter()
+		.map(|((fork, index), features)| {
+			let id = IndexId {
+				file: Some(file.to_string()),
+				name: name.to_string(),
+				fork,
+				index,
+			};
+			(id, features)
+		})
+		.collect()
+}
+
+/// Positions in `candidates` of a subset covering every feature they cover
+/// together: the candidate adding the most features first, the earliest of
+/// those on ties, until nothing is left to add.
+pub fn select(candidates: &[(IndexId, BTreeSet<Feature>)]) -> Vec<usize> {
+	let mut uncovered = candidates
+		.iter()
+		.flat_map(|(_, features)| features.iter().copied())
+		.collect::<BTreeSet<_>>();
+	let mut selected = Vec::new();
+	while !uncovered.is_empty() {
+		let (best, added) = candidates
+			.iter()
+			.enumerate()
+			.map(|(i, (_, features))| (i, features.intersection(&uncovered).count()))
+			.fold((0, 0), |best, candidate| {
+				if candidate.1 > best.1 {
+					candidate
+				} else {
+					best
+				}
+			});
+		if added == 0 {
+			break;
+		}
+		for feature in &candidates[best].1 {
+			uncovered.remove(feature);
+		}
+		selected.push(best);
+	}
+	selected
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn candidate(index: usize, features: &[Feature]) -> (IndexId, BTreeSet<Feature>) {
+		let id = IndexId {
+			file: Some("a.json".to_string()),
+			name: "test".to_string(),
+			fork: "Berlin".to_string(),
+			index,
+		};
+		(id, features.iter().copied().collect())
+	}
+
+	#[test]
+	fn greedy_cover() {
+		let ecrecover = Feature::Precompile(H160::from_low_u64_be(1));
+		let candidates = vec![
+			candidate(0, &[Feature::Opcode(0x01)]),
+			candidate(1, &[Feature::Opcode(0x01), Feature::Opcode(0x02)]),
+			candidate(2, &[Feature::Opcode(0x02), ecrecover]),
+			candidate(3, &[ecrecover]),
+		];
+		assert_eq!(select(&candidates), vec![1, 2]);
+		assert!(select(&[]).is_empty());
+		assert_eq!(Feature::Opcode(0x01).to_string(), "opcode ADD");
+	}
+}
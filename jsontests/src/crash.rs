@@ -0,0 +1,77 @@
+//! Panics raised executing an index, by the evm crate or the harness rather
+//! than by a failing check. They are attributed to the index, with the
+//! backtrace a panic hook records, for the report and artifact of the test,
+//! which fails while the run goes on.
+
+use crate::utils::panic_message;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+
+thread_local! {
+	/// Backtrace of the last panic of the thread.
+	static BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// Record the backtrace of every panic, for `catch` to take it.
+pub fn record_backtraces() {
+	let previous = panic::take_hook();
+	panic::set_hook(Box::new(move |info| {
+		BACKTRACE.with(|backtrace| *backtrace.borrow_mut() = Some(Backtrace::force_capture()));
+		previous(info);
+	}));
+}
+
+/// A panic caught executing an index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Crash {
+	/// Id of the index, as `report::index_id` gives it.
+	pub id: String,
+	pub message: String,
+	/// Backtrace of the panic, if `record_backtraces` installed its hook.
+	pub backtrace: Option<String>,
+}
+
+impl Crash {
+	/// Fail the test with the crash, leaving out the backtrace so failures
+	/// still group by their message.
+	pub fn fail(&self) -> ! {
+		panic!("{}", self)
+	}
+}
+
+impl fmt::Display for Crash {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Panicked executing {}: {}", self.id, self.message)
+	}
+}
+
+/// Run `f`, executing the index `id`, catching a panic.
+pub fn catch<T, F: FnOnce() -> T>(id: &str, f: F) -> Result<T, Crash> {
+	BACKTRACE.with(|backtrace| backtrace.borrow_mut().take());
+	panic::catch_unwind(AssertUnwindSafe(f)).map_err(|payload| Crash {
+		id: id.to_string(),
+		message: panic_message(&*payload),
+		backtrace: BACKTRACE
+			.with(|backtrace| backtrace.borrow_mut().take())
+			.map(|backtrace| backtrace.to_string()),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn attributed() {
+		record_backtraces();
+		let crash = catch("add:Berlin:0", || -> u64 { panic!("stack underflow") }).unwrap_err();
+		assert_eq!(
+			crash.to_string(),
+			"Panicked executing add:Berlin:0: stack underflow"
+		);
+		assert!(crash.backtrace.is_some());
+		assert_eq!(catch("add:Berlin:1", || 1), Ok(1));
+	}
+}
@@ -0,0 +1,497 @@
+use crate::callstack::CallStack;
+use crate::fixture::fork_spec;
+use crate::opcodes;
+use crate::state::{execute, Execution, Test};
+use crate::trace::{gas_left, Tracer};
+use crate::utils::flush;
+use ethjson::spec::ForkSpec;
+use evm::{Capture, ExitReason};
+use evm_runtime::tracing::Event as RuntimeEvent;
+use primitive_types::{H160, H256};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::io::BufRead;
+
+/// Condition at which execution is suspended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Breakpoint {
+	Pc(usize),
+	Opcode(u8),
+	Address(H160),
+}
+
+impl Breakpoint {
+	fn hit(&self, step: &StepView) -> bool {
+		match *self {
+			Breakpoint::Pc(pc) => step.pc == pc,
+			Breakpoint::Opcode(opcode) => step.opcode == opcode,
+			Breakpoint::Address(address) => step.address == address,
+		}
+	}
+}
+
+/// A parsed debugger command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+	Step(usize),
+	Continue,
+	UntilFailure,
+	Break(Breakpoint),
+	Delete(usize),
+	Breakpoints,
+	Stack,
+	Memory(usize, usize),
+	Storage(Option<H160>),
+	Where,
+	Help,
+	Quit,
+}
+
+const HELP: &str = "\
+step|s [N]              execute N instructions (default 1)
+continue|c              run until the next breakpoint
+fail|f                  run until an instruction fails or reverts
+break|b pc N            break at program counter N
+break|b op NAME|0xNN    break at every occurrence of an opcode
+break|b addr ADDRESS    break at every instruction executed by ADDRESS
+delete|d N              remove breakpoint N
+breakpoints|bl          list breakpoints
+stack|st                print the stack, top first
+mem|m [OFFSET [LEN]]    hex dump memory (default the whole memory)
+storage|sto [ADDRESS]   print storage of the current or given account
+where|w                 print the current instruction
+quit|q                  stop debugging and run to completion
+help|h                  print this help";
+
+fn parse_number(value: &str) -> Result<usize, String> {
+	let parsed = match value.strip_prefix("0x") {
+		Some(hex) => usize::from_str_radix(hex, 16),
+		None => value.parse(),
+	};
+	parsed.map_err(|_| format!("Invalid number: {}", value))
+}
+
+fn parse_address(value: &str) -> Result<H160, String> {
+	value
+		.trim_start_matches("0x")
+		.parse()
+		.map_err(|_| format!("Invalid address: {}", value))
+}
+
+impl Command {
+	pub fn parse(line: &str) -> Result<Command, String> {
+		let words = line.split_whitespace().collect::<Vec<_>>();
+		let command = match words.as_slice() {
+			[] | ["s"] | ["step"] => Command::Step(1),
+			["s", n] | ["step", n] => Command::Step(parse_number(n)?),
+			["c"] | ["continue"] => Command::Continue,
+			["f"] | ["fail"] => Command::UntilFailure,
+			["b", "pc", pc] | ["break", "pc", pc] => {
+				Command::Break(Breakpoint::Pc(parse_number(pc)?))
+			}
+			["b", "op", op] | ["break", "op", op] => {
+				let opcode = match opcodes::from_name(op) {
+					Some(opcode) => opcode,
+					None if op.starts_with("0x") => parse_number(op)? as u8,
+					None => return Err(format!("Unknown opcode: {}", op)),
+				};
+				Command::Break(Breakpoint::Opcode(opcode))
+			}
+			["b", "addr", address] | ["break", "addr", address] => {
+				Command::Break(Breakpoint::Address(parse_address(address)?))
+			}
+			["d", n] | ["delete", n] => Command::Delete(parse_number(n)?),
+			["bl"] | ["breakpoints"] => Command::Breakpoints,
+			["st"] | ["stack"] => Command::Stack,
+			["m"] | ["mem"] => Command::Memory(0, usize::MAX),
+			["m", offset] | ["mem", offset] => Command::Memory(parse_number(offset)?, usize::MAX),
+			["m", offset, len] | ["mem", offset, len] => {
+				Command::Memory(parse_number(offset)?, parse_number(len)?)
+			}
+			["sto"] | ["storage"] => Command::Storage(None),
+			["sto", address] | ["storage", address] => {
+				Command::Storage(Some(parse_address(address)?))
+			}
+			["w"] | ["where"] => Command::Where,
+			["h"] | ["help"] => Command::Help,
+			["q"] | ["quit"] => Command::Quit,
+			_ => return Err(format!("Unknown command: {}", line.trim())),
+		};
+		Ok(command)
+	}
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+	/// Stop after the given number of further instructions.
+	Step(usize),
+	Continue,
+	UntilFailure,
+	/// Run to completion without prompting, after `quit` or end of input.
+	Detached,
+}
+
+/// Interpreter state at the instruction about to be executed.
+struct StepView {
+	depth: usize,
+	address: H160,
+	pc: usize,
+	opcode: u8,
+	stack: Vec<H256>,
+	memory: Vec<u8>,
+}
+
+/// Tracer suspending execution at every stop and reading commands from
+/// standard input until told to resume.
+pub struct Debugger {
+	mode: Mode,
+	breakpoints: Vec<Breakpoint>,
+	/// Pre-state storage overlaid with every write observed so far.
+	storage: BTreeMap<H160, BTreeMap<H256, H256>>,
+	/// Gas left in every frame entered and not yet left.
+	frames: CallStack<Option<u64>>,
+	last: Option<StepView>,
+	aborted: bool,
+}
+
+impl Debugger {
+	pub fn new(storage: BTreeMap<H160, BTreeMap<H256, H256>>) -> Self {
+		Debugger {
+			mode: Mode::Step(1),
+			breakpoints: Vec::new(),
+			storage,
+			frames: CallStack::new(),
+			last: None,
+			aborted: false,
+		}
+	}
+
+	/// Whether the session was quit before execution finished.
+	pub fn aborted(&self) -> bool {
+		self.aborted
+	}
+
+	fn describe(&self, step: &StepView) -> String {
+		format!(
+			"[depth {}] {:?} pc {:>5}: {:<14} gas left: {}",
+			step.depth,
+			step.address,
+			step.pc,
+			opcodes::display_name(step.opcode),
+			self.frames
+				.last()
+				.copied()
+				.flatten()
+				.map(|gas| gas.to_string())
+				.unwrap_or_else(|| "-".into()),
+		)
+	}
+
+	fn stack(step: &StepView) -> String {
+		let mut out = String::new();
+		for (i, value) in step.stack.iter().rev().enumerate() {
+			writeln!(out, "{:>4}: {:#x}", i, value).unwrap();
+		}
+		out
+	}
+
+	fn memory(step: &StepView, offset: usize, len: usize) -> String {
+		let start = offset.min(step.memory.len());
+		let end = start.saturating_add(len).min(step.memory.len());
+		let mut out = String::new();
+		for (i, row) in step.memory[start..end].chunks(32).enumerate() {
+			writeln!(out, "{:#06x}: {}", start + i * 32, hex::encode(row)).unwrap();
+		}
+		out
+	}
+
+	fn account_storage(&self, address: H160) -> String {
+		let mut out = String::new();
+		for (key, value) in self.storage.get(&address).into_iter().flatten() {
+			writeln!(out, "{:#x} = {:#x}", key, value).unwrap();
+		}
+		out
+	}
+
+	/// Read and run commands until one resumes execution.
+	fn prompt(&mut self) {
+		let stdin = std::io::stdin();
+		loop {
+			print!("(debug) ");
+			flush();
+
+			let mut line = String::new();
+			if stdin.lock().read_line(&mut line).unwrap_or(0) == 0 {
+				println!();
+				self.mode = Mode::Detached;
+				self.aborted = true;
+				return;
+			}
+			let command = match Command::parse(&line) {
+				Ok(command) => command,
+				Err(err) => {
+					println!("{}", err);
+					continue;
+				}
+			};
+
+			let step = self.last.as_ref();
+			match command {
+				Command::Step(n) => {
+					self.mode = Mode::Step(n.max(1));
+					return;
+				}
+				Command::Continue => {
+					self.mode = Mode::Continue;
+					return;
+				}
+				Command::UntilFailure => {
+					self.mode = Mode::UntilFailure;
+					return;
+				}
+				Command::Quit => {
+					self.mode = Mode::Detached;
+					self.aborted = true;
+					return;
+				}
+				Command::Break(breakpoint) => {
+					self.breakpoints.push(breakpoint);
+					println!(
+						"Breakpoint {}: {:?}",
+						self.breakpoints.len() - 1,
+						breakpoint
+					);
+				}
+				Command::Delete(n) if n < self.breakpoints.len() => {
+					self.breakpoints.remove(n);
+				}
+				Command::Delete(n) => println!("No breakpoint {}", n),
+				Command::Breakpoints => {
+					for (i, breakpoint) in self.breakpoints.iter().enumerate() {
+						println!("{}: {:?}", i, breakpoint);
+					}
+				}
+				Command::Stack => print!("{}", step.map(Self::stack).unwrap_or_default()),
+				Command::Memory(offset, len) => print!(
+					"{}",
+					step.map(|step| Self::memory(step, offset, len))
+						.unwrap_or_default()
+				),
+				Command::Storage(address) => {
+					if let Some(address) = address.or_else(|| step.map(|step| step.address)) {
+						print!("{}", self.account_storage(address));
+					}
+				}
+				Command::Where => {
+					if let Some(step) = step {
+						println!("{}", self.describe(step));
+					}
+				}
+				Command::Help => println!("{}", HELP),
+			}
+		}
+	}
+}
+
+impl Tracer for Debugger {
+	fn evm_event(&mut self, event: evm::tracing::Event) {
+		match event {
+			evm::tracing::Event::Call { .. } | evm::tracing::Event::Create { .. } => {
+				self.frames.enter(&event, None)
+			}
+			evm::tracing::Event::Exit { .. } => {
+				self.frames.exit();
+			}
+			_ => (),
+		}
+	}
+
+	fn runtime_event(&mut self, event: RuntimeEvent) {
+		match event {
+			RuntimeEvent::Step {
+				context,
+				opcode,
+				position,
+				stack,
+				memory,
+			} => {
+				for _ in 0..self.frames.step(stack) {
+					self.frames.exit();
+				}
+				let pc = match position {
+					Ok(pc) => *pc,
+					Err(_) => return,
+				};
+				if self.mode == Mode::Detached {
+					return;
+				}
+				let step = StepView {
+					depth: self.frames.len().saturating_sub(1),
+					address: context.address,
+					pc,
+					opcode: opcode.0,
+					stack: stack.data().clone(),
+					memory: memory.data().clone(),
+				};
+
+				let stop = match &mut self.mode {
+					Mode::Step(n) => {
+						*n = n.saturating_sub(1);
+						*n == 0
+					}
+					_ => false,
+				} || self.breakpoints.iter().any(|b| b.hit(&step));
+
+				self.last = Some(step);
+				if stop {
+					if let Some(step) = &self.last {
+						println!("{}", self.describe(step));
+					}
+					self.prompt();
+				}
+			}
+			RuntimeEvent::StepResult {
+				result: Err(Capture::Exit(reason)),
+				..
+			} => {
+				let failed = match reason {
+					ExitReason::Succeed(_) => false,
+					ExitReason::Error(_) | ExitReason::Revert(_) | ExitReason::Fatal(_) => true,
+				};
+				if failed && self.mode == Mode::UntilFailure {
+					println!("Exit: {:?}", reason);
+					if let Some(step) = &self.last {
+						println!("{}", self.describe(step));
+					}
+					self.prompt();
+				}
+			}
+			RuntimeEvent::SStore {
+				address,
+				index,
+				value,
+			} => {
+				self.storage
+					.entry(address)
+					.or_default()
+					.insert(index, value);
+			}
+			_ => (),
+		}
+	}
+
+	fn gas_event(&mut self, event: evm_gasometer::tracing::Event) {
+		if let Some((gas, snapshot)) = self.frames.gas_event(&event) {
+			*gas = Some(gas_left(&snapshot));
+		}
+	}
+}
+
+/// Run a single index of a state test under the interactive debugger, then
+/// compare the resulting state root with the expected one. Without a fork,
+/// the first fork of the test is debugged.
+pub fn debug(test: Test, spec: Option<ForkSpec>, index: usize) {
+	use std::thread;
+
+	const STACK_SIZE: usize = 16 * 1024 * 1024;
+
+	let child = thread::Builder::new()
+		.stack_size(STACK_SIZE)
+		.spawn(move || debug_run(&test, spec.as_ref(), index))
+		.unwrap();
+
+	child.join().unwrap();
+}
+
+fn debug_run(test: &Test, spec: Option<&ForkSpec>, index: usize) {
+	let fork = match spec {
+		Some(spec) => format!("{:?}", spec),
+		None => match test.0.post_states.keys().next() {
+			Some(fork) => fork.clone(),
+			None => {
+				println!("Test has no post states");
+				return;
+			}
+		},
+	};
+	let state = match test.0.post_states.get(&fork).and_then(|s| s.get(index)) {
+		Some(state) => state,
+		None => {
+			println!("No post state {}:{}", fork, index);
+			return;
+		}
+	};
+	let spec = match fork_spec(&fork) {
+		Some(spec) => spec,
+		None => {
+			println!("Unsupported fork {}", fork);
+			return;
+		}
+	};
+	let spec = &spec;
+
+	let original_state = test.unwrap_to_pre_state();
+	let vicinity = test.unwrap_to_vicinity();
+	let storage = original_state
+		.iter()
+		.map(|(address, account)| (*address, account.storage.clone()))
+		.collect();
+
+	println!("Type `help` for a list of commands.");
+	let Execution {
+		backend, tracer, ..
+	} = match execute(
+		test,
+		spec,
+		&state.indexes,
+		&vicinity,
+		&original_state,
+		Debugger::new(storage),
+	) {
+		Some(execution) => execution,
+		None => {
+			println!("Unsupported spec {:?}", spec);
+			return;
+		}
+	};
+
+	if tracer.aborted() {
+		println!("Session aborted, execution ran to completion");
+	}
+	let root = backend.state_root();
+	println!("State root: {:?}", root);
+	println!("Expected:   {:?}", state.hash);
+	println!(
+		"{}",
+		if root == state.hash {
+			"passed"
+		} else {
+			"failed"
+		}
+	);
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parse_commands() {
+		assert_eq!(Command::parse(""), Ok(Command::Step(1)));
+		assert_eq!(Command::parse("step 10"), Ok(Command::Step(10)));
+		assert_eq!(
+			Command::parse("b op sstore"),
+			Ok(Command::Break(Breakpoint::Opcode(0x55)))
+		);
+		assert_eq!(
+			Command::parse("b op 0xfe"),
+			Ok(Command::Break(Breakpoint::Opcode(0xfe)))
+		);
+		assert_eq!(
+			Command::parse("b pc 0x1f"),
+			Ok(Command::Break(Breakpoint::Pc(0x1f)))
+		);
+		assert_eq!(Command::parse("mem 32 64"), Ok(Command::Memory(32, 64)));
+		assert!(Command::parse("b op NOSUCHOP").is_err());
+	}
+}
@@ -0,0 +1,178 @@
+use evm::backend::MemoryAccount;
+use primitive_types::{H160, H256, U256};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const RESET: &str = "\x1b[0m";
+
+/// Change of a single storage slot. `None` means the slot is unset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SlotChange {
+	pub key: H256,
+	pub from: Option<H256>,
+	pub to: Option<H256>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AccountChange {
+	Created(H160, MemoryAccount),
+	Deleted(H160, MemoryAccount),
+	Modified {
+		address: H160,
+		balance: Option<(U256, U256)>,
+		nonce: Option<(U256, U256)>,
+		code: Option<(Vec<u8>, Vec<u8>)>,
+		storage: Vec<SlotChange>,
+	},
+}
+
+impl AccountChange {
+	pub fn address(&self) -> H160 {
+		match self {
+			AccountChange::Created(address, _) | AccountChange::Deleted(address, _) => *address,
+			AccountChange::Modified { address, .. } => *address,
+		}
+	}
+}
+
+fn storage_diff(from: &BTreeMap<H256, H256>, to: &BTreeMap<H256, H256>) -> Vec<SlotChange> {
+	from.keys()
+		.chain(to.keys())
+		.collect::<BTreeSet<_>>()
+		.into_iter()
+		.filter_map(|key| {
+			let from = from.get(key).cloned();
+			let to = to.get(key).cloned();
+			if from == to {
+				None
+			} else {
+				Some(SlotChange {
+					key: *key,
+					from,
+					to,
+				})
+			}
+		})
+		.collect()
+}
+
+pub fn state_diff(
+	from: &BTreeMap<H160, MemoryAccount>,
+	to: &BTreeMap<H160, MemoryAccount>,
+) -> Vec<AccountChange> {
+	from.keys()
+		.chain(to.keys())
+		.collect::<BTreeSet<_>>()
+		.into_iter()
+		.filter_map(|address| match (from.get(address), to.get(address)) {
+			(None, Some(account)) => Some(AccountChange::Created(*address, account.clone())),
+			(Some(account), None) => Some(AccountChange::Deleted(*address, account.clone())),
+			(Some(a), Some(b)) if a != b => Some(AccountChange::Modified {
+				address: *address,
+				balance: if a.balance != b.balance {
+					Some((a.balance, b.balance))
+				} else {
+					None
+				},
+				nonce: if a.nonce != b.nonce {
+					Some((a.nonce, b.nonce))
+				} else {
+					None
+				},
+				code: if a.code != b.code {
+					Some((a.code.clone(), b.code.clone()))
+				} else {
+					None
+				},
+				storage: storage_diff(&a.storage, &b.storage),
+			}),
+			_ => None,
+		})
+		.collect()
+}
+
+fn paint(color: bool, code: &str, text: String) -> String {
+	if color {
+		format!("{}{}{}", code, text, RESET)
+	} else {
+		text
+	}
+}
+
+fn slot(value: &Option<H256>) -> String {
+	match value {
+		Some(value) => format!("{:#x}", U256::from_big_endian(&value[..])),
+		None => "<unset>".to_string(),
+	}
+}
+
+/// Render a state diff for the console, one line per changed field.
+pub fn render_diff(changes: &[AccountChange], color: bool) -> String {
+	let mut out = String::new();
+
+	for change in changes {
+		match change {
+			AccountChange::Created(address, account) => {
+				let line = format!(
+					"+ {:?} balance: {}, nonce: {}, code: {} bytes, storage: {} slots",
+					address,
+					account.balance,
+					account.nonce,
+					account.code.len(),
+					account.storage.len()
+				);
+				writeln!(out, "{}", paint(color, GREEN, line)).unwrap();
+			}
+			AccountChange::Deleted(address, account) => {
+				let line = format!(
+					"- {:?} balance: {}, nonce: {}, code: {} bytes, storage: {} slots",
+					address,
+					account.balance,
+					account.nonce,
+					account.code.len(),
+					account.storage.len()
+				);
+				writeln!(out, "{}", paint(color, RED, line)).unwrap();
+			}
+			AccountChange::Modified {
+				address,
+				balance,
+				nonce,
+				code,
+				storage,
+			} => {
+				writeln!(out, "{}", paint(color, YELLOW, format!("~ {:?}", address))).unwrap();
+				if let Some((from, to)) = balance {
+					writeln!(out, "    balance: {} -> {}", from, to).unwrap();
+				}
+				if let Some((from, to)) = nonce {
+					writeln!(out, "    nonce: {} -> {}", from, to).unwrap();
+				}
+				if let Some((from, to)) = code {
+					writeln!(
+						out,
+						"    code: 0x{} -> 0x{}",
+						hex::encode(from),
+						hex::encode(to)
+					)
+					.unwrap();
+				}
+				for s in storage {
+					writeln!(
+						out,
+						"    storage[{:#x}]: {} -> {}",
+						s.key,
+						paint(color, RED, slot(&s.from)),
+						paint(color, GREEN, slot(&s.to))
+					)
+					.unwrap();
+				}
+			}
+		}
+	}
+
+	out
+}
@@ -0,0 +1,197 @@
+//! Disassembly of the code a failing test runs, annotated with the jump
+//! destinations of each jump whose target is pushed right before it.
+
+use crate::opcodes::{display_name, immediate_size};
+use serde_json::Value;
+use std::collections::BTreeSet;
+use std::fmt::Write;
+
+const JUMP: u8 = 0x56;
+const JUMPI: u8 = 0x57;
+const JUMPDEST: u8 = 0x5b;
+
+/// Offsets of `JUMPDEST`s in `code` outside push data.
+pub fn jump_destinations(code: &[u8]) -> BTreeSet<usize> {
+	let mut destinations = BTreeSet::new();
+	let mut pc = 0;
+	while pc < code.len() {
+		if code[pc] == JUMPDEST {
+			destinations.insert(pc);
+		}
+		pc += 1 + immediate_size(code[pc]);
+	}
+	destinations
+}
+
+/// One line per instruction of `code`, with its offset and immediate.
+/// Jumps to a pushed constant name where they land, and jump destinations
+/// are marked.
+pub fn disassemble(code: &[u8]) -> String {
+	let destinations = jump_destinations(code);
+	let mut text = String::new();
+	let mut pushed: Option<usize> = None;
+	let mut pc = 0;
+	while pc < code.len() {
+		let opcode = code[pc];
+		let size = immediate_size(opcode);
+		let immediate = &code[(pc + 1).min(code.len())..(pc + 1 + size).min(code.len())];
+		write!(text, "{:04x}: {}", pc, display_name(opcode)).unwrap();
+		if size > 0 {
+			write!(text, " 0x{}", hex::encode(immediate)).unwrap();
+			if immediate.len() < size {
+				text.push_str(" (truncated)");
+			}
+		}
+		match opcode {
+			JUMPDEST => text.push_str("  <- jump destination"),
+			JUMP | JUMPI => match pushed {
+				Some(target) if destinations.contains(&target) => {
+					write!(text, "  -> {:04x}", target).unwrap()
+				}
+				Some(target) => write!(text, "  -> {:04x} (invalid destination)", target).unwrap(),
+				None => {}
+			},
+			_ => {}
+		}
+		text.push('\n');
+
+		pushed = if size > 0 && immediate.len() <= 8 {
+			Some(
+				immediate
+					.iter()
+					.fold(0, |value, byte| value << 8 | *byte as usize),
+			)
+		} else {
+			None
+		};
+		pc += 1 + size;
+	}
+	text
+}
+
+fn bytes(value: &Value) -> Option<Vec<u8>> {
+	let hex = value.as_str()?;
+	hex::decode(hex.trim_start_matches("0x")).ok()
+}
+
+fn push_section(text: &mut String, title: &str, code: &[u8]) {
+	if code.is_empty() {
+		return;
+	}
+	writeln!(text, "# {}", title).unwrap();
+	text.push_str(&disassemble(code));
+	text.push('\n');
+}
+
+/// Disassembly of the code the test `test` of a `kind` fixture runs: the
+/// target contracts of its transactions, the init code of creations and,
+/// where the fixture lists its post state, the contracts created. Of a state
+/// test only the data of `index`, if given, is looked at. `None` if the
+/// test runs no code.
+pub fn of_test(kind: &str, test: &Value, index: Option<usize>) -> Option<String> {
+	let pre = test["pre"].as_object();
+	let code_at = |address: &str| {
+		let address = address.to_lowercase();
+		pre?.iter()
+			.find(|(account, _)| account.to_lowercase() == address)
+			.and_then(|(_, account)| bytes(&account["code"]))
+	};
+
+	let mut text = String::new();
+	match kind {
+		"vm" => {
+			let code = bytes(&test["exec"]["code"]).unwrap_or_default();
+			push_section(&mut text, "code", &code);
+		}
+		"state" => {
+			let transaction = &test["transaction"];
+			let to = transaction["to"].as_str().unwrap_or_default();
+			if to.is_empty() {
+				let data = transaction["data"].as_array().cloned().unwrap_or_default();
+				for (i, data) in data.iter().enumerate() {
+					if index.map_or(true, |index| index == i) {
+						let code = bytes(data).unwrap_or_default();
+						push_section(&mut text, &format!("init code of data {}", i), &code);
+					}
+				}
+			} else {
+				let code = code_at(to).unwrap_or_default();
+				push_section(&mut text, &format!("target {}", to), &code);
+			}
+		}
+		_ => {
+			let mut targets = BTreeSet::new();
+			let blocks = test["blocks"].as_array().cloned().unwrap_or_default();
+			for (i, block) in blocks.iter().enumerate() {
+				let transactions = block["transactions"]
+					.as_array()
+					.cloned()
+					.unwrap_or_default();
+				for (j, transaction) in transactions.iter().enumerate() {
+					match transaction["to"].as_str().unwrap_or_default() {
+						"" => {
+							let code = bytes(&transaction["data"]).unwrap_or_default();
+							let title = format!("init code of transaction {} of block {}", j, i);
+							push_section(&mut text, &title, &code);
+						}
+						to => {
+							targets.insert(to.to_lowercase());
+						}
+					}
+				}
+			}
+			for target in targets {
+				let code = code_at(&target).unwrap_or_default();
+				push_section(&mut text, &format!("target {}", target), &code);
+			}
+			if let Some(post) = test["postState"].as_object() {
+				for (address, account) in post {
+					if code_at(address).is_none() {
+						let code = bytes(&account["code"]).unwrap_or_default();
+						push_section(&mut text, &format!("created {}", address), &code);
+					}
+				}
+			}
+		}
+	}
+	if text.is_empty() {
+		None
+	} else {
+		Some(text)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn jumps_are_annotated() {
+		// The byte at 4 is a JUMPDEST inside push data.
+		let code = hex::decode("600556605b5b600457").unwrap();
+		assert_eq!(
+			jump_destinations(&code).into_iter().collect::<Vec<_>>(),
+			[5]
+		);
+		assert_eq!(
+			disassemble(&code),
+			"0000: PUSH1 0x05\n\
+			 0002: JUMP  -> 0005\n\
+			 0003: PUSH1 0x5b\n\
+			 0005: JUMPDEST  <- jump destination\n\
+			 0006: PUSH1 0x04\n\
+			 0008: JUMPI  -> 0004 (invalid destination)\n"
+		);
+
+		let test = json!({
+			"pre": { "0x00000000000000000000000000000000000000AA": { "code": "0x00" } },
+			"transaction": { "to": "0x00000000000000000000000000000000000000aa", "data": ["0x"] },
+		});
+		let text = of_test("state", &test, Some(0)).unwrap();
+		assert_eq!(
+			text,
+			"# target 0x00000000000000000000000000000000000000aa\n0000: STOP\n\n"
+		);
+	}
+}
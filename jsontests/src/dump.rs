@@ -0,0 +1,95 @@
+use crate::utils::{code_hash, dump_account, state_root, storage_root};
+use evm::backend::MemoryAccount;
+use primitive_types::{H160, U256};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{self, BufWriter};
+use std::path::{Path, PathBuf};
+
+/// World state dump, in the format produced by geth's `dump` command.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct StateDump {
+	pub root: String,
+	pub accounts: BTreeMap<String, AccountDump>,
+}
+
+/// Single account entry of a geth-style state dump.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccountDump {
+	/// Balance, as a decimal string.
+	pub balance: String,
+	pub nonce: u64,
+	pub root: String,
+	pub code_hash: String,
+	#[serde(skip_serializing_if = "String::is_empty")]
+	pub code: String,
+	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
+	pub storage: BTreeMap<String, String>,
+}
+
+impl AccountDump {
+	pub fn new(account: &MemoryAccount) -> Self {
+		AccountDump {
+			balance: account.balance.to_string(),
+			nonce: account.nonce.low_u64(),
+			root: format!("{:#x}", storage_root(account)),
+			code_hash: format!("{:#x}", code_hash(&account.code)),
+			code: if account.code.is_empty() {
+				String::new()
+			} else {
+				format!("0x{}", hex::encode(&account.code))
+			},
+			storage: account
+				.storage
+				.iter()
+				.map(|(k, v)| {
+					(
+						format!("{:#x}", k),
+						format!("{:#x}", U256::from_big_endian(&v[..])),
+					)
+				})
+				.collect(),
+		}
+	}
+}
+
+impl StateDump {
+	pub fn new(state: &BTreeMap<H160, MemoryAccount>) -> Self {
+		StateDump {
+			root: format!("{:#x}", state_root(state)),
+			accounts: state
+				.keys()
+				.map(|address| (format!("{:#x}", address), dump_account(state, *address)))
+				.collect(),
+		}
+	}
+
+	pub fn write(&self, path: &Path) -> io::Result<()> {
+		if let Some(parent) = path.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		let writer = BufWriter::new(File::create(path)?);
+		serde_json::to_writer_pretty(writer, self)?;
+		Ok(())
+	}
+}
+
+/// `name` with every character not safe in a file name replaced.
+pub fn file_stem(name: &str) -> String {
+	name.chars()
+		.map(|c| {
+			if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+				c
+			} else {
+				'_'
+			}
+		})
+		.collect()
+}
+
+/// File name a dump for the given test index is written to inside `dir`.
+pub fn dump_path(dir: &Path, name: &str, spec: &str, index: usize) -> PathBuf {
+	dir.join(format!("{}-{}-{}.json", file_stem(name), spec, index))
+}
@@ -0,0 +1,86 @@
+//! Running fixtures handed over as bytes, for embedders without a file
+//! system or threads such as wasm runtimes. What tests print is returned
+//! with their outcome instead of going to standard output.
+
+use crate::blockchain;
+use crate::capture;
+use crate::state;
+use crate::summary::Outcome;
+use crate::utils::panic_message;
+use crate::vm;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Result of one test of a fixture.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TestResult {
+	pub name: String,
+	/// What the test executed, or the message it failed with.
+	pub outcome: Result<Outcome, String>,
+	/// Everything the test printed.
+	pub output: String,
+}
+
+impl TestResult {
+	/// The result as a JSON object of `name`, `passed`, `executed`,
+	/// `skipped`, `error` and `output`.
+	pub fn to_json(&self) -> Value {
+		let (executed, skipped) = match &self.outcome {
+			Ok(outcome) => (outcome.executed, outcome.skipped),
+			Err(_) => (0, 0),
+		};
+		json!({
+			"name": self.name,
+			"passed": self.outcome.is_ok(),
+			"executed": executed,
+			"skipped": skipped,
+			"error": self.outcome.as_ref().err(),
+			"output": self.output,
+		})
+	}
+}
+
+/// Run every test of the fixture `json` with `run`. Failures are caught
+/// where panics unwind: on `wasm32-unknown-unknown` a failing test aborts.
+fn run_all<T, F>(json: &[u8], run: F) -> Result<Vec<TestResult>, String>
+where
+	T: DeserializeOwned,
+	F: Fn(&str, T) -> Outcome,
+{
+	let tests: BTreeMap<String, T> = serde_json::from_slice(json).map_err(|err| err.to_string())?;
+	Ok(tests
+		.into_iter()
+		.map(|(name, test)| {
+			let (outcome, output) =
+				capture::capture(|| panic::catch_unwind(AssertUnwindSafe(|| run(&name, test))));
+			TestResult {
+				outcome: outcome.map_err(|payload| panic_message(&*payload)),
+				name,
+				output,
+			}
+		})
+		.collect())
+}
+
+pub fn run_state(json: &[u8], options: &state::Options) -> Result<Vec<TestResult>, String> {
+	run_all(json, |name, test| {
+		state::test_with_options(name, test, options)
+	})
+}
+
+pub fn run_vm(json: &[u8], options: &vm::Options) -> Result<Vec<TestResult>, String> {
+	run_all(json, |name, test| {
+		vm::test_with_options(name, test, options)
+	})
+}
+
+pub fn run_blockchain(
+	json: &[u8],
+	options: &blockchain::Options,
+) -> Result<Vec<TestResult>, String> {
+	run_all(json, |name, test| {
+		blockchain::test_with_options(name, test, options)
+	})
+}
@@ -0,0 +1,213 @@
+//! Filler-style encodings of calldata and code: plain hex, `:raw 0x..`,
+//! `:abi fn(types) args..` and, with the `solc` feature, `:yul` and
+//! `:solidity` sources.
+
+use ethabi::param_type::Reader;
+use ethabi::token::{LenientTokenizer, Tokenizer};
+use serde::{Deserialize, Deserializer};
+
+/// Bytes `value` encodes.
+pub fn decode(value: &str) -> Result<Vec<u8>, String> {
+	let value = value.trim();
+	if let Some(raw) = value.strip_prefix(":raw") {
+		return decode_hex(raw.trim());
+	}
+	if let Some(abi) = value.strip_prefix(":abi") {
+		return encode_abi(abi.trim());
+	}
+	if let Some(yul) = value.strip_prefix(":yul") {
+		return compile(Language::Yul, yul.trim());
+	}
+	if let Some(solidity) = value.strip_prefix(":solidity") {
+		return compile(Language::Solidity, solidity.trim());
+	}
+	if value.starts_with(':') {
+		let encoding = value.split_whitespace().next().unwrap_or(value);
+		return Err(format!("unknown encoding {}", encoding));
+	}
+	decode_hex(value)
+}
+
+/// Deserialize a string in any encoding `decode` accepts.
+pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+	let value = String::deserialize(deserializer)?;
+	decode(&value).map_err(serde::de::Error::custom)
+}
+
+fn decode_hex(value: &str) -> Result<Vec<u8>, String> {
+	hex::decode(value.trim_start_matches("0x")).map_err(|err| format!("{}: {}", value, err))
+}
+
+/// Calldata of `signature args..`, e.g. `baz(uint32,bool) 69 1`. Arguments are
+/// separated by whitespace, so arrays and tuples must not contain any.
+fn encode_abi(call: &str) -> Result<Vec<u8>, String> {
+	let mut words = call.split_whitespace();
+	let signature = words.next().ok_or("missing function signature")?;
+	let open = signature
+		.find('(')
+		.filter(|_| signature.ends_with(')'))
+		.ok_or_else(|| format!("invalid function signature {}", signature))?;
+	let name = &signature[..open];
+	let types = split_types(&signature[open + 1..signature.len() - 1]);
+	let params = types
+		.iter()
+		.map(|param| Reader::read(param).map_err(|err| format!("{}: {}", param, err)))
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let args = words.collect::<Vec<_>>();
+	if args.len() != params.len() {
+		return Err(format!(
+			"{} takes {} arguments, {} given",
+			signature,
+			params.len(),
+			args.len()
+		));
+	}
+	let tokens = params
+		.iter()
+		.zip(&args)
+		.map(|(param, arg)| {
+			LenientTokenizer::tokenize(param, arg).map_err(|err| format!("{}: {}", arg, err))
+		})
+		.collect::<Result<Vec<_>, _>>()?;
+
+	let mut data = ethabi::short_signature(name, &params).to_vec();
+	data.extend(ethabi::encode(&tokens));
+	Ok(data)
+}
+
+/// Parameter types of a signature, split at the commas outside of tuples.
+fn split_types(types: &str) -> Vec<&str> {
+	let mut split = Vec::new();
+	let mut depth = 0;
+	let mut start = 0;
+	for (i, c) in types.char_indices() {
+		match c {
+			'(' => depth += 1,
+			')' => depth -= 1,
+			',' if depth == 0 => {
+				split.push(types[start..i].trim());
+				start = i + 1;
+			}
+			_ => (),
+		}
+	}
+	let last = types[start..].trim();
+	if !last.is_empty() {
+		split.push(last);
+	}
+	split
+}
+
+#[derive(Clone, Copy, Debug)]
+enum Language {
+	Yul,
+	Solidity,
+}
+
+/// Bytecode of `source`, which may start with the EVM version to compile
+/// for, e.g. `:yul berlin { .. }`. Solidity sources compile to the runtime
+/// code of their last contract.
+#[cfg(feature = "solc")]
+fn compile(language: Language, source: &str) -> Result<Vec<u8>, String> {
+	use std::io::Write;
+	use std::process::{Command, Stdio};
+
+	let (version, source) = match source.split_once(char::is_whitespace) {
+		Some((version, rest)) if version.chars().all(|c| c.is_ascii_alphabetic()) => {
+			(Some(version.to_lowercase()), rest)
+		}
+		_ => (None, source),
+	};
+	let mut command = Command::new("solc");
+	match language {
+		Language::Yul => command.args(&["--strict-assembly", "--bin", "-"]),
+		Language::Solidity => command.args(&["--bin-runtime", "-"]),
+	};
+	if let Some(version) = &version {
+		command.args(&["--evm-version", version]);
+	}
+	let mut child = command
+		.stdin(Stdio::piped())
+		.stdout(Stdio::piped())
+		.stderr(Stdio::piped())
+		.spawn()
+		.map_err(|err| format!("Running solc failed: {}", err))?;
+	child
+		.stdin
+		.take()
+		.unwrap()
+		.write_all(source.as_bytes())
+		.map_err(|err| format!("Writing to solc failed: {}", err))?;
+	let output = child
+		.wait_with_output()
+		.map_err(|err| format!("Running solc failed: {}", err))?;
+	if !output.status.success() {
+		return Err(format!(
+			"solc failed: {}",
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+
+	// The bytecode is the line following the last binary heading.
+	let stdout = String::from_utf8_lossy(&output.stdout);
+	let heading = match language {
+		Language::Yul => "Binary representation:",
+		Language::Solidity => "Binary of the runtime part:",
+	};
+	let lines = stdout.lines().collect::<Vec<_>>();
+	let position = lines
+		.iter()
+		.rposition(|line| line.starts_with(heading))
+		.ok_or_else(|| format!("No bytecode in solc output:\n{}", stdout))?;
+	decode_hex(lines.get(position + 1).unwrap_or(&"").trim())
+}
+
+#[cfg(not(feature = "solc"))]
+fn compile(language: Language, _source: &str) -> Result<Vec<u8>, String> {
+	Err(format!(
+		"{:?} sources need a build with the solc feature",
+		language
+	))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn hex_and_raw() {
+		assert_eq!(decode("0x0102").unwrap(), vec![1, 2]);
+		assert_eq!(decode(":raw 0x0102").unwrap(), vec![1, 2]);
+		assert_eq!(decode("").unwrap(), Vec::<u8>::new());
+		assert!(decode(":lll {}").is_err());
+	}
+
+	#[test]
+	fn abi() {
+		// Example of the Solidity ABI specification.
+		let data = decode(":abi baz(uint32,bool) 69 1").unwrap();
+		let mut expected = hex::decode("cdcd77c0").unwrap();
+		expected.extend(vec![0; 31]);
+		expected.push(0x45);
+		expected.extend(vec![0; 31]);
+		expected.push(0x01);
+		assert_eq!(data, expected);
+
+		assert_eq!(
+			decode(":abi f()").unwrap(),
+			hex::decode("26121ff0").unwrap()
+		);
+		assert!(decode(":abi baz(uint32,bool) 69").is_err());
+		assert!(decode(":abi baz").is_err());
+	}
+
+	#[test]
+	fn tuple_types() {
+		assert_eq!(
+			split_types("uint256,(address,bool),bytes"),
+			vec!["uint256", "(address,bool)", "bytes"]
+		);
+		assert!(split_types("").is_empty());
+	}
+}
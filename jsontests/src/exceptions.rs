@@ -0,0 +1,202 @@
+//! Mapping between the exceptions fixtures expect and the reasons the runner
+//! has for them, read from `exceptions.toml` so that exceptions of new forks
+//! are added without code changes.
+
+use evm::ExitReason;
+use once_cell::sync::OnceCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Table used unless another one is set, built into the binary.
+const DEFAULT_TABLE: &str = include_str!("../res/exceptions.toml");
+
+/// Reasons a table may name: rejections of transactions and blocks, then
+/// exit reasons by type.
+pub const REASONS: &[&str] = &[
+	"Rejection::IntrinsicGas",
+	"Rejection::InitCodeLimitExceeded",
+	"Rejection::FeeCapBelowBaseFee",
+	"Rejection::PriorityFeeAboveFeeCap",
+	"Rejection::InsufficientFunds",
+	"Rejection::GasLimitReached",
+	"Rejection::NoBlobs",
+	"Rejection::TooManyBlobs",
+	"Block::Rlp",
+	"Block::UnknownParent",
+	"Block::InvalidNumber",
+	"Block::TimestampEqualParent",
+	"Block::TimestampOlderParent",
+	"Block::ExtraDataTooLong",
+	"Block::InvalidGasLimit",
+	"Block::GasUsedAboveLimit",
+	"Block::MissingBaseFee",
+	"Block::UnexpectedBaseFee",
+	"Block::InvalidBaseFee",
+	"Block::MissingBlobGas",
+	"Block::UnexpectedBlobGas",
+	"Block::BlobGasAboveLimit",
+	"Block::BlobGasNotMultiple",
+	"Block::InvalidExcessBlobGas",
+	"Block::InvalidDaoExtraData",
+	"Block::NoForkActive",
+	"Block::UnsupportedFork",
+	"Block::InvalidSignature",
+	"Block::NonceTooLow",
+	"Block::NonceTooHigh",
+	"Block::InsufficientFunds",
+	"Block::GasLimitReached",
+	"Block::GasUsedMismatch",
+	"Block::TransactionsRootMismatch",
+	"Block::ReceiptsRootMismatch",
+	"Block::OmmersHashMismatch",
+	"Block::TooManyOmmers",
+	"Block::DuplicateOmmer",
+	"Block::OmmerInChain",
+	"Block::OmmerIsAncestor",
+	"Block::OmmerParentNotAncestor",
+	"Block::InvalidOmmer",
+	"Block::StateRootMismatch",
+	"Block::InvalidDepositLog",
+	"Block::SystemCallFailed",
+	"Block::InvalidRequests",
+	"Block::RequestsHashMismatch",
+	"ExitError::StackUnderflow",
+	"ExitError::StackOverflow",
+	"ExitError::InvalidJump",
+	"ExitError::InvalidRange",
+	"ExitError::DesignatedInvalid",
+	"ExitError::CallTooDeep",
+	"ExitError::CreateCollision",
+	"ExitError::CreateContractLimit",
+	"ExitError::OutOfOffset",
+	"ExitError::OutOfGas",
+	"ExitError::OutOfFund",
+	"ExitError::PCUnderflow",
+	"ExitError::CreateEmpty",
+	"ExitError::Other",
+	"ExitRevert::Reverted",
+	"ExitFatal::NotSupported",
+	"ExitFatal::UnhandledInterrupt",
+	"ExitFatal::CallErrorAsFatal",
+	"ExitFatal::Other",
+];
+
+/// Exceptions by the reasons standing for them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Exceptions {
+	by_reason: BTreeMap<String, String>,
+}
+
+impl Exceptions {
+	/// Parse a table of exceptions to their reasons. Unknown reasons, and
+	/// reasons of more than one exception, are errors.
+	pub fn parse(toml: &str) -> Result<Self, String> {
+		let table: BTreeMap<String, Vec<String>> =
+			toml::from_str(toml).map_err(|err| err.to_string())?;
+		let mut by_reason = BTreeMap::<String, String>::new();
+		for (exception, reasons) in table {
+			if reasons.is_empty() {
+				return Err(format!("{}: no reasons", exception));
+			}
+			for reason in reasons {
+				if !REASONS.contains(&reason.as_str()) {
+					return Err(format!("{}: unknown reason {}", exception, reason));
+				}
+				if let Some(other) = by_reason.get(&reason) {
+					return Err(format!(
+						"{}: ambiguous, also the reason of {}",
+						reason, other
+					));
+				}
+				by_reason.insert(reason, exception.clone());
+			}
+		}
+		Ok(Exceptions { by_reason })
+	}
+
+	/// Read the table at `path`.
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let toml =
+			fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+		Self::parse(&toml).map_err(|err| format!("{}: {}", path.display(), err))
+	}
+
+	/// Exception of `reason`, one of `REASONS`, if the table has it.
+	pub fn exception(&self, reason: &str) -> Option<&str> {
+		self.by_reason.get(reason).map(String::as_str)
+	}
+
+	/// Whether `expected`, exceptions separated by `|`, includes the one of
+	/// `reason`.
+	pub fn is_expected(&self, expected: &str, reason: &str) -> bool {
+		match self.exception(reason) {
+			Some(exception) => expected
+				.split('|')
+				.any(|expected| expected.trim() == exception),
+			None => false,
+		}
+	}
+}
+
+/// Reason of an execution ending with `reason`, as tables name it, or `None`
+/// if it succeeded.
+pub fn exit_reason(reason: &ExitReason) -> Option<String> {
+	// Variants are named by their `Debug` form without the fields.
+	let variant = |debug: String| debug.split('(').next().unwrap_or_default().to_string();
+	match reason {
+		ExitReason::Succeed(_) => None,
+		ExitReason::Error(error) => Some(format!("ExitError::{}", variant(format!("{:?}", error)))),
+		ExitReason::Revert(revert) => {
+			Some(format!("ExitRevert::{}", variant(format!("{:?}", revert))))
+		}
+		ExitReason::Fatal(fatal) => Some(format!("ExitFatal::{}", variant(format!("{:?}", fatal)))),
+	}
+}
+
+static TABLE: OnceCell<Exceptions> = OnceCell::new();
+
+/// Map exceptions with `table` instead of the built-in one. Fails once the
+/// table is in use or another one was set.
+pub fn set_table(table: Exceptions) -> Result<(), String> {
+	TABLE
+		.set(table)
+		.map_err(|_| "exception table already in use".to_string())
+}
+
+/// The exception table in use.
+pub fn table() -> &'static Exceptions {
+	TABLE.get_or_init(|| {
+		Exceptions::parse(DEFAULT_TABLE)
+			.unwrap_or_else(|err| panic!("Invalid built-in exception table: {}", err))
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use evm::{ExitError, ExitSucceed};
+
+	#[test]
+	fn flags_ambiguous_reasons() {
+		let exceptions = Exceptions::parse(DEFAULT_TABLE).unwrap();
+		let reason = exit_reason(&ExitReason::Error(ExitError::OutOfGas)).unwrap();
+		assert_eq!(exceptions.exception(&reason), Some("OOG"));
+		assert!(exceptions.is_expected("TR_IntrinsicGas|OOG", &reason));
+		assert!(exceptions.is_expected("InvalidGasLimit", "Block::InvalidGasLimit"));
+		assert_eq!(
+			exit_reason(&ExitReason::Succeed(ExitSucceed::Stopped)),
+			None
+		);
+		assert_eq!(
+			exit_reason(&ExitReason::Error(ExitError::Other("no".into()))).as_deref(),
+			Some("ExitError::Other")
+		);
+
+		let ambiguous = "OOG = [\"ExitError::OutOfGas\"]\nOutOfGas = [\"ExitError::OutOfGas\"]";
+		assert!(Exceptions::parse(ambiguous)
+			.unwrap_err()
+			.contains("ambiguous"));
+		assert!(Exceptions::parse("OOG = [\"ExitError::OutOfGass\"]").is_err());
+	}
+}
@@ -0,0 +1,191 @@
+//! Execution of raw bytecode outside of any fixture, like `geth evm run`.
+
+use crate::fixture::fork_spec;
+use crate::state::{fork_config, transact, Transaction};
+use crate::structlog::StructLogger;
+use evm::backend::{MemoryAccount, MemoryVicinity};
+use evm::ExitReason;
+use primitive_types::{H160, U256};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// Sender of the call, `sender` in ASCII as with geth.
+pub fn sender() -> H160 {
+	H160::from_low_u64_be(0x73656e646572)
+}
+
+/// Account the code runs at, `receiver` in ASCII as with geth.
+pub fn receiver() -> H160 {
+	H160::from_low_u64_be(0x7265636569766572)
+}
+
+#[derive(Clone, Debug)]
+pub struct Options {
+	/// Code of the receiver, replacing the one of the pre-state if any.
+	pub code: Option<Vec<u8>>,
+	/// Calldata of the call.
+	pub input: Vec<u8>,
+	pub gas: u64,
+	pub value: U256,
+	/// Fork to execute on, named as in fixtures.
+	pub fork: String,
+	/// Record an EIP-3155 trace of the execution.
+	pub trace: bool,
+	/// Accounts to run against, in addition to the sender and receiver.
+	pub pre_state: BTreeMap<H160, MemoryAccount>,
+}
+
+#[derive(Clone, Debug)]
+pub struct Report {
+	pub output: Vec<u8>,
+	/// Gas used by the call, including the intrinsic gas of a transaction.
+	pub used_gas: u64,
+	pub exit_reason: ExitReason,
+	/// EIP-3155 trace, if requested.
+	pub trace: Option<String>,
+	/// State after the call.
+	pub post_state: BTreeMap<H160, MemoryAccount>,
+}
+
+impl Report {
+	/// Fail unless the call returned `expected`.
+	pub fn check_output(&self, expected: &[u8]) -> Result<(), String> {
+		if self.output != expected {
+			return Err(format!(
+				"Output 0x{} differs from the expected 0x{}",
+				hex::encode(&self.output),
+				hex::encode(expected)
+			));
+		}
+		Ok(())
+	}
+}
+
+fn empty_account() -> MemoryAccount {
+	MemoryAccount {
+		balance: U256::zero(),
+		nonce: U256::zero(),
+		code: Vec::new(),
+		storage: BTreeMap::new(),
+	}
+}
+
+/// State the call runs against: the given pre-state, with the code at the
+/// receiver and a sender funded for the value sent.
+fn pre_state(options: &Options) -> BTreeMap<H160, MemoryAccount> {
+	let mut state = options.pre_state.clone();
+	let sender = state.entry(sender()).or_insert_with(empty_account);
+	sender.balance = sender.balance.max(options.value);
+	let receiver = state.entry(receiver()).or_insert_with(empty_account);
+	if let Some(code) = &options.code {
+		receiver.code = code.clone();
+	}
+	state
+}
+
+/// Call the code with the options given, at a gas price of zero.
+pub fn run(options: &Options) -> Result<Report, String> {
+	let spec = fork_spec(&options.fork)
+		.filter(|spec| fork_config(spec).is_some())
+		.ok_or_else(|| format!("Unsupported fork {}", options.fork))?;
+	let vicinity = MemoryVicinity {
+		gas_price: U256::zero(),
+		origin: sender(),
+		block_hashes: Vec::new(),
+		block_number: U256::zero(),
+		block_coinbase: H160::zero(),
+		block_timestamp: U256::zero(),
+		block_difficulty: U256::zero(),
+		block_gas_limit: options.gas.into(),
+		chain_id: U256::one(),
+	};
+	let state = pre_state(options);
+	let transaction = Transaction {
+		caller: sender(),
+		to: Some(receiver()),
+		value: options.value,
+		data: options.input.clone(),
+		gas_limit: options.gas,
+		access_list: Vec::new(),
+		pricing: None,
+	};
+
+	let logger = if options.trace {
+		Some(StructLogger::new())
+	} else {
+		None
+	};
+	let execution = transact(&spec, &vicinity, Cow::Owned(state), &transaction, logger)
+		.ok_or_else(|| format!("Unsupported fork {}", options.fork))?;
+	let trace = execution
+		.tracer
+		.as_ref()
+		.map(|logger| logger.eip3155(&execution.output, execution.used_gas));
+	Ok(Report {
+		output: execution.output,
+		used_gas: execution.used_gas,
+		exit_reason: execution.exit_reason,
+		trace,
+		post_state: execution.backend.state().into_owned(),
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use evm::ExitSucceed;
+
+	#[test]
+	fn returns_calldata() {
+		// CALLDATASIZE PUSH1 0 PUSH1 0 CALLDATACOPY CALLDATASIZE PUSH1 0 RETURN
+		let options = Options {
+			code: Some(hex::decode("366000600037366000f3").unwrap()),
+			input: vec![1, 2, 3],
+			gas: 100_000,
+			value: U256::zero(),
+			fork: "Berlin".to_string(),
+			trace: true,
+			pre_state: BTreeMap::new(),
+		};
+		let report = run(&options).unwrap();
+		assert_eq!(report.output, vec![1, 2, 3]);
+		assert_eq!(report.check_output(&[1, 2, 3]), Ok(()));
+		assert!(report.check_output(&[1, 2]).is_err());
+		assert_eq!(
+			report.exit_reason,
+			ExitReason::Succeed(ExitSucceed::Returned)
+		);
+		assert!(report.used_gas > 21_000);
+		assert!(report.trace.unwrap().lines().count() > 7);
+
+		let options = Options {
+			fork: "Frontier".to_string(),
+			..options
+		};
+		assert!(run(&options).is_err());
+	}
+
+	#[test]
+	fn geth_alloc() {
+		let json = r#"{
+			"0x0000000000000000000000007265636569766572": { "code": "0x6001600055" }
+		}"#;
+		let state = crate::gethalloc::parse(json).unwrap();
+
+		// Code of the alloc runs unless other code is given.
+		let options = Options {
+			code: None,
+			input: Vec::new(),
+			gas: 100_000,
+			value: U256::zero(),
+			fork: "Berlin".to_string(),
+			trace: false,
+			pre_state: state,
+		};
+		let state = pre_state(&options);
+		assert_eq!(state[&receiver()].code, vec![0x60, 0x01, 0x60, 0x00, 0x55]);
+		let report = run(&options).unwrap();
+		assert!(report.exit_reason.is_succeed());
+		assert_eq!(report.post_state[&receiver()].storage.len(), 1);
+	}
+}
@@ -0,0 +1,170 @@
+//! Expansion of the alternatives of a state test transaction, to tell what
+//! an index of a failure like `add:Berlin:4` executed: the data, gas limit
+//! and value each post-state selects, with the labels of the data, and every
+//! combination of them with the post-states checking it.
+
+use crate::fixture::{Indexes, StateFixture};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Bytes of data shown before it is cut off.
+const SHOWN_DATA: usize = 32;
+
+fn data_summary(bytes: &[u8]) -> String {
+	let shown = &bytes[..bytes.len().min(SHOWN_DATA)];
+	let more = if bytes.len() > SHOWN_DATA { "..." } else { "" };
+	format!("{} bytes 0x{}{}", bytes.len(), hex::encode(shown), more)
+}
+
+fn label_suffix(label: Option<&str>) -> String {
+	label
+		.map(|label| format!(" [{}]", label))
+		.unwrap_or_default()
+}
+
+/// Report of the alternatives of `fixture`, the test `name`.
+pub fn explain(name: &str, fixture: &StateFixture) -> String {
+	let transaction = &fixture.transaction;
+	let mut report = String::new();
+	writeln!(
+		report,
+		"{}: {} data, {} gas limits, {} values",
+		name,
+		transaction.data.len(),
+		transaction.gas_limit.len(),
+		transaction.value.len()
+	)
+	.unwrap();
+
+	writeln!(report, "Data:").unwrap();
+	for (i, data) in transaction.data.iter().enumerate() {
+		let indexes = Indexes {
+			data: i,
+			gas: 0,
+			value: 0,
+		};
+		let mut line = format!(
+			"  {}{}: {}",
+			i,
+			label_suffix(fixture.label(&indexes)),
+			data_summary(&data.bytes)
+		);
+		if let Some(Some(access_list)) = transaction.access_lists.get(i) {
+			let keys = access_list
+				.iter()
+				.map(|item| item.storage_keys.len())
+				.sum::<usize>();
+			write!(
+				line,
+				", access list of {} addresses and {} storage keys",
+				access_list.len(),
+				keys
+			)
+			.unwrap();
+		}
+		writeln!(report, "{}", line).unwrap();
+	}
+	writeln!(report, "Gas limits:").unwrap();
+	for (i, gas_limit) in transaction.gas_limit.iter().enumerate() {
+		writeln!(report, "  {}: {}", i, gas_limit).unwrap();
+	}
+	writeln!(report, "Values:").unwrap();
+	for (i, value) in transaction.value.iter().enumerate() {
+		writeln!(report, "  {}: {:#x}", i, value).unwrap();
+	}
+
+	// Post-states checking each combination, as `fork:index`.
+	let mut checked_by = BTreeMap::<(usize, usize, usize), Vec<String>>::new();
+	writeln!(report, "Post-states:").unwrap();
+	for (fork, states) in &fixture.post_states {
+		writeln!(report, "  {}:", fork).unwrap();
+		for (i, state) in states.iter().enumerate() {
+			let indexes = &state.indexes;
+			let mut line = format!(
+				"    {}: data {}{}, gas {}, value {}",
+				i,
+				indexes.data,
+				label_suffix(fixture.label(indexes)),
+				indexes.gas,
+				indexes.value
+			);
+			if let Some(exception) = &state.expect_exception {
+				write!(line, ", expects {}", exception).unwrap();
+			}
+			writeln!(report, "{}", line).unwrap();
+			checked_by
+				.entry((indexes.data, indexes.gas, indexes.value))
+				.or_default()
+				.push(format!("{}:{}", fork, i));
+		}
+	}
+
+	writeln!(report, "Combinations:").unwrap();
+	for data in 0..transaction.data.len() {
+		for gas in 0..transaction.gas_limit.len() {
+			for value in 0..transaction.value.len() {
+				let states = checked_by
+					.get(&(data, gas, value))
+					.map_or_else(|| "not checked".to_string(), |states| states.join(" "));
+				writeln!(
+					report,
+					"  data {}, gas {}, value {}: {}",
+					data, gas, value, states
+				)
+				.unwrap();
+			}
+		}
+	}
+	report
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn expanded() {
+		let fixture = json!({
+			"env": {
+				"currentCoinbase": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba",
+				"currentDifficulty": "0x020000",
+				"currentGasLimit": "0x05f5e100",
+				"currentNumber": "0x01",
+				"currentTimestamp": "0x03e8"
+			},
+			"pre": {},
+			"transaction": {
+				"data": [":label short 0x01", "0x02"],
+				"gasLimit": ["0x0186a0", "0x5208"],
+				"gasPrice": "0x0a",
+				"nonce": "0x00",
+				"secretKey": "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8",
+				"to": "0x0000000000000000000000000000000000001000",
+				"value": ["0x00"]
+			},
+			"post": {
+				"Berlin": [
+					{
+						"hash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+						"indexes": { "data": 1, "gas": 0, "value": 0 }
+					},
+					{
+						"hash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+						"indexes": { "data": 0, "gas": 1, "value": 0 },
+						"expectException": "TR_IntrinsicGas"
+					}
+				]
+			}
+		});
+		let fixture: StateFixture = serde_json::from_value(fixture).unwrap();
+		let report = explain("add", &fixture);
+		assert!(report.starts_with("add: 2 data, 2 gas limits, 1 values\n"));
+		assert!(report.contains("  0 [short]: 1 bytes 0x01\n"));
+		assert!(report.contains("  1: 21000\n"));
+		assert!(report.contains("    1: data 0 [short], gas 1, value 0, expects TR_IntrinsicGas\n"));
+		assert!(report.contains("  data 1, gas 0, value 0: Berlin:0\n"));
+		assert!(report.contains("  data 0, gas 0, value 0: not checked\n"));
+		assert_eq!(data_summary(&[0; 40]).len(), "40 bytes 0x".len() + 64 + 3);
+	}
+}
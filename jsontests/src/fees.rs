@@ -0,0 +1,297 @@
+//! Fees of each transaction type: the fixed gas price of legacy and
+//! EIP-2930 transactions, the fee caps of EIP-1559 and the blob gas of
+//! EIP-4844, with how much of the fee is burnt and how much the coinbase
+//! gets.
+
+use crate::fixture::MultiTransaction;
+use crate::header::{BASE_FEE_FORKS, BLOB_GAS_FORKS, BLOB_GAS_PER_BLOB};
+use primitive_types::U256;
+use std::fmt;
+
+/// Lowest price of blob gas.
+pub const MIN_BLOB_BASE_FEE: u64 = 1;
+/// Controls how fast the blob base fee follows the excess blob gas.
+pub const BLOB_BASE_FEE_UPDATE_FRACTION: u64 = 3_338_477;
+
+/// How a transaction prices its gas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeSchema {
+	Legacy {
+		gas_price: U256,
+	},
+	/// EIP-2930, priced as legacy transactions.
+	AccessList {
+		gas_price: U256,
+	},
+	/// EIP-1559.
+	DynamicFee {
+		max_fee: U256,
+		priority_fee: U256,
+	},
+	/// EIP-4844, with the fee caps of EIP-1559 and a separate one for blob
+	/// gas.
+	Blob {
+		max_fee: U256,
+		priority_fee: U256,
+		max_blob_fee: U256,
+		blobs: usize,
+	},
+}
+
+/// Where the fee of a transaction goes, in wei.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Fees {
+	/// Price paid per unit of gas.
+	pub price: U256,
+	/// Base fee of the gas used and the blob fee, destroyed.
+	pub burnt: U256,
+	/// Paid to the coinbase.
+	pub tip: U256,
+	/// Fee of the blob gas, also part of `burnt`.
+	pub blob: U256,
+}
+
+impl Fees {
+	/// Everything the sender pays: what is burnt and the coinbase tip.
+	pub fn total(&self) -> U256 {
+		self.burnt.saturating_add(self.tip)
+	}
+}
+
+impl fmt::Display for Fees {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(
+			f,
+			"Gas price {}, burnt {}, coinbase tip {}, blob fee {}",
+			self.price, self.burnt, self.tip, self.blob
+		)
+	}
+}
+
+impl FeeSchema {
+	/// Schema of `transaction` by the fields it has.
+	pub fn of(transaction: &MultiTransaction) -> Self {
+		let max_fee = transaction.max_fee_per_gas.unwrap_or_default();
+		let priority_fee = transaction.max_priority_fee_per_gas.unwrap_or_default();
+		match transaction.gas_price {
+			Some(gas_price) if transaction.access_lists.is_empty() => {
+				FeeSchema::Legacy { gas_price }
+			}
+			Some(gas_price) => FeeSchema::AccessList { gas_price },
+			None => match transaction.max_fee_per_blob_gas {
+				Some(max_blob_fee) => FeeSchema::Blob {
+					max_fee,
+					priority_fee,
+					max_blob_fee,
+					blobs: transaction.blob_versioned_hashes.len(),
+				},
+				None => FeeSchema::DynamicFee {
+					max_fee,
+					priority_fee,
+				},
+			},
+		}
+	}
+
+	/// The EIP-1559 fee caps, `None` for transactions paying a fixed price.
+	pub fn caps(&self) -> Option<(U256, U256)> {
+		match *self {
+			FeeSchema::Legacy { .. } | FeeSchema::AccessList { .. } => None,
+			FeeSchema::DynamicFee {
+				max_fee,
+				priority_fee,
+			}
+			| FeeSchema::Blob {
+				max_fee,
+				priority_fee,
+				..
+			} => Some((max_fee, priority_fee)),
+		}
+	}
+
+	/// Price paid per unit of gas under `base_fee`, or the highest one
+	/// without a base fee.
+	pub fn effective_gas_price(&self, base_fee: Option<U256>) -> U256 {
+		match *self {
+			FeeSchema::Legacy { gas_price } | FeeSchema::AccessList { gas_price } => gas_price,
+			FeeSchema::DynamicFee {
+				max_fee,
+				priority_fee,
+			}
+			| FeeSchema::Blob {
+				max_fee,
+				priority_fee,
+				..
+			} => match base_fee {
+				Some(base_fee) => max_fee.min(base_fee.saturating_add(priority_fee)),
+				None => max_fee,
+			},
+		}
+	}
+
+	/// Blob gas of the transaction, none but for blob transactions.
+	pub fn blob_gas(&self) -> u64 {
+		match self {
+			FeeSchema::Blob { blobs, .. } => BLOB_GAS_PER_BLOB * *blobs as u64,
+			_ => 0,
+		}
+	}
+
+	/// Where the fee of `used_gas` goes: before London, with no base fee,
+	/// all of it to the coinbase.
+	pub fn fees(&self, used_gas: u64, base_fee: Option<U256>, blob_base_fee: U256) -> Fees {
+		let price = self.effective_gas_price(base_fee);
+		let burnt_price = base_fee.unwrap_or_default().min(price);
+		let blob = blob_base_fee.saturating_mul(self.blob_gas().into());
+		Fees {
+			price,
+			burnt: burnt_price.saturating_mul(used_gas.into()) + blob,
+			tip: (price - burnt_price).saturating_mul(used_gas.into()),
+			blob,
+		}
+	}
+}
+
+/// How a transaction prices its gas under the base fees of its block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Pricing {
+	pub schema: FeeSchema,
+	/// Base fee of the block, `None` before London.
+	pub base_fee: Option<U256>,
+	pub blob_base_fee: U256,
+}
+
+impl Pricing {
+	/// Pricing at the fixed `gas_price` without a base fee, whose fee all
+	/// goes to the coinbase.
+	pub fn legacy(gas_price: U256) -> Self {
+		Pricing {
+			schema: FeeSchema::Legacy { gas_price },
+			base_fee: None,
+			blob_base_fee: U256::zero(),
+		}
+	}
+
+	/// Where the fee of `used_gas` goes.
+	pub fn fees(&self, used_gas: u64) -> Fees {
+		self.schema
+			.fees(used_gas, self.base_fee, self.blob_base_fee)
+	}
+
+	/// Price paid per unit of gas.
+	pub fn gas_price(&self) -> U256 {
+		self.fees(0).price
+	}
+
+	/// The pricing on `fork`, which burns no base fee before London and
+	/// charges no blob fee before Cancun.
+	pub fn on(self, fork: &str) -> Self {
+		Pricing {
+			base_fee: self.base_fee.filter(|_| BASE_FEE_FORKS.contains(&fork)),
+			blob_base_fee: if BLOB_GAS_FORKS.contains(&fork) {
+				self.blob_base_fee
+			} else {
+				U256::zero()
+			},
+			..self
+		}
+	}
+}
+
+/// Price of blob gas under `excess_blob_gas`, of EIP-4844.
+pub fn blob_base_fee(excess_blob_gas: u64) -> U256 {
+	fake_exponential(
+		MIN_BLOB_BASE_FEE.into(),
+		excess_blob_gas.into(),
+		BLOB_BASE_FEE_UPDATE_FRACTION.into(),
+	)
+}
+
+/// `factor * e ** (numerator / denominator)` in integers, as EIP-4844
+/// approximates it.
+fn fake_exponential(factor: U256, numerator: U256, denominator: U256) -> U256 {
+	let mut output = U256::zero();
+	let mut accumulator = factor * denominator;
+	let mut i = U256::one();
+	while !accumulator.is_zero() {
+		output += accumulator;
+		accumulator = accumulator * numerator / (denominator * i);
+		i += U256::one();
+	}
+	output / denominator
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fees_by_schema() {
+		let base_fee = Some(U256::from(10));
+		let legacy = FeeSchema::Legacy {
+			gas_price: U256::from(12),
+		};
+		assert_eq!(
+			legacy.fees(100, None, U256::zero()),
+			Fees {
+				price: U256::from(12),
+				burnt: U256::zero(),
+				tip: U256::from(1200),
+				blob: U256::zero(),
+			}
+		);
+		assert_eq!(
+			legacy.fees(100, base_fee, U256::zero()).tip,
+			U256::from(200)
+		);
+
+		let dynamic = FeeSchema::DynamicFee {
+			max_fee: U256::from(100),
+			priority_fee: U256::from(5),
+		};
+		assert_eq!(dynamic.effective_gas_price(base_fee), U256::from(15));
+		assert_eq!(dynamic.effective_gas_price(None), U256::from(100));
+		let fees = dynamic.fees(100, base_fee, U256::zero());
+		assert_eq!((fees.burnt, fees.tip), (U256::from(1000), U256::from(500)));
+
+		let blob = FeeSchema::Blob {
+			max_fee: U256::from(12),
+			priority_fee: U256::from(5),
+			max_blob_fee: U256::from(3),
+			blobs: 2,
+		};
+		assert_eq!(blob.blob_gas(), 2 * BLOB_GAS_PER_BLOB);
+		let fees = blob.fees(100, base_fee, U256::from(2));
+		assert_eq!(fees.price, U256::from(12));
+		assert_eq!(fees.blob, U256::from(4 * BLOB_GAS_PER_BLOB));
+		assert_eq!(fees.burnt, U256::from(1000 + 4 * BLOB_GAS_PER_BLOB));
+		assert_eq!(fees.tip, U256::from(200));
+	}
+
+	#[test]
+	fn pricing_by_fork() {
+		let pricing = Pricing {
+			schema: FeeSchema::Legacy {
+				gas_price: U256::from(12),
+			},
+			base_fee: Some(U256::from(10)),
+			blob_base_fee: U256::from(2),
+		};
+		assert_eq!(pricing.on("London").fees(100).tip, U256::from(200));
+		assert_eq!(pricing.on("Berlin").fees(100).tip, U256::from(1200));
+		assert_eq!(pricing.on("Berlin").fees(100).total(), U256::from(1200));
+		assert_eq!(pricing.on("Cancun").blob_base_fee, U256::from(2));
+		assert_eq!(pricing.on("London").blob_base_fee, U256::zero());
+	}
+
+	#[test]
+	fn blob_base_fee_grows_exponentially() {
+		assert_eq!(blob_base_fee(0), U256::one());
+		// e, rounded down.
+		assert_eq!(blob_base_fee(BLOB_BASE_FEE_UPDATE_FRACTION), U256::from(2));
+		assert_eq!(
+			blob_base_fee(10 * BLOB_BASE_FEE_UPDATE_FRACTION),
+			U256::from(22026)
+		);
+	}
+}
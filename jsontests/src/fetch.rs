@@ -0,0 +1,107 @@
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+/// Fixture source a release can be fetched from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Source {
+	/// Source archive of an ethereum/tests tag or commit.
+	EthereumTests,
+	/// `fixtures.tar.gz` asset of an execution-spec-tests release.
+	ExecutionSpecTests,
+}
+
+impl std::str::FromStr for Source {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"ethereum/tests" => Ok(Source::EthereumTests),
+			"ethereum/execution-spec-tests" => Ok(Source::ExecutionSpecTests),
+			_ => Err(format!("Unknown fixture source: {}", s)),
+		}
+	}
+}
+
+impl Source {
+	/// Download URL of the archive for a release tag or commit sha.
+	pub fn url(&self, version: &str) -> String {
+		match self {
+			Source::EthereumTests => format!(
+				"https://github.com/ethereum/tests/archive/{}.tar.gz",
+				version
+			),
+			Source::ExecutionSpecTests => format!(
+				"https://github.com/ethereum/execution-spec-tests/releases/download/{}/fixtures.tar.gz",
+				version
+			),
+		}
+	}
+
+	fn cache_name(&self) -> &'static str {
+		match self {
+			Source::EthereumTests => "ethereum-tests",
+			Source::ExecutionSpecTests => "execution-spec-tests",
+		}
+	}
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+	hex::encode(Sha256::digest(bytes))
+}
+
+fn verify(path: &Path, bytes: &[u8], checksum: Option<&str>) -> io::Result<String> {
+	let actual = sha256_hex(bytes);
+	match checksum {
+		Some(expected) if !expected.eq_ignore_ascii_case(&actual) => Err(io::Error::new(
+			io::ErrorKind::InvalidData,
+			format!(
+				"Checksum mismatch for {}: expected {}, got {}",
+				path.display(),
+				expected,
+				actual
+			),
+		)),
+		_ => Ok(actual),
+	}
+}
+
+fn download(url: &str) -> io::Result<Vec<u8>> {
+	let response = ureq::get(url)
+		.call()
+		.map_err(|err| io::Error::new(io::ErrorKind::Other, err.to_string()))?;
+	let mut bytes = Vec::new();
+	response.into_reader().read_to_end(&mut bytes)?;
+	Ok(bytes)
+}
+
+/// Path of the cached archive of `version`, downloading it first unless
+/// already cached. The archive is checked against the SHA-256 `checksum`
+/// when given, both after download and when reusing the cache; the actual
+/// checksum is returned for pinning.
+pub fn fetch(
+	source: Source,
+	version: &str,
+	cache_dir: &Path,
+	checksum: Option<&str>,
+) -> io::Result<(PathBuf, String)> {
+	let dir = cache_dir.join(source.cache_name());
+	let path = dir.join(format!("{}.tar.gz", version.replace('/', "_")));
+
+	if path.is_file() {
+		let bytes = fs::read(&path)?;
+		let actual = verify(&path, &bytes, checksum)?;
+		return Ok((path, actual));
+	}
+
+	let bytes = download(&source.url(version))?;
+	let actual = verify(&path, &bytes, checksum)?;
+	fs::create_dir_all(&dir)?;
+	// Write under a temporary name so an interrupted download is never
+	// mistaken for a cached archive.
+	let partial = path.with_extension("partial");
+	fs::write(&partial, &bytes)?;
+	fs::rename(&partial, &path)?;
+	Ok((path, actual))
+}
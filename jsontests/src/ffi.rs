@@ -0,0 +1,97 @@
+//! C API of the runner, exported by its cdylib for harnesses of other
+//! languages and fuzzing drivers. Fixtures are passed as buffers and results
+//! come back as JSON strings the caller frees with `evm_jsontests_free`.
+//! Declarations are in `include/evm_jsontests.h`.
+
+use crate::embed::{self, TestResult};
+use crate::{state, vm};
+use serde_json::{json, Value};
+use std::ffi::CString;
+use std::os::raw::c_char;
+use std::slice;
+
+/// JSON array of `results`, or an object with the error of the fixture.
+fn into_c_string(results: Result<Vec<TestResult>, String>) -> *mut c_char {
+	let json = match results {
+		Ok(results) => Value::Array(results.iter().map(TestResult::to_json).collect()),
+		Err(err) => json!({ "error": err }),
+	};
+	// JSON escapes control characters, so the string holds no NUL.
+	CString::new(json.to_string()).unwrap().into_raw()
+}
+
+/// Bytes of the buffer `data` of `len` bytes.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes, or be null with `len` 0.
+unsafe fn buffer<'a>(data: *const u8, len: usize) -> &'a [u8] {
+	if data.is_null() {
+		&[]
+	} else {
+		slice::from_raw_parts(data, len)
+	}
+}
+
+/// Run every test of the state fixture in `data`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn evm_jsontests_run_state(data: *const u8, len: usize) -> *mut c_char {
+	let options = state::Options {
+		quiet: true,
+		..Default::default()
+	};
+	into_c_string(embed::run_state(buffer(data, len), &options))
+}
+
+/// Run every test of the vm fixture in `data`.
+///
+/// # Safety
+///
+/// `data` must point to `len` readable bytes.
+#[no_mangle]
+pub unsafe extern "C" fn evm_jsontests_run_vm(data: *const u8, len: usize) -> *mut c_char {
+	let options = vm::Options {
+		quiet: true,
+		..Default::default()
+	};
+	into_c_string(embed::run_vm(buffer(data, len), &options))
+}
+
+/// Free results returned by the runner.
+///
+/// # Safety
+///
+/// `results` must have been returned by this API and not freed yet, or be
+/// null.
+#[no_mangle]
+pub unsafe extern "C" fn evm_jsontests_free(results: *mut c_char) {
+	if !results.is_null() {
+		drop(CString::from_raw(results));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::ffi::CStr;
+
+	#[test]
+	fn invalid_fixture() {
+		let fixture = b"{ not json";
+		unsafe {
+			let results = evm_jsontests_run_state(fixture.as_ptr(), fixture.len());
+			let json: Value =
+				serde_json::from_str(CStr::from_ptr(results).to_str().unwrap()).unwrap();
+			assert!(json["error"].is_string());
+			evm_jsontests_free(results);
+
+			let results = evm_jsontests_run_vm(std::ptr::null(), 0);
+			assert!(!results.is_null());
+			evm_jsontests_free(results);
+		}
+	}
+}
@@ -0,0 +1,750 @@
+//! Filling of state tests: a filler gives the pre-state, environment and
+//! transaction of a test along with expectations on the post-state, and
+//! filling executes every selected index to record the post-state root and
+//! logs hash of a GeneralStateTests fixture.
+
+use crate::builtins::FORKS;
+use crate::encoding;
+use crate::fixture::{fork_spec, parse_quantity, Indexes, StateFixture};
+use crate::fixtures::json_files;
+use crate::logs::logs_hash;
+use crate::state::{execute, fork_config, Test};
+use crate::utils::u256_to_h256;
+use evm::backend::MemoryAccount;
+use primitive_types::{H160, U256};
+use serde::{Deserialize, Deserializer};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+/// A filler test. `env`, `pre` and `transaction` take the same form as in
+/// fixtures.
+#[derive(Deserialize, Debug, Clone)]
+pub struct Filler {
+	pub env: Value,
+	pub pre: Value,
+	pub transaction: Value,
+	pub expect: Vec<Expectation>,
+}
+
+/// Post-state expected on some forks for some indexes.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct Expectation {
+	#[serde(default)]
+	pub indexes: IndexSelector,
+	/// Forks, as names or ranges like `>=Istanbul` and `<Berlin`.
+	pub network: Vec<String>,
+	/// Accounts to check, with unchecked fields left out.
+	#[serde(default)]
+	pub result: BTreeMap<H160, ExpectedAccount>,
+	/// Gas the transaction uses, usually a range so the test survives
+	/// changes of the gas schedule.
+	#[serde(default, rename = "gasUsed")]
+	pub gas_used: Option<ValueMatch>,
+	/// Exceptions the transaction is rejected with, by forks as in
+	/// `network`, as upstream fillers give them.
+	#[serde(default, rename = "expectException")]
+	pub expect_exception: BTreeMap<String, String>,
+}
+
+impl Expectation {
+	/// Whether the transaction is expected to be rejected on `fork`.
+	fn expects_exception(&self, fork: usize) -> bool {
+		self.expect_exception
+			.keys()
+			.any(|network| forks(&[network.clone()]).map_or(false, |forks| forks.contains(&fork)))
+	}
+}
+
+/// What a post-state value is checked against: a quantity, `ANY`,
+/// `NONZERO`, a bound like `>=0x5208` or `<1000`, or an inclusive range like
+/// `21000..30000`. Fixtures with anything but quantities pin no exact
+/// post-state, so their checks hold across gas schedule changes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueMatch {
+	Exact(U256),
+	Any,
+	Nonzero,
+	/// Inclusive on both ends.
+	Range(U256, U256),
+}
+
+impl ValueMatch {
+	pub fn matches(&self, value: U256) -> bool {
+		match self {
+			ValueMatch::Exact(expected) => value == *expected,
+			ValueMatch::Any => true,
+			ValueMatch::Nonzero => !value.is_zero(),
+			ValueMatch::Range(min, max) => *min <= value && value <= *max,
+		}
+	}
+}
+
+impl FromStr for ValueMatch {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let s = s.trim();
+		let bound = |value: &str| parse_quantity(value.trim());
+		let one = U256::one();
+		Ok(if s == "ANY" {
+			ValueMatch::Any
+		} else if s == "NONZERO" {
+			ValueMatch::Nonzero
+		} else if let Some(min) = s.strip_prefix(">=") {
+			ValueMatch::Range(bound(min)?, U256::max_value())
+		} else if let Some(min) = s.strip_prefix('>') {
+			let min = bound(min)?;
+			if min == U256::max_value() {
+				return Err(format!("{} matches no value", s));
+			}
+			ValueMatch::Range(min + one, U256::max_value())
+		} else if let Some(max) = s.strip_prefix("<=") {
+			ValueMatch::Range(U256::zero(), bound(max)?)
+		} else if let Some(max) = s.strip_prefix('<') {
+			let max = bound(max)?;
+			if max.is_zero() {
+				return Err(format!("{} matches no value", s));
+			}
+			ValueMatch::Range(U256::zero(), max - one)
+		} else if let Some((min, max)) = s.split_once("..") {
+			let (min, max) = (bound(min)?, bound(max)?);
+			if min > max {
+				return Err(format!("{} matches no value", s));
+			}
+			ValueMatch::Range(min, max)
+		} else {
+			ValueMatch::Exact(bound(s)?)
+		})
+	}
+}
+
+impl<'de> Deserialize<'de> for ValueMatch {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let value = String::deserialize(deserializer)?;
+		value.parse().map_err(serde::de::Error::custom)
+	}
+}
+
+impl fmt::Display for ValueMatch {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ValueMatch::Exact(value) => write!(f, "{:#x}", value),
+			ValueMatch::Any => f.write_str("ANY"),
+			ValueMatch::Nonzero => f.write_str("NONZERO"),
+			ValueMatch::Range(min, max) if *max == U256::max_value() => write!(f, ">={:#x}", min),
+			ValueMatch::Range(min, max) => write!(f, "{:#x}..{:#x}", min, max),
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct IndexSelector {
+	#[serde(default)]
+	pub data: Selector,
+	#[serde(default)]
+	pub gas: Selector,
+	#[serde(default)]
+	pub value: Selector,
+}
+
+/// An index, `-1` for any, a list of indexes or, for data, the `:label` of
+/// the alternatives.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Selector {
+	One(i64),
+	Many(Vec<usize>),
+	Label(String),
+}
+
+impl Default for Selector {
+	fn default() -> Self {
+		Selector::One(-1)
+	}
+}
+
+impl Selector {
+	fn matches(&self, index: usize, label: Option<&str>) -> bool {
+		match self {
+			Selector::One(-1) => true,
+			Selector::One(selected) => *selected == index as i64,
+			Selector::Many(selected) => selected.contains(&index),
+			Selector::Label(selected) => {
+				let selected = selected.trim();
+				label == Some(selected.strip_prefix(":label").unwrap_or(selected).trim())
+			}
+		}
+	}
+}
+
+impl IndexSelector {
+	/// Whether the selector selects `indexes`, whose data is labelled
+	/// `label`.
+	fn matches(&self, indexes: &Indexes, label: Option<&str>) -> bool {
+		self.data.matches(indexes.data, label)
+			&& self.gas.matches(indexes.gas, None)
+			&& self.value.matches(indexes.value, None)
+	}
+}
+
+/// `shouldnotexist` as `true` or, as upstream fillers have it, `1`.
+fn deserialize_flag<'de, D: Deserializer<'de>>(deserializer: D) -> Result<bool, D::Error> {
+	match Value::deserialize(deserializer)? {
+		Value::Bool(flag) => Ok(flag),
+		Value::Number(number) => Ok(number.as_u64() != Some(0)),
+		Value::String(flag) => Ok(!matches!(flag.as_str(), "0" | "false")),
+		value => Err(serde::de::Error::custom(format!("invalid flag {}", value))),
+	}
+}
+
+fn deserialize_code<'de, D: Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Option<Vec<u8>>, D::Error> {
+	encoding::deserialize(deserializer).map(Some)
+}
+
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+pub struct ExpectedAccount {
+	#[serde(default)]
+	pub balance: Option<ValueMatch>,
+	#[serde(default)]
+	pub nonce: Option<ValueMatch>,
+	#[serde(default, deserialize_with = "deserialize_code")]
+	pub code: Option<Vec<u8>>,
+	/// Slots to check, the others may hold anything.
+	#[serde(default)]
+	pub storage: BTreeMap<U256, ValueMatch>,
+	#[serde(
+		default,
+		rename = "shouldnotexist",
+		deserialize_with = "deserialize_flag"
+	)]
+	pub should_not_exist: bool,
+}
+
+impl ExpectedAccount {
+	/// Check `account`, the post-state account at `address`.
+	fn check(&self, address: &H160, account: Option<&MemoryAccount>) -> Result<(), String> {
+		let account = match (account, self.should_not_exist) {
+			(None, true) => return Ok(()),
+			(Some(_), true) => return Err(format!("{:?} should not exist", address)),
+			(None, false) => return Err(format!("{:?} does not exist", address)),
+			(Some(account), false) => account,
+		};
+		let mismatch = |field: &str,
+		                expected: &dyn fmt::Display,
+		                got: &dyn fmt::Display|
+		 -> Result<(), String> {
+			Err(format!(
+				"{:?} {}: expected {}, got {}",
+				address, field, expected, got
+			))
+		};
+		if let Some(balance) = self.balance {
+			if !balance.matches(account.balance) {
+				return mismatch("balance", &balance, &format!("{:#x}", account.balance));
+			}
+		}
+		if let Some(nonce) = self.nonce {
+			if !nonce.matches(account.nonce) {
+				return mismatch("nonce", &nonce, &format!("{:#x}", account.nonce));
+			}
+		}
+		if let Some(code) = &self.code {
+			if *code != account.code {
+				return mismatch("code", &hex::encode(code), &hex::encode(&account.code));
+			}
+		}
+		for (key, value) in &self.storage {
+			let got = account
+				.storage
+				.get(&u256_to_h256(*key))
+				.map(|value| U256::from_big_endian(value.as_bytes()))
+				.unwrap_or_default();
+			if !value.matches(got) {
+				return mismatch(
+					&format!("storage {:#x}", key),
+					value,
+					&format!("{:#x}", got),
+				);
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Whether the runner executes `fork`, so that fixtures can be filled for it.
+fn fillable(fork: &str) -> bool {
+	fork_spec(fork).map_or(false, |spec| fork_config(&spec).is_some())
+}
+
+/// Positions in activation order of the forks `network` names.
+fn forks(network: &[String]) -> Result<Vec<usize>, String> {
+	let position = |name: &str| {
+		FORKS
+			.iter()
+			.position(|fork| *fork == name)
+			.ok_or_else(|| format!("unknown fork {}", name))
+	};
+	let mut forks = Vec::new();
+	for entry in network {
+		let entry = entry.trim();
+		let range = if let Some(name) = entry.strip_prefix(">=") {
+			position(name)?..FORKS.len()
+		} else if let Some(name) = entry.strip_prefix('>') {
+			position(name)? + 1..FORKS.len()
+		} else if let Some(name) = entry.strip_prefix("<=") {
+			0..position(name)? + 1
+		} else if let Some(name) = entry.strip_prefix('<') {
+			0..position(name)?
+		} else {
+			let fork = position(entry)?;
+			fork..fork + 1
+		};
+		forks.extend(range);
+	}
+	forks.sort_unstable();
+	forks.dedup();
+	Ok(forks)
+}
+
+/// Forks of `network` to fill: ranges only take in the forks that are
+/// `fillable`, a fork named on its own is taken as is.
+fn filled_network(network: &[String]) -> Result<Vec<usize>, String> {
+	let mut filled = Vec::new();
+	for entry in network {
+		let named = FORKS.contains(&entry.trim());
+		filled.extend(
+			forks(&[entry.clone()])?
+				.into_iter()
+				.filter(|fork| named || fillable(FORKS[*fork])),
+		);
+	}
+	filled.sort_unstable();
+	filled.dedup();
+	Ok(filled)
+}
+
+/// Fixture accounts of `state`.
+pub fn accounts_json(state: &BTreeMap<H160, MemoryAccount>) -> Value {
+	let accounts = state
+		.iter()
+		.map(|(address, account)| {
+			let storage = account
+				.storage
+				.iter()
+				.map(|(key, value)| {
+					let key = U256::from_big_endian(key.as_bytes());
+					let value = U256::from_big_endian(value.as_bytes());
+					(format!("{:#x}", key), json!(format!("{:#x}", value)))
+				})
+				.collect::<Map<_, _>>();
+			let account = json!({
+				"balance": format!("{:#x}", account.balance),
+				"code": format!("0x{}", hex::encode(&account.code)),
+				"nonce": format!("{:#x}", account.nonce),
+				"storage": storage,
+			});
+			(format!("{:?}", address), account)
+		})
+		.collect::<Map<_, _>>();
+	Value::Object(accounts)
+}
+
+/// Forks from `from` on that the runner supports, to generate fillers for.
+pub fn filled_forks(from: &str) -> Vec<&'static str> {
+	let from = FORKS.iter().position(|name| *name == from).unwrap_or(0);
+	FORKS[from..]
+		.iter()
+		.filter(|fork| fillable(fork))
+		.cloned()
+		.collect()
+}
+
+/// What validating a filler checked and left out.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validation {
+	/// Indexes executed and checked against their expectations.
+	pub checked: usize,
+	/// Forks of expectations the runner does not know or support, as named
+	/// in `network`.
+	pub unsupported_forks: BTreeSet<String>,
+	/// Indexes expecting an exception, which are not executed.
+	pub exceptions: usize,
+}
+
+/// Why a filler could not be filled or validated.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FillError {
+	/// The filler uses what the runner does not support, such as LLL code
+	/// or an unknown fork.
+	Unsupported(String),
+	/// An index does not meet an expectation.
+	Failed(String),
+}
+
+impl FillError {
+	pub fn reason(&self) -> &str {
+		match self {
+			FillError::Unsupported(reason) | FillError::Failed(reason) => reason,
+		}
+	}
+}
+
+impl fmt::Display for FillError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.reason())
+	}
+}
+
+/// Forks of `network` when validating: entries naming forks the runner does
+/// not know are recorded in `validation` and left out.
+fn known_forks(network: &[String], validation: &mut Validation) -> Vec<usize> {
+	let mut known = Vec::new();
+	for entry in network {
+		match forks(&[entry.clone()]) {
+			Ok(forks) => known.extend(forks),
+			Err(_) => {
+				validation.unsupported_forks.insert(entry.clone());
+			}
+		}
+	}
+	known.sort_unstable();
+	known.dedup();
+	known
+}
+
+/// State root and logs hash of every index `filler` has expectations for,
+/// by fork, checking each against them. Validating, what the runner cannot
+/// execute is left out and recorded in the validation instead of failing.
+fn execute_expectations(
+	name: &str,
+	filler: &Filler,
+	validating: bool,
+) -> Result<
+	(
+		Test,
+		BTreeMap<H160, MemoryAccount>,
+		Map<String, Value>,
+		Validation,
+	),
+	FillError,
+> {
+	let fixture = json!({
+		"env": filler.env,
+		"pre": filler.pre,
+		"transaction": filler.transaction,
+		"post": {},
+	});
+	let fixture: StateFixture = serde_json::from_value(fixture)
+		.map_err(|err| FillError::Unsupported(format!("{}: {}", name, err)))?;
+	let test = Test(fixture);
+	let setup = test.setup();
+	let vicinity = setup
+		.vicinity
+		.as_ref()
+		.map_err(|err| FillError::Unsupported(format!("{}: transaction has {}", name, err)))?;
+
+	let transaction = &test.0.transaction;
+	let mut combinations = Vec::new();
+	for data in 0..transaction.data.len() {
+		for gas in 0..transaction.gas_limit.len() {
+			for value in 0..transaction.value.len() {
+				combinations.push(Indexes { data, gas, value });
+			}
+		}
+	}
+
+	let mut validation = Validation::default();
+	let networks = if validating {
+		filler
+			.expect
+			.iter()
+			.map(|expectation| known_forks(&expectation.network, &mut validation))
+			.collect()
+	} else {
+		filler
+			.expect
+			.iter()
+			.map(|expectation| filled_network(&expectation.network))
+			.collect::<Result<Vec<_>, _>>()
+			.map_err(|err| FillError::Unsupported(format!("{}: {}", name, err)))?
+	};
+
+	let mut post = Map::new();
+	for (fork, fork_name) in FORKS.iter().enumerate() {
+		let expectations = filler
+			.expect
+			.iter()
+			.zip(&networks)
+			.filter(|(_, forks)| forks.contains(&fork))
+			.map(|(expectation, _)| expectation)
+			.collect::<Vec<_>>();
+		if expectations.is_empty() {
+			continue;
+		}
+		let spec = match fork_spec(fork_name).filter(|spec| fork_config(spec).is_some()) {
+			Some(spec) => spec,
+			None if validating => {
+				validation.unsupported_forks.insert(fork_name.to_string());
+				continue;
+			}
+			None => {
+				return Err(FillError::Unsupported(format!(
+					"{}: fork {} is not supported",
+					name, fork_name
+				)))
+			}
+		};
+
+		let mut states = Vec::new();
+		for indexes in &combinations {
+			let label = transaction.data[indexes.data].label.as_deref();
+			let matching = expectations
+				.iter()
+				.filter(|expectation| expectation.indexes.matches(indexes, label))
+				.collect::<Vec<_>>();
+			if matching.is_empty() {
+				continue;
+			}
+			if matching
+				.iter()
+				.any(|expectation| expectation.expects_exception(fork))
+			{
+				if !validating {
+					return Err(FillError::Unsupported(format!(
+						"{}: expected exceptions are only validated, not filled",
+						name
+					)));
+				}
+				validation.exceptions += 1;
+				continue;
+			}
+			let execution = execute(&test, &spec, indexes, vicinity, &setup.pre_state, ())
+				.ok_or_else(|| {
+					FillError::Unsupported(format!("{}: fork {} is not supported", name, fork_name))
+				})?;
+			let state = execution.backend.state();
+			let failed = |err: String| {
+				FillError::Failed(format!(
+					"{}:{}:{}/{}/{}: {}",
+					name, fork_name, indexes.data, indexes.gas, indexes.value, err
+				))
+			};
+			for expectation in matching {
+				for (address, expected) in &expectation.result {
+					expected
+						.check(address, state.get(address))
+						.map_err(failed)?;
+				}
+				if let Some(gas) = expectation.gas_used {
+					if !gas.matches(execution.used_gas.into()) {
+						return Err(failed(format!(
+							"gas used: expected {}, got {:#x}",
+							gas, execution.used_gas
+						)));
+					}
+				}
+			}
+			validation.checked += 1;
+			states.push(json!({
+				"hash": execution.backend.state_root(),
+				"logs": logs_hash(&execution.logs),
+				"indexes": {
+					"data": indexes.data,
+					"gas": indexes.gas,
+					"value": indexes.value,
+				},
+			}));
+		}
+		post.insert(fork_name.to_string(), Value::Array(states));
+	}
+	let pre_state = setup.pre_state;
+	Ok((test, pre_state, post, validation))
+}
+
+/// Fill `filler`, checking every executed index against its expectations.
+pub fn fill(name: &str, filler: &Filler) -> Result<Value, String> {
+	let (test, pre_state, post, _) =
+		execute_expectations(name, filler, false).map_err(|err| err.to_string())?;
+	let transaction = &test.0.transaction;
+
+	// Data is written out as plain hex, labels move to `_info`.
+	let mut filled_transaction = filler.transaction.clone();
+	filled_transaction["data"] = transaction
+		.data
+		.iter()
+		.map(|data| json!(format!("0x{}", hex::encode(&data.bytes))))
+		.collect();
+	let labels = transaction
+		.data
+		.iter()
+		.enumerate()
+		.filter_map(|(i, data)| Some((i.to_string(), json!(data.label.as_ref()?))))
+		.collect::<Map<_, _>>();
+
+	Ok(json!({
+		"_info": { "labels": labels },
+		"env": filler.env,
+		"pre": accounts_json(&pre_state),
+		"transaction": filled_transaction,
+		"post": post,
+	}))
+}
+
+/// Fill every test of the filler file at `path`.
+pub fn fill_file(path: &Path) -> Result<Value, String> {
+	let json = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+	let fillers: BTreeMap<String, Filler> =
+		serde_json::from_str(&json).map_err(|err| format!("{}: {}", path.display(), err))?;
+	let mut filled = Map::new();
+	for (name, filler) in &fillers {
+		filled.insert(name.clone(), fill(name, filler)?);
+	}
+	Ok(Value::Object(filled))
+}
+
+/// Validate the execution of `filler` against its expectations, without
+/// filling it. Forks the runner does not know and indexes expecting an
+/// exception are left out, so upstream fillers of all forks can be checked.
+pub fn validate(name: &str, filler: &Filler) -> Result<Validation, FillError> {
+	execute_expectations(name, filler, true).map(|(_, _, _, validation)| validation)
+}
+
+/// Validate every test of the filler files at `path`, a file or a directory
+/// like `src/GeneralStateTestsFiller` searched for JSON files. Files that do
+/// not parse as fillers are unsupported.
+pub fn validate_files(path: &Path) -> Vec<(String, Result<Validation, FillError>)> {
+	let mut results = Vec::new();
+	for file in json_files(path) {
+		let fillers = fs::read_to_string(&file)
+			.map_err(|err| err.to_string())
+			.and_then(|json| {
+				serde_json::from_str::<BTreeMap<String, Filler>>(&json)
+					.map_err(|err| err.to_string())
+			});
+		match fillers {
+			Ok(fillers) => {
+				for (name, filler) in &fillers {
+					results.push((name.clone(), validate(name, filler)));
+				}
+			}
+			Err(err) => {
+				let name = file.display().to_string();
+				let err = FillError::Unsupported(format!("{}: {}", name, err));
+				results.push((name, Err(err)));
+			}
+		}
+	}
+	results
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn network_ranges() {
+		let network = |entries: &[&str]| {
+			forks(
+				&entries
+					.iter()
+					.map(|entry| entry.to_string())
+					.collect::<Vec<_>>(),
+			)
+		};
+		assert_eq!(network(&[">=Berlin"]).unwrap(), vec![8, 9]);
+		assert_eq!(network(&["<EIP150", "London"]).unwrap(), vec![0, 1, 9]);
+		assert_eq!(
+			network(&[">Istanbul", "<=Homestead"]).unwrap(),
+			vec![0, 1, 8, 9]
+		);
+		assert!(network(&["Merge"]).is_err());
+	}
+
+	#[test]
+	fn selectors() {
+		let indexes = Indexes {
+			data: 1,
+			gas: 0,
+			value: 2,
+		};
+		assert!(IndexSelector::default().matches(&indexes, None));
+		let selector: IndexSelector =
+			serde_json::from_str(r#"{ "data": [0, 1], "value": 2 }"#).unwrap();
+		assert!(selector.matches(&indexes, None));
+		let selector: IndexSelector = serde_json::from_str(r#"{ "gas": 1 }"#).unwrap();
+		assert!(!selector.matches(&indexes, None));
+		let selector: IndexSelector =
+			serde_json::from_str(r#"{ "data": ":label transfer", "value": -1 }"#).unwrap();
+		assert!(selector.matches(&indexes, Some("transfer")));
+		assert!(!selector.matches(&indexes, Some("call")));
+		assert!(!selector.matches(&indexes, None));
+	}
+
+	#[test]
+	fn value_matches() {
+		let parse = |s: &str| s.parse::<ValueMatch>().unwrap();
+		assert_eq!(parse("0x0a"), ValueMatch::Exact(10.into()));
+		assert_eq!(parse("21000"), ValueMatch::Exact(21000.into()));
+		assert!(parse("ANY").matches(U256::zero()));
+		assert!(!parse("NONZERO").matches(U256::zero()));
+		assert!(parse("NONZERO").matches(U256::max_value()));
+		assert!(parse("21000..30000").matches(21000.into()));
+		assert!(parse("21000..30000").matches(30000.into()));
+		assert!(!parse("21000..30000").matches(30001.into()));
+		assert!(!parse(">21000").matches(21000.into()));
+		assert!(parse("<=0x10").matches(16.into()));
+		assert!(!parse("<0x10").matches(16.into()));
+		assert_eq!(parse("<0x10").to_string(), "0x0..0xf");
+		assert!("<0".parse::<ValueMatch>().is_err());
+		assert!("2..1".parse::<ValueMatch>().is_err());
+		assert!("SOME".parse::<ValueMatch>().is_err());
+	}
+
+	#[test]
+	fn account_expectations() {
+		let address = H160::repeat_byte(1);
+		let mut account = MemoryAccount {
+			balance: 10.into(),
+			nonce: U256::one(),
+			code: vec![0x00],
+			storage: BTreeMap::new(),
+		};
+		account
+			.storage
+			.insert(u256_to_h256(1.into()), u256_to_h256(2.into()));
+
+		let expected: ExpectedAccount =
+			serde_json::from_str(r#"{ "balance": "0x0a", "storage": { "0x01": "0x02" } }"#)
+				.unwrap();
+		assert!(expected.check(&address, Some(&account)).is_ok());
+		assert!(expected.check(&address, None).is_err());
+
+		let expected: ExpectedAccount =
+			serde_json::from_str(r#"{ "storage": { "0x02": "0x01" } }"#).unwrap();
+		assert!(expected.check(&address, Some(&account)).is_err());
+
+		let expected: ExpectedAccount = serde_json::from_str(
+			r#"{ "balance": "ANY", "nonce": "1..2", "storage": { "0x01": "NONZERO", "0x02": "<1" } }"#,
+		)
+		.unwrap();
+		assert!(expected.check(&address, Some(&account)).is_ok());
+		let expected: ExpectedAccount = serde_json::from_str(r#"{ "balance": ">0x0a" }"#).unwrap();
+		assert_eq!(
+			expected.check(&address, Some(&account)),
+			Err(format!("{:?} balance: expected >=0xb, got 0xa", address))
+		);
+
+		let expected: ExpectedAccount =
+			serde_json::from_str(r#"{ "shouldnotexist": true }"#).unwrap();
+		assert!(expected.check(&address, None).is_ok());
+		assert!(expected.check(&address, Some(&account)).is_err());
+	}
+}
@@ -0,0 +1,525 @@
+use crate::encoding;
+use crate::state::Transaction;
+use crate::utils::{deserialize_hex, deserialize_optional_hex, JsonAccount};
+use ethbloom::Bloom;
+use ethjson::spec::ForkSpec;
+use primitive_types::{H160, H256, U256};
+use serde::{Deserialize, Deserializer, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Accounts of a pre-state.
+pub type AccountState = BTreeMap<H160, JsonAccount>;
+
+/// Fork a post-state key names, or `None` if the runner does not know it.
+pub fn fork_spec(name: &str) -> Option<ForkSpec> {
+	serde_json::from_value(serde_json::Value::String(name.to_string())).ok()
+}
+
+/// A data alternative of a transaction. Fixtures may prefix it with
+/// `:label NAME` to name the case, followed by data in any encoding of
+/// `encoding::decode`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Data {
+	pub label: Option<String>,
+	pub bytes: Vec<u8>,
+}
+
+impl Data {
+	pub fn parse(value: &str) -> Result<Self, String> {
+		let mut label = None;
+		let mut rest = value.trim();
+		if let Some(labelled) = rest.strip_prefix(":label") {
+			let labelled = labelled.trim_start();
+			let end = labelled.find(char::is_whitespace).unwrap_or(labelled.len());
+			if end == 0 {
+				return Err(format!("missing label in {}", value));
+			}
+			label = Some(labelled[..end].to_string());
+			rest = labelled[end..].trim_start();
+		}
+		let bytes = encoding::decode(rest)?;
+		Ok(Data { label, bytes })
+	}
+}
+
+impl<'de> Deserialize<'de> for Data {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let value = String::deserialize(deserializer)?;
+		Data::parse(&value).map_err(serde::de::Error::custom)
+	}
+}
+
+/// `to` of a transaction, empty for contract creation.
+fn deserialize_to<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<H160>, D::Error> {
+	let value = String::deserialize(deserializer)?;
+	if value.is_empty() {
+		return Ok(None);
+	}
+	let bytes = hex::decode(value.trim_start_matches("0x")).map_err(serde::de::Error::custom)?;
+	if bytes.len() != 20 {
+		return Err(serde::de::Error::custom(format!(
+			"invalid address {}",
+			value
+		)));
+	}
+	Ok(Some(H160::from_slice(&bytes)))
+}
+
+/// Block environment of a state test.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Env {
+	pub current_coinbase: H160,
+	pub current_difficulty: U256,
+	pub current_gas_limit: U256,
+	pub current_number: U256,
+	pub current_timestamp: U256,
+	/// EIP-1559 base fee, since London.
+	#[serde(default)]
+	pub current_base_fee: Option<U256>,
+	/// `PREVRANDAO` of EIP-4399, replacing the difficulty since the merge.
+	#[serde(default)]
+	pub current_random: Option<H256>,
+	/// EIP-4844 excess blob gas, since Cancun.
+	#[serde(default)]
+	pub current_excess_blob_gas: Option<U256>,
+	#[serde(default)]
+	pub previous_hash: Option<H256>,
+}
+
+/// Replacement of a field of the block environment, given as `key=value`
+/// to probe how a fixture depends on it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EnvOverride {
+	Timestamp(U256),
+	Difficulty(U256),
+	GasLimit(U256),
+	Coinbase(H160),
+	Number(U256),
+}
+
+/// Quantity in decimal, or in hex with a `0x` prefix.
+pub fn parse_quantity(value: &str) -> Result<U256, String> {
+	let parsed = match value.strip_prefix("0x") {
+		Some(hex) => U256::from_str_radix(hex, 16).ok(),
+		None => U256::from_dec_str(value).ok(),
+	};
+	parsed.ok_or_else(|| format!("invalid quantity {}", value))
+}
+
+impl FromStr for EnvOverride {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		let (key, value) = s
+			.split_once('=')
+			.ok_or_else(|| format!("{} is not key=value", s))?;
+		Ok(match key {
+			"timestamp" => EnvOverride::Timestamp(parse_quantity(value)?),
+			"difficulty" => EnvOverride::Difficulty(parse_quantity(value)?),
+			"gas-limit" => EnvOverride::GasLimit(parse_quantity(value)?),
+			"coinbase" => EnvOverride::Coinbase(
+				value
+					.trim_start_matches("0x")
+					.parse()
+					.map_err(|_| format!("invalid address {}", value))?,
+			),
+			"number" => EnvOverride::Number(parse_quantity(value)?),
+			_ => {
+				return Err(format!(
+					"unknown env key {}, expected timestamp, difficulty, gas-limit, coinbase or \
+					 number",
+					key
+				))
+			}
+		})
+	}
+}
+
+impl EnvOverride {
+	pub fn apply(&self, env: &mut Env) {
+		match *self {
+			EnvOverride::Timestamp(timestamp) => env.current_timestamp = timestamp,
+			EnvOverride::Difficulty(difficulty) => env.current_difficulty = difficulty,
+			EnvOverride::GasLimit(gas_limit) => env.current_gas_limit = gas_limit,
+			EnvOverride::Coinbase(coinbase) => env.current_coinbase = coinbase,
+			EnvOverride::Number(number) => env.current_number = number,
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListItem {
+	pub address: H160,
+	pub storage_keys: Vec<H256>,
+}
+
+/// EIP-7702 authorization of a set-code transaction.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Authorization {
+	pub chain_id: U256,
+	pub address: H160,
+	pub nonce: U256,
+	pub v: U256,
+	pub r: U256,
+	pub s: U256,
+	/// Recovered signer, as filled in by the fixture generator.
+	#[serde(default)]
+	pub signer: Option<H160>,
+}
+
+/// Transaction of a state test, with the data, gas limit and value
+/// alternatives post-states select from.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct MultiTransaction {
+	pub data: Vec<Data>,
+	/// Access list of each data alternative, `null` for none.
+	#[serde(default)]
+	pub access_lists: Vec<Option<Vec<AccessListItem>>>,
+	pub gas_limit: Vec<U256>,
+	/// Gas price of legacy and EIP-2930 transactions.
+	#[serde(default)]
+	pub gas_price: Option<U256>,
+	#[serde(default)]
+	pub max_fee_per_gas: Option<U256>,
+	#[serde(default)]
+	pub max_priority_fee_per_gas: Option<U256>,
+	pub nonce: U256,
+	pub secret_key: H256,
+	#[serde(deserialize_with = "deserialize_to")]
+	pub to: Option<H160>,
+	pub value: Vec<U256>,
+	#[serde(default)]
+	pub max_fee_per_blob_gas: Option<U256>,
+	#[serde(default)]
+	pub blob_versioned_hashes: Vec<H256>,
+	#[serde(default)]
+	pub authorization_list: Vec<Authorization>,
+}
+
+impl MultiTransaction {
+	/// Message of the alternative `indexes` selects, sent by `caller`.
+	pub fn message(&self, indexes: &Indexes, caller: H160) -> Transaction {
+		let access_list = self
+			.access_lists
+			.get(indexes.data)
+			.cloned()
+			.flatten()
+			.unwrap_or_default();
+		Transaction {
+			caller,
+			to: self.to,
+			value: self.value[indexes.value],
+			data: self.data[indexes.data].bytes.clone(),
+			gas_limit: self.gas_limit[indexes.gas].low_u64(),
+			access_list: access_list
+				.into_iter()
+				.map(|item| (item.address, item.storage_keys))
+				.collect(),
+			pricing: None,
+		}
+	}
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Indexes {
+	pub data: usize,
+	pub gas: usize,
+	pub value: usize,
+}
+
+/// Expected outcome of one alternative of the transaction on one fork.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct PostState {
+	/// State root after the transaction.
+	pub hash: H256,
+	/// Hash of the RLP-encoded logs emitted by the transaction.
+	#[serde(default)]
+	pub logs: Option<H256>,
+	/// Logs bloom, as it would appear in a block header.
+	#[serde(default)]
+	pub bloom: Option<Bloom>,
+	pub indexes: Indexes,
+	/// Data the transaction returns, in fixtures that check it.
+	#[serde(default, deserialize_with = "deserialize_optional_hex")]
+	pub output: Option<Vec<u8>>,
+	/// Reason the transaction is invalid, if it is.
+	#[serde(default)]
+	pub expect_exception: Option<String>,
+	/// The transaction as signed, in newer fixtures.
+	#[serde(default, deserialize_with = "deserialize_hex")]
+	pub txbytes: Vec<u8>,
+}
+
+/// Where a test was filled from and with what, as the fixture generator
+/// records it in `_info`, to locate the filler of a failing test.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct FillerInfo {
+	/// Path of the filler in the tests repository.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub source: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub source_hash: Option<String>,
+	/// Version of the tool that filled the test, `filledwith` in older
+	/// fixtures.
+	#[serde(
+		default,
+		alias = "filledwith",
+		alias = "filling-tool-version",
+		skip_serializing_if = "Option::is_none"
+	)]
+	pub filled_with: Option<String>,
+	/// Client the generator filled the test with.
+	#[serde(
+		default,
+		alias = "filling-rpc-server",
+		skip_serializing_if = "Option::is_none"
+	)]
+	pub filling_rpc_server: Option<String>,
+	#[serde(
+		default,
+		alias = "lllcversion",
+		skip_serializing_if = "Option::is_none"
+	)]
+	pub lllc_version: Option<String>,
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub solidity: Option<String>,
+}
+
+impl FillerInfo {
+	/// Filler metadata of the test `test` of fixture JSON, whatever its kind.
+	pub fn of_json(test: &serde_json::Value) -> Option<Self> {
+		let info = serde_json::from_value::<FillerInfo>(test.get("_info")?.clone()).ok()?;
+		Some(info).filter(|info| !info.is_empty())
+	}
+
+	pub fn is_empty(&self) -> bool {
+		*self == FillerInfo::default()
+	}
+}
+
+impl fmt::Display for FillerInfo {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let fields = [
+			("filler", &self.source),
+			("source hash", &self.source_hash),
+			("filled with", &self.filled_with),
+			("rpc server", &self.filling_rpc_server),
+			("lllc", &self.lllc_version),
+			("solidity", &self.solidity),
+		];
+		let present = fields
+			.iter()
+			.filter_map(|(name, value)| Some(format!("{} {}", name, value.as_ref()?)))
+			.collect::<Vec<_>>();
+		write!(f, "Filled from: {}", present.join(", "))
+	}
+}
+
+/// Metadata the fixture generator adds to a test.
+#[derive(Deserialize, Debug, Clone, Default)]
+pub struct Info {
+	/// Labels of the data alternatives, by data index.
+	#[serde(default)]
+	pub labels: BTreeMap<usize, String>,
+	#[serde(flatten)]
+	pub filler: FillerInfo,
+}
+
+/// A GeneralStateTests test case.
+#[derive(Deserialize, Debug, Clone)]
+pub struct StateFixture {
+	pub env: Env,
+	#[serde(rename = "pre")]
+	pub pre_state: AccountState,
+	/// Post-states by fork name, including forks the runner does not know.
+	#[serde(rename = "post")]
+	pub post_states: BTreeMap<String, Vec<PostState>>,
+	pub transaction: MultiTransaction,
+	/// Transactions applied in order before `transaction`, selected with the
+	/// same indexes. Only the state they leave behind is checked.
+	#[serde(rename = "preTransactions", default)]
+	pub pre_transactions: Vec<MultiTransaction>,
+	#[serde(rename = "_info", default)]
+	pub info: Info,
+}
+
+impl StateFixture {
+	/// Label of the data alternative `indexes` selects, from the data itself
+	/// or else from `_info`.
+	pub fn label(&self, indexes: &Indexes) -> Option<&str> {
+		self.transaction
+			.data
+			.get(indexes.data)
+			.and_then(|data| data.label.as_deref())
+			.or_else(|| self.info.labels.get(&indexes.data).map(String::as_str))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::fees::FeeSchema;
+
+	const FIXTURE: &str = r#"{
+		"env": {
+			"currentBaseFee": "0x0a",
+			"currentCoinbase": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba",
+			"currentDifficulty": "0x020000",
+			"currentGasLimit": "0xff112233445566",
+			"currentNumber": "0x01",
+			"currentTimestamp": "0x03e8",
+			"currentExcessBlobGas": "0x00"
+		},
+		"pre": {
+			"0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b": {
+				"balance": "0x0de0b6b3a7640000",
+				"code": "0x",
+				"nonce": "0x00",
+				"storage": { "0x01": "0x02" }
+			}
+		},
+		"post": {
+			"Cancun": [
+				{
+					"hash": "0xe8010ce590f401c9d61fef8ab05bea9bcec24281b795e5868809bc4e515aa530",
+					"logs": "0x1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347",
+					"indexes": { "data": 0, "gas": 0, "value": 0 },
+					"output": "0x01",
+					"txbytes": "0x02f8"
+				}
+			]
+		},
+		"transaction": {
+			"data": [":label first :raw 0x00", "0x"],
+			"accessLists": [null, [{ "address": "0x0000000000000000000000000000000000000001", "storageKeys": [] }]],
+			"gasLimit": ["0x0f4240"],
+			"maxFeePerGas": "0x64",
+			"maxPriorityFeePerGas": "0x05",
+			"nonce": "0x00",
+			"secretKey": "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8",
+			"to": "",
+			"value": ["0x00"]
+		}
+	}"#;
+
+	#[test]
+	fn filler_info() {
+		let old: Info = serde_json::from_str(
+			r#"{
+				"comment": "",
+				"filledwith": "cpp-1.3.0+commit.6e0ce939",
+				"lllcversion": "Version: 0.4.26-develop",
+				"source": "src/GeneralStateTestsFiller/stExample/add11Filler.json",
+				"sourceHash": "e474fc13b1ea4c60efe2ba925dd48d6f9c1b12317dcd631f5eeeb3722a790a37",
+				"labels": { "0": "first" }
+			}"#,
+		)
+		.unwrap();
+		assert_eq!(old.labels[&0], "first");
+		assert_eq!(
+			old.filler.filled_with.as_deref(),
+			Some("cpp-1.3.0+commit.6e0ce939")
+		);
+		assert!(old.filler.lllc_version.is_some());
+
+		let new = serde_json::json!({
+			"add11": {
+				"_info": {
+					"filling-rpc-server": "evm version 1.11.4",
+					"filling-tool-version": "retesteth-0.3.0",
+					"solidity": "Version: 0.8.17"
+				}
+			}
+		});
+		let filler = FillerInfo::of_json(&new["add11"]).unwrap();
+		assert_eq!(
+			filler.to_string(),
+			"Filled from: filled with retesteth-0.3.0, rpc server evm version 1.11.4, \
+			 solidity Version: 0.8.17"
+		);
+		assert_eq!(
+			FillerInfo::of_json(&serde_json::json!({ "_info": {} })),
+			None
+		);
+		assert!(Info::default().filler.is_empty());
+	}
+
+	#[test]
+	fn env_overrides() {
+		let mut fixture: StateFixture = serde_json::from_str(FIXTURE).unwrap();
+		let coinbase = H160::repeat_byte(0xcb);
+		let overrides = [
+			"timestamp=2000".parse::<EnvOverride>().unwrap(),
+			"gas-limit=0x10".parse().unwrap(),
+			format!("coinbase={:?}", coinbase).parse().unwrap(),
+		];
+		for env_override in &overrides {
+			env_override.apply(&mut fixture.env);
+		}
+		assert_eq!(fixture.env.current_timestamp, U256::from(2000));
+		assert_eq!(fixture.env.current_gas_limit, U256::from(16));
+		assert_eq!(fixture.env.current_coinbase, coinbase);
+		assert_eq!(fixture.env.current_number, U256::one());
+
+		assert!("number".parse::<EnvOverride>().is_err());
+		assert!("number=0xzz".parse::<EnvOverride>().is_err());
+		assert!("base-fee=7".parse::<EnvOverride>().is_err());
+	}
+
+	#[test]
+	fn modern_fields() {
+		let fixture: StateFixture = serde_json::from_str(FIXTURE).unwrap();
+		assert_eq!(fixture.env.current_base_fee, Some(U256::from(10)));
+		assert!(fork_spec("Cancun").is_none());
+		assert!(fork_spec("Berlin").is_some());
+
+		let transaction = &fixture.transaction;
+		assert_eq!(transaction.to, None);
+		assert_eq!(
+			FeeSchema::of(transaction).effective_gas_price(fixture.env.current_base_fee),
+			U256::from(15)
+		);
+
+		let indexes = |data| Indexes {
+			data,
+			gas: 0,
+			value: 0,
+		};
+		let caller = H160::repeat_byte(1);
+		assert!(transaction
+			.message(&indexes(0), caller)
+			.access_list
+			.is_empty());
+		assert_eq!(
+			transaction.message(&indexes(1), caller).access_list,
+			vec![(H160::from_low_u64_be(1), Vec::new())]
+		);
+		assert_eq!(fixture.post_states["Cancun"][0].txbytes, vec![0x02, 0xf8]);
+		assert_eq!(fixture.post_states["Cancun"][0].output, Some(vec![0x01]));
+		assert_eq!(fixture.label(&indexes(0)), Some("first"));
+		assert_eq!(fixture.label(&indexes(1)), None);
+	}
+
+	#[test]
+	fn labelled_data() {
+		let data = |value| Data::parse(value).unwrap();
+		assert_eq!(data("0x0102").label, None);
+		assert_eq!(data(":label store 0x01").label.as_deref(), Some("store"));
+		assert_eq!(data(":label store :raw 0x0102").bytes, vec![1, 2]);
+		assert_eq!(data(":label  store   0x").bytes, Vec::<u8>::new());
+		assert!(Data::parse(":label").is_err());
+		assert!(Data::parse(":label store 0xzz").is_err());
+		assert_eq!(
+			data(":label call :abi f()").bytes,
+			vec![0x26, 0x12, 0x1f, 0xf0]
+		);
+	}
+}
@@ -0,0 +1,194 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Extension of zstd-compressed fixture files.
+pub const COMPRESSED: &str = ".json.zst";
+
+fn is_hidden(path: &Path) -> bool {
+	path.file_name()
+		.and_then(|name| name.to_str())
+		.map(|name| name.starts_with('.'))
+		.unwrap_or(false)
+}
+
+fn collect(path: &Path, files: &mut Vec<PathBuf>) {
+	if path.is_dir() {
+		let entries = match fs::read_dir(path) {
+			Ok(entries) => entries,
+			Err(_) => return,
+		};
+		for entry in entries.flatten() {
+			let path = entry.path();
+			if !is_hidden(&path) {
+				collect(&path, files);
+			}
+		}
+	} else if is_compressed(path)
+		|| path
+			.extension()
+			.and_then(|ext| ext.to_str())
+			.map_or(false, |ext| ext.eq_ignore_ascii_case("json"))
+	{
+		files.push(path.to_path_buf());
+	}
+}
+
+/// Whether `path` names a zstd-compressed fixture file.
+pub fn is_compressed(path: &Path) -> bool {
+	path.to_string_lossy().ends_with(COMPRESSED)
+}
+
+#[cfg(feature = "zstd-fixtures")]
+fn decompress(file: fs::File) -> io::Result<Vec<u8>> {
+	zstd::stream::decode_all(file)
+}
+
+#[cfg(feature = "zstd-fixtures")]
+fn compress(contents: &[u8], level: i32) -> io::Result<Vec<u8>> {
+	zstd::stream::encode_all(contents, level)
+}
+
+#[cfg(not(feature = "zstd-fixtures"))]
+fn no_zstd() -> io::Error {
+	io::Error::new(
+		io::ErrorKind::Other,
+		"compressed fixtures need the zstd-fixtures feature",
+	)
+}
+
+#[cfg(not(feature = "zstd-fixtures"))]
+fn decompress(_file: fs::File) -> io::Result<Vec<u8>> {
+	Err(no_zstd())
+}
+
+#[cfg(not(feature = "zstd-fixtures"))]
+fn compress(_contents: &[u8], _level: i32) -> io::Result<Vec<u8>> {
+	Err(no_zstd())
+}
+
+/// Contents of the fixture file at `path`, decompressed if it is a
+/// `.json.zst` file.
+pub fn read(path: &Path) -> io::Result<Vec<u8>> {
+	if is_compressed(path) {
+		decompress(fs::File::open(path)?)
+	} else {
+		fs::read(path)
+	}
+}
+
+/// Sizes of the fixtures `compress_tree` compressed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Compressed {
+	pub files: usize,
+	pub bytes: u64,
+	pub compressed_bytes: u64,
+}
+
+/// Compress every JSON fixture file at `src` with zstd at `level` into a
+/// `.json.zst` file at the same path relative to it under `out`.
+pub fn compress_tree(src: &Path, out: &Path, level: i32) -> io::Result<Compressed> {
+	let mut compressed = Compressed::default();
+	for file in json_files(src)
+		.into_iter()
+		.filter(|file| !is_compressed(file))
+	{
+		let relative = file.strip_prefix(src).unwrap_or(&file);
+		let relative = if relative.as_os_str().is_empty() {
+			Path::new(file.file_name().unwrap_or_default())
+		} else {
+			relative
+		};
+		let target = out.join(relative).with_extension(&COMPRESSED[1..]);
+		if let Some(parent) = target.parent() {
+			fs::create_dir_all(parent)?;
+		}
+		let contents = fs::read(&file)?;
+		let zstd = compress(&contents, level)?;
+		fs::write(&target, &zstd)?;
+		compressed.files += 1;
+		compressed.bytes += contents.len() as u64;
+		compressed.compressed_bytes += zstd.len() as u64;
+	}
+	Ok(compressed)
+}
+
+/// Whether `name` is a directory of the ethereum/tests suite taxonomy, such as
+/// `stCreate2`, `vmArithmeticTest` or `bcValidBlockTest`.
+fn is_suite(name: &str) -> bool {
+	["st", "vm", "bc"].iter().any(|prefix| {
+		name.strip_prefix(prefix)
+			.and_then(|rest| rest.chars().next())
+			.map_or(false, |c| c.is_ascii_uppercase() || c.is_ascii_digit())
+	})
+}
+
+/// Suite of the fixture at `path`: the innermost directory it is in that is
+/// named like the suites of ethereum/tests.
+pub fn suite(path: &str) -> Option<String> {
+	let mut components = path.split(|c| c == '/' || c == '\\').collect::<Vec<_>>();
+	components.pop();
+	components
+		.into_iter()
+		.rev()
+		.find(|name| is_suite(name))
+		.map(String::from)
+}
+
+/// All JSON fixture files at `path`, recursing into directories and skipping
+/// hidden entries, sorted by path.
+pub fn json_files(path: &Path) -> Vec<PathBuf> {
+	let mut files = Vec::new();
+	if path.is_file() {
+		files.push(path.to_path_buf());
+	} else {
+		collect(path, &mut files);
+	}
+	files.sort();
+	files
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn suites() {
+		assert_eq!(
+			suite("tests/GeneralStateTests/stCreate2/create2collisionBalance.json"),
+			Some("stCreate2".to_string())
+		);
+		assert_eq!(
+			suite("BlockchainTests/GeneralStateTests/stPreCompiledContracts2/a.json"),
+			Some("stPreCompiledContracts2".to_string())
+		);
+		assert_eq!(
+			suite("VMTests/vmArithmeticTest/add.json"),
+			Some("vmArithmeticTest".to_string())
+		);
+		assert_eq!(suite("fixtures/state/stack.json"), None);
+		assert_eq!(suite("stCreate2.json"), None);
+	}
+
+	#[cfg(feature = "zstd-fixtures")]
+	#[test]
+	fn compressed_fixtures() {
+		let dir = std::env::temp_dir().join(format!("evm-jsontests-zstd-{}", std::process::id()));
+		let (src, out) = (dir.join("src"), dir.join("out"));
+		fs::create_dir_all(src.join("stExample")).unwrap();
+		fs::write(src.join("stExample/add.json"), r#"{ "add": {} }"#).unwrap();
+
+		let compressed = compress_tree(&src, &out, 3).unwrap();
+		assert_eq!(compressed.files, 1);
+		let files = json_files(&out);
+		assert_eq!(files, vec![out.join("stExample/add.json.zst")]);
+		assert!(is_compressed(&files[0]));
+		assert_eq!(read(&files[0]).unwrap(), br#"{ "add": {} }"#);
+		assert_eq!(
+			suite(&files[0].display().to_string()),
+			Some("stExample".to_string())
+		);
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
@@ -0,0 +1,451 @@
+//! Backend over the state of a remote node, read lazily: an account, slot
+//! or block hash is only requested the first time it is read, and kept in a
+//! cache that can be saved to disk so that later runs do not request it
+//! again.
+
+use crate::utils::{deserialize_hex, serialize_hex};
+use evm::backend::{Apply, ApplyBackend, Backend, Basic, Log, MemoryVicinity};
+use primitive_types::{H160, H256, U256};
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+/// Where a forked backend reads the state it does not have yet, all at the
+/// same block.
+pub trait Remote {
+	/// Balance, nonce and code of `address`, or `None` if it is empty.
+	fn account(&self, address: H160) -> Result<Option<RemoteAccount>, String>;
+	fn storage(&self, address: H160, index: H256) -> Result<H256, String>;
+	fn block_hash(&self, number: U256) -> Result<H256, String>;
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct RemoteAccount {
+	pub balance: U256,
+	pub nonce: U256,
+	#[serde(serialize_with = "serialize_hex", deserialize_with = "deserialize_hex")]
+	pub code: Vec<u8>,
+	/// Slots read so far, zero ones included.
+	#[serde(default)]
+	pub storage: BTreeMap<H256, H256>,
+}
+
+/// State read from a remote, as saved on disk.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Cache {
+	/// Accounts read so far, `None` for empty ones.
+	pub accounts: BTreeMap<H160, Option<RemoteAccount>>,
+	pub block_hashes: BTreeMap<U256, H256>,
+}
+
+impl Cache {
+	/// Cache saved at `path`, or an empty one if there is none.
+	pub fn load(path: &Path) -> io::Result<Self> {
+		match fs::read_to_string(path) {
+			Ok(json) => serde_json::from_str(&json).map_err(io::Error::from),
+			Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Cache::default()),
+			Err(err) => Err(err),
+		}
+	}
+
+	pub fn save(&self, path: &Path) -> io::Result<()> {
+		if let Some(dir) = path.parent() {
+			fs::create_dir_all(dir)?;
+		}
+		fs::write(path, serde_json::to_string(self)?)
+	}
+}
+
+/// Account as changed by applied transactions.
+#[derive(Debug, Clone)]
+enum Change {
+	Deleted,
+	Modified {
+		basic: Basic,
+		code: Vec<u8>,
+		/// Slots written, zero ones included.
+		storage: BTreeMap<H256, H256>,
+		/// Whether slots not written are zero rather than remote ones.
+		reset_storage: bool,
+	},
+}
+
+pub struct ForkedBackend<'a, R> {
+	vicinity: &'a MemoryVicinity,
+	remote: R,
+	cache: RefCell<Cache>,
+	changes: BTreeMap<H160, Change>,
+	/// First failure to read from the remote. Reads that fail return empty
+	/// state, so the execution they happen in is to be discarded.
+	error: RefCell<Option<String>>,
+}
+
+impl<'a, R: Remote> ForkedBackend<'a, R> {
+	pub fn new(vicinity: &'a MemoryVicinity, remote: R, cache: Cache) -> Self {
+		ForkedBackend {
+			vicinity,
+			remote,
+			cache: RefCell::new(cache),
+			changes: BTreeMap::new(),
+			error: RefCell::new(None),
+		}
+	}
+
+	/// State read from the remote so far.
+	pub fn cache(&self) -> Cache {
+		self.cache.borrow().clone()
+	}
+
+	/// First failure to read from the remote, if any.
+	pub fn error(&self) -> Option<String> {
+		self.error.borrow().clone()
+	}
+
+	/// Addresses of the accounts applied transactions changed.
+	pub fn changed(&self) -> impl Iterator<Item = &H160> {
+		self.changes.keys()
+	}
+
+	fn fail(&self, err: String) {
+		self.error.borrow_mut().get_or_insert(err);
+	}
+
+	/// Call `f` on the remote account at `address`, reading it if needed.
+	fn with_remote<T>(&self, address: H160, f: impl FnOnce(Option<&RemoteAccount>) -> T) -> T {
+		if !self.cache.borrow().accounts.contains_key(&address) {
+			match self.remote.account(address) {
+				Ok(account) => {
+					self.cache.borrow_mut().accounts.insert(address, account);
+				}
+				Err(err) => {
+					self.fail(format!("Reading {:?} failed: {}", address, err));
+					return f(None);
+				}
+			}
+		}
+		let cache = self.cache.borrow();
+		f(cache.accounts[&address].as_ref())
+	}
+
+	fn remote_storage(&self, address: H160, index: H256) -> H256 {
+		let cached = self.with_remote(address, |account| {
+			account.map(|account| account.storage.get(&index).cloned())
+		});
+		match cached {
+			// Empty accounts have no storage.
+			None => H256::default(),
+			Some(Some(value)) => value,
+			Some(None) => match self.remote.storage(address, index) {
+				Ok(value) => {
+					let mut cache = self.cache.borrow_mut();
+					if let Some(Some(account)) = cache.accounts.get_mut(&address) {
+						account.storage.insert(index, value);
+					}
+					value
+				}
+				Err(err) => {
+					self.fail(format!(
+						"Reading {:?} of {:?} failed: {}",
+						index, address, err
+					));
+					H256::default()
+				}
+			},
+		}
+	}
+}
+
+impl<'a, R: Remote> Backend for ForkedBackend<'a, R> {
+	fn gas_price(&self) -> U256 {
+		self.vicinity.gas_price
+	}
+
+	fn origin(&self) -> H160 {
+		self.vicinity.origin
+	}
+
+	fn block_hash(&self, number: U256) -> H256 {
+		let current = self.vicinity.block_number;
+		if number >= current || current - number > U256::from(256) {
+			return H256::default();
+		}
+		if let Some(hash) = self.cache.borrow().block_hashes.get(&number) {
+			return *hash;
+		}
+		match self.remote.block_hash(number) {
+			Ok(hash) => {
+				self.cache.borrow_mut().block_hashes.insert(number, hash);
+				hash
+			}
+			Err(err) => {
+				self.fail(format!("Reading hash of block {} failed: {}", number, err));
+				H256::default()
+			}
+		}
+	}
+
+	fn block_number(&self) -> U256 {
+		self.vicinity.block_number
+	}
+
+	fn block_coinbase(&self) -> H160 {
+		self.vicinity.block_coinbase
+	}
+
+	fn block_timestamp(&self) -> U256 {
+		self.vicinity.block_timestamp
+	}
+
+	fn block_difficulty(&self) -> U256 {
+		self.vicinity.block_difficulty
+	}
+
+	fn block_gas_limit(&self) -> U256 {
+		self.vicinity.block_gas_limit
+	}
+
+	fn chain_id(&self) -> U256 {
+		self.vicinity.chain_id
+	}
+
+	fn exists(&self, address: H160) -> bool {
+		match self.changes.get(&address) {
+			Some(Change::Deleted) => false,
+			Some(Change::Modified { .. }) => true,
+			None => self.with_remote(address, |account| account.is_some()),
+		}
+	}
+
+	fn basic(&self, address: H160) -> Basic {
+		match self.changes.get(&address) {
+			Some(Change::Deleted) => Basic::default(),
+			Some(Change::Modified { basic, .. }) => basic.clone(),
+			None => self.with_remote(address, |account| {
+				account
+					.map(|account| Basic {
+						balance: account.balance,
+						nonce: account.nonce,
+					})
+					.unwrap_or_default()
+			}),
+		}
+	}
+
+	fn code(&self, address: H160) -> Vec<u8> {
+		match self.changes.get(&address) {
+			Some(Change::Deleted) => Vec::new(),
+			Some(Change::Modified { code, .. }) => code.clone(),
+			None => self.with_remote(address, |account| {
+				account
+					.map(|account| account.code.clone())
+					.unwrap_or_default()
+			}),
+		}
+	}
+
+	fn storage(&self, address: H160, index: H256) -> H256 {
+		match self.changes.get(&address) {
+			Some(Change::Deleted) => H256::default(),
+			Some(Change::Modified {
+				storage,
+				reset_storage,
+				..
+			}) => match storage.get(&index) {
+				Some(value) => *value,
+				None if *reset_storage => H256::default(),
+				None => self.remote_storage(address, index),
+			},
+			None => self.remote_storage(address, index),
+		}
+	}
+
+	fn original_storage(&self, address: H160, index: H256) -> Option<H256> {
+		Some(self.storage(address, index))
+	}
+}
+
+impl<'a, R: Remote> ApplyBackend for ForkedBackend<'a, R> {
+	/// Same semantics as `MemoryBackend::apply`. Logs are not kept, the
+	/// executor returns them.
+	fn apply<A, I, L>(&mut self, values: A, _logs: L, delete_empty: bool)
+	where
+		A: IntoIterator<Item = Apply<I>>,
+		I: IntoIterator<Item = (H256, H256)>,
+		L: IntoIterator<Item = Log>,
+	{
+		for apply in values {
+			match apply {
+				Apply::Modify {
+					address,
+					basic,
+					code,
+					storage,
+					reset_storage,
+				} => {
+					let (mut account_code, mut account_storage, mut account_reset) =
+						match self.changes.remove(&address) {
+							Some(Change::Modified {
+								code,
+								storage,
+								reset_storage,
+								..
+							}) => (code, storage, reset_storage),
+							// A deleted account is recreated without its storage.
+							Some(Change::Deleted) => (Vec::new(), BTreeMap::new(), true),
+							None => (self.code(address), BTreeMap::new(), false),
+						};
+					if let Some(code) = code {
+						account_code = code;
+					}
+					if reset_storage {
+						account_storage.clear();
+						account_reset = true;
+					}
+					account_storage.extend(storage);
+
+					let is_empty = basic.balance == U256::zero()
+						&& basic.nonce == U256::zero()
+						&& account_code.is_empty();
+					let change = if is_empty && delete_empty {
+						Change::Deleted
+					} else {
+						Change::Modified {
+							basic,
+							code: account_code,
+							storage: account_storage,
+							reset_storage: account_reset,
+						}
+					};
+					self.changes.insert(address, change);
+				}
+				Apply::Delete { address } => {
+					self.changes.insert(address, Change::Deleted);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::cell::Cell;
+
+	/// Remote with one account holding slot 1, counting its reads.
+	#[derive(Default)]
+	struct Fake {
+		reads: Cell<usize>,
+	}
+
+	fn address() -> H160 {
+		H160::repeat_byte(0xaa)
+	}
+
+	impl Remote for Fake {
+		fn account(&self, requested: H160) -> Result<Option<RemoteAccount>, String> {
+			self.reads.set(self.reads.get() + 1);
+			if requested != address() {
+				return Ok(None);
+			}
+			Ok(Some(RemoteAccount {
+				balance: 10.into(),
+				nonce: U256::one(),
+				code: vec![0x00],
+				storage: BTreeMap::new(),
+			}))
+		}
+
+		fn storage(&self, _address: H160, index: H256) -> Result<H256, String> {
+			self.reads.set(self.reads.get() + 1);
+			Ok(if index == H256::from_low_u64_be(1) {
+				H256::repeat_byte(2)
+			} else {
+				H256::default()
+			})
+		}
+
+		fn block_hash(&self, _number: U256) -> Result<H256, String> {
+			Err("unavailable".to_string())
+		}
+	}
+
+	fn vicinity() -> MemoryVicinity {
+		MemoryVicinity {
+			gas_price: U256::zero(),
+			origin: H160::zero(),
+			block_hashes: Vec::new(),
+			block_number: 100.into(),
+			block_coinbase: H160::zero(),
+			block_timestamp: U256::zero(),
+			block_difficulty: U256::zero(),
+			block_gas_limit: U256::zero(),
+			chain_id: U256::one(),
+		}
+	}
+
+	#[test]
+	fn reads_are_cached() {
+		let vicinity = vicinity();
+		let backend = ForkedBackend::new(&vicinity, Fake::default(), Cache::default());
+		let slot = H256::from_low_u64_be(1);
+		assert_eq!(backend.basic(address()).balance, 10.into());
+		assert_eq!(backend.storage(address(), slot), H256::repeat_byte(2));
+		assert_eq!(backend.storage(address(), slot), H256::repeat_byte(2));
+		assert!(!backend.exists(H160::zero()));
+		assert_eq!(backend.storage(H160::zero(), slot), H256::default());
+		assert_eq!(backend.remote.reads.get(), 3);
+		assert!(backend.error().is_none());
+
+		// A saved cache answers every read.
+		let cache = backend.cache();
+		let json = serde_json::to_string(&cache).unwrap();
+		let cache: Cache = serde_json::from_str(&json).unwrap();
+		let backend = ForkedBackend::new(&vicinity, Fake::default(), cache);
+		assert_eq!(backend.storage(address(), slot), H256::repeat_byte(2));
+		assert!(!backend.exists(H160::zero()));
+		assert_eq!(backend.remote.reads.get(), 0);
+
+		assert_eq!(backend.block_hash(99.into()), H256::default());
+		assert!(backend.error().is_some());
+	}
+
+	#[test]
+	fn changes_shadow_the_remote() {
+		let vicinity = vicinity();
+		let mut backend = ForkedBackend::new(&vicinity, Fake::default(), Cache::default());
+		let slot = H256::from_low_u64_be(1);
+		let other = H256::from_low_u64_be(2);
+		let modify = |storage: Vec<(H256, H256)>, reset_storage| Apply::Modify {
+			address: address(),
+			basic: Basic {
+				balance: 5.into(),
+				nonce: 2.into(),
+			},
+			code: None,
+			storage,
+			reset_storage,
+		};
+
+		backend.apply(
+			vec![modify(vec![(other, H256::repeat_byte(3))], false)],
+			Vec::new(),
+			true,
+		);
+		assert_eq!(backend.basic(address()).balance, 5.into());
+		assert_eq!(backend.code(address()), vec![0x00]);
+		assert_eq!(backend.storage(address(), slot), H256::repeat_byte(2));
+		assert_eq!(backend.storage(address(), other), H256::repeat_byte(3));
+
+		backend.apply(vec![modify(Vec::new(), true)], Vec::new(), true);
+		assert_eq!(backend.storage(address(), slot), H256::default());
+
+		let delete = Apply::<Vec<_>>::Delete { address: address() };
+		backend.apply(vec![delete], Vec::new(), true);
+		assert!(!backend.exists(address()));
+		assert_eq!(backend.changed().collect::<Vec<_>>(), vec![&address()]);
+	}
+}
@@ -0,0 +1,105 @@
+//! Formats of fixture files, told apart by the fields of their tests, so
+//! that directories mixing them can be run without sorting files by hand.
+
+use serde::de::IgnoredAny;
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Format {
+	State,
+	Vm,
+	Blockchain,
+}
+
+impl Format {
+	pub const ALL: [Format; 3] = [Format::State, Format::Vm, Format::Blockchain];
+
+	/// Name of the format, the same as of the subcommand running it.
+	pub fn name(&self) -> &'static str {
+		match self {
+			Format::State => "state",
+			Format::Vm => "vm",
+			Format::Blockchain => "blockchain",
+		}
+	}
+}
+
+impl fmt::Display for Format {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+impl FromStr for Format {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		Format::ALL
+			.iter()
+			.find(|format| format.name() == s)
+			.copied()
+			.ok_or_else(|| format!("unknown fixture format {}", s))
+	}
+}
+
+/// Fields of a test telling its format apart.
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Probe {
+	exec: Option<IgnoredAny>,
+	blocks: Option<IgnoredAny>,
+	genesis_block_header: Option<IgnoredAny>,
+	transaction: Option<IgnoredAny>,
+	post: Option<IgnoredAny>,
+}
+
+impl Probe {
+	fn format(&self) -> Option<Format> {
+		if self.blocks.is_some() || self.genesis_block_header.is_some() {
+			Some(Format::Blockchain)
+		} else if self.exec.is_some() {
+			Some(Format::Vm)
+		} else if self.transaction.is_some() && self.post.is_some() {
+			Some(Format::State)
+		} else {
+			None
+		}
+	}
+}
+
+/// Format of the fixture file `contents`, if all its tests have the same
+/// known one.
+pub fn detect(contents: &[u8]) -> Option<Format> {
+	let tests = serde_json::from_slice::<BTreeMap<String, Probe>>(contents).ok()?;
+	let mut formats = tests.values().map(Probe::format);
+	let first = formats.next()??;
+	if formats.all(|format| format == Some(first)) {
+		Some(first)
+	} else {
+		None
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn detects_formats() {
+		let state = br#"{ "add": { "env": {}, "pre": {}, "transaction": {}, "post": {} } }"#;
+		let vm = br#"{ "add": { "env": {}, "exec": {}, "pre": {} } }"#;
+		let blockchain = br#"{ "add": { "blocks": [], "genesisBlockHeader": {}, "pre": {} } }"#;
+		assert_eq!(detect(state), Some(Format::State));
+		assert_eq!(detect(vm), Some(Format::Vm));
+		assert_eq!(detect(blockchain), Some(Format::Blockchain));
+
+		let mixed = br#"{ "a": { "exec": {} }, "b": { "blocks": [] } }"#;
+		assert_eq!(detect(mixed), None);
+		assert_eq!(detect(b"{}"), None);
+		assert_eq!(detect(b"[]"), None);
+		assert_eq!("blockchain".parse(), Ok(Format::Blockchain));
+	}
+}
@@ -0,0 +1,213 @@
+use crate::callstack::CallStack;
+use crate::opcodes;
+use crate::trace::Tracer;
+use evm::tracing::Event;
+use evm_gasometer::tracing::Event as GasEvent;
+use evm_runtime::tracing::Event as RuntimeEvent;
+use primitive_types::H160;
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct OpcodeGas {
+	pub count: u64,
+	pub gas: u64,
+}
+
+/// Gas consumed by a transaction, broken down by what consumed it.
+#[derive(Serialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GasBreakdown {
+	pub intrinsic: u64,
+	pub refunded: i64,
+	pub opcodes: BTreeMap<String, OpcodeGas>,
+	pub categories: BTreeMap<String, u64>,
+	pub precompiles: BTreeMap<H160, u64>,
+}
+
+impl GasBreakdown {
+	pub fn report(&self) -> String {
+		let mut out = String::new();
+		writeln!(
+			out,
+			"intrinsic: {}, refunded: {}",
+			self.intrinsic, self.refunded
+		)
+		.unwrap();
+
+		let mut opcodes = self.opcodes.iter().collect::<Vec<_>>();
+		opcodes.sort_by(|a, b| b.1.gas.cmp(&a.1.gas).then(a.0.cmp(b.0)));
+		for (name, gas) in opcodes {
+			writeln!(out, "  {:<16} {:>10} gas {:>8}x", name, gas.gas, gas.count).unwrap();
+		}
+		for (category, gas) in &self.categories {
+			writeln!(out, "  [{}] {}", category, gas).unwrap();
+		}
+		for (address, gas) in &self.precompiles {
+			writeln!(out, "  precompile {:?} {}", address, gas).unwrap();
+		}
+		out
+	}
+}
+
+struct Frame {
+	precompile: Option<H160>,
+	opcode: Option<u8>,
+	memory_gas: u64,
+}
+
+/// Attributes gasometer charges to the opcode (or precompile) that caused
+/// them. Gas forwarded to and returned from sub-calls is not counted against
+/// the calling opcode, so every unit of gas is attributed exactly once.
+pub struct GasTracer {
+	precompiles: BTreeSet<H160>,
+	frames: CallStack<Frame>,
+	breakdown: GasBreakdown,
+}
+
+impl GasTracer {
+	pub fn new<I: IntoIterator<Item = H160>>(precompiles: I) -> Self {
+		GasTracer {
+			precompiles: precompiles.into_iter().collect(),
+			frames: CallStack::new(),
+			breakdown: GasBreakdown::default(),
+		}
+	}
+
+	pub fn into_breakdown(self) -> GasBreakdown {
+		self.breakdown
+	}
+
+	fn enter(&mut self, event: &Event, code_address: Option<H160>) {
+		let precompile = code_address.filter(|address| self.precompiles.contains(address));
+		self.frames.enter(
+			event,
+			Frame {
+				precompile,
+				opcode: None,
+				memory_gas: 0,
+			},
+		);
+	}
+
+	/// Charge `gas` and the memory expansion up to `memory_gas` to the frame
+	/// whose gasometer recorded `event`. The gas a caller forwards to a
+	/// sub-call is no frame's charge.
+	fn charge(&mut self, event: &GasEvent, gas: u64, memory_gas: u64) {
+		let frame = match self.frames.gas_event(event) {
+			Some((frame, _)) => frame,
+			None => return,
+		};
+		let gas = gas + memory_gas.saturating_sub(frame.memory_gas);
+		frame.memory_gas = frame.memory_gas.max(memory_gas);
+
+		if let Some(address) = frame.precompile {
+			*self.breakdown.precompiles.entry(address).or_insert(0) += gas;
+		} else if let Some(opcode) = frame.opcode {
+			self.breakdown
+				.opcodes
+				.entry(opcodes::display_name(opcode))
+				.or_default()
+				.gas += gas;
+			*self
+				.breakdown
+				.categories
+				.entry(opcodes::category(opcode).to_string())
+				.or_insert(0) += gas;
+		}
+	}
+}
+
+impl Tracer for GasTracer {
+	fn evm_event(&mut self, event: Event) {
+		match event {
+			Event::Call { code_address, .. } => self.enter(&event, Some(code_address)),
+			Event::Create { .. } => self.enter(&event, None),
+			Event::Exit { .. } => {
+				self.frames.exit();
+			}
+			_ => (),
+		}
+	}
+
+	fn runtime_event(&mut self, event: RuntimeEvent) {
+		if let RuntimeEvent::Step { opcode, stack, .. } = event {
+			for _ in 0..self.frames.step(stack) {
+				self.frames.exit();
+			}
+			if let Some(frame) = self.frames.last_mut() {
+				frame.opcode = Some(opcode.0);
+			}
+			self.breakdown
+				.opcodes
+				.entry(opcodes::display_name(opcode.0))
+				.or_default()
+				.count += 1;
+		}
+	}
+
+	fn gas_event(&mut self, event: GasEvent) {
+		match event {
+			GasEvent::RecordTransaction { cost, .. } => self.breakdown.intrinsic += cost,
+			GasEvent::RecordRefund { refund, .. } => self.breakdown.refunded += refund,
+			// Returned gas of a finished sub-call.
+			GasEvent::RecordStipend { .. } => (),
+			GasEvent::RecordCost { cost, .. } => self.charge(&event, cost, 0),
+			GasEvent::RecordDynamicCost {
+				gas_cost,
+				memory_gas,
+				gas_refund,
+				..
+			} => {
+				self.breakdown.refunded += gas_refund;
+				self.charge(&event, gas_cost, memory_gas);
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::trace::test_helpers::{account, call};
+
+	#[test]
+	fn breakdown_with_precompile_call() {
+		let contract = H160::from_low_u64_be(0x1000);
+		let callee = H160::from_low_u64_be(0x2000);
+		let identity = H160::from_low_u64_be(4);
+		let mut state = BTreeMap::new();
+		// CALL of the identity precompile, CALL of the callee, then STOP.
+		state.insert(
+			contract,
+			account(concat!(
+				"60006000600060006000600461fffff150",
+				"6000600060006000600061200061fffff150",
+				"00"
+			)),
+		);
+		// PUSH1 2 PUSH1 0 SSTORE STOP.
+		state.insert(callee, account("600260005500"));
+		let execution = call(state, contract, GasTracer::new(vec![identity]));
+		assert!(execution.exit_reason.is_succeed());
+
+		let breakdown = execution.tracer.into_breakdown();
+		assert_eq!(breakdown.intrinsic, 21_000);
+		assert_eq!(breakdown.precompiles[&identity], 15);
+		// A warm and a cold call, without the gas they forward.
+		assert_eq!(
+			breakdown.opcodes["CALL"],
+			OpcodeGas {
+				count: 2,
+				gas: 100 + 2600,
+			}
+		);
+		assert_eq!(breakdown.opcodes["SSTORE"].gas, 22_100);
+		// Steps of the caller and of the callee.
+		assert_eq!(breakdown.opcodes["PUSH1"].count, 11 + 2);
+		let attributed = breakdown.opcodes.values().map(|gas| gas.gas).sum::<u64>()
+			+ breakdown.precompiles.values().sum::<u64>();
+		assert_eq!(breakdown.intrinsic + attributed, execution.used_gas);
+	}
+}
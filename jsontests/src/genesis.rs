@@ -0,0 +1,280 @@
+//! Genesis block of a chain: the state of its `pre` alloc and the header
+//! sealing it, checked against the fixture's `genesisBlockHeader` and
+//! `genesisRLP`. Shared by the blockchain runner and the retesteth server.
+
+use crate::utils::{
+	assert_valid_hash, deserialize_hex, state_root, JsonAccount, StateRootMismatch,
+};
+use evm::backend::MemoryAccount;
+use primitive_types::{H160, H256, U256};
+use rlp::{Rlp, RlpStream};
+use serde::Deserialize;
+use sha3::{Digest, Keccak256};
+use std::collections::BTreeMap;
+use std::fmt;
+
+fn keccak(bytes: &[u8]) -> H256 {
+	H256::from_slice(Keccak256::digest(bytes).as_slice())
+}
+
+/// Header of a genesis block, with the fields later forks added present only
+/// from the fork on.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct GenesisHeader {
+	pub hash: H256,
+	pub parent_hash: H256,
+	pub uncle_hash: H256,
+	pub coinbase: H160,
+	pub state_root: H256,
+	pub transactions_trie: H256,
+	pub receipt_trie: H256,
+	#[serde(deserialize_with = "deserialize_hex")]
+	pub bloom: Vec<u8>,
+	#[serde(default)]
+	pub difficulty: U256,
+	pub number: U256,
+	pub gas_limit: U256,
+	pub gas_used: U256,
+	pub timestamp: U256,
+	#[serde(default, deserialize_with = "deserialize_hex")]
+	pub extra_data: Vec<u8>,
+	#[serde(default)]
+	pub mix_hash: H256,
+	#[serde(default = "zero_nonce", deserialize_with = "deserialize_hex")]
+	pub nonce: Vec<u8>,
+	#[serde(default)]
+	pub base_fee_per_gas: Option<U256>,
+	#[serde(default)]
+	pub withdrawals_root: Option<H256>,
+	#[serde(default)]
+	pub blob_gas_used: Option<U256>,
+	#[serde(default)]
+	pub excess_blob_gas: Option<U256>,
+	#[serde(default)]
+	pub parent_beacon_block_root: Option<H256>,
+	#[serde(default)]
+	pub requests_hash: Option<H256>,
+}
+
+fn zero_nonce() -> Vec<u8> {
+	vec![0; 8]
+}
+
+impl GenesisHeader {
+	/// Header of an empty genesis block at `state_root`, sealed. Fields
+	/// other than the roots are zero.
+	pub fn empty(state_root: H256) -> Self {
+		let mut header = GenesisHeader {
+			hash: H256::zero(),
+			parent_hash: H256::zero(),
+			uncle_hash: keccak(&rlp::EMPTY_LIST_RLP),
+			coinbase: H160::zero(),
+			state_root,
+			transactions_trie: keccak(&rlp::NULL_RLP),
+			receipt_trie: keccak(&rlp::NULL_RLP),
+			bloom: vec![0; 256],
+			difficulty: U256::zero(),
+			number: U256::zero(),
+			gas_limit: U256::zero(),
+			gas_used: U256::zero(),
+			timestamp: U256::zero(),
+			extra_data: Vec::new(),
+			mix_hash: H256::zero(),
+			nonce: zero_nonce(),
+			base_fee_per_gas: None,
+			withdrawals_root: None,
+			blob_gas_used: None,
+			excess_blob_gas: None,
+			parent_beacon_block_root: None,
+			requests_hash: None,
+		};
+		header.seal();
+		header
+	}
+
+	/// RLP encoding of the header.
+	pub fn rlp(&self) -> Vec<u8> {
+		let optional = [
+			self.base_fee_per_gas.is_some(),
+			self.withdrawals_root.is_some(),
+			self.blob_gas_used.is_some(),
+			self.excess_blob_gas.is_some(),
+			self.parent_beacon_block_root.is_some(),
+			self.requests_hash.is_some(),
+		];
+		let mut stream = RlpStream::new_list(15 + optional.iter().filter(|some| **some).count());
+		stream
+			.append(&self.parent_hash)
+			.append(&self.uncle_hash)
+			.append(&self.coinbase)
+			.append(&self.state_root)
+			.append(&self.transactions_trie)
+			.append(&self.receipt_trie)
+			.append(&self.bloom)
+			.append(&self.difficulty)
+			.append(&self.number)
+			.append(&self.gas_limit)
+			.append(&self.gas_used)
+			.append(&self.timestamp)
+			.append(&self.extra_data)
+			.append(&self.mix_hash)
+			.append(&self.nonce);
+		if let Some(base_fee) = &self.base_fee_per_gas {
+			stream.append(base_fee);
+		}
+		if let Some(root) = &self.withdrawals_root {
+			stream.append(root);
+		}
+		if let Some(used) = &self.blob_gas_used {
+			stream.append(used);
+		}
+		if let Some(excess) = &self.excess_blob_gas {
+			stream.append(excess);
+		}
+		if let Some(root) = &self.parent_beacon_block_root {
+			stream.append(root);
+		}
+		if let Some(hash) = &self.requests_hash {
+			stream.append(hash);
+		}
+		stream.out().to_vec()
+	}
+
+	/// Hash of the encoding of the header.
+	pub fn sealed_hash(&self) -> H256 {
+		keccak(&self.rlp())
+	}
+
+	/// Set the hash of the header to the one of its encoding, after changing
+	/// its fields.
+	pub fn seal(&mut self) {
+		self.hash = self.sealed_hash();
+	}
+}
+
+/// Why a genesis block does not match its fixture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GenesisError {
+	/// The state root of the alloc is not the one of the header.
+	StateRoot(StateRootMismatch),
+	/// The header hash is not the hash of its fields.
+	Hash { expected: H256, actual: H256 },
+	/// The header is encoded differently in the fixture's block RLP.
+	Rlp { expected: Vec<u8>, actual: Vec<u8> },
+	/// The fixture's block RLP does not decode.
+	Decode(rlp::DecoderError),
+}
+
+impl fmt::Display for GenesisError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			GenesisError::StateRoot(mismatch) => write!(f, "Genesis state root: {}", mismatch),
+			GenesisError::Hash { expected, actual } => write!(
+				f,
+				"Genesis hash not equal; calculated: {:?}, expect: {:?}",
+				actual, expected
+			),
+			GenesisError::Rlp { expected, actual } => write!(
+				f,
+				"Genesis header RLP not equal; calculated: 0x{}, expect: 0x{}",
+				hex::encode(actual),
+				hex::encode(expected)
+			),
+			GenesisError::Decode(err) => write!(f, "Genesis RLP does not decode: {}", err),
+		}
+	}
+}
+
+impl std::error::Error for GenesisError {}
+
+fn alloc(pre: BTreeMap<H160, JsonAccount>) -> BTreeMap<H160, MemoryAccount> {
+	pre.into_iter()
+		.map(|(address, account)| (address, account.into()))
+		.collect()
+}
+
+/// Genesis block and the state after it.
+#[derive(Debug, Clone)]
+pub struct Genesis {
+	pub header: GenesisHeader,
+	pub state: BTreeMap<H160, MemoryAccount>,
+}
+
+impl Genesis {
+	/// Genesis of `header` with the accounts of `pre`, unchecked.
+	pub fn new(header: GenesisHeader, pre: BTreeMap<H160, JsonAccount>) -> Self {
+		Genesis {
+			header,
+			state: alloc(pre),
+		}
+	}
+
+	/// Genesis with the accounts of `pre` and the header `fields` returns
+	/// from an empty one at their state root, sealed.
+	pub fn of_alloc(
+		pre: BTreeMap<H160, JsonAccount>,
+		fields: impl FnOnce(GenesisHeader) -> GenesisHeader,
+	) -> Self {
+		let state = alloc(pre);
+		let mut header = fields(GenesisHeader::empty(state_root(&state)));
+		header.seal();
+		Genesis { header, state }
+	}
+
+	/// Check that the state has the root of the header, that the header has
+	/// its hash and, if given, that it is the header `rlp` encodes the block
+	/// of.
+	pub fn verify(&self, rlp: Option<&[u8]>) -> Result<(), GenesisError> {
+		assert_valid_hash(&self.header.state_root, &self.state).map_err(GenesisError::StateRoot)?;
+		let actual = self.header.rlp();
+		if let Some(rlp) = rlp {
+			let block = Rlp::new(rlp);
+			let expected = block.at(0).map_err(GenesisError::Decode)?.as_raw().to_vec();
+			if expected != actual {
+				return Err(GenesisError::Rlp { expected, actual });
+			}
+		}
+		let actual = keccak(&actual);
+		if actual != self.header.hash {
+			return Err(GenesisError::Hash {
+				expected: self.header.hash,
+				actual,
+			});
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn empty_genesis_verifies() {
+		let genesis = Genesis::of_alloc(BTreeMap::new(), |header| GenesisHeader {
+			gas_limit: U256::from(0x2fefd8),
+			difficulty: U256::from(0x020000),
+			..header
+		});
+		assert_eq!(genesis.header.state_root, keccak(&rlp::NULL_RLP));
+		let mut block = RlpStream::new_list(3);
+		block
+			.append_raw(&genesis.header.rlp(), 1)
+			.begin_list(0)
+			.begin_list(0);
+		let block = block.out().to_vec();
+		assert_eq!(genesis.verify(Some(&block)), Ok(()));
+
+		let mut tampered = genesis.clone();
+		tampered.header.timestamp = U256::one();
+		assert!(matches!(
+			tampered.verify(Some(&block)),
+			Err(GenesisError::Rlp { .. })
+		));
+		assert!(matches!(
+			tampered.verify(None),
+			Err(GenesisError::Hash { .. })
+		));
+	}
+}
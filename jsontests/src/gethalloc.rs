@@ -0,0 +1,178 @@
+//! State in the `alloc` format of geth genesis files, which `geth evm`, t8n
+//! tools and most external tooling read and write, converted to and from the
+//! accounts the runner executes on. Quantities are read in hex or decimal
+//! and written in hex, as geth does.
+
+use crate::utils::{serialize_hex, JsonAccount};
+use evm::backend::MemoryAccount;
+use primitive_types::{H160, H256, U256};
+use serde::de::{self, Deserializer};
+use serde::ser::{SerializeMap, Serializer};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// Accounts of an alloc, by address.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Alloc(pub BTreeMap<H160, MemoryAccount>);
+
+/// Account as geth writes it, leaving out what is empty.
+#[derive(Serialize)]
+struct GethAccount {
+	#[serde(
+		skip_serializing_if = "Vec::is_empty",
+		serialize_with = "serialize_hex"
+	)]
+	code: Vec<u8>,
+	#[serde(skip_serializing_if = "BTreeMap::is_empty")]
+	storage: BTreeMap<H256, H256>,
+	balance: U256,
+	#[serde(skip_serializing_if = "U256::is_zero")]
+	nonce: U256,
+}
+
+impl Serialize for Alloc {
+	fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+		let mut map = serializer.serialize_map(Some(self.0.len()))?;
+		for (address, account) in &self.0 {
+			map.serialize_entry(
+				address,
+				&GethAccount {
+					code: account.code.clone(),
+					storage: account.storage.clone(),
+					balance: account.balance,
+					nonce: account.nonce,
+				},
+			)?;
+		}
+		map.end()
+	}
+}
+
+/// Quantities of geth allocs may be decimal, fixtures only take hex.
+fn hex_quantity(value: &mut Value) -> Result<(), String> {
+	if let Value::String(quantity) = value {
+		if !quantity.starts_with("0x") {
+			let decimal = U256::from_dec_str(quantity)
+				.map_err(|_| format!("invalid quantity {}", quantity))?;
+			*quantity = format!("{:#x}", decimal);
+		}
+	}
+	Ok(())
+}
+
+fn accounts(value: Value) -> Result<BTreeMap<H160, MemoryAccount>, String> {
+	let accounts = match value {
+		Value::Object(accounts) => accounts,
+		_ => return Err("alloc is not an object".to_string()),
+	};
+	let mut state = BTreeMap::new();
+	for (address, mut account) in accounts {
+		let bytes = hex::decode(address.trim_start_matches("0x"))
+			.ok()
+			.filter(|bytes| bytes.len() == 20)
+			.ok_or_else(|| format!("invalid address {}", address))?;
+		for field in &["balance", "nonce"] {
+			if let Some(quantity) = account.get_mut(*field) {
+				hex_quantity(quantity).map_err(|err| format!("{}: {}", address, err))?;
+			}
+		}
+		let account: JsonAccount =
+			serde_json::from_value(account).map_err(|err| format!("{}: {}", address, err))?;
+		state.insert(H160::from_slice(&bytes), account.into());
+	}
+	Ok(state)
+}
+
+impl<'de> Deserialize<'de> for Alloc {
+	fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+		let value = Value::deserialize(deserializer)?;
+		accounts(value).map(Alloc).map_err(de::Error::custom)
+	}
+}
+
+impl From<BTreeMap<H160, MemoryAccount>> for Alloc {
+	fn from(state: BTreeMap<H160, MemoryAccount>) -> Self {
+		Alloc(state)
+	}
+}
+
+impl From<Alloc> for BTreeMap<H160, MemoryAccount> {
+	fn from(alloc: Alloc) -> Self {
+		alloc.0
+	}
+}
+
+/// Accounts of a geth genesis file, or of just its `alloc` section.
+pub fn parse(json: &str) -> Result<BTreeMap<H160, MemoryAccount>, String> {
+	let mut value: Value = serde_json::from_str(json).map_err(|err| err.to_string())?;
+	if let Some(alloc) = value.get_mut("alloc") {
+		value = alloc.take();
+	}
+	accounts(value)
+}
+
+/// Read the alloc at `path`, see `parse`.
+pub fn read(path: &Path) -> Result<BTreeMap<H160, MemoryAccount>, String> {
+	let json = fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+	parse(&json).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+/// Write `state` to `path` as an alloc.
+pub fn write(path: &Path, state: &BTreeMap<H160, MemoryAccount>) -> Result<(), String> {
+	let json = serde_json::to_string_pretty(&Alloc(state.clone())).unwrap();
+	fs::write(path, json).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde_json::json;
+
+	#[test]
+	fn geth_alloc() {
+		let json = r#"{
+			"config": { "chainId": 1 },
+			"alloc": {
+				"0000000000000000000000007265636569766572": {
+					"balance": "1000000000000000000",
+					"code": "0x6001600055",
+					"storage": { "0x01": "0x02" }
+				},
+				"0x000000000000000000000000000073656e646572": { "balance": "0x10", "nonce": "0x01" }
+			}
+		}"#;
+		let state = parse(json).unwrap();
+		let receiver = H160::from_low_u64_be(0x7265636569766572);
+		let sender = H160::from_low_u64_be(0x73656e646572);
+		assert_eq!(state[&receiver].balance, U256::exp10(18));
+		assert_eq!(state[&receiver].storage.len(), 1);
+		assert_eq!(state[&sender].nonce, U256::one());
+
+		assert!(parse(r#"{ "0x01": {} }"#).is_err());
+		assert!(parse(r#"{ "alloc": [] }"#).is_err());
+		assert!(
+			parse(r#"{ "0x0000000000000000000000000000000000000001": { "nonce": "x" } }"#).is_err()
+		);
+	}
+
+	#[test]
+	fn round_trip() {
+		let json = json!({
+			"0x000000000000000000000000000073656e646572": { "balance": "0x10", "nonce": "0x1" },
+			"0x0000000000000000000000007265636569766572": {
+				"code": "0x6001600055",
+				"storage": {
+					"0x0000000000000000000000000000000000000000000000000000000000000001":
+						"0x0000000000000000000000000000000000000000000000000000000000000002"
+				},
+				"balance": "0x0"
+			}
+		});
+		let alloc: Alloc = serde_json::from_value(json.clone()).unwrap();
+		assert_eq!(alloc.0.len(), 2);
+		assert_eq!(serde_json::to_value(&alloc).unwrap(), json);
+	}
+}
@@ -0,0 +1,367 @@
+//! Rules a block header must follow against its parent under each fork: the
+//! number, timestamp, extra data and gas limit bounds of every fork, the
+//! EIP-1559 base fee from London and the EIP-4844 blob gas from Cancun.
+
+use primitive_types::U256;
+use std::fmt;
+
+/// Forks with an EIP-1559 base fee in their headers.
+pub const BASE_FEE_FORKS: &[&str] = &[
+	"London",
+	"ArrowGlacier",
+	"GrayGlacier",
+	"Paris",
+	"Merge",
+	"Shanghai",
+	"Cancun",
+	"Prague",
+];
+/// Forks with EIP-4844 blob gas fields in their headers.
+pub const BLOB_GAS_FORKS: &[&str] = &["Cancun", "Prague"];
+
+/// Longest extra data of a header.
+pub const MAX_EXTRA_DATA: usize = 32;
+/// Lowest gas limit of a block.
+pub const MIN_GAS_LIMIT: u64 = 5000;
+/// A gas limit changes by less than this fraction of the parent's.
+pub const GAS_LIMIT_BOUND_DIVISOR: u64 = 1024;
+/// Ratio of the gas limit to the gas target from London.
+pub const ELASTICITY_MULTIPLIER: u64 = 2;
+/// The base fee changes by at most this fraction of the parent's.
+pub const BASE_FEE_MAX_CHANGE_DENOMINATOR: u64 = 8;
+/// Base fee of the first London block.
+pub const INITIAL_BASE_FEE: u64 = 1_000_000_000;
+/// Blob gas of a single blob.
+pub const BLOB_GAS_PER_BLOB: u64 = 1 << 17;
+/// Blob gas a block targets, three blobs.
+pub const TARGET_BLOB_GAS_PER_BLOCK: u64 = 3 * BLOB_GAS_PER_BLOB;
+/// Most blob gas of a block, six blobs.
+pub const MAX_BLOB_GAS_PER_BLOCK: u64 = 6 * BLOB_GAS_PER_BLOB;
+/// Extra data of the first blocks of chains taking the DAO fork.
+pub const DAO_EXTRA_DATA: &[u8] = b"dao-hard-fork";
+/// Blocks from the DAO fork on with its extra data.
+pub const DAO_EXTRA_DATA_BLOCKS: u64 = 10;
+
+/// Header fields the rules look at. Fields of later forks are `None` in
+/// headers of earlier ones.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Fields {
+	pub number: U256,
+	pub timestamp: U256,
+	pub gas_limit: U256,
+	pub gas_used: U256,
+	pub extra_data: Vec<u8>,
+	pub base_fee: Option<U256>,
+	pub blob_gas_used: Option<u64>,
+	pub excess_blob_gas: Option<u64>,
+}
+
+/// Why a header is invalid.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Rejection {
+	InvalidNumber,
+	TimestampEqualParent,
+	TimestampOlderParent,
+	ExtraDataTooLong,
+	InvalidGasLimit,
+	GasUsedAboveLimit,
+	MissingBaseFee,
+	UnexpectedBaseFee,
+	InvalidBaseFee { expected: U256, actual: U256 },
+	MissingBlobGas,
+	UnexpectedBlobGas,
+	BlobGasAboveLimit,
+	BlobGasNotMultiple,
+	InvalidExcessBlobGas { expected: u64, actual: u64 },
+	InvalidDaoExtraData,
+}
+
+impl Rejection {
+	/// Reason of the rejection in exception tables.
+	pub fn reason(&self) -> &'static str {
+		match self {
+			Rejection::InvalidNumber => "Block::InvalidNumber",
+			Rejection::TimestampEqualParent => "Block::TimestampEqualParent",
+			Rejection::TimestampOlderParent => "Block::TimestampOlderParent",
+			Rejection::ExtraDataTooLong => "Block::ExtraDataTooLong",
+			Rejection::InvalidGasLimit => "Block::InvalidGasLimit",
+			Rejection::GasUsedAboveLimit => "Block::GasUsedAboveLimit",
+			Rejection::MissingBaseFee => "Block::MissingBaseFee",
+			Rejection::UnexpectedBaseFee => "Block::UnexpectedBaseFee",
+			Rejection::InvalidBaseFee { .. } => "Block::InvalidBaseFee",
+			Rejection::MissingBlobGas => "Block::MissingBlobGas",
+			Rejection::UnexpectedBlobGas => "Block::UnexpectedBlobGas",
+			Rejection::BlobGasAboveLimit => "Block::BlobGasAboveLimit",
+			Rejection::BlobGasNotMultiple => "Block::BlobGasNotMultiple",
+			Rejection::InvalidExcessBlobGas { .. } => "Block::InvalidExcessBlobGas",
+			Rejection::InvalidDaoExtraData => "Block::InvalidDaoExtraData",
+		}
+	}
+}
+
+impl fmt::Display for Rejection {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Rejection::InvalidNumber => write!(f, "invalid block number"),
+			Rejection::TimestampEqualParent => write!(f, "timestamp equal to parent"),
+			Rejection::TimestampOlderParent => write!(f, "timestamp before parent"),
+			Rejection::ExtraDataTooLong => write!(f, "extra data too long"),
+			Rejection::InvalidGasLimit => write!(f, "invalid gas limit"),
+			Rejection::GasUsedAboveLimit => write!(f, "gas used above gas limit"),
+			Rejection::MissingBaseFee => write!(f, "base fee missing"),
+			Rejection::UnexpectedBaseFee => write!(f, "base fee before London"),
+			Rejection::InvalidBaseFee { expected, actual } => {
+				write!(f, "base fee {}, expected {}", actual, expected)
+			}
+			Rejection::MissingBlobGas => write!(f, "blob gas fields missing"),
+			Rejection::UnexpectedBlobGas => write!(f, "blob gas fields before Cancun"),
+			Rejection::BlobGasAboveLimit => {
+				write!(f, "blob gas used above limit {}", MAX_BLOB_GAS_PER_BLOCK)
+			}
+			Rejection::BlobGasNotMultiple => {
+				write!(f, "blob gas used not a multiple of {}", BLOB_GAS_PER_BLOB)
+			}
+			Rejection::InvalidExcessBlobGas { expected, actual } => {
+				write!(f, "excess blob gas {}, expected {}", actual, expected)
+			}
+			Rejection::InvalidDaoExtraData => write!(f, "extra data not the DAO fork's"),
+		}
+	}
+}
+
+/// Whether a block may have `gas_limit` after a parent with
+/// `parent_gas_limit`.
+pub fn gas_limit_valid(gas_limit: U256, parent_gas_limit: U256) -> bool {
+	let bound = parent_gas_limit / GAS_LIMIT_BOUND_DIVISOR;
+	gas_limit < parent_gas_limit + bound
+		&& gas_limit + bound > parent_gas_limit
+		&& gas_limit >= U256::from(MIN_GAS_LIMIT)
+}
+
+/// Base fee of the child of `parent`, the initial one if `parent` is
+/// before London.
+pub fn base_fee(parent: &Fields) -> U256 {
+	let parent_base_fee = match parent.base_fee {
+		Some(base_fee) => base_fee,
+		None => return U256::from(INITIAL_BASE_FEE),
+	};
+	let target = parent.gas_limit / ELASTICITY_MULTIPLIER;
+	if target.is_zero() || parent.gas_used == target {
+		return parent_base_fee;
+	}
+	if parent.gas_used > target {
+		let delta =
+			parent_base_fee * (parent.gas_used - target) / target / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+		parent_base_fee + delta.max(U256::one())
+	} else {
+		let delta =
+			parent_base_fee * (target - parent.gas_used) / target / BASE_FEE_MAX_CHANGE_DENOMINATOR;
+		parent_base_fee.saturating_sub(delta)
+	}
+}
+
+/// Excess blob gas of the child of `parent`, zero if `parent` is before
+/// Cancun.
+pub fn excess_blob_gas(parent: &Fields) -> u64 {
+	let excess = parent.excess_blob_gas.unwrap_or(0) + parent.blob_gas_used.unwrap_or(0);
+	excess.saturating_sub(TARGET_BLOB_GAS_PER_BLOCK)
+}
+
+/// Check `header` of a block under `fork` against its `parent`.
+pub fn check(fork: &str, header: &Fields, parent: &Fields) -> Result<(), Rejection> {
+	if header.number != parent.number + 1 {
+		return Err(Rejection::InvalidNumber);
+	}
+	if header.timestamp == parent.timestamp {
+		return Err(Rejection::TimestampEqualParent);
+	}
+	if header.timestamp < parent.timestamp {
+		return Err(Rejection::TimestampOlderParent);
+	}
+	if header.extra_data.len() > MAX_EXTRA_DATA {
+		return Err(Rejection::ExtraDataTooLong);
+	}
+
+	let london = BASE_FEE_FORKS.contains(&fork);
+	// The first London block doubles the gas limit, keeping the target.
+	let parent_gas_limit = if london && parent.base_fee.is_none() {
+		parent.gas_limit * ELASTICITY_MULTIPLIER
+	} else {
+		parent.gas_limit
+	};
+	if !gas_limit_valid(header.gas_limit, parent_gas_limit) {
+		return Err(Rejection::InvalidGasLimit);
+	}
+	if header.gas_used > header.gas_limit {
+		return Err(Rejection::GasUsedAboveLimit);
+	}
+
+	match (london, header.base_fee) {
+		(true, None) => return Err(Rejection::MissingBaseFee),
+		(false, Some(_)) => return Err(Rejection::UnexpectedBaseFee),
+		(true, Some(actual)) => {
+			let expected = base_fee(parent);
+			if actual != expected {
+				return Err(Rejection::InvalidBaseFee { expected, actual });
+			}
+		}
+		(false, None) => {}
+	}
+
+	let cancun = BLOB_GAS_FORKS.contains(&fork);
+	match (cancun, header.blob_gas_used, header.excess_blob_gas) {
+		(true, Some(used), Some(actual)) => {
+			if used > MAX_BLOB_GAS_PER_BLOCK {
+				return Err(Rejection::BlobGasAboveLimit);
+			}
+			if used % BLOB_GAS_PER_BLOB != 0 {
+				return Err(Rejection::BlobGasNotMultiple);
+			}
+			let expected = excess_blob_gas(parent);
+			if actual != expected {
+				return Err(Rejection::InvalidExcessBlobGas { expected, actual });
+			}
+		}
+		(true, _, _) => return Err(Rejection::MissingBlobGas),
+		(false, None, None) => {}
+		(false, _, _) => return Err(Rejection::UnexpectedBlobGas),
+	}
+	Ok(())
+}
+
+/// Check `header` of a chain taking the DAO fork at `dao_block` carries the
+/// fork's extra data if it is one of the first blocks of the fork.
+pub fn check_dao(dao_block: u64, header: &Fields) -> Result<(), Rejection> {
+	let first = U256::from(dao_block);
+	if header.number >= first
+		&& header.number < first + DAO_EXTRA_DATA_BLOCKS
+		&& header.extra_data != DAO_EXTRA_DATA
+	{
+		return Err(Rejection::InvalidDaoExtraData);
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn parent(gas_limit: u64, gas_used: u64, base_fee: Option<u64>) -> Fields {
+		Fields {
+			number: U256::one(),
+			timestamp: U256::from(10),
+			gas_limit: U256::from(gas_limit),
+			gas_used: U256::from(gas_used),
+			base_fee: base_fee.map(U256::from),
+			..Default::default()
+		}
+	}
+
+	fn child(parent: &Fields, gas_limit: u64, base_fee: Option<U256>) -> Fields {
+		Fields {
+			number: parent.number + 1,
+			timestamp: parent.timestamp + 12,
+			gas_limit: U256::from(gas_limit),
+			base_fee,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn base_fee_follows_parent_gas_used() {
+		// At, above and below the target of a 30M gas limit.
+		assert_eq!(
+			base_fee(&parent(30_000_000, 15_000_000, Some(1000))),
+			1000.into()
+		);
+		assert_eq!(
+			base_fee(&parent(30_000_000, 30_000_000, Some(1000))),
+			1125.into()
+		);
+		assert_eq!(base_fee(&parent(30_000_000, 0, Some(1000))), 875.into());
+		// The increase is at least one wei.
+		assert_eq!(base_fee(&parent(30_000_000, 15_000_001, Some(7))), 8.into());
+		assert_eq!(
+			base_fee(&parent(30_000_000, 0, None)),
+			INITIAL_BASE_FEE.into()
+		);
+	}
+
+	#[test]
+	fn gas_limit_bounds() {
+		let parent = parent(1_024_000, 0, None);
+		assert!(check("Berlin", &child(&parent, 1_024_999, None), &parent).is_ok());
+		assert_eq!(
+			check("Berlin", &child(&parent, 1_025_000, None), &parent),
+			Err(Rejection::InvalidGasLimit)
+		);
+		assert_eq!(
+			check("Berlin", &child(&parent, 1_023_000, None), &parent),
+			Err(Rejection::InvalidGasLimit)
+		);
+
+		// The first London block doubles the limit and starts the base fee.
+		let initial = Some(U256::from(INITIAL_BASE_FEE));
+		assert!(check("London", &child(&parent, 2_048_000, initial), &parent).is_ok());
+		assert_eq!(
+			check("London", &child(&parent, 2_048_000, None), &parent),
+			Err(Rejection::MissingBaseFee)
+		);
+		assert_eq!(
+			check("Berlin", &child(&parent, 1_024_000, initial), &parent),
+			Err(Rejection::UnexpectedBaseFee)
+		);
+		assert_eq!(
+			check(
+				"London",
+				&child(&parent, 2_048_000, Some(U256::one())),
+				&parent
+			),
+			Err(Rejection::InvalidBaseFee {
+				expected: INITIAL_BASE_FEE.into(),
+				actual: U256::one(),
+			})
+		);
+
+		let mut long = child(&parent, 1_024_000, None);
+		long.extra_data = vec![0; MAX_EXTRA_DATA + 1];
+		assert_eq!(
+			check("Berlin", &long, &parent),
+			Err(Rejection::ExtraDataTooLong)
+		);
+
+		// Blocks 2 to 11 of a chain forking at 2.
+		assert_eq!(check_dao(2, &long), Err(Rejection::InvalidDaoExtraData));
+		long.extra_data = DAO_EXTRA_DATA.to_vec();
+		assert_eq!(check_dao(2, &long), Ok(()));
+		assert_eq!(check_dao(3, &parent), Ok(()));
+	}
+
+	#[test]
+	fn excess_blob_gas_updates() {
+		let mut parent = parent(30_000_000, 0, Some(7));
+		assert_eq!(excess_blob_gas(&parent), 0);
+		parent.blob_gas_used = Some(MAX_BLOB_GAS_PER_BLOCK);
+		parent.excess_blob_gas = Some(TARGET_BLOB_GAS_PER_BLOCK);
+		assert_eq!(excess_blob_gas(&parent), 2 * TARGET_BLOB_GAS_PER_BLOCK);
+
+		let mut header = child(&parent, 30_000_000, Some(base_fee(&parent)));
+		header.blob_gas_used = Some(BLOB_GAS_PER_BLOB);
+		header.excess_blob_gas = Some(2 * TARGET_BLOB_GAS_PER_BLOCK);
+		assert!(check("Cancun", &header, &parent).is_ok());
+		assert_eq!(
+			check("Shanghai", &header, &parent),
+			Err(Rejection::UnexpectedBlobGas)
+		);
+		header.blob_gas_used = Some(BLOB_GAS_PER_BLOB + 1);
+		assert_eq!(
+			check("Cancun", &header, &parent),
+			Err(Rejection::BlobGasNotMultiple)
+		);
+		header.blob_gas_used = None;
+		assert_eq!(
+			check("Cancun", &header, &parent),
+			Err(Rejection::MissingBlobGas)
+		);
+	}
+}
@@ -0,0 +1,88 @@
+//! Suites whose tests take a lot of memory, and a limit on how many of their
+//! fixture files run at once, apart from the number of jobs: a few of them
+//! running together can exhaust the memory of the host.
+
+use std::sync::{Condvar, Mutex};
+
+/// Suites whose tests allocate large memories or states.
+pub const HEAVY_SUITES: &[&str] = &["stQuadraticComplexity", "stMemoryStressTest"];
+
+/// Whether fixture files of `suite` are heavy.
+pub fn is_heavy(suite: &str) -> bool {
+	HEAVY_SUITES.contains(&suite)
+}
+
+/// Number of heavy fixture files allowed to run at once.
+#[derive(Debug)]
+pub struct Limit {
+	running: Mutex<usize>,
+	released: Condvar,
+	max: usize,
+}
+
+/// One of the heavy fixture files a [`Limit`] allows, running until dropped.
+pub struct Permit<'a> {
+	limit: &'a Limit,
+}
+
+impl Limit {
+	/// Allow `max` heavy files at once, at least one.
+	pub fn new(max: usize) -> Self {
+		Limit {
+			running: Mutex::new(0),
+			released: Condvar::new(),
+			max: max.max(1),
+		}
+	}
+
+	/// Wait until fewer than the maximum of heavy files run.
+	pub fn acquire(&self) -> Permit {
+		let mut running = self.running.lock().unwrap();
+		while *running >= self.max {
+			running = self.released.wait(running).unwrap();
+		}
+		*running += 1;
+		Permit { limit: self }
+	}
+
+	/// Number of heavy files running.
+	pub fn running(&self) -> usize {
+		*self.running.lock().unwrap()
+	}
+}
+
+impl Drop for Permit<'_> {
+	fn drop(&mut self) {
+		*self.limit.running.lock().unwrap() -= 1;
+		self.limit.released.notify_one();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+	use std::thread;
+	use std::time::Duration;
+
+	#[test]
+	fn heavy_files_are_limited() {
+		assert!(is_heavy("stQuadraticComplexity"));
+		assert!(!is_heavy("stCreate2"));
+
+		let limit = Limit::new(2);
+		let most = AtomicUsize::new(0);
+		thread::scope(|scope| {
+			for _ in 0..8 {
+				scope.spawn(|| {
+					let _permit = limit.acquire();
+					most.fetch_max(limit.running(), Ordering::SeqCst);
+					thread::sleep(Duration::from_millis(5));
+				});
+			}
+		});
+		assert!(most.load(Ordering::SeqCst) <= 2);
+		assert_eq!(limit.running(), 0);
+		assert_eq!(Limit::new(0).max, 1);
+	}
+}
@@ -0,0 +1,257 @@
+use crate::report::{index_id, IndexReport};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Upper bounds of the timing histogram buckets, in microseconds.
+const BUCKETS: [(u64, &str); 5] = [
+	(1_000, "< 1ms"),
+	(10_000, "1-10ms"),
+	(100_000, "10-100ms"),
+	(1_000_000, "100ms-1s"),
+	(10_000_000, "1-10s"),
+];
+
+const STYLE: &str = "\
+body { font-family: sans-serif; margin: 2em; }
+table { border-collapse: collapse; margin-bottom: 1em; }
+th, td { border: 1px solid #ccc; padding: 0.3em 0.8em; text-align: right; }
+th:first-child, td:first-child { text-align: left; }
+.failed { color: #b00; }
+.bar { background: #4a90d9; height: 1em; }
+pre { background: #f4f4f4; padding: 0.5em; overflow: auto; max-height: 30em; }
+";
+
+pub fn escape(text: &str) -> String {
+	let mut escaped = String::with_capacity(text.len());
+	for c in text.chars() {
+		match c {
+			'<' => escaped.push_str("&lt;"),
+			'>' => escaped.push_str("&gt;"),
+			'&' => escaped.push_str("&amp;"),
+			'"' => escaped.push_str("&quot;"),
+			c => escaped.push(c),
+		}
+	}
+	escaped
+}
+
+fn format_time(time_us: u64) -> String {
+	format!("{:.3}s", time_us as f64 / 1_000_000.0)
+}
+
+/// Millions of `amount` per second of `time_us`, if any was recorded.
+fn format_rate(amount: u64, time_us: u64) -> String {
+	if time_us == 0 {
+		"-".to_string()
+	} else {
+		format!("{:.1}", amount as f64 / time_us as f64)
+	}
+}
+
+#[derive(Default)]
+struct Totals {
+	passed: usize,
+	failed: usize,
+	time_us: u64,
+	/// Gas used and time of the indexes that recorded it.
+	gas: (u64, u64),
+	/// Steps executed and time of the indexes that recorded them.
+	steps: (u64, u64),
+}
+
+impl Totals {
+	fn add(&mut self, result: &IndexReport) {
+		if result.passed {
+			self.passed += 1;
+		} else {
+			self.failed += 1;
+		}
+		self.time_us += result.time_us;
+		if let Some(gas) = result.used_gas {
+			self.gas.0 += gas;
+			self.gas.1 += result.time_us;
+		}
+		if let Some(steps) = result.steps {
+			self.steps.0 += steps;
+			self.steps.1 += result.time_us;
+		}
+	}
+
+	fn row(&self, label: &str) -> String {
+		format!(
+			"<tr><td>{}</td><td>{}</td><td class=\"{}\">{}</td><td>{}</td><td>{}</td><td>{}</td></tr>\n",
+			escape(label),
+			self.passed,
+			if self.failed > 0 { "failed" } else { "" },
+			self.failed,
+			format_time(self.time_us),
+			format_rate(self.gas.0, self.gas.1),
+			format_rate(self.steps.0, self.steps.1)
+		)
+	}
+}
+
+fn histogram(results: &[IndexReport]) -> Vec<(&'static str, usize)> {
+	let mut counts = BUCKETS
+		.iter()
+		.map(|(_, label)| (*label, 0))
+		.collect::<Vec<_>>();
+	counts.push(("> 10s", 0));
+	for result in results {
+		let bucket = BUCKETS
+			.iter()
+			.position(|(bound, _)| result.time_us < *bound)
+			.unwrap_or(BUCKETS.len());
+		counts[bucket].1 += 1;
+	}
+	counts
+}
+
+/// Static HTML page summarising `results`: totals, a table per fork, the
+/// failing indexes with their diffs and traces, and a timing histogram.
+pub fn render(results: &[IndexReport]) -> String {
+	let mut total = Totals::default();
+	let mut forks = BTreeMap::<&str, Totals>::new();
+	for result in results {
+		total.add(result);
+		forks.entry(&result.fork).or_default().add(result);
+	}
+
+	let mut html = String::new();
+	html.push_str("<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n");
+	writeln!(
+		html,
+		"<title>EVM test report</title>\n<style>\n{}</style>",
+		STYLE
+	)
+	.unwrap();
+	html.push_str("</head>\n<body>\n<h1>EVM test report</h1>\n");
+
+	html.push_str("<h2>Summary</h2>\n<table>\n");
+	html.push_str(
+		"<tr><th></th><th>Passed</th><th>Failed</th><th>Time</th><th>Mgas/s</th>\
+		 <th>Msteps/s</th></tr>\n",
+	);
+	html.push_str(&total.row("All indexes"));
+	html.push_str("</table>\n");
+
+	html.push_str("<h2>Forks</h2>\n<table>\n");
+	html.push_str(
+		"<tr><th>Fork</th><th>Passed</th><th>Failed</th><th>Time</th><th>Mgas/s</th>\
+		 <th>Msteps/s</th></tr>\n",
+	);
+	for (fork, totals) in &forks {
+		html.push_str(&totals.row(fork));
+	}
+	html.push_str("</table>\n");
+
+	html.push_str("<h2>Failures</h2>\n");
+	let failures = results.iter().filter(|result| !result.passed);
+	let mut any_failure = false;
+	for result in failures {
+		any_failure = true;
+		writeln!(
+			html,
+			"<details>\n<summary class=\"failed\">{}: {}</summary>",
+			escape(&index_id(
+				&result.name,
+				&result.fork,
+				result.index,
+				result.label.as_deref()
+			)),
+			escape(result.error.as_deref().unwrap_or("failed"))
+		)
+		.unwrap();
+		if let Some(diff) = &result.diff {
+			writeln!(html, "<h4>State diff</h4>\n<pre>{}</pre>", escape(diff)).unwrap();
+		}
+		if let Some(trace) = &result.trace {
+			writeln!(html, "<h4>Trace</h4>\n<pre>{}</pre>", escape(trace)).unwrap();
+		}
+		html.push_str("</details>\n");
+	}
+	if !any_failure {
+		html.push_str("<p>None.</p>\n");
+	}
+
+	html.push_str("<h2>Timing</h2>\n<table>\n");
+	html.push_str("<tr><th>Time</th><th>Indexes</th><th></th></tr>\n");
+	let histogram = histogram(results);
+	let max = histogram.iter().map(|(_, count)| *count).max().unwrap_or(0);
+	for (label, count) in histogram {
+		let width = if max == 0 { 0 } else { count * 300 / max };
+		writeln!(
+			html,
+			"<tr><td>{}</td><td>{}</td><td><div class=\"bar\" style=\"width: {}px\"></div></td></tr>",
+			escape(label),
+			count,
+			width
+		)
+		.unwrap();
+	}
+	html.push_str("</table>\n</body>\n</html>\n");
+
+	html
+}
+
+/// Write the report of `results` to `index.html` in `dir`, returning its
+/// path.
+pub fn write(dir: &Path, results: &[IndexReport]) -> io::Result<PathBuf> {
+	fs::create_dir_all(dir)?;
+	let path = dir.join("index.html");
+	fs::write(&path, render(results))?;
+	Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn result(fork: &str, passed: bool, time_us: u64) -> IndexReport {
+		IndexReport {
+			name: "add<11>".to_string(),
+			fork: fork.to_string(),
+			passed,
+			time_us,
+			error: if passed {
+				None
+			} else {
+				Some("State root mismatch".to_string())
+			},
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn histogram_buckets() {
+		let results = vec![
+			result("Berlin", true, 10),
+			result("Berlin", true, 5_000),
+			result("Berlin", true, 20_000_000),
+		];
+		let counts = histogram(&results)
+			.into_iter()
+			.map(|(_, count)| count)
+			.collect::<Vec<_>>();
+		assert_eq!(counts, vec![1, 1, 0, 0, 0, 1]);
+	}
+
+	#[test]
+	fn render_escapes_failures() {
+		let html = render(&[result("Berlin", true, 10), result("Istanbul", false, 10)]);
+		assert!(html.contains("add&lt;11&gt;:Istanbul:0: State root mismatch"));
+		assert!(!html.contains("add<11>"));
+		assert!(html.contains("<tr><td>Berlin</td><td>1</td>"));
+		assert!(html.contains("<td>0.000s</td><td>-</td><td>-</td>"));
+
+		let measured = IndexReport {
+			used_gas: Some(21_000),
+			steps: Some(500),
+			..result("Berlin", true, 1_000)
+		};
+		assert!(render(&[measured]).contains("<td>21.0</td><td>0.5</td>"));
+	}
+}
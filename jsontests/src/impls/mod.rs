@@ -0,0 +1,236 @@
+//! State test execution on a second version of the evm crate, `evm-next`,
+//! built with the `compare-impl` feature, to validate an upgrade on the
+//! fixtures before bumping the dependency everywhere. Indexes run on either
+//! version, or on both, failing where they behave differently.
+
+#[cfg(feature = "compare-impl")]
+mod next;
+
+use crate::fixture::Indexes;
+use crate::state::{self, Test};
+use ethjson::spec::ForkSpec;
+use evm::backend::{Log, MemoryAccount, MemoryVicinity};
+use primitive_types::{H160, H256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+/// Version of the evm crate indexes run on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EvmImpl {
+	/// The version the harness builds against.
+	#[default]
+	Old,
+	/// `evm-next`.
+	New,
+	/// Both, failing indexes they disagree on.
+	Both,
+}
+
+impl EvmImpl {
+	fn name(&self) -> &'static str {
+		match self {
+			EvmImpl::Old => "old",
+			EvmImpl::New => "new",
+			EvmImpl::Both => "both",
+		}
+	}
+}
+
+impl fmt::Display for EvmImpl {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+impl FromStr for EvmImpl {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		[EvmImpl::Old, EvmImpl::New, EvmImpl::Both]
+			.iter()
+			.find(|implementation| implementation.name() == s)
+			.copied()
+			.ok_or_else(|| format!("unknown evm implementation {}", s))
+	}
+}
+
+/// Fail unless `implementation` is built in: the new version needs the
+/// `compare-impl` feature.
+pub fn check(implementation: EvmImpl) -> Result<(), String> {
+	if implementation != EvmImpl::Old && !cfg!(feature = "compare-impl") {
+		return Err(format!(
+			"--compare-impl {} needs the compare-impl feature",
+			implementation
+		));
+	}
+	Ok(())
+}
+
+/// Why `fork` cannot run on `implementation`, or `None` if it can. Both
+/// versions must support it to compare them.
+pub fn unsupported(fork: &str, implementation: EvmImpl) -> Option<String> {
+	match implementation {
+		EvmImpl::Old => state::unsupported(fork),
+		EvmImpl::New => new_unsupported(fork),
+		EvmImpl::Both => state::unsupported(fork).or_else(|| new_unsupported(fork)),
+	}
+}
+
+#[cfg(feature = "compare-impl")]
+fn new_unsupported(fork: &str) -> Option<String> {
+	next::unsupported(fork)
+}
+
+#[cfg(not(feature = "compare-impl"))]
+fn new_unsupported(fork: &str) -> Option<String> {
+	Some(format!(
+		"fork {} needs the compare-impl feature on evm-next",
+		fork
+	))
+}
+
+/// What executing an index did, in terms both versions share.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Behaviour {
+	pub state_root: H256,
+	pub used_gas: u64,
+	/// Debug form of the exit reason, which both versions spell alike.
+	pub exit_reason: String,
+	pub output: Vec<u8>,
+	pub logs: Vec<Log>,
+}
+
+impl Behaviour {
+	pub fn new(
+		state_root: H256,
+		used_gas: u64,
+		exit_reason: &impl fmt::Debug,
+		output: &[u8],
+		logs: &[Log],
+	) -> Self {
+		Behaviour {
+			state_root,
+			used_gas,
+			exit_reason: format!("{:?}", exit_reason),
+			output: output.to_vec(),
+			logs: logs.to_vec(),
+		}
+	}
+
+	/// How `new` differs from this behaviour of the old version, one line
+	/// per field, none if they agree.
+	pub fn differences(&self, new: &Behaviour) -> Vec<String> {
+		let mut differences = Vec::new();
+		if self.exit_reason != new.exit_reason {
+			differences.push(format!(
+				"exit reason {} on old, {} on new",
+				self.exit_reason, new.exit_reason
+			));
+		}
+		if self.used_gas != new.used_gas {
+			differences.push(format!(
+				"gas used {} on old, {} on new",
+				self.used_gas, new.used_gas
+			));
+		}
+		if self.output != new.output {
+			differences.push(format!(
+				"output 0x{} on old, 0x{} on new",
+				hex::encode(&self.output),
+				hex::encode(&new.output)
+			));
+		}
+		if self.logs != new.logs {
+			differences.push(format!(
+				"logs differ, {} on old, {} on new",
+				self.logs.len(),
+				new.logs.len()
+			));
+		}
+		if self.state_root != new.state_root {
+			differences.push(format!(
+				"state root {:?} on old, {:?} on new",
+				self.state_root, new.state_root
+			));
+		}
+		differences
+	}
+}
+
+/// An index executed on the new version, with its post-state converted to
+/// the accounts of the old one.
+#[derive(Debug, Clone)]
+pub struct Executed {
+	pub state: BTreeMap<H160, MemoryAccount>,
+	pub behaviour: Behaviour,
+}
+
+/// Pre-state of the indexes of a fork on the new version, converted once
+/// and shared by them: each index only puts back the accounts it changed.
+#[cfg(feature = "compare-impl")]
+pub use next::PreState as NewPreState;
+
+#[cfg(not(feature = "compare-impl"))]
+pub struct NewPreState;
+
+#[cfg(not(feature = "compare-impl"))]
+impl NewPreState {
+	pub fn new(_pre_state: &BTreeMap<H160, MemoryAccount>) -> Self {
+		NewPreState
+	}
+}
+
+/// Execute the transaction selected by `indexes` on the new version against
+/// `pre_state`, after its pre-transactions. Returns `None` if the new
+/// version does not support the fork.
+#[cfg(feature = "compare-impl")]
+pub fn execute_new(
+	test: &Test,
+	spec: &ForkSpec,
+	indexes: &Indexes,
+	vicinity: &MemoryVicinity,
+	pre_state: &mut NewPreState,
+) -> Option<Executed> {
+	next::execute(test, spec, indexes, vicinity, pre_state)
+}
+
+#[cfg(not(feature = "compare-impl"))]
+pub fn execute_new(
+	_test: &Test,
+	_spec: &ForkSpec,
+	_indexes: &Indexes,
+	_vicinity: &MemoryVicinity,
+	_pre_state: &mut NewPreState,
+) -> Option<Executed> {
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use evm::{ExitReason, ExitSucceed};
+
+	#[test]
+	fn differences_of_behaviours() {
+		assert_eq!("both".parse(), Ok(EvmImpl::Both));
+		assert!("newest".parse::<EvmImpl>().is_err());
+		assert_eq!(check(EvmImpl::Old), Ok(()));
+
+		let stopped = ExitReason::Succeed(ExitSucceed::Stopped);
+		let old = Behaviour::new(H256::zero(), 21000, &stopped, &[], &[]);
+		assert!(old.differences(&old).is_empty());
+		let new = Behaviour {
+			used_gas: 21100,
+			output: vec![1],
+			..old.clone()
+		};
+		assert_eq!(
+			old.differences(&new),
+			vec![
+				"gas used 21000 on old, 21100 on new".to_string(),
+				"output 0x on old, 0x01 on new".to_string(),
+			]
+		);
+	}
+}
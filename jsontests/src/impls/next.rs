@@ -0,0 +1,361 @@
+//! Transaction execution on `evm-next`, as `conformance::transact` does it
+//! on the version the harness builds against. Accounts, logs and vicinities
+//! are converted from and back to the types of that version, so the rest of
+//! the runner only sees those.
+
+use super::{Behaviour, Executed};
+use crate::builtins::{self, Builtins};
+use crate::chainspec;
+use crate::fixture::{fork_spec, Indexes};
+use crate::precompiles::Builtin;
+use crate::state::{deletes_empty, Test, Transaction};
+use crate::utils::state_root;
+use ethjson::spec::ForkSpec;
+use evm::backend::{Log, MemoryAccount, MemoryVicinity};
+use evm_next::backend::{self as next_backend, Apply, ApplyBackend, MemoryBackend};
+use evm_next::executor::stack::{
+	MemoryStackState, PrecompileFailure, PrecompileOutput, PrecompileSet, StackExecutor,
+	StackSubstateMetadata,
+};
+use evm_next::{Config, Context, ExitError, ExitSucceed};
+use primitive_types::{H160, U256};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+type NextState = BTreeMap<H160, next_backend::MemoryAccount>;
+
+/// Gasometer config of a fork on the new version. Chain spec tweaks are made
+/// to the config of the old one, so custom chains do not run here.
+fn config(spec: &ForkSpec) -> Option<Config> {
+	match spec {
+		ForkSpec::Istanbul => Some(Config::istanbul()),
+		ForkSpec::Berlin => Some(Config::berlin()),
+		ForkSpec::London => Some(Config::london()),
+		_ => None,
+	}
+}
+
+/// Why the new version cannot execute `fork`, or `None` if it can.
+pub fn unsupported(fork: &str) -> Option<String> {
+	match fork_spec(fork) {
+		None => Some(format!("fork {} unknown", fork)),
+		Some(spec) if config(&spec).is_none() => {
+			Some(format!("fork {} unsupported by evm-next", fork))
+		}
+		Some(_) if chainspec::active().is_some() => Some(format!(
+			"fork {} of a chain spec unsupported by evm-next",
+			fork
+		)),
+		Some(_) => None,
+	}
+}
+
+/// Builtins of a fork as precompiles of the new version, which holds them
+/// rather than looking them up from plain function pointers.
+struct Precompiles(Arc<Builtins>);
+
+impl PrecompileSet for Precompiles {
+	fn execute(
+		&self,
+		address: H160,
+		input: &[u8],
+		gas_limit: Option<u64>,
+		_context: &Context,
+		_is_static: bool,
+	) -> Option<Result<PrecompileOutput, PrecompileFailure>> {
+		let builtin = self.0.get(&address)?;
+		Some(execute_builtin(builtin, input, gas_limit))
+	}
+
+	fn is_precompile(&self, address: H160) -> bool {
+		self.0.contains_key(&address)
+	}
+}
+
+/// `JsonPrecompile::exec_as_precompile` with the results of the new version.
+fn execute_builtin(
+	builtin: &Builtin,
+	input: &[u8],
+	gas_limit: Option<u64>,
+) -> Result<PrecompileOutput, PrecompileFailure> {
+	let cost = builtin.cost(input);
+	if let Some(target_gas) = gas_limit {
+		if cost > U256::from(u64::MAX) || target_gas < cost.as_u64() {
+			return Err(PrecompileFailure::Error {
+				exit_status: ExitError::OutOfGas,
+			});
+		}
+	}
+	match builtin.execute(input) {
+		Ok(output) => Ok(PrecompileOutput {
+			exit_status: ExitSucceed::Stopped,
+			output,
+			cost: cost.as_u64(),
+			logs: Vec::new(),
+		}),
+		Err(e) => Err(PrecompileFailure::Error {
+			exit_status: ExitError::Other(e.into()),
+		}),
+	}
+}
+
+fn next_account(account: &MemoryAccount) -> next_backend::MemoryAccount {
+	next_backend::MemoryAccount {
+		nonce: account.nonce,
+		balance: account.balance,
+		storage: account.storage.clone(),
+		code: account.code.clone(),
+	}
+}
+
+fn old_account(account: &next_backend::MemoryAccount) -> MemoryAccount {
+	MemoryAccount {
+		nonce: account.nonce,
+		balance: account.balance,
+		storage: account.storage.clone(),
+		code: account.code.clone(),
+	}
+}
+
+fn old_log(log: next_backend::Log) -> Log {
+	Log {
+		address: log.address,
+		topics: log.topics,
+		data: log.data,
+	}
+}
+
+/// Vicinity of the new version, which also knows the base fee of London.
+fn next_vicinity(vicinity: &MemoryVicinity, base_fee: U256) -> next_backend::MemoryVicinity {
+	next_backend::MemoryVicinity {
+		gas_price: vicinity.gas_price,
+		origin: vicinity.origin,
+		chain_id: vicinity.chain_id,
+		block_hashes: vicinity.block_hashes.clone(),
+		block_number: vicinity.block_number,
+		block_coinbase: vicinity.block_coinbase,
+		block_timestamp: vicinity.block_timestamp,
+		block_difficulty: vicinity.block_difficulty,
+		block_gas_limit: vicinity.block_gas_limit,
+		block_base_fee_per_gas: base_fee,
+	}
+}
+
+/// Pre-state of the indexes of a fork, converted to the accounts of the new
+/// version once. Every index changes it in place and then puts back the
+/// accounts it changed, rather than converting the whole pre-state again.
+pub struct PreState {
+	state: NextState,
+	/// Accounts the index running replaced, `None` where there were none,
+	/// as they were before it.
+	replaced: BTreeMap<H160, Option<next_backend::MemoryAccount>>,
+}
+
+impl PreState {
+	pub fn new(pre_state: &BTreeMap<H160, MemoryAccount>) -> Self {
+		PreState {
+			state: pre_state
+				.iter()
+				.map(|(address, account)| (*address, next_account(account)))
+				.collect(),
+			replaced: BTreeMap::new(),
+		}
+	}
+
+	/// Remember the accounts of `state` that `values` are about to change,
+	/// unless an earlier transaction of the index already did.
+	fn record<I>(&mut self, state: &NextState, values: &[Apply<I>]) {
+		for apply in values {
+			let address = match apply {
+				Apply::Modify { address, .. } | Apply::Delete { address } => *address,
+			};
+			self.replaced
+				.entry(address)
+				.or_insert_with(|| state.get(&address).cloned());
+		}
+	}
+
+	/// Put back the accounts changed since the last reset.
+	fn reset(&mut self) {
+		for (address, account) in std::mem::take(&mut self.replaced) {
+			match account {
+				Some(account) => self.state.insert(address, account),
+				None => self.state.remove(&address),
+			};
+		}
+	}
+}
+
+/// What a transaction did on the new version.
+struct Transacted {
+	exit_reason: evm_next::ExitReason,
+	output: Vec<u8>,
+	used_gas: u64,
+	logs: Vec<Log>,
+}
+
+/// Execute `transaction` on `pre_state` under `config`, paying the coinbase
+/// the priority fee above the base fee, which is burnt. The state is moved
+/// into the backend and back, not copied.
+fn transact(
+	config: &Config,
+	delete_empty: bool,
+	vicinity: &next_backend::MemoryVicinity,
+	pre_state: &mut PreState,
+	transaction: &Transaction,
+	precompiles: &Precompiles,
+) -> Transacted {
+	let caller = transaction.caller;
+	let gas_limit = transaction.gas_limit;
+	let gas_price = vicinity.gas_price;
+	let mut backend = MemoryBackend::new(vicinity, std::mem::take(&mut pre_state.state));
+
+	let metadata = StackSubstateMetadata::new(gas_limit, config);
+	let executor_state = MemoryStackState::new(metadata, &backend);
+	let mut executor = StackExecutor::new_with_precompiles(executor_state, config, precompiles);
+	let total_fee = gas_price * gas_limit;
+	executor.state_mut().withdraw(caller, total_fee).unwrap();
+
+	let access_list = transaction.access_list.clone();
+	let value = transaction.value;
+	let data = transaction.data.clone();
+	let (exit_reason, output) = match transaction.to {
+		Some(to) => executor.transact_call(caller, to, value, data, gas_limit, access_list),
+		None => {
+			let reason = executor.transact_create(caller, value, data, gas_limit, access_list);
+			(reason, Vec::new())
+		}
+	};
+
+	let used_gas = executor.used_gas();
+	let actual_fee = executor.fee(gas_price);
+	let priority_fee = executor.fee(gas_price.saturating_sub(vicinity.block_base_fee_per_gas));
+	executor
+		.state_mut()
+		.deposit(vicinity.block_coinbase, priority_fee);
+	executor.state_mut().deposit(caller, total_fee - actual_fee);
+	let (values, logs) = executor.into_state().deconstruct();
+	let values = values.into_iter().collect::<Vec<_>>();
+	let logs = logs.into_iter().collect::<Vec<_>>();
+	pre_state.record(backend.state(), &values);
+	backend.apply(values, logs.clone(), delete_empty);
+	pre_state.state = std::mem::take(backend.state_mut());
+
+	Transacted {
+		exit_reason,
+		output,
+		used_gas,
+		logs: logs.into_iter().map(old_log).collect(),
+	}
+}
+
+/// Execute the transaction selected by `indexes` against `pre_state` after
+/// its pre-transactions, leaving `pre_state` as it was, or `None` if the
+/// fork is not supported.
+pub fn execute(
+	test: &Test,
+	spec: &ForkSpec,
+	indexes: &Indexes,
+	vicinity: &MemoryVicinity,
+	pre_state: &mut PreState,
+) -> Option<Executed> {
+	let config = config(spec)?;
+	let delete_empty = deletes_empty(spec);
+	let precompiles = Precompiles(builtins::for_fork(&format!("{:?}", spec))?);
+	let base_fee = test.0.env.current_base_fee.unwrap_or_default();
+
+	for (vicinity, message) in test.pre_messages(spec, indexes) {
+		let vicinity = next_vicinity(&vicinity, base_fee);
+		transact(
+			&config,
+			delete_empty,
+			&vicinity,
+			pre_state,
+			&message,
+			&precompiles,
+		);
+	}
+	let transaction = test.0.transaction.message(indexes, vicinity.origin);
+	let transacted = transact(
+		&config,
+		delete_empty,
+		&next_vicinity(vicinity, base_fee),
+		pre_state,
+		&transaction,
+		&precompiles,
+	);
+
+	let state = pre_state
+		.state
+		.iter()
+		.map(|(address, account)| (*address, old_account(account)))
+		.collect();
+	pre_state.reset();
+	let behaviour = Behaviour::new(
+		state_root(&state),
+		transacted.used_gas,
+		&transacted.exit_reason,
+		&transacted.output,
+		&transacted.logs,
+	);
+	Some(Executed { state, behaviour })
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use evm_next::backend::Basic;
+	use primitive_types::H256;
+
+	#[test]
+	fn pre_state_reset_puts_back_changed_accounts() {
+		let a = H160::repeat_byte(0xaa);
+		let b = H160::repeat_byte(0xbb);
+		let account = MemoryAccount {
+			nonce: U256::one(),
+			balance: U256::from(10),
+			storage: BTreeMap::new(),
+			code: vec![0x00],
+		};
+		let original = vec![(a, account)].into_iter().collect::<BTreeMap<_, _>>();
+		let mut pre_state = PreState::new(&original);
+
+		let modify = |address| Apply::Modify {
+			address,
+			basic: Basic {
+				balance: U256::from(5),
+				nonce: U256::from(2),
+			},
+			code: None,
+			storage: Vec::<(H256, H256)>::new(),
+			reset_storage: false,
+		};
+		let vicinity = next_backend::MemoryVicinity {
+			gas_price: U256::zero(),
+			origin: H160::zero(),
+			chain_id: U256::one(),
+			block_hashes: Vec::new(),
+			block_number: U256::zero(),
+			block_coinbase: H160::zero(),
+			block_timestamp: U256::zero(),
+			block_difficulty: U256::zero(),
+			block_gas_limit: U256::zero(),
+			block_base_fee_per_gas: U256::zero(),
+		};
+		for values in vec![
+			vec![modify(a), modify(b)],
+			vec![Apply::Delete { address: a }],
+		] {
+			let state = pre_state.state.clone();
+			pre_state.record(&state, &values);
+			let mut backend = MemoryBackend::new(&vicinity, std::mem::take(&mut pre_state.state));
+			backend.apply(values, Vec::new(), false);
+			pre_state.state = std::mem::take(backend.state_mut());
+		}
+		assert_eq!(pre_state.state.keys().collect::<Vec<_>>(), vec![&b]);
+
+		pre_state.reset();
+		assert_eq!(pre_state.state, PreState::new(&original).state);
+		assert!(pre_state.replaced.is_empty());
+	}
+}
@@ -0,0 +1,146 @@
+//! Running of a test in a child process, so that a crash of native code, like
+//! a segfault in a builtin or an abort of the interpreter, fails that test
+//! instead of taking the whole run down.
+//!
+//! The child is forked, not spawned, so it runs the test with the state of
+//! the parent. Locks other threads hold at the fork stay held in the child,
+//! which is fine for the locks tests take: they read the builtins, report
+//! and record through their own handles.
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::os::unix::io::FromRawFd;
+use std::panic::{self, AssertUnwindSafe};
+
+/// Exit status of a child whose closure panicked.
+const PANICKED: i32 = 101;
+
+/// How a child failed to return a result.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Crash {
+	/// Killed by the signal.
+	Signal(i32),
+	/// Exited with the status before returning.
+	Exit(i32),
+	/// The child could not be started or its result not read.
+	Io(String),
+}
+
+fn signal_name(signal: i32) -> Option<&'static str> {
+	Some(match signal {
+		libc::SIGSEGV => "SIGSEGV",
+		libc::SIGABRT => "SIGABRT",
+		libc::SIGBUS => "SIGBUS",
+		libc::SIGILL => "SIGILL",
+		libc::SIGFPE => "SIGFPE",
+		libc::SIGKILL => "SIGKILL",
+		_ => return None,
+	})
+}
+
+impl fmt::Display for Crash {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Crash::Signal(signal) => match signal_name(*signal) {
+				Some(name) => write!(f, "killed by signal {} ({})", signal, name),
+				None => write!(f, "killed by signal {}", signal),
+			},
+			Crash::Exit(status) => write!(f, "exited with status {}", status),
+			Crash::Io(err) => write!(f, "{}", err),
+		}
+	}
+}
+
+fn last_error(what: &str) -> Crash {
+	Crash::Io(format!("{} failed: {}", what, io::Error::last_os_error()))
+}
+
+/// Run `f` in a child process and return its result, passed back through a
+/// pipe.
+pub fn isolated<T, F>(f: F) -> Result<T, Crash>
+where
+	T: Serialize + DeserializeOwned,
+	F: FnOnce() -> T,
+{
+	let mut fds = [0; 2];
+	if unsafe { libc::pipe(fds.as_mut_ptr()) } != 0 {
+		return Err(last_error("Create pipe"));
+	}
+	let (read, write) = (fds[0], fds[1]);
+	// Buffered output would otherwise be written by both processes.
+	let _ = io::stdout().flush();
+	let _ = io::stderr().flush();
+
+	match unsafe { libc::fork() } {
+		-1 => {
+			let err = last_error("Fork");
+			unsafe {
+				libc::close(read);
+				libc::close(write);
+			}
+			Err(err)
+		}
+		0 => {
+			unsafe { libc::close(read) };
+			let mut pipe = unsafe { File::from_raw_fd(write) };
+			// Unwinding out of here would run the rest of the parent in the
+			// child.
+			let status = match panic::catch_unwind(AssertUnwindSafe(f)) {
+				Ok(result) => match serde_json::to_writer(&mut pipe, &result) {
+					Ok(()) => 0,
+					Err(_) => 1,
+				},
+				Err(_) => PANICKED,
+			};
+			let _ = io::stdout().flush();
+			let _ = io::stderr().flush();
+			unsafe { libc::_exit(status) }
+		}
+		pid => {
+			unsafe { libc::close(write) };
+			let mut pipe = unsafe { File::from_raw_fd(read) };
+			let mut message = Vec::new();
+			let received = pipe.read_to_end(&mut message);
+
+			let mut status = 0;
+			if unsafe { libc::waitpid(pid, &mut status, 0) } == -1 {
+				return Err(last_error("Wait for child"));
+			}
+			if libc::WIFSIGNALED(status) {
+				return Err(Crash::Signal(libc::WTERMSIG(status)));
+			}
+			match libc::WEXITSTATUS(status) {
+				0 => (),
+				status => return Err(Crash::Exit(status)),
+			}
+			received.map_err(|err| Crash::Io(format!("Read result of child failed: {}", err)))?;
+			serde_json::from_slice(&message)
+				.map_err(|err| Crash::Io(format!("Parse result of child failed: {}", err)))
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn crashes_are_contained() {
+		assert_eq!(isolated(|| vec![1, 2]), Ok(vec![1, 2]));
+		assert_eq!(
+			isolated(|| -> u8 { unsafe { libc::abort() } }),
+			Err(Crash::Signal(libc::SIGABRT))
+		);
+		assert_eq!(
+			isolated(|| -> u8 { std::process::exit(3) }),
+			Err(Crash::Exit(3))
+		);
+		assert_eq!(
+			Crash::Signal(libc::SIGSEGV).to_string(),
+			format!("killed by signal {} (SIGSEGV)", libc::SIGSEGV)
+		);
+	}
+}
@@ -0,0 +1,164 @@
+//! Trusted setup of the KZG commitments checked by the point evaluation
+//! precompile of Cancun. The setup is read and verified once, by whichever
+//! worker needs it first, and shared by all of them afterwards.
+//!
+//! Unless another file is given, the mainnet setup is read from
+//! `res/kzg/trusted_setup.txt`, in the text format of c-kzg, and must match
+//! the SHA-256 digest pinned in `res/kzg/trusted_setup.sha256`.
+
+use crate::resources::res_path;
+use once_cell::sync::OnceCell;
+use sha2::{Digest, Sha256};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// G1 points of the mainnet setup, one per field element of a blob.
+pub const G1_POINTS: usize = 4096;
+/// G2 points of the mainnet setup.
+pub const G2_POINTS: usize = 65;
+
+/// Path of the mainnet setup.
+pub fn default_path() -> PathBuf {
+	res_path("kzg/trusted_setup.txt")
+}
+
+/// Path of the pinned digest of the mainnet setup.
+pub fn default_digest_path() -> PathBuf {
+	res_path("kzg/trusted_setup.sha256")
+}
+
+/// SHA-256 digest in hex of `text` with LF line endings, the same for a
+/// checkout with CRLF ones.
+fn lf_digest(text: &str) -> String {
+	hex::encode(Sha256::digest(text.replace("\r\n", "\n").as_bytes()))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustedSetup {
+	/// Compressed G1 points, in Lagrange form.
+	pub g1: Vec<[u8; 48]>,
+	/// Compressed G2 points, in monomial form.
+	pub g2: Vec<[u8; 96]>,
+}
+
+fn points<const N: usize>(
+	lines: &mut dyn Iterator<Item = &str>,
+	count: usize,
+) -> Result<Vec<[u8; N]>, String> {
+	(0..count)
+		.map(|i| {
+			let line = lines.next().ok_or_else(|| format!("missing point {}", i))?;
+			let bytes = hex::decode(line.trim().trim_start_matches("0x"))
+				.map_err(|err| format!("point {}: {}", i, err))?;
+			let mut point = [0; N];
+			if bytes.len() != N {
+				return Err(format!("point {} has {} bytes, not {}", i, bytes.len(), N));
+			}
+			point.copy_from_slice(&bytes);
+			Ok(point)
+		})
+		.collect()
+}
+
+impl TrustedSetup {
+	/// Parse a setup in the text format of c-kzg: the number of G1 and of
+	/// G2 points on a line each, then every point in hex on a line of its own.
+	pub fn parse(text: &str) -> Result<Self, String> {
+		let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+		let mut count = |what| {
+			lines
+				.next()
+				.and_then(|line| line.trim().parse::<usize>().ok())
+				.ok_or_else(|| format!("missing number of {} points", what))
+		};
+		let (g1_count, g2_count) = (count("G1")?, count("G2")?);
+		let g1 = points(&mut lines, g1_count).map_err(|err| format!("G1 {}", err))?;
+		let g2 = points(&mut lines, g2_count).map_err(|err| format!("G2 {}", err))?;
+		if lines.next().is_some() {
+			return Err("trailing lines after the G2 points".to_string());
+		}
+		Ok(TrustedSetup { g1, g2 })
+	}
+
+	/// Read the setup at `path`, checking it has the shape of the mainnet
+	/// one and, if given, its SHA-256 digest in hex.
+	pub fn load(path: &Path, digest: Option<&str>) -> Result<Self, String> {
+		let text =
+			fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+		if let Some(expected) = digest {
+			let actual = lf_digest(&text);
+			if !actual.eq_ignore_ascii_case(expected.trim()) {
+				return Err(format!(
+					"{}: SHA-256 {} is not the expected {}",
+					path.display(),
+					actual,
+					expected.trim()
+				));
+			}
+		}
+		let setup = Self::parse(&text).map_err(|err| format!("{}: {}", path.display(), err))?;
+		if setup.g1.len() != G1_POINTS || setup.g2.len() != G2_POINTS {
+			return Err(format!(
+				"{}: {} G1 and {} G2 points, not {} and {}",
+				path.display(),
+				setup.g1.len(),
+				setup.g2.len(),
+				G1_POINTS,
+				G2_POINTS
+			));
+		}
+		Ok(setup)
+	}
+}
+
+static OVERRIDE: OnceCell<PathBuf> = OnceCell::new();
+static SETUP: OnceCell<Result<TrustedSetup, String>> = OnceCell::new();
+
+/// Read the setup from `path` instead of the mainnet one. Fails once the
+/// setup is in use or another file was given.
+pub fn set_trusted_setup(path: PathBuf) -> Result<(), String> {
+	if SETUP.get().is_some() {
+		return Err("trusted setup already loaded".to_string());
+	}
+	OVERRIDE
+		.set(path)
+		.map_err(|_| "trusted setup already set".to_string())
+}
+
+/// The trusted setup, read on the first call. A custom setup is taken as
+/// is, the mainnet one must match its pinned digest.
+pub fn trusted_setup() -> Result<&'static TrustedSetup, String> {
+	SETUP
+		.get_or_init(|| match OVERRIDE.get() {
+			Some(path) => TrustedSetup::load(path, None),
+			None => {
+				let digest_path = default_digest_path();
+				let digest = fs::read_to_string(&digest_path)
+					.map_err(|err| format!("{}: {}", digest_path.display(), err))?;
+				TrustedSetup::load(&default_path(), Some(&digest))
+			}
+		})
+		.as_ref()
+		.map_err(Clone::clone)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn parses_setup() {
+		let g1 = "a".repeat(96);
+		let g2 = "b".repeat(192);
+		let text = format!("2\n1\n{}\n0x{}\n{}\n", g1, g1, g2);
+		let setup = TrustedSetup::parse(&text).unwrap();
+		assert_eq!(setup.g1, vec![[0xaa; 48]; 2]);
+		assert_eq!(setup.g2, vec![[0xbb; 96]]);
+
+		assert!(TrustedSetup::parse(&format!("2\n1\n{}\n{}\n", g1, g2)).is_err());
+		assert!(TrustedSetup::parse(&format!("1\n1\n{}\n{}\n{}\n", g1, g2, g2)).is_err());
+		assert!(TrustedSetup::parse(&format!("1\n1\n{}\n{}\n", g2, g1)).is_err());
+
+		assert_eq!(lf_digest("2\r\n1\r\n"), lf_digest("2\n1\n"));
+	}
+}
@@ -1,4 +1,107 @@
 mod utils;
 
+pub mod access;
+pub mod alloc;
+pub mod archive;
+pub mod artifact;
+pub mod b11r;
+pub mod batch;
+pub mod bench;
+pub mod bisect;
+pub mod blockchain;
+pub mod boundaries;
+pub mod builtins;
+pub mod callstack;
+pub mod calltree;
+pub mod capture;
+#[cfg(feature = "net")]
+pub mod cassette;
+pub mod chainspec;
+#[cfg(feature = "net")]
+pub mod client;
+pub mod compare;
+pub mod config;
+pub mod conformance;
+pub mod consistency;
+pub mod console;
+pub mod corpus;
+pub mod crash;
+pub mod debugger;
+pub mod diff;
+pub mod disasm;
+pub mod dump;
+pub mod embed;
+pub mod encoding;
+pub mod exceptions;
+pub mod exec;
+pub mod explain;
+pub mod fees;
+#[cfg(feature = "net")]
+pub mod fetch;
+pub mod ffi;
+pub mod filler;
+pub mod fixture;
+pub mod fixtures;
+pub mod forked;
+pub mod format;
+pub mod gasreport;
+pub mod genesis;
+pub mod gethalloc;
+pub mod header;
+pub mod heavy;
+pub mod html;
+pub mod impls;
+#[cfg(unix)]
+pub mod isolate;
+pub mod kzg;
+pub mod listener;
+pub mod logs;
+pub mod manifest;
+pub mod matrix;
+pub mod metrics;
+pub mod minimize;
+pub mod opcodes;
+pub mod order;
+pub mod overlay;
+pub mod params;
+pub mod perturb;
+pub mod precompiles;
+pub mod profile;
+pub mod progress;
+pub mod provenance;
+#[cfg(feature = "python")]
+pub mod python;
+pub mod reft8n;
+#[cfg(feature = "net")]
+pub mod replay;
+pub mod report;
+pub mod requests;
+pub mod resources;
+pub mod retesteth;
+pub mod roots;
+pub mod rpc;
+pub mod sarif;
+pub mod schedule;
+pub mod schema;
+pub mod service;
+pub mod signing;
+pub mod smoke;
+pub mod soak;
 pub mod state;
+pub mod stats;
+pub mod structlog;
+pub mod summary;
+pub mod synthetic;
+pub mod tags;
+pub mod testindex;
+pub mod timings;
+pub mod touched;
+pub mod trace;
+pub mod tracediff;
+pub mod transaction;
+pub mod validators;
+pub mod validity;
+pub mod vectors;
 pub mod vm;
+pub mod warmth;
+pub mod watch;
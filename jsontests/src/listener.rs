@@ -0,0 +1,476 @@
+//! Hooks for library users to observe fixture execution without changing
+//! the runner, e.g. for coverage tools or invariant checkers. A
+//! [`Listener`] is told of every step, call frame, storage write, log and
+//! precompile call of the transactions it is attached to, in the order they
+//! happen, through the [`Listening`] tracer or [`listen`].
+
+use crate::calltree::CallKind;
+use crate::fixture::fork_spec;
+use crate::state::{self, JsonPrecompile, Test};
+use crate::trace::Tracer;
+use evm::tracing::Event;
+use evm::{CreateScheme, ExitReason};
+use evm_runtime::tracing::Event as RuntimeEvent;
+use primitive_types::{H160, H256, U256};
+use std::collections::BTreeSet;
+
+/// An instruction about to run.
+#[derive(Debug, Clone, Copy)]
+pub struct Step<'a> {
+	/// Depth of the frame, 0 for the transaction's.
+	pub depth: usize,
+	/// Account the code runs as.
+	pub address: H160,
+	pub pc: usize,
+	pub opcode: u8,
+	/// The stack, with its top last.
+	pub stack: &'a [H256],
+	pub memory: &'a [u8],
+}
+
+/// A call or creation frame being entered.
+#[derive(Debug, Clone)]
+pub struct Frame {
+	pub depth: usize,
+	pub kind: CallKind,
+	pub from: H160,
+	/// Code address of a call, address of the created contract otherwise.
+	pub to: H160,
+	pub value: U256,
+	/// Calldata, or init code of a creation.
+	pub input: Vec<u8>,
+	pub gas: Option<u64>,
+	pub is_static: bool,
+}
+
+/// A frame returning to its caller.
+#[derive(Debug, Clone, Copy)]
+pub struct FrameExit<'a> {
+	pub depth: usize,
+	pub reason: &'a ExitReason,
+	pub output: &'a [u8],
+}
+
+/// A storage slot being written.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Sstore {
+	pub depth: usize,
+	pub address: H160,
+	pub key: H256,
+	pub value: H256,
+}
+
+/// A log emitted by a LOG instruction, which is dropped again if its frame
+/// or one of its callers reverts.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LogEvent {
+	pub depth: usize,
+	pub address: H160,
+	pub topics: Vec<H256>,
+	pub data: Vec<u8>,
+}
+
+/// A finished call to a precompile.
+#[derive(Debug, Clone, Copy)]
+pub struct PrecompileCall<'a> {
+	pub depth: usize,
+	pub address: H160,
+	pub input: &'a [u8],
+	pub output: &'a [u8],
+	pub reason: &'a ExitReason,
+}
+
+/// Receiver of execution events. Every method does nothing by default, so
+/// implementations only override the events they analyse.
+pub trait Listener {
+	/// Index `index` of `fork` starts, when run by [`listen`].
+	fn index(&mut self, _fork: &str, _index: usize) {}
+	fn step(&mut self, _step: &Step) {}
+	fn enter(&mut self, _frame: &Frame) {}
+	fn exit(&mut self, _exit: &FrameExit) {}
+	fn sstore(&mut self, _sstore: &Sstore) {}
+	fn log(&mut self, _log: &LogEvent) {}
+	fn precompile(&mut self, _call: &PrecompileCall) {}
+}
+
+/// An open frame: its account, whether it is static and, for a precompile,
+/// its address and input.
+struct Open {
+	address: H160,
+	is_static: bool,
+	precompile: Option<(H160, Vec<u8>)>,
+}
+
+/// Tracer turning the event streams of the evm into the events of a
+/// [`Listener`].
+pub struct Listening<L> {
+	listener: L,
+	precompiles: BTreeSet<H160>,
+	frames: Vec<Open>,
+}
+
+impl<L: Listener> Listening<L> {
+	/// Listen with `listener` to a transaction on a fork with `precompiles`.
+	pub fn new(listener: L, precompiles: impl IntoIterator<Item = H160>) -> Self {
+		Listening {
+			listener,
+			precompiles: precompiles.into_iter().collect(),
+			frames: Vec::new(),
+		}
+	}
+
+	pub fn into_listener(self) -> L {
+		self.listener
+	}
+
+	fn enter(&mut self, frame: Frame) {
+		let precompile = if frame.kind != CallKind::Create
+			&& frame.kind != CallKind::Create2
+			&& self.precompiles.contains(&frame.to)
+		{
+			Some((frame.to, frame.input.clone()))
+		} else {
+			None
+		};
+		let address = match frame.kind {
+			// Code runs as the caller.
+			CallKind::CallCode | CallKind::DelegateCall => frame.from,
+			_ => frame.to,
+		};
+		self.listener.enter(&frame);
+		self.frames.push(Open {
+			address,
+			is_static: frame.is_static,
+			precompile,
+		});
+	}
+
+	fn depth(&self) -> usize {
+		self.frames.len().saturating_sub(1)
+	}
+}
+
+/// A stack word as a memory offset or size, `None` if it is too large for
+/// the instruction to do anything but run out of gas.
+fn memory_word(word: &H256) -> Option<usize> {
+	let word = U256::from_big_endian(word.as_bytes());
+	if word > U256::from(u32::MAX) {
+		return None;
+	}
+	Some(word.as_usize())
+}
+
+/// Log the LOG instruction about to run at `step` emits, `None` if it fails
+/// for its operands.
+fn log_of(step: &Step) -> Option<LogEvent> {
+	let count = step.opcode.checked_sub(0xa0).filter(|count| *count <= 4)? as usize;
+	let mut operands = step.stack.iter().rev();
+	let offset = memory_word(operands.next()?)?;
+	let size = memory_word(operands.next()?)?;
+	let topics = operands.take(count).copied().collect::<Vec<_>>();
+	if topics.len() < count {
+		return None;
+	}
+	let data = (offset..offset + size)
+		.map(|i| step.memory.get(i).copied().unwrap_or(0))
+		.collect();
+	Some(LogEvent {
+		depth: step.depth,
+		address: step.address,
+		topics,
+		data,
+	})
+}
+
+impl<L: Listener> Tracer for Listening<L> {
+	fn evm_event(&mut self, event: Event) {
+		match event {
+			Event::Call {
+				code_address,
+				transfer,
+				input,
+				target_gas,
+				is_static,
+				context,
+			} => {
+				let parent_static = self.frames.last().map_or(false, |frame| frame.is_static);
+				let kind = if context.address != code_address {
+					if transfer.is_some() {
+						CallKind::CallCode
+					} else {
+						CallKind::DelegateCall
+					}
+				} else if is_static && !parent_static {
+					CallKind::StaticCall
+				} else {
+					CallKind::Call
+				};
+				self.enter(Frame {
+					depth: self.frames.len(),
+					kind,
+					from: context.caller,
+					to: code_address,
+					value: context.apparent_value,
+					input: input.to_vec(),
+					gas: target_gas,
+					is_static,
+				});
+			}
+			Event::Create {
+				caller,
+				address,
+				scheme,
+				value,
+				init_code,
+				target_gas,
+			} => {
+				let kind = match scheme {
+					CreateScheme::Create2 { .. } => CallKind::Create2,
+					_ => CallKind::Create,
+				};
+				self.enter(Frame {
+					depth: self.frames.len(),
+					kind,
+					from: caller,
+					to: address,
+					value,
+					input: init_code.to_vec(),
+					gas: target_gas,
+					is_static: false,
+				});
+			}
+			Event::Exit {
+				reason,
+				return_value,
+			} => {
+				let depth = self.depth();
+				if let Some(frame) = self.frames.pop() {
+					if let Some((address, input)) = &frame.precompile {
+						self.listener.precompile(&PrecompileCall {
+							depth,
+							address: *address,
+							input,
+							output: return_value,
+							reason,
+						});
+					}
+					self.listener.exit(&FrameExit {
+						depth,
+						reason,
+						output: return_value,
+					});
+				}
+			}
+			_ => (),
+		}
+	}
+
+	fn runtime_event(&mut self, event: RuntimeEvent) {
+		let depth = self.depth();
+		let frame = match self.frames.last() {
+			Some(frame) => frame,
+			None => return,
+		};
+		match event {
+			RuntimeEvent::Step {
+				opcode,
+				position,
+				stack,
+				memory,
+				..
+			} => {
+				let pc = match position {
+					Ok(pc) => *pc,
+					Err(_) => return,
+				};
+				let step = Step {
+					depth,
+					address: frame.address,
+					pc,
+					opcode: opcode.0,
+					stack: stack.data(),
+					memory: memory.data(),
+				};
+				let log = if frame.is_static { None } else { log_of(&step) };
+				self.listener.step(&step);
+				if let Some(log) = log {
+					self.listener.log(&log);
+				}
+			}
+			RuntimeEvent::SStore {
+				address,
+				index,
+				value,
+			} => self.listener.sstore(&Sstore {
+				depth,
+				address,
+				key: index,
+				value,
+			}),
+			_ => (),
+		}
+	}
+}
+
+/// Execute every index of `test` on the forks the runner supports, in the
+/// order of the fixture, telling `listener` what each does, and hand it
+/// back. Indexes whose sender cannot be recovered are not run.
+pub fn listen<L: Listener + 'static>(test: &Test, mut listener: L) -> L {
+	let setup = test.setup();
+	let vicinity = match &setup.vicinity {
+		Ok(vicinity) => vicinity,
+		Err(_) => return listener,
+	};
+	for (fork, states) in &test.0.post_states {
+		if state::unsupported(fork).is_some() {
+			continue;
+		}
+		let spec = fork_spec(fork).unwrap();
+		let precompiles = match JsonPrecompile::precompile(&spec) {
+			Some(precompiles) => precompiles.into_keys().collect::<Vec<_>>(),
+			None => continue,
+		};
+		for (index, post_state) in states.iter().enumerate() {
+			listener.index(fork, index);
+			let listening = Listening::new(listener, precompiles.iter().copied());
+			let execution = state::execute(
+				test,
+				&spec,
+				&post_state.indexes,
+				vicinity,
+				&setup.pre_state,
+				listening,
+			)
+			.unwrap();
+			listener = execution.tracer.into_listener();
+		}
+	}
+	listener
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::state::{transact, Transaction};
+	use evm::backend::{MemoryAccount, MemoryVicinity};
+	use std::borrow::Cow;
+	use std::collections::BTreeMap;
+
+	#[derive(Default)]
+	struct Recorder {
+		steps: usize,
+		frames: Vec<(usize, CallKind)>,
+		exits: usize,
+		sstores: Vec<Sstore>,
+		logs: Vec<LogEvent>,
+		precompiles: Vec<(H160, Vec<u8>)>,
+	}
+
+	impl Listener for Recorder {
+		fn step(&mut self, _step: &Step) {
+			self.steps += 1;
+		}
+
+		fn enter(&mut self, frame: &Frame) {
+			self.frames.push((frame.depth, frame.kind));
+		}
+
+		fn exit(&mut self, _exit: &FrameExit) {
+			self.exits += 1;
+		}
+
+		fn sstore(&mut self, sstore: &Sstore) {
+			self.sstores.push(*sstore);
+		}
+
+		fn log(&mut self, log: &LogEvent) {
+			self.logs.push(log.clone());
+		}
+
+		fn precompile(&mut self, call: &PrecompileCall) {
+			self.precompiles.push((call.address, call.output.to_vec()));
+		}
+	}
+
+	#[test]
+	fn events_of_a_call() {
+		let contract = H160::from_low_u64_be(0x1000);
+		// PUSH1 0x2a PUSH1 0 SSTORE, PUSH1 0xff PUSH1 0 MSTORE8,
+		// PUSH1 7 PUSH1 1 PUSH1 0 LOG1, then STATICCALL of identity on the
+		// byte and STOP.
+		let code = hex::decode(
+			"602a600055\
+			 60ff600053\
+			 600760016000a1\
+			 600160006001600060045afa00",
+		)
+		.unwrap();
+		let mut state = BTreeMap::new();
+		state.insert(
+			contract,
+			MemoryAccount {
+				nonce: U256::one(),
+				balance: U256::zero(),
+				storage: BTreeMap::new(),
+				code,
+			},
+		);
+		let caller = H160::from_low_u64_be(0x2000);
+		let vicinity = MemoryVicinity {
+			gas_price: U256::zero(),
+			origin: caller,
+			block_hashes: Vec::new(),
+			block_number: U256::zero(),
+			block_coinbase: H160::zero(),
+			block_timestamp: U256::zero(),
+			block_difficulty: U256::zero(),
+			block_gas_limit: U256::from(1_000_000),
+			chain_id: U256::one(),
+		};
+		let transaction = Transaction {
+			caller,
+			to: Some(contract),
+			value: U256::zero(),
+			data: Vec::new(),
+			gas_limit: 1_000_000,
+			access_list: Vec::new(),
+			pricing: None,
+		};
+		let spec = fork_spec("Berlin").unwrap();
+		let precompiles = JsonPrecompile::precompile(&spec).unwrap();
+		let listening = Listening::new(Recorder::default(), precompiles.into_keys());
+		let execution =
+			transact(&spec, &vicinity, Cow::Owned(state), &transaction, listening).unwrap();
+		assert!(execution.exit_reason.is_succeed());
+
+		let recorder = execution.tracer.into_listener();
+		assert_eq!(
+			recorder.frames,
+			vec![(0, CallKind::Call), (1, CallKind::StaticCall)]
+		);
+		assert_eq!(recorder.exits, 2);
+		assert_eq!(recorder.steps, 18);
+		assert_eq!(
+			recorder.sstores,
+			vec![Sstore {
+				depth: 0,
+				address: contract,
+				key: H256::zero(),
+				value: H256::from_low_u64_be(0x2a),
+			}]
+		);
+		assert_eq!(
+			recorder.logs,
+			vec![LogEvent {
+				depth: 0,
+				address: contract,
+				topics: vec![H256::from_low_u64_be(7)],
+				data: vec![0xff],
+			}]
+		);
+		assert_eq!(
+			recorder.precompiles,
+			vec![(H160::from_low_u64_be(4), vec![0xff])]
+		);
+	}
+}
@@ -0,0 +1,179 @@
+use ethbloom::{Bloom, Input};
+use evm::backend::Log;
+use primitive_types::H256;
+use sha3::{Digest, Keccak256};
+use std::fmt;
+
+/// Bloom bit positions set by a single input, following the m3:2048 scheme
+/// of the yellow paper.
+pub fn bloom_bits(input: &[u8]) -> [usize; 3] {
+	let hash = Keccak256::digest(input);
+	let mut bits = [0usize; 3];
+	for (i, bit) in bits.iter_mut().enumerate() {
+		*bit = (((hash[2 * i] as usize) << 8) | hash[2 * i + 1] as usize) & 2047;
+	}
+	bits
+}
+
+pub fn bloom_has_bit(bloom: &Bloom, bit: usize) -> bool {
+	bloom.as_bytes()[255 - bit / 8] & (1 << (bit % 8)) != 0
+}
+
+pub fn logs_bloom(logs: &[Log]) -> Bloom {
+	let mut bloom = Bloom::default();
+	for log in logs {
+		bloom.accrue(Input::Raw(log.address.as_bytes()));
+		for topic in &log.topics {
+			bloom.accrue(Input::Raw(topic.as_bytes()));
+		}
+	}
+	bloom
+}
+
+pub fn logs_hash(logs: &[Log]) -> H256 {
+	let mut stream = rlp::RlpStream::new_list(logs.len());
+	for log in logs {
+		stream.begin_list(3);
+		stream.append(&log.address);
+		stream.begin_list(log.topics.len());
+		for topic in &log.topics {
+			stream.append(topic);
+		}
+		stream.append(&log.data);
+	}
+	H256::from_slice(Keccak256::digest(&stream.out()).as_slice())
+}
+
+/// Origin of a bloom bit among the emitted logs.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum BloomSource {
+	Address { log: usize },
+	Topic { log: usize, topic: usize },
+}
+
+impl fmt::Display for BloomSource {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			BloomSource::Address { log } => write!(f, "address of log {}", log),
+			BloomSource::Topic { log, topic } => write!(f, "topic {} of log {}", topic, log),
+		}
+	}
+}
+
+/// A bit that is set in exactly one of the expected and calculated blooms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BloomBitDiff {
+	pub bit: usize,
+	/// Whether the bit is set in the calculated bloom (and thus missing from
+	/// the expected one).
+	pub unexpected: bool,
+	/// Emitted log inputs setting this bit.
+	pub sources: Vec<BloomSource>,
+}
+
+pub fn bloom_diff(expected: &Bloom, logs: &[Log]) -> Vec<BloomBitDiff> {
+	let actual = logs_bloom(logs);
+	let mut sources = vec![Vec::new(); 2048];
+	for (i, log) in logs.iter().enumerate() {
+		for bit in bloom_bits(log.address.as_bytes()).iter() {
+			sources[*bit].push(BloomSource::Address { log: i });
+		}
+		for (j, topic) in log.topics.iter().enumerate() {
+			for bit in bloom_bits(topic.as_bytes()).iter() {
+				sources[*bit].push(BloomSource::Topic { log: i, topic: j });
+			}
+		}
+	}
+
+	(0..2048)
+		.filter(|bit| bloom_has_bit(expected, *bit) != bloom_has_bit(&actual, *bit))
+		.map(|bit| BloomBitDiff {
+			bit,
+			unexpected: bloom_has_bit(&actual, bit),
+			sources: sources[bit].clone(),
+		})
+		.collect()
+}
+
+pub fn assert_valid_bloom(expected: &Bloom, logs: &[Log]) {
+	let diff = bloom_diff(expected, logs);
+	if diff.is_empty() {
+		return;
+	}
+
+	let mut message = format!(
+		"Bloom not equal; calculated: {:?}, expect: {:?}\n",
+		logs_bloom(logs),
+		expected
+	);
+	for d in diff {
+		if d.unexpected {
+			let sources = d
+				.sources
+				.iter()
+				.map(|s| s.to_string())
+				.collect::<Vec<_>>()
+				.join(", ");
+			message.push_str(&format!("  bit {} unexpected, set by {}\n", d.bit, sources));
+		} else {
+			message.push_str(&format!("  bit {} expected, not set by any log\n", d.bit));
+		}
+	}
+	panic!("{}Logs: {:#x?}", message, logs);
+}
+
+pub fn assert_valid_logs_hash(expected: &H256, logs: &[Log]) {
+	let hash = logs_hash(logs);
+	if hash != *expected {
+		panic!(
+			"Logs hash not equal; calculated: {:?}, expect: {:?}\nLogs: {:#x?}",
+			hash, expected, logs
+		);
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use primitive_types::H160;
+	use std::str::FromStr;
+
+	fn log() -> Log {
+		Log {
+			address: H160::from_str("ef2d6d194084c2de36e0dabfce45d046b37d1106").unwrap(),
+			topics: vec![H256::from_str(
+				"02c69be41d0b7e40352fc85be1cd65eb03d40ef8427a0ca4596b1ead9a00e9fc",
+			)
+			.unwrap()],
+			data: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn empty_logs_hash() {
+		assert_eq!(
+			logs_hash(&[]),
+			H256::from_str("1dcc4de8dec75d7aab85b567b6ccd41ad312451b948a7413f0a142fd40d49347")
+				.unwrap()
+		);
+	}
+
+	#[test]
+	fn bloom_bits_match_accrue() {
+		let logs = vec![log()];
+		let bloom = logs_bloom(&logs);
+		for bit in bloom_bits(logs[0].address.as_bytes()).iter() {
+			assert!(bloom_has_bit(&bloom, *bit));
+		}
+		assert!(bloom.contains_input(Input::Raw(logs[0].topics[0].as_bytes())));
+	}
+
+	#[test]
+	fn bloom_diff_attributes_bits() {
+		let logs = vec![log()];
+		let diff = bloom_diff(&Bloom::default(), &logs);
+		assert!(!diff.is_empty());
+		assert!(diff.iter().all(|d| d.unexpected && !d.sources.is_empty()));
+		assert!(bloom_diff(&logs_bloom(&logs), &logs).is_empty());
+	}
+}
@@ -1,58 +1,3247 @@
-use clap::{App, Arg, SubCommand};
+use clap::{App, Arg, ArgMatches, SubCommand};
+use ethjson::spec::ForkSpec;
+use evm_jsontests::archive;
+use evm_jsontests::artifact::Artifact;
+use evm_jsontests::b11r;
+use evm_jsontests::batch::Selection;
+use evm_jsontests::bisect::Bisect;
+use evm_jsontests::blockchain as blockchaintests;
+use evm_jsontests::boundaries;
+use evm_jsontests::builtins;
+use evm_jsontests::capture;
+use evm_jsontests::chainspec::{self, ChainSpec};
+use evm_jsontests::compare;
+use evm_jsontests::config::{self, Config};
+use evm_jsontests::console::{self, FileReport, Status};
+use evm_jsontests::corpus;
+use evm_jsontests::crash;
+use evm_jsontests::debugger;
+use evm_jsontests::disasm;
+use evm_jsontests::embed;
+use evm_jsontests::encoding;
+use evm_jsontests::exceptions::{self, Exceptions};
+use evm_jsontests::exec;
+use evm_jsontests::explain;
+use evm_jsontests::filler;
+use evm_jsontests::fixture::FillerInfo;
+use evm_jsontests::fixtures::{self, json_files};
+use evm_jsontests::format::{self, Format};
+use evm_jsontests::gethalloc;
+use evm_jsontests::heavy;
+use evm_jsontests::html;
+use evm_jsontests::impls::{self, EvmImpl};
+use evm_jsontests::kzg;
+use evm_jsontests::manifest::Manifest;
+use evm_jsontests::matrix::{self, Matrix};
+use evm_jsontests::metrics;
+use evm_jsontests::minimize;
+use evm_jsontests::order::{random_seed, report_seed_on_panic, Rng};
+use evm_jsontests::params;
+use evm_jsontests::precompiles;
+use evm_jsontests::profile::{profiled, Per, Profile};
+use evm_jsontests::progress::Progress;
+use evm_jsontests::report;
+use evm_jsontests::resources;
+use evm_jsontests::retesteth::Retesteth;
+use evm_jsontests::rpc;
+use evm_jsontests::sarif::{self, Finding};
+use evm_jsontests::schedule::{parse_duration, History, Priority};
+use evm_jsontests::schema;
+use evm_jsontests::service::Service;
+use evm_jsontests::smoke;
+use evm_jsontests::soak::{self, Samples};
 use evm_jsontests::state as statetests;
+use evm_jsontests::stats;
+use evm_jsontests::structlog::TraceFormat;
+use evm_jsontests::summary::{Outcome, Summary, EXIT_FAILURES};
+use evm_jsontests::synthetic;
+use evm_jsontests::tags;
+use evm_jsontests::testindex::{self, TestIndex};
+use evm_jsontests::timings::{self, Timings};
+use evm_jsontests::trace;
+use evm_jsontests::tracediff;
+use evm_jsontests::validators;
+use evm_jsontests::validity::INITCODE_LIMIT_FORKS;
+use evm_jsontests::vectors;
 use evm_jsontests::vm as vmtests;
-use std::collections::HashMap;
-use std::fs::File;
-use std::io::BufReader;
+use evm_jsontests::watch::watch;
+use primitive_types::U256;
+use rayon::prelude::*;
+use regex::Regex;
+use serde::de::{DeserializeOwned, IgnoredAny};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::{Mutex, Once};
+use std::thread;
+use std::time::{Duration, Instant};
 
-fn main() {
-	let matches = App::new("jsontests")
-		.version("0.1.0")
-		.author("Wei Tang <hi@that.world>")
-		.about("EVM json test utilities")
-		.subcommand(
-			SubCommand::with_name("vm").arg(
-				Arg::with_name("FILE")
-					.help("Target yaml file to import")
-					.required(true)
-					.min_values(1),
-			),
-		)
-		.subcommand(
-			SubCommand::with_name("state").arg(
-				Arg::with_name("FILE")
-					.help("Target yaml file to import")
-					.required(true)
-					.min_values(1),
-			),
-		)
-		.get_matches();
-
-	if let Some(matches) = matches.subcommand_matches("vm") {
-		for file_name in matches.values_of("FILE").unwrap() {
-			let file = File::open(file_name).expect("Open file failed");
-
-			let reader = BufReader::new(file);
-			let coll = serde_json::from_reader::<_, HashMap<String, vmtests::Test>>(reader)
-				.expect("Parse test cases failed");
-
-			for (name, test) in coll {
-				vmtests::test(&name, test);
+fn shuffle_arg() -> Arg<'static, 'static> {
+	Arg::with_name("shuffle")
+		.long("shuffle")
+		.value_name("SEED")
+		.help("Run files and tests in a random order, optionally with a fixed seed")
+		.takes_value(true)
+		.min_values(0)
+}
+
+fn time_budget_arg() -> Arg<'static, 'static> {
+	Arg::with_name("time-budget")
+		.long("time-budget")
+		.value_name("DURATION")
+		.help("Stop starting tests after DURATION, e.g. 90s, 10m or 1h")
+		.takes_value(true)
+}
+
+/// Shuffle generator requested on the command line, if any.
+fn shuffle_rng(matches: &ArgMatches) -> Option<Rng> {
+	if !matches.is_present("shuffle") {
+		return None;
+	}
+	let seed = match matches.value_of("shuffle") {
+		Some(seed) => seed.parse().expect("Invalid shuffle seed"),
+		None => random_seed(),
+	};
+	println!("Shuffle seed: {}", seed);
+	report_seed_on_panic(seed);
+	Some(Rng::new(seed))
+}
+
+/// Tests of a single fixture file, sorted by name.
+fn read_file<T: DeserializeOwned>(file_name: &Path) -> Result<Vec<(String, T)>, String> {
+	let contents = fixtures::read(file_name).map_err(|err| err.to_string())?;
+	let coll =
+		serde_json::from_slice::<BTreeMap<String, T>>(&contents).map_err(|err| err.to_string())?;
+
+	Ok(coll.into_iter().collect())
+}
+
+fn load_file<T: DeserializeOwned, P: AsRef<Path>>(file_name: P) -> Vec<(String, T)> {
+	let file_name = file_name.as_ref();
+	read_file(file_name)
+		.unwrap_or_else(|err| panic!("Load {} failed: {}", file_name.display(), err))
+}
+
+/// Tests of `file`, only the test `name` if given and an index of the
+/// directory of `file` says where it is.
+fn load_test<T: DeserializeOwned>(file: &str, name: Option<&str>) -> Vec<(String, T)> {
+	let indexed = name.and_then(|name| testindex::read_test(Path::new(file), name));
+	match indexed {
+		Some(contents) => serde_json::from_slice::<BTreeMap<String, T>>(&contents)
+			.unwrap_or_else(|err| panic!("Load {} failed: {}", file, err))
+			.into_iter()
+			.collect(),
+		None => load_file(file),
+	}
+}
+
+fn archive_filter_arg() -> Arg<'static, 'static> {
+	Arg::with_name("archive-filter")
+		.long("archive-filter")
+		.value_name("REGEX")
+		.help("Only run archive entries whose internal path matches REGEX")
+		.takes_value(true)
+}
+
+fn skip_arg() -> Arg<'static, 'static> {
+	Arg::with_name("skip")
+		.long("skip")
+		.value_name("NAME")
+		.help("Do not run the test NAME, may be repeated")
+		.takes_value(true)
+		.multiple(true)
+		.number_of_values(1)
+}
+
+fn skip_pattern_arg() -> Arg<'static, 'static> {
+	Arg::with_name("skip-pattern")
+		.long("skip-pattern")
+		.value_name("REGEX")
+		.help(
+			"Do not run tests whose `file:test` id, or indexes whose `file:test:fork:index` \
+			 id matches REGEX, may be repeated",
+		)
+		.takes_value(true)
+		.multiple(true)
+		.number_of_values(1)
+}
+
+fn tag_arg() -> Arg<'static, 'static> {
+	Arg::with_name("tag")
+		.long("tag")
+		.value_name("TAG")
+		.help("Only run tests tagged TAG (precompiles, create, sstore or calls), may be repeated")
+		.takes_value(true)
+		.multiple(true)
+		.number_of_values(1)
+}
+
+fn test_filter_arg() -> Arg<'static, 'static> {
+	Arg::with_name("test")
+		.long("test")
+		.value_name("NAME")
+		.help(
+			"Only run the test NAME, may be repeated. With it or --fork, fixture directories \
+			 are indexed to only read the files with selected tests",
+		)
+		.takes_value(true)
+		.multiple(true)
+		.number_of_values(1)
+}
+
+/// Tests and forks given with `--test` and `--fork`.
+fn index_filter(matches: &ArgMatches) -> testindex::Filter {
+	let values = |name| {
+		matches
+			.values_of(name)
+			.into_iter()
+			.flatten()
+			.map(String::from)
+			.collect()
+	};
+	testindex::Filter {
+		tests: values("test"),
+		forks: values("fork"),
+	}
+}
+
+/// Tags given with `--tag`.
+fn selected_tags(matches: &ArgMatches) -> Vec<&'static str> {
+	let known = tags::names();
+	matches
+		.values_of("tag")
+		.into_iter()
+		.flatten()
+		.map(|tag| {
+			*known
+				.iter()
+				.find(|known| **known == tag)
+				.unwrap_or_else(|| panic!("Unknown tag {}, expected one of {:?}", tag, known))
+		})
+		.collect()
+}
+
+/// `--profile` flags, when built with the `pprof` feature.
+fn profile_args() -> Vec<Arg<'static, 'static>> {
+	if !cfg!(feature = "pprof") {
+		return Vec::new();
+	}
+	vec![
+		Arg::with_name("profile")
+			.long("profile")
+			.value_name("DIR")
+			.help("Write CPU flamegraphs of the run into DIR, running one file at a time")
+			.takes_value(true),
+		Arg::with_name("profile-per")
+			.long("profile-per")
+			.value_name("UNIT")
+			.help("Write a flamegraph per test or per suite")
+			.takes_value(true)
+			.possible_values(&["test", "suite"])
+			.default_value("test")
+			.requires("profile"),
+	]
+}
+
+/// Flamegraphs requested with `--profile`.
+fn profile(matches: &ArgMatches) -> Option<Profile> {
+	matches.value_of("profile").map(|dir| Profile {
+		dir: PathBuf::from(dir),
+		per: match matches.value_of("profile-per") {
+			Some("suite") => Per::Suite,
+			_ => Per::Test,
+		},
+	})
+}
+
+/// Patterns given with `--skip-pattern`.
+fn skip_patterns(matches: &ArgMatches) -> Vec<Regex> {
+	matches
+		.values_of("skip-pattern")
+		.into_iter()
+		.flatten()
+		.map(|pattern| Regex::new(pattern).expect("Invalid skip pattern"))
+		.collect()
+}
+
+fn fork_filter_arg() -> Arg<'static, 'static> {
+	Arg::with_name("fork")
+		.long("fork")
+		.value_name("FORK")
+		.help("Only run FORK, may be repeated")
+		.takes_value(true)
+		.multiple(true)
+		.number_of_values(1)
+}
+
+fn jobs_arg() -> Arg<'static, 'static> {
+	Arg::with_name("jobs")
+		.long("jobs")
+		.short("j")
+		.value_name("N")
+		.help("Number of fixture files run in parallel")
+		.takes_value(true)
+}
+
+fn heavy_jobs_arg() -> Arg<'static, 'static> {
+	Arg::with_name("heavy-jobs")
+		.long("heavy-jobs")
+		.value_name("N")
+		.help(
+			"Number of fixture files of memory heavy suites, such as stQuadraticComplexity, \
+			 run in parallel [default: 1]",
+		)
+		.takes_value(true)
+}
+
+fn metrics_addr_arg() -> Arg<'static, 'static> {
+	Arg::with_name("metrics-addr")
+		.long("metrics-addr")
+		.value_name("ADDR")
+		.help(
+			"Serve progress, pass and fail counts, gas per second and suite durations of the \
+			 run as Prometheus metrics on ADDR, e.g. 0.0.0.0:9100",
+		)
+		.takes_value(true)
+}
+
+fn nocapture_arg() -> Arg<'static, 'static> {
+	Arg::with_name("nocapture")
+		.long("nocapture")
+		.help("Print the output of every test as it runs, not only of failing ones")
+}
+
+fn artifacts_dir_arg() -> Arg<'static, 'static> {
+	Arg::with_name("artifacts-dir")
+		.long("artifacts-dir")
+		.value_name("DIR")
+		.help(
+			"Bundle the fixture, output, state diff and trace of every failing test into \
+			 DIR, to be run again with `reproduce`",
+		)
+		.takes_value(true)
+}
+
+fn disassemble_arg() -> Arg<'static, 'static> {
+	Arg::with_name("disassemble")
+		.long("disassemble")
+		.requires("artifacts-dir")
+		.help(
+			"Add the disassembly of the code a failing test runs, with its jump \
+			 destinations, to its artifact",
+		)
+}
+
+fn isolate_arg() -> Arg<'static, 'static> {
+	Arg::with_name("isolate")
+		.long("isolate")
+		.value_name("MODE")
+		.help(
+			"Run every test in a child process, so that a crash fails the test with its \
+			 signal instead of ending the run",
+		)
+		.takes_value(true)
+		.possible_values(&["process"])
+}
+
+/// Result of running a test, failures with the message they panicked with.
+#[derive(Serialize, Deserialize)]
+enum TestResult {
+	Passed(Outcome),
+	Failed(String),
+	/// Stopped by the step limit of `--max-steps`.
+	StepLimited(String),
+}
+
+impl From<thread::Result<Outcome>> for TestResult {
+	fn from(result: thread::Result<Outcome>) -> Self {
+		match result {
+			Ok(outcome) => TestResult::Passed(outcome),
+			Err(payload) => {
+				let message = console::failure_message(&*payload);
+				if trace::is_step_limit(&*payload) {
+					TestResult::StepLimited(message)
+				} else {
+					TestResult::Failed(message)
+				}
+			}
+		}
+	}
+}
+
+/// Run `run` in a child process, failing the test if the child crashes.
+#[cfg(unix)]
+fn run_isolated<F>(run: F) -> (TestResult, String, bool)
+where
+	F: FnOnce() -> (TestResult, String),
+{
+	match evm_jsontests::isolate::isolated(run) {
+		Ok((result, output)) => (result, output, false),
+		Err(crash) => (
+			TestResult::Failed(format!("crashed: {}", crash)),
+			format!("Crashed: {}\n", crash),
+			true,
+		),
+	}
+}
+
+#[cfg(not(unix))]
+fn run_isolated<F>(_run: F) -> (TestResult, String, bool)
+where
+	F: FnOnce() -> (TestResult, String),
+{
+	panic!("--isolate process is only supported on Unix");
+}
+
+fn max_steps_arg() -> Arg<'static, 'static> {
+	Arg::with_name("max-steps")
+		.long("max-steps")
+		.value_name("N")
+		.help(
+			"Fail transactions taking more than N interpreter steps, stopping where they \
+			 stop on every machine unlike --timeout",
+		)
+		.takes_value(true)
+}
+
+fn strict_schema_arg() -> Arg<'static, 'static> {
+	Arg::with_name("strict-schema").long("strict-schema").help(
+		"Fail to load fixtures with fields the runner does not know, instead of ignoring \
+			 them",
+	)
+}
+
+fn sarif_arg() -> Arg<'static, 'static> {
+	Arg::with_name("sarif")
+		.long("sarif")
+		.value_name("FILE")
+		.help("Write the failing tests as a SARIF log into FILE, for code scanning dashboards")
+		.takes_value(true)
+}
+
+fn allow_failed_skips_arg() -> Arg<'static, 'static> {
+	Arg::with_name("allow-failed-skips")
+		.long("allow-failed-skips")
+		.help("Do not fail the run because of forks the runner does not support")
+}
+
+fn timeout_arg() -> Arg<'static, 'static> {
+	Arg::with_name("timeout")
+		.long("timeout")
+		.value_name("SECS")
+		.help("Fail tests that run longer than SECS seconds")
+		.takes_value(true)
+}
+
+fn vm_options(matches: &ArgMatches, progress: bool) -> vmtests::Options {
+	vmtests::Options {
+		diff: matches.is_present("diff"),
+		quiet: progress,
+	}
+}
+
+/// Indexes `--tests-from` lists and `--failed-from` failed, both if both
+/// are given.
+fn selection(matches: &ArgMatches) -> Option<Selection> {
+	let listed = matches
+		.value_of("tests-from")
+		.map(|path| Selection::read(Path::new(path)).unwrap_or_else(|err| fail(err)));
+	let failed = matches.value_of("failed-from").map(|path| {
+		let reports =
+			report::load(Path::new(path)).unwrap_or_else(|err| fail(format!("{}: {}", path, err)));
+		Selection::failed(&reports)
+	});
+	match (listed, failed) {
+		(Some(listed), Some(failed)) => Some(listed.intersect(&failed)),
+		(listed, failed) => listed.or(failed),
+	}
+}
+
+/// Options of the state runner given by the flags of `matches`.
+fn state_options(
+	matches: &ArgMatches,
+	config: &Config,
+	progress: bool,
+	json_report: Option<PathBuf>,
+) -> statetests::Options {
+	let evm_impl = matches
+		.value_of("compare-impl")
+		.map_or_else(EvmImpl::default, |implementation| {
+			implementation.parse().unwrap()
+		});
+	impls::check(evm_impl).unwrap_or_else(|err| fail(err));
+	statetests::Options {
+		dump_state: path_or(matches, "dump-state", &config.dump_state),
+		diff: matches.is_present("diff"),
+		provenance: matches.is_present("provenance"),
+		verbose: matches.is_present("verbose"),
+		json_report,
+		timings: matches.value_of("record-timings").map(PathBuf::from),
+		tracer: matches
+			.value_of("tracer")
+			.map(|tracer| tracer.parse().unwrap()),
+		trace_dir: path_or(matches, "trace-dir", &config.trace_dir),
+		forks: executable_forks(matches, config, evm_impl),
+		labels: values_or(matches, "label", &[]),
+		indexes: Vec::new(),
+		skip_patterns: skip_patterns(matches),
+		selection: selection(matches),
+		timeout: timeout(matches, config),
+		quiet: progress,
+		carry_state: matches.is_present("carry-state"),
+		determinism: matches.is_present("determinism"),
+		fork_consistency: matches.is_present("fork-consistency"),
+		diagnose_failures: matches.is_present("artifacts-dir"),
+		ignore_post_state: false,
+		evm_impl,
+		repeat: matches.value_of("repeat").map(|repeat| {
+			repeat
+				.parse()
+				.ok()
+				.filter(|repeat| *repeat > 0)
+				.expect("Invalid number of repetitions")
+		}),
+		base_fee: matches
+			.value_of("base-fee")
+			.map(|base_fee| U256::from_dec_str(base_fee).expect("Invalid base fee"))
+			.or_else(|| config.base_fee.map(U256::from)),
+		env_overrides: matches
+			.values_of("override-env")
+			.map_or_else(Vec::new, |values| {
+				values
+					.map(|value| value.parse().unwrap_or_else(|err| fail(err)))
+					.collect()
+			}),
+		perturb: matches
+			.value_of("perturb")
+			.map(|seed| seed.parse().expect("Invalid perturbation seed")),
+		stress: matches.value_of("stress").map(|threads| {
+			threads
+				.parse()
+				.ok()
+				.filter(|threads| *threads > 1)
+				.expect("Invalid number of stress threads")
+		}),
+		reference_t8n: matches.value_of("reference-t8n").map(PathBuf::from),
+	}
+}
+
+fn blockchain_options(
+	matches: &ArgMatches,
+	config: &Config,
+	progress: bool,
+) -> blockchaintests::Options {
+	blockchaintests::Options {
+		forks: executable_forks(matches, config, EvmImpl::Old),
+		timeout: timeout(matches, config),
+		quiet: progress,
+	}
+}
+
+/// Values of a repeatable flag, or the configured ones if it is not given.
+fn values_or(matches: &ArgMatches, name: &str, configured: &[String]) -> Vec<String> {
+	match matches.values_of(name) {
+		Some(values) => values.map(String::from).collect(),
+		None => configured.to_vec(),
+	}
+}
+
+/// Forks to run, narrowed to those the runner executes or checks the init
+/// code rules of. The others are reported, and failing if none is left.
+/// Forks of `--fork` or the config `evm_impl` can run.
+fn executable_forks(matches: &ArgMatches, config: &Config, evm_impl: EvmImpl) -> Vec<String> {
+	let forks = values_or(matches, "fork", &config.forks);
+	let (executable, unsupported): (Vec<_>, Vec<_>) = forks.into_iter().partition(|fork| {
+		impls::unsupported(fork, evm_impl).is_none()
+			|| INITCODE_LIMIT_FORKS.contains(&fork.as_str())
+	});
+	for fork in &unsupported {
+		eprintln!(
+			"Not running {}",
+			impls::unsupported(fork, evm_impl).unwrap()
+		);
+	}
+	if executable.is_empty() && !unsupported.is_empty() {
+		fail("None of the selected forks can be run".to_string());
+	}
+	executable
+}
+
+fn path_or(matches: &ArgMatches, name: &str, configured: &Option<PathBuf>) -> Option<PathBuf> {
+	matches
+		.value_of(name)
+		.map(PathBuf::from)
+		.or_else(|| configured.clone())
+}
+
+fn timeout(matches: &ArgMatches, config: &Config) -> Option<Duration> {
+	matches
+		.value_of("timeout")
+		.map(|timeout| timeout.parse().expect("Invalid timeout"))
+		.or(config.timeout)
+		.map(Duration::from_secs)
+}
+
+/// Fixture files given on the command line, or the suite's configured paths,
+/// with directories expanded, to the files the index of the directory says
+/// have tests of `--test` and `--fork` if either is given.
+fn fixture_files(matches: &ArgMatches, suite: &config::Suite) -> Vec<PathBuf> {
+	let paths = match matches.values_of("FILE") {
+		Some(files) => files.map(PathBuf::from).collect(),
+		None => suite.paths.clone(),
+	};
+	if paths.is_empty() {
+		panic!(
+			"No fixture files given and no paths configured in {}",
+			config::FILE_NAME
+		);
+	}
+
+	let filter = index_filter(matches);
+	paths
+		.into_iter()
+		.flat_map(|path| {
+			if path.is_dir() && !filter.is_empty() {
+				TestIndex::open(&path).files(&path, &filter)
+			} else if path.is_dir() {
+				json_files(&path)
+			} else {
+				vec![path]
+			}
+		})
+		.collect()
+}
+
+/// Run `f` on the tests of every file of `files`, in command-line order with each
+/// file's tests sorted by name, or in random order when shuffling. Fixture
+/// archives are streamed entry by entry in archive order. With more than one
+/// job, files are run in parallel and their output interleaves.
+///
+/// A test fails by panicking; failures and unparsable files are counted
+/// and the run carries on.
+fn for_each_test<T, F>(
+	matches: &ArgMatches,
+	kind: &str,
+	config: &Config,
+	mut files: Vec<PathBuf>,
+	progress: bool,
+	history: Option<&History>,
+	f: F,
+) -> Summary
+where
+	T: DeserializeOwned,
+	F: Fn(&str, String, T) -> Outcome + Sync,
+{
+	let mut rng = shuffle_rng(matches);
+	let filter = matches
+		.value_of("archive-filter")
+		.map(|filter| Regex::new(filter).expect("Invalid archive filter"));
+	let skip = values_or(matches, "skip", &config.skip);
+	let skip_patterns = skip_patterns(matches);
+	let tags = selected_tags(matches);
+	let selected = index_filter(matches).tests;
+	let artifacts_dir = matches.value_of("artifacts-dir").map(PathBuf::from);
+	let disassemble = matches.is_present("disassemble");
+	let isolate = matches.value_of("isolate") == Some("process");
+	let strict = matches.is_present("strict-schema");
+	let sarif = matches.is_present("sarif");
+	trace::set_max_steps(
+		matches
+			.value_of("max-steps")
+			.map(|steps| steps.parse().expect("Invalid number of steps")),
+	);
+	let jobs = matches
+		.value_of("jobs")
+		.map(|jobs| jobs.parse().expect("Invalid number of jobs"))
+		.or(config.jobs)
+		.unwrap_or(1);
+	// Samples of tests running in parallel would end up in each other's
+	// flamegraph.
+	let profile = profile(matches);
+	let jobs = if profile.is_some() { 1 } else { jobs };
+	let heavy = heavy::Limit::new(
+		matches
+			.value_of("heavy-jobs")
+			.map(|jobs| jobs.parse().expect("Invalid number of heavy jobs"))
+			.or(config.heavy_jobs)
+			.unwrap_or(1),
+	);
+
+	let deadline = matches
+		.value_of("time-budget")
+		.map(|budget| Instant::now() + parse_duration(budget).expect("Invalid time budget"));
+
+	if let Some(rng) = &mut rng {
+		rng.shuffle(&mut files);
+	}
+	if let Some(history) = history {
+		files.sort_by_cached_key(|file| file_priority(history, file));
+	}
+	// Every file shuffles its tests with its own generator, so the order of
+	// a seed does not depend on the number of jobs.
+	let files = files
+		.into_iter()
+		.map(|file| (file, rng.as_mut().map(|rng| Rng::new(rng.next_u64()))))
+		.collect::<Vec<_>>();
+
+	let capture = !matches.is_present("nocapture") && !prints_reports(matches);
+	let metrics = matches.value_of("metrics-addr").map(|addr| {
+		serve_metrics(addr);
+		metrics::global()
+	});
+	let total = if progress || metrics.is_some() {
+		count_tests(&files, filter.as_ref())
+	} else {
+		0
+	};
+	if let Some(metrics) = metrics {
+		metrics.start(total);
+	}
+	let progress = if progress {
+		Some(Progress::new(total))
+	} else {
+		None
+	};
+	let summary = Mutex::new(Summary::default());
+	let parse_error = |path: &dyn std::fmt::Display, err: &dyn std::fmt::Display| {
+		eprintln!("Parse test cases in {} failed: {}", path, err);
+		summary.lock().unwrap().parse_errors += 1;
+	};
+	let run_tests = |file: &str,
+	                 contents: &[u8],
+	                 mut coll: Vec<(String, T)>,
+	                 rng: &mut Option<Rng>| {
+		if let Some(rng) = rng {
+			rng.shuffle(&mut coll);
+		}
+		if let Some(history) = history {
+			history.prioritize(&mut coll);
+		}
+		let suite = fixtures::suite(file);
+		let mut report = FileReport::new(file);
+		for (name, test) in coll {
+			if deadline.map_or(false, |deadline| Instant::now() >= deadline) {
+				if let Some(progress) = &progress {
+					progress.inc(false);
+				}
+				summary.lock().unwrap().out_of_time += 1;
+				continue;
+			}
+			let id = format!("{}:{}", file, name);
+			let tagged = tags.is_empty()
+				|| tags::tags_of(suite.as_deref(), &name)
+					.iter()
+					.any(|tag| tags.contains(tag));
+			if !tagged || !(selected.is_empty() || selected.contains(&name)) {
+				if let Some(progress) = &progress {
+					progress.inc(false);
+				}
+				continue;
+			}
+			if skip.contains(&name) || skip_patterns.iter().any(|pattern| pattern.is_match(&id)) {
+				if let Some(metrics) = metrics {
+					metrics.test_skipped();
+				}
+				match &progress {
+					Some(progress) => progress.inc(false),
+					None => report.push(Status::Skipped, &name, ""),
+				}
+				continue;
+			}
+			let run = || {
+				let run = || {
+					profiled(profile.as_ref(), Per::Test, &name, || {
+						panic::catch_unwind(AssertUnwindSafe(|| f(file, name.clone(), test))).into()
+					})
+				};
+				if capture {
+					capture::capture(run)
+				} else {
+					(run(), String::new())
+				}
+			};
+			let started = Instant::now();
+			let (result, output, crashed) = if isolate {
+				run_isolated(run)
+			} else {
+				let (result, output) = run();
+				(result, output, false)
+			};
+			if let Some(metrics) = metrics {
+				let passed = matches!(result, TestResult::Passed(_));
+				metrics.test_done(suite.as_deref(), passed, started.elapsed());
+			}
+			let step_limited = matches!(result, TestResult::StepLimited(_));
+			let (result, failure) = match result {
+				TestResult::Passed(outcome) => (Some(outcome), None),
+				TestResult::Failed(message) | TestResult::StepLimited(message) => {
+					(None, Some(message))
+				}
+			};
+			let fixed = result.is_some()
+				&& history.map_or(false, |history| history.priority(&name) == Priority::Failed);
+			let status = match (&result, fixed) {
+				(None, _) => Status::Failed,
+				(Some(_), true) => Status::Fixed,
+				(Some(_), false) => Status::Passed,
+			};
+			let (shown, detail) = if result.is_none() {
+				let artifact = artifacts_dir
+					.as_ref()
+					.and_then(|dir| {
+						write_artifact(dir, kind, file, &name, contents, &output, disassemble)
+					})
+					.map(|path| format!("Artifact: {}\n", path.display()))
+					.unwrap_or_default();
+				let limited = if step_limited {
+					" (step limit exceeded)"
+				} else {
+					""
+				};
+				(
+					format!("{}{}", name, limited),
+					format!("{}{}", output, artifact),
+				)
+			} else {
+				(name.clone(), String::new())
+			};
+			// With a progress bar failures, and tests that failed before
+			// and pass now, are reported as soon as they are known,
+			// without it with the rest of their file.
+			match &progress {
+				Some(progress) => {
+					if status != Status::Passed {
+						let id = format!("{}:{}", file, shown);
+						progress.println(&console::result_line(status, &id, &detail));
+					}
+					progress.inc(result.is_none());
+				}
+				None => report.push(status, &shown, &detail),
 			}
+			let mut summary = summary.lock().unwrap();
+			if let Some(suite) = &suite {
+				summary.count_suite(suite, result.as_ref());
+			}
+			if crashed {
+				summary.crashed += 1;
+			}
+			if step_limited {
+				summary.step_limited += 1;
+			}
+			match (result, failure) {
+				(Some(outcome), _) => summary.pass(outcome),
+				(None, failure) => {
+					let message = failure.unwrap_or_default();
+					summary.fail(&name, &message);
+					if sarif {
+						summary.findings.push(Finding {
+							kind: kind.to_string(),
+							file: file.to_string(),
+							line: sarif::line_of(contents, &name),
+							name,
+							message: message.lines().next().unwrap_or_default().to_string(),
+							output,
+						});
+					}
+				}
+			}
+		}
+		if !report.is_empty() {
+			print!("{}", report.render());
+		}
+	};
+	let run_file = |(file, mut rng): (PathBuf, Option<Rng>)| {
+		// Only files running alongside others need a permit.
+		let _permit = if jobs > 1
+			&& fixtures::suite(&file.display().to_string())
+				.map_or(false, |suite| heavy::is_heavy(&suite))
+		{
+			Some(heavy.acquire())
+		} else {
+			None
+		};
+		if archive::is_archive(&file) {
+			let result = archive::for_each_fixture(&file, filter.as_ref(), |path, contents| {
+				match schema::parse::<T>(&contents, strict) {
+					Ok(coll) => run_tests(path, &contents, coll.into_iter().collect(), &mut rng),
+					Err(err) => parse_error(&path, &err),
+				}
+			});
+			if let Err(err) = result {
+				parse_error(&file.display(), &err);
+			}
+		} else {
+			let coll = fixtures::read(&file)
+				.map_err(|err| err.to_string())
+				.and_then(|contents| {
+					schema::parse::<T>(&contents, strict).map(|coll| (contents, coll))
+				});
+			match coll {
+				Ok((contents, coll)) => run_tests(
+					&file.display().to_string(),
+					&contents,
+					coll.into_iter().collect(),
+					&mut rng,
+				),
+				Err(err) => parse_error(&file.display(), &err),
+			}
+		}
+	};
+
+	if jobs > 1 {
+		rayon::ThreadPoolBuilder::new()
+			.num_threads(jobs)
+			.build()
+			.expect("Start worker threads failed")
+			.install(|| files.into_par_iter().for_each(run_file));
+	} else {
+		for (suite, files) in group_by_suite(files) {
+			profiled(profile.as_ref(), Per::Suite, &suite, || {
+				files.into_iter().for_each(&run_file)
+			});
+		}
+	}
+
+	if let Some(progress) = &progress {
+		progress.finish();
+	}
+	if let Some(metrics) = metrics {
+		metrics.finish();
+	}
+	summary.into_inner().unwrap()
+}
+
+/// Serve the metrics of the run on `addr`, once however many runs the
+/// command makes.
+fn serve_metrics(addr: &str) {
+	static SERVED: Once = Once::new();
+	SERVED.call_once(|| {
+		metrics::serve(addr)
+			.unwrap_or_else(|err| fail(format!("Serve metrics on {} failed: {}", addr, err)))
+	});
+}
+
+/// Bundle the failing test `name` of the fixture `contents` with its output,
+/// and the disassembly of its code if `disassemble` is set, into `dir`,
+/// returning the path of the bundle.
+fn write_artifact(
+	dir: &Path,
+	kind: &str,
+	file: &str,
+	name: &str,
+	contents: &[u8],
+	output: &str,
+	disassemble: bool,
+) -> Option<PathBuf> {
+	let mut tests = serde_json::from_slice::<Map<String, Value>>(contents).ok()?;
+	let mut fixture = Map::new();
+	fixture.insert(name.to_string(), tests.remove(name)?);
+	let failing = match kind {
+		"state" => statetests::failing_index(output),
+		_ => None,
+	};
+	let disassembly = if disassemble {
+		let index = failing.as_ref().map(|(_, index)| *index);
+		disasm::of_test(kind, &fixture[name], index)
+	} else {
+		None
+	};
+	let artifact = Artifact {
+		kind: kind.to_string(),
+		file: file.to_string(),
+		name: name.to_string(),
+		fork: failing.as_ref().map(|(fork, _)| fork.clone()),
+		index: failing.map(|(_, index)| index),
+		filler: FillerInfo::of_json(&fixture[name]),
+		fixture: Value::Object(fixture),
+		output: output.to_string(),
+		disassembly,
+	};
+	artifact
+		.write_to(dir)
+		.map_err(|err| eprintln!("Write artifact of {} failed: {}", name, err))
+		.ok()
+}
+
+/// Run the failing test of an artifact again, only the failing fork and index
+/// of a state test, printing its output and exiting with the status of a run.
+fn reproduce(path: &Path, trace: bool, diff: bool) -> ! {
+	let artifact =
+		Artifact::read(path).unwrap_or_else(|err| panic!("Read artifact failed: {}", err));
+	let index = match (&artifact.fork, artifact.index) {
+		(Some(fork), Some(index)) => format!(":{}:{}", fork, index),
+		_ => String::new(),
+	};
+	println!(
+		"Reproducing {} test {}{} of {}",
+		artifact.kind, artifact.name, index, artifact.file
+	);
+	if let Some(filler) = &artifact.filler {
+		println!("{}", filler);
+	}
+	let fixture = serde_json::to_vec(&artifact.fixture).unwrap();
+	let results = match artifact.kind.as_str() {
+		"state" => embed::run_state(
+			&fixture,
+			&statetests::Options {
+				diff,
+				tracer: if trace {
+					Some(TraceFormat::Eip3155)
+				} else {
+					None
+				},
+				forks: artifact.fork.iter().cloned().collect(),
+				indexes: artifact.index.into_iter().collect(),
+				..Default::default()
+			},
+		),
+		"vm" => embed::run_vm(
+			&fixture,
+			&vmtests::Options {
+				diff,
+				..Default::default()
+			},
+		),
+		"blockchain" => embed::run_blockchain(&fixture, &Default::default()),
+		kind => Err(format!("unknown kind of test {}", kind)),
+	}
+	.unwrap_or_else(|err| panic!("Run artifact failed: {}", err));
+
+	let mut failed = false;
+	for result in results {
+		print!("{}", result.output);
+		match result.outcome {
+			Ok(_) => println!("PASSED: {}", result.name),
+			Err(err) => {
+				failed = true;
+				println!("{}\nFAILED: {}", err, result.name);
+			}
+		}
+	}
+	process::exit(if failed { EXIT_FAILURES } else { 0 })
+}
+
+/// Runs of consecutive files of the same suite, named `other` for files in
+/// none.
+fn group_by_suite<T>(files: Vec<(PathBuf, T)>) -> Vec<(String, Vec<(PathBuf, T)>)> {
+	let mut groups: Vec<(String, Vec<_>)> = Vec::new();
+	for file in files {
+		let suite =
+			fixtures::suite(&file.0.display().to_string()).unwrap_or_else(|| "other".into());
+		match groups.last_mut() {
+			Some((last, files)) if *last == suite => files.push(file),
+			_ => groups.push((suite, vec![file])),
+		}
+	}
+	groups
+}
+
+/// Number of tests in `files`, counted without deserializing them.
+fn count_tests(files: &[(PathBuf, Option<Rng>)], filter: Option<&Regex>) -> usize {
+	let count = |contents: &[u8]| {
+		serde_json::from_slice::<BTreeMap<String, IgnoredAny>>(contents)
+			.map(|coll| coll.len())
+			.unwrap_or(0)
+	};
+
+	files
+		.iter()
+		.map(|(file, _)| {
+			if archive::is_archive(file) {
+				let mut total = 0;
+				let _ = archive::for_each_fixture(file, filter, |_, contents| {
+					total += count(&contents)
+				});
+				total
+			} else {
+				fixtures::read(file)
+					.map(|contents| count(&contents))
+					.unwrap_or(0)
+			}
+		})
+		.sum()
+}
+
+fn read_history(path: &Path) -> History {
+	let reports = report::load(path)
+		.unwrap_or_else(|err| panic!("Read history {} failed: {}", path.display(), err));
+	History::from_reports(&reports)
+}
+
+/// Priority of a fixture file, unknown for archives.
+fn file_priority(history: &History, file: &Path) -> Priority {
+	if archive::is_archive(file) {
+		return Priority::Unknown;
+	}
+	let names = fixtures::read(file)
+		.ok()
+		.and_then(|contents| serde_json::from_slice::<BTreeMap<String, IgnoredAny>>(&contents).ok())
+		.unwrap_or_default();
+	history.file_priority(names.keys().map(String::as_str))
+}
+
+/// Whether to draw a progress bar instead of a status line per test: only
+/// on a terminal, and not when tests print reports of their own.
+fn show_progress(matches: &ArgMatches) -> bool {
+	atty::is(atty::Stream::Stderr) && !prints_reports(matches)
+}
+
+/// Whether tests print reports that were asked for even when they pass, so
+/// their output is not captured.
+fn prints_reports(matches: &ArgMatches) -> bool {
+	["verbose", "diff", "provenance", "tracer", "repeat"]
+		.iter()
+		.any(|flag| matches.is_present(flag))
+}
+
+/// Print `err` and exit unsuccessfully.
+fn fail(err: String) -> ! {
+	eprintln!("{}", err);
+	process::exit(1);
+}
+
+fn soak_arg() -> Arg<'static, 'static> {
+	Arg::with_name("soak")
+		.long("soak")
+		.value_name("N")
+		.help(
+			"Run the tests N times in this process, failing if resident memory grows every \
+			 round after the first",
+		)
+		.takes_value(true)
+}
+
+/// Run `round` once, or as many times as `--soak` asks while sampling the
+/// resident memory after every round, with the totals of all rounds.
+fn soaked<F: FnMut() -> Summary>(matches: &ArgMatches, mut round: F) -> Summary {
+	let rounds: usize = match matches.value_of("soak") {
+		Some(rounds) => rounds
+			.parse()
+			.ok()
+			.filter(|rounds| *rounds > 0)
+			.expect("Invalid number of soak rounds"),
+		None => return round(),
+	};
+	if soak::rss_bytes().is_none() {
+		eprintln!("Resident memory cannot be read on this system, leaks are not detected");
+	}
+	let mut summary = Summary::default();
+	let mut samples = Samples::default();
+	for i in 0..rounds {
+		let totals = round();
+		println!("Soak round {} of {}: {}", i + 1, rounds, totals);
+		summary.merge(totals);
+		samples.0.extend(soak::rss_bytes());
+	}
+	if !samples.0.is_empty() {
+		println!("{}", samples);
+	}
+	summary.leaked = samples.leaks();
+	summary
+}
+
+fn manifest_args() -> Vec<Arg<'static, 'static>> {
+	vec![
+		Arg::with_name("manifest")
+			.long("manifest")
+			.value_name("FILE")
+			.help("Fail forks executing fewer post states or chains than the manifest FILE expects")
+			.takes_value(true),
+		Arg::with_name("record-manifest")
+			.long("record-manifest")
+			.value_name("FILE")
+			.help("Write the counts every fork executed as a manifest named after FILE")
+			.takes_value(true),
+	]
+}
+
+/// Compare the counts of `summary` with the manifest given, and record them
+/// into a new one if asked to.
+fn check_manifest(matches: &ArgMatches, summary: &mut Summary) {
+	if let Some(path) = matches.value_of("manifest") {
+		let manifest = Manifest::load(Path::new(path)).unwrap_or_else(|err| fail(err));
+		let shortfalls = manifest.shortfalls(&summary.forks);
+		for shortfall in &shortfalls {
+			println!("SHORT of release {}: {}", manifest.release, shortfall);
 		}
+		summary.short_forks += shortfalls.len();
 	}
+	if let Some(path) = matches.value_of("record-manifest") {
+		let path = Path::new(path);
+		let release = path
+			.file_stem()
+			.map(|stem| stem.to_string_lossy().into_owned())
+			.unwrap_or_default();
+		Manifest::record(&release, &summary.forks)
+			.write(path)
+			.unwrap_or_else(|err| fail(err));
+		println!("Manifest of {} written to {}", release, path.display());
+	}
+}
 
-	if let Some(matches) = matches.subcommand_matches("state") {
-		for file_name in matches.values_of("FILE").unwrap() {
-			let file = File::open(file_name).expect("Open file failed");
+/// Write the failing tests of `summary` into the SARIF log given.
+fn write_sarif(matches: &ArgMatches, summary: &Summary) {
+	if let Some(path) = matches.value_of("sarif") {
+		sarif::write(Path::new(path), &summary.findings)
+			.unwrap_or_else(|err| fail(format!("Write SARIF log failed: {}", err)));
+		println!("SARIF log written to {}", path);
+	}
+}
 
-			let reader = BufReader::new(file);
-			let coll = serde_json::from_reader::<_, HashMap<String, statetests::Test>>(reader)
-				.expect("Parse test cases failed");
+/// Print the totals of a run and exit with the code they map to.
+fn exit_with(summary: &Summary, allow_skips: bool) -> ! {
+	if !summary.suites.is_empty() {
+		print!("{}", summary.suites_report());
+	}
+	if !summary.skips.is_empty() {
+		print!("{}", summary.skips_report());
+	}
+	if !summary.failures.is_empty() {
+		print!("{}", summary.failures_report());
+	}
+	println!("{}", summary);
+	process::exit(summary.exit_code(allow_skips))
+}
 
-			for (name, test) in coll {
+/// Run every test of a fixture file, reporting failures instead of aborting.
+fn run_watched(file: &Path, vm: bool) {
+	let result = panic::catch_unwind(AssertUnwindSafe(|| {
+		if vm {
+			for (name, test) in load_file::<vmtests::Test, _>(file) {
+				vmtests::test(&name, test);
+			}
+		} else {
+			for (name, test) in load_file::<statetests::Test, _>(file) {
 				statetests::test(&name, test);
 			}
 		}
+	}));
+	if result.is_err() {
+		println!("FAILED: {}", file.display());
+	}
+}
+
+fn vm_command() -> App<'static, 'static> {
+	SubCommand::with_name("vm")
+		.arg(
+			Arg::with_name("FILE")
+				.help(
+					"Fixture files, directories or .tar.gz/.zip fixture archives, \
+					 defaults to the configured paths",
+				)
+				.min_values(1),
+		)
+		.arg(
+			Arg::with_name("diff")
+				.long("diff")
+				.help("Print pre/post state differences of every test"),
+		)
+		.arg(skip_arg())
+		.arg(skip_pattern_arg())
+		.arg(tag_arg())
+		.arg(test_filter_arg())
+		.args(&profile_args())
+		.arg(jobs_arg())
+		.arg(heavy_jobs_arg())
+		.arg(metrics_addr_arg())
+		.arg(strict_schema_arg())
+		.arg(sarif_arg())
+		.arg(nocapture_arg())
+		.arg(artifacts_dir_arg())
+		.arg(disassemble_arg())
+		.arg(isolate_arg())
+		.arg(max_steps_arg())
+		.arg(archive_filter_arg())
+		.arg(shuffle_arg())
+		.arg(time_budget_arg())
+}
+
+/// Run the vm tests of the fixture files and exit with the status of the run.
+fn run_vm(matches: &ArgMatches, config: &Config) {
+	let progress = show_progress(matches);
+	let options = vm_options(matches, progress);
+
+	let summary = for_each_test::<vmtests::Test, _>(
+		matches,
+		"vm",
+		config,
+		fixture_files(matches, &config.vm),
+		progress,
+		None,
+		|_, name, test| vmtests::test_with_options(&name, test, &options),
+	);
+	write_sarif(matches, &summary);
+	exit_with(&summary, true);
+}
+
+fn state_command() -> App<'static, 'static> {
+	SubCommand::with_name("state")
+		.alias("run")
+		.arg(
+			Arg::with_name("FILE")
+				.help(
+					"Fixture files, directories or .tar.gz/.zip fixture archives, \
+					 defaults to the configured paths",
+				)
+				.min_values(1),
+		)
+		.arg(
+			Arg::with_name("smoke")
+				.long("smoke")
+				.help(
+					"Run only the smoke set of in-repo fillers, filled first, instead of \
+					 fixture files",
+				)
+				.conflicts_with("FILE"),
+		)
+		.arg(
+			Arg::with_name("dump-state")
+				.long("dump-state")
+				.value_name("DIR")
+				.help("Write the post-execution state of every index as JSON into DIR")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("diff")
+				.long("diff")
+				.help("Print pre/post state differences of every index"),
+		)
+		.arg(
+			Arg::with_name("label")
+				.long("label")
+				.value_name("LABEL")
+				.help("Only run indexes whose data is labelled LABEL, may be repeated")
+				.takes_value(true)
+				.multiple(true)
+				.number_of_values(1),
+		)
+		.arg(Arg::with_name("carry-state").long("carry-state").help(
+			"Execute every index against the post-state of the previous one, \
+					 for suites written as a sequence of transactions",
+		))
+		.arg(
+			Arg::with_name("repeat")
+				.long("repeat")
+				.value_name("N")
+				.help(
+					"Execute every passing index N more times after a warm-up run and \
+					 print its min/median/max time and gas per second",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("base-fee")
+				.long("base-fee")
+				.value_name("WEI")
+				.help(
+					"Replace the base fee of every test's block, e.g. with 0 for \
+					 chains without one",
+				)
+				.takes_value(true),
+		)
+		.arg(Arg::with_name("determinism").long("determinism").help(
+			"Execute every passing index twice more with a trace and fail it \
+					 unless both traces are byte for byte the same",
+		))
+		.arg(
+			Arg::with_name("matrix")
+				.long("matrix")
+				.value_name("FILE")
+				.help(
+					"Run the fixtures once per configuration of flags and environment \
+					 variables of the JSON matrix in FILE, reporting indexes whose \
+					 result depends on the configuration",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("fork-consistency")
+				.long("fork-consistency")
+				.help(
+					"Report indexes whose post-state root changes between adjacent \
+					 forks for only one of the fixture and the runner",
+				),
+		)
+		.arg(
+			Arg::with_name("override-env")
+				.long("override-env")
+				.value_name("KEY=VALUE")
+				.help(
+					"Replace a field of every test's block: timestamp, difficulty, \
+					 gas-limit, coinbase or number. May be given several times",
+				)
+				.takes_value(true)
+				.multiple(true)
+				.number_of_values(1),
+		)
+		.arg(
+			Arg::with_name("record-precompiles")
+				.long("record-precompiles")
+				.value_name("FILE")
+				.help(
+					"Record every distinct precompile call into FILE as golden vectors \
+					 for `replay-precompiles`",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("perturb")
+				.long("perturb")
+				.value_name("SEED")
+				.help(
+					"Execute every passing index again with untouched accounts added to \
+					 its pre-state from SEED and fail it unless the result is the same",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("reference-t8n")
+				.long("reference-t8n")
+				.value_name("GETH_EVM")
+				.help(
+					"Also run every index with `evm t8n` of the geth evm binary \
+					 GETH_EVM and fail the indexes whose post-state alloc or receipt \
+					 differ from the runner's",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("compare-impl")
+				.long("compare-impl")
+				.value_name("IMPL")
+				.help(
+					"Run indexes on the evm crate the runner builds against, on evm-next \
+					 or on both, failing indexes they behave differently on; the last \
+					 two need the compare-impl feature",
+				)
+				.takes_value(true)
+				.possible_values(&["old", "new", "both"]),
+		)
+		.arg(
+			Arg::with_name("stress")
+				.long("stress")
+				.value_name("THREADS")
+				.help(
+					"Execute every passing index again on THREADS threads at once and \
+					 fail it unless they all compute the same result",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("provenance")
+				.long("provenance")
+				.help("On root mismatch, print the writer of every storage slot"),
+		)
+		.arg(
+			Arg::with_name("verbose")
+				.long("verbose")
+				.short("v")
+				.help("Print the message-call tree and gas breakdown of every index"),
+		)
+		.arg(
+			Arg::with_name("json-report")
+				.long("json-report")
+				.value_name("FILE")
+				.help("Append a JSON line per executed index to FILE")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("tests-from")
+				.long("tests-from")
+				.value_name("FILE")
+				.help(
+					"Run only the indexes listed in FILE, one path:test:fork:index per \
+					 line, from the fixture files they name",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("failed-from")
+				.long("failed-from")
+				.value_name("REPORT")
+				.help(
+					"Run only the indexes that failed in the JSON report REPORT, of \
+					 those listed with --tests-from if given",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("history")
+				.long("history")
+				.value_name("FILE")
+				.help(
+					"JSON report of an earlier run, to run the tests that failed in \
+					 it first and the slowest last, defaults to the JSON report being \
+					 written if it exists",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("record-timings")
+				.long("record-timings")
+				.value_name("FILE")
+				.help(
+					"Append the wall time of every executed index to FILE, keeping \
+					 the last runs, for the `timings` report",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("html-report")
+				.long("html-report")
+				.value_name("DIR")
+				.help("Write a static HTML report of the run into DIR")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("tracer")
+				.long("tracer")
+				.value_name("FORMAT")
+				.help("Write an execution trace of every index")
+				.takes_value(true)
+				.possible_values(&["struct", "call", "3155"]),
+		)
+		.arg(
+			Arg::with_name("trace-dir")
+				.long("trace-dir")
+				.value_name("DIR")
+				.help("Write traces into DIR instead of standard error")
+				.takes_value(true)
+				.requires("tracer"),
+		)
+		.arg(skip_arg())
+		.arg(skip_pattern_arg())
+		.arg(tag_arg())
+		.arg(test_filter_arg())
+		.args(&profile_args())
+		.arg(fork_filter_arg())
+		.arg(allow_failed_skips_arg())
+		.arg(jobs_arg())
+		.arg(heavy_jobs_arg())
+		.arg(metrics_addr_arg())
+		.arg(strict_schema_arg())
+		.arg(sarif_arg())
+		.arg(nocapture_arg())
+		.arg(artifacts_dir_arg())
+		.arg(disassemble_arg())
+		.arg(isolate_arg())
+		.arg(timeout_arg())
+		.arg(max_steps_arg())
+		.arg(archive_filter_arg())
+		.arg(shuffle_arg())
+		.arg(time_budget_arg())
+		.arg(soak_arg())
+		.args(&manifest_args())
+}
+
+/// Run the state tests of the fixture files, or each configuration of a
+/// matrix, and exit with the status of the run.
+fn run_state(matches: &ArgMatches, config: &Config) {
+	if let Some(path) = matches.value_of("matrix") {
+		let matrix = Matrix::load(Path::new(path)).unwrap_or_else(|err| fail(err));
+		let binary = std::env::current_exe().expect("Locate runner binary failed");
+		let parent = std::env::args().skip(1).collect::<Vec<_>>();
+		let runs = matrix::run(&matrix, &binary, &parent).unwrap_or_else(|err| fail(err));
+		let divergences = matrix::divergences(&runs);
+		for divergence in &divergences {
+			println!("{}", divergence);
+		}
+		println!(
+			"{} indexes of {} configurations depend on the configuration",
+			divergences.len(),
+			runs.len()
+		);
+		process::exit(if divergences.is_empty() { 0 } else { 1 });
+	}
+	let progress = show_progress(matches);
+	let html_report = path_or(matches, "html-report", &config.html_report);
+	// Results of the previous run, read before this one replaces them.
+	let history = match matches.value_of("history") {
+		Some(path) => Some(read_history(Path::new(path))),
+		None => path_or(matches, "json-report", &config.json_report)
+			.or_else(|| html_report.as_ref().map(|dir| dir.join("results.jsonl")))
+			.filter(|path| path.exists())
+			.map(|path| read_history(&path)),
+	};
+	// The HTML report is rendered from the JSON report of the run, kept
+	// next to it unless written elsewhere.
+	let json_report = path_or(matches, "json-report", &config.json_report).or_else(|| {
+		html_report.as_ref().map(|dir| {
+			let path = dir.join("results.jsonl");
+			fs::create_dir_all(dir).expect("Create HTML report directory failed");
+			fs::write(&path, "").expect("Create JSON report failed");
+			path
+		})
+	});
+	let options = state_options(matches, config, progress, json_report.clone());
+	if options.timings.is_some() {
+		timings::run_id();
+	}
+	let record_precompiles = matches.value_of("record-precompiles").map(Path::new);
+	if record_precompiles.is_some() {
+		vectors::start_recording();
+	}
+	let files = if matches.is_present("smoke") {
+		let dir = std::env::temp_dir().join(format!("evm-jsontests-smoke-{}", process::id()));
+		smoke::write_to(&dir).unwrap_or_else(|err| fail(format!("Fill smoke set failed: {}", err)))
+	} else {
+		// Listed indexes name their files.
+		match options.selection.as_ref().and_then(Selection::files) {
+			Some(files) => files,
+			None => fixture_files(matches, &config.state),
+		}
+	};
+
+	let mut summary = soaked(matches, || {
+		for_each_test::<statetests::Test, _>(
+			matches,
+			"state",
+			config,
+			files.clone(),
+			progress,
+			history.as_ref(),
+			|file, name, test| statetests::test_in_file(file, &name, test, &options),
+		)
+	});
+	if let Some(path) = record_precompiles {
+		let count = vectors::finish_recording(path).expect("Write precompile vectors failed");
+		println!("{} precompile vectors written to {}", count, path.display());
+	}
+	if let (Some(dir), Some(json_report)) = (&html_report, &json_report) {
+		let results = report::load(json_report).expect("Read JSON report failed");
+		let path = html::write(dir, &results).expect("Write HTML report failed");
+		println!("HTML report written to {}", path.display());
+	}
+	if let Some(path) = &options.timings {
+		Timings::truncate(path, timings::KEPT_RUNS).unwrap_or_else(|err| fail(err));
+	}
+	check_manifest(matches, &mut summary);
+	write_sarif(matches, &summary);
+	exit_with(
+		&summary,
+		matches.is_present("allow-failed-skips") || config.allow_failed_skips,
+	);
+}
+
+fn auto_command() -> App<'static, 'static> {
+	SubCommand::with_name("auto")
+		.about(
+			"Run fixtures of any format, routing each file to the runner of the \
+			 format its tests have",
+		)
+		.arg(
+			Arg::with_name("FILE")
+				.help(
+					"Fixture files, directories or .tar.gz/.zip fixture archives, \
+					 defaults to the configured paths of every format",
+				)
+				.min_values(1),
+		)
+		.arg(
+			Arg::with_name("format")
+				.long("format")
+				.value_name("FORMAT")
+				.help("Run every file as this format instead of detecting it")
+				.takes_value(true)
+				.possible_values(&["state", "vm", "blockchain"]),
+		)
+		.arg(
+			Arg::with_name("diff")
+				.long("diff")
+				.help("Print pre/post state differences of every test"),
+		)
+		.arg(skip_arg())
+		.arg(skip_pattern_arg())
+		.arg(tag_arg())
+		.arg(test_filter_arg())
+		.args(&profile_args())
+		.arg(fork_filter_arg())
+		.arg(allow_failed_skips_arg())
+		.arg(jobs_arg())
+		.arg(heavy_jobs_arg())
+		.arg(metrics_addr_arg())
+		.arg(strict_schema_arg())
+		.arg(sarif_arg())
+		.arg(nocapture_arg())
+		.arg(artifacts_dir_arg())
+		.arg(disassemble_arg())
+		.arg(isolate_arg())
+		.arg(timeout_arg())
+		.arg(max_steps_arg())
+		.arg(archive_filter_arg())
+		.arg(shuffle_arg())
+		.arg(time_budget_arg())
+}
+
+/// Run every fixture file on the runner of its detected format and exit
+/// with the status of all the runs.
+fn run_auto(matches: &ArgMatches, config: &Config) {
+	let progress = show_progress(matches);
+	let forced = matches
+		.value_of("format")
+		.map(|format| format.parse::<Format>().unwrap());
+	let configured = config::Suite {
+		paths: [&config.state, &config.vm, &config.blockchain]
+			.iter()
+			.flat_map(|suite| suite.paths.clone())
+			.collect(),
+	};
+
+	let mut summary = Summary::default();
+	let mut files_by_format = BTreeMap::<Format, Vec<PathBuf>>::new();
+	for file in fixture_files(matches, &configured) {
+		// Archives mix formats, their entries are not detected.
+		let format = match forced {
+			Some(format) => Some(format),
+			None if archive::is_archive(&file) => None,
+			None => fixtures::read(&file)
+				.ok()
+				.and_then(|contents| format::detect(&contents)),
+		};
+		match format {
+			Some(format) => files_by_format.entry(format).or_default().push(file),
+			None => {
+				eprintln!(
+					"Format of {} unknown, select one with --format",
+					file.display()
+				);
+				summary.parse_errors += 1;
+			}
+		}
+	}
+
+	let mut totals = Vec::new();
+	for (format, files) in files_by_format {
+		let count = files.len();
+		let format_summary = match format {
+			Format::State => {
+				let options = state_options(matches, config, progress, None);
+				for_each_test::<statetests::Test, _>(
+					matches,
+					format.name(),
+					config,
+					files,
+					progress,
+					None,
+					|file, name, test| statetests::test_in_file(file, &name, test, &options),
+				)
+			}
+			Format::Vm => {
+				let options = vm_options(matches, progress);
+				for_each_test::<vmtests::Test, _>(
+					matches,
+					format.name(),
+					config,
+					files,
+					progress,
+					None,
+					|_, name, test| vmtests::test_with_options(&name, test, &options),
+				)
+			}
+			Format::Blockchain => {
+				let options = blockchain_options(matches, config, progress);
+				for_each_test::<blockchaintests::Test, _>(
+					matches,
+					format.name(),
+					config,
+					files,
+					progress,
+					None,
+					|_, name, test| blockchaintests::test_with_options(&name, test, &options),
+				)
+			}
+		};
+		totals.push(format!("{} ({} files): {}", format, count, format_summary));
+		summary.merge(format_summary);
+	}
+	for total in totals {
+		println!("{}", total);
+	}
+	write_sarif(matches, &summary);
+	exit_with(
+		&summary,
+		matches.is_present("allow-failed-skips") || config.allow_failed_skips,
+	);
+}
+
+fn blockchain_command() -> App<'static, 'static> {
+	SubCommand::with_name("blockchain")
+		.about("Run blockchain tests in the execution-spec-tests fixture format")
+		.arg(
+			Arg::with_name("FILE")
+				.help(
+					"Fixture files, directories or .tar.gz/.zip fixture archives, \
+					 defaults to the configured paths",
+				)
+				.min_values(1),
+		)
+		.arg(skip_arg())
+		.arg(skip_pattern_arg())
+		.arg(tag_arg())
+		.arg(test_filter_arg())
+		.args(&profile_args())
+		.arg(fork_filter_arg())
+		.arg(allow_failed_skips_arg())
+		.arg(jobs_arg())
+		.arg(heavy_jobs_arg())
+		.arg(metrics_addr_arg())
+		.arg(strict_schema_arg())
+		.arg(sarif_arg())
+		.arg(nocapture_arg())
+		.arg(artifacts_dir_arg())
+		.arg(disassemble_arg())
+		.arg(isolate_arg())
+		.arg(timeout_arg())
+		.arg(max_steps_arg())
+		.arg(archive_filter_arg())
+		.arg(shuffle_arg())
+		.arg(time_budget_arg())
+		.arg(soak_arg())
+		.args(&manifest_args())
+}
+
+/// Run the blockchain tests of the fixture files and exit with the status
+/// of the run.
+fn run_blockchain(matches: &ArgMatches, config: &Config) {
+	let progress = show_progress(matches);
+	let options = blockchain_options(matches, config, progress);
+
+	let files = fixture_files(matches, &config.blockchain);
+	let mut summary = soaked(matches, || {
+		for_each_test::<blockchaintests::Test, _>(
+			matches,
+			"blockchain",
+			config,
+			files.clone(),
+			progress,
+			None,
+			|_, name, test| blockchaintests::test_with_options(&name, test, &options),
+		)
+	});
+	check_manifest(matches, &mut summary);
+	write_sarif(matches, &summary);
+	exit_with(
+		&summary,
+		matches.is_present("allow-failed-skips") || config.allow_failed_skips,
+	);
+}
+
+fn debug_command() -> App<'static, 'static> {
+	SubCommand::with_name("debug")
+		.about("Step through a single state test index interactively")
+		.arg(
+			Arg::with_name("FILE")
+				.help("State test fixture file")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("test")
+				.long("test")
+				.value_name("NAME")
+				.help("Test to debug, required if the file has more than one")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("fork")
+				.long("fork")
+				.value_name("FORK")
+				.help("Fork of the post state to debug, defaults to the first one")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("index")
+				.long("index")
+				.value_name("INDEX")
+				.help("Index of the post state within the fork")
+				.takes_value(true)
+				.default_value("0"),
+		)
+}
+
+/// Step through the selected state test index.
+fn run_debug(matches: &ArgMatches) {
+	let mut tests =
+		load_test::<statetests::Test>(matches.value_of("FILE").unwrap(), matches.value_of("test"));
+	let test = match matches.value_of("test") {
+		Some(name) => tests
+			.into_iter()
+			.find(|(test_name, _)| test_name == name)
+			.map(|(_, test)| test)
+			.expect("No such test in file"),
+		None if tests.len() == 1 => tests.remove(0).1,
+		None => panic!("File has several tests, select one with --test"),
+	};
+	let spec = matches
+		.value_of("fork")
+		.map(|fork| serde_json::from_value::<ForkSpec>(fork.into()).expect("Unknown fork"));
+	let index = matches
+		.value_of("index")
+		.unwrap()
+		.parse()
+		.expect("Invalid index");
+
+	debugger::debug(test, spec, index);
+}
+
+fn explain_command() -> App<'static, 'static> {
+	SubCommand::with_name("explain")
+		.about(
+			"Show the data, gas limit and value each post state index of a state test \
+			 executes",
+		)
+		.arg(
+			Arg::with_name("FILE")
+				.help("State test fixture file")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("test")
+				.long("test")
+				.value_name("NAME")
+				.help("Test to explain, required if the file has more than one")
+				.takes_value(true),
+		)
+}
+
+/// Print the transaction alternatives the post-state indexes of a state
+/// test select.
+fn run_explain(matches: &ArgMatches) {
+	let mut tests =
+		load_test::<statetests::Test>(matches.value_of("FILE").unwrap(), matches.value_of("test"));
+	let (name, test) = match matches.value_of("test") {
+		Some(name) => tests
+			.into_iter()
+			.find(|(test_name, _)| test_name == name)
+			.expect("No such test in file"),
+		None if tests.len() == 1 => tests.remove(0),
+		None => panic!("File has several tests, select one with --test"),
+	};
+
+	print!("{}", explain::explain(&name, &test.0));
+}
+
+fn trace_diff_command() -> App<'static, 'static> {
+	SubCommand::with_name("trace-diff")
+		.about("Report the first step at which two EIP-3155 traces diverge")
+		.arg(
+			Arg::with_name("REFERENCE")
+				.help("Reference trace, e.g. generated by geth")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("OURS")
+				.help("Trace written with --tracer 3155")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("context")
+				.long("context")
+				.value_name("N")
+				.help("Number of steps to show before the divergence")
+				.takes_value(true)
+				.default_value("5"),
+		)
+}
+
+/// Print the first step at which two traces diverge.
+fn run_trace_diff(matches: &ArgMatches) {
+	let reference = tracediff::load_trace(Path::new(matches.value_of("REFERENCE").unwrap()))
+		.expect("Load reference trace failed");
+	let ours = tracediff::load_trace(Path::new(matches.value_of("OURS").unwrap()))
+		.expect("Load trace failed");
+	let context = matches
+		.value_of("context")
+		.unwrap()
+		.parse()
+		.expect("Invalid context");
+
+	match tracediff::first_divergence(&reference, &ours) {
+		Some(divergence) => print!(
+			"{}",
+			tracediff::render_divergence(&reference, &ours, &divergence, context)
+		),
+		None => println!("Traces match ({} steps)", ours.len()),
+	}
+}
+
+fn compare_command() -> App<'static, 'static> {
+	SubCommand::with_name("compare")
+		.about("Compare the JSON reports of two runs, e.g. of main and a PR branch")
+		.arg(
+			Arg::with_name("BASE")
+				.help("JSON report of the base run")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("HEAD")
+				.help("JSON report of the run to check")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("threshold")
+				.long("threshold")
+				.value_name("PERCENT")
+				.help("Report indexes whose time grew by more than PERCENT")
+				.takes_value(true)
+				.default_value("50"),
+		)
+		.arg(
+			Arg::with_name("gas-tolerance")
+				.long("gas-tolerance")
+				.value_name("GAS")
+				.help("Report indexes whose gas used changed by more than GAS")
+				.takes_value(true)
+				.default_value("0"),
+		)
+}
+
+/// Compare the JSON reports of two runs, exiting unsuccessfully on a
+/// regression.
+fn run_compare(matches: &ArgMatches) {
+	let base = report::load(Path::new(matches.value_of("BASE").unwrap()))
+		.expect("Read base report failed");
+	let head = report::load(Path::new(matches.value_of("HEAD").unwrap()))
+		.expect("Read head report failed");
+	let tolerance = compare::Tolerance {
+		time_percent: matches
+			.value_of("threshold")
+			.unwrap()
+			.parse()
+			.expect("Invalid threshold"),
+		gas: matches
+			.value_of("gas-tolerance")
+			.unwrap()
+			.parse()
+			.expect("Invalid gas tolerance"),
+	};
+
+	let comparison = compare::compare(&base, &head, tolerance);
+	print!("{}", compare::render(&comparison));
+	if comparison.is_regression() {
+		process::exit(EXIT_FAILURES);
+	}
+}
+
+fn retesteth_command() -> App<'static, 'static> {
+	SubCommand::with_name("retesteth")
+		.about("Serve the RPC methods retesteth drives a client under test with")
+		.arg(
+			Arg::with_name("addr")
+				.long("addr")
+				.value_name("ADDR")
+				.help("Address to listen on")
+				.takes_value(true)
+				.default_value("127.0.0.1:8545"),
+		)
+}
+
+/// Serve the RPC methods of retesteth.
+fn run_retesteth(matches: &ArgMatches) {
+	let addr = matches.value_of("addr").unwrap();
+	println!("Listening on {}", addr);
+	rpc::serve(addr, &mut Retesteth::new()).expect("Serve failed");
+}
+
+fn serve_command() -> App<'static, 'static> {
+	SubCommand::with_name("serve")
+		.about(
+			"Serve JSON-RPC methods running submitted fixtures in the background \
+			 (test_submit, test_result)",
+		)
+		.arg(
+			Arg::with_name("addr")
+				.long("addr")
+				.value_name("ADDR")
+				.help("Address to listen on")
+				.takes_value(true)
+				.default_value("127.0.0.1:8546"),
+		)
+}
+
+/// Serve the RPC methods running submitted fixtures.
+fn run_serve(matches: &ArgMatches) {
+	let addr = matches.value_of("addr").unwrap();
+	println!("Listening on {}", addr);
+	rpc::serve(addr, &mut Service::new()).expect("Serve failed");
+}
+
+fn replay_precompiles_command() -> App<'static, 'static> {
+	SubCommand::with_name("replay-precompiles")
+		.about(
+			"Call the builtins with the inputs of golden vectors recorded by \
+			 --record-precompiles and compare their results, without the EVM",
+		)
+		.arg(
+			Arg::with_name("FILE")
+				.help("Golden vectors file")
+				.required(true)
+				.index(1),
+		)
+}
+
+/// Replay recorded precompile vectors, exiting unsuccessfully if one
+/// differs.
+fn run_replay_precompiles(matches: &ArgMatches) {
+	let path = Path::new(matches.value_of("FILE").unwrap());
+	let vectors =
+		vectors::read(path).unwrap_or_else(|err| panic!("Read {} failed: {}", path.display(), err));
+	let mut failed = 0;
+	for vector in &vectors {
+		if let Err(err) = vector.replay() {
+			failed += 1;
+			println!(
+				"{} {:?} 0x{} (gas limit {:?}): {}",
+				vector.fork,
+				vector.address,
+				hex::encode(&vector.input),
+				vector.gas_limit,
+				err
+			);
+		}
+	}
+	println!("{} of {} vectors differ", failed, vectors.len());
+	process::exit(if failed > 0 { EXIT_FAILURES } else { 0 });
+}
+
+fn reproduce_command() -> App<'static, 'static> {
+	SubCommand::with_name("reproduce")
+		.about(
+			"Run the failing test of an artifact written by --artifacts-dir again, \
+			 tracing it and printing its state diff",
+		)
+		.arg(
+			Arg::with_name("ARTIFACT")
+				.help("Artifact to reproduce")
+				.required(true)
+				.index(1),
+		)
+		.arg(
+			Arg::with_name("no-trace")
+				.long("no-trace")
+				.help("Do not trace the execution"),
+		)
+		.arg(
+			Arg::with_name("no-diff")
+				.long("no-diff")
+				.help("Do not print pre/post state differences"),
+		)
+}
+
+/// Run the failing test of an artifact again, traced and diffed unless
+/// told not to.
+fn run_reproduce(matches: &ArgMatches) {
+	reproduce(
+		Path::new(matches.value_of("ARTIFACT").unwrap()),
+		!matches.is_present("no-trace"),
+		!matches.is_present("no-diff"),
+	);
+}
+
+fn minimize_command() -> App<'static, 'static> {
+	SubCommand::with_name("minimize")
+		.about(
+			"Shrink the pre-state and calldata of a state test that crashes or fails \
+			 a check to a fixture failing the same way",
+		)
+		.arg(
+			Arg::with_name("FILE")
+				.help("State test fixture")
+				.required(true)
+				.index(1),
+		)
+		.arg(
+			Arg::with_name("test")
+				.long("test")
+				.value_name("NAME")
+				.help("Test to minimize, the first failing one by default")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("output")
+				.long("output")
+				.value_name("FILE")
+				.help("File to write the minimized fixture to, standard output by default")
+				.takes_value(true),
+		)
+}
+
+/// Print or write the minimized fixture of a failing state test.
+fn run_minimize(matches: &ArgMatches) {
+	let file = matches.value_of("FILE").unwrap();
+	let json = std::fs::read(file).unwrap_or_else(|err| fail(format!("{}: {}", file, err)));
+	let minimized = minimize::minimize(&json, matches.value_of("test"))
+		.unwrap_or_else(|err| fail(format!("{}: {}", file, err)));
+	let fixture = serde_json::to_string_pretty(&minimized.fixture).unwrap();
+	match matches.value_of("output") {
+		Some(path) => std::fs::write(path, fixture).expect("Write fixture failed"),
+		None => println!("{}", fixture),
+	}
+	eprintln!("{}", minimized);
+}
+
+fn select_corpus_command() -> App<'static, 'static> {
+	SubCommand::with_name("select-corpus")
+		.about(
+			"Pick a small set of state test indexes executing every opcode and \
+			 precompile the fixtures do, written as a list for --tests-from",
+		)
+		.arg(
+			Arg::with_name("FILE")
+				.help("Fixture files or directories, the configured state paths by default")
+				.multiple(true)
+				.index(1),
+		)
+		.arg(
+			Arg::with_name("output")
+				.long("output")
+				.value_name("FILE")
+				.help("File to write the list to, standard output by default")
+				.takes_value(true),
+		)
+}
+
+/// Select the state test indexes covering every opcode and precompile
+/// any of them covers.
+fn run_select_corpus(matches: &ArgMatches, config: &Config) {
+	let mut candidates = Vec::new();
+	for file in fixture_files(matches, &config.state) {
+		let path = file.display().to_string();
+		for (name, test) in load_file::<statetests::Test, _>(&file) {
+			candidates.extend(corpus::coverage(&path, &name, &test));
+		}
+	}
+	let selected = corpus::select(&candidates);
+	let list = selected
+		.iter()
+		.map(|i| format!("{}\n", candidates[*i].0))
+		.collect::<String>();
+	match matches.value_of("output") {
+		Some(path) => fs::write(path, list).expect("Write corpus list failed"),
+		None => print!("{}", list),
+	}
+	let features = candidates
+		.iter()
+		.flat_map(|(_, features)| features.iter())
+		.collect::<BTreeSet<_>>();
+	eprintln!(
+		"{} of {} indexes cover {} opcodes and precompiles",
+		selected.len(),
+		candidates.len(),
+		features.len()
+	);
+}
+
+fn bisect_command() -> App<'static, 'static> {
+	SubCommand::with_name("bisect")
+		.about(
+			"Rebuild the runner against the checked out evm sources and run a test \
+			 with it, exiting as `git bisect run` expects: 0 if it passes, 1 if it \
+			 fails and 125 if the runner does not build",
+		)
+		.arg(
+			Arg::with_name("FILE")
+				.help("State test fixture")
+				.required(true)
+				.index(1),
+		)
+		.arg(
+			Arg::with_name("test")
+				.long("test")
+				.value_name("NAME")
+				.help("Test to run")
+				.takes_value(true)
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("fork")
+				.long("fork")
+				.value_name("FORK")
+				.help("Fork to run the test on, all of its forks by default")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("target-dir")
+				.long("target-dir")
+				.value_name("DIR")
+				.help("Directory to build the runner into, target/bisect by default")
+				.takes_value(true),
+		)
+}
+
+/// Run one step of a bisection, exiting with its verdict.
+fn run_bisect(matches: &ArgMatches) {
+	let bisect = Bisect {
+		fixture: PathBuf::from(matches.value_of("FILE").unwrap()),
+		test: matches.value_of("test").unwrap().to_string(),
+		fork: matches.value_of("fork").map(String::from),
+		target_dir: matches.value_of("target-dir").map_or_else(
+			|| Path::new(env!("CARGO_MANIFEST_DIR")).join("../target/bisect"),
+			PathBuf::from,
+		),
+	};
+	let verdict = bisect.step();
+	eprintln!("Bisect step: {}", verdict);
+	process::exit(verdict.exit_code());
+}
+
+fn b11r_command() -> App<'static, 'static> {
+	SubCommand::with_name("b11r")
+		.about("Assemble a block RLP, following the interface of geth's `evm b11r`")
+		.arg(
+			Arg::with_name("input.header")
+				.long("input.header")
+				.value_name("FILE")
+				.help("Header template JSON")
+				.takes_value(true)
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("input.txs")
+				.long("input.txs")
+				.value_name("FILE")
+				.help("JSON string of the hex-encoded RLP list of transactions")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("input.ommers")
+				.long("input.ommers")
+				.value_name("FILE")
+				.help("JSON array of hex-encoded ommer headers")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("input.withdrawals")
+				.long("input.withdrawals")
+				.value_name("FILE")
+				.help("JSON array of withdrawals")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("output.block")
+				.long("output.block")
+				.value_name("FILE")
+				.help("Where to write the block, or `stdout`")
+				.takes_value(true)
+				.default_value("stdout"),
+		)
+}
+
+/// Assemble a block from its header, transactions, ommers and withdrawals.
+fn run_b11r(matches: &ArgMatches) {
+	let inputs = b11r::Inputs {
+		header: matches.value_of("input.header").map(Path::new),
+		txs: matches.value_of("input.txs").map(Path::new),
+		ommers: matches.value_of("input.ommers").map(Path::new),
+		withdrawals: matches.value_of("input.withdrawals").map(Path::new),
+	};
+	let block = match b11r::run(&inputs) {
+		Ok(block) => serde_json::to_string_pretty(&block).unwrap(),
+		Err(err) => {
+			eprintln!("{}", err);
+			std::process::exit(1);
+		}
+	};
+	match matches.value_of("output.block").unwrap() {
+		"stdout" => println!("{}", block),
+		path => std::fs::write(path, block).expect("Write block failed"),
+	}
+}
+
+fn exec_command() -> App<'static, 'static> {
+	SubCommand::with_name("exec")
+		.about("Run raw bytecode, like geth's `evm run`")
+		.arg(
+			Arg::with_name("code")
+				.long("code")
+				.value_name("CODE")
+				.help(
+					"Code to run, hex or any fixture data encoding, defaults to the \
+					 code of the receiver in the pre-state",
+				)
+				.takes_value(true)
+				.required_unless("prestate"),
+		)
+		.arg(
+			Arg::with_name("prestate")
+				.long("prestate")
+				.value_name("FILE")
+				.help("State to run against, as a geth genesis alloc")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("post-alloc")
+				.long("post-alloc")
+				.value_name("FILE")
+				.help("Write the state after the call to FILE, as a geth genesis alloc")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("input")
+				.long("input")
+				.value_name("DATA")
+				.help("Calldata, hex or any fixture data encoding")
+				.takes_value(true)
+				.default_value("0x"),
+		)
+		.arg(
+			Arg::with_name("gas")
+				.long("gas")
+				.value_name("GAS")
+				.help("Gas limit of the call")
+				.takes_value(true)
+				.default_value("10000000"),
+		)
+		.arg(
+			Arg::with_name("value")
+				.long("value")
+				.value_name("WEI")
+				.help("Value sent with the call")
+				.takes_value(true)
+				.default_value("0"),
+		)
+		.arg(
+			Arg::with_name("fork")
+				.long("fork")
+				.value_name("FORK")
+				.help("Fork to run on")
+				.takes_value(true)
+				.default_value("Berlin"),
+		)
+		.arg(
+			Arg::with_name("trace")
+				.long("trace")
+				.help("Print an EIP-3155 trace to standard error"),
+		)
+		.arg(
+			Arg::with_name("expect-output")
+				.long("expect-output")
+				.value_name("DATA")
+				.help("Fail unless the call returns DATA, hex or any fixture data encoding")
+				.takes_value(true),
+		)
+}
+
+/// Run raw bytecode, printing its output, gas used and exit reason.
+fn run_exec(matches: &ArgMatches) {
+	let bytes = |name| {
+		matches.value_of(name).map(|value| {
+			encoding::decode(value).unwrap_or_else(|err| {
+				eprintln!("--{}: {}", name, err);
+				std::process::exit(1);
+			})
+		})
+	};
+	let pre_state = match matches.value_of("prestate") {
+		Some(path) => gethalloc::read(Path::new(path)).unwrap_or_else(|err| fail(err)),
+		None => BTreeMap::new(),
+	};
+	let options = exec::Options {
+		code: bytes("code"),
+		input: bytes("input").unwrap(),
+		gas: matches
+			.value_of("gas")
+			.unwrap()
+			.parse()
+			.expect("Invalid gas"),
+		value: U256::from_dec_str(matches.value_of("value").unwrap()).expect("Invalid value"),
+		fork: matches.value_of("fork").unwrap().to_string(),
+		trace: matches.is_present("trace"),
+		pre_state,
+	};
+	let report = exec::run(&options).unwrap_or_else(|err| fail(err));
+	if let Some(trace) = &report.trace {
+		eprint!("{}", trace);
+	}
+	println!("Output:      0x{}", hex::encode(&report.output));
+	println!("Gas used:    {}", report.used_gas);
+	println!("Exit reason: {:?}", report.exit_reason);
+	if let Some(path) = matches.value_of("post-alloc") {
+		gethalloc::write(Path::new(path), &report.post_state).unwrap_or_else(|err| fail(err));
+	}
+	if let Some(expected) = bytes("expect-output") {
+		report
+			.check_output(&expected)
+			.unwrap_or_else(|err| fail(err));
+	}
+}
+
+fn fill_command() -> App<'static, 'static> {
+	SubCommand::with_name("fill")
+		.about("Fill state test fillers into GeneralStateTests fixtures")
+		.arg(
+			Arg::with_name("FILE")
+				.help("Filler files")
+				.required(true)
+				.min_values(1),
+		)
+		.arg(
+			Arg::with_name("output")
+				.long("output")
+				.value_name("DIR")
+				.help("Directory to write fixtures into, named after their fillers")
+				.takes_value(true)
+				.required(true),
+		)
+}
+
+/// Fill state test fillers into fixtures of the output directory.
+fn run_fill(matches: &ArgMatches) {
+	let dir = Path::new(matches.value_of("output").unwrap());
+	std::fs::create_dir_all(dir).expect("Create output directory failed");
+	for file in matches.values_of("FILE").unwrap() {
+		let path = Path::new(file);
+		let filled = filler::fill_file(path).unwrap_or_else(|err| fail(err));
+		// `addFiller.json` fills into `add.json`, as upstream.
+		let stem = path.file_stem().unwrap().to_string_lossy();
+		let name = format!("{}.json", stem.trim_end_matches("Filler"));
+		let output = dir.join(name);
+		std::fs::write(&output, serde_json::to_string_pretty(&filled).unwrap())
+			.expect("Write fixture failed");
+		println!("{}", output.display());
+	}
+}
+
+fn stats_command() -> App<'static, 'static> {
+	SubCommand::with_name("stats")
+		.about(
+			"Report statistics of fixtures without executing them: tests by suite and \
+			 fork, transaction types, access lists, code sizes and supported forks",
+		)
+		.arg(
+			Arg::with_name("PATH")
+				.help("Fixture files or directories of them")
+				.required(true)
+				.min_values(1),
+		)
+}
+
+/// Print statistics of the fixture corpus.
+fn run_stats(matches: &ArgMatches) {
+	let mut total = stats::Stats::default();
+	for path in matches.values_of("PATH").unwrap() {
+		total.scan(Path::new(path));
+	}
+	print!("{}", total);
+}
+
+fn compress_fixtures_command() -> App<'static, 'static> {
+	SubCommand::with_name("compress-fixtures")
+		.about(
+			"Compress the JSON fixtures of a directory with zstd into .json.zst files, \
+			 which runs read like uncompressed ones",
+		)
+		.arg(
+			Arg::with_name("SRC")
+				.help("Fixture file or directory of them")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("OUT")
+				.help("Directory to write the compressed fixtures into, keeping their paths")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("level")
+				.long("level")
+				.value_name("LEVEL")
+				.help("zstd compression level")
+				.takes_value(true)
+				.default_value("19"),
+		)
+}
+
+/// Compress a tree of fixtures.
+fn run_compress_fixtures(matches: &ArgMatches) {
+	let src = Path::new(matches.value_of("SRC").unwrap());
+	let out = Path::new(matches.value_of("OUT").unwrap());
+	let level = matches
+		.value_of("level")
+		.unwrap()
+		.parse()
+		.expect("Invalid compression level");
+	let compressed = fixtures::compress_tree(src, out, level)
+		.unwrap_or_else(|err| fail(format!("Compress fixtures failed: {}", err)));
+	println!(
+		"{} fixtures compressed from {} to {} bytes into {}",
+		compressed.files,
+		compressed.bytes,
+		compressed.compressed_bytes,
+		out.display()
+	);
+}
+
+fn validate_fillers_command() -> App<'static, 'static> {
+	SubCommand::with_name("validate-fillers")
+		.about(
+			"Validate execution against the expect sections of fillers, such as those \
+			 of ethereum/tests src/GeneralStateTestsFiller, without filling them",
+		)
+		.arg(
+			Arg::with_name("PATH")
+				.help("Filler files or directories of them")
+				.required(true)
+				.min_values(1),
+		)
+}
+
+/// Check that fillers fill, exiting unsuccessfully if one fails.
+fn run_validate_fillers(matches: &ArgMatches) {
+	let mut total = filler::Validation::default();
+	let (mut passed, mut failed, mut unsupported) = (0, 0, 0);
+	for path in matches.values_of("PATH").unwrap() {
+		for (name, result) in filler::validate_files(Path::new(path)) {
+			match result {
+				Ok(validation) => {
+					passed += 1;
+					println!("{}", console::result_line(Status::Passed, &name, ""));
+					total.checked += validation.checked;
+					total.exceptions += validation.exceptions;
+					total.unsupported_forks.extend(validation.unsupported_forks);
+				}
+				Err(filler::FillError::Unsupported(reason)) => {
+					unsupported += 1;
+					println!("{}", console::result_line(Status::Skipped, &name, &reason));
+				}
+				Err(filler::FillError::Failed(reason)) => {
+					failed += 1;
+					println!("{}", console::result_line(Status::Failed, &name, &reason));
+				}
+			}
+		}
+	}
+	println!(
+		"{} passed, {} failed, {} unsupported; {} indexes checked, {} expecting exceptions",
+		passed, failed, unsupported, total.checked, total.exceptions
+	);
+	if !total.unsupported_forks.is_empty() {
+		let forks = total.unsupported_forks.into_iter().collect::<Vec<_>>();
+		println!("Unsupported forks: {}", forks.join(", "));
+	}
+	if failed > 0 {
+		process::exit(EXIT_FAILURES);
+	}
+}
+
+fn gen_state_command() -> App<'static, 'static> {
+	SubCommand::with_name("gen-state")
+		.about(
+			"Generate a state test with a seeded synthetic pre-state of any size, to \
+			 benchmark and soak the executor beyond the sizes of fixtures",
+		)
+		.arg(
+			Arg::with_name("output")
+				.long("output")
+				.value_name("FILE")
+				.help("Fixture file to write the filled test to")
+				.takes_value(true)
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("accounts")
+				.long("accounts")
+				.value_name("N")
+				.help("Number of accounts")
+				.takes_value(true)
+				.default_value("1000"),
+		)
+		.arg(
+			Arg::with_name("slots")
+				.long("slots")
+				.value_name("M")
+				.help("Storage slots of every account")
+				.takes_value(true)
+				.default_value("10"),
+		)
+		.arg(
+			Arg::with_name("code-size")
+				.long("code-size")
+				.value_name("K")
+				.help("Bytes of code of every account")
+				.takes_value(true)
+				.default_value("1024"),
+		)
+		.arg(
+			Arg::with_name("seed")
+				.long("seed")
+				.value_name("SEED")
+				.help("Seed the state is derived from")
+				.takes_value(true)
+				.default_value("0"),
+		)
+		.arg(
+			Arg::with_name("template")
+				.long("template")
+				.value_name("TEMPLATE")
+				.help("Transaction of the test, a value transfer or a contract call")
+				.takes_value(true)
+				.possible_values(&["transfer", "call"])
+				.default_value("transfer"),
+		)
+		.arg(
+			Arg::with_name("fork")
+				.long("fork")
+				.value_name("FORK")
+				.help("Forks to fill the test for")
+				.takes_value(true)
+				.multiple(true)
+				.number_of_values(1)
+				.default_value("Berlin"),
+		)
+		.arg(
+			Arg::with_name("alloc")
+				.long("alloc")
+				.value_name("FILE")
+				.help("Also write the pre-state to FILE, as a geth genesis alloc")
+				.takes_value(true),
+		)
+}
+
+/// Write a state test on a generated pre-state.
+fn run_gen_state(matches: &ArgMatches) {
+	let number = |name| {
+		matches
+			.value_of(name)
+			.unwrap()
+			.parse()
+			.unwrap_or_else(|_| fail(format!("Invalid --{}", name)))
+	};
+	let params = synthetic::Params {
+		accounts: number("accounts"),
+		slots: number("slots"),
+		code_size: number("code-size"),
+		seed: matches
+			.value_of("seed")
+			.unwrap()
+			.parse()
+			.unwrap_or_else(|_| fail("Invalid --seed".to_string())),
+		template: matches.value_of("template").unwrap().parse().unwrap(),
+		forks: matches
+			.values_of("fork")
+			.unwrap()
+			.map(String::from)
+			.collect(),
+	};
+	if let Some(path) = matches.value_of("alloc") {
+		let (state, _) = synthetic::pre_state(&params);
+		gethalloc::write(Path::new(path), &state).unwrap_or_else(|err| fail(err));
+	}
+	let fixture = synthetic::generate(&params).unwrap_or_else(|err| fail(err));
+	let output = Path::new(matches.value_of("output").unwrap());
+	fs::write(output, serde_json::to_string_pretty(&fixture).unwrap())
+		.expect("Write fixture failed");
+	println!("{}", output.display());
+}
+
+fn boundaries_command() -> App<'static, 'static> {
+	SubCommand::with_name("boundaries")
+		.about(
+			"Generate and fill a fixture calling precompiles with boundary inputs, \
+			 expecting what the builtins compute",
+		)
+		.arg(
+			Arg::with_name("output")
+				.long("output")
+				.value_name("DIR")
+				.help("Directory to write the fixture into")
+				.takes_value(true)
+				.required(true),
+		)
+}
+
+/// Fill the precompile boundaries test into the output directory.
+fn run_boundaries(matches: &ArgMatches) {
+	let dir = Path::new(matches.value_of("output").unwrap());
+	std::fs::create_dir_all(dir).expect("Create output directory failed");
+	let filler = boundaries::precompile_boundaries_filler();
+	let filled = filler::fill(boundaries::NAME, &filler).unwrap_or_else(|err| fail(err));
+	let output = dir.join(format!("{}.json", boundaries::NAME));
+	let mut fixture = serde_json::Map::new();
+	fixture.insert(boundaries::NAME.to_string(), filled);
+	std::fs::write(&output, serde_json::to_string_pretty(&fixture).unwrap())
+		.expect("Write fixture failed");
+	println!("{}", output.display());
+}
+
+fn timings_command() -> App<'static, 'static> {
+	SubCommand::with_name("timings")
+		.about(
+			"Show the slowest indexes of the last run recorded with \
+			 --record-timings, with their times in earlier runs, and flag the \
+			 ones more than twice as slow as in the previous run",
+		)
+		.arg(
+			Arg::with_name("FILE")
+				.help("Timings file written by --record-timings")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("top")
+				.long("top")
+				.value_name("N")
+				.help("Number of slowest indexes to show")
+				.takes_value(true)
+				.default_value("20"),
+		)
+}
+
+/// Print the slowest indexes of recorded timings.
+fn run_timings(matches: &ArgMatches) {
+	let timings =
+		Timings::load(Path::new(matches.value_of("FILE").unwrap())).unwrap_or_else(|err| fail(err));
+	let top = matches
+		.value_of("top")
+		.unwrap()
+		.parse()
+		.expect("Invalid number of indexes");
+	print!("{}", timings.report(top));
+}
+
+fn config_command() -> App<'static, 'static> {
+	SubCommand::with_name("config")
+		.about(
+			"Print the gasometer config and builtins a fork runs with as JSON, after \
+			 the chain spec and builtins schedule in use are applied",
+		)
+		.arg(
+			Arg::with_name("fork")
+				.long("fork")
+				.value_name("FORK")
+				.help("Fork to print the config of")
+				.takes_value(true)
+				.required(true),
+		)
+}
+
+/// Print the parameters of a fork.
+fn run_config(matches: &ArgMatches) {
+	let params =
+		params::describe(matches.value_of("fork").unwrap()).unwrap_or_else(|err| fail(err));
+	println!("{}", serde_json::to_string_pretty(&params).unwrap());
+}
+
+fn self_check_command() -> App<'static, 'static> {
+	SubCommand::with_name("self-check").about(
+		"Check the fixtures, builtins schedule, chain spec and trusted setup a run reads \
+		 resolve on this host, exiting with 1 if a configured one does not",
+	)
+}
+
+/// Print whether each resource a run reads resolves, under the global
+/// options of `matches`, and exit with 1 if a configured one does not.
+fn run_self_check(matches: &ArgMatches, config: &Config) -> ! {
+	let config = Config {
+		builtins_dir: path_or(matches, "builtins-dir", &config.builtins_dir),
+		chain_spec: path_or(matches, "chain-spec", &config.chain_spec),
+		..config.clone()
+	};
+	let checks = resources::check(&config, matches.value_of("trusted-setup").map(Path::new));
+	for check in &checks {
+		println!("{}", check);
+	}
+	process::exit(if checks.iter().any(resources::Check::failed) {
+		1
+	} else {
+		0
+	});
+}
+
+/// Load what the global options of `matches` and the config file select:
+/// the builtins schedule, precompiles, trusted setup, exception table, chain
+/// spec and validators. Returns the directory of the builtins schedule, read
+/// again when watching.
+fn apply_global_options(matches: &ArgMatches, config: &Config) -> Option<PathBuf> {
+	let builtins_dir = path_or(matches, "builtins-dir", &config.builtins_dir);
+	if let Some(dir) = &builtins_dir {
+		let schedule =
+			builtins::read_dir(dir).unwrap_or_else(|err| panic!("Load builtins failed: {}", err));
+		builtins::set_schedule(&schedule);
+	}
+	let implementation = matches.value_of("precompiles").unwrap().parse().unwrap();
+	precompiles::set_implementation(implementation).unwrap_or_else(|err| panic!("{}", err));
+	if matches.is_present("p256verify") {
+		#[cfg(feature = "p256verify")]
+		builtins::enable_p256verify();
+		#[cfg(not(feature = "p256verify"))]
+		panic!("--p256verify needs the p256verify feature");
+	}
+	if let Some(path) = matches.value_of("trusted-setup") {
+		kzg::set_trusted_setup(PathBuf::from(path)).unwrap_or_else(|err| panic!("{}", err));
+	}
+	if let Some(path) = matches.value_of("exceptions") {
+		let table = Exceptions::load(Path::new(path))
+			.unwrap_or_else(|err| panic!("Load exception table failed: {}", err));
+		exceptions::set_table(table).unwrap_or_else(|err| panic!("{}", err));
+	}
+	if let Some(path) = path_or(matches, "chain-spec", &config.chain_spec) {
+		let spec =
+			ChainSpec::load(&path).unwrap_or_else(|err| panic!("Load chain spec failed: {}", err));
+		chainspec::set_chain_spec(Some(spec));
+	}
+	for validator in matches.values_of("validator").into_iter().flatten() {
+		validators::register_parsed(validator).unwrap_or_else(|err| panic!("{}", err));
+	}
+	builtins_dir
+}
+
+fn watch_command() -> App<'static, 'static> {
+	SubCommand::with_name("watch")
+		.about(
+			"Rerun fixtures whose files change. To also rerun on evm source changes, \
+			 run under cargo-watch, e.g. `cargo watch -w ../evm -x 'run -- watch DIR'`",
+		)
+		.arg(
+			Arg::with_name("PATH")
+				.help("Fixture files or directories to watch")
+				.required(true)
+				.min_values(1),
+		)
+		.arg(
+			Arg::with_name("vm")
+				.long("vm")
+				.help("Treat fixtures as vm tests instead of state tests"),
+		)
+		.arg(
+			Arg::with_name("interval")
+				.long("interval")
+				.value_name("MS")
+				.help("Polling interval in milliseconds")
+				.takes_value(true)
+				.default_value("500"),
+		)
+}
+
+/// Run fixture files again whenever they change.
+fn run_watch(matches: &ArgMatches, builtins_dir: Option<&Path>) {
+	let paths = matches
+		.values_of("PATH")
+		.unwrap()
+		.map(PathBuf::from)
+		.collect::<Vec<_>>();
+	let interval = matches
+		.value_of("interval")
+		.unwrap()
+		.parse()
+		.expect("Invalid interval");
+	let vm = matches.is_present("vm");
+
+	watch(&paths, Duration::from_millis(interval), |file| {
+		// Pick up edits of the pricing schedule. An invalid one keeps the
+		// previous pricing so that the watch goes on.
+		if let Some(dir) = builtins_dir {
+			match builtins::read_dir(dir) {
+				Ok(schedule) => builtins::set_schedule(&schedule),
+				Err(err) => println!("Keeping previous builtins: {}", err),
+			}
+		}
+		run_watched(file, vm)
+	});
+}
+
+#[cfg(feature = "net")]
+fn fetch_command() -> App<'static, 'static> {
+	SubCommand::with_name("fetch")
+		.about("Download and cache a pinned fixture release")
+		.arg(
+			Arg::with_name("VERSION")
+				.help("Release tag or commit sha")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("source")
+				.long("source")
+				.value_name("REPO")
+				.help("Repository the fixtures are released from")
+				.takes_value(true)
+				.possible_values(&["ethereum/tests", "ethereum/execution-spec-tests"])
+				.default_value("ethereum/tests"),
+		)
+		.arg(
+			Arg::with_name("dir")
+				.long("dir")
+				.value_name("DIR")
+				.help("Cache directory")
+				.takes_value(true)
+				.default_value("fixtures"),
+		)
+		.arg(
+			Arg::with_name("sha256")
+				.long("sha256")
+				.value_name("HEX")
+				.help("Expected SHA-256 checksum of the archive")
+				.takes_value(true),
+		)
+}
+
+/// Download a pinned fixture release into the cache.
+#[cfg(feature = "net")]
+fn run_fetch(matches: &ArgMatches) {
+	let (path, checksum) = evm_jsontests::fetch::fetch(
+		matches.value_of("source").unwrap().parse().unwrap(),
+		matches.value_of("VERSION").unwrap(),
+		Path::new(matches.value_of("dir").unwrap()),
+		matches.value_of("sha256"),
+	)
+	.expect("Fetch fixtures failed");
+	println!("{} (sha256 {})", path.display(), checksum);
+}
+
+#[cfg(feature = "net")]
+fn replay_command() -> App<'static, 'static> {
+	SubCommand::with_name("replay")
+		.about(
+			"Replay a transaction on state read from a node, comparing gas used and \
+			 balances with the chain",
+		)
+		.arg(
+			Arg::with_name("HASH")
+				.help("Hash of the transaction")
+				.required(true),
+		)
+		.arg(
+			Arg::with_name("rpc")
+				.long("rpc")
+				.value_name("URL")
+				.help("JSON-RPC endpoint of an archive node")
+				.takes_value(true)
+				.required_unless("cassette"),
+		)
+		.arg(
+			Arg::with_name("record")
+				.long("record")
+				.value_name("FILE")
+				.help("Record every call made to the node into FILE")
+				.takes_value(true)
+				.conflicts_with("cassette"),
+		)
+		.arg(
+			Arg::with_name("cassette")
+				.long("cassette")
+				.value_name("FILE")
+				.help("Replay offline, answering calls from a recording made with --record")
+				.takes_value(true)
+				.conflicts_with("rpc"),
+		)
+		.arg(
+			Arg::with_name("fork")
+				.long("fork")
+				.value_name("FORK")
+				.help("Fork to replay on, defaults to the mainnet fork of the block")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("cache")
+				.long("cache")
+				.value_name("DIR")
+				.help("Directory state read from the node is cached in")
+				.takes_value(true),
+		)
+}
+
+/// Replay a transaction on state read from a node, exiting unsuccessfully
+/// if it diverges from the chain.
+#[cfg(feature = "net")]
+fn run_replay(matches: &ArgMatches) {
+	use evm_jsontests::cassette::Cassette;
+	use evm_jsontests::client::Client;
+
+	let hash = matches
+		.value_of("HASH")
+		.unwrap()
+		.parse()
+		.expect("Invalid transaction hash");
+	let client = match matches.value_of("cassette") {
+		Some(path) => {
+			Client::offline(Cassette::load(Path::new(path)).unwrap_or_else(|err| fail(err)))
+		}
+		None => Client::new(matches.value_of("rpc").unwrap()),
+	};
+	let client = if matches.is_present("record") {
+		client.record()
+	} else {
+		client
+	};
+	let report = evm_jsontests::replay::replay(
+		&client,
+		hash,
+		matches.value_of("fork"),
+		matches.value_of("cache").map(Path::new),
+	);
+	if let (Some(path), Some(cassette)) = (matches.value_of("record"), client.recorded()) {
+		cassette
+			.save(Path::new(path))
+			.unwrap_or_else(|err| fail(err));
+	}
+	let report = report.unwrap_or_else(|err| fail(err));
+	print!("{}", report);
+	if report.diverged() {
+		std::process::exit(1);
+	}
+}
+
+fn main() {
+	let app = App::new("jsontests")
+		.version("0.1.0")
+		.author("Wei Tang <hi@that.world>")
+		.about("EVM json test utilities")
+		.after_help(
+			"Test runs exit with 0 if every test passed, 1 on failures, 2 if a fixture \
+			 file could not be parsed and 3 if no test was run.",
+		)
+		.arg(
+			Arg::with_name("config")
+				.long("config")
+				.value_name("FILE")
+				.help("Config file with default settings, defaults to ./evm-tests.toml")
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("builtins-dir")
+				.long("builtins-dir")
+				.value_name("DIR")
+				.help(
+					"Directory with the builtins.json pricing schedule of precompiles, \
+					 defaults to the built-in schedule",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("precompiles")
+				.long("precompiles")
+				.value_name("IMPL")
+				.help(
+					"Run precompiles natively, on OpenEthereum's builtins or on both, failing \
+					 where they differ; the last two need the ethcore feature",
+				)
+				.takes_value(true)
+				.possible_values(&["native", "builtin_json", "cross_check"])
+				.default_value("native"),
+		)
+		.arg(Arg::with_name("p256verify").long("p256verify").help(
+			"Add the secp256r1 verification precompile of RIP-7212 at 0x100 to every \
+					 fork, needs the p256verify feature",
+		))
+		.arg(
+			Arg::with_name("trusted-setup")
+				.long("trusted-setup")
+				.value_name("FILE")
+				.help(
+					"KZG trusted setup of the point evaluation precompile in the c-kzg text \
+					 format, defaults to the mainnet one",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("exceptions")
+				.long("exceptions")
+				.value_name("FILE")
+				.help(
+					"TOML table of the exceptions fixtures expect and the rejections and exit \
+					 reasons standing for them, replacing the built-in one",
+				)
+				.takes_value(true),
+		)
+		.arg(
+			Arg::with_name("validator")
+				.long("validator")
+				.value_name("NAME=VALUE")
+				.help(
+					"Fail every state test index breaking an invariant: max-balance=WEI for \
+					 no account over a balance, no-precompile=ADDRESS for a precompile never \
+					 called",
+				)
+				.takes_value(true)
+				.multiple(true)
+				.number_of_values(1),
+		)
+		.arg(
+			Arg::with_name("chain-spec")
+				.long("chain-spec")
+				.value_name("FILE")
+				.help(
+					"JSON chain spec of a custom chain: run every test only under the fork \
+					 the chain follows at its block, with its chain id and gas table",
+				)
+				.takes_value(true),
+		)
+		.subcommand(vm_command())
+		.subcommand(state_command())
+		.subcommand(auto_command())
+		.subcommand(blockchain_command())
+		.subcommand(debug_command())
+		.subcommand(explain_command())
+		.subcommand(trace_diff_command())
+		.subcommand(compare_command())
+		.subcommand(retesteth_command())
+		.subcommand(serve_command())
+		.subcommand(replay_precompiles_command())
+		.subcommand(reproduce_command())
+		.subcommand(minimize_command())
+		.subcommand(select_corpus_command())
+		.subcommand(bisect_command())
+		.subcommand(b11r_command())
+		.subcommand(exec_command())
+		.subcommand(fill_command())
+		.subcommand(stats_command())
+		.subcommand(compress_fixtures_command())
+		.subcommand(validate_fillers_command())
+		.subcommand(gen_state_command())
+		.subcommand(boundaries_command())
+		.subcommand(timings_command())
+		.subcommand(config_command())
+		.subcommand(self_check_command())
+		.subcommand(watch_command());
+	#[cfg(feature = "net")]
+	let app = app.subcommand(fetch_command());
+	#[cfg(feature = "net")]
+	let app = app.subcommand(replay_command());
+	let matches = app.get_matches();
+	capture::capture_panics();
+	crash::record_backtraces();
+	console::set_color(console::color_by_default());
+	let config = Config::discover(matches.value_of("config").map(Path::new))
+		.unwrap_or_else(|err| panic!("Load config failed: {}", err));
+	// Resolved before anything loads them, to report every resource that
+	// does not instead of the first.
+	if matches.subcommand_matches("self-check").is_some() {
+		run_self_check(&matches, &config);
+	}
+	let builtins_dir = apply_global_options(&matches, &config);
+
+	match matches.subcommand() {
+		("vm", Some(matches)) => run_vm(matches, &config),
+		("state", Some(matches)) => run_state(matches, &config),
+		("auto", Some(matches)) => run_auto(matches, &config),
+		("blockchain", Some(matches)) => run_blockchain(matches, &config),
+		("debug", Some(matches)) => run_debug(matches),
+		("explain", Some(matches)) => run_explain(matches),
+		("trace-diff", Some(matches)) => run_trace_diff(matches),
+		("compare", Some(matches)) => run_compare(matches),
+		("retesteth", Some(matches)) => run_retesteth(matches),
+		("serve", Some(matches)) => run_serve(matches),
+		("replay-precompiles", Some(matches)) => run_replay_precompiles(matches),
+		("reproduce", Some(matches)) => run_reproduce(matches),
+		("minimize", Some(matches)) => run_minimize(matches),
+		("select-corpus", Some(matches)) => run_select_corpus(matches, &config),
+		("bisect", Some(matches)) => run_bisect(matches),
+		("b11r", Some(matches)) => run_b11r(matches),
+		("exec", Some(matches)) => run_exec(matches),
+		("fill", Some(matches)) => run_fill(matches),
+		("stats", Some(matches)) => run_stats(matches),
+		("compress-fixtures", Some(matches)) => run_compress_fixtures(matches),
+		("validate-fillers", Some(matches)) => run_validate_fillers(matches),
+		("gen-state", Some(matches)) => run_gen_state(matches),
+		("boundaries", Some(matches)) => run_boundaries(matches),
+		("timings", Some(matches)) => run_timings(matches),
+		("config", Some(matches)) => run_config(matches),
+		("watch", Some(matches)) => run_watch(matches, builtins_dir.as_deref()),
+		#[cfg(feature = "net")]
+		("fetch", Some(matches)) => run_fetch(matches),
+		#[cfg(feature = "net")]
+		("replay", Some(matches)) => run_replay(matches),
+		_ => (),
 	}
 }
@@ -0,0 +1,130 @@
+//! Expected totals of a fixture release: how many post states or chains
+//! every fork executes. A run executing fewer than its manifest expects
+//! skipped them without failing, e.g. because a fork stopped being
+//! supported, and fails for it.
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+/// Counts a release is expected to execute.
+///
+/// ```toml
+/// release = "v10.4"
+/// tolerance = 0.01
+///
+/// [forks]
+/// Istanbul = 13391
+/// Berlin = 13402
+/// ```
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+	/// Fixture release the counts are of.
+	pub release: String,
+	/// Fraction of the expected count a fork may fall short by.
+	#[serde(default)]
+	pub tolerance: f64,
+	pub forks: BTreeMap<String, usize>,
+}
+
+/// A fork that executed fewer than expected.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shortfall {
+	pub fork: String,
+	pub expected: usize,
+	pub executed: usize,
+}
+
+impl fmt::Display for Shortfall {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} executed {} of the {} expected",
+			self.fork, self.executed, self.expected
+		)
+	}
+}
+
+impl Manifest {
+	/// Manifest of `release` expecting the counts of a run, `executed` by
+	/// fork.
+	pub fn record(release: &str, executed: &BTreeMap<String, usize>) -> Self {
+		Manifest {
+			release: release.to_string(),
+			tolerance: 0.0,
+			forks: executed.clone(),
+		}
+	}
+
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let toml =
+			fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+		let manifest: Manifest =
+			toml::from_str(&toml).map_err(|err| format!("{}: {}", path.display(), err))?;
+		if !(0.0..=1.0).contains(&manifest.tolerance) {
+			return Err(format!(
+				"{}: tolerance {} is not a fraction",
+				path.display(),
+				manifest.tolerance
+			));
+		}
+		Ok(manifest)
+	}
+
+	pub fn write(&self, path: &Path) -> Result<(), String> {
+		let toml = toml::to_string(self).map_err(|err| err.to_string())?;
+		fs::write(path, toml).map_err(|err| format!("{}: {}", path.display(), err))
+	}
+
+	/// Forks of the manifest whose `executed` count falls short of the
+	/// expected one by more than the tolerance.
+	pub fn shortfalls(&self, executed: &BTreeMap<String, usize>) -> Vec<Shortfall> {
+		self.forks
+			.iter()
+			.filter_map(|(fork, &expected)| {
+				let executed = executed.get(fork).copied().unwrap_or(0);
+				let allowed = (expected as f64 * self.tolerance).floor() as usize;
+				if executed + allowed < expected {
+					Some(Shortfall {
+						fork: fork.clone(),
+						expected,
+						executed,
+					})
+				} else {
+					None
+				}
+			})
+			.collect()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn shortfalls_beyond_tolerance() {
+		let manifest: Manifest = toml::from_str(
+			"release = \"v10.4\"\ntolerance = 0.1\n[forks]\nIstanbul = 100\nBerlin = 100\n",
+		)
+		.unwrap();
+		let executed = [("Istanbul".to_string(), 90), ("London".to_string(), 5)]
+			.iter()
+			.cloned()
+			.collect::<BTreeMap<_, _>>();
+		assert_eq!(
+			manifest.shortfalls(&executed),
+			vec![Shortfall {
+				fork: "Berlin".to_string(),
+				expected: 100,
+				executed: 0,
+			}]
+		);
+		assert!(Manifest::record("v10.4", &executed)
+			.shortfalls(&executed)
+			.is_empty());
+	}
+}
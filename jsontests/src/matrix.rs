@@ -0,0 +1,293 @@
+//! Runs of the same state fixtures under a matrix of configurations, such as
+//! native against OpenEthereum precompiles or different stack sizes of the
+//! test threads, each in a child process of the runner with its own flags
+//! and environment. Indexes whose result depends on the configuration are
+//! reported: they are the ones that break on some platforms only.
+//!
+//! ```json
+//! {
+//!   "configurations": [
+//!     { "name": "native" },
+//!     { "name": "builtin-json", "args": ["--precompiles", "builtin_json"] },
+//!     { "name": "small-stack", "env": { "EVM_JSONTESTS_STACK_SIZE": "4194304" } }
+//!   ]
+//! }
+//! ```
+
+use crate::compare::Key;
+use crate::report::{self, IndexReport};
+use serde::Deserialize;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+
+/// Flags of the `state` command a child run is not given, as the matrix run
+/// sets them itself or they are only read by the parent.
+const PARENT_FLAGS: &[&str] = &["--matrix", "--json-report", "--html-report"];
+
+/// Flags and environment of one run of the matrix.
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Configuration {
+	pub name: String,
+	/// Flags given before the `state` command, such as `--precompiles`.
+	#[serde(default)]
+	pub args: Vec<String>,
+	/// Flags of the `state` command, such as `--compare-impl`.
+	#[serde(default)]
+	pub state_args: Vec<String>,
+	/// Variables set in the environment of the run.
+	#[serde(default)]
+	pub env: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+#[serde(deny_unknown_fields)]
+pub struct Matrix {
+	pub configurations: Vec<Configuration>,
+}
+
+impl Matrix {
+	pub fn parse(json: &str) -> Result<Self, String> {
+		let matrix: Matrix = serde_json::from_str(json).map_err(|err| err.to_string())?;
+		if matrix.configurations.len() < 2 {
+			return Err("a matrix needs at least two configurations".to_string());
+		}
+		let mut names = BTreeSet::new();
+		for configuration in &matrix.configurations {
+			if !names.insert(&configuration.name) {
+				return Err(format!(
+					"configuration {} is defined twice",
+					configuration.name
+				));
+			}
+		}
+		Ok(matrix)
+	}
+
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let json =
+			fs::read_to_string(path).map_err(|err| format!("{}: {}", path.display(), err))?;
+		Self::parse(&json).map_err(|err| format!("{}: {}", path.display(), err))
+	}
+}
+
+/// Arguments of the run of `configuration`, from the arguments `parent` of
+/// the matrix run without its program name, writing its JSON report to
+/// `report`.
+pub fn child_args(parent: &[String], configuration: &Configuration, report: &Path) -> Vec<String> {
+	let command = parent
+		.iter()
+		.position(|arg| arg == "state")
+		.unwrap_or(parent.len());
+	let mut args = parent[..command].to_vec();
+	args.extend(configuration.args.iter().cloned());
+	args.push("state".to_string());
+	args.extend(configuration.state_args.iter().cloned());
+	let mut rest = parent.iter().skip(command + 1);
+	while let Some(arg) = rest.next() {
+		let flag = arg.split('=').next().unwrap_or_default();
+		if PARENT_FLAGS.contains(&flag) {
+			if !arg.contains('=') {
+				rest.next();
+			}
+			continue;
+		}
+		args.push(arg.clone());
+	}
+	args.push("--json-report".to_string());
+	args.push(report.display().to_string());
+	args
+}
+
+/// Run every configuration of `matrix` with `binary`, returning the report
+/// of each run by configuration name.
+pub fn run(
+	matrix: &Matrix,
+	binary: &Path,
+	parent: &[String],
+) -> Result<Vec<(String, Vec<IndexReport>)>, String> {
+	matrix
+		.configurations
+		.iter()
+		.enumerate()
+		.map(|(position, configuration)| {
+			let report = std::env::temp_dir().join(format!(
+				"evm-jsontests-matrix-{}-{}.jsonl",
+				process::id(),
+				position
+			));
+			fs::write(&report, "").map_err(|err| format!("{}: {}", report.display(), err))?;
+			eprintln!("Run configuration {}", configuration.name);
+			let status = Command::new(binary)
+				.args(child_args(parent, configuration, &report))
+				.envs(&configuration.env)
+				.status()
+				.map_err(|err| format!("run {} failed: {}", binary.display(), err))?;
+			let results = report::load(&report);
+			let _ = fs::remove_file(&report);
+			let results = results.map_err(|err| format!("{}: {}", report.display(), err))?;
+			// Failing indexes exit with 1, any other status is a crash, such
+			// as a stack overflow, which the indexes it did not get to show.
+			if !matches!(status.code(), Some(0) | Some(1)) {
+				eprintln!("Configuration {} crashed, {}", configuration.name, status);
+			}
+			Ok((configuration.name.clone(), results))
+		})
+		.collect()
+}
+
+/// What configurations of an index are compared on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Observed {
+	pub passed: bool,
+	pub used_gas: Option<u64>,
+	pub output: Option<String>,
+}
+
+impl fmt::Display for Observed {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(if self.passed { "passed" } else { "failed" })?;
+		if let Some(gas) = self.used_gas {
+			write!(f, " using {} gas", gas)?;
+		}
+		if let Some(output) = &self.output {
+			write!(f, " returning 0x{}", output)?;
+		}
+		Ok(())
+	}
+}
+
+/// An index whose result is not the same under every configuration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+	pub key: Key,
+	/// Result under each configuration, `None` where it was not executed.
+	pub observed: Vec<(String, Option<Observed>)>,
+}
+
+impl fmt::Display for Divergence {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{} depends on the configuration:", self.key)?;
+		for (name, observed) in &self.observed {
+			match observed {
+				Some(observed) => write!(f, "\n  {}: {}", name, observed)?,
+				None => write!(f, "\n  {}: not executed", name)?,
+			}
+		}
+		Ok(())
+	}
+}
+
+/// Indexes of `runs` whose result differs between configurations.
+pub fn divergences(runs: &[(String, Vec<IndexReport>)]) -> Vec<Divergence> {
+	let mut by_key: BTreeMap<Key, Vec<Option<Observed>>> = BTreeMap::new();
+	for (position, (_, results)) in runs.iter().enumerate() {
+		for result in results {
+			let key = Key {
+				name: result.name.clone(),
+				fork: result.fork.clone(),
+				index: result.index,
+			};
+			by_key.entry(key).or_insert_with(|| vec![None; runs.len()])[position] =
+				Some(Observed {
+					passed: result.passed,
+					used_gas: result.used_gas,
+					output: result.output.clone(),
+				});
+		}
+	}
+	by_key
+		.into_iter()
+		.filter(|(_, observed)| observed.iter().any(|other| *other != observed[0]))
+		.map(|(key, observed)| Divergence {
+			key,
+			observed: runs
+				.iter()
+				.map(|(name, _)| name.clone())
+				.zip(observed)
+				.collect(),
+		})
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn args(args: &[&str]) -> Vec<String> {
+		args.iter().map(|arg| arg.to_string()).collect()
+	}
+
+	#[test]
+	fn child_runs() {
+		assert!(Matrix::parse(r#"{ "configurations": [{ "name": "native" }] }"#).is_err());
+		let matrix = Matrix::parse(
+			r#"{ "configurations": [
+				{ "name": "native" },
+				{ "name": "json", "args": ["--precompiles", "builtin_json"], "env": { "A": "1" } }
+			] }"#,
+		)
+		.unwrap();
+		assert_eq!(matrix.configurations[1].env["A"], "1");
+
+		let parent = args(&[
+			"--config",
+			"ci.toml",
+			"state",
+			"--matrix",
+			"matrix.json",
+			"--json-report=report.jsonl",
+			"-j",
+			"4",
+			"fixtures",
+		]);
+		assert_eq!(
+			child_args(&parent, &matrix.configurations[1], Path::new("child.jsonl")),
+			args(&[
+				"--config",
+				"ci.toml",
+				"--precompiles",
+				"builtin_json",
+				"state",
+				"-j",
+				"4",
+				"fixtures",
+				"--json-report",
+				"child.jsonl",
+			])
+		);
+	}
+
+	#[test]
+	fn divergent_indexes() {
+		let result = |name: &str, passed, used_gas| IndexReport {
+			name: name.to_string(),
+			fork: "Berlin".to_string(),
+			passed,
+			time_us: 0,
+			used_gas: Some(used_gas),
+			..Default::default()
+		};
+		let runs = vec![
+			(
+				"native".to_string(),
+				vec![result("add", true, 21000), result("sub", true, 21000)],
+			),
+			(
+				"json".to_string(),
+				vec![result("add", true, 21000), result("sub", false, 21000)],
+			),
+		];
+		let divergences = divergences(&runs);
+		assert_eq!(divergences.len(), 1);
+		assert_eq!(divergences[0].key.name, "sub");
+		assert_eq!(
+			divergences[0].to_string(),
+			"sub:Berlin:0 depends on the configuration:\n  native: passed using 21000 gas\n  \
+			 json: failed using 21000 gas"
+		);
+	}
+}
@@ -0,0 +1,266 @@
+//! Prometheus metrics of a running test run, served with `--metrics-addr`
+//! so long conformance runs on shared machines can be watched and alerted
+//! on. Runs that stall show as `evm_jsontests_last_test_timestamp_seconds`
+//! falling behind while `evm_jsontests_running` is 1.
+//!
+//! Gas is counted for the indexes of state tests run in this process, not
+//! for those of `--isolate process` children.
+
+use once_cell::sync::Lazy;
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct State {
+	running: bool,
+	started: Option<Instant>,
+	planned: usize,
+	passed: usize,
+	failed: usize,
+	skipped: usize,
+	/// Seconds since the epoch the last test completed at.
+	last_test: Option<f64>,
+	/// Time spent running the tests of each suite.
+	suites: BTreeMap<String, Duration>,
+}
+
+/// Metrics of the run of this process.
+#[derive(Debug, Default)]
+pub struct Metrics {
+	state: Mutex<State>,
+	gas: AtomicU64,
+}
+
+static METRICS: Lazy<Metrics> = Lazy::new(Metrics::default);
+
+/// The metrics the server of `serve` exposes.
+pub fn global() -> &'static Metrics {
+	&METRICS
+}
+
+fn now() -> f64 {
+	SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map_or(0.0, |now| now.as_secs_f64())
+}
+
+/// Escape `value` for a label.
+fn label(value: &str) -> String {
+	value
+		.replace('\\', "\\\\")
+		.replace('"', "\\\"")
+		.replace('\n', "\\n")
+}
+
+impl Metrics {
+	/// Start a run of `planned` tests.
+	pub fn start(&self, planned: usize) {
+		let mut state = self.state.lock().unwrap();
+		state.running = true;
+		state.started.get_or_insert_with(Instant::now);
+		state.planned += planned;
+	}
+
+	pub fn finish(&self) {
+		self.state.lock().unwrap().running = false;
+	}
+
+	/// Count a test of `suite` that ran for `elapsed`, passing if `passed`.
+	pub fn test_done(&self, suite: Option<&str>, passed: bool, elapsed: Duration) {
+		let mut state = self.state.lock().unwrap();
+		if passed {
+			state.passed += 1;
+		} else {
+			state.failed += 1;
+		}
+		state.last_test = Some(now());
+		if let Some(suite) = suite {
+			*state.suites.entry(suite.to_string()).or_default() += elapsed;
+		}
+	}
+
+	pub fn test_skipped(&self) {
+		self.state.lock().unwrap().skipped += 1;
+	}
+
+	/// Count `gas` used by an executed index.
+	pub fn add_gas(&self, gas: u64) {
+		self.gas.fetch_add(gas, Ordering::Relaxed);
+	}
+
+	/// The metrics in the Prometheus text format.
+	pub fn render(&self) -> String {
+		let state = self.state.lock().unwrap().clone();
+		let gas = self.gas.load(Ordering::Relaxed);
+		let elapsed = state
+			.started
+			.map_or(0.0, |started| started.elapsed().as_secs_f64());
+		let mut out = String::new();
+		let mut metric = |name: &str, kind: &str, help: &str, samples: &[(String, String)]| {
+			writeln!(out, "# HELP evm_jsontests_{} {}", name, help).unwrap();
+			writeln!(out, "# TYPE evm_jsontests_{} {}", name, kind).unwrap();
+			for (labels, value) in samples {
+				writeln!(out, "evm_jsontests_{}{} {}", name, labels, value).unwrap();
+			}
+		};
+		metric(
+			"running",
+			"gauge",
+			"Whether tests are running.",
+			&[(String::new(), (state.running as u8).to_string())],
+		);
+		metric(
+			"tests_planned",
+			"gauge",
+			"Tests the run selected.",
+			&[(String::new(), state.planned.to_string())],
+		);
+		metric(
+			"tests_total",
+			"counter",
+			"Tests completed, by result.",
+			&[
+				("{result=\"passed\"}".to_string(), state.passed.to_string()),
+				("{result=\"failed\"}".to_string(), state.failed.to_string()),
+				(
+					"{result=\"skipped\"}".to_string(),
+					state.skipped.to_string(),
+				),
+			],
+		);
+		metric(
+			"gas_used_total",
+			"counter",
+			"Gas used by the executed indexes of state tests.",
+			&[(String::new(), gas.to_string())],
+		);
+		let gas_per_second = if elapsed > 0.0 {
+			gas as f64 / elapsed
+		} else {
+			0.0
+		};
+		metric(
+			"gas_per_second",
+			"gauge",
+			"Gas used per second of the run so far.",
+			&[(String::new(), format!("{:.0}", gas_per_second))],
+		);
+		metric(
+			"elapsed_seconds",
+			"gauge",
+			"Time since the run started.",
+			&[(String::new(), format!("{:.3}", elapsed))],
+		);
+		if let Some(last_test) = state.last_test {
+			metric(
+				"last_test_timestamp_seconds",
+				"gauge",
+				"Time the last test completed at.",
+				&[(String::new(), format!("{:.3}", last_test))],
+			);
+		}
+		let suites = state
+			.suites
+			.iter()
+			.map(|(suite, duration)| {
+				(
+					format!("{{suite=\"{}\"}}", label(suite)),
+					format!("{:.3}", duration.as_secs_f64()),
+				)
+			})
+			.collect::<Vec<_>>();
+		metric(
+			"suite_duration_seconds",
+			"counter",
+			"Time spent running the tests of each suite.",
+			&suites,
+		);
+		out
+	}
+}
+
+fn respond(metrics: &Metrics, stream: TcpStream) -> io::Result<()> {
+	let mut reader = BufReader::new(stream.try_clone()?);
+	let mut line = String::new();
+	reader.read_line(&mut line)?;
+	// The rest of the request is not needed.
+	loop {
+		let mut header = String::new();
+		if reader.read_line(&mut header)? == 0 || header.trim_end().is_empty() {
+			break;
+		}
+	}
+	let body = metrics.render();
+	let mut writer = stream;
+	write!(
+		writer,
+		"HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\n\
+		 Connection: close\r\n\r\n{}",
+		body.len(),
+		body
+	)?;
+	writer.flush()
+}
+
+/// Serve the [`global`] metrics on `addr` from a background thread.
+pub fn serve<A: ToSocketAddrs>(addr: A) -> io::Result<()> {
+	let listener = TcpListener::bind(addr)?;
+	thread::spawn(move || {
+		for stream in listener.incoming() {
+			if let Err(err) = stream.and_then(|stream| respond(global(), stream)) {
+				eprintln!("Metrics connection error: {}", err);
+			}
+		}
+	});
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::io::Read;
+
+	#[test]
+	fn prometheus_format() {
+		let metrics = Metrics::default();
+		metrics.start(3);
+		metrics.test_done(Some("stExample"), true, Duration::from_millis(1500));
+		metrics.test_done(None, false, Duration::from_millis(10));
+		metrics.test_skipped();
+		metrics.add_gas(21000);
+		let rendered = metrics.render();
+		assert!(rendered.contains("evm_jsontests_running 1\n"));
+		assert!(rendered.contains("evm_jsontests_tests_planned 3\n"));
+		assert!(rendered.contains("evm_jsontests_tests_total{result=\"passed\"} 1\n"));
+		assert!(rendered.contains("evm_jsontests_tests_total{result=\"failed\"} 1\n"));
+		assert!(rendered.contains("evm_jsontests_gas_used_total 21000\n"));
+		assert!(
+			rendered.contains("evm_jsontests_suite_duration_seconds{suite=\"stExample\"} 1.500\n")
+		);
+		assert!(rendered.contains("# TYPE evm_jsontests_tests_total counter\n"));
+		metrics.finish();
+		assert!(metrics.render().contains("evm_jsontests_running 0\n"));
+		assert_eq!(label("a\"b"), "a\\\"b");
+	}
+
+	#[test]
+	fn served() {
+		global().add_gas(1);
+		let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+		let addr = listener.local_addr().unwrap();
+		drop(listener);
+		serve(addr).unwrap();
+		let mut stream = TcpStream::connect(addr).unwrap();
+		stream.write_all(b"GET /metrics HTTP/1.1\r\n\r\n").unwrap();
+		let mut response = String::new();
+		stream.read_to_string(&mut response).unwrap();
+		assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+		assert!(response.contains("evm_jsontests_gas_used_total "));
+	}
+}
@@ -0,0 +1,263 @@
+//! Shrinking of a failing state test to a small fixture failing the same
+//! way, for bug reports against the evm crate. The failing index is kept
+//! alone, then accounts, their storage and the calldata are cut down by
+//! delta debugging as long as the failure keeps its signature.
+//!
+//! A failure only of the expected post-state cannot be kept: any change to
+//! the pre-state changes the root. Candidates are run ignoring it, so what
+//! is shrunk is a crash, a panic or a failed invariant of the fork.
+
+use crate::embed;
+use crate::state;
+use lazy_static::lazy_static;
+use regex::Regex;
+use serde_json::{Map, Value};
+use std::cmp;
+use std::fmt;
+
+lazy_static! {
+	static ref NUMBER: Regex = Regex::new(r"0x[0-9a-fA-F]+|[0-9]+").unwrap();
+}
+
+/// First line of a failure message with its numbers left out, so that
+/// amounts of gas, lengths and hashes may change as the fixture shrinks.
+pub fn signature(message: &str) -> String {
+	NUMBER
+		.replace_all(message.lines().next().unwrap_or_default(), "N")
+		.into_owned()
+}
+
+/// Smallest subset of `items` found for which `fails` still holds: the
+/// complements of ever smaller chunks are tried, keeping any still failing.
+pub fn ddmin<T: Clone>(mut items: Vec<T>, mut fails: impl FnMut(&[T]) -> bool) -> Vec<T> {
+	if items.is_empty() || fails(&[]) {
+		return Vec::new();
+	}
+	let mut chunks = 2;
+	while items.len() >= 2 {
+		let size = items.len().div_ceil(chunks);
+		let reduced = (0..items.len()).step_by(size).find_map(|start| {
+			let end = cmp::min(start + size, items.len());
+			let complement = [&items[..start], &items[end..]].concat();
+			if fails(&complement) {
+				Some(complement)
+			} else {
+				None
+			}
+		});
+		match reduced {
+			Some(complement) => {
+				items = complement;
+				chunks = cmp::max(chunks - 1, 2);
+			}
+			None if chunks >= items.len() => break,
+			None => chunks = cmp::min(chunks * 2, items.len()),
+		}
+	}
+	items
+}
+
+/// Accounts, storage slots and calldata bytes of a test.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Size {
+	pub accounts: usize,
+	pub slots: usize,
+	pub calldata: usize,
+}
+
+impl Size {
+	fn of(test: &Value) -> Self {
+		let pre = test["pre"].as_object().cloned().unwrap_or_default();
+		Size {
+			accounts: pre.len(),
+			slots: pre
+				.values()
+				.map(|account| account["storage"].as_object().map_or(0, Map::len))
+				.sum(),
+			calldata: test["transaction"]["data"].as_array().map_or(0, |data| {
+				data.iter()
+					.filter_map(calldata)
+					.map(|data| data.len())
+					.sum()
+			}),
+		}
+	}
+}
+
+/// A minimized test and what it was cut down from.
+#[derive(Debug, Clone)]
+pub struct Minimized {
+	pub name: String,
+	pub fork: String,
+	pub signature: String,
+	/// The fixture of the minimized test alone.
+	pub fixture: Value,
+	pub before: Size,
+	pub after: Size,
+}
+
+impl fmt::Display for Minimized {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(
+			f,
+			"Minimized {} on {}: {}",
+			self.name, self.fork, self.signature
+		)?;
+		write!(
+			f,
+			"{} -> {} accounts, {} -> {} storage slots, {} -> {} calldata bytes",
+			self.before.accounts,
+			self.after.accounts,
+			self.before.slots,
+			self.after.slots,
+			self.before.calldata,
+			self.after.calldata
+		)
+	}
+}
+
+fn calldata(data: &Value) -> Option<Vec<u8>> {
+	hex::decode(data.as_str()?.strip_prefix("0x")?).ok()
+}
+
+/// Signature of the failure of index `index` of `fork` of `test`, or `None`
+/// if it passes. Crashes are caught in a child process where there is one.
+fn failure(name: &str, test: &Value, fork: &str, index: usize) -> Option<String> {
+	let mut fixture = Map::new();
+	fixture.insert(name.to_string(), test.clone());
+	let fixture = serde_json::to_vec(&fixture).unwrap();
+	let options = state::Options {
+		forks: vec![fork.to_string()],
+		indexes: vec![index],
+		quiet: true,
+		ignore_post_state: true,
+		..Default::default()
+	};
+	let run = || match embed::run_state(&fixture, &options) {
+		Ok(results) => results.into_iter().find_map(|result| result.outcome.err()),
+		Err(err) => Some(err),
+	};
+	#[cfg(unix)]
+	let message =
+		crate::isolate::isolated(run).unwrap_or_else(|crash| Some(format!("Crashed: {}", crash)));
+	#[cfg(not(unix))]
+	let message = run();
+	message.map(|message| signature(&message))
+}
+
+/// `test` with only the index `index` of `fork`, and only the data, gas
+/// limit and value it selects.
+fn single_index(test: &Value, fork: &str, index: usize) -> Option<Value> {
+	let mut post = test["post"][fork][index].clone();
+	let mut test = test.clone();
+	for &(field, selector) in &[("data", "data"), ("gasLimit", "gas"), ("value", "value")] {
+		let selected = post["indexes"][selector].as_u64()? as usize;
+		let value = test["transaction"][field].get(selected)?.clone();
+		test["transaction"][field] = Value::Array(vec![value]);
+		post["indexes"][selector] = Value::from(0);
+	}
+	let mut forks = Map::new();
+	forks.insert(fork.to_string(), Value::Array(vec![post]));
+	test["post"] = Value::Object(forks);
+	Some(test)
+}
+
+/// Shrink the test `name` of the state fixture `json`, or its first test
+/// with a failing index. Tests failing only their post-state are not.
+pub fn minimize(json: &[u8], name: Option<&str>) -> Result<Minimized, String> {
+	let tests: Map<String, Value> = serde_json::from_slice(json).map_err(|err| err.to_string())?;
+	let failing = tests
+		.iter()
+		.filter(|(test_name, _)| name.map_or(true, |name| name == test_name.as_str()))
+		.find_map(|(name, test)| {
+			let post = test["post"].as_object()?;
+			post.iter().find_map(|(fork, states)| {
+				(0..states.as_array()?.len()).find_map(|index| {
+					let signature = failure(name, test, fork, index)?;
+					Some((name, test, fork.clone(), index, signature))
+				})
+			})
+		});
+	let (name, test, fork, index, signature) = failing.ok_or_else(|| match name {
+		Some(name) if !tests.contains_key(name) => format!("no test {}", name),
+		_ => "no index fails other than by its post-state".to_string(),
+	})?;
+
+	let before = Size::of(test);
+	let mut test = single_index(test, &fork, index)
+		.ok_or_else(|| format!("{}: invalid indexes for {}:{}", name, fork, index))?;
+	let reproduces = |test: &Value| failure(name, test, &fork, 0).as_ref() == Some(&signature);
+	if !reproduces(&test) {
+		return Err(format!(
+			"{}: {}:{} fails differently alone",
+			name, fork, index
+		));
+	}
+
+	let accounts = test["pre"].as_object().cloned().unwrap_or_default();
+	let accounts = ddmin(accounts.into_iter().collect(), |accounts| {
+		let mut candidate = test.clone();
+		candidate["pre"] = Value::Object(accounts.iter().cloned().collect());
+		reproduces(&candidate)
+	});
+	test["pre"] = Value::Object(accounts.into_iter().collect());
+
+	let addresses = test["pre"]
+		.as_object()
+		.unwrap()
+		.keys()
+		.cloned()
+		.collect::<Vec<_>>();
+	for address in addresses {
+		let storage = test["pre"][&address]["storage"]
+			.as_object()
+			.cloned()
+			.unwrap_or_default();
+		let storage = ddmin(storage.into_iter().collect(), |storage| {
+			let mut candidate = test.clone();
+			candidate["pre"][&address]["storage"] =
+				Value::Object(storage.iter().cloned().collect());
+			reproduces(&candidate)
+		});
+		test["pre"][&address]["storage"] = Value::Object(storage.into_iter().collect());
+	}
+
+	if let Some(data) = calldata(&test["transaction"]["data"][0]) {
+		let encode = |data: &[u8]| Value::from(format!("0x{}", hex::encode(data)));
+		let data = ddmin(data, |data| {
+			let mut candidate = test.clone();
+			candidate["transaction"]["data"][0] = encode(data);
+			reproduces(&candidate)
+		});
+		test["transaction"]["data"][0] = encode(&data);
+	}
+
+	let after = Size::of(&test);
+	let mut fixture = Map::new();
+	fixture.insert(name.clone(), test);
+	Ok(Minimized {
+		name: name.clone(),
+		fork,
+		signature,
+		fixture: Value::Object(fixture),
+		before,
+		after,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn ddmin_keeps_what_fails() {
+		let items = (0..16).collect::<Vec<_>>();
+		let fails = |items: &[i32]| items.contains(&3) && items.contains(&11);
+		assert_eq!(ddmin(items, fails), vec![3, 11]);
+		assert_eq!(ddmin(vec![1, 2], |_| true), Vec::<i32>::new());
+		assert_eq!(
+			signature("Gas mismatch: 0x5208 != 21001\nmore"),
+			"Gas mismatch: N != N"
+		);
+	}
+}
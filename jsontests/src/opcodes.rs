@@ -0,0 +1,149 @@
+/// Mnemonic of an opcode, or `None` if the byte is not an assigned opcode.
+pub fn name(opcode: u8) -> Option<&'static str> {
+	let name = match opcode {
+		0x00 => "STOP",
+		0x01 => "ADD",
+		0x02 => "MUL",
+		0x03 => "SUB",
+		0x04 => "DIV",
+		0x05 => "SDIV",
+		0x06 => "MOD",
+		0x07 => "SMOD",
+		0x08 => "ADDMOD",
+		0x09 => "MULMOD",
+		0x0a => "EXP",
+		0x0b => "SIGNEXTEND",
+		0x10 => "LT",
+		0x11 => "GT",
+		0x12 => "SLT",
+		0x13 => "SGT",
+		0x14 => "EQ",
+		0x15 => "ISZERO",
+		0x16 => "AND",
+		0x17 => "OR",
+		0x18 => "XOR",
+		0x19 => "NOT",
+		0x1a => "BYTE",
+		0x1b => "SHL",
+		0x1c => "SHR",
+		0x1d => "SAR",
+		0x20 => "SHA3",
+		0x30 => "ADDRESS",
+		0x31 => "BALANCE",
+		0x32 => "ORIGIN",
+		0x33 => "CALLER",
+		0x34 => "CALLVALUE",
+		0x35 => "CALLDATALOAD",
+		0x36 => "CALLDATASIZE",
+		0x37 => "CALLDATACOPY",
+		0x38 => "CODESIZE",
+		0x39 => "CODECOPY",
+		0x3a => "GASPRICE",
+		0x3b => "EXTCODESIZE",
+		0x3c => "EXTCODECOPY",
+		0x3d => "RETURNDATASIZE",
+		0x3e => "RETURNDATACOPY",
+		0x3f => "EXTCODEHASH",
+		0x40 => "BLOCKHASH",
+		0x41 => "COINBASE",
+		0x42 => "TIMESTAMP",
+		0x43 => "NUMBER",
+		0x44 => "DIFFICULTY",
+		0x45 => "GASLIMIT",
+		0x46 => "CHAINID",
+		0x47 => "SELFBALANCE",
+		0x48 => "BASEFEE",
+		0x50 => "POP",
+		0x51 => "MLOAD",
+		0x52 => "MSTORE",
+		0x53 => "MSTORE8",
+		0x54 => "SLOAD",
+		0x55 => "SSTORE",
+		0x56 => "JUMP",
+		0x57 => "JUMPI",
+		0x58 => "PC",
+		0x59 => "MSIZE",
+		0x5a => "GAS",
+		0x5b => "JUMPDEST",
+		0x5f => "PUSH0",
+		0x60..=0x7f => PUSH[(opcode - 0x60) as usize],
+		0x80..=0x8f => DUP[(opcode - 0x80) as usize],
+		0x90..=0x9f => SWAP[(opcode - 0x90) as usize],
+		0xa0 => "LOG0",
+		0xa1 => "LOG1",
+		0xa2 => "LOG2",
+		0xa3 => "LOG3",
+		0xa4 => "LOG4",
+		0xf0 => "CREATE",
+		0xf1 => "CALL",
+		0xf2 => "CALLCODE",
+		0xf3 => "RETURN",
+		0xf4 => "DELEGATECALL",
+		0xf5 => "CREATE2",
+		0xfa => "STATICCALL",
+		0xfd => "REVERT",
+		0xfe => "INVALID",
+		0xff => "SELFDESTRUCT",
+		_ => return None,
+	};
+	Some(name)
+}
+
+/// Opcode with the given mnemonic, matched case-insensitively.
+pub fn from_name(mnemonic: &str) -> Option<u8> {
+	(0..=0xffu8).find(|opcode| {
+		name(*opcode)
+			.map(|name| name.eq_ignore_ascii_case(mnemonic))
+			.unwrap_or(false)
+	})
+}
+
+/// Mnemonic of an opcode, falling back to its hex value for unassigned bytes.
+pub fn display_name(opcode: u8) -> String {
+	match name(opcode) {
+		Some(name) => name.to_string(),
+		None => format!("0x{:02x}", opcode),
+	}
+}
+
+/// Number of immediate bytes following the opcode in code.
+pub fn immediate_size(opcode: u8) -> usize {
+	match opcode {
+		0x60..=0x7f => (opcode - 0x5f) as usize,
+		_ => 0,
+	}
+}
+
+/// Coarse grouping of opcodes, following the sections of the yellow paper.
+pub fn category(opcode: u8) -> &'static str {
+	match opcode {
+		0x00..=0x0f => "arithmetic",
+		0x10..=0x1f => "comparison",
+		0x20..=0x2f => "sha3",
+		0x30..=0x3f => "environment",
+		0x40..=0x4f => "block",
+		0x50 | 0x5f..=0x9f => "stack",
+		0x51..=0x53 | 0x59 => "memory",
+		0x54 | 0x55 => "storage",
+		0x56..=0x58 | 0x5a..=0x5e => "flow",
+		0xa0..=0xaf => "logging",
+		_ => "system",
+	}
+}
+
+const PUSH: [&str; 32] = [
+	"PUSH1", "PUSH2", "PUSH3", "PUSH4", "PUSH5", "PUSH6", "PUSH7", "PUSH8", "PUSH9", "PUSH10",
+	"PUSH11", "PUSH12", "PUSH13", "PUSH14", "PUSH15", "PUSH16", "PUSH17", "PUSH18", "PUSH19",
+	"PUSH20", "PUSH21", "PUSH22", "PUSH23", "PUSH24", "PUSH25", "PUSH26", "PUSH27", "PUSH28",
+	"PUSH29", "PUSH30", "PUSH31", "PUSH32",
+];
+
+const DUP: [&str; 16] = [
+	"DUP1", "DUP2", "DUP3", "DUP4", "DUP5", "DUP6", "DUP7", "DUP8", "DUP9", "DUP10", "DUP11",
+	"DUP12", "DUP13", "DUP14", "DUP15", "DUP16",
+];
+
+const SWAP: [&str; 16] = [
+	"SWAP1", "SWAP2", "SWAP3", "SWAP4", "SWAP5", "SWAP6", "SWAP7", "SWAP8", "SWAP9", "SWAP10",
+	"SWAP11", "SWAP12", "SWAP13", "SWAP14", "SWAP15", "SWAP16",
+];
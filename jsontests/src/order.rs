@@ -0,0 +1,69 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Small deterministic generator (splitmix64), so that a shuffle seed
+/// reproduces the same order on every platform and release.
+#[derive(Clone, Debug)]
+pub struct Rng(u64);
+
+impl Rng {
+	pub fn new(seed: u64) -> Self {
+		Rng(seed)
+	}
+
+	pub fn next_u64(&mut self) -> u64 {
+		self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+		let mut z = self.0;
+		z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+		z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+		z ^ (z >> 31)
+	}
+
+	/// Uniform value in `0..bound`. `bound` must be non-zero.
+	pub fn below(&mut self, bound: u64) -> u64 {
+		self.next_u64() % bound
+	}
+
+	/// Fisher-Yates shuffle of `items`.
+	pub fn shuffle<T>(&mut self, items: &mut [T]) {
+		for i in (1..items.len()).rev() {
+			let j = self.below(i as u64 + 1) as usize;
+			items.swap(i, j);
+		}
+	}
+}
+
+/// Seed for runs that ask for a shuffle without specifying one.
+pub fn random_seed() -> u64 {
+	let now = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.unwrap_or_default();
+	now.as_secs() ^ (now.subsec_nanos() as u64) << 32
+}
+
+/// Print the seed after the default panic message, so a failing shuffled
+/// run can be reproduced.
+pub fn report_seed_on_panic(seed: u64) {
+	let default_hook = std::panic::take_hook();
+	std::panic::set_hook(Box::new(move |info| {
+		default_hook(info);
+		eprintln!("note: tests were shuffled, rerun with `--shuffle {}`", seed);
+	}));
+}
+
+#[cfg(test)]
+mod tests {
+	use super::Rng;
+
+	#[test]
+	fn shuffle_is_deterministic() {
+		let mut a = (0..32).collect::<Vec<_>>();
+		let mut b = a.clone();
+		Rng::new(42).shuffle(&mut a);
+		Rng::new(42).shuffle(&mut b);
+		assert_eq!(a, b);
+		assert_ne!(a, (0..32).collect::<Vec<_>>());
+
+		a.sort();
+		assert_eq!(a, (0..32).collect::<Vec<_>>());
+	}
+}
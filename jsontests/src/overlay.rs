@@ -0,0 +1,469 @@
+use crate::utils::{state_root_of, AccountRef};
+use evm::backend::{Apply, ApplyBackend, Backend, Basic, Log, MemoryAccount, MemoryVicinity};
+use primitive_types::{H160, H256, U256};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+/// Account changed on top of the shared state. Unless a transaction gives
+/// it new code it keeps the one of the shared account, so that changing the
+/// balance or storage of a contract does not copy its code.
+#[derive(Clone, Debug, Default)]
+struct Changed {
+	nonce: U256,
+	balance: U256,
+	storage: BTreeMap<H256, H256>,
+	/// Code of the account, `None` for the one of the shared account.
+	code: Option<Vec<u8>>,
+}
+
+impl Changed {
+	fn of(account: &MemoryAccount) -> Self {
+		Changed {
+			nonce: account.nonce,
+			balance: account.balance,
+			storage: account.storage.clone(),
+			code: None,
+		}
+	}
+
+	/// Account created where there was none, or where one was deleted.
+	fn created() -> Self {
+		Changed {
+			code: Some(Vec::new()),
+			..Default::default()
+		}
+	}
+
+	fn view<'c>(&'c self, shared: Option<&'c MemoryAccount>) -> AccountRef<'c> {
+		AccountRef {
+			nonce: self.nonce,
+			balance: self.balance,
+			storage: &self.storage,
+			code: match &self.code {
+				Some(code) => code.as_slice(),
+				None => shared.map_or(&[][..], |account| account.code.as_slice()),
+			},
+		}
+	}
+
+	fn into_account(self, shared: Option<&MemoryAccount>) -> MemoryAccount {
+		MemoryAccount {
+			nonce: self.nonce,
+			balance: self.balance,
+			storage: self.storage,
+			code: match self.code {
+				Some(code) => code,
+				None => shared.map_or_else(Vec::new, |account| account.code.clone()),
+			},
+		}
+	}
+}
+
+/// Backend over a shared state that copies an account only once a
+/// transaction changes it, and its code only if it is replaced, so that
+/// executing every index of a test does not clone its whole pre-state,
+/// contract code included.
+pub struct OverlayBackend<'a> {
+	vicinity: &'a MemoryVicinity,
+	base: Cow<'a, BTreeMap<H160, MemoryAccount>>,
+	/// Accounts changed on top of `base`, `None` for deleted ones.
+	changes: BTreeMap<H160, Option<Changed>>,
+}
+
+impl<'a> OverlayBackend<'a> {
+	pub fn new(vicinity: &'a MemoryVicinity, base: Cow<'a, BTreeMap<H160, MemoryAccount>>) -> Self {
+		OverlayBackend {
+			vicinity,
+			base,
+			changes: BTreeMap::new(),
+		}
+	}
+
+	fn account(&self, address: H160) -> Option<AccountRef<'_>> {
+		match self.changes.get(&address) {
+			Some(changed) => Some(changed.as_ref()?.view(self.base.get(&address))),
+			None => self.base.get(&address).map(AccountRef::from),
+		}
+	}
+
+	/// Accounts of the current state, in no particular order.
+	pub fn accounts(&self) -> impl Iterator<Item = (&H160, AccountRef<'_>)> {
+		let unchanged = self
+			.base
+			.iter()
+			.filter(move |(address, _)| !self.changes.contains_key(address))
+			.map(|(address, account)| (address, AccountRef::from(account)));
+		let changed = self.changes.iter().filter_map(move |(address, account)| {
+			let account = account.as_ref()?.view(self.base.get(address));
+			Some((address, account))
+		});
+		unchanged.chain(changed)
+	}
+
+	pub fn state_root(&self) -> H256 {
+		state_root_of(self.accounts())
+	}
+
+	/// The current state, borrowing the shared one if nothing changed.
+	pub fn state(&self) -> Cow<BTreeMap<H160, MemoryAccount>> {
+		if self.changes.is_empty() {
+			return Cow::Borrowed(&*self.base);
+		}
+		Cow::Owned(
+			self.accounts()
+				.map(|(address, account)| (*address, account.to_account()))
+				.collect(),
+		)
+	}
+
+	/// Accounts changed on top of the shared state, `None` for deleted ones.
+	pub fn into_changes(self) -> BTreeMap<H160, Option<MemoryAccount>> {
+		let base = &self.base;
+		self.changes
+			.into_iter()
+			.map(|(address, changed)| {
+				let account = changed.map(|changed| changed.into_account(base.get(&address)));
+				(address, account)
+			})
+			.collect()
+	}
+
+	/// The current state, moving the code of accounts into it rather than
+	/// copying.
+	pub fn into_state(self) -> BTreeMap<H160, MemoryAccount> {
+		let mut state = self.base.into_owned();
+		for (address, changed) in self.changes {
+			let changed = match changed {
+				Some(changed) => changed,
+				None => {
+					state.remove(&address);
+					continue;
+				}
+			};
+			let account = state.entry(address).or_default();
+			account.nonce = changed.nonce;
+			account.balance = changed.balance;
+			account.storage = changed.storage;
+			if let Some(code) = changed.code {
+				account.code = code;
+			}
+		}
+		state
+	}
+}
+
+/// Point of a [`Journal`] its state can be reverted to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Checkpoint(usize);
+
+/// State that transactions change one after the other, keeping what every
+/// change replaced so that it can be reverted to an earlier checkpoint
+/// without a copy of the state per checkpoint.
+#[derive(Clone, Debug, Default)]
+pub struct Journal {
+	state: BTreeMap<H160, MemoryAccount>,
+	/// Accounts replaced by changes, `None` where there was none, the most
+	/// recent last.
+	undo: Vec<(H160, Option<MemoryAccount>)>,
+}
+
+impl Journal {
+	pub fn new(state: BTreeMap<H160, MemoryAccount>) -> Self {
+		Journal {
+			state,
+			undo: Vec::new(),
+		}
+	}
+
+	pub fn state(&self) -> &BTreeMap<H160, MemoryAccount> {
+		&self.state
+	}
+
+	/// Checkpoint of the current state.
+	pub fn checkpoint(&self) -> Checkpoint {
+		Checkpoint(self.undo.len())
+	}
+
+	/// Apply `changes`, as returned by [`OverlayBackend::into_changes`].
+	pub fn commit(&mut self, changes: BTreeMap<H160, Option<MemoryAccount>>) {
+		for (address, account) in changes {
+			let replaced = match account {
+				Some(account) => self.state.insert(address, account),
+				None => self.state.remove(&address),
+			};
+			self.undo.push((address, replaced));
+		}
+	}
+
+	/// Undo every change committed since `checkpoint`, which becomes the most
+	/// recent checkpoint to revert to again.
+	pub fn revert_to(&mut self, checkpoint: Checkpoint) {
+		while self.undo.len() > checkpoint.0 {
+			let (address, replaced) = self.undo.pop().unwrap();
+			match replaced {
+				Some(account) => self.state.insert(address, account),
+				None => self.state.remove(&address),
+			};
+		}
+	}
+}
+
+impl<'a> Backend for OverlayBackend<'a> {
+	fn gas_price(&self) -> U256 {
+		self.vicinity.gas_price
+	}
+
+	fn origin(&self) -> H160 {
+		self.vicinity.origin
+	}
+
+	fn block_hash(&self, number: U256) -> H256 {
+		let vicinity = self.vicinity;
+		if number >= vicinity.block_number
+			|| vicinity.block_number - number - U256::one()
+				>= U256::from(vicinity.block_hashes.len())
+		{
+			H256::default()
+		} else {
+			let index = (vicinity.block_number - number - U256::one()).as_usize();
+			vicinity.block_hashes[index]
+		}
+	}
+
+	fn block_number(&self) -> U256 {
+		self.vicinity.block_number
+	}
+
+	fn block_coinbase(&self) -> H160 {
+		self.vicinity.block_coinbase
+	}
+
+	fn block_timestamp(&self) -> U256 {
+		self.vicinity.block_timestamp
+	}
+
+	fn block_difficulty(&self) -> U256 {
+		self.vicinity.block_difficulty
+	}
+
+	fn block_gas_limit(&self) -> U256 {
+		self.vicinity.block_gas_limit
+	}
+
+	fn chain_id(&self) -> U256 {
+		self.vicinity.chain_id
+	}
+
+	fn exists(&self, address: H160) -> bool {
+		self.account(address).is_some()
+	}
+
+	fn basic(&self, address: H160) -> Basic {
+		self.account(address)
+			.map(|account| Basic {
+				balance: account.balance,
+				nonce: account.nonce,
+			})
+			.unwrap_or_default()
+	}
+
+	fn code(&self, address: H160) -> Vec<u8> {
+		self.account(address)
+			.map(|account| account.code.to_vec())
+			.unwrap_or_default()
+	}
+
+	fn storage(&self, address: H160, index: H256) -> H256 {
+		self.account(address)
+			.and_then(|account| account.storage.get(&index).cloned())
+			.unwrap_or_default()
+	}
+
+	fn original_storage(&self, address: H160, index: H256) -> Option<H256> {
+		Some(self.storage(address, index))
+	}
+}
+
+impl<'a> ApplyBackend for OverlayBackend<'a> {
+	/// Same semantics as `MemoryBackend::apply`. Logs are not kept, the
+	/// executor returns them.
+	fn apply<A, I, L>(&mut self, values: A, _logs: L, delete_empty: bool)
+	where
+		A: IntoIterator<Item = Apply<I>>,
+		I: IntoIterator<Item = (H256, H256)>,
+		L: IntoIterator<Item = Log>,
+	{
+		for apply in values {
+			match apply {
+				Apply::Modify {
+					address,
+					basic,
+					code,
+					storage,
+					reset_storage,
+				} => {
+					let base = &self.base;
+					let shared = base.get(&address);
+					let account = self
+						.changes
+						.entry(address)
+						.or_insert_with(|| shared.map(Changed::of))
+						.get_or_insert_with(Changed::created);
+
+					account.balance = basic.balance;
+					account.nonce = basic.nonce;
+					if let Some(code) = code {
+						account.code = Some(code);
+					}
+					if reset_storage {
+						account.storage = BTreeMap::new();
+					}
+					account.storage.retain(|_, value| *value != H256::default());
+					for (index, value) in storage {
+						if value == H256::default() {
+							account.storage.remove(&index);
+						} else {
+							account.storage.insert(index, value);
+						}
+					}
+
+					let is_empty = account.balance == U256::zero()
+						&& account.nonce == U256::zero()
+						&& account.view(shared).code.is_empty();
+					if is_empty && delete_empty {
+						self.changes.insert(address, None);
+					}
+				}
+				Apply::Delete { address } => {
+					self.changes.insert(address, None);
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::utils::state_root;
+
+	fn vicinity() -> MemoryVicinity {
+		MemoryVicinity {
+			gas_price: U256::zero(),
+			origin: H160::zero(),
+			block_hashes: Vec::new(),
+			block_number: U256::zero(),
+			block_coinbase: H160::zero(),
+			block_timestamp: U256::zero(),
+			block_difficulty: U256::zero(),
+			block_gas_limit: U256::zero(),
+			chain_id: U256::one(),
+		}
+	}
+
+	fn account(balance: u64) -> MemoryAccount {
+		MemoryAccount {
+			balance: balance.into(),
+			nonce: U256::zero(),
+			code: vec![0x00],
+			storage: BTreeMap::new(),
+		}
+	}
+
+	#[test]
+	fn changes_leave_base_untouched() {
+		let vicinity = vicinity();
+		let a = H160::repeat_byte(0xaa);
+		let b = H160::repeat_byte(0xbb);
+		let base = vec![(a, account(1)), (b, account(2))]
+			.into_iter()
+			.collect::<BTreeMap<_, _>>();
+
+		let mut backend = OverlayBackend::new(&vicinity, Cow::Borrowed(&base));
+		let modify = Apply::Modify {
+			address: a,
+			basic: Basic {
+				balance: 5.into(),
+				nonce: U256::one(),
+			},
+			code: None,
+			storage: vec![(H256::repeat_byte(1), H256::repeat_byte(2))],
+			reset_storage: false,
+		};
+		backend.apply(vec![modify, Apply::Delete { address: b }], Vec::new(), true);
+
+		assert_eq!(backend.basic(a).balance, 5.into());
+		assert!(!backend.exists(b));
+		assert_eq!(base[&a].balance, 1.into());
+
+		let state = backend.state().into_owned();
+		assert_eq!(state.keys().collect::<Vec<_>>(), vec![&a]);
+		assert_eq!(backend.state_root(), state_root(&state));
+		assert_eq!(backend.into_state(), state);
+	}
+
+	#[test]
+	fn changed_accounts_share_code() {
+		let vicinity = vicinity();
+		let a = H160::repeat_byte(0xaa);
+		let b = H160::repeat_byte(0xbb);
+		let base = vec![(a, account(1)), (b, account(2))]
+			.into_iter()
+			.collect::<BTreeMap<_, _>>();
+
+		let mut backend = OverlayBackend::new(&vicinity, Cow::Borrowed(&base));
+		let modify = |address, code| Apply::Modify {
+			address,
+			basic: Basic {
+				balance: 5.into(),
+				nonce: U256::one(),
+			},
+			code,
+			storage: Vec::<(H256, H256)>::new(),
+			reset_storage: false,
+		};
+		backend.apply(
+			vec![modify(a, None), Apply::Delete { address: b }],
+			Vec::new(),
+			false,
+		);
+		backend.apply(vec![modify(b, None)], Vec::new(), false);
+
+		// The balance changed, the code is the shared one.
+		assert_eq!(backend.code(a), vec![0x00]);
+		// Recreated after its deletion, without the code of before.
+		assert!(backend.exists(b));
+		assert!(backend.code(b).is_empty());
+
+		let state = backend.state().into_owned();
+		assert_eq!(state[&a].code, vec![0x00]);
+		assert_eq!(backend.state_root(), state_root(&state));
+		assert_eq!(backend.into_state(), state);
+	}
+
+	#[test]
+	fn journal_reverts_to_checkpoints() {
+		let a = H160::repeat_byte(0xaa);
+		let b = H160::repeat_byte(0xbb);
+		let base = vec![(a, account(1))]
+			.into_iter()
+			.collect::<BTreeMap<_, _>>();
+		let mut journal = Journal::new(base.clone());
+
+		let start = journal.checkpoint();
+		journal.commit(
+			vec![(a, Some(account(2))), (b, Some(account(3)))]
+				.into_iter()
+				.collect(),
+		);
+		let changed = journal.state().clone();
+		let middle = journal.checkpoint();
+		journal.commit(vec![(a, None)].into_iter().collect());
+		assert!(!journal.state().contains_key(&a));
+
+		journal.revert_to(middle);
+		assert_eq!(journal.state(), &changed);
+		journal.revert_to(start);
+		assert_eq!(journal.state(), &base);
+	}
+}
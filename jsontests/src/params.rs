@@ -0,0 +1,106 @@
+//! Parameters a fork runs with: the gasometer config after the chain spec
+//! tweaked it and the builtins active under the schedule in use, printed so
+//! that runs can be checked and forks diffed.
+
+use crate::builtins;
+use crate::fixture::fork_spec;
+use crate::state::{fork_config, unsupported};
+use evm::Config;
+use serde_json::{json, Map, Value};
+
+/// Fields of `config` by their name in `evm::Config`.
+fn config_fields(config: &Config) -> Map<String, Value> {
+	let mut fields = Map::new();
+	macro_rules! fields {
+		($($field:ident),* $(,)?) => {
+			$(fields.insert(stringify!($field).to_string(), json!(config.$field));)*
+		};
+	}
+	fields!(
+		gas_ext_code,
+		gas_ext_code_hash,
+		gas_sstore_set,
+		gas_sstore_reset,
+		refund_sstore_clears,
+		gas_balance,
+		gas_sload,
+		gas_sload_cold,
+		gas_suicide,
+		gas_suicide_new_account,
+		gas_call,
+		gas_expbyte,
+		gas_transaction_create,
+		gas_transaction_call,
+		gas_transaction_zero_data,
+		gas_transaction_non_zero_data,
+		gas_access_list_address,
+		gas_access_list_storage_key,
+		gas_account_access_cold,
+		gas_storage_read_warm,
+		sstore_gas_metering,
+		sstore_revert_under_stipend,
+		increase_state_access_gas,
+		err_on_call_with_more_gas,
+		call_l64_after_gas,
+		empty_considered_exists,
+		create_increase_nonce,
+		stack_limit,
+		memory_limit,
+		call_stack_limit,
+		create_contract_limit,
+		call_stipend,
+		has_delegate_call,
+		has_create2,
+		has_revert,
+		has_return_data,
+		has_bitwise_shifting,
+		has_chain_id,
+		has_self_balance,
+		has_ext_code_hash,
+		estimate,
+	);
+	fields
+}
+
+/// Config, empty-account deletion rule and builtins `fork` runs with.
+pub fn describe(fork: &str) -> Result<Value, String> {
+	let spec = fork_spec(fork).ok_or_else(|| format!("unknown fork {}", fork))?;
+	let (config, deletes_empty) = fork_config(&spec).ok_or_else(|| unsupported(fork).unwrap())?;
+	let precompiles = builtins::for_fork(fork)
+		.ok_or_else(|| format!("no builtins for fork {}", fork))?
+		.iter()
+		.map(|(address, builtin)| {
+			let builtin = json!({
+				"name": builtin.name(),
+				"pricing": builtin.pricing(),
+			});
+			(format!("{:?}", address), builtin)
+		})
+		.collect::<Map<_, _>>();
+	Ok(json!({
+		"fork": fork,
+		"deletesEmpty": deletes_empty,
+		"config": config_fields(&config),
+		"precompiles": precompiles,
+	}))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn berlin_params() {
+		let berlin = describe("Berlin").unwrap();
+		assert_eq!(berlin["config"]["gas_sload_cold"], json!(2100));
+		assert_eq!(berlin["deletesEmpty"], json!(true));
+		assert_eq!(
+			berlin["precompiles"]["0x0000000000000000000000000000000000000001"]["name"],
+			json!("ecrecover")
+		);
+		assert_ne!(describe("Istanbul").unwrap()["config"], berlin["config"]);
+
+		assert!(describe("Frontier").unwrap_err().contains("unsupported"));
+		assert!(describe("Olympic").unwrap_err().contains("unknown"));
+	}
+}
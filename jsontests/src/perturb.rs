@@ -0,0 +1,107 @@
+//! Changes to a pre-state a transaction cannot observe: accounts at random
+//! addresses it never touches, some holding only dust. Executions with and
+//! without them must reach the same result, so a difference shows that the
+//! executor depends on the order of accounts or mishandles empty ones.
+
+use crate::order::Rng;
+use evm::backend::MemoryAccount;
+use primitive_types::{H160, H256, U256};
+use std::collections::BTreeMap;
+
+/// Accounts added to a pre-state.
+const EXTRA_ACCOUNTS: usize = 8;
+
+fn random_address(rng: &mut Rng) -> H160 {
+	let mut bytes = [0; 24];
+	for chunk in bytes.chunks_mut(8) {
+		chunk.copy_from_slice(&rng.next_u64().to_be_bytes());
+	}
+	H160::from_slice(&bytes[..20])
+}
+
+fn random_account(rng: &mut Rng) -> MemoryAccount {
+	match rng.below(3) {
+		// Dust on an account that is empty otherwise.
+		0 => MemoryAccount {
+			balance: U256::from(1 + rng.below(1000)),
+			nonce: U256::zero(),
+			code: Vec::new(),
+			storage: BTreeMap::new(),
+		},
+		1 => MemoryAccount {
+			balance: U256::from(rng.next_u64()),
+			nonce: U256::one(),
+			code: Vec::new(),
+			storage: vec![(
+				H256::from_low_u64_be(rng.next_u64()),
+				H256::from_low_u64_be(rng.next_u64() | 1),
+			)]
+			.into_iter()
+			.collect(),
+		},
+		// PUSH1 0 PUSH1 0 RETURN
+		_ => MemoryAccount {
+			balance: U256::zero(),
+			nonce: U256::one(),
+			code: vec![0x60, 0x00, 0x60, 0x00, 0xf3],
+			storage: BTreeMap::new(),
+		},
+	}
+}
+
+/// `pre_state` with accounts added at addresses derived from `seed`, and the
+/// addresses added.
+pub fn perturb(
+	pre_state: &BTreeMap<H160, MemoryAccount>,
+	seed: u64,
+) -> (BTreeMap<H160, MemoryAccount>, Vec<H160>) {
+	let mut rng = Rng::new(seed);
+	let mut state = pre_state.clone();
+	let mut added = Vec::new();
+	while added.len() < EXTRA_ACCOUNTS {
+		let address = random_address(&mut rng);
+		if state.contains_key(&address) {
+			continue;
+		}
+		state.insert(address, random_account(&mut rng));
+		added.push(address);
+	}
+	(state, added)
+}
+
+/// Remove the `added` accounts of the perturbed pre-state `perturbed` from
+/// `post_state`, failing with the first one the transaction changed.
+pub fn unperturb(
+	post_state: &mut BTreeMap<H160, MemoryAccount>,
+	perturbed: &BTreeMap<H160, MemoryAccount>,
+	added: &[H160],
+) -> Result<(), H160> {
+	for address in added {
+		if post_state.remove(address).as_ref() != perturbed.get(address) {
+			return Err(*address);
+		}
+	}
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn perturbation_is_deterministic() {
+		let pre_state = BTreeMap::new();
+		let (state, added) = perturb(&pre_state, 7);
+		assert_eq!(added.len(), EXTRA_ACCOUNTS);
+		assert_eq!(perturb(&pre_state, 7), (state.clone(), added.clone()));
+		assert_ne!(perturb(&pre_state, 8).1, added);
+
+		let mut post_state = state.clone();
+		assert_eq!(unperturb(&mut post_state, &state, &added), Ok(()));
+		assert_eq!(post_state, pre_state);
+
+		let mut touched = state.clone();
+		touched.get_mut(&added[3]).unwrap().balance += U256::one();
+		assert_eq!(unperturb(&mut touched, &state, &added), Err(added[3]));
+	}
+}
@@ -0,0 +1,425 @@
+//! Builtin contracts, implemented in pure Rust or, with the `ethcore`
+//! feature, by OpenEthereum's `ethcore-builtin`, selected at run time so
+//! that both can be cross-checked in the same run.
+
+mod native;
+
+use primitive_types::U256;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+/// Gas pricing of a builtin, in the format of OpenEthereum chain specs.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub enum Pricing {
+	/// `base` plus `word` per 32-byte word of input.
+	Linear {
+		base: u64,
+		word: u64,
+	},
+	/// EIP-198, or EIP-2565 since Berlin.
+	Modexp {
+		divisor: u64,
+		is_eip_2565: bool,
+	},
+	AltBn128ConstOperations {
+		price: u64,
+	},
+	/// `base` plus `pair` per pair of points.
+	AltBn128Pairing {
+		base: u64,
+		pair: u64,
+	},
+	Blake2F {
+		gas_per_round: u64,
+	},
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+	EcRecover,
+	Sha256,
+	Ripemd160,
+	Identity,
+	Modexp,
+	Bn128Add,
+	Bn128Mul,
+	Bn128Pairing,
+	Blake2F,
+	/// secp256r1 signature verification of RIP-7212, shipped by L2s.
+	#[cfg(feature = "p256verify")]
+	P256Verify,
+}
+
+impl Kind {
+	fn from_name(name: &str) -> Option<Self> {
+		Some(match name {
+			"ecrecover" => Kind::EcRecover,
+			"sha256" => Kind::Sha256,
+			"ripemd160" => Kind::Ripemd160,
+			"identity" => Kind::Identity,
+			"modexp" => Kind::Modexp,
+			"alt_bn128_add" => Kind::Bn128Add,
+			"alt_bn128_mul" => Kind::Bn128Mul,
+			"alt_bn128_pairing" => Kind::Bn128Pairing,
+			"blake2_f" => Kind::Blake2F,
+			#[cfg(feature = "p256verify")]
+			"p256verify" => Kind::P256Verify,
+			_ => return None,
+		})
+	}
+
+	fn name(&self) -> &'static str {
+		match self {
+			Kind::EcRecover => "ecrecover",
+			Kind::Sha256 => "sha256",
+			Kind::Ripemd160 => "ripemd160",
+			Kind::Identity => "identity",
+			Kind::Modexp => "modexp",
+			Kind::Bn128Add => "alt_bn128_add",
+			Kind::Bn128Mul => "alt_bn128_mul",
+			Kind::Bn128Pairing => "alt_bn128_pairing",
+			Kind::Blake2F => "blake2_f",
+			#[cfg(feature = "p256verify")]
+			Kind::P256Verify => "p256verify",
+		}
+	}
+}
+
+/// Implementation builtins run on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Implementation {
+	/// The pure-Rust implementations of this module.
+	Native,
+	/// OpenEthereum's implementations, priced from the same JSON schedule.
+	BuiltinJson,
+	/// Both, failing on the first call they disagree on.
+	CrossCheck,
+}
+
+impl Implementation {
+	fn name(&self) -> &'static str {
+		match self {
+			Implementation::Native => "native",
+			Implementation::BuiltinJson => "builtin_json",
+			Implementation::CrossCheck => "cross_check",
+		}
+	}
+}
+
+impl fmt::Display for Implementation {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+impl FromStr for Implementation {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		[
+			Implementation::Native,
+			Implementation::BuiltinJson,
+			Implementation::CrossCheck,
+		]
+		.iter()
+		.find(|implementation| implementation.name() == s)
+		.copied()
+		.ok_or_else(|| format!("unknown precompiles implementation {}", s))
+	}
+}
+
+static IMPLEMENTATION: AtomicU8 = AtomicU8::new(Implementation::Native as u8);
+
+/// Run builtins on `implementation` from now on, which must be native
+/// unless built with the `ethcore` feature.
+pub fn set_implementation(implementation: Implementation) -> Result<(), String> {
+	if implementation != Implementation::Native && !cfg!(feature = "ethcore") {
+		return Err(format!(
+			"{} precompiles need the ethcore feature",
+			implementation.name()
+		));
+	}
+	IMPLEMENTATION.store(implementation as u8, Ordering::SeqCst);
+	Ok(())
+}
+
+#[cfg(feature = "ethcore")]
+fn implementation() -> Implementation {
+	match IMPLEMENTATION.load(Ordering::Relaxed) {
+		0 => Implementation::Native,
+		1 => Implementation::BuiltinJson,
+		_ => Implementation::CrossCheck,
+	}
+}
+
+/// A builtin contract with the pricing of one fork.
+#[derive(Debug)]
+pub struct Builtin {
+	kind: Kind,
+	pricing: Pricing,
+	/// OpenEthereum's implementation, if it has one.
+	#[cfg(feature = "ethcore")]
+	ethcore: Option<ethcore_builtin::Builtin>,
+}
+
+impl Builtin {
+	pub fn new(name: &str, pricing: Pricing) -> Result<Self, String> {
+		let kind = Kind::from_name(name).ok_or_else(|| format!("unknown builtin {}", name))?;
+		Ok(Builtin {
+			kind,
+			#[cfg(feature = "ethcore")]
+			ethcore: match kind {
+				#[cfg(feature = "p256verify")]
+				Kind::P256Verify => None,
+				_ => Some(ethcore_builtin(name, &pricing)?),
+			},
+			pricing,
+		})
+	}
+
+	/// Name of the builtin in schedules.
+	pub fn name(&self) -> &'static str {
+		self.kind.name()
+	}
+
+	pub fn pricing(&self) -> &Pricing {
+		&self.pricing
+	}
+
+	#[cfg(not(feature = "ethcore"))]
+	pub fn cost(&self, input: &[u8]) -> U256 {
+		native::cost(&self.pricing, input)
+	}
+
+	#[cfg(feature = "ethcore")]
+	pub fn cost(&self, input: &[u8]) -> U256 {
+		let ethcore = match &self.ethcore {
+			Some(ethcore) => move || ethcore.cost(input, 0),
+			None => return native::cost(&self.pricing, input),
+		};
+		match implementation() {
+			Implementation::Native => native::cost(&self.pricing, input),
+			Implementation::BuiltinJson => ethcore(),
+			Implementation::CrossCheck => {
+				self.cross_check("cost", input, native::cost(&self.pricing, input), ethcore())
+			}
+		}
+	}
+
+	#[cfg(not(feature = "ethcore"))]
+	pub fn execute(&self, input: &[u8]) -> Result<Vec<u8>, &'static str> {
+		native::execute(self.kind, input)
+	}
+
+	#[cfg(feature = "ethcore")]
+	pub fn execute(&self, input: &[u8]) -> Result<Vec<u8>, &'static str> {
+		let ethcore = match &self.ethcore {
+			Some(ethcore) => move || {
+				let mut output = Vec::new();
+				ethcore.execute(input, &mut parity_bytes::BytesRef::Flexible(&mut output))?;
+				Ok(output)
+			},
+			None => return native::execute(self.kind, input),
+		};
+		match implementation() {
+			Implementation::Native => native::execute(self.kind, input),
+			Implementation::BuiltinJson => ethcore(),
+			Implementation::CrossCheck => {
+				// Only whether a call fails must agree, not why.
+				let native = native::execute(self.kind, input);
+				let ethcore = ethcore();
+				self.cross_check("output", input, native.as_ref().ok(), ethcore.as_ref().ok());
+				native
+			}
+		}
+	}
+
+	/// `native`, after checking it is the same as `ethcore`.
+	#[cfg(feature = "ethcore")]
+	fn cross_check<T: PartialEq + fmt::Debug>(
+		&self,
+		what: &str,
+		input: &[u8],
+		native: T,
+		ethcore: T,
+	) -> T {
+		if native != ethcore {
+			panic!(
+				"Native {:?} {} {:?} differs from ethcore-builtin {:?} for input 0x{}",
+				self.kind,
+				what,
+				native,
+				ethcore,
+				hex::encode(input)
+			);
+		}
+		native
+	}
+}
+
+#[cfg(feature = "ethcore")]
+fn ethcore_builtin(name: &str, pricing: &Pricing) -> Result<ethcore_builtin::Builtin, String> {
+	use std::convert::TryInto;
+
+	// Both pricing types share the chain spec format.
+	let price = serde_json::to_value(pricing)
+		.and_then(serde_json::from_value)
+		.map_err(|err| err.to_string())?;
+	let builtin = ethjson::spec::Builtin {
+		name: name.to_string(),
+		pricing: vec![(0, ethjson::spec::builtin::PricingAt { info: None, price })]
+			.into_iter()
+			.collect(),
+	};
+	builtin
+		.try_into()
+		.map_err(|err| format!("invalid builtin {}: {}", name, err))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn builtin(name: &str, pricing: Pricing) -> Builtin {
+		Builtin::new(name, pricing).unwrap()
+	}
+
+	#[test]
+	fn linear_pricing_and_hashes() {
+		let sha256 = builtin("sha256", Pricing::Linear { base: 60, word: 12 });
+		assert_eq!(sha256.cost(&[0; 33]), U256::from(84));
+		assert_eq!(
+			hex::encode(sha256.execute(b"").unwrap()),
+			"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+		);
+
+		let ripemd160 = builtin(
+			"ripemd160",
+			Pricing::Linear {
+				base: 600,
+				word: 120,
+			},
+		);
+		assert_eq!(
+			hex::encode(ripemd160.execute(b"").unwrap()),
+			"0000000000000000000000009c1185a5c5e9fc54612808977ee8f548b2258d31"
+		);
+	}
+
+	#[test]
+	fn modexp_of_eip198() {
+		// 3 ** (2 ** 256 - 2 ** 32 - 978) % (2 ** 256 - 2 ** 32 - 977), from
+		// the examples of EIP-198.
+		let input = hex::decode(
+			"0000000000000000000000000000000000000000000000000000000000000001\
+			 0000000000000000000000000000000000000000000000000000000000000020\
+			 0000000000000000000000000000000000000000000000000000000000000020\
+			 03\
+			 fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2e\
+			 fffffffffffffffffffffffffffffffffffffffffffffffffffffffefffffc2f",
+		)
+		.unwrap();
+		let byzantium = builtin(
+			"modexp",
+			Pricing::Modexp {
+				divisor: 20,
+				is_eip_2565: false,
+			},
+		);
+		assert_eq!(byzantium.cost(&input), U256::from(13056));
+		assert_eq!(
+			hex::encode(byzantium.execute(&input).unwrap()),
+			"0000000000000000000000000000000000000000000000000000000000000001"
+		);
+
+		let berlin = builtin(
+			"modexp",
+			Pricing::Modexp {
+				divisor: 3,
+				is_eip_2565: true,
+			},
+		);
+		assert_eq!(berlin.cost(&input), U256::from(1360));
+	}
+
+	#[test]
+	fn bn128_add_of_generator() {
+		let add = builtin(
+			"alt_bn128_add",
+			Pricing::AltBn128ConstOperations { price: 150 },
+		);
+		let mut input = vec![0; 128];
+		input[31] = 1;
+		input[63] = 2;
+		input[95] = 1;
+		input[127] = 2;
+		assert_eq!(
+			hex::encode(add.execute(&input).unwrap()),
+			"030644e72e131a029b85045b68181585d97816a916871ca8d3c208c16d87cfd3\
+			 15ed738c0e0a7c92e7845f96b2ae9c0a68a6a449e3538fc7ff3ebf7a5a18a2c4"
+		);
+		assert_eq!(add.execute(&[]).unwrap(), vec![0; 64]);
+	}
+
+	#[test]
+	fn empty_pairing_holds() {
+		let pairing = builtin(
+			"alt_bn128_pairing",
+			Pricing::AltBn128Pairing {
+				base: 45000,
+				pair: 34000,
+			},
+		);
+		assert_eq!(pairing.execute(&[]).unwrap()[31], 1);
+		assert!(pairing.execute(&[0; 100]).is_err());
+		assert_eq!(pairing.cost(&[0; 384]), U256::from(113000));
+	}
+
+	#[cfg(feature = "p256verify")]
+	#[test]
+	fn p256verify_of_signed_hash() {
+		use p256::ecdsa::signature::hazmat::PrehashSigner;
+		use p256::ecdsa::{Signature, SigningKey};
+		use p256::elliptic_curve::sec1::ToEncodedPoint;
+
+		let key = SigningKey::from_bytes(p256::FieldBytes::from_slice(&[7; 32])).unwrap();
+		let hash = [0x42; 32];
+		let signature: Signature = key.sign_prehash(&hash).unwrap();
+		let point = key.verifying_key().to_encoded_point(false);
+		let mut input = hash.to_vec();
+		input.extend_from_slice(&signature.to_bytes());
+		input.extend_from_slice(point.x().unwrap());
+		input.extend_from_slice(point.y().unwrap());
+
+		let p256verify = builtin(
+			"p256verify",
+			Pricing::Linear {
+				base: 3450,
+				word: 0,
+			},
+		);
+		assert_eq!(p256verify.cost(&input), U256::from(3450));
+		assert_eq!(p256verify.execute(&input).unwrap()[31], 1);
+		input[0] ^= 1;
+		assert!(p256verify.execute(&input).unwrap().is_empty());
+		assert!(p256verify.execute(&input[..159]).unwrap().is_empty());
+	}
+
+	#[test]
+	fn implementation_selection() {
+		assert_eq!("builtin_json".parse(), Ok(Implementation::BuiltinJson));
+		assert!("ethcore".parse::<Implementation>().is_err());
+		assert_eq!(set_implementation(Implementation::Native), Ok(()));
+		if !cfg!(feature = "ethcore") {
+			assert!(set_implementation(Implementation::CrossCheck).is_err());
+		}
+	}
+
+	#[test]
+	fn unknown_builtin_is_rejected() {
+		assert!(Builtin::new("ec_recover", Pricing::Linear { base: 0, word: 0 }).is_err());
+	}
+}
@@ -0,0 +1,294 @@
+//! Pure-Rust implementations of the builtins and their pricing.
+
+use super::{Kind, Pricing};
+use crate::transaction::public_to_address;
+use bn::Group;
+use libsecp256k1::{Message, RecoveryId, Signature};
+use num_bigint::BigUint;
+use primitive_types::{H256, U256};
+use sha2::Digest;
+use std::cmp;
+
+pub(super) fn cost(pricing: &Pricing, input: &[u8]) -> U256 {
+	match *pricing {
+		Pricing::Linear { base, word } => {
+			let words = (input.len() as u64).div_ceil(32);
+			U256::from(base) + U256::from(word) * U256::from(words)
+		}
+		Pricing::Modexp {
+			divisor,
+			is_eip_2565,
+		} => modexp_cost(input, divisor, is_eip_2565),
+		Pricing::AltBn128ConstOperations { price } => U256::from(price),
+		Pricing::AltBn128Pairing { base, pair } => {
+			U256::from(base) + U256::from(pair) * U256::from(input.len() / 192)
+		}
+		Pricing::Blake2F { gas_per_round } => {
+			let rounds = input.get(..4).map_or(0, |rounds| {
+				u32::from_be_bytes([rounds[0], rounds[1], rounds[2], rounds[3]])
+			});
+			U256::from(gas_per_round) * U256::from(rounds)
+		}
+	}
+}
+
+pub(super) fn execute(kind: Kind, input: &[u8]) -> Result<Vec<u8>, &'static str> {
+	match kind {
+		Kind::EcRecover => Ok(ecrecover(input)),
+		Kind::Sha256 => Ok(sha2::Sha256::digest(input).to_vec()),
+		Kind::Ripemd160 => {
+			let mut output = vec![0; 12];
+			output.extend_from_slice(&ripemd160::Ripemd160::digest(input));
+			Ok(output)
+		}
+		Kind::Identity => Ok(input.to_vec()),
+		Kind::Modexp => modexp(input),
+		Kind::Bn128Add => bn128_add(input),
+		Kind::Bn128Mul => bn128_mul(input),
+		Kind::Bn128Pairing => bn128_pairing(input),
+		Kind::Blake2F => blake2_f(input),
+		#[cfg(feature = "p256verify")]
+		Kind::P256Verify => Ok(p256_verify(input)),
+	}
+}
+
+/// `len` bytes of `input` from `offset`, zero-padded past its end.
+fn padded(input: &[u8], offset: usize, len: usize) -> Vec<u8> {
+	let mut bytes = vec![0; len];
+	if offset < input.len() {
+		let available = cmp::min(len, input.len() - offset);
+		bytes[..available].copy_from_slice(&input[offset..offset + available]);
+	}
+	bytes
+}
+
+fn ecrecover(input: &[u8]) -> Vec<u8> {
+	let input = padded(input, 0, 128);
+	// `v` is a 32-byte word holding 27 or 28.
+	if input[32..63].iter().any(|byte| *byte != 0) || !(input[63] == 27 || input[63] == 28) {
+		return Vec::new();
+	}
+	let mut signature = [0; 64];
+	signature.copy_from_slice(&input[64..128]);
+	let recovered = Signature::parse_standard(&signature)
+		.ok()
+		.and_then(|signature| {
+			let recovery_id = RecoveryId::parse(input[63] - 27).ok()?;
+			let message = Message::parse(H256::from_slice(&input[..32]).as_fixed_bytes());
+			libsecp256k1::recover(&message, &signature, &recovery_id).ok()
+		});
+	match recovered {
+		Some(public) => {
+			let mut output = vec![0; 12];
+			output.extend_from_slice(public_to_address(&public).as_bytes());
+			output
+		}
+		None => Vec::new(),
+	}
+}
+
+/// Length of the exponent in EIP-198's gas formula.
+fn adjusted_exponent_length(exponent_length: U256, exponent_head: &[u8]) -> U256 {
+	let head = U256::from_big_endian(exponent_head);
+	let head_bits = if head.is_zero() { 0 } else { head.bits() - 1 };
+	if exponent_length <= U256::from(32) {
+		U256::from(head_bits)
+	} else {
+		(exponent_length - U256::from(32)).saturating_mul(U256::from(8)) + U256::from(head_bits)
+	}
+}
+
+fn modexp_cost(input: &[u8], divisor: u64, is_eip_2565: bool) -> U256 {
+	let base_length = U256::from_big_endian(&padded(input, 0, 32));
+	let exponent_length = U256::from_big_endian(&padded(input, 32, 32));
+	let modulus_length = U256::from_big_endian(&padded(input, 64, 32));
+
+	let max_length = cmp::max(base_length, modulus_length);
+	if max_length > U256::from(u32::MAX) || exponent_length > U256::from(u32::MAX) {
+		return U256::max_value();
+	}
+	let exponent_head = padded(
+		input,
+		96 + base_length.as_usize(),
+		cmp::min(exponent_length.as_usize(), 32),
+	);
+	let iterations = cmp::max(
+		adjusted_exponent_length(exponent_length, &exponent_head),
+		U256::one(),
+	);
+
+	let x = max_length;
+	if is_eip_2565 {
+		let words = (x + U256::from(7)) / U256::from(8);
+		let complexity = words * words;
+		cmp::max(
+			U256::from(200),
+			complexity.saturating_mul(iterations) / U256::from(divisor),
+		)
+	} else {
+		let complexity = if x <= U256::from(64) {
+			x * x
+		} else if x <= U256::from(1024) {
+			x * x / U256::from(4) + U256::from(96) * x - U256::from(3072)
+		} else {
+			x * x / U256::from(16) + U256::from(480) * x - U256::from(199_680)
+		};
+		complexity.saturating_mul(iterations) / U256::from(divisor)
+	}
+}
+
+fn modexp(input: &[u8]) -> Result<Vec<u8>, &'static str> {
+	let length = |offset| {
+		let length = U256::from_big_endian(&padded(input, offset, 32));
+		if length > U256::from(u32::MAX) {
+			Err("Modexp input too large")
+		} else {
+			Ok(length.as_usize())
+		}
+	};
+	let base_length = length(0)?;
+	let exponent_length = length(32)?;
+	let modulus_length = length(64)?;
+	if modulus_length == 0 {
+		return Ok(Vec::new());
+	}
+
+	let base = padded(input, 96, base_length);
+	let exponent = padded(input, 96 + base_length, exponent_length);
+	let modulus = padded(input, 96 + base_length + exponent_length, modulus_length);
+
+	let mut output = vec![0; modulus_length];
+	if modulus.iter().all(|byte| *byte == 0) {
+		return Ok(output);
+	}
+	let result = BigUint::from_bytes_be(&base).modpow(
+		&BigUint::from_bytes_be(&exponent),
+		&BigUint::from_bytes_be(&modulus),
+	);
+	let result = result.to_bytes_be();
+	// The result is below the modulus, so it fits.
+	output[modulus_length - result.len()..].copy_from_slice(&result);
+	Ok(output)
+}
+
+fn read_g1(input: &[u8]) -> Result<bn::G1, &'static str> {
+	let x = bn::Fq::from_slice(&input[0..32]).map_err(|_| "Invalid point x coordinate")?;
+	let y = bn::Fq::from_slice(&input[32..64]).map_err(|_| "Invalid point y coordinate")?;
+	if x.is_zero() && y.is_zero() {
+		return Ok(bn::G1::zero());
+	}
+	bn::AffineG1::new(x, y)
+		.map(Into::into)
+		.map_err(|_| "Invalid curve point")
+}
+
+fn read_g2(input: &[u8]) -> Result<bn::G2, &'static str> {
+	let fq = |range: std::ops::Range<usize>| {
+		bn::Fq::from_slice(&input[range]).map_err(|_| "Invalid point coordinate")
+	};
+	// Coefficients are encoded imaginary part first.
+	let x = bn::Fq2::new(fq(32..64)?, fq(0..32)?);
+	let y = bn::Fq2::new(fq(96..128)?, fq(64..96)?);
+	if x.is_zero() && y.is_zero() {
+		return Ok(bn::G2::zero());
+	}
+	bn::AffineG2::new(x, y)
+		.map(Into::into)
+		.map_err(|_| "Invalid curve point")
+}
+
+fn write_g1(point: bn::G1) -> Vec<u8> {
+	let mut output = vec![0; 64];
+	if let Some(point) = bn::AffineG1::from_jacobian(point) {
+		// Coordinates are field elements, which always fit 32 bytes.
+		point.x().to_big_endian(&mut output[0..32]).unwrap();
+		point.y().to_big_endian(&mut output[32..64]).unwrap();
+	}
+	output
+}
+
+fn bn128_add(input: &[u8]) -> Result<Vec<u8>, &'static str> {
+	let input = padded(input, 0, 128);
+	let sum = read_g1(&input[0..64])? + read_g1(&input[64..128])?;
+	Ok(write_g1(sum))
+}
+
+fn bn128_mul(input: &[u8]) -> Result<Vec<u8>, &'static str> {
+	let input = padded(input, 0, 96);
+	let point = read_g1(&input[0..64])?;
+	let scalar = bn::Fr::from_slice(&input[64..96]).map_err(|_| "Invalid field element")?;
+	Ok(write_g1(point * scalar))
+}
+
+fn bn128_pairing(input: &[u8]) -> Result<Vec<u8>, &'static str> {
+	if input.len() % 192 != 0 {
+		return Err("Invalid input length, must be multiple of 192");
+	}
+	let pairs = input
+		.chunks(192)
+		.map(|pair| Ok((read_g1(&pair[0..64])?, read_g2(&pair[64..192])?)))
+		.collect::<Result<Vec<_>, &'static str>>()?;
+
+	let mut output = vec![0; 32];
+	if bn::pairing_batch(&pairs) == bn::Gt::one() {
+		output[31] = 1;
+	}
+	Ok(output)
+}
+
+fn blake2_f(input: &[u8]) -> Result<Vec<u8>, &'static str> {
+	if input.len() != 213 {
+		return Err("input length for Blake2 F precompile should be exactly 213 bytes");
+	}
+	let word = |offset: usize| {
+		let mut bytes = [0; 8];
+		bytes.copy_from_slice(&input[offset..offset + 8]);
+		u64::from_le_bytes(bytes)
+	};
+	let rounds = u32::from_be_bytes([input[0], input[1], input[2], input[3]]);
+	let mut state = [0; 8];
+	for (i, word_of_state) in state.iter_mut().enumerate() {
+		*word_of_state = word(4 + i * 8);
+	}
+	let mut message = [0; 16];
+	for (i, word_of_message) in message.iter_mut().enumerate() {
+		*word_of_message = word(68 + i * 8);
+	}
+	let count = [word(196), word(204)];
+	let last = match input[212] {
+		0 => false,
+		1 => true,
+		_ => return Err("incorrect final block indicator flag"),
+	};
+
+	eip_152::compress(&mut state, message, count, last, rounds as usize);
+	Ok(state
+		.iter()
+		.flat_map(|word| word.to_le_bytes().to_vec())
+		.collect())
+}
+
+/// One in a word if `input` holds a message hash, a signature `r`, `s` and
+/// a public key `x`, `y` that verify under secp256r1, nothing otherwise.
+#[cfg(feature = "p256verify")]
+fn p256_verify(input: &[u8]) -> Vec<u8> {
+	use p256::ecdsa::signature::hazmat::PrehashVerifier;
+	use p256::ecdsa::{Signature, VerifyingKey};
+	use p256::{EncodedPoint, FieldBytes};
+
+	if input.len() != 160 {
+		return Vec::new();
+	}
+	let field = |range: std::ops::Range<usize>| *FieldBytes::from_slice(&input[range]);
+	let signature = Signature::from_scalars(field(32..64), field(64..96));
+	let point = EncodedPoint::from_affine_coordinates(&field(96..128), &field(128..160), false);
+	let key = VerifyingKey::from_encoded_point(&point);
+	match (signature, key) {
+		(Ok(signature), Ok(key)) if key.verify_prehash(&input[..32], &signature).is_ok() => {
+			let mut output = vec![0; 32];
+			output[31] = 1;
+			output
+		}
+		_ => Vec::new(),
+	}
+}
@@ -0,0 +1,76 @@
+//! CPU profiles of test runs, written as flamegraphs.
+
+#[cfg(feature = "pprof")]
+use crate::dump::file_stem;
+#[cfg(feature = "pprof")]
+use pprof::ProfilerGuard;
+#[cfg(feature = "pprof")]
+use std::fs::{self, File};
+#[cfg(feature = "pprof")]
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Samples taken per second.
+#[cfg(feature = "pprof")]
+const FREQUENCY: i32 = 997;
+
+/// What a flamegraph is written for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Per {
+	Test,
+	Suite,
+}
+
+/// Flamegraphs requested for a run.
+#[derive(Clone, Debug)]
+pub struct Profile {
+	pub dir: PathBuf,
+	pub per: Per,
+}
+
+#[cfg(feature = "pprof")]
+pub struct Profiler(ProfilerGuard<'static>);
+
+#[cfg(feature = "pprof")]
+impl Profiler {
+	pub fn start() -> Result<Self, String> {
+		ProfilerGuard::new(FREQUENCY)
+			.map(Profiler)
+			.map_err(|err| err.to_string())
+	}
+
+	/// Write the flamegraph of everything sampled since the start to
+	/// `dir/<name>.svg`.
+	pub fn finish(self, dir: &Path, name: &str) -> Result<PathBuf, String> {
+		let report = self.0.report().build().map_err(|err| err.to_string())?;
+		fs::create_dir_all(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+		let path = dir.join(format!("{}.svg", file_stem(name)));
+		let file = File::create(&path).map_err(|err| format!("{}: {}", path.display(), err))?;
+		report
+			.flamegraph(file)
+			.map_err(|err| format!("{}: {}", path.display(), err))?;
+		Ok(path)
+	}
+}
+
+/// Run `f`, writing a flamegraph of it named `name` if `profile` asks for one
+/// per `per`.
+#[cfg(feature = "pprof")]
+pub fn profiled<T, F: FnOnce() -> T>(profile: Option<&Profile>, per: Per, name: &str, f: F) -> T {
+	let profile = match profile {
+		Some(profile) if profile.per == per => profile,
+		_ => return f(),
+	};
+	let profiler = Profiler::start().expect("Start profiler failed");
+	let result = f();
+	profiler
+		.finish(&profile.dir, name)
+		.expect("Write flamegraph failed");
+	result
+}
+
+/// Run `f`. Profiles can only be requested with the `pprof` feature.
+#[cfg(not(feature = "pprof"))]
+pub fn profiled<T, F: FnOnce() -> T>(_: Option<&Profile>, _: Per, _: &str, f: F) -> T {
+	f()
+}
@@ -0,0 +1,148 @@
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+const BAR_WIDTH: usize = 30;
+/// Minimum time between two redraws, so that fast tests do not spend their
+/// time writing to the terminal.
+const REDRAW_INTERVAL: Duration = Duration::from_millis(100);
+
+struct State {
+	done: usize,
+	failed: usize,
+	drawn_at: Option<Instant>,
+}
+
+/// Progress bar of a test run drawn on standard error. It can be shared
+/// between worker threads.
+pub struct Progress {
+	total: usize,
+	started: Instant,
+	state: Mutex<State>,
+}
+
+/// Estimated time left, once at least one test has completed.
+pub fn eta(done: usize, total: usize, elapsed: Duration) -> Option<Duration> {
+	if done == 0 {
+		return None;
+	}
+	let left = total.saturating_sub(done) as u32;
+	Some(elapsed / done as u32 * left)
+}
+
+fn format_duration(duration: Duration) -> String {
+	let secs = duration.as_secs();
+	if secs >= 3600 {
+		format!("{}h{:02}m", secs / 3600, secs / 60 % 60)
+	} else {
+		format!("{}m{:02}s", secs / 60, secs % 60)
+	}
+}
+
+/// Single line of the bar, e.g.
+/// `[=============>                ] 120/260 tests, 2 failed, ETA 0m31s`.
+pub fn render(done: usize, total: usize, failed: usize, elapsed: Duration) -> String {
+	let filled = if total == 0 {
+		BAR_WIDTH
+	} else {
+		BAR_WIDTH * done.min(total) / total
+	};
+	let mut bar = "=".repeat(filled);
+	if filled < BAR_WIDTH {
+		bar.push('>');
+		bar.push_str(&" ".repeat(BAR_WIDTH - filled - 1));
+	}
+	let eta = match eta(done, total, elapsed) {
+		Some(eta) => format_duration(eta),
+		None => "-".to_string(),
+	};
+	format!(
+		"[{}] {}/{} tests, {} failed, ETA {}",
+		bar, done, total, failed, eta
+	)
+}
+
+impl Progress {
+	pub fn new(total: usize) -> Self {
+		let progress = Progress {
+			total,
+			started: Instant::now(),
+			state: Mutex::new(State {
+				done: 0,
+				failed: 0,
+				drawn_at: None,
+			}),
+		};
+		progress.draw(&mut progress.state.lock().unwrap(), true);
+		progress
+	}
+
+	fn draw(&self, state: &mut State, force: bool) {
+		let now = Instant::now();
+		if let Some(drawn_at) = state.drawn_at {
+			if !force && now.duration_since(drawn_at) < REDRAW_INTERVAL {
+				return;
+			}
+		}
+		state.drawn_at = Some(now);
+
+		let line = render(state.done, self.total, state.failed, self.started.elapsed());
+		let mut stderr = io::stderr();
+		let _ = write!(stderr, "\r\x1b[2K{}", line);
+		let _ = stderr.flush();
+	}
+
+	/// Count a completed test.
+	pub fn inc(&self, failed: bool) {
+		let mut state = self.state.lock().unwrap();
+		state.done += 1;
+		if failed {
+			state.failed += 1;
+		}
+		let force = state.done == self.total;
+		self.draw(&mut state, force);
+	}
+
+	/// Print a line above the bar.
+	pub fn println(&self, line: &str) {
+		let mut state = self.state.lock().unwrap();
+		eprint!("\r\x1b[2K{}\n", line);
+		self.draw(&mut state, true);
+	}
+
+	/// Remove the bar from the terminal.
+	pub fn finish(&self) {
+		let _state = self.state.lock().unwrap();
+		eprint!("\r\x1b[2K");
+		let _ = io::stderr().flush();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn renders_bar_and_eta() {
+		assert_eq!(
+			render(0, 10, 0, Duration::from_secs(0)),
+			format!("[>{}] 0/10 tests, 0 failed, ETA -", " ".repeat(29))
+		);
+		assert_eq!(
+			render(5, 10, 1, Duration::from_secs(30)),
+			format!(
+				"[{}>{}] 5/10 tests, 1 failed, ETA 0m30s",
+				"=".repeat(15),
+				" ".repeat(14)
+			)
+		);
+		assert_eq!(
+			render(10, 10, 0, Duration::from_secs(7200)),
+			format!("[{}] 10/10 tests, 0 failed, ETA 0m00s", "=".repeat(30))
+		);
+		assert_eq!(
+			eta(1, 4, Duration::from_secs(1200)),
+			Some(Duration::from_secs(3600))
+		);
+	}
+}
@@ -0,0 +1,158 @@
+use crate::callstack::CallStack;
+use crate::trace::Tracer;
+use evm::ExitReason;
+use evm_runtime::tracing::Event as RuntimeEvent;
+use primitive_types::{H160, H256};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+
+/// Location of the instruction that wrote a storage slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotWriter {
+	/// Call depth, with the transaction's own frame at depth zero.
+	pub depth: usize,
+	pub pc: usize,
+	pub opcode: u8,
+	pub value: H256,
+}
+
+/// Attributes every storage write that survives the transaction to the
+/// instruction performing it. Writes of reverted frames are discarded along
+/// with the frame, as are those of frames ending without an exit event.
+#[derive(Default)]
+pub struct StorageProvenance {
+	frames: CallStack<BTreeMap<(H160, H256), SlotWriter>>,
+	committed: BTreeMap<(H160, H256), SlotWriter>,
+	position: Option<(usize, u8)>,
+}
+
+impl StorageProvenance {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	/// Last surviving writer of every written storage slot.
+	pub fn writers(&self) -> &BTreeMap<(H160, H256), SlotWriter> {
+		&self.committed
+	}
+
+	fn exit(&mut self, reason: &ExitReason) {
+		let frame = match self.frames.exit() {
+			Some(frame) => frame,
+			None => return,
+		};
+		if !reason.is_succeed() {
+			return;
+		}
+		match self.frames.last_mut() {
+			Some(parent) => parent.extend(frame),
+			None => self.committed.extend(frame),
+		}
+	}
+
+	pub fn report(&self) -> String {
+		let mut out = String::new();
+		for ((address, key), writer) in &self.committed {
+			writeln!(
+				out,
+				"{:?}[{:#x}] = {:#x} <- depth {}, pc {}, opcode 0x{:02x}",
+				address, key, writer.value, writer.depth, writer.pc, writer.opcode
+			)
+			.unwrap();
+		}
+		out
+	}
+}
+
+impl Tracer for StorageProvenance {
+	fn evm_event(&mut self, event: evm::tracing::Event) {
+		match event {
+			evm::tracing::Event::Call { .. } | evm::tracing::Event::Create { .. } => {
+				self.frames.enter(&event, BTreeMap::new())
+			}
+			evm::tracing::Event::Exit { reason, .. } => self.exit(reason),
+			_ => (),
+		}
+	}
+
+	fn runtime_event(&mut self, event: RuntimeEvent) {
+		match event {
+			RuntimeEvent::Step {
+				opcode,
+				position,
+				stack,
+				..
+			} => {
+				for _ in 0..self.frames.step(stack) {
+					self.frames.exit();
+				}
+				self.position = position.as_ref().ok().map(|pc| (*pc, opcode.0));
+			}
+			RuntimeEvent::SStore {
+				address,
+				index,
+				value,
+			} => {
+				let (pc, opcode) = self.position.unwrap_or((0, 0x55));
+				let writer = SlotWriter {
+					depth: self.frames.len().saturating_sub(1),
+					pc,
+					opcode,
+					value,
+				};
+				if let Some(frame) = self.frames.last_mut() {
+					frame.insert((address, index), writer);
+				}
+			}
+			_ => (),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::trace::test_helpers::{account, call};
+
+	#[test]
+	fn writers_after_precompile_call() {
+		let contract = H160::from_low_u64_be(0x1000);
+		let callee = H160::from_low_u64_be(0x2000);
+		let mut state = BTreeMap::new();
+		// CALL of the identity precompile, CALL of the callee, then
+		// PUSH1 1 PUSH1 0 SSTORE STOP.
+		state.insert(
+			contract,
+			account(concat!(
+				"60006000600060006000600461fffff150",
+				"6000600060006000600061200061fffff150",
+				"600160005500"
+			)),
+		);
+		// PUSH1 2 PUSH1 0 SSTORE STOP.
+		state.insert(callee, account("600260005500"));
+		let execution = call(state, contract, StorageProvenance::new());
+		assert!(execution.exit_reason.is_succeed());
+
+		let writers = execution.tracer.writers();
+		assert_eq!(writers.len(), 2);
+		assert_eq!(
+			writers[&(contract, H256::zero())],
+			SlotWriter {
+				depth: 0,
+				pc: 39,
+				opcode: 0x55,
+				value: H256::from_low_u64_be(1),
+			}
+		);
+		assert_eq!(
+			writers[&(callee, H256::zero())],
+			SlotWriter {
+				depth: 1,
+				pc: 4,
+				opcode: 0x55,
+				value: H256::from_low_u64_be(2),
+			}
+		);
+	}
+}
@@ -0,0 +1,69 @@
+//! Python module `evm_jsontests`, built with the `python` feature by
+//! maturin, for the execution-spec-tests tooling to drive the runner.
+
+use crate::embed::{self, TestResult};
+use crate::state;
+use pyo3::exceptions::{PyIOError, PyValueError};
+use pyo3::prelude::*;
+use std::fs;
+
+/// Outcome of one test of a fixture file.
+#[pyclass(name = "Outcome")]
+#[derive(Clone, Debug)]
+pub struct PyOutcome {
+	#[pyo3(get)]
+	name: String,
+	#[pyo3(get)]
+	passed: bool,
+	/// Post states executed.
+	#[pyo3(get)]
+	executed: usize,
+	/// Post states of forks the runner does not support.
+	#[pyo3(get)]
+	skipped: usize,
+	/// Message of the failure, if the test failed.
+	#[pyo3(get)]
+	error: Option<String>,
+	/// Everything the test printed.
+	#[pyo3(get)]
+	output: String,
+}
+
+impl From<TestResult> for PyOutcome {
+	fn from(result: TestResult) -> Self {
+		let (executed, skipped) = match &result.outcome {
+			Ok(outcome) => (outcome.executed, outcome.skipped),
+			Err(_) => (0, 0),
+		};
+		PyOutcome {
+			name: result.name,
+			passed: result.outcome.is_ok(),
+			executed,
+			skipped,
+			error: result.outcome.err(),
+			output: result.output,
+		}
+	}
+}
+
+/// Run every test of the state fixture file at `path`, under `fork` only if
+/// it is given.
+#[pyfunction]
+fn run_state_fixture(path: &str, fork: Option<String>) -> PyResult<Vec<PyOutcome>> {
+	let json = fs::read(path).map_err(|err| PyIOError::new_err(format!("{}: {}", path, err)))?;
+	let options = state::Options {
+		forks: fork.into_iter().collect(),
+		quiet: true,
+		..Default::default()
+	};
+	let results = embed::run_state(&json, &options)
+		.map_err(|err| PyValueError::new_err(format!("{}: {}", path, err)))?;
+	Ok(results.into_iter().map(PyOutcome::from).collect())
+}
+
+#[pymodule]
+fn evm_jsontests(_py: Python, module: &PyModule) -> PyResult<()> {
+	module.add_class::<PyOutcome>()?;
+	module.add_function(wrap_pyfunction!(run_state_fixture, module)?)?;
+	Ok(())
+}
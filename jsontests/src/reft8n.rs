@@ -0,0 +1,424 @@
+//! Cross-check of executed indexes against geth's `evm t8n`, with
+//! `--reference-t8n`: the pre-state, block environment and transaction of
+//! an index are handed to geth, and the alloc and receipt it writes are
+//! compared field by field with the runner's, for when the correctness of a
+//! fixture is in doubt. Indexes the runner rejects as invalid are not
+//! cross-checked.
+
+use crate::fixture::{parse_quantity, Env, Indexes, MultiTransaction};
+use crate::gethalloc;
+use crate::header::BLOB_GAS_FORKS;
+use crate::signing::{TxType, UnsignedTransaction};
+use evm::backend::{Log, MemoryAccount};
+use primitive_types::{H160, H256, U256};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs;
+use std::path::Path;
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Forks whose blocks carry withdrawals, which geth requires in the env.
+const WITHDRAWALS_FORKS: &[&str] = &["Shanghai", "Cancun", "Prague", "Osaka"];
+
+/// An index of a state test, as geth is given it.
+#[derive(Debug, Clone, Copy)]
+pub struct Case<'a> {
+	pub env: &'a Env,
+	pub tx: &'a MultiTransaction,
+	pub indexes: &'a Indexes,
+	pub fork: &'a str,
+	pub chain_id: u64,
+	pub pre_state: &'a BTreeMap<H160, MemoryAccount>,
+}
+
+/// Result of the transaction of an index.
+#[derive(Debug, Clone)]
+pub struct Executed {
+	pub alloc: BTreeMap<H160, MemoryAccount>,
+	pub success: bool,
+	pub used_gas: u64,
+	pub logs: Vec<Log>,
+}
+
+#[derive(Deserialize)]
+struct GethLog {
+	address: H160,
+	topics: Vec<H256>,
+	data: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GethReceipt {
+	status: String,
+	gas_used: String,
+	#[serde(default)]
+	logs: Option<Vec<GethLog>>,
+}
+
+#[derive(Deserialize)]
+struct GethRejection {
+	error: String,
+}
+
+#[derive(Deserialize)]
+struct GethResult {
+	#[serde(default)]
+	receipts: Vec<GethReceipt>,
+	#[serde(default)]
+	rejected: Vec<GethRejection>,
+}
+
+/// Block environment of `env` as geth reads it on `fork`.
+pub fn env_json(env: &Env, fork: &str) -> Value {
+	let mut json = json!({
+		"currentCoinbase": format!("{:?}", env.current_coinbase),
+		"currentDifficulty": format!("{:#x}", env.current_difficulty),
+		"currentGasLimit": format!("{:#x}", env.current_gas_limit),
+		"currentNumber": format!("{:#x}", env.current_number),
+		"currentTimestamp": format!("{:#x}", env.current_timestamp),
+	});
+	if let Some(base_fee) = env.current_base_fee {
+		json["currentBaseFee"] = json!(format!("{:#x}", base_fee));
+	}
+	if let Some(random) = env.current_random {
+		json["currentRandom"] = json!(format!("{:?}", random));
+	}
+	if let Some(excess) = env.current_excess_blob_gas {
+		json["currentExcessBlobGas"] = json!(format!("{:#x}", excess));
+	}
+	if WITHDRAWALS_FORKS.contains(&fork) {
+		json["withdrawals"] = json!([]);
+	}
+	if BLOB_GAS_FORKS.contains(&fork) {
+		json["parentBeaconBlockRoot"] = json!(format!("{:?}", H256::zero()));
+	}
+	json
+}
+
+/// Transaction of the alternative `indexes` selects, signed on chain
+/// `chain_id`, as geth reads it.
+pub fn tx_json(tx: &MultiTransaction, indexes: &Indexes, chain_id: u64) -> Result<Value, String> {
+	let unsigned = UnsignedTransaction::from_fixture(tx, indexes, chain_id);
+	let signed = unsigned
+		.sign(&tx.secret_key)
+		.map_err(|err| err.to_string())?;
+	let quantity = |value: U256| json!(format!("{:#x}", value));
+	let mut json = Map::new();
+	json.insert(
+		"type".to_string(),
+		json!(format!("{:#x}", unsigned.tx_type.byte().unwrap_or(0))),
+	);
+	json.insert("chainId".to_string(), quantity(chain_id.into()));
+	json.insert("nonce".to_string(), quantity(unsigned.nonce));
+	json.insert("gas".to_string(), quantity(unsigned.gas_limit));
+	json.insert(
+		"to".to_string(),
+		unsigned
+			.to
+			.map_or(Value::Null, |to| json!(format!("{:?}", to))),
+	);
+	json.insert("value".to_string(), quantity(unsigned.value));
+	json.insert(
+		"input".to_string(),
+		json!(format!("0x{}", hex::encode(&unsigned.data))),
+	);
+	match unsigned.tx_type {
+		TxType::Legacy | TxType::AccessList => {
+			json.insert("gasPrice".to_string(), quantity(unsigned.gas_price));
+		}
+		_ => {
+			json.insert(
+				"maxFeePerGas".to_string(),
+				quantity(unsigned.max_fee_per_gas),
+			);
+			json.insert(
+				"maxPriorityFeePerGas".to_string(),
+				quantity(unsigned.max_priority_fee_per_gas),
+			);
+		}
+	}
+	if unsigned.tx_type != TxType::Legacy {
+		let access_list = unsigned
+			.access_list
+			.iter()
+			.map(|(address, keys)| json!({ "address": address, "storageKeys": keys }))
+			.collect();
+		json.insert("accessList".to_string(), Value::Array(access_list));
+	}
+	if unsigned.tx_type == TxType::Blob {
+		json.insert(
+			"maxFeePerBlobGas".to_string(),
+			quantity(unsigned.max_fee_per_blob_gas),
+		);
+		json.insert(
+			"blobVersionedHashes".to_string(),
+			json!(unsigned.blob_versioned_hashes),
+		);
+	}
+	if unsigned.tx_type == TxType::SetCode {
+		let authorizations = unsigned
+			.authorization_list
+			.iter()
+			.map(|authorization| {
+				json!({
+					"chainId": quantity(authorization.chain_id),
+					"address": format!("{:?}", authorization.address),
+					"nonce": quantity(authorization.nonce),
+					"yParity": quantity(authorization.v),
+					"r": quantity(authorization.r),
+					"s": quantity(authorization.s),
+				})
+			})
+			.collect();
+		json.insert(
+			"authorizationList".to_string(),
+			Value::Array(authorizations),
+		);
+	}
+	json.insert("v".to_string(), quantity(signed.v().into()));
+	json.insert(
+		"r".to_string(),
+		quantity(U256::from_big_endian(signed.signature.r.as_bytes())),
+	);
+	json.insert(
+		"s".to_string(),
+		quantity(U256::from_big_endian(signed.signature.s.as_bytes())),
+	);
+	Ok(Value::Array(vec![Value::Object(json)]))
+}
+
+/// Differences of the accounts of `theirs` from `ours`.
+pub fn alloc_differences(
+	ours: &BTreeMap<H160, MemoryAccount>,
+	theirs: &BTreeMap<H160, MemoryAccount>,
+) -> Vec<String> {
+	let mut differences = Vec::new();
+	let addresses = ours.keys().chain(theirs.keys()).collect::<BTreeSet<_>>();
+	for address in addresses {
+		let (ours, theirs) = match (ours.get(address), theirs.get(address)) {
+			(Some(ours), Some(theirs)) => (ours, theirs),
+			(Some(_), None) => {
+				differences.push(format!("{:?} only exists in ours", address));
+				continue;
+			}
+			(None, _) => {
+				differences.push(format!("{:?} only exists in geth's", address));
+				continue;
+			}
+		};
+		if ours.balance != theirs.balance {
+			differences.push(format!(
+				"{:?} balance: ours {:#x}, geth {:#x}",
+				address, ours.balance, theirs.balance
+			));
+		}
+		if ours.nonce != theirs.nonce {
+			differences.push(format!(
+				"{:?} nonce: ours {}, geth {}",
+				address, ours.nonce, theirs.nonce
+			));
+		}
+		if ours.code != theirs.code {
+			differences.push(format!(
+				"{:?} code: ours 0x{}, geth 0x{}",
+				address,
+				hex::encode(&ours.code),
+				hex::encode(&theirs.code)
+			));
+		}
+		let keys = ours
+			.storage
+			.keys()
+			.chain(theirs.storage.keys())
+			.collect::<BTreeSet<_>>();
+		for key in keys {
+			let ours = ours.storage.get(key).copied().unwrap_or_default();
+			let theirs = theirs.storage.get(key).copied().unwrap_or_default();
+			if ours != theirs {
+				differences.push(format!(
+					"{:?} storage {:?}: ours {:?}, geth {:?}",
+					address, key, ours, theirs
+				));
+			}
+		}
+	}
+	differences
+}
+
+fn receipt_differences(ours: &Executed, theirs: &GethReceipt) -> Result<Vec<String>, String> {
+	let mut differences = Vec::new();
+	let success = !parse_quantity(&theirs.status)?.is_zero();
+	if ours.success != success {
+		differences.push(format!(
+			"status: ours {}, geth {}",
+			ours.success as u8, success as u8
+		));
+	}
+	let used_gas = parse_quantity(&theirs.gas_used)?;
+	if U256::from(ours.used_gas) != used_gas {
+		differences.push(format!(
+			"gas used: ours {}, geth {}",
+			ours.used_gas, used_gas
+		));
+	}
+	let logs = theirs.logs.as_deref().unwrap_or_default();
+	if ours.logs.len() != logs.len() {
+		differences.push(format!(
+			"logs: ours {}, geth {}",
+			ours.logs.len(),
+			logs.len()
+		));
+	}
+	for (i, (ours, theirs)) in ours.logs.iter().zip(logs).enumerate() {
+		let data =
+			hex::decode(theirs.data.trim_start_matches("0x")).map_err(|err| err.to_string())?;
+		if ours.address != theirs.address || ours.topics != theirs.topics || ours.data != data {
+			differences.push(format!(
+				"log {}: ours {:?} {:?} 0x{}, geth {:?} {:?} 0x{}",
+				i,
+				ours.address,
+				ours.topics,
+				hex::encode(&ours.data),
+				theirs.address,
+				theirs.topics,
+				hex::encode(&data)
+			));
+		}
+	}
+	Ok(differences)
+}
+
+/// Differences between `ours`, the result of `case`, and the result of
+/// geth's `evm t8n` at `binary`.
+pub fn cross_check(binary: &Path, case: &Case, ours: &Executed) -> Result<Vec<String>, String> {
+	static RUNS: AtomicUsize = AtomicUsize::new(0);
+	let dir = std::env::temp_dir().join(format!(
+		"evm-jsontests-t8n-{}-{}",
+		process::id(),
+		RUNS.fetch_add(1, Ordering::Relaxed)
+	));
+	fs::create_dir_all(&dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+	let result = run_t8n(binary, &dir, case);
+	let _ = fs::remove_dir_all(&dir);
+	let (result, alloc) = result?;
+
+	if let Some(rejection) = result.rejected.first() {
+		return Ok(vec![format!(
+			"geth rejected the transaction: {}",
+			rejection.error
+		)]);
+	}
+	let receipt = result
+		.receipts
+		.first()
+		.ok_or_else(|| "geth wrote no receipt".to_string())?;
+	let mut differences = receipt_differences(ours, receipt)?;
+	differences.extend(alloc_differences(&ours.alloc, &alloc));
+	Ok(differences)
+}
+
+fn run_t8n(
+	binary: &Path,
+	dir: &Path,
+	case: &Case,
+) -> Result<(GethResult, BTreeMap<H160, MemoryAccount>), String> {
+	let write = |name: &str, json: &Value| {
+		let path = dir.join(name);
+		fs::write(&path, serde_json::to_string(json).unwrap())
+			.map_err(|err| format!("{}: {}", path.display(), err))
+	};
+	gethalloc::write(&dir.join("alloc.json"), case.pre_state)?;
+	write("env.json", &env_json(case.env, case.fork))?;
+	write("txs.json", &tx_json(case.tx, case.indexes, case.chain_id)?)?;
+
+	let output = Command::new(binary)
+		.arg("t8n")
+		.args(["--input.alloc", "alloc.json"])
+		.args(["--input.env", "env.json"])
+		.args(["--input.txs", "txs.json"])
+		.args(["--output.basedir", "."])
+		.args(["--output.result", "result.json"])
+		.args(["--output.alloc", "post.json"])
+		.args(["--state.fork", case.fork])
+		.args(["--state.chainid", &case.chain_id.to_string()])
+		// No block reward, a state test has no block.
+		.args(["--state.reward", "-1"])
+		.current_dir(dir)
+		.output()
+		.map_err(|err| format!("run {} failed: {}", binary.display(), err))?;
+	if !output.status.success() {
+		return Err(format!(
+			"{} t8n failed, {}: {}",
+			binary.display(),
+			output.status,
+			String::from_utf8_lossy(&output.stderr).trim()
+		));
+	}
+	let result = fs::read_to_string(dir.join("result.json"))
+		.map_err(|err| format!("read geth result failed: {}", err))?;
+	let result = serde_json::from_str(&result).map_err(|err| format!("geth result: {}", err))?;
+	let alloc = gethalloc::read(&dir.join("post.json"))?;
+	Ok((result, alloc))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn compared_fields() {
+		let account = |balance: u64| MemoryAccount {
+			balance: balance.into(),
+			nonce: U256::one(),
+			code: Vec::new(),
+			storage: BTreeMap::new(),
+		};
+		let address = H160::repeat_byte(1);
+		let ours = Executed {
+			alloc: vec![(address, account(10))].into_iter().collect(),
+			success: true,
+			used_gas: 21000,
+			logs: Vec::new(),
+		};
+		let mut theirs = ours.alloc.clone();
+		assert!(alloc_differences(&ours.alloc, &theirs).is_empty());
+		theirs
+			.get_mut(&address)
+			.unwrap()
+			.storage
+			.insert(H256::zero(), H256::repeat_byte(2));
+		theirs.insert(H160::repeat_byte(2), account(0));
+		assert_eq!(alloc_differences(&ours.alloc, &theirs).len(), 2);
+
+		let receipt: GethReceipt =
+			serde_json::from_str(r#"{ "status": "0x0", "gasUsed": "0x5209", "logs": null }"#)
+				.unwrap();
+		assert_eq!(
+			receipt_differences(&ours, &receipt).unwrap(),
+			vec![
+				"status: ours 1, geth 0".to_string(),
+				"gas used: ours 21000, geth 21001".to_string()
+			]
+		);
+	}
+
+	#[test]
+	fn geth_env() {
+		let env: Env = serde_json::from_value(json!({
+			"currentCoinbase": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba",
+			"currentDifficulty": "0x020000",
+			"currentGasLimit": "0x05f5e100",
+			"currentNumber": "0x01",
+			"currentTimestamp": "0x03e8",
+			"currentBaseFee": "0x0a",
+		}))
+		.unwrap();
+		let json = env_json(&env, "London");
+		assert_eq!(json["currentBaseFee"], "0xa");
+		assert!(json.get("withdrawals").is_none());
+		assert_eq!(env_json(&env, "Cancun")["withdrawals"], json!([]));
+	}
+}
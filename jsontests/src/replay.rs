@@ -0,0 +1,277 @@
+//! Replay of a mainnet transaction against state read from a node, to
+//! compare gas used and balances with what the chain recorded. State is read
+//! lazily at the parent block, so transactions before the replayed one in its
+//! block are not taken into account.
+
+use crate::client::{block_tag, field, Client, NodeRemote};
+use crate::conformance::ExecutorBuilder;
+use crate::fixture::fork_spec;
+use crate::forked::{Cache, ForkedBackend};
+use crate::state::{fork_config, JsonPrecompile};
+use evm::backend::{Apply, ApplyBackend, Backend, MemoryVicinity};
+use evm::ExitReason;
+use primitive_types::{H160, H256, U256};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::Path;
+
+/// Mainnet forks by first block, as named in fixtures.
+const MAINNET_FORKS: [(u64, &str); 9] = [
+	(0, "Frontier"),
+	(1_150_000, "Homestead"),
+	(2_463_000, "EIP150"),
+	(2_675_000, "EIP158"),
+	(4_370_000, "Byzantium"),
+	(7_280_000, "ConstantinopleFix"),
+	(9_069_000, "Istanbul"),
+	(12_244_000, "Berlin"),
+	(12_965_000, "London"),
+];
+
+/// Fork of mainnet block `number`.
+pub fn mainnet_fork(number: u64) -> &'static str {
+	MAINNET_FORKS
+		.iter()
+		.rev()
+		.find(|(first, _)| number >= *first)
+		.map(|(_, fork)| *fork)
+		.unwrap()
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct AccessListItem {
+	address: H160,
+	storage_keys: Vec<H256>,
+}
+
+/// Balance of an account after the transaction, as replayed and on chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Balance {
+	pub address: H160,
+	pub replayed: U256,
+	pub chain: U256,
+}
+
+#[derive(Debug, Clone)]
+pub struct Report {
+	pub fork: String,
+	pub exit_reason: ExitReason,
+	pub used_gas: u64,
+	/// Gas used according to the receipt.
+	pub expected_gas: u64,
+	/// Balances the transaction changed, the coinbase excluded.
+	pub balances: Vec<Balance>,
+	/// Position of the transaction in its block. Earlier transactions are not
+	/// replayed, so results are only exact at 0.
+	pub transaction_index: u64,
+}
+
+impl Report {
+	pub fn diverged(&self) -> bool {
+		self.used_gas != self.expected_gas
+			|| self
+				.balances
+				.iter()
+				.any(|balance| balance.replayed != balance.chain)
+	}
+}
+
+impl fmt::Display for Report {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "Fork:        {}", self.fork)?;
+		writeln!(f, "Exit reason: {:?}", self.exit_reason)?;
+		writeln!(
+			f,
+			"Gas used:    {} (chain {}){}",
+			self.used_gas,
+			self.expected_gas,
+			if self.used_gas == self.expected_gas {
+				""
+			} else {
+				"  DIVERGED"
+			}
+		)?;
+		for balance in &self.balances {
+			writeln!(
+				f,
+				"Balance of {:?}: {} (chain {}){}",
+				balance.address,
+				balance.replayed,
+				balance.chain,
+				if balance.replayed == balance.chain {
+					""
+				} else {
+					"  DIVERGED"
+				}
+			)?;
+		}
+		if self.transaction_index > 0 {
+			writeln!(
+				f,
+				"Note: the {} transactions before it in its block were not replayed",
+				self.transaction_index
+			)?;
+		}
+		Ok(())
+	}
+}
+
+fn quantity(object: &Value, name: &str) -> Result<u64, String> {
+	let value: U256 = field(object, name)?;
+	if value > U256::from(u64::MAX) {
+		return Err(format!("{}: {} overflows", name, value));
+	}
+	Ok(value.as_u64())
+}
+
+/// Replay transaction `hash` with the state `client` reads, on `fork` or
+/// else on the mainnet fork of its block. State read is cached in
+/// `cache_dir` if given.
+pub fn replay(
+	client: &Client,
+	hash: H256,
+	fork: Option<&str>,
+	cache_dir: Option<&Path>,
+) -> Result<Report, String> {
+	let transaction = client.call("eth_getTransactionByHash", json!([hash]))?;
+	let receipt = client.call("eth_getTransactionReceipt", json!([hash]))?;
+	let number: U256 = field(&transaction, "blockNumber")?;
+	let block = client.call("eth_getBlockByNumber", json!([block_tag(number), false]))?;
+	let chain_id: U256 = serde_json::from_value(client.call("eth_chainId", json!([]))?)
+		.map_err(|err| format!("eth_chainId: {}", err))?;
+
+	let fork = match fork {
+		Some(fork) => fork.to_string(),
+		None if chain_id == U256::one() => mainnet_fork(number.low_u64()).to_string(),
+		None => return Err(format!("Chain {} is not mainnet, select a fork", chain_id)),
+	};
+	let spec = fork_spec(&fork).ok_or_else(|| format!("Unknown fork {}", fork))?;
+	let (config, delete_empty) =
+		fork_config(&spec).ok_or_else(|| format!("Unsupported fork {}", fork))?;
+
+	let caller: H160 = field(&transaction, "from")?;
+	let to: Option<H160> = field(&transaction, "to")?;
+	let value: U256 = field(&transaction, "value")?;
+	let input: String = field(&transaction, "input")?;
+	let data = hex::decode(input.trim_start_matches("0x")).map_err(|err| err.to_string())?;
+	let gas_limit = quantity(&transaction, "gas")?;
+	let access_list: Vec<AccessListItem> = match transaction.get("accessList") {
+		Some(Value::Null) | None => Vec::new(),
+		Some(_) => field(&transaction, "accessList")?,
+	};
+	let access_list = access_list
+		.into_iter()
+		.map(|item| (item.address, item.storage_keys))
+		.collect::<Vec<_>>();
+
+	let vicinity = MemoryVicinity {
+		gas_price: field(&transaction, "gasPrice")?,
+		origin: caller,
+		block_hashes: Vec::new(),
+		block_number: number,
+		block_coinbase: field(&block, "miner")?,
+		block_timestamp: field(&block, "timestamp")?,
+		block_difficulty: field(&block, "difficulty")?,
+		block_gas_limit: field(&block, "gasLimit")?,
+		chain_id,
+	};
+	let parent = number - 1;
+
+	let cache_path = cache_dir.map(|dir| dir.join(format!("{}-{}.json", chain_id, parent)));
+	let cache = match &cache_path {
+		Some(path) => Cache::load(path).map_err(|err| format!("{}: {}", path.display(), err))?,
+		None => Cache::default(),
+	};
+	let remote = NodeRemote {
+		client,
+		block: parent,
+	};
+	let mut backend = ForkedBackend::new(&vicinity, remote, cache);
+
+	let precompile =
+		JsonPrecompile::precompile(&spec).ok_or_else(|| format!("Unsupported fork {}", fork))?;
+	let mut executor = ExecutorBuilder::new(&config, &precompile)
+		.gas_limit(gas_limit)
+		.build(&backend);
+	let total_fee = vicinity.gas_price * gas_limit;
+	executor
+		.state_mut()
+		.withdraw(caller, total_fee)
+		.map_err(|_| "Sender cannot pay for gas".to_string())?;
+	let exit_reason = match to {
+		Some(to) => {
+			executor
+				.transact_call(caller, to, value, data, gas_limit, access_list)
+				.0
+		}
+		None => executor.transact_create(caller, value, data, gas_limit, access_list),
+	};
+	let used_gas = executor.used_gas();
+	let actual_fee = executor.fee(vicinity.gas_price);
+	executor
+		.state_mut()
+		.deposit(vicinity.block_coinbase, actual_fee);
+	executor.state_mut().deposit(caller, total_fee - actual_fee);
+	let (values, logs) = executor.into_state().deconstruct();
+	let values = values.into_iter().collect::<Vec<_>>();
+	let logs = logs.into_iter().collect::<Vec<_>>();
+	if let Some(err) = backend.error() {
+		return Err(err);
+	}
+
+	let changed = values
+		.iter()
+		.map(|apply| match apply {
+			Apply::Modify { address, .. } | Apply::Delete { address } => *address,
+		})
+		.filter(|address| *address != vicinity.block_coinbase)
+		.collect::<BTreeSet<_>>();
+	let before = changed
+		.iter()
+		.map(|address| backend.basic(*address).balance)
+		.collect::<Vec<_>>();
+	backend.apply(values, logs, delete_empty);
+	let mut balances = Vec::new();
+	for (address, before) in changed.iter().zip(before) {
+		let after = backend.basic(*address).balance;
+		if after != before {
+			balances.push(Balance {
+				address: *address,
+				replayed: after,
+				chain: client.balance(*address, number)?,
+			});
+		}
+	}
+	if let Some(path) = &cache_path {
+		backend
+			.cache()
+			.save(path)
+			.map_err(|err| format!("{}: {}", path.display(), err))?;
+	}
+
+	Ok(Report {
+		fork,
+		exit_reason,
+		used_gas,
+		expected_gas: quantity(&receipt, "gasUsed")?,
+		balances,
+		transaction_index: quantity(&transaction, "transactionIndex")?,
+	})
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn mainnet_forks() {
+		assert_eq!(mainnet_fork(0), "Frontier");
+		assert_eq!(mainnet_fork(4_369_999), "EIP158");
+		assert_eq!(mainnet_fork(4_370_000), "Byzantium");
+		assert_eq!(mainnet_fork(12_244_000), "Berlin");
+		assert_eq!(mainnet_fork(15_000_000), "London");
+	}
+}
@@ -0,0 +1,174 @@
+use crate::calltree::CallFrame;
+use crate::conformance::Substate;
+use crate::fixture::FillerInfo;
+use crate::gasreport::GasBreakdown;
+use crate::summary::SkipReason;
+use serde::{Deserialize, Serialize};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+
+/// Machine-readable record of a single executed test index. Call trees and
+/// gas breakdowns are written but not read back.
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct IndexReport {
+	pub name: String,
+	pub fork: String,
+	pub index: usize,
+	/// Label of the data alternative of the index, if the fixture names it.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub label: Option<String>,
+	/// Suite of the fixture file, e.g. `stCreate2`.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub suite: Option<String>,
+	pub passed: bool,
+	/// Wall time of executing and checking the index, in microseconds.
+	pub time_us: u64,
+	/// Gas used by the transaction, missing from reports of older runs.
+	#[serde(default)]
+	pub used_gas: Option<u64>,
+	/// Refund already subtracted from the gas used.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub refunded_gas: Option<u64>,
+	/// Gas of the gas limit returned to the sender.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub gas_left: Option<u64>,
+	/// Data returned by the transaction, hex encoded.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub output: Option<String>,
+	/// Whether the fork deletes empty accounts the transaction touches.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub deletes_empty: Option<bool>,
+	/// Accounts the transaction touched, created and deleted, and what it
+	/// accessed.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub substate: Option<Substate>,
+	/// Interpreter steps executed, missing from reports of older runs.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub steps: Option<u64>,
+	/// Heap allocations made executing the transaction, only counted with
+	/// the `alloc-count` feature.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub allocations: Option<u64>,
+	/// Bytes requested by those allocations.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub allocated_bytes: Option<u64>,
+	/// Assertion message of a failed index.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+	/// Pre/post state difference of a failed index.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub diff: Option<String>,
+	/// EIP-3155 trace of a failed index, possibly truncated.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub trace: Option<String>,
+	/// Backtrace of a panic executing a failed index, as opposed to one of
+	/// its checks failing.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub backtrace: Option<String>,
+	/// Where the fixture of a failed index was filled from.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub filler: Option<FillerInfo>,
+	#[serde(skip_serializing_if = "Option::is_none", skip_deserializing)]
+	pub call_tree: Option<CallFrame>,
+	#[serde(skip_serializing_if = "Option::is_none", skip_deserializing)]
+	pub gas: Option<GasBreakdown>,
+}
+
+/// Machine-readable record of the post states of a fork of a test that were
+/// not run, written to the same report as the executed indexes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct SkipReport {
+	pub name: String,
+	pub fork: String,
+	pub skip_reason: SkipReason,
+	/// Why, in words.
+	pub detail: String,
+	/// Post states not run.
+	pub skipped: usize,
+}
+
+/// A line of a report.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Record {
+	Index(Box<IndexReport>),
+	Skip(SkipReport),
+}
+
+/// `name:fork:index` of an index, followed by its label in brackets if it
+/// has one.
+pub fn index_id(name: &str, fork: &str, index: usize, label: Option<&str>) -> String {
+	match label {
+		Some(label) => format!("{}:{}:{}[{}]", name, fork, index, label),
+		None => format!("{}:{}:{}", name, fork, index),
+	}
+}
+
+fn per_second(amount: u64, time_us: u64) -> f64 {
+	amount as f64 * 1_000_000.0 / time_us.max(1) as f64
+}
+
+fn records(path: &Path) -> io::Result<Vec<Record>> {
+	fs::read_to_string(path)?
+		.lines()
+		.filter(|line| !line.trim().is_empty())
+		.map(|line| serde_json::from_str(line).map_err(io::Error::from))
+		.collect()
+}
+
+/// Read every index record of a JSON lines report.
+pub fn load(path: &Path) -> io::Result<Vec<IndexReport>> {
+	let records = records(path)?.into_iter();
+	Ok(records
+		.filter_map(|record| match record {
+			Record::Index(report) => Some(*report),
+			Record::Skip(_) => None,
+		})
+		.collect())
+}
+
+/// Read every skip record of a JSON lines report.
+pub fn load_skips(path: &Path) -> io::Result<Vec<SkipReport>> {
+	let records = records(path)?.into_iter();
+	Ok(records
+		.filter_map(|record| match record {
+			Record::Skip(skip) => Some(skip),
+			Record::Index(_) => None,
+		})
+		.collect())
+}
+
+/// Append `record` as a single JSON line to the report at `path`.
+fn append_line(path: &Path, record: &impl Serialize) -> io::Result<()> {
+	let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+	let line = serde_json::to_string(record)?;
+	writeln!(file, "{}", line)
+}
+
+impl IndexReport {
+	/// Gas executed per second of wall time.
+	pub fn gas_per_second(&self) -> Option<f64> {
+		self.used_gas.map(|gas| per_second(gas, self.time_us))
+	}
+
+	/// Interpreter steps executed per second of wall time, independent of the
+	/// gas schedule of the fork.
+	pub fn steps_per_second(&self) -> Option<f64> {
+		self.steps.map(|steps| per_second(steps, self.time_us))
+	}
+
+	/// Append the record as a single JSON line to the report at `path`.
+	pub fn append_to(&self, path: &Path) -> io::Result<()> {
+		append_line(path, self)
+	}
+}
+
+impl SkipReport {
+	/// Append the record as a single JSON line to the report at `path`.
+	pub fn append_to(&self, path: &Path) -> io::Result<()> {
+		append_line(path, self)
+	}
+}
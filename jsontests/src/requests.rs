@@ -0,0 +1,272 @@
+//! Execution layer requests of Prague blocks (EIP-7685): deposits read from
+//! the logs of the deposit contract (EIP-6110), and withdrawal (EIP-7002) and
+//! consolidation (EIP-7251) requests returned by calls to their system
+//! contracts at the end of the block. The header commits to them by their
+//! requests hash.
+
+use evm::backend::Log;
+use primitive_types::{H160, H256};
+use sha2::{Digest, Sha256};
+use std::convert::TryInto;
+use std::fmt;
+
+/// Forks with requests in their blocks.
+pub const REQUESTS_FORKS: &[&str] = &["Prague", "Osaka"];
+
+pub const DEPOSIT_REQUEST_TYPE: u8 = 0x00;
+pub const WITHDRAWAL_REQUEST_TYPE: u8 = 0x01;
+pub const CONSOLIDATION_REQUEST_TYPE: u8 = 0x02;
+
+/// Gas of the system calls to the request contracts, not counted in the
+/// block's.
+pub const SYSTEM_CALL_GAS: u64 = 30_000_000;
+
+/// Size of a deposit request: public key, withdrawal credentials, amount,
+/// signature and index.
+const DEPOSIT_REQUEST_SIZE: usize = 48 + 32 + 8 + 96 + 8;
+/// Size of a withdrawal request: source address, validator public key and
+/// amount.
+const WITHDRAWAL_REQUEST_SIZE: usize = 20 + 48 + 8;
+/// Size of a consolidation request: source address and both validator
+/// public keys.
+const CONSOLIDATION_REQUEST_SIZE: usize = 20 + 48 + 48;
+
+fn address(hex: &str) -> H160 {
+	hex.parse().unwrap()
+}
+
+/// Caller of the system calls.
+pub fn system_address() -> H160 {
+	address("fffffffffffffffffffffffffffffffffffffffe")
+}
+
+/// Contract whose `DepositEvent` logs are deposits, the mainnet one.
+pub fn deposit_contract() -> H160 {
+	address("00000000219ab540356cbb839cbe05303d7705fa")
+}
+
+pub fn withdrawal_request_contract() -> H160 {
+	address("00000961ef480eb55e80d19ad83579a64c007002")
+}
+
+pub fn consolidation_request_contract() -> H160 {
+	address("0000bbddc7ce488642fb579f8b00f3a590007251")
+}
+
+/// Topic of `DepositEvent(bytes,bytes,bytes,bytes,bytes)`.
+pub fn deposit_event_topic() -> H256 {
+	"649bbc62d0e31342afea4e5cd82d4049e7e1ee912fc0889aa790803be39038c5"
+		.parse()
+		.unwrap()
+}
+
+/// Why the requests of a block cannot be collected.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RequestError {
+	/// Log `log` of the deposit contract is a `DepositEvent` with data not
+	/// laid out as the deposit contract encodes it.
+	InvalidDepositLog { log: usize },
+	/// The call to a request contract did not succeed.
+	SystemCallFailed { contract: H160, reason: String },
+	/// A request contract returned data not made of whole requests.
+	InvalidRequests { request_type: u8, len: usize },
+}
+
+impl RequestError {
+	/// Reason of the error in exception tables.
+	pub fn reason(&self) -> &'static str {
+		match self {
+			RequestError::InvalidDepositLog { .. } => "Block::InvalidDepositLog",
+			RequestError::SystemCallFailed { .. } => "Block::SystemCallFailed",
+			RequestError::InvalidRequests { .. } => "Block::InvalidRequests",
+		}
+	}
+}
+
+impl fmt::Display for RequestError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			RequestError::InvalidDepositLog { log } => {
+				write!(f, "deposit log {} is not a valid DepositEvent", log)
+			}
+			RequestError::SystemCallFailed { contract, reason } => {
+				write!(f, "system call to {:?} failed: {}", contract, reason)
+			}
+			RequestError::InvalidRequests { request_type, len } => write!(
+				f,
+				"{} bytes of requests of type {} are not whole requests",
+				len, request_type
+			),
+		}
+	}
+}
+
+/// Deposit request of a `DepositEvent` log, `None` if its data is not laid
+/// out as the deposit contract encodes it: the offsets and sizes of its five
+/// byte strings are fixed.
+pub fn deposit_request(log: &Log) -> Option<Vec<u8>> {
+	// Offset of each field among the ABI words, and its size.
+	const FIELDS: [(usize, usize); 5] = [(160, 48), (256, 32), (320, 8), (384, 96), (512, 8)];
+	let data = &log.data;
+	if data.len() != 576 {
+		return None;
+	}
+	let word = |at: usize| {
+		let word = &data[at..at + 32];
+		if word[..24].iter().any(|byte| *byte != 0) {
+			return None;
+		}
+		Some(u64::from_be_bytes(word[24..].try_into().unwrap()) as usize)
+	};
+	let mut request = Vec::with_capacity(DEPOSIT_REQUEST_SIZE);
+	for (i, (offset, size)) in FIELDS.iter().enumerate() {
+		if word(32 * i)? != *offset || word(*offset)? != *size {
+			return None;
+		}
+		request.extend_from_slice(&data[offset + 32..offset + 32 + size]);
+	}
+	Some(request)
+}
+
+/// Deposit requests of `logs`, the logs of a block's transactions in order,
+/// from the `DepositEvent`s of `contract`.
+pub fn deposit_requests(logs: &[Log], contract: H160) -> Result<Vec<u8>, RequestError> {
+	let topic = deposit_event_topic();
+	let mut requests = Vec::new();
+	for (i, log) in logs.iter().enumerate() {
+		if log.address != contract || log.topics.first() != Some(&topic) {
+			continue;
+		}
+		let request = deposit_request(log).ok_or(RequestError::InvalidDepositLog { log: i })?;
+		requests.extend(request);
+	}
+	Ok(requests)
+}
+
+/// Requests of a block by type.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Requests {
+	pub deposits: Vec<u8>,
+	pub withdrawals: Vec<u8>,
+	pub consolidations: Vec<u8>,
+}
+
+impl Requests {
+	/// Requests with `withdrawals` and `consolidations` as their system
+	/// contracts returned them, checked to be whole requests.
+	pub fn new(
+		deposits: Vec<u8>,
+		withdrawals: Vec<u8>,
+		consolidations: Vec<u8>,
+	) -> Result<Self, RequestError> {
+		let whole = |request_type: u8, requests: &[u8], size: usize| {
+			if requests.len() % size == 0 {
+				Ok(())
+			} else {
+				Err(RequestError::InvalidRequests {
+					request_type,
+					len: requests.len(),
+				})
+			}
+		};
+		whole(DEPOSIT_REQUEST_TYPE, &deposits, DEPOSIT_REQUEST_SIZE)?;
+		whole(
+			WITHDRAWAL_REQUEST_TYPE,
+			&withdrawals,
+			WITHDRAWAL_REQUEST_SIZE,
+		)?;
+		whole(
+			CONSOLIDATION_REQUEST_TYPE,
+			&consolidations,
+			CONSOLIDATION_REQUEST_SIZE,
+		)?;
+		Ok(Requests {
+			deposits,
+			withdrawals,
+			consolidations,
+		})
+	}
+
+	/// Requests hash of the header: the SHA-256 of the SHA-256 of each
+	/// type's requests, prefixed by the type, leaving out types without any.
+	pub fn hash(&self) -> H256 {
+		let mut outer = Sha256::new();
+		for (request_type, requests) in [
+			(DEPOSIT_REQUEST_TYPE, &self.deposits),
+			(WITHDRAWAL_REQUEST_TYPE, &self.withdrawals),
+			(CONSOLIDATION_REQUEST_TYPE, &self.consolidations),
+		] {
+			if requests.is_empty() {
+				continue;
+			}
+			let mut inner = Sha256::new();
+			inner.update([request_type]);
+			inner.update(requests);
+			outer.update(inner.finalize());
+		}
+		H256::from_slice(&outer.finalize())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn deposit_log(pubkey: u8) -> Log {
+		let mut data = Vec::new();
+		let word = |value: usize| {
+			let mut word = [0u8; 32];
+			word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+			word
+		};
+		for offset in [160, 256, 320, 384, 512] {
+			data.extend(word(offset));
+		}
+		for (size, padded, byte) in [
+			(48, 64, pubkey),
+			(32, 32, 2),
+			(8, 32, 3),
+			(96, 96, 4),
+			(8, 32, 5),
+		] {
+			data.extend(word(size));
+			data.extend(vec![byte; size]);
+			data.extend(vec![0; padded - size]);
+		}
+		Log {
+			address: deposit_contract(),
+			topics: vec![deposit_event_topic()],
+			data,
+		}
+	}
+
+	#[test]
+	fn deposits_and_hash() {
+		let logs = vec![deposit_log(1), deposit_log(7)];
+		let deposits = deposit_requests(&logs, deposit_contract()).unwrap();
+		assert_eq!(deposits.len(), 2 * DEPOSIT_REQUEST_SIZE);
+		assert_eq!(deposits[..48], [1; 48]);
+		assert_eq!(deposits[48..80], [2; 32]);
+		assert_eq!(deposits[DEPOSIT_REQUEST_SIZE], 7);
+		// Logs of other contracts are not deposits.
+		assert_eq!(deposit_requests(&logs, system_address()), Ok(Vec::new()));
+
+		let mut truncated = deposit_log(1);
+		truncated.data.pop();
+		assert_eq!(
+			deposit_requests(&[truncated], deposit_contract()),
+			Err(RequestError::InvalidDepositLog { log: 0 })
+		);
+
+		// The hash of no requests is the SHA-256 of nothing.
+		assert_eq!(
+			Requests::default().hash(),
+			"e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+				.parse()
+				.unwrap()
+		);
+		let requests = Requests::new(deposits, Vec::new(), Vec::new()).unwrap();
+		assert_ne!(requests.hash(), Requests::default().hash());
+		assert!(Requests::new(Vec::new(), vec![0; 75], Vec::new()).is_err());
+	}
+}
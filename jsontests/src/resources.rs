@@ -0,0 +1,172 @@
+//! Resources the runner reads at run time instead of compiling them in, and
+//! the check of `self-check` that they resolve on this host, whatever its
+//! path separator and line endings.
+
+use crate::builtins;
+use crate::chainspec::ChainSpec;
+use crate::config::Config;
+use crate::fixtures::json_files;
+use crate::kzg::{self, TrustedSetup};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+/// Directory of the resources shipped with the runner.
+pub fn res_dir() -> PathBuf {
+	Path::new(env!("CARGO_MANIFEST_DIR")).join("res")
+}
+
+/// Path of `relative`, separated by `/`, inside [`res_dir`].
+pub fn res_path(relative: &str) -> PathBuf {
+	relative
+		.split('/')
+		.fold(res_dir(), |path, component| path.join(component))
+}
+
+/// Outcome of resolving a resource.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Check {
+	pub name: String,
+	pub path: PathBuf,
+	/// Whether runs fail without the resource, rather than only the tests
+	/// needing it.
+	pub required: bool,
+	pub error: Option<String>,
+}
+
+impl Check {
+	fn new(name: &str, path: &Path, required: bool, result: Result<(), String>) -> Self {
+		Check {
+			name: name.to_string(),
+			path: path.to_path_buf(),
+			required,
+			error: result.err(),
+		}
+	}
+
+	/// Whether the check fails the self-check.
+	pub fn failed(&self) -> bool {
+		self.required && self.error.is_some()
+	}
+}
+
+impl fmt::Display for Check {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let status = match (&self.error, self.required) {
+			(None, _) => "ok",
+			(Some(_), true) => "FAILED",
+			(Some(_), false) => "missing",
+		};
+		write!(f, "{:<7} {}: {}", status, self.name, self.path.display())?;
+		if let Some(error) = &self.error {
+			write!(f, "\n        {}", error)?;
+		}
+		Ok(())
+	}
+}
+
+fn fixtures_at(path: &Path) -> Result<(), String> {
+	if !path.exists() {
+		return Err("does not exist".to_string());
+	}
+	if json_files(path).is_empty() {
+		return Err("has no JSON fixtures".to_string());
+	}
+	Ok(())
+}
+
+/// Resolve the resources a run with `config` reads, and the trusted setup
+/// at `trusted_setup` or the mainnet one. Resources `config` names are
+/// required, the shipped ones only needed by some tests are not.
+pub fn check(config: &Config, trusted_setup: Option<&Path>) -> Vec<Check> {
+	let mut checks = vec![Check::new(
+		"resource directory",
+		&res_dir(),
+		true,
+		if res_dir().is_dir() {
+			Ok(())
+		} else {
+			Err("does not exist, run from a checkout of the repository".to_string())
+		},
+	)];
+
+	let ethtests = res_path("ethtests/GeneralStateTests");
+	checks.push(Check::new(
+		"ethereum/tests checkout",
+		&ethtests,
+		false,
+		fixtures_at(&ethtests).map_err(|err| format!("{}, run `git submodule update --init`", err)),
+	));
+
+	let setup = trusted_setup.map_or_else(kzg::default_path, Path::to_path_buf);
+	let digest = match trusted_setup {
+		Some(_) => Ok(None),
+		None => std::fs::read_to_string(kzg::default_digest_path())
+			.map(Some)
+			.map_err(|err| format!("{}: {}", kzg::default_digest_path().display(), err)),
+	};
+	let loaded = digest.and_then(|digest| TrustedSetup::load(&setup, digest.as_deref()));
+	checks.push(Check::new(
+		"KZG trusted setup",
+		&setup,
+		trusted_setup.is_some(),
+		loaded.map(|_| ()),
+	));
+
+	if let Some(dir) = &config.builtins_dir {
+		let result = builtins::read_dir(dir).map(|_| ());
+		checks.push(Check::new("builtins schedule", dir, true, result));
+	}
+	if let Some(path) = &config.chain_spec {
+		let result = ChainSpec::load(path).map(|_| ());
+		checks.push(Check::new("chain spec", path, true, result));
+	}
+	for (kind, suite) in &[
+		("vm", &config.vm),
+		("state", &config.state),
+		("blockchain", &config.blockchain),
+	] {
+		for path in &suite.paths {
+			let name = format!("{} fixtures", kind);
+			checks.push(Check::new(&name, path, true, fixtures_at(path)));
+		}
+	}
+	checks
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::config::Suite;
+
+	#[test]
+	fn resources_resolve() {
+		assert_eq!(
+			res_path("smoke/callFiller.json"),
+			res_dir().join("smoke").join("callFiller.json")
+		);
+
+		let checks = check(&Config::default(), None);
+		assert_eq!(checks[0].error, None);
+		assert!(!checks.iter().any(Check::failed));
+
+		let config = Config {
+			chain_spec: Some(res_path("missing.json")),
+			state: Suite {
+				paths: vec![res_path("smoke")],
+			},
+			..Default::default()
+		};
+		let checks = check(&config, None);
+		let chain_spec = checks
+			.iter()
+			.find(|check| check.name == "chain spec")
+			.unwrap();
+		assert!(chain_spec.failed());
+		assert!(chain_spec.to_string().starts_with("FAILED  chain spec: "));
+		let state = checks
+			.iter()
+			.find(|check| check.name == "state fixtures")
+			.unwrap();
+		assert_eq!(state.error, None);
+	}
+}
@@ -0,0 +1,506 @@
+use crate::genesis::{Genesis, GenesisHeader};
+use crate::logs::logs_hash;
+use crate::rpc::{param, Handler, RpcError};
+use crate::state::{fork_config, transact};
+use crate::transaction::SignedTransaction;
+use crate::utils::{
+	code_hash, deserialize_hex, dump_storage_range, empty_account, state_root, trie_key,
+	u256_to_h256, JsonAccount,
+};
+use ethjson::spec::ForkSpec;
+use evm::backend::{Log, MemoryAccount, MemoryVicinity};
+use primitive_types::{H160, H256, U256};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha3::{Digest, Keccak256};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+const CLIENT_VERSION: &str = concat!("evm-jsontests/", env!("CARGO_PKG_VERSION"));
+
+/// Genesis header fields set by `test_setChainParams`.
+#[derive(Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+struct GenesisParams {
+	author: H160,
+	difficulty: U256,
+	gas_limit: U256,
+	timestamp: U256,
+	#[serde(default, deserialize_with = "deserialize_hex")]
+	extra_data: Vec<u8>,
+	#[serde(default)]
+	mix_hash: H256,
+	#[serde(default)]
+	base_fee_per_gas: Option<U256>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct Params {
+	fork: String,
+	#[serde(rename = "chainID", default = "U256::one")]
+	chain_id: U256,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct ChainParams {
+	params: Params,
+	accounts: BTreeMap<H160, JsonAccount>,
+	genesis: GenesisParams,
+}
+
+struct Receipt {
+	hash: H256,
+	gas_used: u64,
+	/// Gas used by the transaction and the ones before it in the block.
+	cumulative_gas_used: u64,
+	logs: Vec<Log>,
+}
+
+struct Block {
+	number: u64,
+	hash: H256,
+	parent_hash: H256,
+	timestamp: u64,
+	gas_used: u64,
+	state: BTreeMap<H160, MemoryAccount>,
+	state_root: H256,
+	receipts: Vec<Receipt>,
+}
+
+impl Block {
+	/// Identifier of a mined block, derived from its contents. This is not a
+	/// consensus block hash: only the genesis header is sealed.
+	fn seal(&mut self) {
+		let mut stream = rlp::RlpStream::new_list(5);
+		stream.append(&self.parent_hash);
+		stream.append(&self.state_root);
+		stream.append(&self.number);
+		stream.append(&self.timestamp);
+		stream.begin_list(self.receipts.len());
+		for receipt in &self.receipts {
+			stream.append(&receipt.hash);
+		}
+		self.hash = H256::from_slice(Keccak256::digest(&stream.out()).as_slice());
+	}
+}
+
+/// In-memory chain driven by the `test_` RPC methods.
+struct Chain {
+	spec: ForkSpec,
+	chain_id: U256,
+	genesis: GenesisHeader,
+	blocks: Vec<Block>,
+	pending: Vec<SignedTransaction>,
+	next_timestamp: Option<u64>,
+}
+
+impl Chain {
+	fn new(params: ChainParams) -> Result<Self, RpcError> {
+		let spec = serde_json::from_value::<ForkSpec>(Value::String(params.params.fork.clone()))
+			.map_err(|_| {
+				RpcError::invalid_params(format!("Unknown fork {}", params.params.fork))
+			})?;
+		if fork_config(&spec).is_none() {
+			return Err(RpcError::invalid_params(format!(
+				"Unsupported fork {}",
+				params.params.fork
+			)));
+		}
+
+		let fields = params.genesis;
+		let genesis = Genesis::of_alloc(params.accounts, |header| GenesisHeader {
+			coinbase: fields.author,
+			difficulty: fields.difficulty,
+			gas_limit: fields.gas_limit,
+			timestamp: fields.timestamp,
+			extra_data: fields.extra_data,
+			mix_hash: fields.mix_hash,
+			base_fee_per_gas: fields.base_fee_per_gas,
+			..header
+		});
+		let block = Block {
+			number: 0,
+			hash: genesis.header.hash,
+			parent_hash: H256::zero(),
+			timestamp: genesis.header.timestamp.low_u64(),
+			gas_used: 0,
+			state_root: genesis.header.state_root,
+			state: genesis.state,
+			receipts: Vec::new(),
+		};
+
+		Ok(Chain {
+			spec,
+			chain_id: params.params.chain_id,
+			genesis: genesis.header,
+			blocks: vec![block],
+			pending: Vec::new(),
+			next_timestamp: None,
+		})
+	}
+
+	fn head(&self) -> &Block {
+		self.blocks.last().expect("genesis block always exists")
+	}
+
+	fn block(&self, id: &Value) -> Result<&Block, RpcError> {
+		let block = match id.as_str() {
+			Some("latest") | Some("pending") => Some(self.head()),
+			Some("earliest") => self.blocks.first(),
+			Some(id) if id.len() == 66 => {
+				let hash: H256 = id
+					.trim_start_matches("0x")
+					.parse()
+					.map_err(RpcError::invalid_params)?;
+				self.blocks.iter().find(|block| block.hash == hash)
+			}
+			Some(id) => {
+				let number = u64::from_str_radix(id.trim_start_matches("0x"), 16)
+					.map_err(RpcError::invalid_params)?;
+				self.blocks.get(number as usize)
+			}
+			None => id
+				.as_u64()
+				.and_then(|number| self.blocks.get(number as usize)),
+		};
+		block.ok_or_else(|| RpcError::invalid_params(format!("Unknown block {}", id)))
+	}
+
+	/// Mine a block including every valid pending transaction.
+	fn mine(&mut self) {
+		let parent_timestamp = self.head().timestamp;
+		let timestamp = self.next_timestamp.take().unwrap_or(parent_timestamp + 1);
+		let mut state = self.head().state.clone();
+		let mut receipts = Vec::new();
+		let mut gas_used = 0;
+		let block_gas_limit = self.genesis.gas_limit;
+
+		for tx in std::mem::take(&mut self.pending) {
+			let caller = match tx.sender() {
+				Ok(caller) => caller,
+				Err(_) => continue,
+			};
+			let account = state.get(&caller).cloned().unwrap_or_else(empty_account);
+			let upfront = tx
+				.gas_limit
+				.saturating_mul(tx.gas_price)
+				.saturating_add(tx.value);
+			if account.nonce != tx.nonce
+				|| account.balance < upfront
+				|| U256::from(gas_used) + tx.gas_limit > block_gas_limit
+			{
+				continue;
+			}
+
+			let vicinity = MemoryVicinity {
+				gas_price: tx.gas_price,
+				origin: caller,
+				block_hashes: self.blocks.iter().rev().map(|block| block.hash).collect(),
+				block_number: U256::from(self.blocks.len()),
+				block_coinbase: self.genesis.coinbase,
+				block_timestamp: U256::from(timestamp),
+				block_difficulty: self.genesis.difficulty,
+				block_gas_limit,
+				chain_id: self.chain_id,
+			};
+			let message = tx.message(caller);
+			// The execution borrows the state it started from, so it is done with
+			// before the state is replaced.
+			let post_state = {
+				let execution =
+					match transact(&self.spec, &vicinity, Cow::Borrowed(&state), &message, ()) {
+						Some(execution) => execution,
+						None => continue,
+					};
+				gas_used += execution.used_gas;
+				receipts.push(Receipt {
+					hash: tx.hash(),
+					gas_used: execution.used_gas,
+					cumulative_gas_used: gas_used,
+					logs: execution.logs,
+				});
+				execution.backend.into_state()
+			};
+			state = post_state;
+		}
+
+		let mut block = Block {
+			number: self.blocks.len() as u64,
+			hash: H256::zero(),
+			parent_hash: self.head().hash,
+			timestamp,
+			gas_used,
+			state_root: state_root(&state),
+			state,
+			receipts,
+		};
+		block.seal();
+		self.blocks.push(block);
+	}
+
+	fn receipt(&self, hash: H256) -> Option<&Receipt> {
+		self.blocks
+			.iter()
+			.flat_map(|block| &block.receipts)
+			.find(|receipt| receipt.hash == hash)
+	}
+
+	fn block_json(&self, block: &Block) -> Value {
+		json!({
+			"number": format!("{:#x}", block.number),
+			"hash": block.hash,
+			"parentHash": block.parent_hash,
+			"stateRoot": block.state_root,
+			"miner": self.genesis.coinbase,
+			"difficulty": self.genesis.difficulty,
+			"gasLimit": self.genesis.gas_limit,
+			"gasUsed": format!("{:#x}", block.gas_used),
+			"timestamp": format!("{:#x}", block.timestamp),
+			"transactions": block.receipts.iter().map(|receipt| receipt.hash).collect::<Vec<_>>(),
+			"uncles": [],
+		})
+	}
+}
+
+fn quantity(params: &[Value], index: usize) -> Result<u64, RpcError> {
+	match params.get(index) {
+		Some(Value::Number(number)) => number
+			.as_u64()
+			.ok_or_else(|| RpcError::invalid_params("Invalid quantity")),
+		Some(Value::String(value)) => u64::from_str_radix(value.trim_start_matches("0x"), 16)
+			.map_err(RpcError::invalid_params),
+		_ => Err(RpcError::invalid_params("Missing quantity")),
+	}
+}
+
+/// Implementation of the RPC surface retesteth drives a client under test
+/// with. Each `test_setChainParams` call starts a fresh chain. Post-state
+/// queries ignore their transaction index and report the state at the end
+/// of the requested block.
+#[derive(Default)]
+pub struct Retesteth {
+	chain: Option<Chain>,
+}
+
+impl Retesteth {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	fn chain(&mut self) -> Result<&mut Chain, RpcError> {
+		self.chain
+			.as_mut()
+			.ok_or_else(|| RpcError::internal("test_setChainParams has not been called"))
+	}
+
+	fn account_range(&mut self, params: &[Value]) -> Result<Value, RpcError> {
+		let chain = self.chain()?;
+		let block = chain.block(&params.first().cloned().unwrap_or(Value::Null))?;
+		let start: H256 = param(params, 2)?;
+		let max = quantity(params, 3)? as usize;
+
+		let mut accounts = block
+			.state
+			.keys()
+			.map(|address| (trie_key(address.as_bytes()), *address))
+			.filter(|(hash, _)| *hash >= start)
+			.collect::<Vec<_>>();
+		accounts.sort();
+
+		let next = accounts.get(max).map(|(hash, _)| *hash).unwrap_or_default();
+		let map = accounts
+			.into_iter()
+			.take(max)
+			.map(|(hash, address)| (format!("{:?}", hash), json!(address)))
+			.collect::<serde_json::Map<_, _>>();
+		Ok(json!({ "addressMap": map, "nextKey": next }))
+	}
+
+	fn storage_range(&mut self, params: &[Value]) -> Result<Value, RpcError> {
+		let chain = self.chain()?;
+		let block = chain.block(&params.first().cloned().unwrap_or(Value::Null))?;
+		let address: H160 = param(params, 2)?;
+		let start: H256 = param(params, 3)?;
+		let max = quantity(params, 4)? as usize;
+
+		let account = block
+			.state
+			.get(&address)
+			.cloned()
+			.unwrap_or_else(empty_account);
+		Ok(json!(dump_storage_range(&account, start, max)))
+	}
+
+	fn account<F: Fn(&MemoryAccount) -> Value>(
+		&mut self,
+		params: &[Value],
+		f: F,
+	) -> Result<Value, RpcError> {
+		let address: H160 = param(params, 0)?;
+		let chain = self.chain()?;
+		let block = chain.block(params.get(1).unwrap_or(&json!("latest")))?;
+		Ok(f(&block
+			.state
+			.get(&address)
+			.cloned()
+			.unwrap_or_else(empty_account)))
+	}
+}
+
+impl Handler for Retesteth {
+	fn call(&mut self, method: &str, params: &[Value]) -> Result<Value, RpcError> {
+		match method {
+			"web3_clientVersion" => Ok(json!(CLIENT_VERSION)),
+			"test_setChainParams" => {
+				self.chain = Some(Chain::new(param(params, 0)?)?);
+				Ok(json!(true))
+			}
+			"test_mineBlocks" => {
+				let count = quantity(params, 0)?;
+				let chain = self.chain()?;
+				for _ in 0..count {
+					chain.mine();
+				}
+				Ok(json!(true))
+			}
+			"test_modifyTimestamp" => {
+				let timestamp = quantity(params, 0)?;
+				self.chain()?.next_timestamp = Some(timestamp);
+				Ok(json!(true))
+			}
+			"test_rewindToBlock" => {
+				let number = quantity(params, 0)? as usize;
+				let chain = self.chain()?;
+				chain.blocks.truncate(number + 1);
+				chain.pending.clear();
+				Ok(json!(true))
+			}
+			"test_getLogHash" => {
+				let hash: H256 = param(params, 0)?;
+				let receipt = self
+					.chain()?
+					.receipt(hash)
+					.ok_or_else(|| RpcError::invalid_params("Unknown transaction"))?;
+				Ok(json!(logs_hash(&receipt.logs)))
+			}
+			"eth_sendRawTransaction" => {
+				let raw: String = param(params, 0)?;
+				let raw =
+					hex::decode(raw.trim_start_matches("0x")).map_err(RpcError::invalid_params)?;
+				let tx = SignedTransaction::decode(&raw)
+					.map_err(|err| RpcError::invalid_params(format!("{:?}", err)))?;
+				if let Err(err) = tx.sender() {
+					return Err(RpcError::invalid_params(format!(
+						"Invalid signature: {}",
+						err
+					)));
+				}
+				let hash = tx.hash();
+				self.chain()?.pending.push(tx);
+				Ok(json!(hash))
+			}
+			"eth_blockNumber" => Ok(json!(format!("{:#x}", self.chain()?.head().number))),
+			"eth_getBlockByNumber" | "eth_getBlockByHash" => {
+				let chain = self.chain()?;
+				let block = chain.block(params.first().unwrap_or(&Value::Null))?;
+				Ok(chain.block_json(block))
+			}
+			"eth_getBalance" => self.account(params, |account| json!(account.balance)),
+			"eth_getTransactionCount" => self.account(params, |account| json!(account.nonce)),
+			"eth_getCode" => self.account(params, |account| {
+				json!(format!("0x{}", hex::encode(&account.code)))
+			}),
+			"eth_getStorageAt" => {
+				let key: U256 = param(params, 1)?;
+				let address: H160 = param(params, 0)?;
+				let chain = self.chain()?;
+				let block = chain.block(params.get(2).unwrap_or(&json!("latest")))?;
+				let value = block
+					.state
+					.get(&address)
+					.and_then(|account| account.storage.get(&u256_to_h256(key)).cloned())
+					.unwrap_or_default();
+				Ok(json!(value))
+			}
+			"eth_getTransactionReceipt" => {
+				let hash: H256 = param(params, 0)?;
+				let chain = self.chain()?;
+				Ok(match chain.receipt(hash) {
+					Some(receipt) => json!({
+						"transactionHash": receipt.hash,
+						"gasUsed": format!("{:#x}", receipt.gas_used),
+						"cumulativeGasUsed": format!("{:#x}", receipt.cumulative_gas_used),
+						"logsHash": logs_hash(&receipt.logs),
+					}),
+					None => Value::Null,
+				})
+			}
+			"debug_accountRange" => self.account_range(params),
+			"debug_storageRangeAt" => self.storage_range(params),
+			"debug_accountCodeHash" => {
+				self.account(params, |account| json!(code_hash(&account.code)))
+			}
+			_ => Err(RpcError::method_not_found(method)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn chain_params() -> Value {
+		json!({
+			"params": { "fork": "Berlin", "chainID": "0x01" },
+			"accounts": {
+				"0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b": {
+					"balance": "0x0de0b6b3a7640000",
+					"code": "0x",
+					"nonce": "0x00",
+					"storage": {}
+				}
+			},
+			"genesis": {
+				"author": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba",
+				"difficulty": "0x020000",
+				"gasLimit": "0xff112233445566",
+				"extraData": "0x00",
+				"timestamp": "0x00",
+				"nonce": "0x0000000000000000",
+				"mixHash": "0x0000000000000000000000000000000000000000000000000000000000000000"
+			},
+			"sealEngine": "NoProof"
+		})
+	}
+
+	#[test]
+	fn set_chain_params_and_query() {
+		let mut server = Retesteth::new();
+		assert!(server.call("eth_blockNumber", &[]).is_err());
+		assert_eq!(
+			server.call("test_setChainParams", &[chain_params()]),
+			Ok(json!(true))
+		);
+		assert_eq!(server.call("eth_blockNumber", &[]), Ok(json!("0x0")));
+		assert_eq!(
+			server.call(
+				"eth_getBalance",
+				&[json!("0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b")]
+			),
+			Ok(json!("0xde0b6b3a7640000"))
+		);
+
+		let range = server
+			.call(
+				"debug_accountRange",
+				&[
+					json!("0x0"),
+					json!(0),
+					json!(format!("{:?}", H256::zero())),
+					json!(10),
+				],
+			)
+			.unwrap();
+		assert_eq!(range["addressMap"].as_object().unwrap().len(), 1);
+	}
+}
@@ -0,0 +1,113 @@
+//! Roots of the ordered tries a block header commits to: its transactions, the
+//! receipts of their execution and its withdrawals, each keyed by the RLP of
+//! its index in the block.
+
+use crate::logs::logs_bloom;
+use evm::backend::Log;
+use primitive_types::{H256, U256};
+use rlp::RlpStream;
+use sha3::{Digest, Keccak256};
+
+/// Root of an empty trie.
+pub fn empty_root() -> H256 {
+	H256::from_slice(Keccak256::digest(&rlp::NULL_RLP).as_slice())
+}
+
+/// Root of the trie holding `items` at their indexes.
+pub fn ordered_root<I, V>(items: I) -> H256
+where
+	I: IntoIterator<Item = V>,
+	V: AsRef<[u8]>,
+{
+	triehash_ethereum::ordered_trie_root(items)
+}
+
+/// Transactions root of a block with transactions encoded as `encodings`:
+/// RLP lists for legacy transactions, typed envelopes for the others.
+pub fn transactions_root<T: AsRef<[u8]>>(encodings: &[T]) -> H256 {
+	ordered_root(encodings.iter().map(AsRef::as_ref))
+}
+
+/// How a receipt records the outcome of its transaction: the state root
+/// after it before Byzantium, whether it succeeded from Byzantium on
+/// (EIP-658).
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum PostState {
+	Root(H256),
+	Status(bool),
+}
+
+/// Receipt of a transaction of a block.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Receipt {
+	/// Type of the transaction, `None` for legacy ones.
+	pub tx_type: Option<u8>,
+	pub post_state: PostState,
+	/// Gas used by the transaction and the ones before it in the block.
+	pub cumulative_gas_used: U256,
+	pub logs: Vec<Log>,
+}
+
+impl Receipt {
+	/// Encoding of the receipt in the receipts trie, enveloped with the
+	/// transaction type for typed transactions (EIP-2718).
+	pub fn encode(&self) -> Vec<u8> {
+		let mut stream = RlpStream::new_list(4);
+		match self.post_state {
+			PostState::Root(root) => stream.append(&root),
+			PostState::Status(succeeded) => stream.append(&(succeeded as u8)),
+		};
+		stream.append(&self.cumulative_gas_used);
+		stream.append(&logs_bloom(&self.logs).as_bytes());
+		stream.begin_list(self.logs.len());
+		for log in &self.logs {
+			stream.begin_list(3);
+			stream.append(&log.address);
+			stream.append_list(&log.topics);
+			stream.append(&log.data);
+		}
+		let receipt = stream.out().to_vec();
+		match self.tx_type {
+			Some(tx_type) => [vec![tx_type], receipt].concat(),
+			None => receipt,
+		}
+	}
+}
+
+/// Receipts root of a block whose transactions have `receipts`.
+pub fn receipts_root(receipts: &[Receipt]) -> H256 {
+	ordered_root(receipts.iter().map(Receipt::encode))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::str::FromStr;
+
+	#[test]
+	fn roots_of_single_items() {
+		assert_eq!(transactions_root::<Vec<u8>>(&[]), empty_root());
+		assert_eq!(receipts_root(&[]), empty_root());
+
+		// Receipt of a legacy transfer in the first block of a chain.
+		let receipt = Receipt {
+			tx_type: None,
+			post_state: PostState::Status(true),
+			cumulative_gas_used: U256::from(21000),
+			logs: Vec::new(),
+		};
+		let encoding = receipt.encode();
+		assert_eq!(&encoding[..6], [0xf9, 0x01, 0x08, 0x01, 0x82, 0x52]);
+		assert_eq!(
+			receipts_root(&[receipt.clone()]),
+			H256::from_str("056b23fbba480696b65fe5a59b8f2148a1299103c4f57df839233af2cf4ca2d2")
+				.unwrap()
+		);
+
+		let typed = Receipt {
+			tx_type: Some(1),
+			..receipt
+		};
+		assert_eq!(typed.encode(), [vec![1], encoding].concat());
+	}
+}
@@ -0,0 +1,138 @@
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+/// JSON-RPC error object.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RpcError {
+	pub code: i64,
+	pub message: String,
+}
+
+impl RpcError {
+	pub fn method_not_found(method: &str) -> Self {
+		RpcError {
+			code: -32601,
+			message: format!("Method not found: {}", method),
+		}
+	}
+
+	pub fn invalid_params<T: std::fmt::Display>(message: T) -> Self {
+		RpcError {
+			code: -32602,
+			message: message.to_string(),
+		}
+	}
+
+	pub fn internal<T: std::fmt::Display>(message: T) -> Self {
+		RpcError {
+			code: -32603,
+			message: message.to_string(),
+		}
+	}
+}
+
+/// Positional param `index` of a call, `null` if it is missing.
+pub fn param<T: DeserializeOwned>(params: &[Value], index: usize) -> Result<T, RpcError> {
+	let value = params.get(index).cloned().unwrap_or(Value::Null);
+	serde_json::from_value(value).map_err(RpcError::invalid_params)
+}
+
+/// Handles a single JSON-RPC call given its method and positional params.
+pub trait Handler {
+	fn call(&mut self, method: &str, params: &[Value]) -> Result<Value, RpcError>;
+}
+
+fn respond(handler: &mut dyn Handler, request: &Value) -> Value {
+	let id = request.get("id").cloned().unwrap_or(Value::Null);
+	let method = request.get("method").and_then(Value::as_str).unwrap_or("");
+	let params = match request.get("params") {
+		Some(Value::Array(params)) => params.clone(),
+		Some(Value::Null) | None => Vec::new(),
+		Some(param) => vec![param.clone()],
+	};
+
+	match handler.call(method, &params) {
+		Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+		Err(err) => json!({
+			"jsonrpc": "2.0",
+			"id": id,
+			"error": { "code": err.code, "message": err.message },
+		}),
+	}
+}
+
+/// Dispatch a raw request body, which may be a single call or a batch.
+pub fn dispatch(handler: &mut dyn Handler, body: &[u8]) -> Value {
+	match serde_json::from_slice::<Value>(body) {
+		Ok(Value::Array(batch)) => Value::Array(
+			batch
+				.iter()
+				.map(|request| respond(handler, request))
+				.collect(),
+		),
+		Ok(request) => respond(handler, &request),
+		Err(err) => json!({
+			"jsonrpc": "2.0",
+			"id": null,
+			"error": { "code": -32700, "message": err.to_string() },
+		}),
+	}
+}
+
+fn read_request(stream: &mut BufReader<TcpStream>) -> io::Result<Option<Vec<u8>>> {
+	let mut content_length = 0;
+	let mut line = String::new();
+	if stream.read_line(&mut line)? == 0 {
+		return Ok(None);
+	}
+	loop {
+		line.clear();
+		if stream.read_line(&mut line)? == 0 {
+			return Ok(None);
+		}
+		let header = line.trim_end();
+		if header.is_empty() {
+			break;
+		}
+		if let Some((name, value)) = header.split_once(':') {
+			if name.eq_ignore_ascii_case("content-length") {
+				content_length = value.trim().parse().map_err(|_| {
+					io::Error::new(io::ErrorKind::InvalidData, "bad content length")
+				})?;
+			}
+		}
+	}
+
+	let mut body = vec![0; content_length];
+	stream.read_exact(&mut body)?;
+	Ok(Some(body))
+}
+
+fn handle_connection(handler: &mut dyn Handler, stream: TcpStream) -> io::Result<()> {
+	let mut writer = stream.try_clone()?;
+	let mut reader = BufReader::new(stream);
+	while let Some(body) = read_request(&mut reader)? {
+		let response = serde_json::to_vec(&dispatch(handler, &body))?;
+		write!(
+			writer,
+			"HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n",
+			response.len()
+		)?;
+		writer.write_all(&response)?;
+		writer.flush()?;
+	}
+	Ok(())
+}
+
+/// Serve JSON-RPC over HTTP on `addr`, one connection at a time.
+pub fn serve<A: ToSocketAddrs>(addr: A, handler: &mut dyn Handler) -> io::Result<()> {
+	let listener = TcpListener::bind(addr)?;
+	for stream in listener.incoming() {
+		if let Err(err) = handle_connection(handler, stream?) {
+			eprintln!("Connection error: {}", err);
+		}
+	}
+	Ok(())
+}
@@ -0,0 +1,141 @@
+//! SARIF log of the failing tests of a run, for `--sarif`: one result for
+//! each failing test, located at its test in the fixture file, with its
+//! failure and output, such as the state diff, as the message. Code
+//! scanning dashboards like GitHub's show them inline on pull requests.
+
+use serde_json::{json, Value};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::Path;
+
+const SCHEMA: &str = "https://json.schemastore.org/sarif-2.1.0.json";
+
+/// A failing test where a code scanning dashboard shows it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Finding {
+	/// Kind of the test, as `state`, the rule of the result.
+	pub kind: String,
+	pub file: String,
+	pub name: String,
+	/// Line of the test within its file, if it was found.
+	pub line: Option<usize>,
+	/// First line of the failure message.
+	pub message: String,
+	/// Captured output of the test.
+	pub output: String,
+}
+
+/// Line of the key of the test `name` in the fixture file `contents`.
+pub fn line_of(contents: &[u8], name: &str) -> Option<usize> {
+	let key = serde_json::to_vec(name).ok()?;
+	let start = contents
+		.windows(key.len())
+		.position(|window| window == key.as_slice())?;
+	Some(
+		contents[..start]
+			.iter()
+			.filter(|byte| **byte == b'\n')
+			.count() + 1,
+	)
+}
+
+fn rule_id(kind: &str) -> String {
+	format!("{}-test-failure", kind)
+}
+
+fn result(finding: &Finding) -> Value {
+	let mut text = format!("{} failed: {}", finding.name, finding.message);
+	if !finding.output.is_empty() {
+		text.push_str("\n\n");
+		text.push_str(finding.output.trim_end());
+	}
+	let mut location = json!({
+		"artifactLocation": { "uri": finding.file.trim_start_matches("./") },
+	});
+	if let Some(line) = finding.line {
+		location["region"] = json!({ "startLine": line });
+	}
+	json!({
+		"ruleId": rule_id(&finding.kind),
+		"level": "error",
+		"message": { "text": text },
+		"locations": [{ "physicalLocation": location }],
+		"partialFingerprints": { "testId": format!("{}:{}", finding.file, finding.name) },
+	})
+}
+
+/// SARIF log of `findings`.
+pub fn log(findings: &[Finding]) -> Value {
+	let kinds = findings
+		.iter()
+		.map(|finding| finding.kind.as_str())
+		.collect::<BTreeSet<_>>();
+	let rules = kinds
+		.into_iter()
+		.map(|kind| {
+			json!({
+				"id": rule_id(kind),
+				"shortDescription": { "text": format!("Failing {} test", kind) },
+			})
+		})
+		.collect::<Vec<_>>();
+	json!({
+		"$schema": SCHEMA,
+		"version": "2.1.0",
+		"runs": [{
+			"tool": {
+				"driver": {
+					"name": env!("CARGO_PKG_NAME"),
+					"version": env!("CARGO_PKG_VERSION"),
+					"informationUri": env!("CARGO_PKG_REPOSITORY"),
+					"rules": rules,
+				},
+			},
+			"results": findings.iter().map(result).collect::<Vec<_>>(),
+		}],
+	})
+}
+
+pub fn write(path: &Path, findings: &[Finding]) -> Result<(), String> {
+	let contents = serde_json::to_string_pretty(&log(findings)).map_err(|err| err.to_string())?;
+	fs::write(path, contents).map_err(|err| format!("{}: {}", path.display(), err))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn sarif_log() {
+		let contents = b"{\n\t\"add\": {\n\t\t\"env\": {}\n\t},\n\t\"sub\": {}\n}";
+		assert_eq!(line_of(contents, "sub"), Some(5));
+		assert_eq!(line_of(contents, "mul"), None);
+
+		let finding = Finding {
+			kind: "state".to_string(),
+			file: "./tests/stExample/add.json".to_string(),
+			name: "sub".to_string(),
+			line: line_of(contents, "sub"),
+			message: "State root mismatch".to_string(),
+			output: "Running sub:Berlin:0 ... \n".to_string(),
+		};
+		let log = log(&[finding]);
+		let run = &log["runs"][0];
+		assert_eq!(
+			run["tool"]["driver"]["rules"][0]["id"],
+			"state-test-failure"
+		);
+		let result = &run["results"][0];
+		assert_eq!(result["ruleId"], "state-test-failure");
+		assert_eq!(
+			result["message"]["text"],
+			"sub failed: State root mismatch\n\nRunning sub:Berlin:0 ..."
+		);
+		let location = &result["locations"][0]["physicalLocation"];
+		assert_eq!(
+			location["artifactLocation"]["uri"],
+			"tests/stExample/add.json"
+		);
+		assert_eq!(location["region"]["startLine"], 5);
+	}
+}
@@ -0,0 +1,180 @@
+//! Order tests are run in when only part of the suite may run, from the
+//! results of an earlier run.
+
+use crate::report::IndexReport;
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+/// Parse a duration such as `90s`, `10m` or `1h`, in seconds without a unit.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+	let (number, unit) = match value.find(|c: char| !c.is_ascii_digit()) {
+		Some(split) => value.split_at(split),
+		None => (value, "s"),
+	};
+	let number: u64 = number
+		.parse()
+		.map_err(|_| format!("invalid duration {}", value))?;
+	let seconds = match unit {
+		"s" => number,
+		"m" => number * 60,
+		"h" => number * 60 * 60,
+		_ => return Err(format!("invalid duration unit in {}", value)),
+	};
+	Ok(Duration::from_secs(seconds))
+}
+
+/// Result of a test in an earlier run.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Record {
+	/// Whether any index of the test failed.
+	pub failed: bool,
+	/// Total time of its indexes, in microseconds.
+	pub time_us: u64,
+}
+
+/// Priority of a test, lower runs first: tests that failed, then tests with
+/// no record, then passing tests from the fastest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Priority {
+	Failed,
+	Unknown,
+	Passed(u64),
+}
+
+impl Priority {
+	fn rank(&self) -> (u8, u64) {
+		match self {
+			Priority::Failed => (0, 0),
+			Priority::Unknown => (1, 0),
+			Priority::Passed(time_us) => (2, *time_us),
+		}
+	}
+}
+
+impl Ord for Priority {
+	fn cmp(&self, other: &Self) -> Ordering {
+		self.rank().cmp(&other.rank())
+	}
+}
+
+impl PartialOrd for Priority {
+	fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+		Some(self.cmp(other))
+	}
+}
+
+/// Results of an earlier run by test name.
+#[derive(Clone, Debug, Default)]
+pub struct History {
+	pub tests: BTreeMap<String, Record>,
+}
+
+impl History {
+	/// History of the records of a report. Reports appended to by several
+	/// runs hold an index more than once, its last record counts.
+	pub fn from_reports(reports: &[IndexReport]) -> Self {
+		let latest = reports
+			.iter()
+			.map(|report| ((&report.name, &report.fork, report.index), report))
+			.collect::<BTreeMap<_, _>>();
+		let mut tests = BTreeMap::<String, Record>::new();
+		for report in latest.values() {
+			let record = tests.entry(report.name.clone()).or_default();
+			record.failed |= !report.passed;
+			record.time_us += report.time_us;
+		}
+		History { tests }
+	}
+
+	pub fn priority(&self, name: &str) -> Priority {
+		match self.tests.get(name) {
+			Some(record) if record.failed => Priority::Failed,
+			Some(record) => Priority::Passed(record.time_us),
+			None => Priority::Unknown,
+		}
+	}
+
+	/// Priority of a file of tests named `names`: the one of its first test
+	/// to run, ties broken by the total time of the file.
+	pub fn file_priority<'a, I: IntoIterator<Item = &'a str>>(&self, names: I) -> Priority {
+		let mut first = None;
+		let mut total = 0;
+		for priority in names.into_iter().map(|name| self.priority(name)) {
+			if let Priority::Passed(time_us) = priority {
+				total += time_us;
+			}
+			first = Some(first.map_or(priority, |first: Priority| first.min(priority)));
+		}
+		match first {
+			Some(Priority::Passed(_)) => Priority::Passed(total),
+			Some(priority) => priority,
+			None => Priority::Unknown,
+		}
+	}
+
+	/// Sort `tests` by priority, keeping the order of equal ones.
+	pub fn prioritize<T>(&self, tests: &mut [(String, T)]) {
+		tests.sort_by_key(|(name, _)| self.priority(name));
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn report(name: &str, passed: bool, time_us: u64) -> IndexReport {
+		IndexReport {
+			name: name.to_string(),
+			fork: "Berlin".to_string(),
+			passed,
+			time_us,
+			..Default::default()
+		}
+	}
+
+	#[test]
+	fn durations() {
+		assert_eq!(parse_duration("10m"), Ok(Duration::from_secs(600)));
+		assert_eq!(parse_duration("90s"), Ok(Duration::from_secs(90)));
+		assert_eq!(parse_duration("2h"), Ok(Duration::from_secs(7200)));
+		assert_eq!(parse_duration("30"), Ok(Duration::from_secs(30)));
+		assert!(parse_duration("10d").is_err());
+		assert!(parse_duration("m").is_err());
+	}
+
+	#[test]
+	fn failures_first_slowest_last() {
+		let mut slow = report("slow", true, 500);
+		slow.index = 1;
+		let history = History::from_reports(&[
+			report("slow", true, 10),
+			slow,
+			report("fast", false, 5),
+			report("broken", true, 1),
+			// A later run broke `broken` and fixed `fast`.
+			report("broken", false, 900),
+			report("fast", true, 5),
+		]);
+		let mut tests = ["slow", "new", "fast", "broken"]
+			.iter()
+			.map(|name| (name.to_string(), ()))
+			.collect::<Vec<_>>();
+		history.prioritize(&mut tests);
+		let order = tests
+			.iter()
+			.map(|(name, _)| name.as_str())
+			.collect::<Vec<_>>();
+		assert_eq!(order, ["broken", "new", "fast", "slow"]);
+
+		assert_eq!(
+			history.file_priority(vec!["fast", "slow"]),
+			Priority::Passed(515)
+		);
+		assert_eq!(
+			history.file_priority(vec!["fast", "broken"]),
+			Priority::Failed
+		);
+		assert_eq!(history.file_priority(Vec::new()), Priority::Unknown);
+	}
+}
@@ -0,0 +1,82 @@
+//! Strict parsing of fixture files, for `--strict-schema`: fields the
+//! runner does not know are errors instead of being ignored, so that fields
+//! new upstream fixtures add, such as those of new environment values or
+//! transaction types, fail CI rather than pass tests that do not check them.
+//!
+//! `_info` is metadata and never checked. Fields of flattened and untagged
+//! parts of fixtures are buffered before they are parsed and not checked
+//! either.
+
+use serde::de::DeserializeOwned;
+use serde_ignored::Path;
+use std::collections::BTreeMap;
+
+fn is_metadata(path: &Path) -> bool {
+	match path {
+		Path::Root => false,
+		Path::Map { key, .. } if key == "_info" => true,
+		Path::Map { parent, .. }
+		| Path::Seq { parent, .. }
+		| Path::Some { parent }
+		| Path::NewtypeStruct { parent }
+		| Path::NewtypeVariant { parent } => is_metadata(parent),
+	}
+}
+
+/// Tests of the fixture file `contents`, failing on unknown fields if
+/// `strict`.
+pub fn parse<T: DeserializeOwned>(
+	contents: &[u8],
+	strict: bool,
+) -> Result<BTreeMap<String, T>, String> {
+	if !strict {
+		return serde_json::from_slice(contents).map_err(|err| err.to_string());
+	}
+	let mut unknown = Vec::new();
+	let mut deserializer = serde_json::Deserializer::from_slice(contents);
+	let tests = serde_ignored::deserialize(&mut deserializer, |path| {
+		if !is_metadata(&path) {
+			unknown.push(path.to_string());
+		}
+	})
+	.map_err(|err| err.to_string())?;
+	if unknown.is_empty() {
+		Ok(tests)
+	} else {
+		Err(format!("unknown fields {}", unknown.join(", ")))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use serde::Deserialize;
+
+	#[derive(Deserialize, Debug)]
+	struct Env {
+		#[serde(rename = "currentNumber")]
+		#[allow(dead_code)]
+		number: String,
+	}
+
+	#[derive(Deserialize, Debug)]
+	struct Test {
+		#[allow(dead_code)]
+		env: Env,
+	}
+
+	#[test]
+	fn unknown_fields() {
+		let known =
+			br#"{ "add": { "env": { "currentNumber": "0x01" }, "_info": { "comment": "" } } }"#;
+		assert_eq!(parse::<Test>(known, true).unwrap().len(), 1);
+
+		let unknown = br#"{ "add": { "env": { "currentNumber": "0x01", "slotNumber": "0x00" } } }"#;
+		assert!(parse::<Test>(unknown, false).is_ok());
+		assert_eq!(
+			parse::<Test>(unknown, true).unwrap_err(),
+			"unknown fields add.env.slotNumber"
+		);
+		assert!(parse::<Test>(b"{", true).is_err());
+	}
+}
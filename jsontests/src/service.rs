@@ -0,0 +1,149 @@
+//! JSON-RPC service running submitted fixtures in the background, for a
+//! central conformance service to farm runs out to worker machines.
+//!
+//! `test_submit` takes `{"kind", "fixture", "forks", "tracer"}`, with `kind`
+//! one of `state`, `vm` or `blockchain`, and returns the id of the job.
+//! `test_result` takes that id and returns `{"done": false}` while the job
+//! runs, then `{"done": true, "results": [..]}` with the results of
+//! `embed::TestResult::to_json`. Traces are part of the output of the tests.
+
+use crate::embed;
+use crate::rpc::{param, Handler, RpcError};
+use crate::structlog::TraceFormat;
+use crate::{blockchain, state, vm};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+enum Kind {
+	State,
+	Vm,
+	Blockchain,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+struct Submission {
+	kind: Kind,
+	fixture: Value,
+	/// Forks to run, all supported ones if empty.
+	#[serde(default)]
+	forks: Vec<String>,
+	/// Trace format of state tests, as for `--tracer`.
+	#[serde(default)]
+	tracer: Option<String>,
+}
+
+impl Submission {
+	fn run(&self) -> Value {
+		let fixture = self.fixture.to_string().into_bytes();
+		let results = match self.kind {
+			Kind::State => {
+				let options = state::Options {
+					forks: self.forks.clone(),
+					tracer: self.tracer.as_ref().map(|tracer| tracer.parse().unwrap()),
+					quiet: true,
+					..Default::default()
+				};
+				embed::run_state(&fixture, &options)
+			}
+			Kind::Vm => {
+				let options = vm::Options {
+					quiet: true,
+					..Default::default()
+				};
+				embed::run_vm(&fixture, &options)
+			}
+			Kind::Blockchain => {
+				let options = blockchain::Options {
+					forks: self.forks.clone(),
+					quiet: true,
+					..Default::default()
+				};
+				embed::run_blockchain(&fixture, &options)
+			}
+		};
+		match results {
+			Ok(results) => Value::Array(results.iter().map(|result| result.to_json()).collect()),
+			Err(err) => json!({ "error": err }),
+		}
+	}
+}
+
+/// Jobs by id, with their results once done.
+#[derive(Default)]
+pub struct Service {
+	jobs: Arc<Mutex<BTreeMap<u64, Option<Value>>>>,
+	next_id: u64,
+}
+
+impl Service {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	fn submit(&mut self, submission: Submission) -> Result<u64, RpcError> {
+		if let Some(tracer) = &submission.tracer {
+			tracer
+				.parse::<TraceFormat>()
+				.map_err(RpcError::invalid_params)?;
+		}
+		let id = self.next_id;
+		self.next_id += 1;
+		self.jobs.lock().unwrap().insert(id, None);
+		let jobs = self.jobs.clone();
+		thread::spawn(move || {
+			let results = submission.run();
+			jobs.lock().unwrap().insert(id, Some(results));
+		});
+		Ok(id)
+	}
+}
+
+impl Handler for Service {
+	fn call(&mut self, method: &str, params: &[Value]) -> Result<Value, RpcError> {
+		match method {
+			"test_submit" => Ok(json!(self.submit(param(params, 0)?)?)),
+			"test_result" => {
+				let id: u64 = param(params, 0)?;
+				match self.jobs.lock().unwrap().get(&id) {
+					Some(Some(results)) => Ok(json!({ "done": true, "results": results })),
+					Some(None) => Ok(json!({ "done": false })),
+					None => Err(RpcError::invalid_params(format!("Unknown job {}", id))),
+				}
+			}
+			_ => Err(RpcError::method_not_found(method)),
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::time::Duration;
+
+	#[test]
+	fn submit_and_poll() {
+		let mut service = Service::new();
+		let submission = json!({ "kind": "state", "fixture": {} });
+		let id = service.call("test_submit", &[submission]).unwrap();
+		assert_eq!(id, json!(0));
+
+		let result = loop {
+			let result = service.call("test_result", &[id.clone()]).unwrap();
+			if result["done"] == json!(true) {
+				break result;
+			}
+			thread::sleep(Duration::from_millis(10));
+		};
+		assert_eq!(result["results"], json!([]));
+		assert!(service.call("test_result", &[json!(1)]).is_err());
+
+		let invalid = json!({ "kind": "state", "fixture": {}, "tracer": "nope" });
+		assert!(service.call("test_submit", &[invalid]).is_err());
+	}
+}
@@ -0,0 +1,429 @@
+//! Signing of transactions of every type: legacy, EIP-2930 access-list,
+//! EIP-1559 dynamic-fee, EIP-4844 blob and EIP-7702 set-code transactions,
+//! and of the authorizations of the latter. Fixtures only give a secret key
+//! and the fields of their transaction; signing them gives the raw
+//! transaction a client would receive, and recovering its sender checks the
+//! recovery path the runner takes with `Test::unwrap_caller`.
+
+use crate::fixture::{Authorization, Indexes, MultiTransaction};
+use crate::transaction::{append_access_list, append_to, recover, SignatureError};
+use crate::utils::u256_to_h256;
+use libsecp256k1::{Message, SecretKey};
+use primitive_types::{H160, H256, U256};
+use rlp::RlpStream;
+use sha3::{Digest, Keccak256};
+
+/// Prefix of the signing hash of EIP-7702 authorizations.
+const AUTHORIZATION_MAGIC: u8 = 0x05;
+
+/// Type of a transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TxType {
+	Legacy,
+	AccessList,
+	DynamicFee,
+	Blob,
+	SetCode,
+}
+
+impl TxType {
+	pub const ALL: [TxType; 5] = [
+		TxType::Legacy,
+		TxType::AccessList,
+		TxType::DynamicFee,
+		TxType::Blob,
+		TxType::SetCode,
+	];
+
+	/// Type of the data alternative `data` of a state test transaction,
+	/// from the fields the fixture gives.
+	pub fn of_fixture(tx: &MultiTransaction, data: usize) -> Self {
+		if !tx.authorization_list.is_empty() {
+			TxType::SetCode
+		} else if tx.max_fee_per_blob_gas.is_some() || !tx.blob_versioned_hashes.is_empty() {
+			TxType::Blob
+		} else if tx.max_fee_per_gas.is_some() {
+			TxType::DynamicFee
+		} else if tx.access_lists.get(data).map_or(false, Option::is_some) {
+			TxType::AccessList
+		} else {
+			TxType::Legacy
+		}
+	}
+
+	/// Name of the type, after the EIP introducing it.
+	pub fn name(self) -> &'static str {
+		match self {
+			TxType::Legacy => "legacy",
+			TxType::AccessList => "EIP-2930",
+			TxType::DynamicFee => "EIP-1559",
+			TxType::Blob => "EIP-4844",
+			TxType::SetCode => "EIP-7702",
+		}
+	}
+
+	/// EIP-2718 type byte, `None` for legacy transactions.
+	pub fn byte(self) -> Option<u8> {
+		match self {
+			TxType::Legacy => None,
+			TxType::AccessList => Some(0x01),
+			TxType::DynamicFee => Some(0x02),
+			TxType::Blob => Some(0x03),
+			TxType::SetCode => Some(0x04),
+		}
+	}
+}
+
+/// Fields of a transaction before it is signed. Fields its type does not
+/// have are ignored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UnsignedTransaction {
+	pub tx_type: TxType,
+	/// Chain id, `None` only for pre-EIP-155 legacy transactions.
+	pub chain_id: Option<u64>,
+	pub nonce: U256,
+	/// Gas price of legacy and EIP-2930 transactions.
+	pub gas_price: U256,
+	pub max_priority_fee_per_gas: U256,
+	pub max_fee_per_gas: U256,
+	pub gas_limit: U256,
+	pub to: Option<H160>,
+	pub value: U256,
+	pub data: Vec<u8>,
+	pub access_list: Vec<(H160, Vec<H256>)>,
+	pub max_fee_per_blob_gas: U256,
+	pub blob_versioned_hashes: Vec<H256>,
+	pub authorization_list: Vec<Authorization>,
+}
+
+/// Secp256k1 signature, with the recovery id as the y parity.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Signature {
+	pub y_parity: u8,
+	pub r: H256,
+	pub s: H256,
+}
+
+/// A transaction with its signature.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SignedTx {
+	pub tx: UnsignedTransaction,
+	pub signature: Signature,
+}
+
+fn keccak(bytes: &[u8]) -> H256 {
+	H256::from_slice(Keccak256::digest(bytes).as_slice())
+}
+
+fn sign_hash(hash: H256, secret: &H256) -> Result<Signature, SignatureError> {
+	let secret =
+		SecretKey::parse(secret.as_fixed_bytes()).map_err(|_| SignatureError::InvalidSecret)?;
+	let (signature, recovery_id) =
+		libsecp256k1::sign(&Message::parse(hash.as_fixed_bytes()), &secret);
+	let signature = signature.serialize();
+	Ok(Signature {
+		y_parity: recovery_id.serialize(),
+		r: H256::from_slice(&signature[..32]),
+		s: H256::from_slice(&signature[32..]),
+	})
+}
+
+fn append_authorization_list(stream: &mut RlpStream, authorizations: &[Authorization]) {
+	stream.begin_list(authorizations.len());
+	for authorization in authorizations {
+		stream.begin_list(6);
+		stream.append(&authorization.chain_id);
+		stream.append(&authorization.address);
+		stream.append(&authorization.nonce);
+		stream.append(&authorization.v);
+		stream.append(&authorization.r);
+		stream.append(&authorization.s);
+	}
+}
+
+/// Hash the authority of an EIP-7702 authorization signs.
+pub fn authorization_hash(chain_id: U256, address: H160, nonce: U256) -> H256 {
+	let mut stream = RlpStream::new_list(3);
+	stream.append(&chain_id);
+	stream.append(&address);
+	stream.append(&nonce);
+	let mut payload = vec![AUTHORIZATION_MAGIC];
+	payload.extend_from_slice(&stream.out());
+	keccak(&payload)
+}
+
+/// Authorization to delegate the account of `secret` to `address`, with its
+/// signer filled in.
+pub fn sign_authorization(
+	secret: &H256,
+	chain_id: U256,
+	address: H160,
+	nonce: U256,
+) -> Result<Authorization, SignatureError> {
+	let signature = sign_hash(authorization_hash(chain_id, address, nonce), secret)?;
+	let mut authorization = Authorization {
+		chain_id,
+		address,
+		nonce,
+		v: signature.y_parity.into(),
+		r: U256::from_big_endian(signature.r.as_bytes()),
+		s: U256::from_big_endian(signature.s.as_bytes()),
+		signer: None,
+	};
+	authorization.signer = Some(authority(&authorization)?);
+	Ok(authorization)
+}
+
+/// Account that signed `authorization`.
+pub fn authority(authorization: &Authorization) -> Result<H160, SignatureError> {
+	if authorization.v > U256::one() {
+		return Err(SignatureError::InvalidV);
+	}
+	let hash = authorization_hash(
+		authorization.chain_id,
+		authorization.address,
+		authorization.nonce,
+	);
+	recover(
+		hash,
+		authorization.v.low_u32() as u8,
+		&u256_to_h256(authorization.r),
+		&u256_to_h256(authorization.s),
+	)
+}
+
+impl UnsignedTransaction {
+	/// Transaction of the alternative `indexes` selects of a fixture, of the
+	/// type its fields call for, on chain `chain_id`.
+	pub fn from_fixture(tx: &MultiTransaction, indexes: &Indexes, chain_id: u64) -> Self {
+		let access_list = tx.access_lists.get(indexes.data).cloned().flatten();
+		UnsignedTransaction {
+			tx_type: TxType::of_fixture(tx, indexes.data),
+			chain_id: Some(chain_id),
+			nonce: tx.nonce,
+			gas_price: tx.gas_price.unwrap_or_default(),
+			max_priority_fee_per_gas: tx.max_priority_fee_per_gas.unwrap_or_default(),
+			max_fee_per_gas: tx.max_fee_per_gas.unwrap_or_default(),
+			gas_limit: tx.gas_limit[indexes.gas],
+			to: tx.to,
+			value: tx.value[indexes.value],
+			data: tx.data[indexes.data].bytes.clone(),
+			access_list: access_list
+				.unwrap_or_default()
+				.into_iter()
+				.map(|item| (item.address, item.storage_keys))
+				.collect(),
+			max_fee_per_blob_gas: tx.max_fee_per_blob_gas.unwrap_or_default(),
+			blob_versioned_hashes: tx.blob_versioned_hashes.clone(),
+			authorization_list: tx.authorization_list.clone(),
+		}
+	}
+
+	/// Append the fields of the transaction, those of the signature left
+	/// out, to a list of `extra` more items.
+	fn append_fields(&self, stream: &mut RlpStream, extra: usize) {
+		let chain_id = self.chain_id.unwrap_or(0);
+		match self.tx_type {
+			TxType::Legacy => {
+				stream.begin_list(6 + extra);
+				stream.append(&self.nonce);
+				stream.append(&self.gas_price);
+				stream.append(&self.gas_limit);
+				append_to(stream, &self.to);
+				stream.append(&self.value);
+				stream.append(&self.data);
+			}
+			TxType::AccessList => {
+				stream.begin_list(8 + extra);
+				stream.append(&chain_id);
+				stream.append(&self.nonce);
+				stream.append(&self.gas_price);
+				stream.append(&self.gas_limit);
+				append_to(stream, &self.to);
+				stream.append(&self.value);
+				stream.append(&self.data);
+				append_access_list(stream, &self.access_list);
+			}
+			TxType::DynamicFee | TxType::Blob | TxType::SetCode => {
+				let fields = match self.tx_type {
+					TxType::DynamicFee => 9,
+					TxType::Blob => 11,
+					_ => 10,
+				};
+				stream.begin_list(fields + extra);
+				stream.append(&chain_id);
+				stream.append(&self.nonce);
+				stream.append(&self.max_priority_fee_per_gas);
+				stream.append(&self.max_fee_per_gas);
+				stream.append(&self.gas_limit);
+				append_to(stream, &self.to);
+				stream.append(&self.value);
+				stream.append(&self.data);
+				append_access_list(stream, &self.access_list);
+				match self.tx_type {
+					TxType::Blob => {
+						stream.append(&self.max_fee_per_blob_gas);
+						stream.append_list(&self.blob_versioned_hashes);
+					}
+					TxType::SetCode => append_authorization_list(stream, &self.authorization_list),
+					_ => (),
+				}
+			}
+		}
+	}
+
+	/// Encoding of the list in `stream` behind the type byte of the
+	/// transaction.
+	fn envelope(&self, stream: RlpStream) -> Vec<u8> {
+		let mut payload = self.tx_type.byte().map_or_else(Vec::new, |byte| vec![byte]);
+		payload.extend_from_slice(&stream.out());
+		payload
+	}
+
+	/// Hash the sender signs.
+	pub fn signing_hash(&self) -> H256 {
+		let mut stream = RlpStream::new();
+		match (self.tx_type, self.chain_id) {
+			(TxType::Legacy, Some(chain_id)) => {
+				self.append_fields(&mut stream, 3);
+				stream.append(&chain_id);
+				stream.append(&0u8);
+				stream.append(&0u8);
+			}
+			_ => self.append_fields(&mut stream, 0),
+		}
+		keccak(&self.envelope(stream))
+	}
+
+	/// Sign the transaction with `secret`.
+	pub fn sign(&self, secret: &H256) -> Result<SignedTx, SignatureError> {
+		Ok(SignedTx {
+			tx: self.clone(),
+			signature: sign_hash(self.signing_hash(), secret)?,
+		})
+	}
+}
+
+impl SignedTx {
+	/// `v` as encoded: 27 or 28, `chain_id * 2 + 35` or 36 for EIP-155
+	/// legacy transactions, and the y parity for typed ones.
+	pub fn v(&self) -> u64 {
+		let y_parity = u64::from(self.signature.y_parity);
+		match (self.tx.tx_type, self.tx.chain_id) {
+			(TxType::Legacy, None) => 27 + y_parity,
+			(TxType::Legacy, Some(chain_id)) => chain_id * 2 + 35 + y_parity,
+			_ => y_parity,
+		}
+	}
+
+	/// Raw transaction, as sent to a client.
+	pub fn encode(&self) -> Vec<u8> {
+		let mut stream = RlpStream::new();
+		self.tx.append_fields(&mut stream, 3);
+		stream.append(&self.v());
+		stream.append(&U256::from_big_endian(self.signature.r.as_bytes()));
+		stream.append(&U256::from_big_endian(self.signature.s.as_bytes()));
+		self.tx.envelope(stream)
+	}
+
+	/// Hash identifying the transaction.
+	pub fn hash(&self) -> H256 {
+		keccak(&self.encode())
+	}
+
+	/// Recover the sender from the signature.
+	pub fn sender(&self) -> Result<H160, SignatureError> {
+		recover(
+			self.tx.signing_hash(),
+			self.signature.y_parity,
+			&self.signature.r,
+			&self.signature.s,
+		)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::transaction::{secret_to_address, SignedTransaction};
+
+	fn legacy() -> UnsignedTransaction {
+		UnsignedTransaction {
+			tx_type: TxType::Legacy,
+			chain_id: Some(1),
+			nonce: 9.into(),
+			gas_price: 20_000_000_000u64.into(),
+			max_priority_fee_per_gas: U256::zero(),
+			max_fee_per_gas: U256::zero(),
+			gas_limit: 21000.into(),
+			to: Some(H160::repeat_byte(0x35)),
+			value: U256::exp10(18),
+			data: Vec::new(),
+			access_list: Vec::new(),
+			max_fee_per_blob_gas: U256::zero(),
+			blob_versioned_hashes: Vec::new(),
+			authorization_list: Vec::new(),
+		}
+	}
+
+	#[test]
+	fn eip155_example() {
+		let secret = H256::repeat_byte(0x46);
+		let signed = legacy().sign(&secret).unwrap();
+		// Signatures are deterministic by RFC 6979, so the example of EIP-155
+		// comes out byte for byte.
+		assert_eq!(
+			hex::encode(signed.encode()),
+			"f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+		);
+		let decoded = SignedTransaction::decode(&signed.encode()).unwrap();
+		assert_eq!(decoded.signing_hash(), signed.tx.signing_hash());
+	}
+
+	#[test]
+	fn round_trips() {
+		let secret = H256::repeat_byte(0x46);
+		let address = secret_to_address(&secret).unwrap();
+		let authorization =
+			sign_authorization(&secret, U256::one(), H160::repeat_byte(0xaa), U256::zero())
+				.unwrap();
+		assert_eq!(authorization.signer, Some(address));
+
+		for tx_type in &[
+			TxType::Legacy,
+			TxType::AccessList,
+			TxType::DynamicFee,
+			TxType::Blob,
+			TxType::SetCode,
+		] {
+			let tx = UnsignedTransaction {
+				tx_type: *tx_type,
+				access_list: vec![(H160::repeat_byte(1), vec![H256::zero()])],
+				max_fee_per_gas: 10.into(),
+				blob_versioned_hashes: vec![H256::repeat_byte(1)],
+				authorization_list: vec![authorization.clone()],
+				..legacy()
+			};
+			let signed = tx.sign(&secret).unwrap();
+			assert_eq!(signed.sender(), Ok(address), "{:?}", tx_type);
+			assert_eq!(signed.encode()[0] < 0xc0, tx_type.byte().is_some());
+			if matches!(tx_type, TxType::Legacy | TxType::AccessList) {
+				let decoded = SignedTransaction::decode(&signed.encode()).unwrap();
+				assert_eq!(decoded.sender(), Ok(address));
+				assert_eq!(decoded.hash(), signed.hash());
+			}
+		}
+
+		let unprotected = UnsignedTransaction {
+			chain_id: None,
+			..legacy()
+		};
+		let signed = unprotected.sign(&secret).unwrap();
+		assert!(signed.v() == 27 || signed.v() == 28);
+		assert_eq!(signed.sender(), Ok(address));
+		assert_eq!(
+			legacy().sign(&H256::zero()),
+			Err(SignatureError::InvalidSecret)
+		);
+	}
+}
@@ -0,0 +1,66 @@
+//! Smoke set: a handful of fillers in `res/smoke` covering CALL, CREATE2,
+//! SSTORE refunds and access lists, with the generated fillers of the
+//! precompiles. They are filled when run, which checks their expectations,
+//! so that a change gets a quick signal without a checkout of
+//! ethereum/tests.
+
+use crate::filler::{self, Filler};
+use crate::{boundaries, warmth};
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Fillers of the smoke set by name, built into the binary so that it does
+/// not depend on the working directory.
+const FILLERS: &[(&str, &str)] = &[
+	("call", include_str!("../res/smoke/callFiller.json")),
+	("create2", include_str!("../res/smoke/create2Filler.json")),
+	(
+		"sstoreRefund",
+		include_str!("../res/smoke/sstoreRefundFiller.json"),
+	),
+	(
+		"accessList",
+		include_str!("../res/smoke/accessListFiller.json"),
+	),
+];
+
+/// Fill every fixture of the smoke set, by file name.
+pub fn fixtures() -> Result<Vec<(String, Value)>, String> {
+	let mut fixtures = Vec::new();
+	for (name, json) in FILLERS {
+		let fillers: BTreeMap<String, Filler> =
+			serde_json::from_str(json).map_err(|err| format!("{}: {}", name, err))?;
+		let mut filled = Map::new();
+		for (test, filler) in &fillers {
+			filled.insert(test.clone(), filler::fill(test, filler)?);
+		}
+		fixtures.push((name.to_string(), Value::Object(filled)));
+	}
+
+	let generated = [
+		("precompileWarmth", warmth::precompile_warmth_filler()),
+		(boundaries::NAME, boundaries::precompile_boundaries_filler()),
+	];
+	for (name, filler) in &generated {
+		let mut filled = Map::new();
+		filled.insert(name.to_string(), filler::fill(name, filler)?);
+		fixtures.push((name.to_string(), Value::Object(filled)));
+	}
+	Ok(fixtures)
+}
+
+/// Fill the smoke set into `dir`, returning the paths of its fixtures.
+pub fn write_to(dir: &Path) -> Result<Vec<PathBuf>, String> {
+	fs::create_dir_all(dir).map_err(|err| format!("{}: {}", dir.display(), err))?;
+	fixtures()?
+		.into_iter()
+		.map(|(name, fixture)| {
+			let path = dir.join(format!("{}.json", name));
+			fs::write(&path, serde_json::to_string_pretty(&fixture).unwrap())
+				.map_err(|err| format!("{}: {}", path.display(), err))?;
+			Ok(path)
+		})
+		.collect()
+}
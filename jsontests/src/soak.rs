@@ -0,0 +1,66 @@
+//! Soak runs: the same tests run round after round in one process, with the
+//! resident memory sampled after every round. Memory growing every round
+//! past the first, which fills caches, is taken for a leak.
+
+use std::fmt;
+
+/// Growth below this over the whole run is allocator noise.
+pub const LEAK_THRESHOLD: u64 = 1 << 20;
+
+/// Resident set size of the process in bytes, where it can be read.
+#[cfg(target_os = "linux")]
+pub fn rss_bytes() -> Option<u64> {
+	let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+	let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+	let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) };
+	Some(pages * page_size as u64)
+}
+
+#[cfg(not(target_os = "linux"))]
+pub fn rss_bytes() -> Option<u64> {
+	None
+}
+
+/// Resident memory after every round.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Samples(pub Vec<u64>);
+
+impl Samples {
+	/// Whether memory grew after every round past the first, by more than
+	/// `LEAK_THRESHOLD` in all. Takes three rounds to tell.
+	pub fn leaks(&self) -> bool {
+		let rounds = match self.0.get(1..) {
+			Some(rounds) if rounds.len() >= 2 => rounds,
+			_ => return false,
+		};
+		rounds.windows(2).all(|pair| pair[1] > pair[0])
+			&& rounds[rounds.len() - 1] - rounds[0] > LEAK_THRESHOLD
+	}
+}
+
+impl fmt::Display for Samples {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let mib = self
+			.0
+			.iter()
+			.map(|bytes| format!("{:.1}", *bytes as f64 / (1 << 20) as f64))
+			.collect::<Vec<_>>();
+		write!(f, "RSS by round (MiB): {}", mib.join(", "))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn leaks_grow_every_round() {
+		let mib = |samples: &[u64]| Samples(samples.iter().map(|mib| mib << 20).collect());
+		assert!(mib(&[10, 20, 22, 24]).leaks());
+		// The first round fills caches.
+		assert!(!mib(&[10, 20, 20, 20]).leaks());
+		assert!(!mib(&[10, 20, 23, 22, 24]).leaks());
+		assert!(!mib(&[10, 20]).leaks());
+		assert_eq!(mib(&[1, 2]).to_string(), "RSS by round (MiB): 1.0, 2.0");
+	}
+}
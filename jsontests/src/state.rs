@@ -1,135 +1,247 @@
+use crate::access::AccessTracer;
+use crate::alloc;
+use crate::batch::Selection;
+use crate::bench::Timings;
+use crate::builtins::{self, Builtins};
+use crate::calltree::CallTracer;
+use crate::chainspec;
+use crate::conformance::{self, Substate};
+use crate::consistency;
+use crate::console;
+use crate::crash;
+use crate::diff::{render_diff, state_diff};
+use crate::dump::{dump_path, StateDump};
+use crate::exceptions;
+use crate::fees::{self, FeeSchema, Pricing};
+use crate::fixture::{fork_spec, EnvOverride, Indexes, MultiTransaction, PostState, StateFixture};
+use crate::fixtures::suite;
+use crate::gasreport::GasTracer;
+use crate::header::BASE_FEE_FORKS;
+use crate::impls::{self, Behaviour, EvmImpl};
+use crate::logs::{assert_valid_bloom, assert_valid_logs_hash};
+use crate::metrics;
+use crate::overlay::OverlayBackend;
+use crate::perturb::{perturb, unperturb};
+use crate::precompiles::Builtin;
+use crate::provenance::StorageProvenance;
+use crate::reft8n::{self, Case, Executed};
+use crate::report::{index_id, IndexReport, SkipReport};
+use crate::structlog::{StructLogger, TraceFormat};
+use crate::summary::{Outcome, SkipReason};
+use crate::timings::{self, Timing};
+use crate::touched::{self, Existence, Touched, TOUCHED_SUITES};
+use crate::trace::{traced, StepCounter, StepLimit, Tracer};
+use crate::tracediff;
+use crate::transaction::{secret_to_address, SignatureError};
 use crate::utils::*;
+use crate::validators;
+use crate::validity::{self, Rejection};
+use crate::vectors;
+use crate::{ceprint, cprint, cprintln};
 use ethjson::spec::ForkSpec;
-use evm::backend::{ApplyBackend, MemoryAccount, MemoryBackend, MemoryVicinity};
-use evm::executor::{
-	self, MemoryStackState, PrecompileFailure, PrecompileOutput, StackExecutor,
-	StackSubstateMetadata,
-};
-use evm::{Config, Context, ExitError, ExitSucceed};
-use lazy_static::lazy_static;
-use parity_crypto::publickey;
-use primitive_types::{H160, H256, U256};
+use evm::backend::{Backend, Log, MemoryAccount, MemoryVicinity};
+use evm::executor::{self, PrecompileFailure, PrecompileOutput};
+use evm::{Config, Context, ExitError, ExitReason, ExitSucceed};
+use instant::Instant;
+use primitive_types::{H160, U256};
+use regex::Regex;
 use serde::Deserialize;
+use std::borrow::Cow;
+use std::cell::RefCell;
 use std::collections::BTreeMap;
-use std::convert::TryInto;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Deserialize, Debug)]
-pub struct Test(ethjson::test_helpers::state::State);
+pub struct Test(pub StateFixture);
+
+/// What every index of a test shares, derived from the fixture once.
+pub struct Setup {
+	pub pre_state: BTreeMap<H160, MemoryAccount>,
+	/// Vicinity of the transaction, or why its sender cannot be recovered.
+	pub vicinity: Result<MemoryVicinity, SignatureError>,
+}
+
+fn sender(transaction: &MultiTransaction) -> Result<H160, SignatureError> {
+	secret_to_address(&transaction.secret_key)
+}
 
 impl Test {
 	pub fn unwrap_to_pre_state(&self) -> BTreeMap<H160, MemoryAccount> {
-		unwrap_to_state(&self.0.pre_state)
+		self.0
+			.pre_state
+			.iter()
+			.map(|(address, account)| (*address, account.clone().into()))
+			.collect()
 	}
 
-	pub fn unwrap_caller(&self) -> H160 {
-		let secret_key: H256 = self.0.transaction.secret.clone().unwrap().into();
-		let secret = publickey::Secret::import_key(&secret_key[..]).unwrap();
-		let public = publickey::KeyPair::from_secret(secret)
-			.unwrap()
-			.public()
-			.clone();
-		let sender = publickey::public_to_address(&public);
+	/// Sender of the transaction, derived from its secret key.
+	pub fn caller(&self) -> Result<H160, SignatureError> {
+		sender(&self.0.transaction)
+	}
 
-		sender
+	pub fn unwrap_caller(&self) -> H160 {
+		self.caller()
+			.unwrap_or_else(|err| panic!("Transaction has {}", err))
 	}
 
 	pub fn unwrap_to_vicinity(&self) -> MemoryVicinity {
+		self.vicinity_of(&self.0.transaction, self.unwrap_caller())
+	}
+
+	pub fn setup(&self) -> Setup {
+		Setup {
+			pre_state: self.unwrap_to_pre_state(),
+			vicinity: self
+				.caller()
+				.map(|caller| self.vicinity_of(&self.0.transaction, caller)),
+		}
+	}
+
+	/// Message of the alternative `indexes` selects, sent by `caller` and
+	/// priced on `spec`.
+	pub fn message(&self, spec: &ForkSpec, indexes: &Indexes, caller: H160) -> Transaction {
+		self.message_of(&self.0.transaction, spec, indexes, caller)
+	}
+
+	fn message_of(
+		&self,
+		transaction: &MultiTransaction,
+		spec: &ForkSpec,
+		indexes: &Indexes,
+		caller: H160,
+	) -> Transaction {
+		let pricing = self.pricing_of(transaction).on(&format!("{:?}", spec));
+		Transaction {
+			pricing: Some(pricing),
+			..transaction.message(indexes, caller)
+		}
+	}
+
+	/// Vicinity and message, priced on `spec`, of every pre-transaction of
+	/// the index selected by `indexes`, in order.
+	pub fn pre_messages(
+		&self,
+		spec: &ForkSpec,
+		indexes: &Indexes,
+	) -> Vec<(MemoryVicinity, Transaction)> {
+		self.0
+			.pre_transactions
+			.iter()
+			.map(|transaction| {
+				let caller =
+					sender(transaction).unwrap_or_else(|err| panic!("Pre-transaction has {}", err));
+				(
+					self.vicinity_of(transaction, caller),
+					self.message_of(transaction, spec, indexes, caller),
+				)
+			})
+			.collect()
+	}
+
+	/// Pricing of `transaction` under the base fees of the environment.
+	pub fn pricing_of(&self, transaction: &MultiTransaction) -> Pricing {
+		let env = &self.0.env;
+		Pricing {
+			schema: FeeSchema::of(transaction),
+			base_fee: env.current_base_fee,
+			blob_base_fee: fees::blob_base_fee(
+				env.current_excess_blob_gas.unwrap_or_default().low_u64(),
+			),
+		}
+	}
+
+	fn vicinity_of(&self, transaction: &MultiTransaction, origin: H160) -> MemoryVicinity {
 		MemoryVicinity {
-			gas_price: self.0.transaction.gas_price.clone().into(),
-			origin: self.unwrap_caller(),
+			gas_price: self.pricing_of(transaction).gas_price(),
+			origin,
 			block_hashes: Vec::new(),
-			block_number: self.0.env.number.clone().into(),
-			block_coinbase: self.0.env.author.clone().into(),
-			block_timestamp: self.0.env.timestamp.clone().into(),
-			block_difficulty: self.0.env.difficulty.clone().into(),
-			block_gas_limit: self.0.env.gas_limit.clone().into(),
-			chain_id: U256::one(),
+			block_number: self.0.env.current_number,
+			block_coinbase: self.0.env.current_coinbase,
+			block_timestamp: self.0.env.current_timestamp,
+			block_difficulty: self.0.env.current_difficulty,
+			block_gas_limit: self.0.env.current_gas_limit,
+			chain_id: chainspec::active().map_or(U256::one(), |chain| chain.chain_id.into()),
 		}
 	}
 }
 
-lazy_static! {
-	static ref ISTANBUL_BUILTINS: BTreeMap<H160, ethcore_builtin::Builtin> =
-		JsonPrecompile::builtins("./res/istanbul_builtins.json");
+thread_local! {
+	/// Builtins of the fork executing on this thread. Precompiles are plain
+	/// function pointers, so they look their pricing up here.
+	static ACTIVE_BUILTINS: RefCell<Option<(String, Arc<Builtins>)>> = const { RefCell::new(None) };
 }
 
-lazy_static! {
-	static ref BERLIN_BUILTINS: BTreeMap<H160, ethcore_builtin::Builtin> =
-		JsonPrecompile::builtins("./res/berlin_builtins.json");
+/// Precompile of the builtin at address `INDEX` of the active fork.
+fn builtin_precompile<const INDEX: u64>(
+	input: &[u8],
+	gas_limit: Option<u64>,
+	_context: &Context,
+	_is_static: bool,
+) -> Result<PrecompileOutput, PrecompileFailure> {
+	ACTIVE_BUILTINS.with(|active| {
+		let active = active.borrow();
+		let (fork, builtins) = active.as_ref().expect("No active builtins");
+		let address = H160::from_low_u64_be(INDEX);
+		let result = JsonPrecompile::exec_as_precompile(&builtins[&address], input, gas_limit);
+		vectors::record(fork, address, input, gas_limit, &result);
+		result
+	})
 }
 
-macro_rules! precompile_entry {
-	($map:expr, $builtins:expr, $index:expr) => {
-		let x: fn(
-			&[u8],
-			Option<u64>,
-			&Context,
-			bool,
-		) -> Result<PrecompileOutput, PrecompileFailure> =
-			|input: &[u8], gas_limit: Option<u64>, _context: &Context, _is_static: bool| {
-				let builtin = $builtins.get(&H160::from_low_u64_be($index)).unwrap();
-				Self::exec_as_precompile(builtin, input, gas_limit)
-			};
-		$map.insert(H160::from_low_u64_be($index), x);
-	};
-}
+/// Precompile function of every builtin address, starting at 1.
+const PRECOMPILE_FNS: [executor::PrecompileFn; 9] = [
+	builtin_precompile::<1>,
+	builtin_precompile::<2>,
+	builtin_precompile::<3>,
+	builtin_precompile::<4>,
+	builtin_precompile::<5>,
+	builtin_precompile::<6>,
+	builtin_precompile::<7>,
+	builtin_precompile::<8>,
+	builtin_precompile::<9>,
+];
 
 pub struct JsonPrecompile;
 
 impl JsonPrecompile {
+	/// Precompiles of `spec`, or `None` if the fork is unknown. Their
+	/// pricing becomes the active one on the calling thread, so this must be
+	/// called before every execution.
 	pub fn precompile(spec: &ForkSpec) -> Option<BTreeMap<H160, executor::PrecompileFn>> {
-		match spec {
-			ForkSpec::Istanbul => {
-				let mut map = BTreeMap::new();
-				precompile_entry!(map, ISTANBUL_BUILTINS, 1);
-				precompile_entry!(map, ISTANBUL_BUILTINS, 2);
-				precompile_entry!(map, ISTANBUL_BUILTINS, 3);
-				precompile_entry!(map, ISTANBUL_BUILTINS, 4);
-				precompile_entry!(map, ISTANBUL_BUILTINS, 5);
-				precompile_entry!(map, ISTANBUL_BUILTINS, 6);
-				precompile_entry!(map, ISTANBUL_BUILTINS, 7);
-				precompile_entry!(map, ISTANBUL_BUILTINS, 8);
-				precompile_entry!(map, ISTANBUL_BUILTINS, 9);
-				Some(map)
-			}
-			ForkSpec::Berlin => {
-				let mut map = BTreeMap::new();
-				precompile_entry!(map, BERLIN_BUILTINS, 1);
-				precompile_entry!(map, BERLIN_BUILTINS, 2);
-				precompile_entry!(map, BERLIN_BUILTINS, 3);
-				precompile_entry!(map, BERLIN_BUILTINS, 4);
-				precompile_entry!(map, BERLIN_BUILTINS, 5);
-				precompile_entry!(map, BERLIN_BUILTINS, 6);
-				precompile_entry!(map, BERLIN_BUILTINS, 7);
-				precompile_entry!(map, BERLIN_BUILTINS, 8);
-				precompile_entry!(map, BERLIN_BUILTINS, 9);
-				Some(map)
-			}
-			_ => None,
-		}
-	}
-
-	fn builtins(spec_path: &str) -> BTreeMap<H160, ethcore_builtin::Builtin> {
-		let reader = std::fs::File::open(spec_path).unwrap();
-		let builtins: BTreeMap<ethjson::hash::Address, ethjson::spec::builtin::BuiltinCompat> =
-			serde_json::from_reader(reader).unwrap();
-		builtins
-			.into_iter()
-			.map(|(address, builtin)| {
-				(
-					address.into(),
-					ethjson::spec::Builtin::from(builtin).try_into().unwrap(),
-				)
+		let fork = format!("{:?}", spec);
+		let builtins = builtins::for_fork(&fork)?;
+		ACTIVE_BUILTINS.with(|active| *active.borrow_mut() = Some((fork, builtins.clone())));
+
+		let precompiles = builtins
+			.keys()
+			.map(|address| {
+				let index = address.to_low_u64_be();
+				let precompile = match index {
+					builtins::P256VERIFY => builtin_precompile::<{ builtins::P256VERIFY }>,
+					_ => *(index as usize)
+						.checked_sub(1)
+						.and_then(|i| PRECOMPILE_FNS.get(i))
+						.unwrap_or_else(|| {
+							panic!("No precompile function for builtin {:?}", address)
+						}),
+				};
+				(*address, precompile)
 			})
-			.collect()
+			.collect();
+		Some(precompiles)
 	}
 
-	fn exec_as_precompile(
-		builtin: &ethcore_builtin::Builtin,
+	/// Call `builtin` with `input`, failing if it costs more than
+	/// `gas_limit`.
+	pub fn exec_as_precompile(
+		builtin: &Builtin,
 		input: &[u8],
 		gas_limit: Option<u64>,
 	) -> Result<PrecompileOutput, PrecompileFailure> {
-		let cost = builtin.cost(input, 0);
+		let cost = builtin.cost(input);
 
 		if let Some(target_gas) = gas_limit {
 			if cost > U256::from(u64::MAX) || target_gas < cost.as_u64() {
@@ -139,9 +251,8 @@ impl JsonPrecompile {
 			}
 		}
 
-		let mut output = Vec::new();
-		match builtin.execute(input, &mut parity_bytes::BytesRef::Flexible(&mut output)) {
-			Ok(()) => Ok(PrecompileOutput {
+		match builtin.execute(input) {
+			Ok(output) => Ok(PrecompileOutput {
 				exit_status: ExitSucceed::Stopped,
 				output,
 				cost: cost.as_u64(),
@@ -154,95 +265,1129 @@ impl JsonPrecompile {
 	}
 }
 
-pub fn test(name: &str, test: Test) {
-	use std::thread;
+/// Options controlling how state tests are run.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+	/// Directory the post-execution state of every index is dumped into.
+	pub dump_state: Option<PathBuf>,
+	/// Print the difference between pre-state and computed post-state.
+	pub diff: bool,
+	/// Report the instruction that wrote each storage slot when the state
+	/// root does not match.
+	pub provenance: bool,
+	/// Print the message-call tree and gas breakdown of every index.
+	pub verbose: bool,
+	/// File every executed index is appended to as a JSON line.
+	pub json_report: Option<PathBuf>,
+	/// File the wall time of every executed index is appended to, for the
+	/// `timings` report.
+	pub timings: Option<PathBuf>,
+	/// Format of the execution trace written for every index.
+	pub tracer: Option<TraceFormat>,
+	/// Directory traces are written into, one file per index. Traces go to
+	/// standard error without it.
+	pub trace_dir: Option<PathBuf>,
+	/// Forks to run, all supported ones if empty.
+	pub forks: Vec<String>,
+	/// Labels of the indexes to run, all indexes if empty.
+	pub labels: Vec<String>,
+	/// Indexes to run, all indexes if empty.
+	pub indexes: Vec<usize>,
+	/// Patterns of the `file:test:fork:index` ids of indexes not to run.
+	pub skip_patterns: Vec<Regex>,
+	/// Indexes to run, read from a list or a report, all indexes if `None`.
+	pub selection: Option<Selection>,
+	/// Time a single test may take.
+	pub timeout: Option<Duration>,
+	/// Do not print a status line per index.
+	pub quiet: bool,
+	/// Execute every index of a fork against the post-state of the previous
+	/// one instead of the pre-state, like transactions of a block.
+	pub carry_state: bool,
+	/// Execute every passing index this many more times after a warm-up run,
+	/// and print its timings.
+	pub repeat: Option<usize>,
+	/// Execute every passing index again on this many threads at once, and
+	/// fail it unless they all reach the same result.
+	pub stress: Option<usize>,
+	/// Execute every passing index again with accounts it does not touch
+	/// added to its pre-state at addresses derived from this seed, and fail
+	/// it unless the result stays the same.
+	pub perturb: Option<u64>,
+	/// Base fee replacing the one of every fixture's block. Roots computed
+	/// under it are only expected to match where gas prices do not matter.
+	pub base_fee: Option<U256>,
+	/// Fields of every fixture's block environment replaced, likewise.
+	pub env_overrides: Vec<EnvOverride>,
+	/// Execute every passing index twice more with a trace, and fail it
+	/// unless both traces are byte for byte the same.
+	pub determinism: bool,
+	/// Print the indexes whose roots change between adjacent forks for only
+	/// one of the fixture and the runner.
+	pub fork_consistency: bool,
+	/// Print the state diff and trace of every failing index.
+	pub diagnose_failures: bool,
+	/// Do not check the post-state root, logs and bloom indexes expect, only
+	/// that they execute and keep the invariants of their fork.
+	pub ignore_post_state: bool,
+	/// Version of the evm crate indexes run on, or both to fail the indexes
+	/// they behave differently on.
+	pub evm_impl: EvmImpl,
+	/// Geth `evm` binary every index is also run on with `evm t8n`, failing
+	/// the indexes whose alloc or receipt differ.
+	pub reference_t8n: Option<PathBuf>,
+}
+
+impl Options {
+	/// Why an index labelled `label` with id `id` is not to be run, if it
+	/// is not.
+	fn skips(&self, id: &str, index: usize, label: Option<&str>) -> Option<SkipReason> {
+		if self
+			.skip_patterns
+			.iter()
+			.any(|pattern| pattern.is_match(id))
+		{
+			Some(SkipReason::Listed)
+		} else if (self.indexes.is_empty() || self.indexes.contains(&index))
+			&& (self.labels.is_empty()
+				|| label.map_or(false, |label| self.labels.iter().any(|l| l == label)))
+		{
+			None
+		} else {
+			Some(SkipReason::Filtered)
+		}
+	}
+}
+
+pub fn test(name: &str, test: Test) -> Outcome {
+	test_with_options(name, test, &Options::default())
+}
 
-	const STACK_SIZE: usize = 16 * 1024 * 1024;
+pub fn test_with_options(name: &str, test: Test, options: &Options) -> Outcome {
+	test_in_file("", name, test, options)
+}
 
+/// Run `test` of fixture file `file`, the ids skip patterns are matched with
+/// starting with it.
+pub fn test_in_file(file: &str, name: &str, test: Test, options: &Options) -> Outcome {
+	let file = file.to_string();
 	let name = name.to_string();
-	// Spawn thread with explicit stack size
-	let child = thread::Builder::new()
-		.stack_size(STACK_SIZE)
-		.spawn(move || test_run(&name, test))
-		.unwrap();
-
-	// Wait for thread to join
-	child.join().unwrap();
-}
-
-fn test_run(name: &str, test: Test) {
-	for (spec, states) in &test.0.post_states {
-		let (gasometer_config, delete_empty) = match spec {
-			ethjson::spec::ForkSpec::Istanbul => (Config::istanbul(), true),
-			ethjson::spec::ForkSpec::Berlin => (Config::berlin(), true),
-			spec => {
-				println!("Skip spec {:?}", spec);
+	let options = options.clone();
+	run_with_stack(options.timeout, move || {
+		test_run(&file, &name, test, &options)
+	})
+}
+
+/// Version of the evm crate tests run on.
+pub const EVM_VERSION: &str = env!("EVM_VERSION");
+
+/// Why the runner cannot execute `fork`, or `None` if it can.
+pub fn unsupported(fork: &str) -> Option<String> {
+	match fork_spec(fork) {
+		None => Some(format!("fork {} unknown", fork)),
+		Some(spec) if fork_config(&spec).is_none() => {
+			Some(format!("fork {} unsupported by evm v{}", fork, EVM_VERSION))
+		}
+		Some(_) => None,
+	}
+}
+
+/// Gasometer config and empty-account deletion rule of a fork, if the
+/// runner supports it, with the gas table of the active chain spec.
+pub fn fork_config(spec: &ForkSpec) -> Option<(Config, bool)> {
+	let mut config = match spec {
+		ForkSpec::Istanbul => Config::istanbul(),
+		ForkSpec::Berlin => Config::berlin(),
+		_ => return None,
+	};
+	chainspec::tweak(&mut config);
+	Some((config, deletes_empty(spec)))
+}
+
+/// Whether empty accounts a transaction touches are deleted under `spec`,
+/// by EIP-161 from Spurious Dragon on. Transition forks follow the rules of
+/// their first fork.
+pub fn deletes_empty(spec: &ForkSpec) -> bool {
+	match spec {
+		ForkSpec::Frontier
+		| ForkSpec::Homestead
+		| ForkSpec::EIP150
+		| ForkSpec::FrontierToHomesteadAt5
+		| ForkSpec::HomesteadToDaoAt5
+		| ForkSpec::HomesteadToEIP150At5 => false,
+		ForkSpec::EIP158
+		| ForkSpec::Byzantium
+		| ForkSpec::Constantinople
+		| ForkSpec::ConstantinopleFix
+		| ForkSpec::Istanbul
+		| ForkSpec::Berlin
+		| ForkSpec::London
+		| ForkSpec::EIP158ToByzantiumAt5
+		| ForkSpec::ByzantiumToConstantinopleFixAt5
+		| ForkSpec::ConstantinopleFixToIstanbulAt5 => true,
+	}
+}
+
+/// Post-execution backend and emitted logs of a single transaction, along
+/// with the tracer that observed it.
+pub struct Execution<'a, T> {
+	pub backend: OverlayBackend<'a>,
+	pub logs: Vec<Log>,
+	pub exit_reason: ExitReason,
+	/// Return data of a call, empty for contract creation.
+	pub output: Vec<u8>,
+	/// Address of the contract a creation transaction deploys.
+	pub created: Option<H160>,
+	pub used_gas: u64,
+	/// Refund already subtracted from `used_gas`.
+	pub refunded_gas: u64,
+	/// Gas of the gas limit returned to the sender.
+	pub gas_left: u64,
+	pub substate: Substate,
+	pub tracer: T,
+}
+
+pub use crate::conformance::Transaction;
+
+/// Apply the pre-transactions selected by `indexes` to `original_state`,
+/// returning `None` if the fork is not supported.
+fn apply_pre_transactions<'s>(
+	test: &Test,
+	spec: &ForkSpec,
+	indexes: &Indexes,
+	original_state: &'s BTreeMap<H160, MemoryAccount>,
+) -> Option<Cow<'s, BTreeMap<H160, MemoryAccount>>> {
+	let mut state = Cow::Borrowed(original_state);
+	for (vicinity, message) in test.pre_messages(spec, indexes) {
+		let execution = transact(spec, &vicinity, state, &message, ())?;
+		state = Cow::Owned(execution.backend.into_state());
+	}
+	Some(state)
+}
+
+/// Execute the transaction selected by `indexes` against `original_state`,
+/// after its pre-transactions. Returns `None` if the fork is not supported.
+pub fn execute<'a, T: Tracer + 'static>(
+	test: &Test,
+	spec: &ForkSpec,
+	indexes: &Indexes,
+	vicinity: &'a MemoryVicinity,
+	original_state: &'a BTreeMap<H160, MemoryAccount>,
+	tracer: T,
+) -> Option<Execution<'a, T>> {
+	let state = apply_pre_transactions(test, spec, indexes, original_state)?;
+	let transaction = test.message(spec, indexes, vicinity.origin);
+
+	transact(spec, vicinity, state, &transaction, tracer)
+}
+
+/// Execute `transaction` against `state`, charging fees by its pricing or
+/// else at the gas price of `vicinity`. A borrowed `state` is shared, only
+/// the accounts the transaction changes are copied. Returns `None` if the
+/// fork is not supported.
+pub fn transact<'a, T: Tracer + 'static>(
+	spec: &ForkSpec,
+	vicinity: &'a MemoryVicinity,
+	state: Cow<'a, BTreeMap<H160, MemoryAccount>>,
+	transaction: &Transaction,
+	tracer: T,
+) -> Option<Execution<'a, T>> {
+	let (gasometer_config, delete_empty) = fork_config(spec)?;
+	let caller = transaction.caller;
+
+	let mut backend = OverlayBackend::new(vicinity, state);
+	// Derived from the nonce of the caller before the transaction.
+	let created = match transaction.to {
+		Some(_) => None,
+		None => Some(create_address(caller, backend.basic(caller).nonce)),
+	};
+	let precompile = JsonPrecompile::precompile(spec)?;
+	let ((tracer, _), executed) = traced((tracer, StepLimit::current()), || {
+		conformance::transact(
+			&gasometer_config,
+			delete_empty,
+			&mut backend,
+			transaction,
+			&precompile,
+		)
+	});
+
+	Some(Execution {
+		backend,
+		logs: executed.logs,
+		exit_reason: executed.exit_reason,
+		output: executed.output,
+		created,
+		used_gas: executed.used_gas,
+		refunded_gas: executed.refunded_gas,
+		gas_left: transaction.gas_limit - executed.used_gas,
+		substate: executed.substate,
+		tracer,
+	})
+}
+
+/// Execute the system call `call` on a copy-on-write view of `state` under
+/// `spec`, returning the backend with its changes, how the call ended and
+/// its output. Returns `None` if the fork is not supported.
+pub fn system_call<'a>(
+	spec: &ForkSpec,
+	vicinity: &'a MemoryVicinity,
+	state: Cow<'a, BTreeMap<H160, MemoryAccount>>,
+	call: &Transaction,
+) -> Option<(OverlayBackend<'a>, ExitReason, Vec<u8>)> {
+	let (gasometer_config, _) = fork_config(spec)?;
+	let mut backend = OverlayBackend::new(vicinity, state);
+	let precompile = JsonPrecompile::precompile(spec)?;
+	let (exit_reason, output) =
+		conformance::system_call(&gasometer_config, &mut backend, call, &precompile);
+	Some((backend, exit_reason, output))
+}
+
+/// The accounts of `touched` before and after the transaction that left
+/// `backend`.
+fn touched_accounts(
+	pre_state: &BTreeMap<H160, MemoryAccount>,
+	backend: &OverlayBackend,
+	touched: &[H160],
+) -> Vec<Touched> {
+	touched
+		.iter()
+		.map(|address| {
+			let post = if backend.exists(*address) {
+				let basic = backend.basic(*address);
+				Existence::of(Some(&MemoryAccount {
+					nonce: basic.nonce,
+					balance: basic.balance,
+					code: backend.code(*address),
+					storage: BTreeMap::new(),
+				}))
+			} else {
+				Existence::Absent
+			};
+			Touched {
+				address: *address,
+				pre: Existence::of(pre_state.get(address)),
+				post,
+			}
+		})
+		.collect()
+}
+
+/// Assert a creation transaction that succeeded left a contract at the
+/// `address` it derives.
+fn assert_created(backend: &OverlayBackend, address: H160, exit_reason: &ExitReason) {
+	if exit_reason.is_succeed() && backend.basic(address).nonce.is_zero() {
+		panic!(
+			"Contract creation succeeded but there is no contract at {:?}",
+			address
+		);
+	}
+}
+
+/// Steps of a failing index's trace kept in the JSON report.
+const MAX_REPORTED_TRACE_LINES: usize = 5000;
+
+/// Start of the line naming the failing index among the diagnostics of a
+/// failure.
+const FAILING_INDEX: &str = "Failing index: ";
+
+/// Fork and index of the failure whose diagnostics `output` holds.
+pub fn failing_index(output: &str) -> Option<(String, usize)> {
+	output.lines().find_map(|line| {
+		let (fork, index) = line.strip_prefix(FAILING_INDEX)?.rsplit_once(':')?;
+		Some((fork.to_string(), index.parse().ok()?))
+	})
+}
+
+fn truncate_lines(text: &str, max: usize) -> String {
+	let lines = text.lines().count();
+	if lines <= max {
+		return text.to_string();
+	}
+	let mut truncated = text.lines().take(max).collect::<Vec<_>>().join("\n");
+	truncated.push_str(&format!("\n... {} more lines\n", lines - max));
+	truncated
+}
+
+/// Indexes of `fork` the options select, counting the others into
+/// `outcome` as skipped for why they are not run.
+fn selected_indexes<'a>(
+	test: &'a Test,
+	file: &str,
+	name: &str,
+	fork: &str,
+	states: &'a [PostState],
+	options: &Options,
+	outcome: &mut Outcome,
+) -> Vec<(usize, &'a PostState, Option<&'a str>)> {
+	states
+		.iter()
+		.enumerate()
+		.map(|(i, state)| (i, state, test.0.label(&state.indexes)))
+		.filter(|(i, _, label)| {
+			let id = format!("{}:{}:{}:{}", file, name, fork, i);
+			let skip = options.skips(&id, *i, *label).or_else(|| {
+				let selected = options
+					.selection
+					.as_ref()
+					.map_or(true, |selection| selection.selects(file, name, fork, *i));
+				Some(SkipReason::Filtered).filter(|_| !selected)
+			});
+			match skip {
+				Some(reason) => {
+					outcome.skip(fork, reason, 1);
+					false
+				}
+				None => true,
+			}
+		})
+		.collect()
+}
+
+/// Check the indexes of a transaction no sender can be recovered for: each
+/// must expect an exception and leave the pre-state untouched. Returns the
+/// number of indexes checked.
+fn test_rejected(
+	name: &str,
+	fork: &str,
+	indexes: &[(usize, &PostState, Option<&str>)],
+	original_state: &BTreeMap<H160, MemoryAccount>,
+	err: SignatureError,
+	options: &Options,
+) -> usize {
+	for &(i, state, label) in indexes {
+		if !options.quiet {
+			cprint!("Running {} ... ", index_id(name, fork, i, label));
+			flush();
+		}
+		assert!(
+			state.expect_exception.is_some(),
+			"Transaction has {}, but no exception is expected",
+			err
+		);
+		assert_valid_hash(&state.hash, original_state)
+			.unwrap_or_else(|mismatch| mismatch.fail(original_state));
+		if !options.quiet {
+			cprintln!("passed ({})", err);
+		}
+	}
+	indexes.len()
+}
+
+/// Check an index whose transaction is rejected before it executes: it must
+/// expect the exception of `rejection` and leave `pre_state` untouched.
+fn check_rejection(
+	state: &PostState,
+	rejection: Rejection,
+	pre_state: &BTreeMap<H160, MemoryAccount>,
+) {
+	match &state.expect_exception {
+		Some(expected) => assert!(
+			rejection.is_expected(expected),
+			"Transaction has {}, but {} is expected",
+			rejection,
+			expected
+		),
+		None => panic!(
+			"Transaction has {}, but no exception is expected",
+			rejection
+		),
+	}
+	assert_valid_hash(&state.hash, pre_state).unwrap_or_else(|mismatch| mismatch.fail(pre_state));
+}
+
+fn balance_of(state: &BTreeMap<H160, MemoryAccount>, address: H160) -> U256 {
+	state
+		.get(&address)
+		.map_or_else(U256::zero, |account| account.balance)
+}
+
+/// Check the indexes of a fork the runner cannot execute whose transaction
+/// `validity` rejects before it executes: each must expect the exception of
+/// its rejection and leave the pre-state untouched. Returns the number of
+/// indexes checked, the others are left for the fork to be supported.
+fn test_rejections(
+	test: &Test,
+	name: &str,
+	fork: &str,
+	indexes: &[(usize, &PostState, Option<&str>)],
+	setup: &Setup,
+	options: &Options,
+) -> usize {
+	let caller = match &setup.vicinity {
+		Ok(vicinity) => vicinity.origin,
+		Err(_) => return 0,
+	};
+	let mut checked = 0;
+	for &(i, state, label) in indexes {
+		let message = test.0.transaction.message(&state.indexes, caller);
+		let balance = balance_of(&setup.pre_state, caller);
+		let env = &test.0.env;
+		let rejection = match validity::rejection(fork, &test.0.transaction, &message, env, balance)
+		{
+			Some(rejection) => rejection,
+			None => continue,
+		};
+		if !options.quiet {
+			cprint!("Running {} ... ", index_id(name, fork, i, label));
+			flush();
+		}
+		check_rejection(state, rejection, &setup.pre_state);
+		if !options.quiet {
+			cprintln!("passed ({})", rejection);
+		}
+		checked += 1;
+	}
+	checked
+}
+
+/// Assert that a transaction returned the `expected` data.
+fn assert_valid_output(expected: &[u8], output: &[u8]) {
+	if output != expected {
+		panic!(
+			"Output not equal; calculated: 0x{}, expect: 0x{}",
+			hex::encode(output),
+			hex::encode(expected)
+		);
+	}
+}
+
+/// Check the post-state, output and logs of an index executed on the new evm
+/// against the ones `state` expects.
+fn check_new(new: &impls::Executed, state: &PostState) {
+	assert_valid_hash(&state.hash, &new.state).unwrap_or_else(|mismatch| mismatch.fail(&new.state));
+	if let Some(expected) = &state.output {
+		assert_valid_output(expected, &new.behaviour.output);
+	}
+	if let Some(expected) = &state.logs {
+		assert_valid_logs_hash(expected, &new.behaviour.logs);
+	}
+	if let Some(expected) = &state.bloom {
+		assert_valid_bloom(expected, &new.behaviour.logs);
+	}
+}
+
+fn test_run(file: &str, name: &str, mut test: Test, options: &Options) -> Outcome {
+	let mut outcome = Outcome::default();
+	if let Some(base_fee) = options.base_fee {
+		test.0.env.current_base_fee = Some(base_fee);
+	}
+	for env_override in &options.env_overrides {
+		env_override.apply(&mut test.0.env);
+	}
+	if options.fork_consistency {
+		for divergence in consistency::check(name, &test) {
+			cprintln!("Fork divergence: {}", divergence);
+		}
+	}
+	// On a custom chain, only the fork it follows at the block of the test.
+	let chain_fork = chainspec::active().map(|chain| {
+		let env = &test.0.env;
+		chain
+			.fork_at(env.current_number, env.current_timestamp)
+			.map(String::from)
+	});
+	let setup = test.setup();
+	for (fork, states) in &test.0.post_states {
+		if !options.forks.is_empty() && !options.forks.contains(fork) {
+			outcome.skip(fork, SkipReason::Filtered, states.len());
+			continue;
+		}
+		if let Some(chain) = &chain_fork {
+			if chain.as_ref() != Some(fork) {
+				outcome.skip(fork, SkipReason::NotApplicable, states.len());
+				continue;
+			}
+		}
+		let supported = match impls::unsupported(fork, options.evm_impl) {
+			Some(reason) => Err((SkipReason::UnsupportedFork, reason)),
+			None => {
+				let spec = fork_spec(fork).unwrap();
+				JsonPrecompile::precompile(&spec)
+					.map(|precompile| (spec, precompile))
+					.ok_or_else(|| {
+						let reason = format!("fork {} has no builtins", fork);
+						(SkipReason::MissingBuiltins, reason)
+					})
+			}
+		};
+		let (spec, precompile) = match supported {
+			Ok(supported) => supported,
+			Err((skip_reason, reason)) => {
+				let indexes =
+					selected_indexes(&test, file, name, fork, states, options, &mut outcome);
+				let checked = if BASE_FEE_FORKS.contains(&fork.as_str()) {
+					test_rejections(&test, name, fork, &indexes, &setup, options)
+				} else {
+					0
+				};
+				let skipped = indexes.len() - checked;
+				if !options.quiet && skipped > 0 {
+					cprintln!("Skip: {}", reason);
+				}
+				if let (Some(path), true) = (&options.json_report, skipped > 0) {
+					SkipReport {
+						name: name.to_string(),
+						fork: fork.clone(),
+						skip_reason,
+						detail: reason,
+						skipped,
+					}
+					.append_to(path)
+					.expect("Write JSON report failed");
+				}
+				outcome.count_fork(fork, checked);
+				outcome.skip(fork, skip_reason, skipped);
+				continue;
+			}
+		};
+		let spec = &spec;
+
+		let indexes = selected_indexes(&test, file, name, fork, states, options, &mut outcome);
+		let original_state = &setup.pre_state;
+		let vicinity = match &setup.vicinity {
+			Ok(vicinity) => vicinity,
+			Err(err) => {
+				let rejected = test_rejected(name, fork, &indexes, original_state, *err, options);
+				outcome.count_fork(fork, rejected);
 				continue;
 			}
 		};
+		// Post-state of the previous index when carrying state over.
+		let mut carried = None;
+		// Converted on the first index run on evm-next, again for each index
+		// when carrying state over.
+		let mut new_pre_state = None;
 
-		let original_state = test.unwrap_to_pre_state();
-		let vicinity = test.unwrap_to_vicinity();
-		let caller = test.unwrap_caller();
+		for (i, state, label) in indexes {
+			if !options.quiet {
+				cprint!("Running {} ... ", index_id(name, fork, i, label));
+				flush();
+			}
+			let pre_state = carried.as_ref().unwrap_or(original_state);
 
-		for (i, state) in states.iter().enumerate() {
-			print!("Running {}:{:?}:{} ... ", name, spec, i);
-			flush();
+			let message = test.0.transaction.message(&state.indexes, vicinity.origin);
+			let balance = balance_of(pre_state, vicinity.origin);
+			if let Some(rejection) =
+				validity::rejection(fork, &test.0.transaction, &message, &test.0.env, balance)
+			{
+				check_rejection(state, rejection, pre_state);
+				if !options.quiet {
+					cprintln!("passed ({})", rejection);
+				}
+				outcome.count_fork(fork, 1);
+				continue;
+			}
+
+			let new = match options.evm_impl {
+				EvmImpl::Old => None,
+				EvmImpl::New | EvmImpl::Both => {
+					if options.carry_state || new_pre_state.is_none() {
+						new_pre_state = Some(impls::NewPreState::new(pre_state));
+					}
+					let new_pre_state = new_pre_state.as_mut().unwrap();
+					Some(
+						impls::execute_new(&test, spec, &state.indexes, vicinity, new_pre_state)
+							.unwrap(),
+					)
+				}
+			};
+			if options.evm_impl == EvmImpl::New {
+				let new = new.unwrap();
+				if !options.ignore_post_state {
+					check_new(&new, state);
+				}
+				if options.carry_state {
+					carried = Some(new.state);
+				}
+				if !options.quiet {
+					cprintln!("passed");
+				}
+				outcome.count_fork(fork, 1);
+				continue;
+			}
+
+			let provenance = if options.provenance {
+				Some(StorageProvenance::new())
+			} else {
+				None
+			};
+
+			let calls = if options.verbose
+				|| options.json_report.is_some()
+				|| options.tracer == Some(TraceFormat::Call)
+				|| validators::need_calls()
+			{
+				Some(CallTracer::new())
+			} else {
+				None
+			};
 
-			let transaction = test.0.transaction.select(&state.indexes);
-			let gas_limit: u64 = transaction.gas_limit.into();
-			let data: Vec<u8> = transaction.data.into();
-
-			let mut backend = MemoryBackend::new(&vicinity, original_state.clone());
-			let metadata =
-				StackSubstateMetadata::new(transaction.gas_limit.into(), &gasometer_config);
-			let executor_state = MemoryStackState::new(metadata, &backend);
-			let precompile = JsonPrecompile::precompile(spec).unwrap();
-			let mut executor =
-				StackExecutor::new_with_precompiles(executor_state, &gasometer_config, &precompile);
-			let total_fee = vicinity.gas_price * gas_limit;
-
-			executor.state_mut().withdraw(caller, total_fee).unwrap();
-
-			let access_list = transaction
-				.access_list
-				.into_iter()
-				.map(|(address, keys)| (address.0, keys.into_iter().map(|k| k.0).collect()))
-				.collect();
-
-			match transaction.to {
-				ethjson::maybe::MaybeEmpty::Some(to) => {
-					let data = data;
-					let value = transaction.value.into();
-
-					let _reason = executor.transact_call(
-						caller,
-						to.into(),
-						value,
-						data,
-						gas_limit,
-						access_list,
+			let gas = if options.verbose || options.json_report.is_some() {
+				Some(GasTracer::new(precompile.keys().cloned()))
+			} else {
+				None
+			};
+
+			let struct_logger = match options.tracer {
+				Some(TraceFormat::Struct) | Some(TraceFormat::Eip3155) => Some(StructLogger::new()),
+				_ => None,
+			};
+
+			let allocations_at_start = alloc::current();
+			let started = Instant::now();
+			let executed = crash::catch(&index_id(name, fork, i, label), || {
+				execute(
+					&test,
+					spec,
+					&state.indexes,
+					vicinity,
+					pre_state,
+					(
+						(((provenance, calls), gas), struct_logger),
+						StepCounter::default(),
+					),
+				)
+				.unwrap()
+			});
+			let time_us = started.elapsed().as_micros() as u64;
+			let Execution {
+				backend,
+				logs,
+				exit_reason,
+				output,
+				created,
+				used_gas,
+				refunded_gas,
+				gas_left,
+				substate,
+				tracer: ((((provenance, calls), gas), struct_logger), steps),
+			} = match executed {
+				Ok(execution) => execution,
+				Err(crash) => {
+					if let Some(backtrace) = &crash.backtrace {
+						cprint!("Backtrace:\n{}", backtrace);
+					}
+					if options.diagnose_failures {
+						cprintln!("{}{}:{}", FAILING_INDEX, fork, i);
+					}
+					if let Some(path) = &options.json_report {
+						IndexReport {
+							name: name.to_string(),
+							fork: fork.clone(),
+							index: i,
+							label: label.map(String::from),
+							suite: suite(file),
+							passed: false,
+							time_us,
+							error: Some(crash.to_string()),
+							backtrace: crash.backtrace.clone(),
+							filler: Some(test.0.info.filler.clone())
+								.filter(|filler| !filler.is_empty()),
+							..Default::default()
+						}
+						.append_to(path)
+						.expect("Write JSON report failed");
+					}
+					crash.fail()
+				}
+			};
+			let allocations = alloc::current()
+				.zip(allocations_at_start)
+				.map(|(end, start)| end.since(start));
+			let root = backend.state_root();
+
+			let dumped = options.dump_state.as_ref().map(|dir| {
+				let path = dump_path(dir, name, fork, i);
+				StateDump::new(&backend.state())
+					.write(&path)
+					.expect("Write state dump failed");
+				path
+			});
+			if options.diff {
+				cprintln!();
+				cprint!(
+					"{}",
+					render_diff(&state_diff(pre_state, &backend.state()), console::color())
+				);
+			}
+			if let Some(provenance) = &provenance {
+				if root != state.hash {
+					cprintln!();
+					cprint!("Storage writes:\n{}", provenance.report());
+				}
+			}
+			let call_tree = calls.and_then(|calls| calls.into_root());
+			if root != state.hash {
+				let deposits = match &call_tree {
+					Some(call_tree) => call_tree.deposit_report(),
+					None => execute(
+						&test,
+						spec,
+						&state.indexes,
+						vicinity,
+						pre_state,
+						CallTracer::new(),
+					)
+					.unwrap()
+					.tracer
+					.into_root()
+					.map(|call_tree| call_tree.deposit_report())
+					.unwrap_or_default(),
+				};
+				if !deposits.is_empty() {
+					cprintln!();
+					cprint!("Code deposits breaking a rule:\n{}", deposits);
+				}
+			}
+			let accounts = touched_accounts(pre_state, &backend, &substate.touched);
+			let reports_touched =
+				suite(file).map_or(false, |suite| TOUCHED_SUITES.contains(&suite.as_str()));
+			if reports_touched && root != state.hash {
+				cprintln!();
+				cprint!(
+					"Touched accounts, empty ones marked:\n{}",
+					touched::report(&accounts)
+				);
+			}
+			// Warm sets only exist from Berlin on, where the caller is always warm.
+			if root != state.hash && !substate.accessed_addresses.is_empty() {
+				let transaction = test.0.transaction.message(&state.indexes, vicinity.origin);
+				let tracer = AccessTracer::new(
+					builtins::for_fork(fork).unwrap().keys().copied(),
+					&transaction.access_list,
+				);
+				let access = execute(&test, spec, &state.indexes, vicinity, pre_state, tracer)
+					.unwrap()
+					.tracer;
+				if access.suspect(&exit_reason) {
+					cprintln!();
+					cprint!("{}", access.report());
+				}
+			}
+			let gas = gas.map(|gas| gas.into_breakdown());
+			if let Some(format) = options.tracer {
+				let trace = match (format, &struct_logger, &call_tree) {
+					(TraceFormat::Eip3155, Some(logger), _) => logger.eip3155(&output, used_gas),
+					(TraceFormat::Struct, Some(logger), _) => {
+						let failed = !exit_reason.is_succeed();
+						format!("{}\n", logger.geth(failed, &output, used_gas))
+					}
+					(TraceFormat::Call, _, Some(call_tree)) => format!("{}\n", call_tree.geth()),
+					_ => String::new(),
+				};
+				match &options.trace_dir {
+					Some(dir) => {
+						let path = dump_path(dir, name, fork, i).with_extension(format.extension());
+						std::fs::write(path, trace).expect("Write trace failed");
+					}
+					None => ceprint!("{}", trace),
+				}
+			}
+			if options.verbose {
+				cprintln!();
+				cprintln!(
+					"Gas used {}, refunded {}, left {}",
+					used_gas,
+					refunded_gas,
+					gas_left
+				);
+				let pricing = test.pricing_of(&test.0.transaction).on(fork);
+				cprint!("{}", pricing.fees(used_gas));
+				if let Some(reason) = exceptions::exit_reason(&exit_reason) {
+					match exceptions::table().exception(&reason) {
+						Some(exception) => cprintln!("Exit reason {} ({})", reason, exception),
+						None => cprintln!("Exit reason {}", reason),
+					}
+				}
+				cprint!("{}", substate);
+				if let Some(address) = created {
+					cprintln!();
+					cprintln!(
+						"Created {:?} from {} bytes of init code",
+						address,
+						test.0.transaction.data[state.indexes.data].bytes.len()
 					);
 				}
-				ethjson::maybe::MaybeEmpty::None => {
-					let code = data;
-					let value = transaction.value.into();
+				if let Some(call_tree) = &call_tree {
+					cprintln!();
+					cprint!("{}", call_tree.report());
+				}
+				if let Some(gas) = &gas {
+					cprint!("{}", gas.report());
+				}
+			}
+			let checks = panic::catch_unwind(AssertUnwindSafe(|| {
+				if let Some(new) = &new {
+					let old = Behaviour::new(root, used_gas, &exit_reason, &output, &logs);
+					let differences = old.differences(&new.behaviour);
+					if !differences.is_empty() {
+						panic!("The new evm differs: {}", differences.join("; "));
+					}
+				}
+				if let Some(address) = created {
+					assert_created(&backend, address, &exit_reason);
+				}
+				if let Some(binary) = &options.reference_t8n {
+					let case = Case {
+						env: &test.0.env,
+						tx: &test.0.transaction,
+						indexes: &state.indexes,
+						fork,
+						chain_id: chainspec::active().map_or(1, |chain| chain.chain_id),
+						pre_state,
+					};
+					let ours = Executed {
+						alloc: backend.state().into_owned(),
+						success: exit_reason.is_succeed(),
+						used_gas,
+						logs: logs.clone(),
+					};
+					match reft8n::cross_check(binary, &case, &ours) {
+						Ok(differences) if differences.is_empty() => (),
+						Ok(differences) => {
+							panic!("Geth t8n differs:\n  {}", differences.join("\n  "))
+						}
+						Err(err) => panic!("Geth t8n cross-check failed: {}", err),
+					}
+				}
+				if let Err(err) = touched::check(&accounts, deletes_empty(spec), &substate.deleted)
+				{
+					panic!("{}", err);
+				}
+				validators::check(&validators::Index {
+					name,
+					fork,
+					index: i,
+					expected: state,
+					pre_state,
+					post_state: &backend.state(),
+					state_root: root,
+					logs: &logs,
+					used_gas,
+					exit_reason: &exit_reason,
+					output: &output,
+					call_tree: call_tree.as_ref(),
+				});
+				if options.ignore_post_state {
+					return;
+				}
+				// The full post-state is only built to report a mismatch.
+				if root != state.hash {
+					let post_state = backend.state();
+					if let Err(mismatch) = assert_valid_hash(&state.hash, &post_state) {
+						match &dumped {
+							Some(path) => mismatch.dumped_to(path).fail(&post_state),
+							None => mismatch.fail(&post_state),
+						}
+					}
+				}
+				if let Some(expected) = &state.output {
+					assert_valid_output(expected, &output);
+				}
+				if let Some(expected) = &state.logs {
+					assert_valid_logs_hash(expected, &logs);
+				}
+				if let Some(expected) = &state.bloom {
+					assert_valid_bloom(expected, &logs);
+				}
+			}));
+			metrics::global().add_gas(used_gas);
 
-					let _reason =
-						executor.transact_create(caller, value, code, gas_limit, access_list);
+			// Error, state diff and trace of a failing index.
+			let details = match &checks {
+				Err(payload) if options.json_report.is_some() || options.diagnose_failures => {
+					let trace = match &struct_logger {
+						Some(logger) => logger.eip3155(&output, used_gas),
+						None => {
+							let execution = execute(
+								&test,
+								spec,
+								&state.indexes,
+								vicinity,
+								pre_state,
+								StructLogger::new(),
+							)
+							.unwrap();
+							execution
+								.tracer
+								.eip3155(&execution.output, execution.used_gas)
+						}
+					};
+					Some((
+						panic_message(&**payload),
+						render_diff(&state_diff(pre_state, &backend.state()), false),
+						truncate_lines(&trace, MAX_REPORTED_TRACE_LINES),
+					))
+				}
+				_ => None,
+			};
+			let filler = &test.0.info.filler;
+			if checks.is_err() && !filler.is_empty() {
+				cprintln!("{}", filler);
+			}
+			if options.diagnose_failures {
+				if let Some((_, diff, trace)) = &details {
+					cprintln!();
+					cprintln!("{}{}:{}", FAILING_INDEX, fork, i);
+					cprint!("State diff:\n{}Trace:\n{}", diff, trace);
 				}
 			}
 
-			let actual_fee = executor.fee(vicinity.gas_price);
-			executor
-				.state_mut()
-				.deposit(vicinity.block_coinbase, actual_fee);
-			executor.state_mut().deposit(caller, total_fee - actual_fee);
-			let (values, logs) = executor.into_state().deconstruct();
-			backend.apply(values, logs, delete_empty);
-			assert_valid_hash(&state.hash.0, backend.state());
+			if let Some(path) = &options.timings {
+				Timing {
+					run: timings::run_id(),
+					id: index_id(name, fork, i, None),
+					time_us,
+				}
+				.append_to(path)
+				.expect("Write timings failed");
+			}
+			if let Some(path) = &options.json_report {
+				let (error, diff, trace) = match details {
+					Some((error, diff, trace)) => (Some(error), Some(diff), Some(trace)),
+					None => (None, None, None),
+				};
+				IndexReport {
+					name: name.to_string(),
+					fork: fork.clone(),
+					index: i,
+					label: label.map(String::from),
+					suite: suite(file),
+					passed: checks.is_ok(),
+					time_us,
+					used_gas: Some(used_gas),
+					refunded_gas: Some(refunded_gas),
+					gas_left: Some(gas_left),
+					output: Some(format!("0x{}", hex::encode(&output))),
+					deletes_empty: Some(deletes_empty(spec)),
+					substate: Some(substate),
+					steps: Some(steps.steps),
+					allocations: allocations.map(|allocations| allocations.count),
+					allocated_bytes: allocations.map(|allocations| allocations.bytes),
+					error,
+					diff,
+					trace,
+					backtrace: None,
+					filler: Some(filler.clone())
+						.filter(|filler| checks.is_err() && !filler.is_empty()),
+					call_tree,
+					gas,
+				}
+				.append_to(path)
+				.expect("Write JSON report failed");
+			}
+			if let Err(payload) = checks {
+				panic::resume_unwind(payload);
+			}
+			// Reruns are compared with the first execution, whose post-state
+			// root may not be the expected one when the post-state is ignored.
+			let expected = (root, used_gas, exit_reason, logs);
+			if let Some(threads) = options.stress {
+				let results = run_concurrently(threads, || {
+					let execution =
+						execute(&test, spec, &state.indexes, vicinity, pre_state, ()).unwrap();
+					(
+						execution.backend.state_root(),
+						execution.used_gas,
+						execution.exit_reason,
+						execution.logs,
+					)
+				});
+				for (thread, result) in results.into_iter().enumerate() {
+					if result != expected {
+						panic!(
+							"Execution on thread {} of {} differs: root {:?}, gas {}, exit {:?}; \
+							 expect: root {:?}, gas {}, exit {:?}",
+							thread,
+							threads,
+							result.0,
+							result.1,
+							result.2,
+							expected.0,
+							expected.1,
+							expected.2
+						);
+					}
+				}
+			}
 
-			println!("passed");
+			if options.determinism {
+				let trace = || {
+					let execution = execute(
+						&test,
+						spec,
+						&state.indexes,
+						vicinity,
+						pre_state,
+						StructLogger::new(),
+					)
+					.unwrap();
+					execution
+						.tracer
+						.eip3155(&execution.output, execution.used_gas)
+				};
+				let (first, second) = (trace(), trace());
+				if let Some((line, first, second)) =
+					tracediff::first_differing_line(&first, &second)
+				{
+					panic!(
+						"Traces of two executions differ at line {}:\n  {}\n  {}",
+						line, first, second
+					);
+				}
+			}
+
+			if let Some(seed) = options.perturb {
+				let (perturbed, added) = perturb(pre_state, seed);
+				let execution =
+					execute(&test, spec, &state.indexes, vicinity, &perturbed, ()).unwrap();
+				let result = (execution.used_gas, execution.exit_reason, execution.logs);
+				let mut post_state = execution.backend.into_state();
+				if let Err(address) = unperturb(&mut post_state, &perturbed, &added) {
+					panic!(
+						"Perturbed execution changed the added account {:?}",
+						address
+					);
+				}
+				let perturbed_root = state_root(&post_state);
+				if (&perturbed_root, &result.0, &result.1, &result.2)
+					!= (&expected.0, &expected.1, &expected.2, &expected.3)
+				{
+					panic!(
+						"Execution with accounts added by seed {} differs: root {:?}, gas {}, \
+						 exit {:?}; expect: root {:?}, gas {}, exit {:?}",
+						seed,
+						perturbed_root,
+						result.0,
+						result.1,
+						expected.0,
+						expected.1,
+						expected.2
+					);
+				}
+			}
+
+			if !options.quiet {
+				cprintln!("passed");
+			}
+			if let Some(repeat) = options.repeat {
+				let run = || {
+					let started = Instant::now();
+					execute(&test, spec, &state.indexes, vicinity, pre_state, ()).unwrap();
+					started.elapsed()
+				};
+				run();
+				let times = (0..repeat).map(|_| run()).collect();
+				cprintln!("  {}", Timings::new(times, used_gas, steps.steps));
+			}
+			// Carried once the reruns above are done with the pre-state it
+			// replaces.
+			if options.carry_state {
+				carried = Some(backend.into_state());
+			}
+			outcome.count_fork(fork, 1);
 		}
 	}
+	outcome
 }
@@ -0,0 +1,318 @@
+//! Statistics of a fixture corpus, gathered without executing anything: the
+//! tests of each suite and fork, the types of their transactions, how many
+//! use access lists and how large the code of their pre-states is, with
+//! the forks the runner supports marked, to plan coverage work.
+
+use crate::chainspec::ChainSpec;
+use crate::fixture::MultiTransaction;
+use crate::fixtures::{self, json_files, suite};
+use crate::format::{self, Format};
+use crate::signing::TxType;
+use crate::state::unsupported;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fmt::{self, Write};
+use std::path::Path;
+
+/// Upper bounds of the code size histogram buckets, in bytes.
+const CODE_SIZES: [(usize, &str); 4] = [
+	(1, "empty"),
+	(33, "1-32"),
+	(1025, "33-1024"),
+	// The EIP-170 limit on deployed code.
+	(24577, "1025-24576"),
+];
+
+/// Tests and, of state tests, post-states of a fork.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ForkStats {
+	pub tests: usize,
+	pub indexes: usize,
+}
+
+/// Statistics of the fixtures scanned so far.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Stats {
+	pub files: usize,
+	pub tests: usize,
+	/// Files that could not be read or are of no known format, with why.
+	pub unreadable: Vec<String>,
+	pub formats: BTreeMap<Format, usize>,
+	/// Tests by suite, the empty string for files outside of one.
+	pub suites: BTreeMap<String, usize>,
+	/// Forks by post-state key or blockchain test network.
+	pub forks: BTreeMap<String, ForkStats>,
+	/// Transactions by type: a state test has one for each data
+	/// alternative, a blockchain test those of its blocks.
+	pub tx_types: BTreeMap<TxType, usize>,
+	/// Transactions with an access list, and the addresses and storage keys
+	/// those list.
+	pub access_lists: usize,
+	pub access_list_addresses: usize,
+	pub access_list_keys: usize,
+	/// Pre-state accounts by code size bucket, as in `CODE_SIZES` with the
+	/// sizes over the last bound at the end.
+	pub code_sizes: [usize; CODE_SIZES.len() + 1],
+}
+
+fn code_size_bucket(code: &str) -> usize {
+	let len = code.trim_start_matches("0x").len() / 2;
+	CODE_SIZES
+		.iter()
+		.position(|(bound, _)| len < *bound)
+		.unwrap_or(CODE_SIZES.len())
+}
+
+/// Why the runner does not support `network`, also for transition networks
+/// of blockchain tests.
+fn network_unsupported(network: &str) -> Option<String> {
+	match ChainSpec::of_network(network) {
+		Some(chain) => chain
+			.forks
+			.iter()
+			.find_map(|activation| unsupported(&activation.fork)),
+		None => unsupported(network),
+	}
+}
+
+impl Stats {
+	/// Add the fixtures at `path`, a file or a directory searched for JSON
+	/// files.
+	pub fn scan(&mut self, path: &Path) {
+		for file in json_files(path) {
+			let name = file.display().to_string();
+			match fixtures::read(&file) {
+				Ok(contents) => self.add_file(&name, &contents),
+				Err(err) => {
+					self.files += 1;
+					self.unreadable.push(format!("{}: {}", name, err));
+				}
+			}
+		}
+	}
+
+	/// Add the fixture file at `path` with `contents`.
+	pub fn add_file(&mut self, path: &str, contents: &[u8]) {
+		self.files += 1;
+		let format = match format::detect(contents) {
+			Some(format) => format,
+			None => {
+				self.unreadable
+					.push(format!("{}: not a fixture file of one format", path));
+				return;
+			}
+		};
+		let tests = match serde_json::from_slice::<Map<String, Value>>(contents) {
+			Ok(tests) => tests,
+			Err(err) => {
+				self.unreadable.push(format!("{}: {}", path, err));
+				return;
+			}
+		};
+		let suite = suite(path).unwrap_or_default();
+		for (name, test) in &tests {
+			if let Err(err) = self.add_test(format, test) {
+				self.unreadable.push(format!("{}: {}: {}", path, name, err));
+				continue;
+			}
+			self.tests += 1;
+			*self.formats.entry(format).or_default() += 1;
+			*self.suites.entry(suite.clone()).or_default() += 1;
+		}
+	}
+
+	fn add_test(&mut self, format: Format, test: &Value) -> Result<(), String> {
+		match format {
+			Format::State => {
+				let transaction: MultiTransaction =
+					serde_json::from_value(test["transaction"].clone())
+						.map_err(|err| err.to_string())?;
+				for data in 0..transaction.data.len() {
+					*self
+						.tx_types
+						.entry(TxType::of_fixture(&transaction, data))
+						.or_default() += 1;
+				}
+				for access_list in transaction.access_lists.iter().flatten() {
+					self.access_lists += 1;
+					self.access_list_addresses += access_list.len();
+					self.access_list_keys += access_list
+						.iter()
+						.map(|item| item.storage_keys.len())
+						.sum::<usize>();
+				}
+				if let Some(post) = test["post"].as_object() {
+					for (fork, states) in post {
+						let stats = self.forks.entry(fork.clone()).or_default();
+						stats.tests += 1;
+						stats.indexes += states.as_array().map_or(0, Vec::len);
+					}
+				}
+			}
+			Format::Blockchain => {
+				if let Some(network) = test["network"].as_str() {
+					self.forks.entry(network.to_string()).or_default().tests += 1;
+				}
+				let transactions = test["blocks"]
+					.as_array()
+					.into_iter()
+					.flatten()
+					.filter_map(|block| block["transactions"].as_array())
+					.flatten();
+				for transaction in transactions {
+					let byte = match transaction["type"].as_str() {
+						Some(byte) => Some(
+							u8::from_str_radix(byte.trim_start_matches("0x"), 16)
+								.map_err(|_| format!("invalid transaction type {}", byte))?,
+						),
+						None => None,
+					};
+					let tx_type = TxType::ALL
+						.iter()
+						.copied()
+						.find(|tx_type| tx_type.byte() == byte.filter(|byte| *byte != 0))
+						.ok_or_else(|| format!("unknown transaction type {:?}", byte))?;
+					*self.tx_types.entry(tx_type).or_default() += 1;
+					if let Some(access_list) = transaction["accessList"].as_array() {
+						self.access_lists += 1;
+						self.access_list_addresses += access_list.len();
+						self.access_list_keys += access_list
+							.iter()
+							.filter_map(|item| item["storageKeys"].as_array())
+							.map(Vec::len)
+							.sum::<usize>();
+					}
+				}
+			}
+			Format::Vm => (),
+		}
+		for account in test["pre"].as_object().into_iter().flat_map(Map::values) {
+			let code = account["code"].as_str().unwrap_or("");
+			self.code_sizes[code_size_bucket(code)] += 1;
+		}
+		Ok(())
+	}
+}
+
+impl fmt::Display for Stats {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "{} tests in {} files", self.tests, self.files)?;
+		for (format, count) in &self.formats {
+			writeln!(f, "  {}: {}", format.name(), count)?;
+		}
+
+		writeln!(f, "Suites:")?;
+		for (suite, count) in &self.suites {
+			let suite = if suite.is_empty() { "(none)" } else { suite };
+			writeln!(f, "  {}: {}", suite, count)?;
+		}
+
+		writeln!(f, "Forks:")?;
+		for (fork, stats) in &self.forks {
+			let mut line = format!("  {}: {} tests", fork, stats.tests);
+			if stats.indexes > 0 {
+				write!(line, ", {} indexes", stats.indexes)?;
+			}
+			match network_unsupported(fork) {
+				Some(reason) => write!(line, " (unsupported: {})", reason)?,
+				None => line += " (supported)",
+			}
+			writeln!(f, "{}", line)?;
+		}
+
+		writeln!(f, "Transaction types:")?;
+		for (tx_type, count) in &self.tx_types {
+			writeln!(f, "  {}: {}", tx_type.name(), count)?;
+		}
+		writeln!(
+			f,
+			"Access lists: {} transactions, {} addresses, {} storage keys",
+			self.access_lists, self.access_list_addresses, self.access_list_keys
+		)?;
+
+		writeln!(f, "Pre-state code sizes:")?;
+		let labels = CODE_SIZES
+			.iter()
+			.map(|(_, label)| *label)
+			.chain(Some("> 24576"));
+		for (label, count) in labels.zip(&self.code_sizes) {
+			writeln!(f, "  {}: {}", label, count)?;
+		}
+
+		if !self.unreadable.is_empty() {
+			writeln!(f, "Unreadable:")?;
+			for unreadable in &self.unreadable {
+				writeln!(f, "  {}", unreadable)?;
+			}
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn fixture_stats() {
+		let state = br#"{
+			"add": {
+				"env": {},
+				"pre": {
+					"0x1000000000000000000000000000000000000000": { "code": "0x6001" },
+					"0x2000000000000000000000000000000000000000": { "code": "0x" }
+				},
+				"transaction": {
+					"data": ["0x", "0x01"],
+					"accessLists": [null, [{ "address": "0x1000000000000000000000000000000000000000", "storageKeys": ["0x0000000000000000000000000000000000000000000000000000000000000000"] }]],
+					"gasLimit": ["0x5208"],
+					"gasPrice": "0x0a",
+					"nonce": "0x00",
+					"secretKey": "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8",
+					"to": "0x1000000000000000000000000000000000000000",
+					"value": ["0x00"]
+				},
+				"post": { "Berlin": [{}, {}], "Shanghai": [{}] }
+			}
+		}"#;
+		let blockchain = br#"{
+			"add_Berlin": {
+				"network": "Berlin",
+				"genesisBlockHeader": {},
+				"pre": {},
+				"blocks": [{ "transactions": [{ "type": "0x02" }, {}] }]
+			}
+		}"#;
+		let mut stats = Stats::default();
+		stats.add_file("tests/GeneralStateTests/stExample/add.json", state);
+		stats.add_file("tests/BlockchainTests/add.json", blockchain);
+		stats.add_file("tests/other.json", b"[]");
+
+		assert_eq!((stats.files, stats.tests), (3, 2));
+		assert_eq!(stats.unreadable.len(), 1);
+		assert_eq!(stats.suites["stExample"], 1);
+		assert_eq!(
+			stats.forks["Berlin"],
+			ForkStats {
+				tests: 2,
+				indexes: 2
+			}
+		);
+		assert_eq!(stats.tx_types[&TxType::Legacy], 2);
+		assert_eq!(stats.tx_types[&TxType::AccessList], 1);
+		assert_eq!(stats.tx_types[&TxType::DynamicFee], 1);
+		assert_eq!(
+			(
+				stats.access_lists,
+				stats.access_list_addresses,
+				stats.access_list_keys
+			),
+			(1, 1, 1)
+		);
+		assert_eq!(stats.code_sizes, [1, 1, 0, 0, 0]);
+
+		let report = stats.to_string();
+		assert!(report.contains("  Berlin: 2 tests, 2 indexes (supported)\n"));
+		assert!(report.contains("  Shanghai: 1 tests, 1 indexes (unsupported: "));
+	}
+}
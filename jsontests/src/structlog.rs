@@ -0,0 +1,335 @@
+use crate::callstack::CallStack;
+use crate::opcodes;
+use crate::trace::{exit_error, gas_left, Tracer};
+use evm::tracing::Event;
+use evm::Capture;
+use evm_runtime::tracing::Event as RuntimeEvent;
+use primitive_types::{H256, U256};
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::str::FromStr;
+
+/// Output format of execution traces.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TraceFormat {
+	/// Geth's `structLogger` output, as returned by `debug_traceTransaction`.
+	Struct,
+	/// Geth's `callTracer` output.
+	Call,
+	/// EIP-3155 JSON lines, one per step followed by a summary line.
+	Eip3155,
+}
+
+impl FromStr for TraceFormat {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		match s {
+			"struct" => Ok(TraceFormat::Struct),
+			"call" => Ok(TraceFormat::Call),
+			"3155" => Ok(TraceFormat::Eip3155),
+			_ => Err(format!("Unknown tracer: {}", s)),
+		}
+	}
+}
+
+impl TraceFormat {
+	/// File extension of traces written in this format.
+	pub fn extension(&self) -> &'static str {
+		match self {
+			TraceFormat::Struct | TraceFormat::Call => "json",
+			TraceFormat::Eip3155 => "jsonl",
+		}
+	}
+}
+
+/// Interpreter state before a single executed instruction.
+#[derive(Debug, Clone)]
+pub struct StructLog {
+	pub pc: usize,
+	pub op: u8,
+	/// Gas left before the instruction.
+	pub gas: u64,
+	/// Gas charged for the instruction, including gas consumed by the
+	/// sub-call it starts.
+	pub gas_cost: u64,
+	/// Call depth, starting at 1 for the transaction's own frame.
+	pub depth: usize,
+	pub stack: Vec<H256>,
+	pub memory: Vec<u8>,
+	/// Storage of the executing contract accessed so far, recorded for
+	/// `SLOAD` and `SSTORE` only.
+	pub storage: BTreeMap<H256, H256>,
+	pub refund: i64,
+	pub error: Option<String>,
+}
+
+fn stack_item(value: &H256) -> String {
+	format!("{:#x}", U256::from_big_endian(&value[..]))
+}
+
+impl StructLog {
+	/// The step as an EIP-3155 trace line.
+	pub fn eip3155(&self) -> Value {
+		let mut line = json!({
+			"pc": self.pc,
+			"op": self.op,
+			"gas": format!("{:#x}", self.gas),
+			"gasCost": format!("{:#x}", self.gas_cost),
+			"memSize": self.memory.len(),
+			"stack": self.stack.iter().map(stack_item).collect::<Vec<_>>(),
+			"depth": self.depth,
+			"refund": self.refund,
+			"opName": opcodes::display_name(self.op),
+		});
+		if let Some(error) = &self.error {
+			line["error"] = json!(error);
+		}
+		line
+	}
+
+	/// The step as an entry of geth's `structLogs`.
+	pub fn geth(&self) -> Value {
+		let mut log = json!({
+			"pc": self.pc,
+			"op": opcodes::display_name(self.op),
+			"gas": self.gas,
+			"gasCost": self.gas_cost,
+			"depth": self.depth,
+			"stack": self.stack.iter().map(stack_item).collect::<Vec<_>>(),
+			"memory": self.memory.chunks(32).map(hex::encode).collect::<Vec<_>>(),
+		});
+		if !self.storage.is_empty() {
+			log["storage"] = self
+				.storage
+				.iter()
+				.map(|(key, value)| (hex::encode(key), json!(hex::encode(value))))
+				.collect::<serde_json::Map<_, _>>()
+				.into();
+		}
+		if self.refund != 0 {
+			log["refund"] = json!(self.refund);
+		}
+		if let Some(error) = &self.error {
+			log["error"] = json!(error);
+		}
+		log
+	}
+}
+
+struct Frame {
+	/// Gas left according to the frame's gasometer, once it recorded a
+	/// charge.
+	gas: Option<u64>,
+	/// Index of the frame's previous step, whose cost is not yet known.
+	pending: Option<usize>,
+	storage: BTreeMap<H256, H256>,
+}
+
+/// Records every executed instruction together with the interpreter state
+/// before it.
+#[derive(Default)]
+pub struct StructLogger {
+	frames: CallStack<Frame>,
+	logs: Vec<StructLog>,
+	refund: i64,
+}
+
+impl StructLogger {
+	pub fn new() -> Self {
+		Self::default()
+	}
+
+	pub fn logs(&self) -> &[StructLog] {
+		&self.logs
+	}
+
+	/// EIP-3155 trace: one line per step, then a summary line.
+	pub fn eip3155(&self, output: &[u8], used_gas: u64) -> String {
+		let mut out = String::new();
+		for log in &self.logs {
+			writeln!(out, "{}", log.eip3155()).unwrap();
+		}
+		writeln!(
+			out,
+			"{}",
+			json!({
+				"output": hex::encode(output),
+				"gasUsed": format!("{:#x}", used_gas),
+			})
+		)
+		.unwrap();
+		out
+	}
+
+	/// Trace in the format of geth's `debug_traceTransaction` result.
+	pub fn geth(&self, failed: bool, output: &[u8], used_gas: u64) -> Value {
+		json!({
+			"gas": used_gas,
+			"failed": failed,
+			"returnValue": hex::encode(output),
+			"structLogs": self.logs.iter().map(StructLog::geth).collect::<Vec<_>>(),
+		})
+	}
+
+	fn enter(&mut self, event: &Event) {
+		self.frames.enter(
+			event,
+			Frame {
+				gas: None,
+				pending: None,
+				storage: BTreeMap::new(),
+			},
+		);
+	}
+
+	fn exit(&mut self) {
+		self.settle();
+		self.frames.exit();
+	}
+
+	/// Settle the cost of the current frame's previous step.
+	fn settle(&mut self) {
+		if let Some(frame) = self.frames.last_mut() {
+			if let (Some(index), Some(gas)) = (frame.pending.take(), frame.gas) {
+				let log = &mut self.logs[index];
+				log.gas_cost = log.gas.saturating_sub(gas);
+			}
+		}
+	}
+}
+
+impl Tracer for StructLogger {
+	fn evm_event(&mut self, event: Event) {
+		match event {
+			Event::Call { .. } | Event::Create { .. } => self.enter(&event),
+			Event::Exit { .. } => self.exit(),
+			_ => (),
+		}
+	}
+
+	fn runtime_event(&mut self, event: RuntimeEvent) {
+		match event {
+			RuntimeEvent::Step {
+				opcode,
+				position,
+				stack,
+				memory,
+				..
+			} => {
+				for _ in 0..self.frames.step(stack) {
+					self.exit();
+				}
+				let pc = match position {
+					Ok(pc) => *pc,
+					Err(_) => return,
+				};
+				self.settle();
+				let depth = self.frames.len();
+				let refund = self.refund;
+				let forwarded = self.frames.gas();
+				let frame = match self.frames.last_mut() {
+					Some(frame) => frame,
+					None => return,
+				};
+				// Until its first charge, a frame has the gas it was given.
+				if frame.gas.is_none() {
+					frame.gas = forwarded;
+				}
+				frame.pending = Some(self.logs.len());
+				let storage = match opcode.0 {
+					0x54 | 0x55 => frame.storage.clone(),
+					_ => BTreeMap::new(),
+				};
+				self.logs.push(StructLog {
+					pc,
+					op: opcode.0,
+					gas: frame.gas.unwrap_or(0),
+					gas_cost: 0,
+					depth,
+					stack: stack.data().clone(),
+					memory: memory.data().clone(),
+					storage,
+					refund,
+					error: None,
+				});
+			}
+			RuntimeEvent::StepResult {
+				result: Err(Capture::Exit(reason)),
+				..
+			} => {
+				if let Some(index) = self.frames.last().and_then(|frame| frame.pending) {
+					self.logs[index].error = exit_error(reason);
+				}
+			}
+			RuntimeEvent::SLoad { index, value, .. }
+			| RuntimeEvent::SStore { index, value, .. } => {
+				if let Some(frame) = self.frames.last_mut() {
+					frame.storage.insert(index, value);
+				}
+			}
+			_ => (),
+		}
+	}
+
+	fn gas_event(&mut self, event: evm_gasometer::tracing::Event) {
+		if let Some((frame, snapshot)) = self.frames.gas_event(&event) {
+			frame.gas = Some(gas_left(&snapshot));
+			self.refund = snapshot.refunded_gas;
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::trace::test_helpers::{account, call};
+	use primitive_types::H160;
+
+	#[test]
+	fn matches_geth_struct_logs() {
+		let contract = H160::from_low_u64_be(0x1000);
+		let mut state = BTreeMap::new();
+		// CALL of the identity precompile with 0xffff gas, POP, then
+		// PUSH1 1 PUSH1 0 SSTORE STOP.
+		state.insert(
+			contract,
+			account("60006000600060006000600461fffff150600160005500"),
+		);
+		let execution = call(state, contract, StructLogger::new());
+		assert!(execution.exit_reason.is_succeed());
+
+		// pc, op, gas and gasCost of geth's `structLogs` for the transaction.
+		let geth = [
+			(0, "PUSH1", 979000, 3),
+			(2, "PUSH1", 978997, 3),
+			(4, "PUSH1", 978994, 3),
+			(6, "PUSH1", 978991, 3),
+			(8, "PUSH1", 978988, 3),
+			(10, "PUSH1", 978985, 3),
+			(12, "PUSH2", 978982, 3),
+			(15, "CALL", 978979, 65635),
+			(16, "POP", 978864, 2),
+			(17, "PUSH1", 978862, 3),
+			(19, "PUSH1", 978859, 3),
+			(21, "SSTORE", 978856, 22100),
+			(22, "STOP", 956756, 0),
+		];
+		let trace = execution
+			.tracer
+			.geth(false, &execution.output, execution.used_gas);
+		let logs = trace["structLogs"].as_array().unwrap();
+		assert_eq!(logs.len(), geth.len());
+		for (log, (pc, op, gas, gas_cost)) in logs.iter().zip(geth.iter()) {
+			assert_eq!(log["pc"], *pc, "{}", log);
+			assert_eq!(log["op"], *op, "{}", log);
+			assert_eq!(log["gas"], *gas, "{}", log);
+			assert_eq!(log["depth"], 1, "{}", log);
+			// Geth charges a call with the gas it forwards, here with the
+			// gas the precompile used.
+			let gas_cost = if *op == "CALL" { 100 + 15 } else { *gas_cost };
+			assert_eq!(log["gasCost"], gas_cost, "{}", log);
+		}
+	}
+}
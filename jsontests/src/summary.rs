@@ -0,0 +1,475 @@
+use crate::builtins::FORKS;
+use crate::sarif::Finding;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+/// Every selected test passed.
+pub const EXIT_PASSED: i32 = 0;
+/// At least one test failed, a fork was skipped when skips are not allowed,
+/// a fork executed fewer than the manifest expects or memory leaked.
+pub const EXIT_FAILURES: i32 = 1;
+/// At least one fixture file could not be read or parsed.
+pub const EXIT_PARSE_ERRORS: i32 = 2;
+/// No test was run, e.g. because the filters matched nothing.
+pub const EXIT_NOTHING_RAN: i32 = 3;
+
+/// Why the post states of a fork were not run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SkipReason {
+	/// The evm the runner builds against does not support the fork, or does
+	/// not know it.
+	UnsupportedFork,
+	/// There are no builtins for the fork in the schedule.
+	MissingBuiltins,
+	/// Left out by `--fork`, `--index`, `--label` or a list of indexes to
+	/// run.
+	Filtered,
+	/// Matching a `--skip-pattern`, listed as not to be run.
+	Listed,
+	/// Of a fork other than the one a chain spec has at the block of the
+	/// test, which the chain never runs it under.
+	NotApplicable,
+}
+
+impl SkipReason {
+	pub fn name(&self) -> &'static str {
+		match self {
+			SkipReason::UnsupportedFork => "unsupported-fork",
+			SkipReason::MissingBuiltins => "missing-builtins",
+			SkipReason::Filtered => "filtered",
+			SkipReason::Listed => "listed",
+			SkipReason::NotApplicable => "not-applicable",
+		}
+	}
+
+	/// Whether post states skipped for the reason are coverage the runner
+	/// lacks, counted as skipped and failing the run unless skips are
+	/// allowed. The others the run or its chain asked for.
+	pub fn is_unsupported(&self) -> bool {
+		matches!(
+			self,
+			SkipReason::UnsupportedFork | SkipReason::MissingBuiltins
+		)
+	}
+}
+
+impl fmt::Display for SkipReason {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(self.name())
+	}
+}
+
+/// Post states of a fork not run, and why.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Skip {
+	pub fork: String,
+	pub reason: SkipReason,
+	pub count: usize,
+}
+
+/// What a single passing test executed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Outcome {
+	/// Post states, chains or vm tests executed.
+	pub executed: usize,
+	/// Post states skipped because the runner does not support their fork.
+	pub skipped: usize,
+	/// Post states or chains executed on each fork of `FORKS`.
+	pub by_fork: [usize; FORKS.len()],
+	/// Post states not run by fork and reason, filtered ones included.
+	pub skips: Vec<Skip>,
+}
+
+impl Outcome {
+	pub fn executed(executed: usize) -> Self {
+		Outcome {
+			executed,
+			..Default::default()
+		}
+	}
+
+	/// Post states not run for `reason`, of every fork.
+	pub fn skipped_for(&self, reason: SkipReason) -> usize {
+		self.skips
+			.iter()
+			.filter(|skip| skip.reason == reason)
+			.map(|skip| skip.count)
+			.sum()
+	}
+
+	/// Count `executed` post states or chains of `fork`, which is only
+	/// counted by fork if it is one of `FORKS`.
+	pub fn count_fork(&mut self, fork: &str, executed: usize) {
+		self.executed += executed;
+		if let Some(position) = FORKS.iter().position(|name| *name == fork) {
+			self.by_fork[position] += executed;
+		}
+	}
+
+	/// Count `count` post states of `fork` not run for `reason`, as skipped
+	/// if the reason `is_unsupported`.
+	pub fn skip(&mut self, fork: &str, reason: SkipReason, count: usize) {
+		if count == 0 {
+			return;
+		}
+		if reason.is_unsupported() {
+			self.skipped += count;
+		}
+		match self
+			.skips
+			.iter_mut()
+			.find(|skip| skip.fork == fork && skip.reason == reason)
+		{
+			Some(skip) => skip.count += count,
+			None => self.skips.push(Skip {
+				fork: fork.to_string(),
+				reason,
+				count,
+			}),
+		}
+	}
+}
+
+/// Tests of a suite that passed and failed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SuiteTotals {
+	pub passed: usize,
+	pub failed: usize,
+}
+
+/// Totals of a run.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Summary {
+	pub passed: usize,
+	pub failed: usize,
+	/// Post states skipped for an unsupported fork.
+	pub skipped: usize,
+	/// Post states not run by fork and reason, filtered ones included.
+	pub skips: BTreeMap<(String, SkipReason), usize>,
+	pub parse_errors: usize,
+	/// Failed tests whose child process crashed, with `--isolate process`.
+	pub crashed: usize,
+	/// Failed tests stopped by the step limit of `--max-steps`.
+	pub step_limited: usize,
+	/// Tests not started because the time budget of the run ran out.
+	pub out_of_time: usize,
+	/// Post states or chains of passing tests executed by fork.
+	pub forks: BTreeMap<String, usize>,
+	/// Forks executing fewer than the manifest of the run expects.
+	pub short_forks: usize,
+	/// Resident memory grew every round of a soak run.
+	pub leaked: bool,
+	/// Totals by suite of the fixture files, for files in one.
+	pub suites: BTreeMap<String, SuiteTotals>,
+	/// Names of the failed tests by the first line of their failure
+	/// message.
+	pub failures: BTreeMap<String, Vec<String>>,
+	/// Failed tests with their file and output, kept for `--sarif`.
+	pub findings: Vec<Finding>,
+}
+
+impl Summary {
+	pub fn pass(&mut self, outcome: Outcome) {
+		if outcome.executed > 0 {
+			self.passed += 1;
+		}
+		self.skipped += outcome.skipped;
+		for (fork, executed) in FORKS.iter().zip(&outcome.by_fork) {
+			if *executed > 0 {
+				*self.forks.entry(fork.to_string()).or_default() += executed;
+			}
+		}
+		for skip in outcome.skips {
+			*self.skips.entry((skip.fork, skip.reason)).or_default() += skip.count;
+		}
+	}
+
+	/// Count test `name` failing with `message`.
+	pub fn fail(&mut self, name: &str, message: &str) {
+		self.failed += 1;
+		let message = message.lines().next().unwrap_or_default().trim();
+		self.failures
+			.entry(message.to_string())
+			.or_default()
+			.push(name.to_string());
+	}
+
+	/// Count a test of `suite` that passed with `outcome`, or failed if there
+	/// is none.
+	pub fn count_suite(&mut self, suite: &str, outcome: Option<&Outcome>) {
+		let totals = self.suites.entry(suite.to_string()).or_default();
+		match outcome {
+			Some(outcome) if outcome.executed > 0 => totals.passed += 1,
+			Some(_) => (),
+			None => totals.failed += 1,
+		}
+	}
+
+	/// Add the totals of `other`, a run of other tests, to these.
+	pub fn merge(&mut self, other: Summary) {
+		self.passed += other.passed;
+		self.failed += other.failed;
+		self.skipped += other.skipped;
+		for (key, count) in other.skips {
+			*self.skips.entry(key).or_default() += count;
+		}
+		self.parse_errors += other.parse_errors;
+		self.crashed += other.crashed;
+		self.step_limited += other.step_limited;
+		self.short_forks += other.short_forks;
+		self.leaked |= other.leaked;
+		self.out_of_time += other.out_of_time;
+		for (fork, executed) in other.forks {
+			*self.forks.entry(fork).or_default() += executed;
+		}
+		for (suite, totals) in other.suites {
+			let merged = self.suites.entry(suite).or_default();
+			merged.passed += totals.passed;
+			merged.failed += totals.failed;
+		}
+		for (message, names) in other.failures {
+			self.failures.entry(message).or_default().extend(names);
+		}
+		self.findings.extend(other.findings);
+	}
+
+	/// Digest of the failures, the most common message first, with the
+	/// number of tests failing with each and the first few of them.
+	pub fn failures_report(&self) -> String {
+		const SHOWN: usize = 3;
+		let mut failures = self.failures.iter().collect::<Vec<_>>();
+		failures.sort_by_key(|(_, names)| std::cmp::Reverse(names.len()));
+		let mut report = "Failures by message:\n".to_string();
+		for (message, names) in failures {
+			let message = if message.is_empty() {
+				"no message"
+			} else {
+				message
+			};
+			report += &format!("{:>6}  {}\n", names.len(), message);
+			let mut shown = names
+				.iter()
+				.take(SHOWN)
+				.cloned()
+				.collect::<Vec<_>>()
+				.join(", ");
+			if names.len() > SHOWN {
+				shown += &format!(" and {} more", names.len() - SHOWN);
+			}
+			report += &format!("        {}\n", shown);
+		}
+		report
+	}
+
+	/// Table of the totals by suite, failing suites first.
+	pub fn suites_report(&self) -> String {
+		let mut suites = self.suites.iter().collect::<Vec<_>>();
+		suites.sort_by_key(|(_, totals)| totals.failed == 0);
+		let width = suites
+			.iter()
+			.map(|(name, _)| name.len())
+			.max()
+			.unwrap_or(0)
+			.max("Suite".len());
+		let mut report = format!("{:<width$}  passed  failed\n", "Suite", width = width);
+		for (name, totals) in suites {
+			report += &format!(
+				"{:<width$}  {:>6}  {:>6}\n",
+				name,
+				totals.passed,
+				totals.failed,
+				width = width
+			);
+		}
+		report
+	}
+
+	/// Table of the post states not run by fork and reason.
+	pub fn skips_report(&self) -> String {
+		let width = self
+			.skips
+			.keys()
+			.map(|(fork, _)| fork.len())
+			.max()
+			.unwrap_or(0)
+			.max("Fork".len());
+		let mut report = format!(
+			"{:<width$}  {:<16}  post states\n",
+			"Fork",
+			"Skip reason",
+			width = width
+		);
+		for ((fork, reason), count) in &self.skips {
+			report += &format!(
+				"{:<width$}  {:<16}  {:>11}\n",
+				fork,
+				reason.name(),
+				count,
+				width = width
+			);
+		}
+		report
+	}
+
+	/// Process exit code of the run. Parse errors take precedence over
+	/// failures, which take precedence over an empty run.
+	pub fn exit_code(&self, allow_skips: bool) -> i32 {
+		if self.parse_errors > 0 {
+			EXIT_PARSE_ERRORS
+		} else if self.failed > 0
+			|| self.short_forks > 0
+			|| self.leaked
+			|| (self.skipped > 0 && !allow_skips)
+		{
+			EXIT_FAILURES
+		} else if self.passed == 0 {
+			EXIT_NOTHING_RAN
+		} else {
+			EXIT_PASSED
+		}
+	}
+}
+
+impl fmt::Display for Summary {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{} passed, {} failed, {} forks skipped, {} parse errors",
+			self.passed, self.failed, self.skipped, self.parse_errors
+		)?;
+		if self.crashed > 0 {
+			write!(f, ", {} crashed", self.crashed)?;
+		}
+		if self.step_limited > 0 {
+			write!(f, ", {} stopped by the step limit", self.step_limited)?;
+		}
+		if self.short_forks > 0 {
+			write!(f, ", {} forks short of the manifest", self.short_forks)?;
+		}
+		if self.leaked {
+			write!(f, ", memory grew every soak round")?;
+		}
+		if self.out_of_time > 0 {
+			write!(f, ", {} not run within the time budget", self.out_of_time)?;
+		}
+		Ok(())
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn exit_codes() {
+		let mut summary = Summary::default();
+		assert_eq!(summary.exit_code(false), EXIT_NOTHING_RAN);
+
+		summary.pass(Outcome::default());
+		assert_eq!(summary.exit_code(false), EXIT_NOTHING_RAN);
+
+		summary.pass(Outcome {
+			executed: 2,
+			skipped: 1,
+			..Default::default()
+		});
+		assert_eq!(summary.exit_code(false), EXIT_FAILURES);
+		assert_eq!(summary.exit_code(true), EXIT_PASSED);
+
+		summary.failed += 1;
+		assert_eq!(summary.exit_code(true), EXIT_FAILURES);
+
+		summary.parse_errors += 1;
+		assert_eq!(summary.exit_code(true), EXIT_PARSE_ERRORS);
+	}
+
+	#[test]
+	fn skips_by_fork_and_reason() {
+		let mut outcome = Outcome::default();
+		outcome.skip("Cancun", SkipReason::UnsupportedFork, 2);
+		outcome.skip("Cancun", SkipReason::UnsupportedFork, 1);
+		outcome.skip("Istanbul", SkipReason::Filtered, 4);
+		outcome.skip("London", SkipReason::MissingBuiltins, 0);
+		outcome.skip("Berlin", SkipReason::Listed, 1);
+		outcome.skip("Berlin", SkipReason::NotApplicable, 2);
+		assert_eq!(outcome.skipped, 3);
+		assert_eq!(outcome.skips.len(), 4);
+		assert_eq!(outcome.skipped_for(SkipReason::UnsupportedFork), 3);
+		assert_eq!(outcome.skipped_for(SkipReason::Listed), 1);
+		assert_eq!(outcome.skipped_for(SkipReason::MissingBuiltins), 0);
+		assert_eq!(
+			serde_json::to_value(&outcome.skips[0]).unwrap(),
+			serde_json::json!({ "fork": "Cancun", "reason": "unsupported-fork", "count": 3 })
+		);
+
+		let mut summary = Summary::default();
+		summary.pass(outcome.clone());
+		let mut merged = Summary::default();
+		merged.merge(summary.clone());
+		merged.pass(outcome);
+		assert_eq!(merged.skipped, 6);
+		assert_eq!(
+			merged.skips[&("Cancun".to_string(), SkipReason::UnsupportedFork)],
+			6
+		);
+		assert_eq!(
+			summary.skips_report(),
+			"Fork      Skip reason       post states\n\
+			 Berlin    listed                      1\n\
+			 Berlin    not-applicable              2\n\
+			 Cancun    unsupported-fork            3\n\
+			 Istanbul  filtered                    4\n"
+		);
+	}
+
+	#[test]
+	fn failures_by_message() {
+		let mut summary = Summary::default();
+		summary.fail("add", "state root mismatch\nexpected 0x01");
+		summary.fail("sub", "timed out");
+		let mut merged = Summary::default();
+		merged.merge(summary.clone());
+		for name in &["mul", "div", "mod"] {
+			merged.fail(name, "state root mismatch\nexpected 0x02");
+		}
+		assert_eq!(merged.failed, 5);
+		assert_eq!(
+			merged.failures_report(),
+			"Failures by message:\n     \
+			 4  state root mismatch\n        \
+			 add, mul, div and 1 more\n     \
+			 1  timed out\n        \
+			 sub\n"
+		);
+	}
+
+	#[test]
+	fn suite_totals() {
+		let mut summary = Summary::default();
+		summary.count_suite("stCreate2", Some(&Outcome::executed(1)));
+		summary.count_suite("stCreate2", Some(&Outcome::default()));
+		summary.count_suite("stBadOpcode", Some(&Outcome::executed(2)));
+		summary.count_suite("stCreate2", None);
+		assert_eq!(
+			summary.suites["stCreate2"],
+			SuiteTotals {
+				passed: 1,
+				failed: 1
+			}
+		);
+
+		let mut merged = summary.clone();
+		merged.merge(summary.clone());
+		merged.failed += 1;
+		assert_eq!(merged.suites["stCreate2"].failed, 2);
+		assert_eq!(merged.suites["stBadOpcode"].passed, 2);
+		assert_eq!(
+			summary.suites_report(),
+			"Suite        passed  failed\n\
+			 stCreate2         1       1\n\
+			 stBadOpcode       1       0\n"
+		);
+	}
+}
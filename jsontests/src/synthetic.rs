@@ -0,0 +1,263 @@
+//! Synthetic state tests with pre-states far larger than fixtures have, to
+//! benchmark and soak the executor and backends at scale. Everything is
+//! derived from a seed, so a size and seed always give the same test, and
+//! the test is filled like a filler to get the post-state roots to check.
+
+use crate::filler::{accounts_json, fill, Expectation, Filler, IndexSelector};
+use crate::order::Rng;
+use evm::backend::MemoryAccount;
+use primitive_types::{H160, H256, U256};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+/// Sender of the transaction, the account of the usual fixture secret key.
+const SENDER: &str = "a94f5374fce5edbc8e2a8697c15331677e6ebf0b";
+const SECRET_KEY: &str = "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8";
+/// Slots of the called contract its code reads, at most.
+const READ_SLOTS: usize = 64;
+const GAS_LIMIT: u64 = 10_000_000;
+
+/// Transaction the synthetic test runs.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Template {
+	/// Send value to one of the accounts.
+	Transfer,
+	/// Call a contract reading its storage and writing a slot.
+	Call,
+}
+
+impl Template {
+	pub fn name(self) -> &'static str {
+		match self {
+			Template::Transfer => "transfer",
+			Template::Call => "call",
+		}
+	}
+}
+
+impl FromStr for Template {
+	type Err = String;
+
+	fn from_str(s: &str) -> Result<Self, Self::Err> {
+		[Template::Transfer, Template::Call]
+			.iter()
+			.find(|template| template.name() == s)
+			.copied()
+			.ok_or_else(|| format!("unknown transaction template {}", s))
+	}
+}
+
+/// Size and seed of a synthetic test.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Params {
+	pub accounts: usize,
+	/// Storage slots of every account.
+	pub slots: usize,
+	/// Bytes of code of every account. The called contract of the call
+	/// template has more if its code does not fit.
+	pub code_size: usize,
+	pub seed: u64,
+	pub template: Template,
+	/// Forks to fill the test for.
+	pub forks: Vec<String>,
+}
+
+impl Default for Params {
+	fn default() -> Self {
+		Params {
+			accounts: 1000,
+			slots: 10,
+			code_size: 1024,
+			seed: 0,
+			template: Template::Transfer,
+			forks: vec!["Berlin".to_string()],
+		}
+	}
+}
+
+fn bytes(rng: &mut Rng, len: usize) -> Vec<u8> {
+	(0..len).map(|_| rng.next_u64() as u8).collect()
+}
+
+fn word(rng: &mut Rng) -> H256 {
+	H256::from_slice(&bytes(rng, 32))
+}
+
+/// Code reading up to `READ_SLOTS` of `keys` and writing the first word of
+/// calldata to the first, padded with random bytes after its `STOP` to
+/// `size`.
+fn contract_code(rng: &mut Rng, keys: &[H256], size: usize) -> Vec<u8> {
+	let mut code = Vec::new();
+	for key in keys.iter().take(READ_SLOTS) {
+		// PUSH32 key SLOAD POP
+		code.push(0x7f);
+		code.extend_from_slice(key.as_bytes());
+		code.extend_from_slice(&[0x54, 0x50]);
+	}
+	if let Some(key) = keys.first() {
+		// PUSH1 0 CALLDATALOAD PUSH32 key SSTORE
+		code.extend_from_slice(&[0x60, 0x00, 0x35, 0x7f]);
+		code.extend_from_slice(key.as_bytes());
+		code.push(0x55);
+	}
+	code.push(0x00);
+	let padding = size.saturating_sub(code.len());
+	code.extend(bytes(rng, padding));
+	code
+}
+
+/// Pre-state of `params` with the account the transaction goes to, the
+/// sender not included.
+pub fn pre_state(params: &Params) -> (BTreeMap<H160, MemoryAccount>, H160) {
+	let mut rng = Rng::new(params.seed);
+	let mut state = BTreeMap::new();
+	let mut target = H160::zero();
+	let target_index = rng.below(params.accounts.max(1) as u64) as usize;
+	for i in 0..params.accounts {
+		let address = H160::from_slice(&bytes(&mut rng, 20));
+		let keys = (0..params.slots)
+			.map(|_| word(&mut rng))
+			.collect::<Vec<_>>();
+		let storage = keys
+			.iter()
+			.map(|key| {
+				// Zero values are not stored.
+				let mut value = word(&mut rng);
+				value.0[31] |= 1;
+				(*key, value)
+			})
+			.collect();
+		// Value is sent to an account without code.
+		let code = match (i == target_index, params.template) {
+			(true, Template::Transfer) => Vec::new(),
+			(true, Template::Call) => contract_code(&mut rng, &keys, params.code_size),
+			(false, _) => bytes(&mut rng, params.code_size),
+		};
+		let account = MemoryAccount {
+			balance: U256::from(rng.next_u64()),
+			nonce: U256::from(rng.below(256)),
+			code,
+			storage,
+		};
+		if i == target_index {
+			target = address;
+		}
+		state.insert(address, account);
+	}
+	(state, target)
+}
+
+/// Filler of the synthetic test of `params`.
+pub fn filler(params: &Params) -> Filler {
+	let (mut state, target) = pre_state(params);
+	state.insert(
+		SENDER.parse().unwrap(),
+		MemoryAccount {
+			balance: U256::exp10(24),
+			nonce: U256::zero(),
+			code: Vec::new(),
+			storage: BTreeMap::new(),
+		},
+	);
+	let (data, value) = match params.template {
+		Template::Transfer => ("0x".to_string(), "0x01"),
+		Template::Call => {
+			let mut rng = Rng::new(params.seed ^ 0x5eed);
+			(format!("0x{}", hex::encode(word(&mut rng))), "0x00")
+		}
+	};
+	Filler {
+		env: json!({
+			"currentCoinbase": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba",
+			"currentDifficulty": "0x020000",
+			"currentGasLimit": format!("{:#x}", GAS_LIMIT * 10),
+			"currentNumber": "0x01",
+			"currentTimestamp": "0x03e8",
+		}),
+		pre: accounts_json(&state),
+		transaction: json!({
+			"data": [data],
+			"gasLimit": [format!("{:#x}", GAS_LIMIT)],
+			"gasPrice": "0x0a",
+			"nonce": "0x00",
+			"secretKey": SECRET_KEY,
+			"to": format!("{:?}", target),
+			"value": [value],
+		}),
+		expect: vec![Expectation {
+			indexes: IndexSelector::default(),
+			network: params.forks.clone(),
+			result: BTreeMap::new(),
+			gas_used: None,
+			expect_exception: BTreeMap::new(),
+		}],
+	}
+}
+
+/// Name of the synthetic test of `params`.
+pub fn name(params: &Params) -> String {
+	format!(
+		"synthetic_{}_{}x{}_{}",
+		params.template.name(),
+		params.accounts,
+		params.slots,
+		params.seed
+	)
+}
+
+/// Fixture file with the filled synthetic test of `params`.
+pub fn generate(params: &Params) -> Result<Value, String> {
+	let name = name(params);
+	let filled = fill(&name, &filler(params))?;
+	let mut fixture = Map::new();
+	fixture.insert(name, filled);
+	Ok(Value::Object(fixture))
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn seeded_states() {
+		let params = Params {
+			accounts: 20,
+			slots: 3,
+			code_size: 100,
+			template: Template::Call,
+			..Default::default()
+		};
+		let (state, target) = pre_state(&params);
+		assert_eq!(state.len(), 20);
+		assert!(state.values().all(|account| account.storage.len() == 3));
+		assert!(state[&target].code.len() > 100);
+		assert_eq!(
+			state
+				.values()
+				.filter(|account| account.code.len() == 100)
+				.count(),
+			19
+		);
+		assert_eq!(pre_state(&params), (state, target));
+		assert_ne!(
+			pre_state(&Params {
+				seed: 1,
+				..params.clone()
+			})
+			.1,
+			target
+		);
+
+		for template in &[Template::Transfer, Template::Call] {
+			let params = Params {
+				template: *template,
+				..params.clone()
+			};
+			let fixture = generate(&params).unwrap();
+			let post = &fixture[name(&params)]["post"]["Berlin"];
+			assert_eq!(post.as_array().unwrap().len(), 1);
+		}
+		assert_eq!("call".parse(), Ok(Template::Call));
+	}
+}
@@ -0,0 +1,102 @@
+//! Semantic tags of tests, from the suite of their fixture file and their
+//! name, to run the slice of the corpus a change touches.
+
+use lazy_static::lazy_static;
+use regex::Regex;
+
+struct Tag {
+	name: &'static str,
+	/// Suites all tests of which have the tag.
+	suites: &'static [&'static str],
+	/// Pattern of the names of tests of other suites with the tag.
+	names: &'static str,
+}
+
+const TAGS: &[Tag] = &[
+	Tag {
+		name: "precompiles",
+		suites: &[
+			"stPreCompiledContracts",
+			"stPreCompiledContracts2",
+			"stZeroKnowledge",
+			"stZeroKnowledge2",
+		],
+		names: r"(?i)ecrecover|sha256|ripemd|identity|modexp|blake2|ecadd|ecmul|ecpairing|precomp",
+	},
+	Tag {
+		name: "create",
+		suites: &[
+			"stCreate2",
+			"stCreateTest",
+			"stInitCodeTest",
+			"stRecursiveCreate",
+			"stCallCreateCallCodeTest",
+		],
+		names: r"(?i)create",
+	},
+	Tag {
+		name: "sstore",
+		suites: &["stSStoreTest", "stStorage", "stRefundTest"],
+		names: r"(?i)sstore|sload",
+	},
+	Tag {
+		name: "calls",
+		suites: &[
+			"stCallCodes",
+			"stCallCreateCallCodeTest",
+			"stCallDelegateCodesCallCodeHomestead",
+			"stCallDelegateCodesHomestead",
+			"stDelegatecallTestHomestead",
+			"stStaticCall",
+			"stStaticFlagEnabled",
+		],
+		names: r"(?i)call",
+	},
+];
+
+lazy_static! {
+	static ref NAME_PATTERNS: Vec<Regex> = TAGS
+		.iter()
+		.map(|tag| Regex::new(tag.names).unwrap())
+		.collect();
+}
+
+/// Names of every tag.
+pub fn names() -> Vec<&'static str> {
+	TAGS.iter().map(|tag| tag.name).collect()
+}
+
+/// Tags of test `name` of a fixture file in `suite`.
+pub fn tags_of(suite: Option<&str>, name: &str) -> Vec<&'static str> {
+	TAGS.iter()
+		.zip(NAME_PATTERNS.iter())
+		.filter(|(tag, pattern)| {
+			suite.map_or(false, |suite| tag.suites.contains(&suite)) || pattern.is_match(name)
+		})
+		.map(|(tag, _)| tag.name)
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn tags() {
+		assert_eq!(
+			tags_of(Some("stPreCompiledContracts2"), "modexp_0_0_0_20500"),
+			["precompiles"]
+		);
+		assert_eq!(
+			tags_of(Some("stCreate2"), "create2collisionStorage"),
+			["create"]
+		);
+		assert_eq!(
+			tags_of(Some("stStaticCall"), "static_CallEcrecover0"),
+			["precompiles", "calls"]
+		);
+		assert_eq!(tags_of(None, "sstore_combinations"), ["sstore"]);
+		assert!(tags_of(Some("stBadOpcode"), "badOpcodes").is_empty());
+		assert_eq!(names().len(), 4);
+	}
+}
@@ -0,0 +1,314 @@
+//! Persisted index of the tests of a fixture directory: where each test is
+//! in its file, and the forks and post-states it has. Runs filtered by
+//! `--test` or `--fork` only read the files the index says have selected
+//! tests, and lookups of a single test only parse that test.
+//!
+//! The index is kept in the directory as `FILE_NAME` and built on the first
+//! run that needs it. Later runs only index again the files whose size or
+//! modification time changed since. Forks filtered out of a whole file are
+//! not counted as skipped, as the file is never read.
+
+use crate::chainspec::ChainSpec;
+use crate::fixtures::{self, is_compressed, json_files};
+use crate::format::{self, Format};
+use serde::de::IgnoredAny;
+use serde::{Deserialize, Serialize};
+use serde_json::value::RawValue;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::{Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Name of the index in the directory it indexes, hidden so that it is
+/// never taken for a fixture.
+pub const FILE_NAME: &str = ".fixture-index.json";
+
+/// Version of the layout of the index; an index of another one is built
+/// again.
+const VERSION: u32 = 1;
+
+/// A test of an indexed file.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct Entry {
+	pub name: String,
+	/// Bytes of the test's JSON value in its file.
+	pub offset: u64,
+	pub len: u64,
+	/// Post-states by fork of a state test, the network of a blockchain
+	/// test with one.
+	pub forks: BTreeMap<String, usize>,
+}
+
+/// An indexed file, as of its size and modification time.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct IndexedFile {
+	pub size: u64,
+	pub modified_ns: u64,
+	pub tests: Vec<Entry>,
+	/// Why the file could not be indexed. Such a file is always read, to
+	/// fail the run as it would without an index.
+	#[serde(default, skip_serializing_if = "Option::is_none")]
+	pub error: Option<String>,
+}
+
+/// Tests and forks a run selects, any if empty.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Filter {
+	pub tests: Vec<String>,
+	pub forks: Vec<String>,
+}
+
+impl Filter {
+	pub fn is_empty(&self) -> bool {
+		self.tests.is_empty() && self.forks.is_empty()
+	}
+
+	/// Whether a fork or blockchain test network is selected, a transition
+	/// network if all its forks are, as the blockchain runner selects it.
+	fn selects_fork(&self, fork: &str) -> bool {
+		self.forks.iter().any(|selected| selected == fork)
+			|| ChainSpec::of_network(fork).map_or(false, |chain| {
+				chain
+					.forks
+					.iter()
+					.all(|activation| self.forks.contains(&activation.fork))
+			})
+	}
+
+	pub fn selects(&self, entry: &Entry) -> bool {
+		(self.tests.is_empty() || self.tests.contains(&entry.name))
+			&& (self.forks.is_empty()
+				|| entry.forks.is_empty()
+				|| entry.forks.keys().any(|fork| self.selects_fork(fork)))
+	}
+}
+
+#[derive(Deserialize)]
+struct StateShape {
+	#[serde(default)]
+	post: BTreeMap<String, Vec<IgnoredAny>>,
+}
+
+#[derive(Deserialize)]
+struct BlockchainShape {
+	#[serde(default)]
+	network: Option<String>,
+}
+
+/// Entries of the tests of the fixture file `contents`.
+pub fn index_contents(contents: &[u8]) -> Result<Vec<Entry>, String> {
+	let tests = serde_json::from_slice::<BTreeMap<String, &RawValue>>(contents)
+		.map_err(|err| err.to_string())?;
+	let format = format::detect(contents);
+	let start = contents.as_ptr() as usize;
+	tests
+		.into_iter()
+		.map(|(name, raw)| {
+			let json = raw.get();
+			let forks = match format {
+				Some(Format::State) => serde_json::from_str::<StateShape>(json)
+					.map_err(|err| format!("{}: {}", name, err))?
+					.post
+					.into_iter()
+					.map(|(fork, states)| (fork, states.len()))
+					.collect(),
+				Some(Format::Blockchain) => serde_json::from_str::<BlockchainShape>(json)
+					.map_err(|err| format!("{}: {}", name, err))?
+					.network
+					.into_iter()
+					.map(|network| (network, 1))
+					.collect(),
+				Some(Format::Vm) | None => BTreeMap::new(),
+			};
+			Ok(Entry {
+				name,
+				offset: (json.as_ptr() as usize - start) as u64,
+				len: json.len() as u64,
+				forks,
+			})
+		})
+		.collect()
+}
+
+/// Size and modification time of `path`, as an index records them.
+fn stamp(path: &Path) -> Option<(u64, u64)> {
+	let metadata = fs::metadata(path).ok()?;
+	let modified = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+	Some((metadata.len(), modified.as_nanos() as u64))
+}
+
+fn index_file(path: &Path) -> IndexedFile {
+	let (size, modified_ns) = stamp(path).unwrap_or_default();
+	let indexed = fixtures::read(path)
+		.map_err(|err| err.to_string())
+		.and_then(|contents| index_contents(&contents));
+	let (tests, error) = match indexed {
+		Ok(tests) => (tests, None),
+		Err(err) => (Vec::new(), Some(err)),
+	};
+	IndexedFile {
+		size,
+		modified_ns,
+		tests,
+		error,
+	}
+}
+
+/// Index of the fixtures of a directory, by path relative to it.
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct TestIndex {
+	version: u32,
+	pub files: BTreeMap<String, IndexedFile>,
+}
+
+impl TestIndex {
+	/// The index kept in `dir`, or an empty one if there is none of this
+	/// version.
+	pub fn load(dir: &Path) -> Self {
+		fs::read(dir.join(FILE_NAME))
+			.ok()
+			.and_then(|contents| serde_json::from_slice::<TestIndex>(&contents).ok())
+			.filter(|index| index.version == VERSION)
+			.unwrap_or_default()
+	}
+
+	pub fn save(&self, dir: &Path) -> Result<(), String> {
+		let contents = serde_json::to_vec(self).map_err(|err| err.to_string())?;
+		fs::write(dir.join(FILE_NAME), contents).map_err(|err| err.to_string())
+	}
+
+	/// Index the files of `dir` added or changed since, and forget the
+	/// removed ones, returning how many were indexed, or one if only some
+	/// were removed.
+	pub fn refresh(&mut self, dir: &Path) -> usize {
+		self.version = VERSION;
+		let mut files = BTreeMap::new();
+		let mut indexed = 0;
+		for path in json_files(dir) {
+			let relative = match path.strip_prefix(dir) {
+				Ok(relative) => relative.display().to_string(),
+				Err(_) => continue,
+			};
+			let file = match self.files.remove(&relative) {
+				Some(file) if stamp(&path) == Some((file.size, file.modified_ns)) => file,
+				_ => {
+					indexed += 1;
+					index_file(&path)
+				}
+			};
+			files.insert(relative, file);
+		}
+		if indexed == 0 && !self.files.is_empty() {
+			indexed = 1;
+		}
+		self.files = files;
+		indexed
+	}
+
+	/// The index of `dir`, brought up to date and saved again if it changed.
+	pub fn open(dir: &Path) -> Self {
+		let mut index = TestIndex::load(dir);
+		if index.refresh(dir) > 0 {
+			if let Err(err) = index.save(dir) {
+				eprintln!("Save fixture index of {} failed: {}", dir.display(), err);
+			}
+		}
+		index
+	}
+
+	/// Files of `dir` with a test `filter` selects, or that could not be
+	/// indexed.
+	pub fn files(&self, dir: &Path, filter: &Filter) -> Vec<PathBuf> {
+		self.files
+			.iter()
+			.filter(|(_, file)| {
+				file.error.is_some() || file.tests.iter().any(|entry| filter.selects(entry))
+			})
+			.map(|(relative, _)| dir.join(relative))
+			.collect()
+	}
+}
+
+/// The fixture file of the single test `name` of `file`, read from where an
+/// up to date index of a directory containing `file` says it is.
+pub fn read_test(file: &Path, name: &str) -> Option<Vec<u8>> {
+	let file = file.canonicalize().ok()?;
+	let (dir, index) = file
+		.ancestors()
+		.skip(1)
+		.find(|dir| dir.join(FILE_NAME).is_file())
+		.map(|dir| (dir, TestIndex::load(dir)))?;
+	let indexed = index
+		.files
+		.get(&file.strip_prefix(dir).ok()?.display().to_string())?;
+	if stamp(&file) != Some((indexed.size, indexed.modified_ns)) {
+		return None;
+	}
+	let entry = indexed.tests.iter().find(|entry| entry.name == name)?;
+	let (start, end) = (entry.offset as usize, (entry.offset + entry.len) as usize);
+	// Offsets of compressed files are into their decompressed contents.
+	let test = if is_compressed(&file) {
+		fixtures::read(&file).ok()?.get(start..end)?.to_vec()
+	} else {
+		let mut test = vec![0; entry.len as usize];
+		let mut reader = File::open(&file).ok()?;
+		reader.seek(SeekFrom::Start(entry.offset)).ok()?;
+		reader.read_exact(&mut test).ok()?;
+		test
+	};
+	let mut contents = serde_json::to_vec(name).ok()?;
+	contents.insert(0, b'{');
+	contents.push(b':');
+	contents.extend(test);
+	contents.push(b'}');
+	Some(contents)
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::process;
+
+	const STATE: &str = r#"{
+		"add": { "env": {}, "pre": {}, "transaction": {}, "post": { "Berlin": [{}, {}], "London": [{}] } },
+		"sub": { "env": {}, "pre": {}, "transaction": {}, "post": { "Istanbul": [{}] } }
+	}"#;
+
+	#[test]
+	fn filtered_files() {
+		let entries = index_contents(STATE.as_bytes()).unwrap();
+		assert_eq!(entries[0].name, "add");
+		assert_eq!(entries[0].forks["Berlin"], 2);
+		let start = entries[1].offset as usize;
+		assert!(STATE[start..start + entries[1].len as usize].starts_with("{ \"env\""));
+
+		let dir = std::env::temp_dir().join(format!("evm-jsontests-index-{}", process::id()));
+		fs::create_dir_all(dir.join("stExample")).unwrap();
+		fs::write(dir.join("stExample/add.json"), STATE).unwrap();
+		fs::write(dir.join("broken.json"), "{").unwrap();
+
+		let index = TestIndex::open(&dir);
+		assert!(dir.join(FILE_NAME).is_file());
+		assert_eq!(TestIndex::load(&dir), index);
+		assert_eq!(TestIndex::load(&dir).refresh(&dir), 0);
+
+		let filter = |tests: &[&str], forks: &[&str]| Filter {
+			tests: tests.iter().map(|test| test.to_string()).collect(),
+			forks: forks.iter().map(|fork| fork.to_string()).collect(),
+		};
+		assert_eq!(index.files(&dir, &filter(&["add"], &[])).len(), 2);
+		assert_eq!(
+			index.files(&dir, &filter(&["add"], &["Istanbul"])),
+			vec![dir.join("broken.json")]
+		);
+		assert_eq!(index.files(&dir, &filter(&["sub"], &["Istanbul"])).len(), 2);
+
+		let test = read_test(&dir.join("stExample/add.json"), "sub").unwrap();
+		let test = serde_json::from_slice::<BTreeMap<String, serde_json::Value>>(&test).unwrap();
+		assert!(test["sub"]["post"]["Istanbul"].is_array());
+		assert_eq!(read_test(&dir.join("stExample/add.json"), "mul"), None);
+
+		fs::remove_dir_all(&dir).unwrap();
+	}
+}
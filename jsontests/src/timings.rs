@@ -0,0 +1,256 @@
+//! Wall times of state test indexes over the last runs, kept in a JSON lines
+//! file every run appends to, to spot tests that got slower.
+
+use lazy_static::lazy_static;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Runs kept in a timings file, older ones are dropped.
+pub const KEPT_RUNS: usize = 20;
+/// How many times slower than in the previous run an index must get to be
+/// flagged.
+pub const SLOWDOWN: f64 = 2.0;
+
+lazy_static! {
+	/// Start of this run in milliseconds since the epoch, telling its
+	/// records apart from the ones of other runs.
+	static ref RUN: u64 = SystemTime::now()
+		.duration_since(UNIX_EPOCH)
+		.map(|since| since.as_millis() as u64)
+		.unwrap_or_default();
+}
+
+/// Id of this run in timings files. Taken before tests run in child
+/// processes, so that they share it.
+pub fn run_id() -> u64 {
+	*RUN
+}
+
+/// Wall time of an index in one run.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct Timing {
+	pub run: u64,
+	/// `name:fork:index` of the index.
+	pub id: String,
+	pub time_us: u64,
+}
+
+impl Timing {
+	/// Append the record as a single JSON line to the file at `path`.
+	pub fn append_to(&self, path: &Path) -> io::Result<()> {
+		let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+		let line = serde_json::to_string(self)?;
+		writeln!(file, "{}", line)
+	}
+}
+
+/// An index that got slower since the previous run it ran in.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Slowdown {
+	pub id: String,
+	pub before_us: u64,
+	pub after_us: u64,
+}
+
+impl Slowdown {
+	pub fn factor(&self) -> f64 {
+		self.after_us as f64 / self.before_us.max(1) as f64
+	}
+}
+
+impl fmt::Display for Slowdown {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"{}: {}us -> {}us ({:.1}x)",
+			self.id,
+			self.before_us,
+			self.after_us,
+			self.factor()
+		)
+	}
+}
+
+/// Times of every index by run, oldest run first.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Timings {
+	pub runs: BTreeMap<u64, BTreeMap<String, u64>>,
+}
+
+impl Timings {
+	pub fn from_records(records: &[Timing]) -> Self {
+		let mut runs = BTreeMap::<u64, BTreeMap<String, u64>>::new();
+		for record in records {
+			runs.entry(record.run)
+				.or_default()
+				.insert(record.id.clone(), record.time_us);
+		}
+		Timings { runs }
+	}
+
+	/// Timings of the file at `path`, none if it does not exist yet.
+	pub fn load(path: &Path) -> Result<Self, String> {
+		let text = match fs::read_to_string(path) {
+			Ok(text) => text,
+			Err(err) if err.kind() == io::ErrorKind::NotFound => String::new(),
+			Err(err) => return Err(format!("{}: {}", path.display(), err)),
+		};
+		let records = text
+			.lines()
+			.filter(|line| !line.trim().is_empty())
+			.map(serde_json::from_str)
+			.collect::<Result<Vec<Timing>, _>>()
+			.map_err(|err| format!("{}: {}", path.display(), err))?;
+		Ok(Self::from_records(&records))
+	}
+
+	/// Rewrite the file at `path` with the last `runs` runs only.
+	pub fn truncate(path: &Path, runs: usize) -> Result<(), String> {
+		let mut timings = Self::load(path)?;
+		while timings.runs.len() > runs {
+			let oldest = *timings.runs.keys().next().unwrap();
+			timings.runs.remove(&oldest);
+		}
+		let mut text = String::new();
+		for (run, times) in &timings.runs {
+			for (id, time_us) in times {
+				let record = Timing {
+					run: *run,
+					id: id.clone(),
+					time_us: *time_us,
+				};
+				text += &serde_json::to_string(&record).unwrap();
+				text.push('\n');
+			}
+		}
+		fs::write(path, text).map_err(|err| format!("{}: {}", path.display(), err))
+	}
+
+	/// Times of `id` in the runs it ran in, oldest first.
+	pub fn trend(&self, id: &str) -> Vec<u64> {
+		self.runs
+			.values()
+			.filter_map(|times| times.get(id).copied())
+			.collect()
+	}
+
+	/// Indexes of the last run that took more than `factor` times as long
+	/// as in the previous run they ran in, the worst first.
+	pub fn slowdowns(&self, factor: f64) -> Vec<Slowdown> {
+		let latest = match self.runs.values().next_back() {
+			Some(latest) => latest,
+			None => return Vec::new(),
+		};
+		let mut slowdowns = latest
+			.iter()
+			.filter_map(|(id, after_us)| {
+				let trend = self.trend(id);
+				let before_us = *trend.iter().rev().nth(1)?;
+				let slowdown = Slowdown {
+					id: id.clone(),
+					before_us,
+					after_us: *after_us,
+				};
+				Some(slowdown).filter(|slowdown| slowdown.factor() > factor)
+			})
+			.collect::<Vec<_>>();
+		slowdowns.sort_by(|a, b| b.factor().partial_cmp(&a.factor()).unwrap());
+		slowdowns
+	}
+
+	/// The `count` slowest indexes of the last run.
+	pub fn slowest(&self, count: usize) -> Vec<(&str, u64)> {
+		let mut slowest = self
+			.runs
+			.values()
+			.next_back()
+			.map(|latest| {
+				latest
+					.iter()
+					.map(|(id, time_us)| (id.as_str(), *time_us))
+					.collect::<Vec<_>>()
+			})
+			.unwrap_or_default();
+		slowest.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+		slowest.truncate(count);
+		slowest
+	}
+
+	/// The slowest indexes of the last run with their times in the earlier
+	/// runs, and the indexes that got more than [`SLOWDOWN`] times slower.
+	pub fn report(&self, count: usize) -> String {
+		let mut report = format!(
+			"Slowest indexes of the last of {} runs, with their times in earlier runs:\n",
+			self.runs.len()
+		);
+		for (id, _) in self.slowest(count) {
+			let trend = self
+				.trend(id)
+				.iter()
+				.map(|time_us| format!("{}us", time_us))
+				.collect::<Vec<_>>();
+			report += &format!("  {}: {}\n", id, trend.join(" -> "));
+		}
+		let slowdowns = self.slowdowns(SLOWDOWN);
+		if !slowdowns.is_empty() {
+			report += &format!(
+				"{} indexes more than {}x slower than in the previous run:\n",
+				slowdowns.len(),
+				SLOWDOWN
+			);
+			for slowdown in slowdowns {
+				report += &format!("  SLOWER: {}\n", slowdown);
+			}
+		}
+		report
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn timing(run: u64, id: &str, time_us: u64) -> Timing {
+		Timing {
+			run,
+			id: id.to_string(),
+			time_us,
+		}
+	}
+
+	#[test]
+	fn slowdowns_since_previous_run() {
+		let timings = Timings::from_records(&[
+			timing(1, "a:Berlin:0", 100),
+			timing(1, "b:Berlin:0", 100),
+			timing(2, "a:Berlin:0", 150),
+			timing(3, "a:Berlin:0", 400),
+			timing(3, "b:Berlin:0", 210),
+			timing(3, "c:Berlin:0", 900),
+		]);
+		assert_eq!(timings.trend("a:Berlin:0"), [100, 150, 400]);
+		assert_eq!(
+			timings.slowest(2),
+			[("c:Berlin:0", 900), ("a:Berlin:0", 400)]
+		);
+		// `c` has no earlier time, `b` is compared with the run it last ran in.
+		let slowdowns = timings.slowdowns(SLOWDOWN);
+		assert_eq!(
+			slowdowns
+				.iter()
+				.map(|slowdown| slowdown.id.as_str())
+				.collect::<Vec<_>>(),
+			["a:Berlin:0", "b:Berlin:0"]
+		);
+		assert_eq!(slowdowns[0].before_us, 150);
+		assert!(timings
+			.report(1)
+			.contains("SLOWER: b:Berlin:0: 100us -> 210us (2.1x)"));
+	}
+}
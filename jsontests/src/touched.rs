@@ -0,0 +1,143 @@
+//! Oracle for the accounts a transaction touches: from Spurious Dragon on,
+//! EIP-161 (EIP-158 in fixture names) deletes the ones it leaves empty,
+//! before it they stay. Getting this wrong is the most common source of
+//! opaque root mismatches in the stEIP158 and zero-value call suites, so
+//! their failures print every touched account.
+
+use evm::backend::MemoryAccount;
+use primitive_types::H160;
+use std::fmt::{self, Write};
+
+/// Suites whose root mismatches print the touched accounts.
+pub const TOUCHED_SUITES: &[&str] = &["stEIP158Specific", "stZeroCallsTest", "stZeroCallsRevert"];
+
+/// Whether an account is empty as EIP-161 defines it: no nonce, balance or
+/// code.
+pub fn is_empty(account: &MemoryAccount) -> bool {
+	account.nonce.is_zero() && account.balance.is_zero() && account.code.is_empty()
+}
+
+/// Whether an account exists and is empty.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Existence {
+	Absent,
+	Empty,
+	NonEmpty,
+}
+
+impl Existence {
+	pub fn of(account: Option<&MemoryAccount>) -> Self {
+		match account {
+			None => Existence::Absent,
+			Some(account) if is_empty(account) => Existence::Empty,
+			Some(_) => Existence::NonEmpty,
+		}
+	}
+}
+
+impl fmt::Display for Existence {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			Existence::Absent => "absent",
+			Existence::Empty => "empty",
+			Existence::NonEmpty => "non-empty",
+		})
+	}
+}
+
+/// An account a transaction touched, before and after it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Touched {
+	pub address: H160,
+	pub pre: Existence,
+	pub post: Existence,
+}
+
+/// Check the touched accounts against the rule of a fork that deletes
+/// empty ones if `deletes_empty`. Before EIP-161, only the accounts in
+/// `deleted`, which then are self-destructed ones, may disappear.
+pub fn check(touched: &[Touched], deletes_empty: bool, deleted: &[H160]) -> Result<(), String> {
+	for account in touched {
+		if deletes_empty && account.post == Existence::Empty {
+			return Err(format!(
+				"Touched empty account {:?} not deleted by EIP-161",
+				account.address
+			));
+		}
+		if !deletes_empty
+			&& account.pre != Existence::Absent
+			&& account.post == Existence::Absent
+			&& !deleted.contains(&account.address)
+		{
+			return Err(format!(
+				"Touched {} account {:?} deleted before EIP-161",
+				account.pre, account.address
+			));
+		}
+	}
+	Ok(())
+}
+
+/// Line by line rendering of the touched accounts, the empty ones marked.
+pub fn report(touched: &[Touched]) -> String {
+	let mut out = String::new();
+	for account in touched {
+		let marker = if account.pre == Existence::Empty || account.post == Existence::Empty {
+			"*"
+		} else {
+			" "
+		};
+		writeln!(
+			out,
+			" {} {:?}: {} -> {}",
+			marker, account.address, account.pre, account.post
+		)
+		.unwrap();
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use primitive_types::U256;
+	use std::collections::BTreeMap;
+
+	#[test]
+	fn deletion_by_fork() {
+		let empty = MemoryAccount {
+			nonce: U256::zero(),
+			balance: U256::zero(),
+			storage: BTreeMap::new(),
+			code: Vec::new(),
+		};
+		let funded = MemoryAccount {
+			balance: U256::one(),
+			..empty.clone()
+		};
+		assert_eq!(Existence::of(Some(&empty)), Existence::Empty);
+		assert_eq!(Existence::of(Some(&funded)), Existence::NonEmpty);
+
+		let address = H160::from_low_u64_be(1);
+		let kept = Touched {
+			address,
+			pre: Existence::Empty,
+			post: Existence::Empty,
+		};
+		let removed = Touched {
+			post: Existence::Absent,
+			..kept
+		};
+		assert_eq!(check(&[kept], false, &[]), Ok(()));
+		assert!(check(&[kept], true, &[]).is_err());
+		assert_eq!(check(&[removed], true, &[]), Ok(()));
+		assert!(check(&[removed], false, &[]).is_err());
+		// Self-destructed accounts go before EIP-161 too.
+		assert_eq!(check(&[removed], false, &[address]), Ok(()));
+
+		assert_eq!(
+			report(&[removed]),
+			format!(" * {:?}: empty -> absent\n", address)
+		);
+	}
+}
@@ -0,0 +1,317 @@
+use evm::{ExitError, ExitReason};
+use evm_gasometer::tracing::Event as GasEvent;
+use evm_gasometer::Snapshot;
+use std::any::Any;
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Receiver of the executor, interpreter and gasometer event streams emitted
+/// while a transaction runs.
+pub trait Tracer {
+	fn evm_event(&mut self, _event: evm::tracing::Event) {}
+	fn runtime_event(&mut self, _event: evm_runtime::tracing::Event) {}
+	fn gas_event(&mut self, _event: evm_gasometer::tracing::Event) {}
+}
+
+impl Tracer for () {}
+
+/// Number of interpreter steps executed, across every frame.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct StepCounter {
+	pub steps: u64,
+}
+
+impl Tracer for StepCounter {
+	fn runtime_event(&mut self, event: evm_runtime::tracing::Event) {
+		if let evm_runtime::tracing::Event::Step { .. } = event {
+			self.steps += 1;
+		}
+	}
+}
+
+/// Start of the panic message of a transaction stopped by the step limit.
+pub const STEP_LIMIT_EXCEEDED: &str = "Step limit exceeded";
+
+/// Steps a single transaction may take, unlimited if zero.
+static MAX_STEPS: AtomicU64 = AtomicU64::new(0);
+
+/// Stop transactions after `limit` interpreter steps from now on, or never
+/// if `None`.
+pub fn set_max_steps(limit: Option<u64>) {
+	MAX_STEPS.store(limit.unwrap_or(0), Ordering::SeqCst);
+}
+
+/// Panics once a transaction takes more steps than the limit in use. Unlike
+/// a timeout, where it stops is the same on every machine.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StepLimit {
+	limit: Option<u64>,
+	steps: u64,
+}
+
+impl StepLimit {
+	/// The limit set with [`set_max_steps`].
+	pub fn current() -> Self {
+		let limit = MAX_STEPS.load(Ordering::Relaxed);
+		StepLimit {
+			limit: if limit == 0 { None } else { Some(limit) },
+			steps: 0,
+		}
+	}
+}
+
+impl Tracer for StepLimit {
+	fn runtime_event(&mut self, event: evm_runtime::tracing::Event) {
+		if let evm_runtime::tracing::Event::Step { .. } = event {
+			self.steps += 1;
+			if let Some(limit) = self.limit.filter(|limit| self.steps > *limit) {
+				panic!("{} after {} steps", STEP_LIMIT_EXCEEDED, limit);
+			}
+		}
+	}
+}
+
+/// Whether the panic with `payload` stopped a transaction at the step limit.
+pub fn is_step_limit(payload: &(dyn Any + Send)) -> bool {
+	let message = match payload.downcast_ref::<String>() {
+		Some(message) => message.as_str(),
+		None => payload.downcast_ref::<&str>().copied().unwrap_or_default(),
+	};
+	message.starts_with(STEP_LIMIT_EXCEEDED)
+}
+
+impl<T: Tracer> Tracer for Option<T> {
+	fn evm_event(&mut self, event: evm::tracing::Event) {
+		if let Some(tracer) = self {
+			tracer.evm_event(event);
+		}
+	}
+
+	fn runtime_event(&mut self, event: evm_runtime::tracing::Event) {
+		if let Some(tracer) = self {
+			tracer.runtime_event(event);
+		}
+	}
+
+	fn gas_event(&mut self, event: evm_gasometer::tracing::Event) {
+		if let Some(tracer) = self {
+			tracer.gas_event(event);
+		}
+	}
+}
+
+impl<A: Tracer, B: Tracer> Tracer for (A, B) {
+	fn evm_event(&mut self, event: evm::tracing::Event) {
+		self.0.evm_event(event);
+		self.1.evm_event(event);
+	}
+
+	fn runtime_event(&mut self, event: evm_runtime::tracing::Event) {
+		self.0.runtime_event(event);
+		self.1.runtime_event(event);
+	}
+
+	fn gas_event(&mut self, event: evm_gasometer::tracing::Event) {
+		self.0.gas_event(event);
+		self.1.gas_event(event);
+	}
+}
+
+/// Gasometer state attached to a gas event, if the gasometer has not
+/// already run out of gas.
+pub fn gas_snapshot(event: &GasEvent) -> Option<Snapshot> {
+	match *event {
+		GasEvent::RecordCost { snapshot, .. }
+		| GasEvent::RecordRefund { snapshot, .. }
+		| GasEvent::RecordStipend { snapshot, .. }
+		| GasEvent::RecordDynamicCost { snapshot, .. }
+		| GasEvent::RecordTransaction { snapshot, .. } => snapshot,
+	}
+}
+
+/// Gasometer state once the charge, refund or stipend of a gas event is
+/// recorded, where the event carries the state before it.
+pub fn gas_snapshot_after(event: &GasEvent) -> Option<Snapshot> {
+	let mut snapshot = gas_snapshot(event)?;
+	match *event {
+		GasEvent::RecordCost { cost, .. } | GasEvent::RecordTransaction { cost, .. } => {
+			snapshot.used_gas = snapshot.used_gas.saturating_add(cost);
+		}
+		GasEvent::RecordRefund { refund, .. } => snapshot.refunded_gas += refund,
+		GasEvent::RecordStipend { stipend, .. } => {
+			snapshot.used_gas = snapshot.used_gas.saturating_sub(stipend);
+		}
+		GasEvent::RecordDynamicCost {
+			gas_cost,
+			memory_gas,
+			gas_refund,
+			..
+		} => {
+			snapshot.used_gas = snapshot.used_gas.saturating_add(gas_cost);
+			snapshot.memory_gas = memory_gas;
+			snapshot.refunded_gas += gas_refund;
+		}
+	}
+	Some(snapshot)
+}
+
+/// Gas remaining according to a gasometer snapshot.
+pub fn gas_left(snapshot: &Snapshot) -> u64 {
+	snapshot
+		.gas_limit
+		.saturating_sub(snapshot.used_gas)
+		.saturating_sub(snapshot.memory_gas)
+}
+
+/// Geth-style error message of a failed exit, `None` on success.
+pub fn exit_error(reason: &ExitReason) -> Option<String> {
+	match reason {
+		ExitReason::Succeed(_) => None,
+		ExitReason::Revert(_) => Some("execution reverted".into()),
+		ExitReason::Error(ExitError::OutOfGas) => Some("out of gas".into()),
+		ExitReason::Error(ExitError::StackUnderflow) => Some("stack underflow".into()),
+		ExitReason::Error(ExitError::StackOverflow) => Some("stack limit reached".into()),
+		ExitReason::Error(ExitError::InvalidJump) => Some("invalid jump destination".into()),
+		ExitReason::Error(err) => Some(format!("{:?}", err)),
+		ExitReason::Fatal(err) => Some(format!("{:?}", err)),
+	}
+}
+
+struct EvmListener<T>(Rc<RefCell<T>>);
+
+impl<T: Tracer> evm::tracing::EventListener for EvmListener<T> {
+	fn event(&mut self, event: evm::tracing::Event) {
+		self.0.borrow_mut().evm_event(event);
+	}
+}
+
+struct RuntimeListener<T>(Rc<RefCell<T>>);
+
+impl<T: Tracer> evm_runtime::tracing::EventListener for RuntimeListener<T> {
+	fn event(&mut self, event: evm_runtime::tracing::Event) {
+		self.0.borrow_mut().runtime_event(event);
+	}
+}
+
+struct GasListener<T>(Rc<RefCell<T>>);
+
+impl<T: Tracer> evm_gasometer::tracing::EventListener for GasListener<T> {
+	fn event(&mut self, event: evm_gasometer::tracing::Event) {
+		self.0.borrow_mut().gas_event(event);
+	}
+}
+
+/// Run `f` with `tracer` receiving all events it triggers, and hand the
+/// tracer back together with the result.
+pub fn traced<T: Tracer + 'static, R, F: FnOnce() -> R>(tracer: T, f: F) -> (T, R) {
+	let tracer = Rc::new(RefCell::new(tracer));
+	let mut evm_listener = EvmListener(tracer.clone());
+	let mut runtime_listener = RuntimeListener(tracer.clone());
+	let mut gas_listener = GasListener(tracer.clone());
+
+	let result = evm::tracing::using(&mut evm_listener, || {
+		evm_runtime::tracing::using(&mut runtime_listener, || {
+			evm_gasometer::tracing::using(&mut gas_listener, f)
+		})
+	});
+
+	drop((evm_listener, runtime_listener, gas_listener));
+	let tracer = match Rc::try_unwrap(tracer) {
+		Ok(tracer) => tracer.into_inner(),
+		Err(_) => unreachable!("all listeners are dropped"),
+	};
+
+	(tracer, result)
+}
+
+/// Transactions for the tracers' tests to trace.
+#[cfg(test)]
+pub mod test_helpers {
+	use super::{traced, Tracer};
+	use evm::backend::{MemoryAccount, MemoryBackend, MemoryVicinity};
+	use evm::executor::{
+		MemoryStackState, PrecompileFailure, PrecompileFn, PrecompileOutput, StackExecutor,
+		StackSubstateMetadata,
+	};
+	use evm::{Config, Context, ExitError, ExitReason, ExitSucceed};
+	use primitive_types::{H160, U256};
+	use std::collections::BTreeMap;
+
+	/// Account of nonce one holding the hex `code`.
+	pub fn account(code: &str) -> MemoryAccount {
+		MemoryAccount {
+			nonce: U256::one(),
+			balance: U256::zero(),
+			storage: BTreeMap::new(),
+			code: hex::decode(code).unwrap(),
+		}
+	}
+
+	fn identity(
+		input: &[u8],
+		gas_limit: Option<u64>,
+		_: &Context,
+		_: bool,
+	) -> Result<PrecompileOutput, PrecompileFailure> {
+		let cost = 15 + 3 * (input.len() as u64).div_ceil(32);
+		if gas_limit.map_or(false, |limit| cost > limit) {
+			return Err(PrecompileFailure::Error {
+				exit_status: ExitError::OutOfGas,
+			});
+		}
+		Ok(PrecompileOutput {
+			exit_status: ExitSucceed::Returned,
+			cost,
+			output: input.to_vec(),
+			logs: Vec::new(),
+		})
+	}
+
+	/// A traced transaction and what it returned.
+	pub struct Execution<T> {
+		pub tracer: T,
+		pub exit_reason: ExitReason,
+		pub output: Vec<u8>,
+		pub used_gas: u64,
+	}
+
+	/// Call `to` in `state` on Berlin, from 0x3000 with a gas limit of one
+	/// million and no gas price, with `tracer` receiving the events. Only the
+	/// identity precompile is available.
+	pub fn call<T: Tracer + 'static>(
+		state: BTreeMap<H160, MemoryAccount>,
+		to: H160,
+		tracer: T,
+	) -> Execution<T> {
+		let caller = H160::from_low_u64_be(0x3000);
+		let gas_limit = 1_000_000;
+		let vicinity = MemoryVicinity {
+			gas_price: U256::zero(),
+			origin: caller,
+			chain_id: U256::one(),
+			block_hashes: Vec::new(),
+			block_number: U256::zero(),
+			block_coinbase: H160::zero(),
+			block_timestamp: U256::zero(),
+			block_difficulty: U256::zero(),
+			block_gas_limit: U256::from(gas_limit),
+		};
+		let config = Config::berlin();
+		let mut precompiles = BTreeMap::new();
+		precompiles.insert(H160::from_low_u64_be(4), identity as PrecompileFn);
+		let backend = MemoryBackend::new(&vicinity, state);
+		let metadata = StackSubstateMetadata::new(gas_limit, &config);
+		let state = MemoryStackState::new(metadata, &backend);
+		let mut executor = StackExecutor::new_with_precompiles(state, &config, &precompiles);
+		let (tracer, (exit_reason, output)) = traced(tracer, || {
+			executor.transact_call(caller, to, U256::zero(), Vec::new(), gas_limit, Vec::new())
+		});
+		Execution {
+			tracer,
+			exit_reason,
+			output,
+			used_gas: executor.used_gas(),
+		}
+	}
+}
@@ -0,0 +1,266 @@
+use crate::opcodes;
+use primitive_types::U256;
+use serde::de::{self, Deserializer};
+use serde::Deserialize;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::Path;
+
+fn quantity<'de, D: Deserializer<'de>>(deserializer: D) -> Result<u64, D::Error> {
+	#[derive(Deserialize)]
+	#[serde(untagged)]
+	enum Quantity {
+		Number(u64),
+		Hex(String),
+	}
+
+	match Quantity::deserialize(deserializer)? {
+		Quantity::Number(value) => Ok(value),
+		Quantity::Hex(value) => u64::from_str_radix(value.trim_start_matches("0x"), 16)
+			.map_err(|_| de::Error::custom(format!("invalid quantity: {}", value))),
+	}
+}
+
+fn stack<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<U256>, D::Error> {
+	Vec::<String>::deserialize(deserializer)?
+		.into_iter()
+		.map(|item| {
+			U256::from_str_radix(item.trim_start_matches("0x"), 16)
+				.map_err(|_| de::Error::custom(format!("invalid stack item: {}", item)))
+		})
+		.collect()
+}
+
+/// A single step line of an EIP-3155 trace.
+#[derive(Deserialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct TraceStep {
+	pub pc: usize,
+	pub op: u8,
+	#[serde(deserialize_with = "quantity")]
+	pub gas: u64,
+	#[serde(deserialize_with = "quantity")]
+	pub gas_cost: u64,
+	pub depth: usize,
+	#[serde(deserialize_with = "stack")]
+	pub stack: Vec<U256>,
+	#[serde(default)]
+	pub error: Option<String>,
+}
+
+impl TraceStep {
+	fn render(&self, out: &mut String) {
+		let top = self
+			.stack
+			.iter()
+			.rev()
+			.take(4)
+			.map(|item| format!("{:#x}", item))
+			.collect::<Vec<_>>();
+		writeln!(
+			out,
+			"pc {:>5} {:<14} gas {:>10} cost {:>6} depth {} stack top [{}]{}",
+			self.pc,
+			opcodes::display_name(self.op),
+			self.gas,
+			self.gas_cost,
+			self.depth,
+			top.join(", "),
+			self.error
+				.as_ref()
+				.map(|error| format!(" error: {}", error))
+				.unwrap_or_default(),
+		)
+		.unwrap();
+	}
+}
+
+/// Parse the steps of an EIP-3155 trace, skipping summary and other
+/// non-step lines.
+pub fn parse_trace(trace: &str) -> Result<Vec<TraceStep>, serde_json::Error> {
+	let mut steps = Vec::new();
+	for line in trace.lines() {
+		let line = line.trim();
+		if !line.starts_with('{') {
+			continue;
+		}
+		let value: serde_json::Value = serde_json::from_str(line)?;
+		if value.get("pc").is_none() {
+			continue;
+		}
+		steps.push(serde_json::from_value(value)?);
+	}
+	Ok(steps)
+}
+
+pub fn load_trace(path: &Path) -> io::Result<Vec<TraceStep>> {
+	let trace = fs::read_to_string(path)?;
+	parse_trace(&trace).map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))
+}
+
+/// First step at which two traces disagree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+	pub index: usize,
+	/// Fields that differ, empty if one trace ended early.
+	pub fields: Vec<&'static str>,
+}
+
+fn differing_fields(reference: &TraceStep, ours: &TraceStep) -> Vec<&'static str> {
+	let mut fields = Vec::new();
+	if reference.pc != ours.pc {
+		fields.push("pc");
+	}
+	if reference.op != ours.op {
+		fields.push("op");
+	}
+	if reference.depth != ours.depth {
+		fields.push("depth");
+	}
+	if reference.gas != ours.gas {
+		fields.push("gas");
+	}
+	if reference.gas_cost != ours.gas_cost {
+		fields.push("gasCost");
+	}
+	if reference.stack != ours.stack {
+		fields.push("stack");
+	}
+	fields
+}
+
+pub fn first_divergence(reference: &[TraceStep], ours: &[TraceStep]) -> Option<Divergence> {
+	for (index, (a, b)) in reference.iter().zip(ours).enumerate() {
+		let fields = differing_fields(a, b);
+		if !fields.is_empty() {
+			return Some(Divergence { index, fields });
+		}
+	}
+	if reference.len() != ours.len() {
+		return Some(Divergence {
+			index: reference.len().min(ours.len()),
+			fields: Vec::new(),
+		});
+	}
+	None
+}
+
+/// Number of the first line, from 1, where two traces are not byte for byte
+/// the same, with that line of each, `None` if they are identical.
+pub fn first_differing_line<'a>(
+	first: &'a str,
+	second: &'a str,
+) -> Option<(usize, &'a str, &'a str)> {
+	if first == second {
+		return None;
+	}
+	let mut first = first.lines();
+	let mut second = second.lines();
+	let mut number = 1;
+	loop {
+		match (first.next(), second.next()) {
+			(Some(a), Some(b)) if a == b => number += 1,
+			(a, b) => {
+				let end = "<end of trace>";
+				return Some((number, a.unwrap_or(end), b.unwrap_or(end)));
+			}
+		}
+	}
+}
+
+/// Describe the divergence together with the `context` steps preceding it.
+pub fn render_divergence(
+	reference: &[TraceStep],
+	ours: &[TraceStep],
+	divergence: &Divergence,
+	context: usize,
+) -> String {
+	let mut out = String::new();
+	let index = divergence.index;
+	if divergence.fields.is_empty() {
+		writeln!(
+			out,
+			"Traces diverge at step {}: reference has {} steps, ours has {}",
+			index,
+			reference.len(),
+			ours.len()
+		)
+		.unwrap();
+	} else {
+		writeln!(
+			out,
+			"Traces diverge at step {} in {}",
+			index,
+			divergence.fields.join(", ")
+		)
+		.unwrap();
+	}
+
+	for step in &ours[index.saturating_sub(context)..index.min(ours.len())] {
+		out.push_str("            ");
+		step.render(&mut out);
+	}
+	for (label, steps) in &[("reference:  ", reference), ("ours:       ", ours)] {
+		out.push_str(label);
+		match steps.get(index) {
+			Some(step) => step.render(&mut out),
+			None => out.push_str("<end of trace>\n"),
+		}
+	}
+	out
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	const TRACE: &str = r#"{"pc":0,"op":96,"gas":"0x79bc18","gasCost":"0x3","memSize":0,"stack":[],"depth":1,"refund":0,"opName":"PUSH1"}
+{"pc":2,"op":96,"gas":"0x79bc15","gasCost":"0x3","memSize":0,"stack":["0x1"],"depth":1,"refund":0,"opName":"PUSH1"}
+{"output":"","gasUsed":"0x6"}"#;
+
+	#[test]
+	fn differing_lines() {
+		assert_eq!(first_differing_line(TRACE, TRACE), None);
+		let shorter = TRACE.lines().take(2).collect::<Vec<_>>().join("\n");
+		assert_eq!(
+			first_differing_line(TRACE, &shorter),
+			Some((3, r#"{"output":"","gasUsed":"0x6"}"#, "<end of trace>"))
+		);
+		let other_gas = TRACE.replace("0x79bc15", "0x79bc14");
+		assert_eq!(first_differing_line(TRACE, &other_gas).unwrap().0, 2);
+	}
+
+	#[test]
+	fn parse_skips_summary() {
+		let steps = parse_trace(TRACE).unwrap();
+		assert_eq!(steps.len(), 2);
+		assert_eq!(steps[1].gas, 0x79bc15);
+		assert_eq!(steps[1].stack, vec![U256::one()]);
+	}
+
+	#[test]
+	fn divergence() {
+		let reference = parse_trace(TRACE).unwrap();
+		let mut ours = reference.clone();
+		assert_eq!(first_divergence(&reference, &ours), None);
+
+		ours[1].stack[0] = U256::from(2);
+		assert_eq!(
+			first_divergence(&reference, &ours),
+			Some(Divergence {
+				index: 1,
+				fields: vec!["stack"]
+			})
+		);
+
+		ours.truncate(1);
+		assert_eq!(
+			first_divergence(&reference, &ours),
+			Some(Divergence {
+				index: 1,
+				fields: Vec::new()
+			})
+		);
+	}
+}
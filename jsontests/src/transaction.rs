@@ -0,0 +1,325 @@
+use crate::state::Transaction;
+use crate::utils::u256_to_h256;
+pub use crate::utils::{create2_address, create_address};
+use libsecp256k1::{Message, PublicKey, RecoveryId, SecretKey, Signature};
+use primitive_types::{H160, H256, U256};
+use rlp::{DecoderError, Rlp, RlpStream};
+use sha3::{Digest, Keccak256};
+use std::fmt;
+
+/// Type byte of an EIP-2930 access-list transaction envelope.
+pub const ACCESS_LIST_TYPE: u8 = 0x01;
+
+/// Why no sender can be recovered from a signature.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SignatureError {
+	/// `v` encodes no recovery id.
+	InvalidV,
+	/// `r` or `s` is zero or not below the curve order.
+	OutOfRange,
+	/// No public key recovers from the signature.
+	Unrecoverable,
+	/// The secret key of a state test is not a valid private key.
+	InvalidSecret,
+}
+
+impl fmt::Display for SignatureError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		f.write_str(match self {
+			SignatureError::InvalidV => "invalid signature v",
+			SignatureError::OutOfRange => "signature r or s out of range",
+			SignatureError::Unrecoverable => "unrecoverable signature",
+			SignatureError::InvalidSecret => "invalid secret key",
+		})
+	}
+}
+
+/// A signed legacy or EIP-2930 transaction, as found in raw transaction RLP.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SignedTransaction {
+	/// EIP-2718 transaction type, `None` for legacy transactions.
+	pub tx_type: Option<u8>,
+	/// Chain id of the signature, `None` for pre-EIP-155 legacy signatures.
+	pub chain_id: Option<u64>,
+	pub nonce: U256,
+	pub gas_price: U256,
+	pub gas_limit: U256,
+	pub to: Option<H160>,
+	pub value: U256,
+	pub data: Vec<u8>,
+	pub access_list: Vec<(H160, Vec<H256>)>,
+	/// `v` of the signature as encoded: 27 or 28, `chain_id * 2 + 35` or
+	/// 36 for EIP-155, and the y parity for typed transactions. Fixtures carry
+	/// invalid values on purpose, so it is only checked on recovery.
+	pub v: u64,
+	pub r: H256,
+	pub s: H256,
+	/// Encoding the transaction was decoded from.
+	pub raw: Vec<u8>,
+}
+
+fn decode_to(rlp: &Rlp) -> Result<Option<H160>, DecoderError> {
+	if rlp.is_empty() {
+		Ok(None)
+	} else {
+		rlp.as_val().map(Some)
+	}
+}
+
+fn decode_access_list(rlp: &Rlp) -> Result<Vec<(H160, Vec<H256>)>, DecoderError> {
+	rlp.iter()
+		.map(|item| Ok((item.val_at(0)?, item.list_at(1)?)))
+		.collect()
+}
+
+/// Append `to`, empty for contract creation.
+pub fn append_to(stream: &mut RlpStream, to: &Option<H160>) {
+	match to {
+		Some(to) => stream.append(to),
+		None => stream.append_empty_data(),
+	};
+}
+
+/// Append `access_list` as the list of `[address, keys]` of typed transactions.
+pub fn append_access_list(stream: &mut RlpStream, access_list: &[(H160, Vec<H256>)]) {
+	stream.begin_list(access_list.len());
+	for (address, keys) in access_list {
+		stream.begin_list(2);
+		stream.append(address);
+		stream.append_list(keys);
+	}
+}
+
+pub fn public_to_address(public: &PublicKey) -> H160 {
+	let hash = Keccak256::digest(&public.serialize()[1..]);
+	H160::from_slice(&hash[12..])
+}
+
+/// Address of the account controlled by `secret`.
+pub fn secret_to_address(secret: &H256) -> Result<H160, SignatureError> {
+	let secret =
+		SecretKey::parse(secret.as_fixed_bytes()).map_err(|_| SignatureError::InvalidSecret)?;
+	Ok(public_to_address(&PublicKey::from_secret_key(&secret)))
+}
+
+/// Recover the signer of `hash` from the signature `r`, `s` with recovery id
+/// `recovery_id`.
+pub fn recover(hash: H256, recovery_id: u8, r: &H256, s: &H256) -> Result<H160, SignatureError> {
+	let recovery_id = RecoveryId::parse(recovery_id).map_err(|_| SignatureError::InvalidV)?;
+	let mut signature = [0; 64];
+	signature[..32].copy_from_slice(r.as_bytes());
+	signature[32..].copy_from_slice(s.as_bytes());
+	if signature[..32].iter().all(|byte| *byte == 0)
+		|| signature[32..].iter().all(|byte| *byte == 0)
+	{
+		return Err(SignatureError::OutOfRange);
+	}
+	let signature =
+		Signature::parse_standard(&signature).map_err(|_| SignatureError::OutOfRange)?;
+	let message = Message::parse(hash.as_fixed_bytes());
+	let public = libsecp256k1::recover(&message, &signature, &recovery_id)
+		.map_err(|_| SignatureError::Unrecoverable)?;
+	Ok(public_to_address(&public))
+}
+
+/// Recover the sender of `tx` from its signature over the signing hash of
+/// its type, which covers the chain id of EIP-155 and typed transactions.
+pub fn sender_from_tx(tx: &SignedTransaction) -> Result<H160, SignatureError> {
+	recover(tx.signing_hash(), tx.recovery_id()?, &tx.r, &tx.s)
+}
+
+impl SignedTransaction {
+	/// Decode a legacy transaction RLP list or a typed transaction envelope.
+	pub fn decode(raw: &[u8]) -> Result<Self, DecoderError> {
+		match raw.first() {
+			Some(&ACCESS_LIST_TYPE) => Self::decode_access_list_tx(raw),
+			Some(byte) if *byte >= 0xc0 => Self::decode_legacy(raw),
+			Some(_) => Err(DecoderError::Custom("unsupported transaction type")),
+			None => Err(DecoderError::RlpIsTooShort),
+		}
+	}
+
+	fn decode_legacy(raw: &[u8]) -> Result<Self, DecoderError> {
+		let rlp = Rlp::new(raw);
+		if rlp.item_count()? != 9 {
+			return Err(DecoderError::RlpIncorrectListLen);
+		}
+		let v: u64 = rlp.val_at(6)?;
+		let chain_id = if v >= 35 { Some((v - 35) / 2) } else { None };
+
+		Ok(SignedTransaction {
+			tx_type: None,
+			chain_id,
+			nonce: rlp.val_at(0)?,
+			gas_price: rlp.val_at(1)?,
+			gas_limit: rlp.val_at(2)?,
+			to: decode_to(&rlp.at(3)?)?,
+			value: rlp.val_at(4)?,
+			data: rlp.val_at(5)?,
+			access_list: Vec::new(),
+			v,
+			r: u256_to_h256(rlp.val_at(7)?),
+			s: u256_to_h256(rlp.val_at(8)?),
+			raw: raw.to_vec(),
+		})
+	}
+
+	fn decode_access_list_tx(raw: &[u8]) -> Result<Self, DecoderError> {
+		let rlp = Rlp::new(&raw[1..]);
+		if rlp.item_count()? != 11 {
+			return Err(DecoderError::RlpIncorrectListLen);
+		}
+
+		Ok(SignedTransaction {
+			tx_type: Some(ACCESS_LIST_TYPE),
+			chain_id: Some(rlp.val_at(0)?),
+			nonce: rlp.val_at(1)?,
+			gas_price: rlp.val_at(2)?,
+			gas_limit: rlp.val_at(3)?,
+			to: decode_to(&rlp.at(4)?)?,
+			value: rlp.val_at(5)?,
+			data: rlp.val_at(6)?,
+			access_list: decode_access_list(&rlp.at(7)?)?,
+			v: rlp.val_at(8)?,
+			r: u256_to_h256(rlp.val_at(9)?),
+			s: u256_to_h256(rlp.val_at(10)?),
+			raw: raw.to_vec(),
+		})
+	}
+
+	/// Hash identifying the transaction.
+	pub fn hash(&self) -> H256 {
+		H256::from_slice(Keccak256::digest(&self.raw).as_slice())
+	}
+
+	/// Hash the sender signed.
+	pub fn signing_hash(&self) -> H256 {
+		let mut stream = RlpStream::new();
+		match self.tx_type {
+			Some(tx_type) => {
+				stream.begin_list(8);
+				stream.append(&self.chain_id.unwrap_or(0));
+				stream.append(&self.nonce);
+				stream.append(&self.gas_price);
+				stream.append(&self.gas_limit);
+				append_to(&mut stream, &self.to);
+				stream.append(&self.value);
+				stream.append(&self.data);
+				append_access_list(&mut stream, &self.access_list);
+
+				let mut payload = vec![tx_type];
+				payload.extend_from_slice(&stream.out());
+				H256::from_slice(Keccak256::digest(&payload).as_slice())
+			}
+			None => {
+				stream.begin_list(if self.chain_id.is_some() { 9 } else { 6 });
+				stream.append(&self.nonce);
+				stream.append(&self.gas_price);
+				stream.append(&self.gas_limit);
+				append_to(&mut stream, &self.to);
+				stream.append(&self.value);
+				stream.append(&self.data);
+				if let Some(chain_id) = self.chain_id {
+					stream.append(&chain_id);
+					stream.append(&0u8);
+					stream.append(&0u8);
+				}
+				H256::from_slice(Keccak256::digest(&stream.out()).as_slice())
+			}
+		}
+	}
+
+	/// Recovery id encoded in `v`, 0 or 1.
+	pub fn recovery_id(&self) -> Result<u8, SignatureError> {
+		match (self.tx_type, self.v) {
+			(Some(_), v) if v <= 1 => Ok(v as u8),
+			(None, v) if v == 27 || v == 28 => Ok((v - 27) as u8),
+			(None, v) if v >= 35 => Ok(((v - 35) % 2) as u8),
+			_ => Err(SignatureError::InvalidV),
+		}
+	}
+
+	/// Recover the sender from the signature.
+	pub fn sender(&self) -> Result<H160, SignatureError> {
+		sender_from_tx(self)
+	}
+
+	/// The message executed on behalf of `caller`.
+	pub fn message(&self, caller: H160) -> Transaction {
+		Transaction {
+			caller,
+			to: self.to,
+			value: self.value,
+			data: self.data.clone(),
+			gas_limit: self.gas_limit.low_u64(),
+			access_list: self.access_list.clone(),
+			pricing: None,
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Example transaction of EIP-155, signed with the private key
+	// 0x4646464646464646464646464646464646464646464646464646464646464646.
+	const EIP155_TX: &str = "f86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a76400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83";
+
+	#[test]
+	fn eip155_example() {
+		let tx = SignedTransaction::decode(&hex::decode(EIP155_TX).unwrap()).unwrap();
+		assert_eq!(tx.chain_id, Some(1));
+		assert_eq!(
+			format!("{:?}", tx.signing_hash()),
+			"0xdaf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+		);
+		assert_eq!(
+			tx.sender(),
+			Ok("9d8a62f656a8d1615c1294fd71e9cfb3e4855a4f".parse().unwrap())
+		);
+	}
+
+	#[test]
+	fn malformed_signatures() {
+		let tx = SignedTransaction::decode(&hex::decode(EIP155_TX).unwrap()).unwrap();
+		let sender = |tx: SignedTransaction| tx.sender();
+
+		assert_eq!(
+			sender(SignedTransaction {
+				v: 29,
+				..tx.clone()
+			}),
+			Err(SignatureError::InvalidV)
+		);
+		assert_eq!(
+			sender(SignedTransaction {
+				r: H256::zero(),
+				..tx.clone()
+			}),
+			Err(SignatureError::OutOfRange)
+		);
+		assert_eq!(
+			sender(SignedTransaction {
+				s: H256::repeat_byte(0xff),
+				..tx
+			}),
+			Err(SignatureError::OutOfRange)
+		);
+	}
+
+	#[test]
+	fn secret_of_fixtures() {
+		let secret = "45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8"
+			.parse()
+			.unwrap();
+		assert_eq!(
+			secret_to_address(&secret),
+			Ok("a94f5374fce5edbc8e2a8697c15331677e6ebf0b".parse().unwrap())
+		);
+		assert_eq!(
+			secret_to_address(&H256::zero()),
+			Err(SignatureError::InvalidSecret)
+		);
+	}
+}
@@ -1,7 +1,20 @@
+use crate::capture;
+use crate::cprint;
+use crate::dump::AccountDump;
 use evm::backend::MemoryAccount;
 use primitive_types::{H160, H256, U256};
+use serde::Serialize;
 use sha3::{Digest, Keccak256};
+use std::any::Any;
 use std::collections::BTreeMap;
+use std::fmt;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+#[cfg(not(target_arch = "wasm32"))]
+use std::sync::{mpsc, Barrier};
+#[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+use std::time::Duration;
 
 pub fn u256_to_h256(u: U256) -> H256 {
 	let mut h = H256::default();
@@ -110,27 +123,156 @@ pub fn assert_valid_state(a: &ethjson::spec::State, b: &BTreeMap<H160, MemoryAcc
 				b
 			);
 		}
-		ethjson::spec::HashOrMap::Hash(h) => assert_valid_hash(&h.clone().into(), b),
+		ethjson::spec::HashOrMap::Hash(h) => {
+			assert_valid_hash(&h.clone().into(), b).unwrap_or_else(|mismatch| mismatch.fail(b))
+		}
 	}
 }
 
-pub fn assert_valid_hash(h: &H256, b: &BTreeMap<H160, MemoryAccount>) {
-	let tree = b
+pub fn storage_root(account: &MemoryAccount) -> H256 {
+	storage_root_of(&account.storage)
+}
+
+fn storage_root_of(storage: &BTreeMap<H256, H256>) -> H256 {
+	triehash_ethereum::sec_trie_root(
+		storage
+			.iter()
+			.map(|(k, v)| (k, rlp::encode(&U256::from_big_endian(&v[..])))),
+	)
+}
+
+pub fn code_hash(code: &[u8]) -> H256 {
+	H256::from_slice(Keccak256::digest(code).as_slice())
+}
+
+/// Address of the contract created by `caller` with its nonce `nonce`.
+pub fn create_address(caller: H160, nonce: U256) -> H160 {
+	let mut stream = rlp::RlpStream::new_list(2);
+	stream.append(&caller);
+	stream.append(&nonce);
+	H160::from_slice(&Keccak256::digest(&stream.out())[12..])
+}
+
+/// Address of the contract created by `caller` with CREATE2 from init code
+/// hashing to `init_code_hash`, by the rules of EIP-1014.
+pub fn create2_address(caller: H160, salt: H256, init_code_hash: H256) -> H160 {
+	let mut preimage = vec![0xff];
+	preimage.extend_from_slice(caller.as_bytes());
+	preimage.extend_from_slice(salt.as_bytes());
+	preimage.extend_from_slice(init_code_hash.as_bytes());
+	H160::from_slice(&Keccak256::digest(&preimage)[12..])
+}
+
+/// Key of `bytes` in a secure trie.
+pub fn trie_key(bytes: &[u8]) -> H256 {
+	H256::from_slice(Keccak256::digest(bytes).as_slice())
+}
+
+pub fn state_root(b: &BTreeMap<H160, MemoryAccount>) -> H256 {
+	state_root_of(b)
+}
+
+/// Account at `address` in the format of geth's state dumps, empty if it
+/// does not exist.
+pub fn dump_account(state: &BTreeMap<H160, MemoryAccount>, address: H160) -> AccountDump {
+	match state.get(&address) {
+		Some(account) => AccountDump::new(account),
+		None => AccountDump::new(&empty_account()),
+	}
+}
+
+/// Storage slot in the format of `debug_storageRangeAt`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+pub struct StorageSlot {
+	pub key: U256,
+	pub value: U256,
+}
+
+/// Page of the storage of an account in the format of `debug_storageRangeAt`.
+#[derive(Serialize, Debug, Clone, PartialEq, Eq)]
+#[serde(rename_all = "camelCase")]
+pub struct StorageRange {
+	/// Slots by the trie key of their key.
+	pub storage: BTreeMap<H256, StorageSlot>,
+	/// Trie key of the first slot of the next page.
+	pub next_key: H256,
+	pub complete: bool,
+}
+
+/// At most `max` storage slots of `account` in trie order, from the one with
+/// trie key `start`.
+pub fn dump_storage_range(account: &MemoryAccount, start: H256, max: usize) -> StorageRange {
+	let mut slots = account
+		.storage
 		.iter()
-		.map(|(address, account)| {
-			let storage_root = triehash_ethereum::sec_trie_root(
-				account
-					.storage
-					.iter()
-					.map(|(k, v)| (k, rlp::encode(&U256::from_big_endian(&v[..])))),
-			);
-			let code_hash = H256::from_slice(Keccak256::digest(&account.code).as_slice());
+		.map(|(key, value)| (trie_key(key.as_bytes()), *key, *value))
+		.filter(|(hash, _, _)| *hash >= start)
+		.collect::<Vec<_>>();
+	slots.sort();
+
+	let next = slots.get(max).map(|(hash, _, _)| *hash);
+	StorageRange {
+		storage: slots
+			.into_iter()
+			.take(max)
+			.map(|(hash, key, value)| {
+				let slot = StorageSlot {
+					key: U256::from_big_endian(key.as_bytes()),
+					value: U256::from_big_endian(value.as_bytes()),
+				};
+				(hash, slot)
+			})
+			.collect(),
+		next_key: next.unwrap_or_default(),
+		complete: next.is_none(),
+	}
+}
 
+/// An account borrowed from wherever its parts are kept, so that accounts
+/// sharing the code of another need no copy of it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AccountRef<'a> {
+	pub nonce: U256,
+	pub balance: U256,
+	pub storage: &'a BTreeMap<H256, H256>,
+	pub code: &'a [u8],
+}
+
+impl<'a> AccountRef<'a> {
+	pub fn to_account(&self) -> MemoryAccount {
+		MemoryAccount {
+			nonce: self.nonce,
+			balance: self.balance,
+			storage: self.storage.clone(),
+			code: self.code.to_vec(),
+		}
+	}
+}
+
+impl<'a> From<&'a MemoryAccount> for AccountRef<'a> {
+	fn from(account: &'a MemoryAccount) -> Self {
+		AccountRef {
+			nonce: account.nonce,
+			balance: account.balance,
+			storage: &account.storage,
+			code: &account.code,
+		}
+	}
+}
+
+/// State root of `accounts`, given in any order.
+pub fn state_root_of<'a, A: Into<AccountRef<'a>>>(
+	accounts: impl IntoIterator<Item = (&'a H160, A)>,
+) -> H256 {
+	let tree = accounts
+		.into_iter()
+		.map(|(address, account)| {
+			let account = account.into();
 			let account = TrieAccount {
 				nonce: account.nonce,
 				balance: account.balance,
-				storage_root,
-				code_hash,
+				storage_root: storage_root_of(account.storage),
+				code_hash: code_hash(account.code),
 				code_version: U256::zero(),
 			};
 
@@ -138,14 +280,249 @@ pub fn assert_valid_hash(h: &H256, b: &BTreeMap<H160, MemoryAccount>) {
 		})
 		.collect::<Vec<_>>();
 
-	let root = triehash_ethereum::sec_trie_root(tree);
-	let expect = h.clone().into();
+	triehash_ethereum::sec_trie_root(tree)
+}
 
-	if root != expect {
-		panic!(
-			"Hash not equal; calculated: {:?}, expect: {:?}\nState: {:#x?}",
-			root, expect, b
-		);
+/// State root of a post-state differing from the expected one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StateRootMismatch {
+	pub expected: H256,
+	pub actual: H256,
+	/// File the post-state was dumped into, if it was.
+	pub dump: Option<PathBuf>,
+}
+
+impl StateRootMismatch {
+	pub fn dumped_to(self, path: &Path) -> Self {
+		StateRootMismatch {
+			dump: Some(path.to_path_buf()),
+			..self
+		}
+	}
+
+	/// Panic with the mismatch, followed by `state` unless it was dumped.
+	pub fn fail(&self, state: &BTreeMap<H160, MemoryAccount>) -> ! {
+		match &self.dump {
+			Some(_) => panic!("{}", self),
+			None => panic!("{}\nState: {:#x?}", self, state),
+		}
+	}
+}
+
+impl fmt::Display for StateRootMismatch {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(
+			f,
+			"Hash not equal; calculated: {:?}, expect: {:?}",
+			self.actual, self.expected
+		)?;
+		if let Some(path) = &self.dump {
+			write!(f, "\nState dumped to {}", path.display())?;
+		}
+		Ok(())
+	}
+}
+
+impl std::error::Error for StateRootMismatch {}
+
+/// Check that `b` has state root `h`.
+pub fn assert_valid_hash(
+	h: &H256,
+	b: &BTreeMap<H160, MemoryAccount>,
+) -> Result<(), StateRootMismatch> {
+	let actual = state_root(b);
+	if actual != *h {
+		return Err(StateRootMismatch {
+			expected: *h,
+			actual,
+			dump: None,
+		});
+	}
+	Ok(())
+}
+
+pub fn serialize_hex<T: AsRef<[u8]>, S: serde::Serializer>(
+	bytes: T,
+	serializer: S,
+) -> Result<S::Ok, S::Error> {
+	serializer.serialize_str(&format!("0x{}", hex::encode(bytes.as_ref())))
+}
+
+pub fn deserialize_hex<'de, D: serde::Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Vec<u8>, D::Error> {
+	let value = <String as serde::Deserialize>::deserialize(deserializer)?;
+	hex::decode(value.trim_start_matches("0x")).map_err(serde::de::Error::custom)
+}
+
+/// Decode an optional hex string, such as `genesisRLP`.
+pub fn deserialize_optional_hex<'de, D: serde::Deserializer<'de>>(
+	deserializer: D,
+) -> Result<Option<Vec<u8>>, D::Error> {
+	<Option<String> as serde::Deserialize>::deserialize(deserializer)?
+		.map(|value| hex::decode(value.trim_start_matches("0x")).map_err(serde::de::Error::custom))
+		.transpose()
+}
+
+/// Account in the hex-quantity JSON format of genesis allocs and RPC
+/// fixtures, with every field optional. Code may be in any encoding of
+/// `encoding::decode`.
+#[derive(serde::Deserialize, Debug, Clone)]
+pub struct JsonAccount {
+	#[serde(default)]
+	pub balance: U256,
+	#[serde(default)]
+	pub nonce: U256,
+	#[serde(default, deserialize_with = "crate::encoding::deserialize")]
+	pub code: Vec<u8>,
+	#[serde(default)]
+	pub storage: BTreeMap<U256, U256>,
+}
+
+impl From<JsonAccount> for MemoryAccount {
+	fn from(account: JsonAccount) -> Self {
+		MemoryAccount {
+			balance: account.balance,
+			nonce: account.nonce,
+			code: account.code,
+			storage: account
+				.storage
+				.into_iter()
+				.filter(|(_, value)| !value.is_zero())
+				.map(|(key, value)| (u256_to_h256(key), u256_to_h256(value)))
+				.collect(),
+		}
+	}
+}
+
+pub fn empty_account() -> MemoryAccount {
+	MemoryAccount {
+		balance: U256::zero(),
+		nonce: U256::zero(),
+		code: Vec::new(),
+		storage: BTreeMap::new(),
+	}
+}
+
+/// Stack size of the threads tests run on, large enough for deeply nested
+/// calls.
+#[cfg(not(target_arch = "wasm32"))]
+const STACK_SIZE: usize = 16 * 1024 * 1024;
+
+/// Variable replacing the stack size of the threads tests run on, in bytes,
+/// to check no test depends on it.
+pub const STACK_SIZE_VAR: &str = "EVM_JSONTESTS_STACK_SIZE";
+
+#[cfg(not(target_arch = "wasm32"))]
+fn stack_size() -> usize {
+	std::env::var(STACK_SIZE_VAR)
+		.ok()
+		.and_then(|size| size.parse().ok())
+		.unwrap_or(STACK_SIZE)
+}
+
+/// Run a test on a thread with a stack large enough for deeply nested calls,
+/// propagating its result or panic. Panics if the test is still running
+/// after `timeout`, leaving its thread behind. Output the test prints is
+/// captured along with the one of the calling thread.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_with_stack<T, F>(timeout: Option<Duration>, f: F) -> T
+where
+	T: Send + 'static,
+	F: FnOnce() -> T + Send + 'static,
+{
+	let (sender, receiver) = mpsc::channel();
+	let capturing = capture::is_active();
+	// Spawn thread with explicit stack size
+	let child = thread::Builder::new()
+		.stack_size(stack_size())
+		.spawn(move || {
+			let run = || panic::catch_unwind(AssertUnwindSafe(f));
+			let (result, output) = if capturing {
+				capture::capture(run)
+			} else {
+				(run(), String::new())
+			};
+			let _ = sender.send(());
+			(result, output)
+		})
+		.unwrap();
+
+	if let Some(timeout) = timeout {
+		if let Err(mpsc::RecvTimeoutError::Timeout) = receiver.recv_timeout(timeout) {
+			panic!("Timed out after {:?}", timeout);
+		}
+	}
+
+	// Wait for thread to join
+	let (result, output) = child.join().unwrap();
+	cprint!("{}", output);
+	result.unwrap_or_else(|payload| panic::resume_unwind(payload))
+}
+
+/// Run a test. Without threads to run it on, it runs on the calling thread
+/// and `timeout` is not enforced.
+#[cfg(target_arch = "wasm32")]
+pub fn run_with_stack<T, F>(_timeout: Option<Duration>, f: F) -> T
+where
+	T: Send + 'static,
+	F: FnOnce() -> T + Send + 'static,
+{
+	f()
+}
+
+/// Run `f` on `threads` threads started at once, returning their results in
+/// thread order and propagating the first panic.
+#[cfg(not(target_arch = "wasm32"))]
+pub fn run_concurrently<T, F>(threads: usize, f: F) -> Vec<T>
+where
+	T: Send,
+	F: Fn() -> T + Sync,
+{
+	let barrier = Barrier::new(threads);
+	let (barrier, f) = (&barrier, &f);
+	thread::scope(|scope| {
+		let handles = (0..threads)
+			.map(|_| {
+				thread::Builder::new()
+					.stack_size(stack_size())
+					.spawn_scoped(scope, move || {
+						barrier.wait();
+						f()
+					})
+					.unwrap()
+			})
+			.collect::<Vec<_>>();
+		handles
+			.into_iter()
+			.map(|handle| {
+				handle
+					.join()
+					.unwrap_or_else(|payload| panic::resume_unwind(payload))
+			})
+			.collect()
+	})
+}
+
+/// Run `f` `threads` times one after the other, without threads to run it
+/// on at once.
+#[cfg(target_arch = "wasm32")]
+pub fn run_concurrently<T, F>(threads: usize, f: F) -> Vec<T>
+where
+	T: Send,
+	F: Fn() -> T + Sync,
+{
+	(0..threads).map(|_| f()).collect()
+}
+
+/// Message of a caught panic, as printed by the default hook.
+pub fn panic_message(payload: &(dyn Any + Send)) -> String {
+	if let Some(message) = payload.downcast_ref::<&str>() {
+		message.to_string()
+	} else if let Some(message) = payload.downcast_ref::<String>() {
+		message.clone()
+	} else {
+		"Box<dyn Any>".to_string()
 	}
 }
 
@@ -154,3 +531,111 @@ pub fn flush() {
 
 	io::stdout().flush().ok().expect("Could not flush stdout");
 }
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use std::sync::atomic::{AtomicUsize, Ordering};
+
+	#[test]
+	fn runs_concurrently() {
+		let started = AtomicUsize::new(0);
+		let mut order = run_concurrently(4, || started.fetch_add(1, Ordering::SeqCst));
+		order.sort_unstable();
+		assert_eq!(order, [0, 1, 2, 3]);
+	}
+
+	#[test]
+	fn created_addresses() {
+		let caller = "6ac7ea33f8831ea9dcc53393aaa88b25a785dbf0".parse().unwrap();
+		assert_eq!(
+			create_address(caller, U256::zero()),
+			"cd234a471b72ba2f1ccf0a70fcaba648a5eecd8d".parse().unwrap()
+		);
+		assert_eq!(
+			create_address(caller, U256::one()),
+			"343c43a37d37dff08ae8c4a11544c718abb4fcf8".parse().unwrap()
+		);
+
+		// Examples of EIP-1014.
+		let deadbeef = "deadbeef00000000000000000000000000000000".parse().unwrap();
+		let feed = "000000000000000000000000feed000000000000000000000000000000000000"
+			.parse()
+			.unwrap();
+		let examples: [(H160, H256, &[u8], &str); 6] = [
+			(
+				H160::zero(),
+				H256::zero(),
+				&[0x00],
+				"4d1a2e2bb4f88f0250f26ffff098b0b30b26bf38",
+			),
+			(
+				deadbeef,
+				H256::zero(),
+				&[0x00],
+				"b928f69bb1d91cd65274e3c79d8986362984fda3",
+			),
+			(
+				deadbeef,
+				feed,
+				&[0x00],
+				"d04116cdd17bebe565eb2422f2497e06cc1c9833",
+			),
+			(
+				H160::zero(),
+				H256::zero(),
+				&[0xde, 0xad, 0xbe, 0xef],
+				"70f2b2914a2a4b783faefb75f459a580616fcb5e",
+			),
+			(
+				H160::from_low_u64_be(0xdeadbeef),
+				H256::from_low_u64_be(0xcafebabe),
+				&[0xde, 0xad, 0xbe, 0xef],
+				"60f3f640a8508fc6a86d45df051962668e1e8ac7",
+			),
+			(
+				H160::zero(),
+				H256::zero(),
+				&[],
+				"e33c0c7f7df4809055c3eba6c09cfe4baf1bd9e0",
+			),
+		];
+		for (caller, salt, init_code, address) in &examples {
+			assert_eq!(
+				create2_address(*caller, *salt, code_hash(init_code)),
+				address.parse().unwrap()
+			);
+		}
+	}
+
+	#[test]
+	fn storage_range_pages() {
+		let mut account = empty_account();
+		for key in 1..=3u64 {
+			account
+				.storage
+				.insert(u256_to_h256(key.into()), u256_to_h256((key * 10).into()));
+		}
+
+		let first = dump_storage_range(&account, H256::zero(), 2);
+		assert_eq!(first.storage.len(), 2);
+		assert!(!first.complete);
+		let rest = dump_storage_range(&account, first.next_key, 2);
+		assert_eq!(rest.storage.len(), 1);
+		assert!(rest.complete);
+		assert_eq!(rest.next_key, H256::zero());
+
+		let mut values = first
+			.storage
+			.values()
+			.chain(rest.storage.values())
+			.map(|slot| slot.value.low_u64())
+			.collect::<Vec<_>>();
+		values.sort();
+		assert_eq!(values, [10, 20, 30]);
+
+		let json = serde_json::to_value(&rest).unwrap();
+		assert_eq!(json["complete"], true);
+		assert!(json["nextKey"].is_string());
+	}
+}
@@ -0,0 +1,283 @@
+//! Validators run on every executed index of a state test after the checks
+//! of its fixture, for invariants particular to a chain the fixtures do not
+//! know about, such as a cap on balances or a precompile that must never be
+//! called. Downstream runners implement [`Validator`] and `register` it;
+//! users pick the built-in ones with `--validator`.
+//!
+//! An index passes only if every registered validator accepts it. The
+//! failures of all of them are reported together, as any failing index is.
+
+use crate::calltree::{CallFrame, CallKind};
+use crate::fixture::{parse_quantity, PostState};
+use evm::backend::{Log, MemoryAccount};
+use evm::ExitReason;
+use lazy_static::lazy_static;
+use primitive_types::{H160, H256, U256};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::{Arc, RwLock};
+
+/// What executing an index did, as validators see it.
+pub struct Index<'a> {
+	pub name: &'a str,
+	pub fork: &'a str,
+	pub index: usize,
+	/// Post-state the fixture expects.
+	pub expected: &'a PostState,
+	pub pre_state: &'a BTreeMap<H160, MemoryAccount>,
+	pub post_state: &'a BTreeMap<H160, MemoryAccount>,
+	pub state_root: H256,
+	pub logs: &'a [Log],
+	pub used_gas: u64,
+	pub exit_reason: &'a ExitReason,
+	pub output: &'a [u8],
+	/// Frames of the transaction, traced if a registered validator
+	/// `needs_calls` or the run traces them anyway.
+	pub call_tree: Option<&'a CallFrame>,
+}
+
+/// A check of executed indexes.
+pub trait Validator: Send + Sync {
+	/// Name failures are reported under.
+	fn name(&self) -> String;
+
+	/// Whether the validator reads `Index::call_tree`, which costs tracing
+	/// every frame.
+	fn needs_calls(&self) -> bool {
+		false
+	}
+
+	/// Why `index` breaks the invariant, if it does.
+	fn validate(&self, index: &Index) -> Result<(), String>;
+}
+
+/// A validator rejecting an index.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+	pub validator: String,
+	pub message: String,
+}
+
+impl fmt::Display for Failure {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "Validator {} failed: {}", self.validator, self.message)
+	}
+}
+
+lazy_static! {
+	static ref VALIDATORS: RwLock<Vec<Arc<dyn Validator>>> = RwLock::new(Vec::new());
+}
+
+/// Run `validator` on every index executed from now on.
+pub fn register<V: Validator + 'static>(validator: V) {
+	VALIDATORS.write().unwrap().push(Arc::new(validator));
+}
+
+/// Validators registered so far.
+pub fn registered() -> Vec<Arc<dyn Validator>> {
+	VALIDATORS.read().unwrap().clone()
+}
+
+/// Whether a registered validator needs the call tree of indexes.
+pub fn need_calls() -> bool {
+	VALIDATORS
+		.read()
+		.unwrap()
+		.iter()
+		.any(|validator| validator.needs_calls())
+}
+
+/// Failures of `validators` on `index`.
+pub fn validate(validators: &[Arc<dyn Validator>], index: &Index) -> Vec<Failure> {
+	validators
+		.iter()
+		.filter_map(|validator| {
+			validator.validate(index).err().map(|message| Failure {
+				validator: validator.name(),
+				message,
+			})
+		})
+		.collect()
+}
+
+/// Fail the index unless every registered validator accepts it.
+pub fn check(index: &Index) {
+	let failures = validate(&registered(), index);
+	match failures.as_slice() {
+		[] => (),
+		[failure] => panic!("{}", failure),
+		_ => {
+			let failures = failures.iter().map(Failure::to_string).collect::<Vec<_>>();
+			panic!(
+				"{} validators failed:\n  {}",
+				failures.len(),
+				failures.join("\n  ")
+			)
+		}
+	}
+}
+
+/// No account may end an index with more than a balance.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MaxBalance(pub U256);
+
+impl Validator for MaxBalance {
+	fn name(&self) -> String {
+		format!("max-balance={}", self.0)
+	}
+
+	fn validate(&self, index: &Index) -> Result<(), String> {
+		match index
+			.post_state
+			.iter()
+			.find(|(_, account)| account.balance > self.0)
+		{
+			Some((address, account)) => Err(format!(
+				"{:?} has balance {}, over {}",
+				address, account.balance, self.0
+			)),
+			None => Ok(()),
+		}
+	}
+}
+
+/// A precompile no frame may call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NoPrecompile(pub H160);
+
+fn calls(frame: &CallFrame, address: H160) -> Option<&CallFrame> {
+	let create = frame.kind == CallKind::Create || frame.kind == CallKind::Create2;
+	if !create && frame.to == address {
+		return Some(frame);
+	}
+	frame.calls.iter().find_map(|call| calls(call, address))
+}
+
+impl Validator for NoPrecompile {
+	fn name(&self) -> String {
+		format!("no-precompile={:?}", self.0)
+	}
+
+	fn needs_calls(&self) -> bool {
+		true
+	}
+
+	fn validate(&self, index: &Index) -> Result<(), String> {
+		let call_tree = index.call_tree.ok_or("no call tree traced")?;
+		match calls(call_tree, self.0) {
+			Some(frame) => Err(format!("called by {:?}", frame.from)),
+			None => Ok(()),
+		}
+	}
+}
+
+/// Built-in validator of `--validator`, `max-balance=WEI` or
+/// `no-precompile=ADDRESS`.
+pub fn parse(value: &str) -> Result<Box<dyn Validator>, String> {
+	let (name, argument) = value
+		.split_once('=')
+		.ok_or_else(|| format!("Validator {} lacks a value, as NAME=VALUE", value))?;
+	match name {
+		"max-balance" => Ok(Box::new(MaxBalance(parse_quantity(argument)?))),
+		"no-precompile" => {
+			let address = argument
+				.trim_start_matches("0x")
+				.parse()
+				.map_err(|_| format!("Invalid address: {}", argument))?;
+			Ok(Box::new(NoPrecompile(address)))
+		}
+		_ => Err(format!("Unknown validator {}", name)),
+	}
+}
+
+/// Run the built-in validator of `--validator` on every index executed from
+/// now on.
+pub fn register_parsed(value: &str) -> Result<(), String> {
+	VALIDATORS.write().unwrap().push(Arc::from(parse(value)?));
+	Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use evm::ExitSucceed;
+
+	fn account(balance: u64) -> MemoryAccount {
+		MemoryAccount {
+			nonce: U256::zero(),
+			balance: balance.into(),
+			storage: BTreeMap::new(),
+			code: Vec::new(),
+		}
+	}
+
+	fn frame(kind: CallKind, to: H160, calls: Vec<CallFrame>) -> CallFrame {
+		CallFrame {
+			kind,
+			from: H160::repeat_byte(0xaa),
+			to,
+			value: U256::zero(),
+			gas: None,
+			gas_used: None,
+			input: Vec::new(),
+			output: Vec::new(),
+			exit: None,
+			error: None,
+			calls,
+			is_static: None,
+			selfdestruct: None,
+		}
+	}
+
+	#[test]
+	fn built_in() {
+		let expected: PostState = serde_json::from_value(serde_json::json!({
+			"hash": "0x0000000000000000000000000000000000000000000000000000000000000000",
+			"indexes": { "data": 0, "gas": 0, "value": 0 }
+		}))
+		.unwrap();
+		let pre_state = BTreeMap::new();
+		let post_state = vec![(H160::repeat_byte(1), account(100))]
+			.into_iter()
+			.collect();
+		let ecrecover = H160::from_low_u64_be(1);
+		let call_tree = frame(
+			CallKind::Call,
+			H160::repeat_byte(1),
+			vec![frame(CallKind::StaticCall, ecrecover, Vec::new())],
+		);
+		let exit_reason = ExitReason::Succeed(ExitSucceed::Stopped);
+		let index = Index {
+			name: "add",
+			fork: "Berlin",
+			index: 0,
+			expected: &expected,
+			pre_state: &pre_state,
+			post_state: &post_state,
+			state_root: H256::zero(),
+			logs: &[],
+			used_gas: 21000,
+			exit_reason: &exit_reason,
+			output: &[],
+			call_tree: Some(&call_tree),
+		};
+
+		let validators: Vec<Arc<dyn Validator>> = vec![
+			Arc::from(parse("max-balance=100").unwrap()),
+			Arc::from(parse("max-balance=0x10").unwrap()),
+			Arc::from(parse("no-precompile=0x0000000000000000000000000000000000000001").unwrap()),
+			Arc::new(NoPrecompile(H160::from_low_u64_be(2))),
+		];
+		assert!(validators[3].needs_calls());
+		let failures = validate(&validators, &index);
+		assert_eq!(failures.len(), 2);
+		assert_eq!(failures[0].validator, "max-balance=16");
+		assert_eq!(
+			failures[1].to_string(),
+			"Validator no-precompile=0x0000000000000000000000000000000000000001 failed: \
+			 called by 0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+		);
+		assert!(parse("max-balance").is_err());
+		assert!(parse("unknown=1").is_err());
+	}
+}
@@ -0,0 +1,429 @@
+//! Checks a transaction must pass before it executes: that the sender can
+//! pay for it and the block has gas for it, and the EIP-1559 fee rules of
+//! London, the EIP-3860 rules of Shanghai and the EIP-4844 blob rules of
+//! Cancun, so that fixtures of forks the runner cannot execute yet can still
+//! have their invalid transactions checked.
+
+use crate::exceptions;
+use crate::fees::FeeSchema;
+use crate::fixture::{Env, MultiTransaction};
+use crate::header::{BASE_FEE_FORKS, BLOB_GAS_FORKS, BLOB_GAS_PER_BLOB, MAX_BLOB_GAS_PER_BLOCK};
+use crate::state::Transaction;
+use primitive_types::U256;
+use std::fmt;
+
+/// Forks limiting the size of init code.
+pub const INITCODE_LIMIT_FORKS: &[&str] = &["Shanghai", "Cancun", "Prague"];
+
+/// Largest init code of a creation transaction, twice the code size limit.
+pub const MAX_INITCODE_SIZE: usize = 2 * 24576;
+
+/// Gas charged per 32-byte word of init code.
+const INITCODE_WORD_GAS: u64 = 2;
+
+/// Why a transaction is rejected before it executes.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Rejection {
+	IntrinsicGas { required: u64, limit: u64 },
+	InitCodeLimitExceeded { size: usize },
+	FeeCapBelowBaseFee { max_fee: U256, base_fee: U256 },
+	PriorityFeeAboveFeeCap { max_fee: U256, priority_fee: U256 },
+	InsufficientFunds { balance: U256, required: U256 },
+	GasLimitReached { limit: u64, block_limit: U256 },
+	NoBlobs,
+	TooManyBlobs { blobs: usize },
+}
+
+impl Rejection {
+	/// Reason of the rejection in exception tables.
+	pub fn reason(&self) -> &'static str {
+		match self {
+			Rejection::IntrinsicGas { .. } => "Rejection::IntrinsicGas",
+			Rejection::InitCodeLimitExceeded { .. } => "Rejection::InitCodeLimitExceeded",
+			Rejection::FeeCapBelowBaseFee { .. } => "Rejection::FeeCapBelowBaseFee",
+			Rejection::PriorityFeeAboveFeeCap { .. } => "Rejection::PriorityFeeAboveFeeCap",
+			Rejection::InsufficientFunds { .. } => "Rejection::InsufficientFunds",
+			Rejection::GasLimitReached { .. } => "Rejection::GasLimitReached",
+			Rejection::NoBlobs => "Rejection::NoBlobs",
+			Rejection::TooManyBlobs { .. } => "Rejection::TooManyBlobs",
+		}
+	}
+
+	/// Name of the exception fixtures expect for the rejection, if the
+	/// exception table has one.
+	pub fn exception(&self) -> Option<&'static str> {
+		exceptions::table().exception(self.reason())
+	}
+
+	/// Whether `expected`, exceptions separated by `|`, includes this one.
+	pub fn is_expected(&self, expected: &str) -> bool {
+		exceptions::table().is_expected(expected, self.reason())
+	}
+}
+
+impl fmt::Display for Rejection {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Rejection::IntrinsicGas { required, limit } => {
+				write!(f, "intrinsic gas {} above gas limit {}", required, limit)
+			}
+			Rejection::InitCodeLimitExceeded { size } => write!(
+				f,
+				"init code of {} bytes above limit {}",
+				size, MAX_INITCODE_SIZE
+			),
+			Rejection::FeeCapBelowBaseFee { max_fee, base_fee } => {
+				write!(f, "max fee per gas {} below base fee {}", max_fee, base_fee)
+			}
+			Rejection::PriorityFeeAboveFeeCap {
+				max_fee,
+				priority_fee,
+			} => write!(
+				f,
+				"max priority fee per gas {} above max fee per gas {}",
+				priority_fee, max_fee
+			),
+			Rejection::InsufficientFunds { balance, required } => write!(
+				f,
+				"sender balance {} below the {} the transaction may cost",
+				balance, required
+			),
+			Rejection::GasLimitReached { limit, block_limit } => write!(
+				f,
+				"gas limit {} above block gas limit {}",
+				limit, block_limit
+			),
+			Rejection::NoBlobs => write!(f, "blob transaction without blobs"),
+			Rejection::TooManyBlobs { blobs } => write!(
+				f,
+				"{} blobs above the {} of a block",
+				blobs,
+				MAX_BLOB_GAS_PER_BLOCK / BLOB_GAS_PER_BLOB
+			),
+		}
+	}
+}
+
+/// Gas a transaction pays before its first instruction, counting the words
+/// of init code.
+pub fn intrinsic_gas(transaction: &Transaction) -> u64 {
+	let data = transaction
+		.data
+		.iter()
+		.map(|byte| if *byte == 0 { 4 } else { 16 })
+		.sum::<u64>();
+	let access_list = transaction
+		.access_list
+		.iter()
+		.map(|(_, keys)| 2400 + 1900 * keys.len() as u64)
+		.sum::<u64>();
+	let creation = match transaction.to {
+		Some(_) => 0,
+		None => {
+			let words = (transaction.data.len() as u64).div_ceil(32);
+			32000 + INITCODE_WORD_GAS * words
+		}
+	};
+	21000 + data + access_list + creation
+}
+
+/// Why `transaction` is invalid under the init code rules, if it is.
+pub fn initcode_rejection(transaction: &Transaction) -> Option<Rejection> {
+	let required = intrinsic_gas(transaction);
+	if required > transaction.gas_limit {
+		return Some(Rejection::IntrinsicGas {
+			required,
+			limit: transaction.gas_limit,
+		});
+	}
+	match transaction.to {
+		None if transaction.data.len() > MAX_INITCODE_SIZE => {
+			Some(Rejection::InitCodeLimitExceeded {
+				size: transaction.data.len(),
+			})
+		}
+		_ => None,
+	}
+}
+
+/// Why the EIP-1559 fees of `transaction` are invalid under `base_fee`, if
+/// they are. Legacy transactions, and environments without a base fee, are
+/// not checked.
+pub fn fee_rejection(transaction: &MultiTransaction, base_fee: Option<U256>) -> Option<Rejection> {
+	let (max_fee, priority_fee) = FeeSchema::of(transaction).caps()?;
+	if priority_fee > max_fee {
+		return Some(Rejection::PriorityFeeAboveFeeCap {
+			max_fee,
+			priority_fee,
+		});
+	}
+	match base_fee {
+		Some(base_fee) if max_fee < base_fee => {
+			Some(Rejection::FeeCapBelowBaseFee { max_fee, base_fee })
+		}
+		_ => None,
+	}
+}
+
+/// Most `transaction`, whose alternative is `message`, may cost its sender:
+/// its gas limit at the highest price it pays, its value and the blob fee
+/// at its cap.
+pub fn upfront_cost(transaction: &MultiTransaction, message: &Transaction) -> U256 {
+	let schema = FeeSchema::of(transaction);
+	let gas = schema
+		.effective_gas_price(None)
+		.saturating_mul(message.gas_limit.into());
+	let blob_gas = transaction
+		.max_fee_per_blob_gas
+		.unwrap_or_default()
+		.saturating_mul(schema.blob_gas().into());
+	gas.saturating_add(blob_gas).saturating_add(message.value)
+}
+
+/// Why the sender of `message`, with `balance`, cannot pay for it, if it
+/// cannot.
+pub fn funds_rejection(
+	transaction: &MultiTransaction,
+	message: &Transaction,
+	balance: U256,
+) -> Option<Rejection> {
+	let required = upfront_cost(transaction, message);
+	if balance < required {
+		Some(Rejection::InsufficientFunds { balance, required })
+	} else {
+		None
+	}
+}
+
+/// Why the blobs of `transaction` are invalid, if it is a blob transaction
+/// and they are: it needs one, and no more than a block holds.
+pub fn blob_rejection(transaction: &MultiTransaction) -> Option<Rejection> {
+	if transaction.max_fee_per_blob_gas.is_none() {
+		return None;
+	}
+	let blobs = transaction.blob_versioned_hashes.len();
+	if blobs == 0 {
+		Some(Rejection::NoBlobs)
+	} else if blobs as u64 * BLOB_GAS_PER_BLOB > MAX_BLOB_GAS_PER_BLOCK {
+		Some(Rejection::TooManyBlobs { blobs })
+	} else {
+		None
+	}
+}
+
+/// Why `message` does not fit in the block of `env`, if it does not: its gas
+/// limit is above the one of the block.
+pub fn block_gas_rejection(message: &Transaction, env: &Env) -> Option<Rejection> {
+	if U256::from(message.gas_limit) > env.current_gas_limit {
+		Some(Rejection::GasLimitReached {
+			limit: message.gas_limit,
+			block_limit: env.current_gas_limit,
+		})
+	} else {
+		None
+	}
+}
+
+/// Why `transaction`, whose alternative is `message`, is invalid under the
+/// rules of `fork` that apply before execution in the block of `env` to a
+/// sender with `balance`, if it is: fees and blobs are checked first, then
+/// funds, then the gas of the block and of the transaction, as clients do.
+pub fn rejection(
+	fork: &str,
+	transaction: &MultiTransaction,
+	message: &Transaction,
+	env: &Env,
+	balance: U256,
+) -> Option<Rejection> {
+	if BASE_FEE_FORKS.contains(&fork) {
+		if let Some(rejection) = fee_rejection(transaction, env.current_base_fee) {
+			return Some(rejection);
+		}
+	}
+	if BLOB_GAS_FORKS.contains(&fork) {
+		if let Some(rejection) = blob_rejection(transaction) {
+			return Some(rejection);
+		}
+	}
+	if let Some(rejection) = funds_rejection(transaction, message, balance) {
+		return Some(rejection);
+	}
+	if let Some(rejection) = block_gas_rejection(message, env) {
+		return Some(rejection);
+	}
+	if INITCODE_LIMIT_FORKS.contains(&fork) {
+		return initcode_rejection(message);
+	}
+	None
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use primitive_types::{H160, H256, U256};
+
+	fn creation(size: usize, gas_limit: u64) -> Transaction {
+		Transaction {
+			caller: H160::zero(),
+			to: None,
+			value: U256::zero(),
+			data: vec![1; size],
+			gas_limit,
+			access_list: Vec::new(),
+			pricing: None,
+		}
+	}
+
+	#[test]
+	fn initcode_limit() {
+		// 32 words of init code cost 64 gas.
+		assert_eq!(
+			intrinsic_gas(&creation(1024, 0)),
+			21000 + 32000 + 16 * 1024 + 64
+		);
+		assert_eq!(
+			initcode_rejection(&creation(MAX_INITCODE_SIZE, 10_000_000)),
+			None
+		);
+
+		let oversized = initcode_rejection(&creation(MAX_INITCODE_SIZE + 1, 10_000_000)).unwrap();
+		assert_eq!(
+			oversized,
+			Rejection::InitCodeLimitExceeded {
+				size: MAX_INITCODE_SIZE + 1
+			}
+		);
+		assert!(oversized.is_expected("TR_IntrinsicGas|TR_InitCodeLimitExceeded"));
+		assert!(!oversized.is_expected("TR_IntrinsicGas"));
+
+		let short = initcode_rejection(&creation(MAX_INITCODE_SIZE + 1, 100_000)).unwrap();
+		assert_eq!(short.exception(), Some("TR_IntrinsicGas"));
+	}
+
+	fn dynamic_fee(max_fee: u64, priority_fee: u64) -> MultiTransaction {
+		serde_json::from_value(serde_json::json!({
+			"data": ["0x"],
+			"gasLimit": ["0x0f4240"],
+			"maxFeePerGas": format!("{:#x}", max_fee),
+			"maxPriorityFeePerGas": format!("{:#x}", priority_fee),
+			"nonce": "0x00",
+			"secretKey": "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8",
+			"to": "0x0000000000000000000000000000000000001000",
+			"value": ["0x00"]
+		}))
+		.unwrap()
+	}
+
+	fn env(base_fee: Option<u64>, gas_limit: u64) -> Env {
+		let mut env = serde_json::json!({
+			"currentCoinbase": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba",
+			"currentDifficulty": "0x020000",
+			"currentGasLimit": format!("{:#x}", gas_limit),
+			"currentNumber": "0x01",
+			"currentTimestamp": "0x03e8"
+		});
+		if let Some(base_fee) = base_fee {
+			env["currentBaseFee"] = format!("{:#x}", base_fee).into();
+		}
+		serde_json::from_value(env).unwrap()
+	}
+
+	#[test]
+	fn fee_caps() {
+		let base_fee = Some(U256::from(10));
+		assert_eq!(fee_rejection(&dynamic_fee(10, 2), base_fee), None);
+		assert_eq!(fee_rejection(&dynamic_fee(9, 2), None), None);
+
+		let below = fee_rejection(&dynamic_fee(9, 2), base_fee).unwrap();
+		assert_eq!(
+			below,
+			Rejection::FeeCapBelowBaseFee {
+				max_fee: U256::from(9),
+				base_fee: U256::from(10)
+			}
+		);
+		assert_eq!(below.exception(), Some("TR_FeeCapLessThanBlocks"));
+		let tip = fee_rejection(&dynamic_fee(9, 20), base_fee).unwrap();
+		assert_eq!(tip.exception(), Some("TR_TipGtFeeCap"));
+
+		// Fees are only checked from London on.
+		let message = creation(0, 100_000);
+		let balance = U256::exp10(18);
+		let env = env(Some(10), 10_000_000);
+		assert_eq!(
+			rejection("Berlin", &dynamic_fee(9, 2), &message, &env, balance),
+			None
+		);
+		assert_eq!(
+			rejection("London", &dynamic_fee(9, 2), &message, &env, balance),
+			Some(below)
+		);
+	}
+
+	#[test]
+	fn funds() {
+		let transaction = dynamic_fee(10, 2);
+		let message = Transaction {
+			value: U256::from(5),
+			..creation(0, 100_000)
+		};
+		// The gas limit is paid for at the fee cap, whatever the base fee.
+		assert_eq!(upfront_cost(&transaction, &message), U256::from(1_000_005));
+		assert_eq!(
+			funds_rejection(&transaction, &message, U256::from(1_000_005)),
+			None
+		);
+
+		let poor = funds_rejection(&transaction, &message, U256::from(1_000_004)).unwrap();
+		assert_eq!(
+			poor,
+			Rejection::InsufficientFunds {
+				balance: U256::from(1_000_004),
+				required: U256::from(1_000_005)
+			}
+		);
+		assert_eq!(poor.exception(), Some("TR_NoFunds"));
+		assert_eq!(
+			rejection(
+				"Berlin",
+				&transaction,
+				&message,
+				&env(None, 10_000_000),
+				U256::zero()
+			),
+			Some(Rejection::InsufficientFunds {
+				balance: U256::zero(),
+				required: U256::from(1_000_005)
+			})
+		);
+	}
+
+	#[test]
+	fn block_limits() {
+		let message = creation(0, 100_000);
+		assert_eq!(block_gas_rejection(&message, &env(None, 100_000)), None);
+		let over = block_gas_rejection(&message, &env(None, 99_999)).unwrap();
+		assert_eq!(over.exception(), Some("TR_GasLimitReached"));
+		assert_eq!(
+			rejection(
+				"Berlin",
+				&dynamic_fee(10, 2),
+				&message,
+				&env(None, 99_999),
+				U256::exp10(18)
+			),
+			Some(over)
+		);
+
+		let mut blob = dynamic_fee(10, 2);
+		assert_eq!(blob_rejection(&blob), None);
+		blob.max_fee_per_blob_gas = Some(U256::one());
+		assert_eq!(blob_rejection(&blob), Some(Rejection::NoBlobs));
+		blob.blob_versioned_hashes = vec![H256::zero(); 6];
+		assert_eq!(blob_rejection(&blob), None);
+		blob.blob_versioned_hashes.push(H256::zero());
+		let oversize = blob_rejection(&blob).unwrap();
+		assert_eq!(oversize, Rejection::TooManyBlobs { blobs: 7 });
+		assert_eq!(oversize.exception(), Some("TR_BLOBLIST_OVERSIZE"));
+	}
+}
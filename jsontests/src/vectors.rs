@@ -0,0 +1,176 @@
+//! Golden vectors of precompile calls: the input, gas and result of every
+//! distinct call a run makes, recorded once and replayed against the
+//! builtins alone, so that a regression of a precompile shows apart from
+//! changes of the interpreter.
+
+use crate::builtins;
+use crate::state::JsonPrecompile;
+use crate::utils::{deserialize_hex, serialize_hex};
+use evm::executor::{PrecompileFailure, PrecompileOutput};
+use lazy_static::lazy_static;
+use primitive_types::H160;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+
+/// A precompile call and its result.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub struct Vector {
+	pub fork: String,
+	pub address: H160,
+	#[serde(serialize_with = "serialize_hex", deserialize_with = "deserialize_hex")]
+	pub input: Vec<u8>,
+	/// Gas given to the precompile, unlimited if `None`.
+	pub gas_limit: Option<u64>,
+	pub success: bool,
+	/// Gas charged by a successful call.
+	pub cost: u64,
+	#[serde(serialize_with = "serialize_hex", deserialize_with = "deserialize_hex")]
+	pub output: Vec<u8>,
+}
+
+impl Vector {
+	pub fn new(
+		fork: &str,
+		address: H160,
+		input: &[u8],
+		gas_limit: Option<u64>,
+		result: &Result<PrecompileOutput, PrecompileFailure>,
+	) -> Self {
+		let (success, cost, output) = match result {
+			Ok(output) => (true, output.cost, output.output.clone()),
+			Err(_) => (false, 0, Vec::new()),
+		};
+		Vector {
+			fork: fork.to_string(),
+			address,
+			input: input.to_vec(),
+			gas_limit,
+			success,
+			cost,
+			output,
+		}
+	}
+
+	/// Call the builtin of the vector again, failing with a description of
+	/// the difference if the result is not the recorded one.
+	pub fn replay(&self) -> Result<(), String> {
+		let builtins =
+			builtins::for_fork(&self.fork).ok_or_else(|| format!("unknown fork {}", self.fork))?;
+		let builtin = builtins
+			.get(&self.address)
+			.ok_or_else(|| format!("no builtin at {:?} in {}", self.address, self.fork))?;
+		let result = JsonPrecompile::exec_as_precompile(builtin, &self.input, self.gas_limit);
+		let replayed = Vector::new(
+			&self.fork,
+			self.address,
+			&self.input,
+			self.gas_limit,
+			&result,
+		);
+		if replayed == *self {
+			return Ok(());
+		}
+		Err(format!(
+			"success {}, cost {}, output 0x{}; expect: success {}, cost {}, output 0x{}",
+			replayed.success,
+			replayed.cost,
+			hex::encode(&replayed.output),
+			self.success,
+			self.cost,
+			hex::encode(&self.output)
+		))
+	}
+}
+
+static RECORDING: AtomicBool = AtomicBool::new(false);
+
+lazy_static! {
+	static ref RECORDED: Mutex<BTreeSet<Vector>> = Mutex::new(BTreeSet::new());
+}
+
+/// Record every precompile call made from now on, on any thread.
+pub fn start_recording() {
+	RECORDED.lock().unwrap().clear();
+	RECORDING.store(true, Ordering::SeqCst);
+}
+
+/// Record a call of the precompile at `address` if recording.
+pub fn record(
+	fork: &str,
+	address: H160,
+	input: &[u8],
+	gas_limit: Option<u64>,
+	result: &Result<PrecompileOutput, PrecompileFailure>,
+) {
+	if RECORDING.load(Ordering::Relaxed) {
+		let vector = Vector::new(fork, address, input, gas_limit, result);
+		RECORDED.lock().unwrap().insert(vector);
+	}
+}
+
+/// Stop recording and write the distinct calls recorded into `path`, sorted,
+/// one JSON line each. Returns the number of vectors written.
+pub fn finish_recording(path: &Path) -> io::Result<usize> {
+	RECORDING.store(false, Ordering::SeqCst);
+	let recorded = std::mem::take(&mut *RECORDED.lock().unwrap());
+	if let Some(dir) = path.parent() {
+		fs::create_dir_all(dir)?;
+	}
+	let mut file = io::BufWriter::new(fs::File::create(path)?);
+	for vector in &recorded {
+		writeln!(file, "{}", serde_json::to_string(vector)?)?;
+	}
+	file.flush()?;
+	Ok(recorded.len())
+}
+
+pub fn read(path: &Path) -> io::Result<Vec<Vector>> {
+	fs::read_to_string(path)?
+		.lines()
+		.filter(|line| !line.trim().is_empty())
+		.map(|line| serde_json::from_str(line).map_err(io::Error::from))
+		.collect()
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn record_and_replay() {
+		let identity = H160::from_low_u64_be(4);
+		let builtins = builtins::for_fork("Istanbul").unwrap();
+		let call = |input: &[u8], gas_limit| {
+			let result = JsonPrecompile::exec_as_precompile(&builtins[&identity], input, gas_limit);
+			record("Istanbul", identity, input, gas_limit, &result);
+		};
+		let path = std::env::temp_dir().join("evm-jsontests-vectors.jsonl");
+
+		start_recording();
+		call(b"abc", Some(100));
+		call(b"abc", Some(100));
+		call(b"abc", Some(1));
+		finish_recording(&path).unwrap();
+
+		// Other tests may call precompiles while recording.
+		let mut vectors = read(&path)
+			.unwrap()
+			.into_iter()
+			.filter(|vector| vector.address == identity && vector.input == b"abc")
+			.collect::<Vec<_>>();
+		assert_eq!(vectors.len(), 2);
+		assert!(!vectors[0].success);
+		assert_eq!(vectors[1].output, b"abc".to_vec());
+		assert!(vectors.iter().all(|vector| vector.replay().is_ok()));
+
+		vectors[1].cost += 1;
+		assert!(vectors[1].replay().is_err());
+		fs::remove_file(&path).unwrap();
+	}
+}
@@ -1,6 +1,12 @@
+use crate::conformance::ExecutorBuilder;
+use crate::console;
+use crate::diff::{render_diff, state_diff};
+use crate::logs::assert_valid_logs_hash;
+use crate::summary::Outcome;
+use crate::trace::{traced, StepLimit};
 use crate::utils::*;
-use evm::backend::{ApplyBackend, MemoryAccount, MemoryBackend, MemoryVicinity};
-use evm::executor::{MemoryStackState, StackExecutor, StackSubstateMetadata};
+use crate::{cprint, cprintln};
+use evm::backend::{ApplyBackend, Log, MemoryAccount, MemoryBackend, MemoryVicinity};
 use evm::Config;
 use primitive_types::{H160, U256};
 use serde::Deserialize;
@@ -58,46 +64,94 @@ impl Test {
 	}
 }
 
-pub fn test(name: &str, test: Test) {
-	print!("Running test {} ... ", name);
-	flush();
+/// Options controlling how vm tests are run.
+#[derive(Clone, Debug, Default)]
+pub struct Options {
+	/// Print the difference between pre-state, computed post-state and
+	/// expected post-state.
+	pub diff: bool,
+	/// Do not print a status line per test.
+	pub quiet: bool,
+}
+
+pub fn test(name: &str, test: Test) -> Outcome {
+	test_with_options(name, test, &Options::default())
+}
+
+pub fn test_with_options(name: &str, test: Test, options: &Options) -> Outcome {
+	if !options.quiet {
+		cprint!("Running test {} ... ", name);
+		flush();
+	}
 
 	let original_state = test.unwrap_to_pre_state();
 	let vicinity = test.unwrap_to_vicinity();
 	let config = Config::frontier();
-	let mut backend = MemoryBackend::new(&vicinity, original_state);
-	let metadata = StackSubstateMetadata::new(test.unwrap_to_gas_limit(), &config);
-	let state = MemoryStackState::new(metadata, &backend);
+	let mut backend = MemoryBackend::new(&vicinity, original_state.clone());
 	let precompile = BTreeMap::new();
-	let mut executor = StackExecutor::new_with_precompiles(state, &config, &precompile);
+	let mut executor = ExecutorBuilder::new(&config, &precompile)
+		.gas_limit(test.unwrap_to_gas_limit())
+		.build(&backend);
 
 	let code = test.unwrap_to_code();
 	let data = test.unwrap_to_data();
 	let context = test.unwrap_to_context();
 	let mut runtime = evm::Runtime::new(code, data, context, &config);
 
-	let reason = executor.execute(&mut runtime);
+	let (_, reason) = traced(StepLimit::current(), || executor.execute(&mut runtime));
 	let gas = executor.gas();
 	let (values, logs) = executor.into_state().deconstruct();
-	backend.apply(values, logs, false);
+	let logs = logs.into_iter().collect::<Vec<Log>>();
+	backend.apply(values, logs.clone(), false);
+
+	if options.diff {
+		cprintln!();
+		cprint!(
+			"{}",
+			render_diff(
+				&state_diff(&original_state, backend.state()),
+				console::color()
+			)
+		);
+		if let Some(ethjson::spec::HashOrMap::Map(_)) = test.0.post_state.as_ref().map(|s| &s.0) {
+			let expected = unwrap_to_state(test.0.post_state.as_ref().unwrap());
+			cprintln!("computed -> expected:");
+			cprint!(
+				"{}",
+				render_diff(&state_diff(backend.state(), &expected), console::color())
+			);
+		}
+	}
 
 	if test.0.output.is_none() {
-		print!("{:?} ", reason);
+		if !options.quiet {
+			cprint!("{:?} ", reason);
+		}
 
 		assert!(!reason.is_succeed());
 		assert!(test.0.post_state.is_none() && test.0.gas_left.is_none());
 
-		println!("succeed");
+		if !options.quiet {
+			cprintln!("succeed");
+		}
 	} else {
 		let expected_post_gas = test.unwrap_to_post_gas();
-		print!("{:?} ", reason);
+		if !options.quiet {
+			cprint!("{:?} ", reason);
+		}
 
 		assert_eq!(
 			runtime.machine().return_value(),
 			test.unwrap_to_return_value()
 		);
 		assert_valid_state(test.0.post_state.as_ref().unwrap(), &backend.state());
+		if let Some(expected) = &test.0.logs {
+			assert_valid_logs_hash(&expected.0, &logs);
+		}
 		assert_eq!(gas, expected_post_gas);
-		println!("succeed");
+		if !options.quiet {
+			cprintln!("succeed");
+		}
 	}
+	Outcome::executed(1)
 }
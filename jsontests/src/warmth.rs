@@ -0,0 +1,101 @@
+//! Generated filler checking that precompiles are warm from the start of a
+//! transaction since Berlin, by EIP-2929. A regression here only shows as
+//! slightly different gas, which fixtures rarely store.
+
+use crate::builtins::{self, FORKS};
+use crate::filler::{self, Filler};
+use primitive_types::H160;
+use serde_json::{json, Map, Value};
+
+/// Contract measuring the gas of BALANCE on every precompile.
+const PROBE: &str = "0x0000000000000000000000000000000000001000";
+
+/// Gas of PUSH20, BALANCE, POP and GAS, the instructions between the two
+/// GAS of a measurement, on `fork` for a warm or cold address.
+pub fn measured_gas(fork: &str, warm: bool) -> u64 {
+	let berlin = FORKS.iter().position(|name| *name == "Berlin").unwrap();
+	let balance = match FORKS.iter().position(|name| *name == fork) {
+		Some(position) if position >= berlin => {
+			if warm {
+				100
+			} else {
+				2600
+			}
+		}
+		_ => 700,
+	};
+	3 + balance + 2 + 2
+}
+
+/// Code storing in slot `i` the gas measured around BALANCE of the `i`th
+/// address.
+fn probe_code(addresses: &[H160]) -> Vec<u8> {
+	let mut code = Vec::new();
+	for (slot, address) in addresses.iter().enumerate() {
+		// GAS PUSH20 address BALANCE POP GAS SWAP1 SUB PUSH1 slot SSTORE
+		code.extend_from_slice(&[0x5a, 0x73]);
+		code.extend_from_slice(address.as_bytes());
+		code.extend_from_slice(&[0x31, 0x50, 0x5a, 0x90, 0x03, 0x60, slot as u8, 0x55]);
+	}
+	code
+}
+
+/// Filler measuring BALANCE of every precompile of the forks the runner
+/// supports from Istanbul on, expecting warm access from Berlin.
+pub fn precompile_warmth_filler() -> Filler {
+	let forks = filler::filled_forks("Istanbul");
+	let addresses = forks
+		.last()
+		.and_then(|fork| builtins::for_fork(fork))
+		.map(|builtins| builtins.keys().cloned().collect::<Vec<_>>())
+		.unwrap_or_default();
+
+	let expect = forks
+		.iter()
+		.map(|fork| {
+			let gas = measured_gas(fork, true);
+			let storage = (0..addresses.len())
+				.map(|slot| (format!("{:#x}", slot), json!(format!("{:#x}", gas))))
+				.collect::<Map<_, _>>();
+			json!({
+				"network": [fork],
+				"result": { PROBE: { "storage": storage } },
+			})
+		})
+		.collect::<Vec<_>>();
+
+	serde_json::from_value(json!({
+		"env": {
+			"currentCoinbase": "0x2adc25665018aa1fe0e6bc666dac8fc2697ff9ba",
+			"currentDifficulty": "0x020000",
+			"currentGasLimit": "0x05f5e100",
+			"currentNumber": "0x01",
+			"currentTimestamp": "0x03e8",
+		},
+		"pre": {
+			PROBE: {
+				"balance": "0x00",
+				"code": format!("0x{}", hex::encode(probe_code(&addresses))),
+				"nonce": "0x00",
+				"storage": {},
+			},
+			"0xa94f5374fce5edbc8e2a8697c15331677e6ebf0b": {
+				"balance": "0x0de0b6b3a7640000",
+				"code": "0x",
+				"nonce": "0x00",
+				"storage": {},
+			},
+		},
+		"transaction": {
+			"data": ["0x"],
+			"gasLimit": ["0x0f4240"],
+			"gasPrice": "0x0a",
+			"nonce": "0x00",
+			"secretKey": "0x45a915e4d060149eb4365960e6a7a45f334393093061116b197e3240065ff2d8",
+			"to": PROBE,
+			"value": ["0x00"],
+		},
+		"expect": Value::Array(expect),
+	}))
+	.expect("Generated filler is invalid")
+}
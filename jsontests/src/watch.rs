@@ -0,0 +1,48 @@
+use crate::fixtures::json_files;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::{Duration, SystemTime};
+
+fn snapshot(paths: &[PathBuf]) -> BTreeMap<PathBuf, SystemTime> {
+	paths
+		.iter()
+		.flat_map(|path| json_files(path))
+		.filter_map(|file| {
+			let modified = fs::metadata(&file).and_then(|m| m.modified()).ok()?;
+			Some((file, modified))
+		})
+		.collect()
+}
+
+/// Poll `paths` for fixture files every `interval`, calling `run` once for
+/// each file initially and then again for every file that is added or
+/// modified. Never returns.
+pub fn watch<F: FnMut(&Path)>(paths: &[PathBuf], interval: Duration, mut run: F) {
+	let mut known = snapshot(paths);
+	for file in known.keys() {
+		run(file);
+	}
+	println!("Watching {} fixture files for changes ...", known.len());
+
+	loop {
+		thread::sleep(interval);
+
+		let current = snapshot(paths);
+		let changed = current
+			.iter()
+			.filter(|(file, modified)| known.get(*file) != Some(modified))
+			.map(|(file, _)| file.clone())
+			.collect::<Vec<_>>();
+
+		for file in &changed {
+			println!("Changed: {}", file.display());
+			run(file);
+		}
+		if !changed.is_empty() {
+			println!("Watching {} fixture files for changes ...", current.len());
+		}
+		known = current;
+	}
+}
@@ -0,0 +1,216 @@
+//! Random sequences of the state changes a transaction makes, applied both to
+//! evm's `MemoryBackend` and to the runners' `OverlayBackend`. Cases are
+//! generated from a fixed seed, so a failure reproduces on every run.
+
+use evm::backend::{Apply, ApplyBackend, Backend, MemoryAccount, MemoryBackend, MemoryVicinity};
+use evm::executor::{MemoryStackState, StackState, StackSubstateMetadata};
+use evm::{Config, Transfer};
+use evm_jsontests::overlay::OverlayBackend;
+use primitive_types::{H160, H256, U256};
+use proptest::prelude::*;
+use proptest::test_runner::{Config as RunnerConfig, RngAlgorithm, TestRng, TestRunner};
+use std::borrow::Cow;
+use std::collections::BTreeMap;
+
+const ACCOUNTS: usize = 4;
+
+fn address(index: usize) -> H160 {
+	H160::from_low_u64_be(index as u64 + 1)
+}
+
+fn slot(value: u8) -> H256 {
+	H256::from_low_u64_be(value as u64)
+}
+
+#[derive(Clone, Debug)]
+enum Op {
+	Deposit(usize, u64),
+	Withdraw(usize, u64),
+	Transfer(usize, usize, u64),
+	SetStorage(usize, u8, u8),
+	ResetStorage(usize),
+	Delete(usize),
+	/// Apply an account unchanged, as executors do for touched accounts.
+	Touch(usize),
+}
+
+fn op() -> impl Strategy<Value = Op> {
+	let account = 0..ACCOUNTS;
+	let value = 0..1_000u64;
+	prop_oneof![
+		(account.clone(), value.clone()).prop_map(|(a, v)| Op::Deposit(a, v)),
+		(account.clone(), value.clone()).prop_map(|(a, v)| Op::Withdraw(a, v)),
+		(account.clone(), account.clone(), value).prop_map(|(a, b, v)| Op::Transfer(a, b, v)),
+		(account.clone(), 0..4u8, 0..3u8).prop_map(|(a, k, v)| Op::SetStorage(a, k, v)),
+		account.clone().prop_map(Op::ResetStorage),
+		account.clone().prop_map(Op::Delete),
+		account.prop_map(Op::Touch),
+	]
+}
+
+fn vicinity() -> MemoryVicinity {
+	MemoryVicinity {
+		gas_price: U256::zero(),
+		origin: H160::zero(),
+		block_hashes: Vec::new(),
+		block_number: U256::zero(),
+		block_coinbase: H160::zero(),
+		block_timestamp: U256::zero(),
+		block_difficulty: U256::zero(),
+		block_gas_limit: U256::zero(),
+		chain_id: U256::one(),
+	}
+}
+
+fn pre_state(balances: &[u64]) -> BTreeMap<H160, MemoryAccount> {
+	balances
+		.iter()
+		.enumerate()
+		.map(|(i, balance)| {
+			let account = MemoryAccount {
+				balance: (*balance).into(),
+				nonce: U256::one(),
+				code: Vec::new(),
+				storage: vec![(slot(1), slot(1))].into_iter().collect(),
+			};
+			(address(i), account)
+		})
+		.collect()
+}
+
+fn total_balance(state: &BTreeMap<H160, MemoryAccount>) -> U256 {
+	state
+		.values()
+		.fold(U256::zero(), |total, account| total + account.balance)
+}
+
+/// State changes of `op` against `backend`, and the change in total balance
+/// it makes.
+fn changes(
+	backend: &MemoryBackend,
+	config: &Config,
+	op: &Op,
+) -> (Vec<Apply<Vec<(H256, H256)>>>, i128) {
+	if let Op::Touch(a) = *op {
+		if !backend.exists(address(a)) {
+			return (Vec::new(), 0);
+		}
+		let touch = Apply::Modify {
+			address: address(a),
+			basic: backend.basic(address(a)),
+			code: None,
+			storage: Vec::new(),
+			reset_storage: false,
+		};
+		return (vec![touch], 0);
+	}
+
+	let metadata = StackSubstateMetadata::new(u64::MAX, config);
+	let mut state = MemoryStackState::new(metadata, backend);
+	let balance = |a: usize| backend.basic(address(a)).balance.low_u64() as i128;
+	let delta = match *op {
+		Op::Deposit(a, value) => {
+			state.deposit(address(a), value.into());
+			value as i128
+		}
+		Op::Withdraw(a, value) => match state.withdraw(address(a), value.into()) {
+			Ok(()) => -(value as i128),
+			Err(_) => 0,
+		},
+		Op::Transfer(a, b, value) => {
+			let transfer = Transfer {
+				source: address(a),
+				target: address(b),
+				value: value.into(),
+			};
+			let _ = StackState::transfer(&mut state, transfer);
+			0
+		}
+		Op::SetStorage(a, key, value) => {
+			StackState::set_storage(&mut state, address(a), slot(key), slot(value));
+			0
+		}
+		Op::ResetStorage(a) => {
+			StackState::reset_storage(&mut state, address(a));
+			0
+		}
+		Op::Delete(a) => {
+			StackState::set_deleted(&mut state, address(a));
+			-balance(a)
+		}
+		Op::Touch(_) => unreachable!(),
+	};
+
+	let (values, _) = state.deconstruct();
+	let values = values
+		.into_iter()
+		.map(|apply| match apply {
+			Apply::Modify {
+				address,
+				basic,
+				code,
+				storage,
+				reset_storage,
+			} => Apply::Modify {
+				address,
+				basic,
+				code,
+				storage: storage.into_iter().collect(),
+				reset_storage,
+			},
+			Apply::Delete { address } => Apply::Delete { address },
+		})
+		.collect();
+	(values, delta)
+}
+
+fn is_empty(account: Option<&MemoryAccount>) -> bool {
+	account.map_or(true, |account| {
+		account.balance.is_zero() && account.nonce.is_zero() && account.code.is_empty()
+	})
+}
+
+#[test]
+fn overlay_matches_memory_backend() {
+	let config = Config::berlin();
+	let vicinity = vicinity();
+	let strategy = (
+		prop::collection::vec(0..1_000_000u64, ACCOUNTS),
+		prop::collection::vec((op(), any::<bool>()), 1..40),
+	);
+	let rng = TestRng::deterministic_rng(RngAlgorithm::ChaCha);
+	let mut runner = TestRunner::new_with_rng(RunnerConfig::with_cases(256), rng);
+
+	runner
+		.run(&strategy, |(balances, ops)| {
+			let pre_state = pre_state(&balances);
+			let mut memory = MemoryBackend::new(&vicinity, pre_state.clone());
+			let mut overlay = OverlayBackend::new(&vicinity, Cow::Borrowed(&pre_state));
+			let mut expected_total = total_balance(&pre_state).low_u64() as i128;
+
+			for (op, delete_empty) in &ops {
+				let (values, delta) = changes(&memory, &config, op);
+				let root = overlay.state_root();
+				memory.apply(values.clone(), Vec::new(), *delete_empty);
+				overlay.apply(values, Vec::new(), *delete_empty);
+				expected_total += delta;
+
+				prop_assert_eq!(&*overlay.state(), memory.state(), "after {:?}", op);
+				prop_assert_eq!(
+					total_balance(memory.state()).low_u64() as i128,
+					expected_total,
+					"balance not conserved by {:?}",
+					op
+				);
+				if let Op::Touch(a) = *op {
+					let account = memory.state().get(&address(a));
+					if !(*delete_empty && is_empty(account)) {
+						prop_assert_eq!(overlay.state_root(), root, "touch changed the root");
+					}
+				}
+			}
+			prop_assert_eq!(overlay.into_state(), memory.state().clone());
+			Ok(())
+		})
+		.unwrap();
+}
@@ -0,0 +1,51 @@
+use evm_jsontests::blockchain as blockchaintests;
+use std::collections::BTreeMap;
+use std::fs::{self, File};
+use std::io::BufReader;
+use std::path::PathBuf;
+
+pub fn run(dir: &str) {
+	let _ = env_logger::try_init();
+
+	let mut dest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+	dest.extend(dir.split('/'));
+
+	let mut entries = fs::read_dir(dest)
+		.unwrap()
+		.map(|entry| entry.unwrap())
+		.collect::<Vec<_>>();
+	entries.sort_by_key(|entry| entry.path());
+
+	for entry in entries {
+		if let Some(s) = entry.file_name().to_str() {
+			if s.starts_with(".") {
+				continue;
+			}
+		}
+
+		let path = entry.path();
+
+		let file = File::open(path).expect("Open file failed");
+
+		let reader = BufReader::new(file);
+		let coll = serde_json::from_reader::<_, BTreeMap<String, blockchaintests::Test>>(reader)
+			.expect("Parse test cases failed");
+
+		for (name, test) in coll {
+			blockchaintests::test(&name, test);
+		}
+	}
+}
+
+#[test]
+fn bc_invalid_header() {
+	run("res/ethtests/BlockchainTests/InvalidBlocks/bcInvalidHeaderTest")
+}
+#[test]
+fn bc_eip1559() {
+	run("res/ethtests/BlockchainTests/InvalidBlocks/bcEIP1559")
+}
+#[test]
+fn bc_uncle_header_validity() {
+	run("res/ethtests/BlockchainTests/InvalidBlocks/bcUncleHeaderTest")
+}
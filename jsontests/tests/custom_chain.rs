@@ -0,0 +1,49 @@
+//! Runs `examples/custom_chain.rs` on the smoke set, so that the library API
+//! it embeds the runner with keeps working.
+
+#[allow(dead_code)]
+#[path = "../examples/custom_chain.rs"]
+mod custom_chain;
+
+use evm_jsontests::builtins;
+use evm_jsontests::report;
+use evm_jsontests::smoke;
+use evm_jsontests::summary::{SkipReason, EXIT_PASSED};
+use regex::Regex;
+use std::fs;
+use std::process;
+
+#[test]
+fn custom_chain_passes_smoke_set() {
+	// Filled without BLAKE2F, so that the expectations are the chain's.
+	builtins::set_schedule(&custom_chain::precompiles());
+	let dir = std::env::temp_dir().join(format!("evm-jsontests-custom-chain-{}", process::id()));
+	let files = smoke::write_to(&dir).expect("Fill smoke set failed");
+	custom_chain::install();
+
+	let report = dir.join("report.jsonl");
+	let mut options = custom_chain::options(&report);
+	options
+		.skip_patterns
+		.push(Regex::new(r"/sstoreRefund\.json:").unwrap());
+	let summary = custom_chain::validate(&files, &options);
+
+	assert_eq!(summary.failed, 0, "{}", summary.failures_report());
+	assert_eq!(summary.parse_errors, 0);
+	assert_eq!(summary.exit_code(false), EXIT_PASSED);
+	assert!(summary
+		.skips
+		.contains_key(&("Berlin".to_string(), SkipReason::Listed)));
+	assert!(summary
+		.skips
+		.contains_key(&("Istanbul".to_string(), SkipReason::NotApplicable)));
+
+	let indexes = report::load(&report).expect("Read JSON report failed");
+	assert!(!indexes.is_empty());
+	assert!(indexes
+		.iter()
+		.all(|index| index.passed && index.fork == "Berlin"));
+	assert!(indexes.iter().all(|index| index.name != "sstoreRefund"));
+
+	fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,153 @@
+use evm_jsontests::boundaries;
+use evm_jsontests::filler;
+use evm_jsontests::signing::UnsignedTransaction;
+use evm_jsontests::smoke;
+use evm_jsontests::state as statetests;
+use evm_jsontests::warmth;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+#[test]
+fn filled_fixtures_pass() {
+	let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+	path.extend(&["res", "fillers", "sstoreFiller.json"]);
+
+	let filled = filler::fill_file(&path).expect("Fill failed");
+	assert_eq!(filled["sstore"]["_info"]["labels"]["1"], "two");
+	let coll = serde_json::from_value::<BTreeMap<String, statetests::Test>>(filled)
+		.expect("Parse filled test cases failed");
+
+	for (name, test) in coll {
+		let outcome = statetests::test(&name, test);
+		// Both data alternatives on Istanbul and Berlin, the forks of
+		// `>=Istanbul` the runner supports.
+		assert_eq!(outcome.executed, 4);
+	}
+}
+
+/// Wildcard expectations pin no post-state root, only bounds on it.
+#[test]
+fn wildcard_expectations() {
+	let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+	path.extend(&["res", "fillers", "wildcardFiller.json"]);
+	let filled = filler::fill_file(&path).expect("Fill failed");
+	// `>=Istanbul` fills the forks from Istanbul on the runner supports.
+	let forks = filled["wildcard"]["post"]
+		.as_object()
+		.unwrap()
+		.keys()
+		.collect::<Vec<_>>();
+	assert_eq!(forks, vec!["Berlin", "Istanbul"]);
+
+	let json = std::fs::read_to_string(&path).unwrap();
+	let mut fillers: BTreeMap<String, filler::Filler> = serde_json::from_str(&json).unwrap();
+	let wildcard = fillers.get_mut("wildcard").unwrap();
+	wildcard.expect[0].gas_used = Some("<21000".parse().unwrap());
+	let err = filler::fill("wildcard", wildcard).unwrap_err();
+	assert!(err.contains("gas used: expected 0x0..0x5207"), "{}", err);
+}
+
+/// Upstream fillers are validated on the forks the runner knows, leaving out
+/// indexes expecting an exception.
+#[test]
+fn upstream_expect_sections() {
+	let mut path = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
+	path.extend(&["res", "fillers", "upstreamFiller.json"]);
+	let results = filler::validate_files(&path);
+	assert_eq!(results.len(), 1);
+	let validation = results[0].1.as_ref().expect("Validation failed");
+	// One index of each expectation on Istanbul and Berlin.
+	assert_eq!(validation.checked, 2);
+	assert_eq!(validation.exceptions, 2);
+	assert!(validation.unsupported_forks.contains("Merge"));
+
+	let json = std::fs::read_to_string(&path).unwrap();
+	let mut fillers: BTreeMap<String, filler::Filler> = serde_json::from_str(&json).unwrap();
+	let upstream = fillers.get_mut("upstream").unwrap();
+	assert!(filler::fill("upstream", upstream).is_err());
+	upstream.expect[0]
+		.result
+		.values_mut()
+		.next()
+		.unwrap()
+		.balance = Some("2".parse().unwrap());
+	match filler::validate("upstream", upstream) {
+		Err(filler::FillError::Failed(reason)) => assert!(reason.contains("balance"), "{}", reason),
+		result => panic!("Unexpected {:?}", result),
+	}
+}
+
+#[test]
+fn precompiles_warm_since_berlin() {
+	assert_eq!(warmth::measured_gas("Istanbul", true), 707);
+	assert_eq!(warmth::measured_gas("Berlin", true), 107);
+	assert_eq!(warmth::measured_gas("Berlin", false), 2607);
+
+	let filler = warmth::precompile_warmth_filler();
+	let filled = filler::fill("precompileWarmth", &filler).expect("Fill failed");
+	let test =
+		serde_json::from_value::<statetests::Test>(filled).expect("Parse filled test case failed");
+	let outcome = statetests::test("precompileWarmth", test);
+	assert!(outcome.executed >= 2);
+}
+
+#[test]
+fn precompile_boundaries_match_builtins() {
+	let cases = boundaries::cases();
+	let case = |label: &str| cases.iter().find(|case| case.label == label).unwrap();
+	assert_eq!(
+		boundaries::expected_call("Berlin", case("modexpMaxLengths")),
+		(false, Vec::new())
+	);
+	assert_eq!(
+		boundaries::expected_call("Berlin", case("blake2fMaxRounds")),
+		(false, Vec::new())
+	);
+	let (success, output) = boundaries::expected_call("Istanbul", case("bn128PairingEmpty"));
+	assert!(success);
+	assert_eq!(output[31], 1);
+
+	let filler = boundaries::precompile_boundaries_filler();
+	let filled = filler::fill(boundaries::NAME, &filler).expect("Fill failed");
+	let test =
+		serde_json::from_value::<statetests::Test>(filled).expect("Parse filled test case failed");
+	let outcome = statetests::test(boundaries::NAME, test);
+	assert!(outcome.executed >= 2 * cases.len());
+}
+
+/// Transactions signed from the fields and secret key of fixtures recover
+/// to the caller the runner derives from the key.
+#[test]
+fn signed_transactions_recover_caller() {
+	for (_, fixture) in smoke::fixtures().expect("Fill smoke set failed") {
+		let coll = serde_json::from_value::<BTreeMap<String, statetests::Test>>(fixture)
+			.expect("Parse filled test cases failed");
+		for (name, test) in coll {
+			let transaction = &test.0.transaction;
+			for state in test.0.post_states.values().flatten() {
+				let signed = UnsignedTransaction::from_fixture(transaction, &state.indexes, 1)
+					.sign(&transaction.secret_key)
+					.expect("Sign failed");
+				assert_eq!(signed.sender(), Ok(test.unwrap_caller()), "{}", name);
+			}
+		}
+	}
+}
+
+/// The smoke set runs with the determinism check, so that CI catches reads
+/// of the clock or randomness during execution.
+#[test]
+fn smoke_set_passes() {
+	let options = statetests::Options {
+		determinism: true,
+		..Default::default()
+	};
+	for (file, fixture) in smoke::fixtures().expect("Fill smoke set failed") {
+		let coll = serde_json::from_value::<BTreeMap<String, statetests::Test>>(fixture)
+			.expect("Parse filled test cases failed");
+		for (name, test) in coll {
+			let outcome = statetests::test_with_options(&name, test, &options);
+			assert!(outcome.executed > 0, "{}:{} executed nothing", file, name);
+		}
+	}
+}
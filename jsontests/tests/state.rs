@@ -1,5 +1,5 @@
 use evm_jsontests::state as statetests;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::io::BufReader;
 use std::path::PathBuf;
@@ -8,10 +8,15 @@ pub fn run(dir: &str) {
 	let _ = env_logger::try_init();
 
 	let mut dest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-	dest.push(dir);
+	dest.extend(dir.split('/'));
 
-	for entry in fs::read_dir(dest).unwrap() {
-		let entry = entry.unwrap();
+	let mut entries = fs::read_dir(dest)
+		.unwrap()
+		.map(|entry| entry.unwrap())
+		.collect::<Vec<_>>();
+	entries.sort_by_key(|entry| entry.path());
+
+	for entry in entries {
 		if let Some(s) = entry.file_name().to_str() {
 			if s.starts_with(".") {
 				continue;
@@ -23,7 +28,7 @@ pub fn run(dir: &str) {
 		let file = File::open(path).expect("Open file failed");
 
 		let reader = BufReader::new(file);
-		let coll = serde_json::from_reader::<_, HashMap<String, statetests::Test>>(reader)
+		let coll = serde_json::from_reader::<_, BTreeMap<String, statetests::Test>>(reader)
 			.expect("Parse test cases failed");
 
 		for (name, test) in coll {
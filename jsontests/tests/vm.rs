@@ -1,5 +1,5 @@
 use evm_jsontests::vm as vmtests;
-use std::collections::HashMap;
+use std::collections::BTreeMap;
 use std::fs::{self, File};
 use std::io::BufReader;
 use std::path::PathBuf;
@@ -8,16 +8,19 @@ pub fn run(dir: &str) {
 	let _ = env_logger::try_init();
 
 	let mut dest = PathBuf::from(env!("CARGO_MANIFEST_DIR"));
-	dest.push(dir);
+	dest.extend(dir.split('/'));
 
-	for entry in fs::read_dir(dest).unwrap() {
-		let entry = entry.unwrap();
-		let path = entry.path();
+	let mut paths = fs::read_dir(dest)
+		.unwrap()
+		.map(|entry| entry.unwrap().path())
+		.collect::<Vec<_>>();
+	paths.sort();
 
+	for path in paths {
 		let file = File::open(path).expect("Open file failed");
 
 		let reader = BufReader::new(file);
-		let coll = serde_json::from_reader::<_, HashMap<String, vmtests::Test>>(reader)
+		let coll = serde_json::from_reader::<_, BTreeMap<String, vmtests::Test>>(reader)
 			.expect("Parse test cases failed");
 
 		for (name, test) in coll {